@@ -5,7 +5,7 @@
 
 use std::{path::PathBuf, sync::Arc, time::Duration};
 
-use sd_core::{custom_uri::create_custom_uri_endpoint, Node, NodeError};
+use sd_core::{custom_uri::create_custom_uri_endpoint, Node, NodeError, DEFAULT_APP_FOLDER};
 
 use tauri::{api::path, async_runtime::block_on, plugin::TauriPlugin, Manager, RunEvent, Runtime};
 use tokio::{task::block_in_place, time::sleep};
@@ -33,7 +33,7 @@ pub fn tauri_error_plugin<R: Runtime>(err: NodeError) -> TauriPlugin<R> {
 async fn main() -> tauri::Result<()> {
 	let data_dir = path::data_dir()
 		.unwrap_or_else(|| PathBuf::from("./"))
-		.join("spacedrive");
+		.join(DEFAULT_APP_FOLDER);
 
 	#[cfg(debug_assertions)]
 	let data_dir = data_dir.join("dev");
@@ -35,10 +35,16 @@ pub extern "system" fn Java_com_spacedrive_app_SDCore_registerCoreEventListener(
 		})
 	});
 
-	if let Err(err) = result {
-		// TODO: Send rspc error or something here so we can show this in the UI.
-		// TODO: Maybe reinitialise the core cause it could be in an invalid state?
-		println!("Error in Java_com_spacedrive_app_SDCore_registerCoreEventListener: {err:?}");
+	match result {
+		Ok(Ok(())) => {}
+		Ok(Err(err)) => {
+			println!("Error in Java_com_spacedrive_app_SDCore_registerCoreEventListener: {err}")
+		}
+		Err(err) => {
+			// TODO: Send rspc error or something here so we can show this in the UI.
+			// TODO: Maybe reinitialise the core cause it could be in an invalid state?
+			println!("Error in Java_com_spacedrive_app_SDCore_registerCoreEventListener: {err:?}");
+		}
 	}
 }
 
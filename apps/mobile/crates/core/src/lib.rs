@@ -1,9 +1,10 @@
 use futures::future::join_all;
 use once_cell::sync::{Lazy, OnceCell};
 use rspc::internal::jsonrpc::*;
-use sd_core::{api::Router, Node};
+use sd_core::{api::Router, Node, NodeError};
 use serde_json::{from_str, from_value, to_string, Value};
 use std::{collections::HashMap, marker::Send, sync::Arc};
+use thiserror::Error;
 use tokio::{
 	runtime::Runtime,
 	sync::{
@@ -24,22 +25,42 @@ pub static SUBSCRIPTIONS: Lazy<Mutex<HashMap<RequestId, oneshot::Sender<()>>>> =
 
 pub static EVENT_SENDER: OnceCell<UnboundedSender<Response>> = OnceCell::new();
 
+/// Error type for failures to set up the core from the mobile app.
+#[derive(Error, Debug)]
+pub enum CoreInitError {
+	#[error("the core event listener has already been registered")]
+	AlreadyInitialized,
+	#[error("failed to start the node: {0}")]
+	Node(#[from] NodeError),
+}
+
+/// Returns the node, starting it in `data_dir` if this is the first call.
+///
+/// A node that fails to start isn't kept, so the next call tries again.
+async fn get_or_init_node(data_dir: String) -> Result<(Arc<Node>, Arc<Router>), CoreInitError> {
+	let node = &mut *NODE.lock().await;
+	match node {
+		Some(node) => Ok(node.clone()),
+		None => {
+			let new_node = Node::new(data_dir).await?;
+			node.replace(new_node.clone());
+			Ok(new_node)
+		}
+	}
+}
+
 pub fn handle_core_msg(
 	query: String,
 	data_dir: String,
 	callback: impl FnOnce(Result<String, String>) + Send + 'static,
 ) {
 	RUNTIME.spawn(async move {
-		let (node, router) = {
-			let node = &mut *NODE.lock().await;
-			match node {
-				Some(node) => node.clone(),
-				None => {
-					// TODO: probably don't unwrap
-					let new_node = Node::new(data_dir).await.unwrap();
-					node.replace(new_node.clone());
-					new_node
-				}
+		let (node, router) = match get_or_init_node(data_dir).await {
+			Ok(node) => node,
+			Err(err) => {
+				error!("failed to initialise the core: {}", err);
+				callback(Err(err.to_string()));
+				return;
 			}
 		};
 
@@ -86,9 +107,14 @@ pub fn handle_core_msg(
 	});
 }
 
-pub fn spawn_core_event_listener(callback: impl Fn(String) + Send + 'static) {
+/// Forwards core events to `callback`, which can only be registered once per process.
+pub fn spawn_core_event_listener(
+	callback: impl Fn(String) + Send + 'static,
+) -> Result<(), CoreInitError> {
 	let (tx, mut rx) = unbounded_channel();
-	let _ = EVENT_SENDER.set(tx);
+	EVENT_SENDER
+		.set(tx)
+		.map_err(|_| CoreInitError::AlreadyInitialized)?;
 
 	RUNTIME.spawn(async move {
 		while let Some(event) = rx.recv().await {
@@ -103,4 +129,21 @@ pub fn spawn_core_event_listener(callback: impl Fn(String) + Send + 'static) {
 			callback(data);
 		}
 	});
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn event_listener_is_only_registered_once() {
+		spawn_core_event_listener(|_| {}).unwrap();
+
+		assert!(matches!(
+			spawn_core_event_listener(|_| {}),
+			Err(CoreInitError::AlreadyInitialized)
+		));
+	}
 }
@@ -40,13 +40,17 @@ pub unsafe extern "C" fn register_core_event_listener(id: *mut Object) {
 		spawn_core_event_listener(move |data| {
 			let data = NSString::from_str(&data);
 			let _: () = msg_send![id, sendCoreEvent: data];
-		});
+		})
 	});
 
-	if let Err(err) = result {
-		// TODO: Send rspc error or something here so we can show this in the UI.
-		// TODO: Maybe reinitialise the core cause it could be in an invalid state?
-		println!("Error in register_core_event_listener: {:?}", err);
+	match result {
+		Ok(Ok(())) => {}
+		Ok(Err(err)) => println!("Error in register_core_event_listener: {err}"),
+		Err(err) => {
+			// TODO: Send rspc error or something here so we can show this in the UI.
+			// TODO: Maybe reinitialise the core cause it could be in an invalid state?
+			println!("Error in register_core_event_listener: {:?}", err);
+		}
 	}
 }
 
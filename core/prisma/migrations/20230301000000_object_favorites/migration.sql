@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "date_favorited" DATETIME;
+
+-- CreateIndex
+CREATE INDEX "object_favorite_date_favorited_idx" ON "object"("favorite", "date_favorited");
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "date_taken" DATETIME;
+
+-- CreateIndex
+CREATE INDEX "object_date_taken_idx" ON "object"("date_taken");
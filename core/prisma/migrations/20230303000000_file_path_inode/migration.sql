@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "device" BLOB;
+ALTER TABLE "file_path" ADD COLUMN "inode" BLOB;
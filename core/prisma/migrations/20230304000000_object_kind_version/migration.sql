@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "kind_version" INTEGER NOT NULL DEFAULT 0;
+
+-- CreateIndex
+CREATE INDEX "object_kind_version_idx" ON "object"("kind_version");
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "thumbnail_status" INTEGER NOT NULL DEFAULT 0;
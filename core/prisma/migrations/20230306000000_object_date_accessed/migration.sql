@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "date_accessed" DATETIME;
+
+-- CreateIndex
+CREATE INDEX "object_date_accessed_idx" ON "object"("date_accessed");
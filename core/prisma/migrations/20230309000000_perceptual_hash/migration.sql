@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "perceptual_hashing" BOOLEAN NOT NULL DEFAULT false;
+
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "perceptual_hash" BLOB;
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "color_label" INTEGER;
+
+-- CreateIndex
+CREATE INDEX "object_color_label_idx" ON "object"("color_label");
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "date_deleted" DATETIME;
+
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "date_deleted" DATETIME;
@@ -2,18 +2,28 @@ use crate::{
 	invalidate_query,
 	job::Job,
 	library::LibraryContext,
-	object::fs::{
-		copy::{FileCopierJob, FileCopierJobInit},
-		cut::{FileCutterJob, FileCutterJobInit},
-		decrypt::{FileDecryptorJob, FileDecryptorJobInit},
-		delete::{FileDeleterJob, FileDeleterJobInit},
-		encrypt::{FileEncryptorJob, FileEncryptorJobInit},
-		erase::{FileEraserJob, FileEraserJobInit},
+	object::{
+		bundle,
+		color_label::{self, ColorLabel},
+		favorite,
+		fs::{
+			copy::{FileCopierJob, FileCopierJobInit},
+			cut::{FileCutterJob, FileCutterJobInit},
+			decrypt::{FileDecryptorJob, FileDecryptorJobInit},
+			delete::{FileDeleterJob, FileDeleterJobInit},
+			encrypt::{FileEncryptorJob, FileEncryptorJobInit},
+			erase::{FileEraserJob, FileEraserJobInit},
+		},
+		list::{self, ListQuery},
+		move_to_location, quarantine, recents, similar, Decision, NameConflict,
 	},
 	prisma::object,
 };
 
+use std::path::PathBuf;
+
 use rspc::Type;
+use sd_crypto::Protected;
 use serde::Deserialize;
 use tokio::sync::oneshot;
 
@@ -60,6 +70,24 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_query("list", |t| {
+			t(|_, query: ListQuery, library: LibraryContext| async move {
+				Ok(list::list(&library.db, query).await?)
+			})
+		})
+		.library_query("listFavorites", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct ListFavoritesArgs {
+				pub limit: i32,
+				pub offset: i32,
+			}
+
+			t(
+				|_, args: ListFavoritesArgs, library: LibraryContext| async move {
+					Ok(favorite::list_favorites(&library.db, args.limit, args.offset).await?)
+				},
+			)
+		})
 		.library_mutation("setFavorite", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct SetFavoriteArgs {
@@ -69,15 +97,109 @@ pub(crate) fn mount() -> RouterBuilder {
 
 			t(
 				|_, args: SetFavoriteArgs, library: LibraryContext| async move {
-					library
-						.db
-						.object()
-						.update(
-							object::id::equals(args.id),
-							vec![object::favorite::set(args.favorite)],
-						)
-						.exec()
-						.await?;
+					favorite::set_favorite(&library.db, args.id, args.favorite).await?;
+
+					invalidate_query!(library, "locations.getExplorerData");
+					invalidate_query!(library, "tags.getExplorerData");
+					invalidate_query!(library, "files.listFavorites");
+
+					Ok(())
+				},
+			)
+		})
+		.library_query("listByColor", |t| {
+			t(|_, label: ColorLabel, library: LibraryContext| async move {
+				Ok(color_label::list_by_color(&library.db, label).await?)
+			})
+		})
+		.library_mutation("setColorLabel", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SetColorLabelArgs {
+				pub id: i32,
+				pub label: Option<ColorLabel>,
+			}
+
+			t(
+				|_, args: SetColorLabelArgs, library: LibraryContext| async move {
+					color_label::set_color_label(&library.db, args.id, args.label).await?;
+
+					invalidate_query!(library, "locations.getExplorerData");
+					invalidate_query!(library, "tags.getExplorerData");
+					invalidate_query!(library, "files.listByColor");
+
+					Ok(())
+				},
+			)
+		})
+		.library_query("listRecents", |t| {
+			t(|_, limit: i32, library: LibraryContext| async move {
+				Ok(recents::list_recents(&library.db, limit).await?)
+			})
+		})
+		.library_mutation("recordAccess", |t| {
+			t(|_, id: i32, library: LibraryContext| async move {
+				recents::record_access(&library, id).await?;
+
+				invalidate_query!(library, "files.listRecents");
+
+				Ok(())
+			})
+		})
+		.library_query("findSimilar", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct FindSimilarArgs {
+				pub id: i32,
+				pub max_distance: u32,
+			}
+
+			t(
+				|_, args: FindSimilarArgs, library: LibraryContext| async move {
+					Ok(similar::find_similar(&library.db, args.id, args.max_distance).await?)
+				},
+			)
+		})
+		.library_query("listQuarantine", |t| {
+			t(|_, _: (), library: LibraryContext| async move {
+				Ok(quarantine::list_quarantine(&library.db).await?)
+			})
+		})
+		.library_mutation("review", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct ReviewArgs {
+				pub id: i32,
+				pub decision: Decision,
+			}
+
+			t(|_, args: ReviewArgs, library: LibraryContext| async move {
+				quarantine::review(&library, args.id, args.decision).await?;
+
+				invalidate_query!(library, "files.listQuarantine");
+				if let Decision::Accept { .. } = args.decision {
+					invalidate_query!(library, "locations.getExplorerData");
+				}
+
+				Ok(())
+			})
+		})
+		.library_mutation("moveToLocation", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct MoveToLocationArgs {
+				pub id: i32,
+				pub location_id: i32,
+				pub subpath: String,
+				pub on_conflict: NameConflict,
+			}
+
+			t(
+				|_, args: MoveToLocationArgs, library: LibraryContext| async move {
+					move_to_location(
+						&library,
+						args.id,
+						args.location_id,
+						args.subpath,
+						args.on_conflict,
+					)
+					.await?;
 
 					invalidate_query!(library, "locations.getExplorerData");
 					invalidate_query!(library, "tags.getExplorerData");
@@ -86,6 +208,58 @@ pub(crate) fn mount() -> RouterBuilder {
 				},
 			)
 		})
+		.library_mutation("exportBundle", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct ExportBundleArgs {
+				pub ids: Vec<i32>,
+				pub path: PathBuf,
+				pub password: Protected<String>,
+			}
+
+			t(
+				|_, args: ExportBundleArgs, library: LibraryContext| async move {
+					Ok(bundle::export_bundle(
+						&library,
+						&args.ids,
+						args.path,
+						Protected::new(args.password.expose().as_bytes().to_vec()),
+					)
+					.await?)
+				},
+			)
+		})
+		.library_mutation("importBundle", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct ImportBundleArgs {
+				pub path: PathBuf,
+				pub location_id: i32,
+				pub subpath: String,
+				pub password: Protected<String>,
+				pub restore_tags: bool,
+			}
+
+			t(
+				|_, args: ImportBundleArgs, library: LibraryContext| async move {
+					let manifest = bundle::import_bundle(
+						&library,
+						args.path,
+						args.location_id,
+						args.subpath,
+						Protected::new(args.password.expose().as_bytes().to_vec()),
+						args.restore_tags,
+					)
+					.await?;
+
+					invalidate_query!(library, "locations.getExplorerData");
+					if args.restore_tags {
+						invalidate_query!(library, "tags.list");
+						invalidate_query!(library, "tags.getExplorerData");
+					}
+
+					Ok(manifest)
+				},
+			)
+		})
 		.library_mutation("delete", |t| {
 			t(|_, id: i32, library: LibraryContext| async move {
 				library
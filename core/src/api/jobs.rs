@@ -1,9 +1,11 @@
 use crate::{
-	job::{Job, JobManager},
-	location::{fetch_location, LocationError},
+	job::{Job, JobManager, JobScope},
+	location::{fetch_location, integrity_scan, LocationError},
 	object::{
 		identifier_job::full_identifier_job::{FullFileIdentifierJob, FullFileIdentifierJobInit},
+		import_native_tags,
 		preview::{ThumbnailJob, ThumbnailJobInit},
+		reidentify_all,
 		validation::validator_job::{ObjectValidatorJob, ObjectValidatorJobInit},
 	},
 	prisma::location,
@@ -12,6 +14,7 @@ use crate::{
 use rspc::{ErrorCode, Type};
 use serde::Deserialize;
 use std::path::PathBuf;
+use uuid::Uuid;
 
 use super::{utils::LibraryRequest, CoreEvent, RouterBuilder};
 
@@ -32,6 +35,18 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_mutation("cancel", |t| {
+			t(|ctx, id: Uuid, _| async move {
+				if !ctx.jobs.cancel(id).await {
+					return Err(rspc::Error::new(
+						ErrorCode::NotFound,
+						"No running job with this id".into(),
+					));
+				}
+
+				Ok(())
+			})
+		})
 		.library_mutation("generateThumbsForLocation", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct GenerateThumbsForLocationArgs {
@@ -66,6 +81,24 @@ pub(crate) fn mount() -> RouterBuilder {
 				},
 			)
 		})
+		.library_mutation("prioritizeThumbnails", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct PrioritizeThumbnailsArgs {
+				pub location_id: i32,
+				pub path: PathBuf,
+			}
+
+			t(|ctx, args: PrioritizeThumbnailsArgs, library| async move {
+				ctx.jobs
+					.prioritize_thumbnails(JobScope {
+						library_id: library.id,
+						location_id: args.location_id,
+						path: args.path,
+					})
+					.await;
+				Ok(())
+			})
+		})
 		.library_mutation("objectValidator", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct ObjectValidatorArgs {
@@ -95,6 +128,11 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_mutation("objectIntegrityScan", |t| {
+			t(|_, location_id: i32, library| async move {
+				Ok(integrity_scan(&library, location_id).await?)
+			})
+		})
 		.library_mutation("identifyUniqueFiles", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct IdentifyUniqueFilesArgs {
@@ -123,6 +161,44 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_mutation("reidentifyObjects", |t| {
+			t(|_, location_id: Option<i32>, library| async move {
+				if let Some(location_id) = location_id {
+					if fetch_location(&library, location_id)
+						.exec()
+						.await?
+						.is_none()
+					{
+						return Err(rspc::Error::new(
+							ErrorCode::NotFound,
+							"Location not found".into(),
+						));
+					}
+				}
+
+				reidentify_all(&library, location_id).await;
+
+				Ok(())
+			})
+		})
+		.library_mutation("importNativeTags", |t| {
+			t(|_, location_id: i32, library| async move {
+				if fetch_location(&library, location_id)
+					.exec()
+					.await?
+					.is_none()
+				{
+					return Err(rspc::Error::new(
+						ErrorCode::NotFound,
+						"Location not found".into(),
+					));
+				}
+
+				import_native_tags(&library, location_id).await;
+
+				Ok(())
+			})
+		})
 		.library_subscription("newThumbnail", |t| {
 			t(|ctx, _: (), _| {
 				// TODO: Only return event for the library that was subscribed to
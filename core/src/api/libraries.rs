@@ -1,8 +1,12 @@
 use crate::{
 	api::Ctx,
 	invalidate_query,
-	library::{LibraryConfig, LibraryContext},
-	prisma::statistics,
+	library::{
+		backup::{self, BackupSchedule},
+		LibraryConfig, LibraryContext,
+	},
+	location::LocationError,
+	prisma::{location, statistics},
 	volume::{get_volumes, save_volume},
 };
 
@@ -11,17 +15,27 @@ use sd_crypto::{
 	primitives::types::OnboardingConfig, Protected,
 };
 
+use std::path::PathBuf;
+
 use chrono::Utc;
 use rspc::{Error, ErrorCode, Type};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::debug;
 use uuid::Uuid;
 
 use super::{
 	utils::{get_size, LibraryRequest},
-	RouterBuilder,
+	CoreEvent, RouterBuilder,
 };
 
+#[derive(Serialize, Type)]
+pub enum BackupEvent {
+	Created(PathBuf),
+	/// The destination location was offline, the backup is taken once it's back.
+	Skipped,
+	Failed(String),
+}
+
 pub(crate) fn mount() -> RouterBuilder {
 	<RouterBuilder>::new()
 		.query("list", |t| {
@@ -171,4 +185,52 @@ pub(crate) fn mount() -> RouterBuilder {
 		.mutation("delete", |t| {
 			t(|ctx: Ctx, id: Uuid| async move { Ok(ctx.library_manager.delete_library(id).await?) })
 		})
+		.library_mutation("scheduleBackup", |t| {
+			t(|ctx, args: BackupSchedule, library| async move {
+				library
+					.db
+					.location()
+					.find_unique(location::id::equals(args.location_id))
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(args.location_id))?;
+
+				ctx.library_manager
+					.set_backup_schedule(library.id, Some(args))
+					.await?;
+				backup::schedule(library, args).await;
+
+				Ok(())
+			})
+		})
+		.library_mutation("unscheduleBackup", |t| {
+			t(|ctx, _: (), library: LibraryContext| async move {
+				ctx.library_manager
+					.set_backup_schedule(library.id, None)
+					.await?;
+
+				Ok(backup::unschedule(library.id).await)
+			})
+		})
+		.library_subscription("backupEvents", |t| {
+			t(|ctx, _: (), library_id| {
+				let mut event_bus_rx = ctx.event_bus.subscribe();
+				async_stream::stream! {
+					while let Ok(event) = event_bus_rx.recv().await {
+						match event {
+							CoreEvent::BackupCreated { library_id: id, path } if id == library_id => {
+								yield BackupEvent::Created(path)
+							}
+							CoreEvent::BackupSkipped { library_id: id, .. } if id == library_id => {
+								yield BackupEvent::Skipped
+							}
+							CoreEvent::BackupFailed { library_id: id, message } if id == library_id => {
+								yield BackupEvent::Failed(message)
+							}
+							_ => {}
+						}
+					}
+				}
+			})
+		})
 }
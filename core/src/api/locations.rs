@@ -3,7 +3,8 @@ use crate::{
 	location::{
 		delete_location, fetch_location,
 		indexer::{indexer_job::indexer_job_location, rules::IndexerRuleCreateArgs},
-		relink_location, scan_location, LocationCreateArgs, LocationError, LocationUpdateArgs,
+		reconcile, relink_location, scan_location, LocationCreateArgs, LocationError,
+		LocationUpdateArgs,
 	},
 	prisma::{file_path, indexer_rule, indexer_rules_in_location, location, object, tag},
 };
@@ -113,6 +114,7 @@ pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 					.find_many(vec![
 						file_path::location_id::equals(location.id),
 						file_path::parent_id::equals(Some(directory.id)),
+						file_path::date_deleted::equals(None),
 					])
 					.include(file_path_with_object::include())
 					.exec()
@@ -197,6 +199,11 @@ pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 				.map_err(Into::into)
 			})
 		})
+		.library_mutation("reconcile", |t| {
+			t(|_, location_id: i32, library| async move {
+				reconcile(&library, location_id).await.map_err(Into::into)
+			})
+		})
 		.library_mutation("quickRescan", |t| {
 			t(|_, _: (), _| async move {
 				#[allow(unreachable_code)]
@@ -1,4 +1,5 @@
 use std::{
+	path::PathBuf,
 	sync::Arc,
 	time::{Duration, Instant},
 };
@@ -6,12 +7,15 @@ use std::{
 use rspc::{Config, Type};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
+use uuid::Uuid;
 
 use crate::{
+	error::CryptoFailureKind,
 	job::JobManager,
 	library::LibraryManager,
 	node::{NodeConfig, NodeConfigManager},
 	util::secure_temp_keystore::SecureTempKeystore,
+	volume::Volume,
 };
 
 use utils::{InvalidRequests, InvalidateOperationEvent};
@@ -22,9 +26,51 @@ pub(crate) type RouterBuilder = rspc::RouterBuilder<Ctx>;
 /// Represents an internal core event, these are exposed to client via a rspc subscription.
 #[derive(Debug, Clone, Serialize, Type)]
 pub enum CoreEvent {
-	NewThumbnail { cas_id: String },
+	NewThumbnail {
+		cas_id: String,
+	},
+	ThumbnailFailed {
+		cas_id: String,
+	},
 	InvalidateOperation(InvalidateOperationEvent),
 	InvalidateOperationDebounced(InvalidateOperationEvent),
+	VolumeMounted {
+		volume: Volume,
+	},
+	VolumeUnmounted {
+		volume: Volume,
+	},
+	JobFailed {
+		message: String,
+	},
+	JobCanceled {
+		id: Uuid,
+	},
+	CryptoJobFailed {
+		kind: CryptoFailureKind,
+		message: String,
+	},
+	BackupCreated {
+		library_id: Uuid,
+		path: PathBuf,
+	},
+	BackupSkipped {
+		library_id: Uuid,
+		location_id: i32,
+	},
+	BackupFailed {
+		library_id: Uuid,
+		message: String,
+	},
+	ActivityPaused,
+	ActivityResumed,
+	LibraryDatabaseUnavailable {
+		library_id: Uuid,
+		reason: String,
+	},
+	LibraryDatabaseRecovered {
+		library_id: Uuid,
+	},
 }
 
 /// Is provided when executing the router from the request.
@@ -53,14 +99,23 @@ struct NodeState {
 	data_path: String,
 }
 
+/// The config every router is built with, which doesn't export the bindings on its own.
+pub(crate) fn config() -> Config {
+	Config::new().set_ts_bindings_header("/* eslint-disable */")
+}
+
 pub(crate) fn mount() -> Arc<Router> {
-	let config = Config::new().set_ts_bindings_header("/* eslint-disable */");
+	let config = config();
 
 	#[cfg(all(debug_assertions, not(feature = "mobile")))]
 	let config = config.export_ts_bindings(
 		std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../packages/client/src/core.ts"),
 	);
 
+	mount_with(config)
+}
+
+pub(crate) fn mount_with(config: Config) -> Arc<Router> {
 	let r = <Router>::new()
 		.config(config)
 		.query("buildInfo", |t| {
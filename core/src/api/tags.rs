@@ -9,6 +9,7 @@ use crate::{
 	api::locations::{object_with_file_paths, ExplorerContext, ExplorerData, ExplorerItem},
 	invalidate_query,
 	library::LibraryContext,
+	object::tag::assign_tag,
 	prisma::{object, tag, tag_on_object},
 	sync,
 };
@@ -176,6 +177,7 @@ pub(crate) fn mount() -> RouterBuilder {
 				pub unassign: bool,
 			}
 
+			// Returns whether anything changed, so assigning a tag that's already there is a no-op
 			t(|_, args: TagAssignArgs, library| async move {
 				if args.unassign {
 					library
@@ -184,22 +186,13 @@ pub(crate) fn mount() -> RouterBuilder {
 						.delete(tag_on_object::tag_id_object_id(args.tag_id, args.object_id))
 						.exec()
 						.await?;
-				} else {
-					library
-						.db
-						.tag_on_object()
-						.create(
-							tag::id::equals(args.tag_id),
-							object::id::equals(args.object_id),
-							vec![],
-						)
-						.exec()
-						.await?;
+				} else if !assign_tag(&library.db, args.object_id, args.tag_id).await? {
+					return Ok(false);
 				}
 
 				invalidate_query!(library, "tags.getForObject");
 
-				Ok(())
+				Ok(true)
 			})
 		})
 		.library_mutation("update", |t| {
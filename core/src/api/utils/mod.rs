@@ -1,20 +1,25 @@
-use std::path::Path;
+use std::{collections::HashSet, path::Path};
 
 use tokio::{fs, io};
 
+use crate::object::fs::hardlink::FileId;
+
 mod invalidate;
 mod library;
+mod subscribe;
 
 pub use invalidate::*;
 pub use library::*;
+pub(crate) use subscribe::*;
 
-/// Returns the size of the file or directory
+/// Returns the size of the file or directory, where a file with several hardlinks in it is counted once
 pub async fn get_size(path: impl AsRef<Path>) -> Result<u64, io::Error> {
 	let path = path.as_ref();
 	let metadata = fs::metadata(path).await?;
 
 	if metadata.is_dir() {
 		let mut result = 0;
+		let mut seen = HashSet::new();
 		let mut to_walk = vec![path.to_path_buf()];
 
 		while let Some(path) = to_walk.pop() {
@@ -24,7 +29,7 @@ pub async fn get_size(path: impl AsRef<Path>) -> Result<u64, io::Error> {
 				let metadata = entry.metadata().await?;
 				if metadata.is_dir() {
 					to_walk.push(entry.path())
-				} else {
+				} else if FileId::from_metadata(&metadata).map_or(true, |id| seen.insert(id)) {
 					result += metadata.len()
 				}
 			}
@@ -0,0 +1,152 @@
+use tokio::sync::{broadcast, mpsc};
+use tracing::debug;
+
+use crate::api::CoreEvent;
+
+/// How many events a subscriber can fall behind by, before events for it are dropped.
+pub(crate) const SUBSCRIBER_CAPACITY: usize = 128;
+
+/// Forwards the events from the event bus that match `filter` to a channel of their own.
+///
+/// A subscriber that doesn't keep up misses the events that don't fit in its channel, without holding up
+/// the event bus or any other subscriber. Forwarding stops as soon as the receiver is dropped.
+pub(crate) fn filtered_events(
+	mut event_bus_rx: broadcast::Receiver<CoreEvent>,
+	filter: impl Fn(&CoreEvent) -> bool + Send + 'static,
+	capacity: usize,
+) -> mpsc::Receiver<CoreEvent> {
+	let (tx, rx) = mpsc::channel(capacity);
+
+	tokio::spawn(async move {
+		loop {
+			// the receiver can be dropped while the event bus is quiet, which mustn't keep this task around
+			let received = tokio::select! {
+				received = event_bus_rx.recv() => received,
+				_ = tx.closed() => break,
+			};
+
+			let event = match received {
+				Ok(event) => event,
+				Err(broadcast::error::RecvError::Lagged(skipped)) => {
+					debug!("Event subscriber lagged, skipped {skipped} events");
+					continue;
+				}
+				Err(broadcast::error::RecvError::Closed) => break,
+			};
+
+			if !filter(&event) {
+				continue;
+			}
+
+			match tx.try_send(event) {
+				Ok(()) => {}
+				Err(mpsc::error::TrySendError::Full(event)) => {
+					debug!("Event subscriber is full, dropping event: {event:?}");
+				}
+				Err(mpsc::error::TrySendError::Closed(_)) => break,
+			}
+		}
+	});
+
+	rx
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn subscribers_only_receive_matching_events() {
+		let (event_bus_tx, _) = broadcast::channel(16);
+
+		let mut thumbnails = filtered_events(
+			event_bus_tx.subscribe(),
+			|event| matches!(event, CoreEvent::NewThumbnail { .. }),
+			SUBSCRIBER_CAPACITY,
+		);
+		let mut activity = filtered_events(
+			event_bus_tx.subscribe(),
+			|event| {
+				matches!(
+					event,
+					CoreEvent::ActivityPaused | CoreEvent::ActivityResumed
+				)
+			},
+			SUBSCRIBER_CAPACITY,
+		);
+
+		for event in [
+			CoreEvent::ActivityPaused,
+			CoreEvent::NewThumbnail { cas_id: "a".into() },
+			CoreEvent::JobFailed {
+				message: "failed".into(),
+			},
+			CoreEvent::NewThumbnail { cas_id: "b".into() },
+			CoreEvent::ActivityResumed,
+		] {
+			event_bus_tx.send(event).unwrap();
+		}
+		drop(event_bus_tx);
+
+		let mut cas_ids = Vec::new();
+		while let Some(event) = thumbnails.recv().await {
+			match event {
+				CoreEvent::NewThumbnail { cas_id } => cas_ids.push(cas_id),
+				event => panic!("unexpected event: {event:?}"),
+			}
+		}
+		assert_eq!(cas_ids, ["a", "b"]);
+
+		assert!(matches!(
+			activity.recv().await,
+			Some(CoreEvent::ActivityPaused)
+		));
+		assert!(matches!(
+			activity.recv().await,
+			Some(CoreEvent::ActivityResumed)
+		));
+		assert!(activity.recv().await.is_none());
+	}
+
+	#[tokio::test]
+	async fn full_subscribers_only_drop_their_own_events() {
+		let (event_bus_tx, _) = broadcast::channel(16);
+
+		let mut slow = filtered_events(event_bus_tx.subscribe(), |_| true, 1);
+		let mut fast = filtered_events(event_bus_tx.subscribe(), |_| true, SUBSCRIBER_CAPACITY);
+
+		for _ in 0..3 {
+			event_bus_tx.send(CoreEvent::ActivityPaused).unwrap();
+		}
+		drop(event_bus_tx);
+
+		let mut received = 0;
+		while fast.recv().await.is_some() {
+			received += 1;
+		}
+		assert_eq!(received, 3);
+
+		// the slow subscriber never read, so only the first event fit
+		assert!(slow.recv().await.is_some());
+		assert!(slow.recv().await.is_none());
+	}
+
+	#[tokio::test]
+	async fn forwarding_stops_when_the_receiver_is_dropped() {
+		let (event_bus_tx, _) = broadcast::channel::<CoreEvent>(16);
+
+		let events = filtered_events(event_bus_tx.subscribe(), |_| true, SUBSCRIBER_CAPACITY);
+		assert_eq!(event_bus_tx.receiver_count(), 1);
+
+		// nothing is sent, so the forwarding task only notices through the channel closing
+		drop(events);
+		for _ in 0..100 {
+			if event_bus_tx.receiver_count() == 0 {
+				return;
+			}
+			tokio::task::yield_now().await;
+		}
+
+		panic!("the forwarding task outlived its receiver");
+	}
+}
@@ -1,7 +1,31 @@
-use crate::volume::get_volumes;
+use crate::volume::{get_volumes, Volume};
 
-use super::RouterBuilder;
+use rspc::Type;
+use serde::Serialize;
+
+use super::{CoreEvent, RouterBuilder};
+
+#[derive(Serialize, Type)]
+pub enum VolumeEvent {
+	Mounted(Volume),
+	Unmounted(Volume),
+}
 
 pub(crate) fn mount() -> RouterBuilder {
-	RouterBuilder::new().query("list", |t| t(|_, _: ()| Ok(get_volumes()?)))
+	RouterBuilder::new()
+		.query("list", |t| t(|_, _: ()| Ok(get_volumes()?)))
+		.subscription("events", |t| {
+			t(|ctx, _: ()| {
+				let mut event_bus_rx = ctx.event_bus.subscribe();
+				async_stream::stream! {
+					while let Ok(event) = event_bus_rx.recv().await {
+						match event {
+							CoreEvent::VolumeMounted { volume } => yield VolumeEvent::Mounted(volume),
+							CoreEvent::VolumeUnmounted { volume } => yield VolumeEvent::Unmounted(volume),
+							_ => {}
+						}
+					}
+				}
+			})
+		})
 }
@@ -0,0 +1,85 @@
+//! Exports the TypeScript bindings of the core, so the frontend build doesn't depend on the side
+//! effects of building the router in a debug build.
+
+use std::{fs, io, path::Path};
+
+use rspc::{ExportError, Type};
+use thiserror::Error;
+
+use crate::{
+	api::{self, utils::InvalidateOperationEvent},
+	job::{JobConcurrency, JobReport, JobStatus},
+	library::LibraryConfigWrapped,
+	node::NodeConfig,
+	volume::Volume,
+};
+
+/// The files `export` writes into the output directory.
+pub const FILES: &[&str] = &["core.ts"];
+
+/// Every type reachable from a procedure ends up in the bindings, these are the ones the frontend
+/// uses on its own, which must not fall out of them when a procedure changes.
+macro_rules! registry {
+	($($ty:ident),* $(,)?) => {
+		const REGISTRY: &[&str] = &[$(stringify!($ty)),*];
+
+		// A type in the registry which stops deriving `Type` fails to compile here
+		const _: fn() = || {
+			fn exported<T: Type>() {}
+			$(exported::<$ty>();)*
+		};
+	};
+}
+
+registry!(
+	InvalidateOperationEvent,
+	JobConcurrency,
+	JobReport,
+	JobStatus,
+	LibraryConfigWrapped,
+	NodeConfig,
+	Volume,
+);
+
+#[derive(Error, Debug)]
+pub enum BindingsError {
+	#[error("failed to create the output directory: {0}")]
+	Io(#[from] io::Error),
+	#[error("failed to export the bindings: {0}")]
+	Export(#[from] ExportError),
+}
+
+/// Writes the bindings of every procedure of the core, and the types they use, into `out_dir`.
+pub fn export(out_dir: &Path) -> Result<(), BindingsError> {
+	fs::create_dir_all(out_dir)?;
+
+	api::mount_with(api::config()).export_ts(out_dir.join(FILES[0]))?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn export_writes_every_file() {
+		let out_dir = tempfile::tempdir().unwrap();
+		export(&out_dir.path().join("bindings")).unwrap();
+
+		let mut files = fs::read_dir(out_dir.path().join("bindings"))
+			.unwrap()
+			.map(|entry| entry.unwrap().file_name().into_string().unwrap())
+			.collect::<Vec<_>>();
+		files.sort();
+		assert_eq!(files, FILES);
+
+		let bindings = fs::read_to_string(out_dir.path().join("bindings").join(FILES[0])).unwrap();
+		for name in REGISTRY {
+			assert!(
+				bindings.contains(&format!("export type {name} ")),
+				"{name} is missing from the bindings"
+			);
+		}
+	}
+}
@@ -0,0 +1,117 @@
+use std::io;
+
+use prisma_client_rust::QueryError;
+use rspc::Type;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{api::CoreEvent, job::JobError};
+
+/// Errors from any part of the core, which can be turned into an event for the client.
+#[derive(Error, Debug)]
+pub enum CoreError {
+	#[error("crypto error: {}", .0.developer_detail())]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("I/O error: {0}")]
+	Io(#[from] io::Error),
+	#[error("job error: {0}")]
+	Job(JobError),
+}
+
+impl From<JobError> for CoreError {
+	fn from(err: JobError) -> Self {
+		// Jobs wrap the errors of the crates they use, which are unwrapped so they map to the same event
+		match err {
+			JobError::CryptoError(e) => Self::Crypto(e),
+			JobError::DatabaseError(e) => Self::Database(e),
+			JobError::IOError(e) => Self::Io(e),
+			e => Self::Job(e),
+		}
+	}
+}
+
+/// Why an encryption or decryption job failed, so the client can offer the right way to recover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Type)]
+pub enum CryptoFailureKind {
+	WrongPassword,
+	/// The key manager has to be unlocked first
+	Locked,
+	/// The key the file needs isn't available
+	MissingKey,
+	/// The file isn't valid encrypted data
+	Corrupted,
+	Other,
+}
+
+impl From<&sd_crypto::Error> for CryptoFailureKind {
+	fn from(err: &sd_crypto::Error) -> Self {
+		use sd_crypto::Error;
+
+		match err {
+			Error::IncorrectPassword => Self::WrongPassword,
+			Error::NotUnlocked | Error::NoVerificationKey => Self::Locked,
+			Error::KeyNotFound
+			| Error::KeyNotMounted
+			| Error::NoDefaultKeySet
+			| Error::NoKeyslots => Self::MissingKey,
+			Error::Decrypt
+			| Error::HeaderCorrupt
+			| Error::InconsistentHeader
+			| Error::TruncatedTag
+			| Error::NonceLengthMismatch
+			| Error::VecArrSizeMismatch
+			| Error::KeyCommitmentFailed
+			| Error::Serialization
+			| Error::StringParse(_) => Self::Corrupted,
+			_ => Self::Other,
+		}
+	}
+}
+
+impl CoreError {
+	/// The event telling the client that a job failed because of this error, with a message
+	/// which can be shown to the user as is.
+	pub fn to_client_failure(&self) -> CoreEvent {
+		match self {
+			Self::Crypto(e) => CoreEvent::CryptoJobFailed {
+				kind: e.into(),
+				// The crypto crate's messages are already meant for end users
+				message: e.to_string(),
+			},
+			Self::Database(_) => CoreEvent::JobFailed {
+				message: "There was a problem accessing the library.".to_string(),
+			},
+			Self::Io(_) => CoreEvent::JobFailed {
+				message: "There was a problem reading or writing a file.".to_string(),
+			},
+			Self::Job(e) => CoreEvent::JobFailed {
+				message: e.to_string(),
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wrong_password_in_a_job() {
+		let err = CoreError::from(JobError::from(sd_crypto::Error::IncorrectPassword));
+
+		assert!(matches!(
+			err.to_client_failure(),
+			CoreEvent::CryptoJobFailed {
+				kind: CryptoFailureKind::WrongPassword,
+				message,
+			} if message == sd_crypto::Error::IncorrectPassword.to_string()
+		));
+
+		assert!(matches!(
+			CoreError::from(io::Error::from(io::ErrorKind::NotFound)).to_client_failure(),
+			CoreEvent::JobFailed { .. }
+		));
+	}
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Lets a running job be canceled from outside of it.
+///
+/// Jobs stop as soon as they reach an await point, so a step that was interrupted can leave partial output
+/// behind (e.g. a half-written encrypted file), which is up to the caller to clean up.
+#[derive(Clone)]
+pub struct CancelToken(Arc<watch::Sender<bool>>);
+
+impl Default for CancelToken {
+	fn default() -> Self {
+		Self(Arc::new(watch::channel(false).0))
+	}
+}
+
+impl CancelToken {
+	pub fn cancel(&self) {
+		self.0.send_replace(true);
+	}
+
+	pub fn is_canceled(&self) -> bool {
+		*self.0.borrow()
+	}
+
+	/// Resolves once the job has been canceled, which may be straight away.
+	pub async fn canceled(&self) {
+		let mut rx = self.0.subscribe();
+		// the sender lives as long as `self`, so this can't fail
+		while !*rx.borrow_and_update() && rx.changed().await.is_ok() {}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use tokio::time::timeout;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn waiting_jobs_see_the_cancellation() {
+		let token = CancelToken::default();
+		assert!(!token.is_canceled());
+
+		let waiting = tokio::spawn({
+			let token = token.clone();
+			async move { token.canceled().await }
+		});
+		tokio::task::yield_now().await;
+		assert!(!waiting.is_finished());
+
+		token.cancel();
+		timeout(Duration::from_secs(1), waiting)
+			.await
+			.unwrap()
+			.unwrap();
+
+		// once canceled, a job that checks in later stops straight away
+		assert!(token.is_canceled());
+		timeout(Duration::from_secs(1), token.canceled())
+			.await
+			.unwrap();
+	}
+}
@@ -0,0 +1,272 @@
+use std::{
+	collections::HashMap,
+	num::NonZeroUsize,
+	sync::{Arc, Mutex},
+	thread::available_parallelism,
+};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// What a job mostly spends its time on, which decides how many of them can run at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JobCategory {
+	Hashing,
+	Encryption,
+	Thumbnails,
+	Transfers,
+	/// Everything else, which mostly writes to the database so it runs one job at a time
+	General,
+}
+
+impl JobCategory {
+	const ALL: [Self; 5] = [
+		Self::Hashing,
+		Self::Encryption,
+		Self::Thumbnails,
+		Self::Transfers,
+		Self::General,
+	];
+}
+
+/// How many jobs of each category can run at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct JobConcurrency {
+	pub hashing: u32,
+	pub encryption: u32,
+	pub thumbnails: u32,
+	pub transfers: u32,
+}
+
+impl Default for JobConcurrency {
+	fn default() -> Self {
+		let cpus = available_parallelism().map_or(1, NonZeroUsize::get) as u32;
+
+		Self {
+			// Hashing is bound by the disk, where more readers than this just make it seek more
+			hashing: (cpus / 2).clamp(1, 4),
+			encryption: cpus,
+			thumbnails: (cpus / 2).max(1),
+			transfers: 4,
+		}
+	}
+}
+
+impl JobConcurrency {
+	fn limit(&self, category: JobCategory) -> usize {
+		let limit = match category {
+			JobCategory::Hashing => self.hashing,
+			JobCategory::Encryption => self.encryption,
+			JobCategory::Thumbnails => self.thumbnails,
+			JobCategory::Transfers => self.transfers,
+			JobCategory::General => 1,
+		};
+
+		// A limit of zero would leave the jobs in the queue forever
+		limit.max(1) as usize
+	}
+}
+
+/// Hands out a permit for each running job, from a separate semaphore per category.
+///
+/// The database only has a single writer, so on top of that, jobs that write to it run one at a time.
+pub(super) struct JobLimits {
+	concurrency: Mutex<JobConcurrency>,
+	categories: HashMap<JobCategory, CategoryLimit>,
+	db_writer: Arc<Semaphore>,
+}
+
+struct CategoryLimit {
+	semaphore: Arc<Semaphore>,
+	// How many permits still have to be taken out of circulation after the limit was lowered
+	debt: Arc<Mutex<usize>>,
+}
+
+/// Frees up a spot in the job's category, and the database if it writes to it, once dropped.
+pub(super) struct JobPermit {
+	permit: Option<OwnedSemaphorePermit>,
+	debt: Arc<Mutex<usize>>,
+	_db_writer: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for JobPermit {
+	fn drop(&mut self) {
+		let mut debt = self.debt.lock().expect("poisoned job concurrency lock");
+
+		if let Some(permit) = self.permit.take() {
+			if *debt > 0 {
+				*debt -= 1;
+				permit.forget();
+			}
+		}
+	}
+}
+
+impl JobLimits {
+	pub(super) fn new(concurrency: JobConcurrency) -> Self {
+		Self {
+			categories: JobCategory::ALL
+				.into_iter()
+				.map(|category| {
+					(
+						category,
+						CategoryLimit {
+							semaphore: Arc::new(Semaphore::new(concurrency.limit(category))),
+							debt: Default::default(),
+						},
+					)
+				})
+				.collect(),
+			concurrency: Mutex::new(concurrency),
+			db_writer: Arc::new(Semaphore::new(1)),
+		}
+	}
+
+	pub(super) fn try_acquire(
+		&self,
+		category: JobCategory,
+		writes_to_db: bool,
+	) -> Option<JobPermit> {
+		let limit = &self.categories[&category];
+
+		let mut permit = JobPermit {
+			permit: Some(Arc::clone(&limit.semaphore).try_acquire_owned().ok()?),
+			debt: Arc::clone(&limit.debt),
+			_db_writer: None,
+		};
+		if writes_to_db {
+			permit._db_writer = Some(Arc::clone(&self.db_writer).try_acquire_owned().ok()?);
+		}
+
+		Some(permit)
+	}
+
+	pub(super) fn has_capacity(&self, category: JobCategory, writes_to_db: bool) -> bool {
+		self.categories[&category].semaphore.available_permits() > 0
+			&& (!writes_to_db || self.db_writer.available_permits() > 0)
+	}
+
+	/// Changes the limits for newly scheduled jobs.
+	///
+	/// Jobs that are already running are never stopped, so lowering a limit only takes effect once
+	/// enough of them finish.
+	pub(super) fn set(&self, concurrency: JobConcurrency) {
+		let mut current = self
+			.concurrency
+			.lock()
+			.expect("poisoned job concurrency lock");
+
+		for category in JobCategory::ALL {
+			let (old, new) = (current.limit(category), concurrency.limit(category));
+			let limit = &self.categories[&category];
+			let mut debt = limit.debt.lock().expect("poisoned job concurrency lock");
+
+			if new > old {
+				// Permits that are still owed from lowering the limit are kept rather than added back
+				let repaid = (new - old).min(*debt);
+				*debt -= repaid;
+				limit.semaphore.add_permits(new - old - repaid);
+			} else if new < old {
+				// The idle permits are taken out of circulation now, and the rest as the running jobs release them
+				let mut excess = old - new;
+				while excess > 0 {
+					let Ok(permit) = limit.semaphore.try_acquire() else {
+						break;
+					};
+					permit.forget();
+					excess -= 1;
+				}
+				*debt += excess;
+			}
+		}
+
+		*current = concurrency;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CONCURRENCY: JobConcurrency = JobConcurrency {
+		hashing: 2,
+		encryption: 3,
+		thumbnails: 1,
+		transfers: 1,
+	};
+
+	fn acquire(limits: &JobLimits, category: JobCategory, count: usize) -> Vec<JobPermit> {
+		(0..count)
+			.map(|_| limits.try_acquire(category, false))
+			.collect::<Option<Vec<_>>>()
+			.unwrap()
+	}
+
+	#[test]
+	fn categories_are_limited_separately() {
+		let limits = JobLimits::new(CONCURRENCY);
+
+		let mut hashing = acquire(&limits, JobCategory::Hashing, 2);
+		assert!(!limits.has_capacity(JobCategory::Hashing, false));
+		assert!(limits.try_acquire(JobCategory::Hashing, false).is_none());
+
+		// hashing being saturated doesn't hold back encryption, which still runs up to its own limit
+		let encryption = acquire(&limits, JobCategory::Encryption, 3);
+		assert!(limits.try_acquire(JobCategory::Encryption, false).is_none());
+		assert!(limits.try_acquire(JobCategory::General, false).is_some());
+
+		// raising a limit lets another job start straight away
+		limits.set(JobConcurrency {
+			hashing: 3,
+			..CONCURRENCY
+		});
+		hashing.push(limits.try_acquire(JobCategory::Hashing, false).unwrap());
+		assert!(limits.try_acquire(JobCategory::Hashing, false).is_none());
+
+		// lowering it only applies once the running jobs are done
+		limits.set(JobConcurrency {
+			hashing: 1,
+			..CONCURRENCY
+		});
+		drop(hashing);
+
+		let _hashing = limits.try_acquire(JobCategory::Hashing, false).unwrap();
+		assert!(limits.try_acquire(JobCategory::Hashing, false).is_none());
+
+		drop(encryption);
+		assert!(limits.has_capacity(JobCategory::Encryption, false));
+	}
+
+	#[test]
+	fn raising_a_limit_cancels_the_lowering() {
+		let limits = JobLimits::new(CONCURRENCY);
+
+		let hashing = acquire(&limits, JobCategory::Hashing, 2);
+		limits.set(JobConcurrency {
+			hashing: 1,
+			..CONCURRENCY
+		});
+		limits.set(CONCURRENCY);
+		drop(hashing);
+
+		// the jobs that were running release their permits back, so the whole limit is available again
+		let _hashing = acquire(&limits, JobCategory::Hashing, 2);
+		assert!(limits.try_acquire(JobCategory::Hashing, false).is_none());
+	}
+
+	#[test]
+	fn database_writers_run_one_at_a_time() {
+		let limits = JobLimits::new(CONCURRENCY);
+
+		let writer = limits.try_acquire(JobCategory::Hashing, true).unwrap();
+		assert!(!limits.has_capacity(JobCategory::General, true));
+		assert!(limits.try_acquire(JobCategory::General, true).is_none());
+
+		// a job that doesn't write to the database isn't held back by one that does
+		assert!(limits.try_acquire(JobCategory::Hashing, false).is_some());
+
+		drop(writer);
+		assert!(limits.try_acquire(JobCategory::General, true).is_some());
+	}
+}
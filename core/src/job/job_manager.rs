@@ -1,6 +1,10 @@
 use crate::{
 	invalidate_query,
-	job::{worker::Worker, DynJob, Job, JobError},
+	job::{
+		concurrency::{JobLimits, JobPermit},
+		worker::Worker,
+		DynJob, Job, JobConcurrency, JobError, JobPriority, JobQueue, JobScope,
+	},
 	library::LibraryContext,
 	location::indexer::indexer_job::{IndexerJob, INDEXER_JOB_NAME},
 	object::{
@@ -10,23 +14,32 @@ use crate::{
 			delete::{FileDeleterJob, DELETE_JOB_NAME},
 			erase::{FileEraserJob, ERASE_JOB_NAME},
 		},
-		identifier_job::full_identifier_job::{FullFileIdentifierJob, FULL_IDENTIFIER_JOB_NAME},
+		identifier_job::{
+			full_identifier_job::{FullFileIdentifierJob, FULL_IDENTIFIER_JOB_NAME},
+			perceptual_hash_job::{PerceptualHashJob, PERCEPTUAL_HASH_JOB_NAME},
+			reidentifier_job::{ObjectReidentifierJob, REIDENTIFIER_JOB_NAME},
+		},
+		native_tags::{NativeTagsImportJob, NATIVE_TAGS_IMPORT_JOB_NAME},
 		preview::{ThumbnailJob, THUMBNAIL_JOB_NAME},
-		validation::validator_job::{ObjectValidatorJob, VALIDATOR_JOB_NAME},
+		validation::{
+			integrity_job::{ObjectIntegrityJob, INTEGRITY_JOB_NAME},
+			validator_job::{ObjectValidatorJob, VALIDATOR_JOB_NAME},
+		},
 	},
 	prisma::{job, node},
 };
 
 use std::{
-	collections::{HashMap, HashSet, VecDeque},
+	collections::{HashMap, HashSet},
 	fmt::Debug,
 	fmt::{Display, Formatter},
+	mem,
 	sync::Arc,
 	time::Duration,
 };
 
 use int_enum::IntEnum;
-use prisma_client_rust::Direction;
+use prisma_client_rust::{and, not, or, Direction};
 use rspc::Type;
 use serde::{Deserialize, Serialize};
 use tokio::{
@@ -36,9 +49,6 @@ use tokio::{
 use tracing::{debug, error, info};
 use uuid::Uuid;
 
-// db is single threaded, nerd
-const MAX_WORKERS: usize = 1;
-
 pub enum JobManagerEvent {
 	IngestJob(LibraryContext, Box<dyn DynJob>),
 }
@@ -48,20 +58,33 @@ pub enum JobManagerEvent {
 ///
 pub struct JobManager {
 	current_jobs_hashes: RwLock<HashSet<u64>>,
-	job_queue: RwLock<VecDeque<Box<dyn DynJob>>>,
-	running_workers: RwLock<HashMap<Uuid, Arc<Mutex<Worker>>>>,
+	job_queue: RwLock<JobQueue<(LibraryContext, Box<dyn DynJob>)>>,
+	// The scope the user is currently looking at, whose thumbnails are generated first
+	thumbnail_scope: RwLock<Option<JobScope>>,
+	running_workers: RwLock<HashMap<Uuid, RunningJob>>,
+	limits: JobLimits,
+	pause_gate: Mutex<PauseGate<(LibraryContext, Box<dyn DynJob>)>>,
 	internal_sender: mpsc::UnboundedSender<JobManagerEvent>,
 	shutdown_tx: Arc<broadcast::Sender<()>>,
 }
 
+struct RunningJob {
+	worker: Arc<Mutex<Worker>>,
+	// Frees up a spot in the job's category (and the database) once it's removed from the running jobs
+	_permit: JobPermit,
+}
+
 impl JobManager {
-	pub fn new() -> Arc<Self> {
+	pub fn new(concurrency: JobConcurrency) -> Arc<Self> {
 		let (shutdown_tx, _shutdown_rx) = broadcast::channel(1);
 		let (internal_sender, mut internal_receiver) = mpsc::unbounded_channel();
 		let this = Arc::new(Self {
 			current_jobs_hashes: RwLock::new(HashSet::new()),
-			job_queue: RwLock::new(VecDeque::new()),
+			job_queue: RwLock::new(JobQueue::default()),
+			thumbnail_scope: RwLock::new(None),
 			running_workers: RwLock::new(HashMap::new()),
+			limits: JobLimits::new(concurrency),
+			pause_gate: Mutex::new(PauseGate::default()),
 			internal_sender,
 			shutdown_tx: Arc::new(shutdown_tx),
 		});
@@ -103,13 +126,17 @@ impl JobManager {
 		}
 	}
 
-	pub async fn ingest_queue(&self, job: Box<dyn DynJob>) {
+	pub async fn ingest_queue(&self, ctx: &LibraryContext, job: Box<dyn DynJob>) {
 		let job_hash = job.hash();
 		debug!("Queueing job: <name='{}', hash='{}'>", job.name(), job_hash);
 
 		if !self.current_jobs_hashes.read().await.contains(&job_hash) {
 			self.current_jobs_hashes.write().await.insert(job_hash);
-			self.job_queue.write().await.push_back(job);
+			let priority = self.priority(ctx, job.as_ref()).await;
+			self.job_queue
+				.write()
+				.await
+				.push((ctx.clone(), job), priority);
 		} else {
 			debug!(
 				"Job already in queue: <name='{}', hash='{}'>",
@@ -119,32 +146,74 @@ impl JobManager {
 		}
 	}
 
-	pub async fn complete(self: Arc<Self>, ctx: &LibraryContext, job_id: Uuid, job_hash: u64) {
+	pub async fn complete(self: Arc<Self>, job_id: Uuid, job_hash: u64) {
 		// remove worker from running workers and from current jobs hashes
 		self.current_jobs_hashes.write().await.remove(&job_hash);
 		self.running_workers.write().await.remove(&job_id);
-		// continue queue
-		let job = self.job_queue.write().await.pop_front();
-		if let Some(job) = job {
+		// continue queue, with the library each job was queued for
+		let queued = self
+			.job_queue
+			.write()
+			.await
+			.pop(|(_, job)| self.limits.has_capacity(job.category(), job.writes_to_db()));
+		if let Some((ctx, job)) = queued {
 			// We can't directly execute `self.ingest` here because it would cause an async cycle.
 			self.internal_sender
-				.send(JobManagerEvent::IngestJob(ctx.clone(), job))
+				.send(JobManagerEvent::IngestJob(ctx, job))
 				.unwrap_or_else(|_| {
 					error!("Failed to ingest job!");
 				});
 		}
 	}
 
+	/// Moves the pending thumbnail jobs for the given scope to the front of the queue.
+	///
+	/// The scope is remembered, so thumbnail jobs queued for it afterwards are prioritized as well,
+	/// until another scope is prioritized.
+	pub async fn prioritize_thumbnails(&self, scope: JobScope) {
+		let prioritized = self
+			.job_queue
+			.write()
+			.await
+			.prioritize(|(ctx, job)| is_thumbnail_job_in(ctx, job.as_ref(), &scope));
+
+		debug!("Prioritized {prioritized} thumbnail jobs for {scope:?}");
+
+		*self.thumbnail_scope.write().await = Some(scope);
+	}
+
+	async fn priority(&self, ctx: &LibraryContext, job: &dyn DynJob) -> JobPriority {
+		match &*self.thumbnail_scope.read().await {
+			Some(scope) if is_thumbnail_job_in(ctx, job, scope) => JobPriority::High,
+			_ => JobPriority::Normal,
+		}
+	}
+
 	pub async fn get_running(&self) -> Vec<JobReport> {
 		let mut ret = vec![];
 
-		for worker in self.running_workers.read().await.values() {
-			let worker = worker.lock().await;
+		for running_job in self.running_workers.read().await.values() {
+			let worker = running_job.worker.lock().await;
 			ret.push(worker.report());
 		}
 		ret
 	}
 
+	/// Cancels a running job, returning whether there was one with this id.
+	pub async fn cancel(&self, job_id: Uuid) -> bool {
+		match self.running_workers.read().await.get(&job_id) {
+			Some(running_job) => {
+				running_job.worker.lock().await.cancel();
+				true
+			}
+			None => false,
+		}
+	}
+
+	pub async fn running_count(&self) -> usize {
+		self.running_workers.read().await.len()
+	}
+
 	pub async fn get_history(
 		ctx: &LibraryContext,
 	) -> Result<Vec<JobReport>, prisma_client_rust::QueryError> {
@@ -170,6 +239,37 @@ impl JobManager {
 		Ok(())
 	}
 
+	/// Changes how many jobs of each category can run at once.
+	///
+	/// Raising a limit starts the queued jobs there's now room for straight away, while lowering it
+	/// only applies once enough of the running jobs finish.
+	pub async fn set_concurrency(self: Arc<Self>, concurrency: JobConcurrency) {
+		self.limits.set(concurrency);
+		self.dispatch_queued().await;
+	}
+
+	/// Starts as many of the queued jobs as there's room for.
+	async fn dispatch_queued(self: Arc<Self>) {
+		// The held back jobs are all dispatched on resume, so there's nothing to start until then
+		if self.pause_gate.lock().await.paused {
+			return;
+		}
+
+		loop {
+			let queued = self
+				.job_queue
+				.write()
+				.await
+				.pop(|(_, job)| self.limits.has_capacity(job.category(), job.writes_to_db()));
+			let Some((ctx, job)) = queued else {
+				break;
+			};
+
+			// Each job takes its permit before the next one is picked, so this stops at the limits
+			Arc::clone(&self).dispatch_job(&ctx, job).await;
+		}
+	}
+
 	pub fn shutdown_tx(&self) -> Arc<broadcast::Sender<()>> {
 		Arc::clone(&self.shutdown_tx)
 	}
@@ -192,11 +292,51 @@ impl JobManager {
 		}
 	}
 
+	/// Stops starting jobs and pauses the running ones, which checkpoint at their next step.
+	///
+	/// Jobs dispatched until [`JobManager::resume`] is called are held back instead of running.
+	pub async fn suspend(&self) {
+		self.pause_gate.lock().await.pause();
+		self.pause().await;
+	}
+
+	/// Runs the jobs held back since [`JobManager::suspend`], and picks the paused jobs of each
+	/// library back up from their checkpoint.
+	pub async fn resume(self: Arc<Self>, libraries: Vec<LibraryContext>) {
+		let held = self.pause_gate.lock().await.resume();
+		for (ctx, job) in held {
+			Arc::clone(&self).dispatch_job(&ctx, job).await;
+		}
+
+		for ctx in libraries {
+			if let Err(e) = Arc::clone(&self).resume_jobs(&ctx).await {
+				error!("Failed to resume jobs for library. {:#?}", e);
+			}
+		}
+	}
+
 	pub async fn resume_jobs(self: Arc<Self>, ctx: &LibraryContext) -> Result<(), JobError> {
+		let running_ids = self
+			.running_workers
+			.read()
+			.await
+			.keys()
+			.map(|id| id.as_bytes().to_vec())
+			.collect::<Vec<_>>();
+
 		let paused_jobs = ctx
 			.db
 			.job()
-			.find_many(vec![job::status::equals(JobStatus::Paused.int_value())])
+			.find_many(vec![or![
+				job::status::equals(JobStatus::Paused.int_value()),
+				// Jobs which checkpoint their steps were running when the node stopped without
+				// pausing them, so they go on from their last checkpoint too
+				and![
+					job::status::equals(JobStatus::Running.int_value()),
+					not![job::data::equals(None)],
+					not![job::id::in_vec(running_ids)]
+				]
+			]])
 			.exec()
 			.await?;
 
@@ -212,7 +352,7 @@ impl JobManager {
 				}
 				INDEXER_JOB_NAME => {
 					Arc::clone(&self)
-						.dispatch_job(ctx, Job::resume(paused_job, IndexerJob {})?)
+						.dispatch_job(ctx, Job::resume(paused_job, IndexerJob::default())?)
 						.await;
 				}
 				FULL_IDENTIFIER_JOB_NAME => {
@@ -220,11 +360,34 @@ impl JobManager {
 						.dispatch_job(ctx, Job::resume(paused_job, FullFileIdentifierJob {})?)
 						.await;
 				}
+				REIDENTIFIER_JOB_NAME => {
+					Arc::clone(&self)
+						.dispatch_job(ctx, Job::resume(paused_job, ObjectReidentifierJob {})?)
+						.await;
+				}
+				PERCEPTUAL_HASH_JOB_NAME => {
+					Arc::clone(&self)
+						.dispatch_job(ctx, Job::resume(paused_job, PerceptualHashJob {})?)
+						.await;
+				}
+				NATIVE_TAGS_IMPORT_JOB_NAME => {
+					Arc::clone(&self)
+						.dispatch_job(ctx, Job::resume(paused_job, NativeTagsImportJob {})?)
+						.await;
+				}
 				VALIDATOR_JOB_NAME => {
 					Arc::clone(&self)
 						.dispatch_job(ctx, Job::resume(paused_job, ObjectValidatorJob {})?)
 						.await;
 				}
+				INTEGRITY_JOB_NAME => {
+					Arc::clone(&self)
+						.dispatch_job(
+							ctx,
+							Job::resume(paused_job, ObjectIntegrityJob { report_tx: None })?,
+						)
+						.await;
+				}
 				CUT_JOB_NAME => {
 					Arc::clone(&self)
 						.dispatch_job(ctx, Job::resume(paused_job, FileCutterJob {})?)
@@ -261,10 +424,15 @@ impl JobManager {
 		Ok(())
 	}
 
-	async fn dispatch_job(self: Arc<Self>, ctx: &LibraryContext, mut job: Box<dyn DynJob>) {
+	async fn dispatch_job(self: Arc<Self>, ctx: &LibraryContext, job: Box<dyn DynJob>) {
+		let Some((ctx, mut job)) = self.pause_gate.lock().await.admit((ctx.clone(), job)) else {
+			debug!("Holding back job while paused");
+			return;
+		};
+
 		// create worker to process job
 		let mut running_workers = self.running_workers.write().await;
-		if running_workers.len() < MAX_WORKERS {
+		if let Some(permit) = self.limits.try_acquire(job.category(), job.writes_to_db()) {
 			info!("Running job: {:?}", job.name());
 
 			let job_report = job
@@ -283,7 +451,14 @@ impl JobManager {
 			{
 				error!("Error spawning worker: {:?}", e);
 			} else {
-				running_workers.insert(job_id, wrapped_worker);
+				running_workers.insert(
+					job_id,
+					RunningJob {
+						worker: wrapped_worker,
+						_permit: permit,
+					},
+				);
+				ctx.metrics().job_started();
 			}
 		} else {
 			debug!(
@@ -291,11 +466,56 @@ impl JobManager {
 				job.name(),
 				job.hash()
 			);
-			self.job_queue.write().await.push_back(job);
+			let priority = self.priority(&ctx, job.as_ref()).await;
+			self.job_queue.write().await.push((ctx, job), priority);
+		}
+	}
+}
+
+/// Holds back the jobs dispatched while background activity is paused.
+struct PauseGate<T> {
+	paused: bool,
+	held: Vec<T>,
+}
+
+impl<T> Default for PauseGate<T> {
+	fn default() -> Self {
+		Self {
+			paused: false,
+			held: Vec::new(),
 		}
 	}
 }
 
+impl<T> PauseGate<T> {
+	/// Keeps the job while paused, otherwise hands it back to be run.
+	fn admit(&mut self, job: T) -> Option<T> {
+		if self.paused {
+			self.held.push(job);
+			None
+		} else {
+			Some(job)
+		}
+	}
+
+	fn pause(&mut self) {
+		self.paused = true;
+	}
+
+	/// Lets jobs through again, returning the ones held back in the meantime.
+	fn resume(&mut self) -> Vec<T> {
+		self.paused = false;
+		mem::take(&mut self.held)
+	}
+}
+
+fn is_thumbnail_job_in(ctx: &LibraryContext, job: &dyn DynJob, scope: &JobScope) -> bool {
+	job.name() == THUMBNAIL_JOB_NAME
+		&& job
+			.scope(ctx.id)
+			.map_or(false, |job_scope| job_scope.overlaps(scope))
+}
+
 #[derive(Debug)]
 pub enum JobReportUpdate {
 	TaskCount(usize),
@@ -422,3 +642,137 @@ pub enum JobStatus {
 	Failed = 4,
 	Paused = 5,
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	use crate::{
+		job::{JobCategory, JobResult, JobState, StatefulJob, WorkerContext},
+		Node,
+	};
+
+	/// A hashing job that never finishes, so it holds on to its spot.
+	struct WaitingJob;
+
+	#[async_trait::async_trait]
+	impl StatefulJob for WaitingJob {
+		type Init = usize;
+		type Data = ();
+		type Step = ();
+
+		const CATEGORY: JobCategory = JobCategory::Hashing;
+		const WRITES_TO_DB: bool = false;
+
+		fn name(&self) -> &'static str {
+			"waiting"
+		}
+
+		async fn init(&self, _: WorkerContext, _: &mut JobState<Self>) -> Result<(), JobError> {
+			std::future::pending().await
+		}
+
+		async fn execute_step(
+			&self,
+			_: WorkerContext,
+			_: &mut JobState<Self>,
+		) -> Result<(), JobError> {
+			Ok(())
+		}
+
+		async fn finalize(&mut self, _: WorkerContext, _: &mut JobState<Self>) -> JobResult {
+			Ok(None)
+		}
+	}
+
+	#[tokio::test]
+	async fn raising_a_limit_starts_queued_jobs() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+
+		let concurrency = JobConcurrency {
+			hashing: 1,
+			encryption: 1,
+			thumbnails: 1,
+			transfers: 1,
+		};
+		let jobs = JobManager::new(concurrency);
+		for init in 0..3 {
+			Arc::clone(&jobs)
+				.ingest(&library, Job::new(init, WaitingJob))
+				.await;
+		}
+		assert_eq!(jobs.running_count().await, 1);
+
+		Arc::clone(&jobs)
+			.set_concurrency(JobConcurrency {
+				hashing: 3,
+				..concurrency
+			})
+			.await;
+		assert_eq!(jobs.running_count().await, 3);
+	}
+
+	/// How many times `StartedJob` has started.
+	static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+	/// A job that counts how many times it started, then never finishes.
+	struct StartedJob;
+
+	#[async_trait::async_trait]
+	impl StatefulJob for StartedJob {
+		type Init = ();
+		type Data = ();
+		type Step = ();
+
+		fn name(&self) -> &'static str {
+			"started"
+		}
+
+		async fn init(&self, _: WorkerContext, _: &mut JobState<Self>) -> Result<(), JobError> {
+			STARTED.fetch_add(1, Ordering::SeqCst);
+			std::future::pending().await
+		}
+
+		async fn execute_step(
+			&self,
+			_: WorkerContext,
+			_: &mut JobState<Self>,
+		) -> Result<(), JobError> {
+			Ok(())
+		}
+
+		async fn finalize(&mut self, _: WorkerContext, _: &mut JobState<Self>) -> JobResult {
+			Ok(None)
+		}
+	}
+
+	#[tokio::test]
+	async fn jobs_dispatched_while_paused_run_on_resume() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+
+		node.pause().await;
+		Arc::clone(&node.jobs)
+			.ingest(&library, Job::new((), StartedJob))
+			.await;
+
+		sleep(Duration::from_millis(100)).await;
+		assert_eq!(node.jobs.running_count().await, 0);
+		assert_eq!(STARTED.load(Ordering::SeqCst), 0);
+
+		node.resume().await;
+		tokio::time::timeout(Duration::from_secs(5), async {
+			while STARTED.load(Ordering::SeqCst) == 0 {
+				sleep(Duration::from_millis(10)).await;
+			}
+		})
+		.await
+		.expect("the job never started");
+		assert_eq!(node.jobs.running_count().await, 1);
+	}
+}
@@ -1,6 +1,6 @@
 use crate::{
 	location::{indexer::IndexerError, LocationError, LocationManagerError},
-	object::{identifier_job::IdentifierJobError, preview::ThumbnailError},
+	object::{identifier_job::IdentifierJobError, preview::ThumbnailError, tag::TagError},
 };
 
 use std::{
@@ -16,10 +16,16 @@ use thiserror::Error;
 use tracing::warn;
 use uuid::Uuid;
 
+mod cancel;
+mod concurrency;
 mod job_manager;
+mod queue;
 mod worker;
 
+pub use cancel::*;
+pub use concurrency::*;
 pub use job_manager::*;
+pub use queue::*;
 pub use worker::*;
 
 #[derive(Error, Debug)]
@@ -63,6 +69,8 @@ pub enum JobError {
 	IdentifierError(#[from] IdentifierJobError),
 	#[error("Crypto error: {0}")]
 	CryptoError(#[from] CryptoError),
+	#[error("Tag error: {0}")]
+	TagError(#[from] TagError),
 
 	// Not errors
 	#[error("Job had a early finish: <name='{name}', reason='{reason}'>")]
@@ -71,6 +79,8 @@ pub enum JobError {
 	JobDataNotFound(String),
 	#[error("Job paused")]
 	Paused(Vec<u8>),
+	#[error("Job canceled")]
+	Canceled,
 }
 
 pub type JobResult = Result<JobMetadata, JobError>;
@@ -82,7 +92,23 @@ pub trait StatefulJob: Send + Sync + Sized {
 	type Data: Serialize + DeserializeOwned + Send + Sync;
 	type Step: Serialize + DeserializeOwned + Send + Sync;
 
+	/// Which concurrency limit the job counts against.
+	const CATEGORY: JobCategory = JobCategory::General;
+
+	/// Whether the job writes to the library's database, which only one such job can do at a time.
+	const WRITES_TO_DB: bool = true;
+
+	/// Whether the state is saved after every step, so the job picks up from its last step if the
+	/// node stops without pausing it first, e.g. when it crashes.
+	const CHECKPOINT_STEPS: bool = false;
+
 	fn name(&self) -> &'static str;
+
+	/// The part of a location the job works on, if it's bound to one.
+	fn scope(_library_id: Uuid, _init: &Self::Init) -> Option<JobScope> {
+		None
+	}
+
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError>;
 
 	async fn execute_step(
@@ -98,6 +124,9 @@ pub trait StatefulJob: Send + Sync + Sized {
 pub trait DynJob: Send + Sync {
 	fn report(&mut self) -> &mut Option<JobReport>;
 	fn name(&self) -> &'static str;
+	fn scope(&self, library_id: Uuid) -> Option<JobScope>;
+	fn category(&self) -> JobCategory;
+	fn writes_to_db(&self) -> bool;
 	async fn run(&mut self, ctx: WorkerContext) -> JobResult;
 	fn hash(&self) -> u64;
 }
@@ -171,10 +200,25 @@ impl<State: StatefulJob> DynJob for Job<State> {
 		self.stateful_job.name()
 	}
 
+	fn scope(&self, library_id: Uuid) -> Option<JobScope> {
+		State::scope(library_id, &self.state.init)
+	}
+
+	fn category(&self) -> JobCategory {
+		State::CATEGORY
+	}
+
+	fn writes_to_db(&self) -> bool {
+		State::WRITES_TO_DB
+	}
+
 	async fn run(&mut self, ctx: WorkerContext) -> JobResult {
 		// Checking if we have a brand new job, or if we are resuming an old one.
 		if self.state.data.is_none() {
-			self.stateful_job.init(ctx.clone(), &mut self.state).await?;
+			tokio::select! {
+				init_result = self.stateful_job.init(ctx.clone(), &mut self.state) => init_result?,
+				_ = ctx.cancel_token.canceled() => return Err(JobError::Canceled),
+			}
 		}
 
 		let mut shutdown_rx = ctx.shutdown_rx();
@@ -182,6 +226,11 @@ impl<State: StatefulJob> DynJob for Job<State> {
 		tokio::pin!(shutdown_rx_fut);
 
 		while !self.state.steps.is_empty() {
+			// A step which finished right as the job was canceled mustn't be followed by another
+			if ctx.cancel_token.is_canceled() {
+				return Err(JobError::Canceled);
+			}
+
 			tokio::select! {
 				step_result = self.stateful_job.execute_step(
 					ctx.clone(),
@@ -194,6 +243,9 @@ impl<State: StatefulJob> DynJob for Job<State> {
 						step_result?;
 					};
 					self.state.steps.pop_front();
+					if State::CHECKPOINT_STEPS {
+						ctx.checkpoint(rmp_serde::to_vec_named(&self.state)?);
+					}
 				}
 				_ = &mut shutdown_rx_fut => {
 					return Err(
@@ -202,13 +254,17 @@ impl<State: StatefulJob> DynJob for Job<State> {
 						)
 					);
 				}
+				_ = ctx.cancel_token.canceled() => {
+					return Err(JobError::Canceled);
+				}
 			}
 			self.state.step_number += 1;
 		}
 
-		self.stateful_job
-			.finalize(ctx.clone(), &mut self.state)
-			.await
+		tokio::select! {
+			finalize_result = self.stateful_job.finalize(ctx.clone(), &mut self.state) => finalize_result,
+			_ = ctx.cancel_token.canceled() => Err(JobError::Canceled),
+		}
 	}
 
 	fn hash(&self) -> u64 {
@@ -217,3 +273,127 @@ impl<State: StatefulJob> DynJob for Job<State> {
 		hasher.finish()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::Node;
+
+	use std::{
+		sync::{
+			atomic::{AtomicUsize, Ordering},
+			Arc,
+		},
+		time::Duration,
+	};
+
+	use tokio::time::timeout;
+
+	/// Writes a number of blocks, one per step, like the encryption jobs do.
+	struct BlocksJob {
+		written: Arc<AtomicUsize>,
+		cancel_token: CancelToken,
+		/// Cancels the job once this many blocks are written
+		cancel_after: usize,
+		init_forever: bool,
+	}
+
+	#[async_trait::async_trait]
+	impl StatefulJob for BlocksJob {
+		type Init = usize;
+		type Data = ();
+		type Step = usize;
+
+		fn name(&self) -> &'static str {
+			"blocks"
+		}
+
+		async fn init(
+			&self,
+			_ctx: WorkerContext,
+			state: &mut JobState<Self>,
+		) -> Result<(), JobError> {
+			if self.init_forever {
+				std::future::pending::<()>().await;
+			}
+
+			state.steps = (0..state.init).collect();
+			state.data = Some(());
+			Ok(())
+		}
+
+		async fn execute_step(
+			&self,
+			_ctx: WorkerContext,
+			_state: &mut JobState<Self>,
+		) -> Result<(), JobError> {
+			if self.written.fetch_add(1, Ordering::SeqCst) + 1 == self.cancel_after {
+				self.cancel_token.cancel();
+			}
+			Ok(())
+		}
+
+		async fn finalize(
+			&mut self,
+			_ctx: WorkerContext,
+			_state: &mut JobState<Self>,
+		) -> JobResult {
+			Ok(None)
+		}
+	}
+
+	#[tokio::test]
+	async fn cancel_after_one_block() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+
+		let cancel_token = CancelToken::default();
+		let written = Arc::new(AtomicUsize::new(0));
+		let mut job = Job::new(
+			4,
+			BlocksJob {
+				written: Arc::clone(&written),
+				cancel_token: cancel_token.clone(),
+				cancel_after: 1,
+				init_forever: false,
+			},
+		);
+
+		let (ctx, _events) = WorkerContext::for_test(library, cancel_token);
+		assert!(matches!(job.run(ctx).await, Err(JobError::Canceled)));
+		assert_eq!(written.load(Ordering::SeqCst), 1);
+	}
+
+	#[tokio::test]
+	async fn cancel_during_init() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+
+		let cancel_token = CancelToken::default();
+		let written = Arc::new(AtomicUsize::new(0));
+		let mut job = Job::new(
+			4,
+			BlocksJob {
+				written: Arc::clone(&written),
+				cancel_token: cancel_token.clone(),
+				cancel_after: 0,
+				init_forever: true,
+			},
+		);
+
+		let (ctx, _events) = WorkerContext::for_test(library, cancel_token.clone());
+		let running = tokio::spawn(async move { job.run(ctx).await });
+		tokio::task::yield_now().await;
+
+		cancel_token.cancel();
+		let result = timeout(Duration::from_secs(5), running)
+			.await
+			.expect("init wasn't interrupted")
+			.unwrap();
+		assert!(matches!(result, Err(JobError::Canceled)));
+		assert_eq!(written.load(Ordering::SeqCst), 0);
+	}
+}
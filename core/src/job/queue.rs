@@ -0,0 +1,194 @@
+use std::{collections::VecDeque, path::PathBuf};
+
+use uuid::Uuid;
+
+/// How many times a job can be overtaken by higher priority ones before it runs regardless,
+/// so a steady stream of high priority jobs can't starve the rest of the queue.
+const MAX_OVERTAKES: usize = 8;
+
+/// The part of a location a job works on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobScope {
+	/// Location ids are only unique within their library
+	pub library_id: Uuid,
+	pub location_id: i32,
+	/// A folder relative to the location's root, where an empty path is the whole location
+	pub path: PathBuf,
+}
+
+impl JobScope {
+	/// Two scopes overlap if one of them is inside the other.
+	pub fn overlaps(&self, other: &Self) -> bool {
+		self.library_id == other.library_id
+			&& self.location_id == other.location_id
+			&& (self.path.starts_with(&other.path) || other.path.starts_with(&self.path))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobPriority {
+	Normal,
+	High,
+}
+
+struct QueuedJob<T> {
+	job: T,
+	priority: JobPriority,
+	overtaken: usize,
+}
+
+/// A queue of jobs waiting for a worker, where high priority jobs go first.
+///
+/// Jobs of the same priority run in the order they were queued.
+pub struct JobQueue<T> {
+	jobs: VecDeque<QueuedJob<T>>,
+}
+
+impl<T> Default for JobQueue<T> {
+	fn default() -> Self {
+		Self {
+			jobs: VecDeque::new(),
+		}
+	}
+}
+
+impl<T> JobQueue<T> {
+	pub fn push(&mut self, job: T, priority: JobPriority) {
+		self.jobs.push_back(QueuedJob {
+			job,
+			priority,
+			overtaken: 0,
+		});
+	}
+
+	/// Takes the next job out of the queue, skipping the jobs that can't run right now.
+	pub fn pop(&mut self, mut can_run: impl FnMut(&T) -> bool) -> Option<T> {
+		let runnable = self
+			.jobs
+			.iter()
+			.enumerate()
+			.filter(|(_, queued)| can_run(&queued.job))
+			.map(|(index, queued)| (index, queued.priority, queued.overtaken))
+			.collect::<Vec<_>>();
+
+		let (index, ..) = runnable
+			.iter()
+			.find(|(.., overtaken)| *overtaken >= MAX_OVERTAKES)
+			.or_else(|| {
+				runnable
+					.iter()
+					.find(|(_, priority, _)| *priority == JobPriority::High)
+			})
+			.or_else(|| runnable.first())
+			.copied()?;
+
+		// Every job that could have run before the one we picked has just been overtaken
+		for (overtaken_index, ..) in runnable.iter().take_while(|(i, ..)| *i < index) {
+			self.jobs[*overtaken_index].overtaken += 1;
+		}
+
+		self.jobs.remove(index).map(|queued| queued.job)
+	}
+
+	/// Bumps every queued job matching `predicate` to high priority, returning how many there were.
+	pub fn prioritize(&mut self, mut predicate: impl FnMut(&T) -> bool) -> usize {
+		let mut prioritized = 0;
+
+		for queued in self.jobs.iter_mut() {
+			if queued.priority != JobPriority::High && predicate(&queued.job) {
+				queued.priority = JobPriority::High;
+				prioritized += 1;
+			}
+		}
+
+		prioritized
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn high_priority_jobs_run_first_without_starving_the_rest() {
+		let mut queue = JobQueue::default();
+
+		queue.push("library", JobPriority::Normal);
+		queue.push("viewed folder", JobPriority::High);
+		assert_eq!(queue.pop(|_| true), Some("viewed folder"));
+
+		// once the viewed folder keeps adding jobs, the pending one eventually gets its turn anyway
+		for _ in 0..=MAX_OVERTAKES {
+			queue.push("viewed folder", JobPriority::High);
+		}
+
+		let order = std::iter::from_fn(|| queue.pop(|_| true)).collect::<Vec<_>>();
+		assert_eq!(order.len(), MAX_OVERTAKES + 2);
+		assert_eq!(order.iter().position(|job| *job == "library"), Some(7));
+	}
+
+	#[test]
+	fn prioritize_pending_jobs_in_scope() {
+		let library = Uuid::new_v4();
+		let other_library = Uuid::new_v4();
+
+		let viewed = JobScope {
+			library_id: library,
+			location_id: 1,
+			path: PathBuf::from("photos/2022"),
+		};
+		let mut queue = JobQueue::default();
+
+		for (library_id, location_id, path) in [
+			(library, 1, ""),
+			(library, 1, "documents"),
+			(library, 2, "photos/2022"),
+			(other_library, 1, "photos/2022"),
+			(library, 1, "photos"),
+		] {
+			queue.push(
+				JobScope {
+					library_id,
+					location_id,
+					path: PathBuf::from(path),
+				},
+				JobPriority::Normal,
+			);
+		}
+
+		// the whole location and the parent folder both cover the viewed folder, while the same
+		// location id in another library is a different location
+		assert_eq!(queue.prioritize(|scope| scope.overlaps(&viewed)), 2);
+		assert_eq!(queue.prioritize(|scope| scope.overlaps(&viewed)), 0);
+
+		let order = std::iter::from_fn(|| queue.pop(|_| true))
+			.map(|scope| (scope.library_id, scope.location_id, scope.path))
+			.collect::<Vec<_>>();
+
+		assert_eq!(
+			order,
+			[
+				(library, 1, PathBuf::new()),
+				(library, 1, PathBuf::from("photos")),
+				(library, 1, PathBuf::from("documents")),
+				(library, 2, PathBuf::from("photos/2022")),
+				(other_library, 1, PathBuf::from("photos/2022")),
+			]
+		);
+	}
+
+	#[test]
+	fn jobs_that_cant_run_are_skipped() {
+		let mut queue = JobQueue::default();
+
+		queue.push(("hashing", 1), JobPriority::High);
+		queue.push(("thumbnails", 2), JobPriority::Normal);
+		queue.push(("hashing", 3), JobPriority::Normal);
+
+		// with hashing saturated, the thumbnails go first even though they were queued later
+		assert_eq!(queue.pop(|job| job.0 != "hashing"), Some(("thumbnails", 2)));
+		assert_eq!(queue.pop(|job| job.0 != "hashing"), None);
+		assert_eq!(queue.pop(|_| true), Some(("hashing", 1)));
+		assert_eq!(queue.pop(|_| true), Some(("hashing", 3)));
+	}
+}
@@ -1,5 +1,7 @@
+use crate::api::CoreEvent;
+use crate::error::CoreError;
 use crate::invalidate_query;
-use crate::job::{DynJob, JobError, JobManager, JobReportUpdate, JobStatus};
+use crate::job::{CancelToken, DynJob, JobError, JobManager, JobReportUpdate, JobStatus};
 use crate::library::LibraryContext;
 use std::{sync::Arc, time::Duration};
 use tokio::sync::oneshot;
@@ -22,9 +24,11 @@ pub enum WorkerEvent {
 		updates: Vec<JobReportUpdate>,
 		debounce: bool,
 	},
+	Checkpointed(Vec<u8>),
 	Completed(oneshot::Sender<()>, JobMetadata),
 	Failed(oneshot::Sender<()>),
 	Paused(Vec<u8>, oneshot::Sender<()>),
+	Canceled(oneshot::Sender<()>),
 }
 
 #[derive(Clone)]
@@ -32,6 +36,7 @@ pub struct WorkerContext {
 	pub library_ctx: LibraryContext,
 	events_tx: UnboundedSender<WorkerEvent>,
 	shutdown_tx: Arc<broadcast::Sender<()>>,
+	pub cancel_token: CancelToken,
 }
 
 impl WorkerContext {
@@ -52,6 +57,13 @@ impl WorkerContext {
 			.expect("critical error: failed to send worker worker progress event updates");
 	}
 
+	/// Saves the state of the job, to resume it from here if the node stops while it's running.
+	pub fn checkpoint(&self, state: Vec<u8>) {
+		self.events_tx
+			.send(WorkerEvent::Checkpointed(state))
+			.expect("critical error: failed to send worker checkpoint event");
+	}
+
 	pub fn shutdown_rx(&self) -> broadcast::Receiver<()> {
 		self.shutdown_tx.subscribe()
 	}
@@ -64,6 +76,7 @@ pub struct Worker {
 	report: JobReport,
 	worker_events_tx: UnboundedSender<WorkerEvent>,
 	worker_events_rx: Option<UnboundedReceiver<WorkerEvent>>,
+	cancel_token: CancelToken,
 }
 
 impl Worker {
@@ -75,12 +88,18 @@ impl Worker {
 			report,
 			worker_events_tx,
 			worker_events_rx: Some(worker_events_rx),
+			cancel_token: CancelToken::default(),
 		}
 	}
 
 	pub fn report(&self) -> JobReport {
 		self.report.clone()
 	}
+
+	/// Asks the job to stop, which it does at its next await point.
+	pub fn cancel(&self) {
+		self.cancel_token.cancel();
+	}
 	// spawns a thread and extracts channel sender to communicate with it
 	pub async fn spawn(
 		job_manager: Arc<JobManager>,
@@ -102,6 +121,7 @@ impl Worker {
 
 		let job_hash = job.hash();
 		let job_id = worker.report.id;
+		let cancel_token = worker.cancel_token.clone();
 		let old_status = worker.report.status;
 
 		worker.report.status = JobStatus::Running;
@@ -129,6 +149,7 @@ impl Worker {
 				library_ctx,
 				events_tx: worker_events_tx,
 				shutdown_tx: job_manager.shutdown_tx(),
+				cancel_token,
 			};
 
 			// track time
@@ -168,8 +189,17 @@ impl Worker {
 						.send(WorkerEvent::Paused(state, done_tx))
 						.expect("critical error: failed to send worker pause event");
 				}
+				Err(JobError::Canceled) => {
+					worker_ctx
+						.events_tx
+						.send(WorkerEvent::Canceled(done_tx))
+						.expect("critical error: failed to send worker cancel event");
+				}
 				Err(e) => {
 					error!("job '{}' failed with error: {:#?}", job_id, e);
+					worker_ctx
+						.library_ctx
+						.emit(CoreError::from(e).to_client_failure());
 					worker_ctx
 						.events_tx
 						.send(WorkerEvent::Failed(done_tx))
@@ -180,7 +210,7 @@ impl Worker {
 			if let Err(e) = done_rx.await {
 				error!("failed to wait for worker completion: {:#?}", e);
 			}
-			job_manager.complete(&ctx, job_id, job_hash).await;
+			job_manager.complete(job_id, job_hash).await;
 		});
 
 		Ok(())
@@ -229,6 +259,12 @@ impl Worker {
 
 					invalidate_query!(library, "jobs.getRunning");
 				}
+				WorkerEvent::Checkpointed(state) => {
+					worker.report.data = Some(state);
+					if let Err(e) = worker.report.update(&library).await {
+						error!("failed to update job report: {:#?}", e);
+					}
+				}
 				WorkerEvent::Completed(done_tx, metadata) => {
 					worker.report.status = JobStatus::Completed;
 					worker.report.data = None;
@@ -251,6 +287,7 @@ impl Worker {
 				}
 				WorkerEvent::Failed(done_tx) => {
 					worker.report.status = JobStatus::Failed;
+					library.metrics().job_failed();
 					worker.report.data = None;
 					if let Err(e) = worker.report.update(&library).await {
 						error!("failed to update job report: {:#?}", e);
@@ -266,6 +303,28 @@ impl Worker {
 
 					break;
 				}
+				WorkerEvent::Canceled(done_tx) => {
+					worker.report.status = JobStatus::Canceled;
+					worker.report.data = None;
+					if let Err(e) = worker.report.update(&library).await {
+						error!("failed to update job report: {:#?}", e);
+					}
+
+					invalidate_query!(library, "jobs.isRunning");
+					invalidate_query!(library, "jobs.getRunning");
+					invalidate_query!(library, "jobs.getHistory");
+
+					info!("{}", worker.report);
+					library.emit(CoreEvent::JobCanceled {
+						id: worker.report.id,
+					});
+
+					done_tx
+						.send(())
+						.expect("critical error: failed to send worker completion");
+
+					break;
+				}
 				WorkerEvent::Paused(state, done_tx) => {
 					worker.report.status = JobStatus::Paused;
 					worker.report.data = Some(state);
@@ -287,3 +346,24 @@ impl Worker {
 		}
 	}
 }
+
+#[cfg(test)]
+impl WorkerContext {
+	/// A context for running a job outside of a worker, along with the events it sends.
+	pub(crate) fn for_test(
+		library_ctx: LibraryContext,
+		cancel_token: CancelToken,
+	) -> (Self, UnboundedReceiver<WorkerEvent>) {
+		let (events_tx, events_rx) = unbounded_channel();
+
+		(
+			Self {
+				library_ctx,
+				events_tx,
+				shutdown_tx: Arc::new(broadcast::channel(1).0),
+				cancel_token,
+			},
+			events_rx,
+		)
+	}
+}
@@ -1,18 +1,30 @@
 use api::{CoreEvent, Ctx, Router};
-use job::JobManager;
+use job::{JobConcurrency, JobManager};
 use library::LibraryManager;
 use location::{LocationManager, LocationManagerError};
-use node::NodeConfigManager;
+use node::{NodeConfigError, NodeConfigManager, NodeMetrics};
+use object::preview::ThumbnailRequests;
 use util::secure_temp_keystore::SecureTempKeystore;
 
 use std::{path::Path, sync::Arc};
 use thiserror::Error;
-use tokio::{fs, sync::broadcast};
-use tracing::{debug, error, info};
+use tokio::{
+	fs,
+	sync::{broadcast, mpsc, watch},
+	task::JoinHandle,
+};
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::{prelude::*, EnvFilter};
 
+pub use node::Metrics;
+
+/// The folder the node's data is kept in when it's created with `Node::new_in`, unless told otherwise.
+pub const DEFAULT_APP_FOLDER: &str = "spacedrive";
+
 pub mod api;
+pub mod bindings;
 pub mod custom_uri;
+pub mod error;
 pub(crate) mod job;
 pub(crate) mod library;
 pub(crate) mod location;
@@ -31,14 +43,32 @@ pub struct NodeContext {
 	pub jobs: Arc<JobManager>,
 	pub location_manager: Arc<LocationManager>,
 	pub event_bus_tx: broadcast::Sender<CoreEvent>,
+	pub thumbnail_requests: Arc<ThumbnailRequests>,
+	pub metrics: Arc<NodeMetrics>,
+}
+
+impl NodeContext {
+	/// Sends an event to everyone subscribed to the event bus, see [`library::LibraryContext::emit`].
+	pub(crate) fn emit(&self, event: CoreEvent) {
+		let result = self.event_bus_tx.send(event);
+		self.metrics.event_emitted(result.is_ok());
+
+		if let Err(e) = result {
+			warn!("Error sending event to event bus: {e:?}");
+		}
+	}
 }
 
 pub struct Node {
 	config: Arc<NodeConfigManager>,
 	library_manager: Arc<LibraryManager>,
 	jobs: Arc<JobManager>,
+	location_manager: Arc<LocationManager>,
 	event_bus: (broadcast::Sender<CoreEvent>, broadcast::Receiver<CoreEvent>),
 	secure_temp_keystore: Arc<SecureTempKeystore>,
+	metrics: Arc<NodeMetrics>,
+	paused_tx: watch::Sender<bool>,
+	volume_watcher: JoinHandle<()>,
 }
 
 #[cfg(not(feature = "android"))]
@@ -52,11 +82,26 @@ const CONSOLE_LOG_FILTER: tracing_subscriber::filter::LevelFilter = {
 };
 
 impl Node {
+	/// Creates a node which keeps its data in the `app_folder` subfolder of `data_dir`.
+	///
+	/// Each subfolder holds its own config and libraries, so several isolated nodes can share the same parent.
+	pub async fn new_in(
+		data_dir: impl AsRef<Path>,
+		app_folder: &str,
+	) -> Result<(Arc<Node>, Arc<Router>), NodeError> {
+		Self::new(data_dir.as_ref().join(app_folder)).await
+	}
+
 	pub async fn new(data_dir: impl AsRef<Path>) -> Result<(Arc<Node>, Arc<Router>), NodeError> {
 		let data_dir = data_dir.as_ref();
 
-		// This error is ignored because it's throwing on mobile despite the folder existing.
-		let _ = fs::create_dir_all(&data_dir).await;
+		// `create_dir_all` can fail on mobile despite the folder existing, so that case isn't an error.
+		let created = !data_dir.is_dir();
+		if let Err(e) = fs::create_dir_all(&data_dir).await {
+			if !data_dir.is_dir() {
+				return Err(NodeError::FailedToCreateDataDirectory(e));
+			}
+		}
 
 		// dbg!(get_object_kind_from_extension("png"));
 
@@ -103,51 +148,46 @@ impl Node {
 		let subscriber = subscriber.with(tracing_subscriber::fmt::layer().with_filter(CONSOLE_LOG_FILTER));
 		#[cfg(feature = "android")]
 		let subscriber = subscriber.with(tracing_android::layer("com.spacedrive.app").unwrap()); // TODO: This is not working
-		subscriber
+		// This only fails if another node in this process already set up tracing, which is fine
+		let _ = subscriber
 			// .with(
 			// 	Layer::default()
 			// 		.with_writer(non_blocking)
 			// 		.with_ansi(false)
 			// 		.with_filter(LevelFilter::DEBUG),
 			// )
-			.init();
+			.try_init();
 
 		let event_bus = broadcast::channel(1024);
-		let config = NodeConfigManager::new(data_dir.to_path_buf()).await?;
+		let config = match NodeConfigManager::new(data_dir.to_path_buf()).await {
+			Ok(config) => config,
+			Err(e) => return Err(Self::abandon(data_dir, created, e.into()).await),
+		};
 
-		let jobs = JobManager::new();
+		let jobs = JobManager::new(config.get().await.job_concurrency);
 		let location_manager = LocationManager::new();
 		let secure_temp_keystore = SecureTempKeystore::new();
-		let library_manager = LibraryManager::new(
+		let metrics = Arc::new(NodeMetrics::default());
+		let library_manager = match LibraryManager::new(
 			data_dir.join("libraries"),
 			NodeContext {
 				config: Arc::clone(&config),
 				jobs: Arc::clone(&jobs),
 				location_manager: Arc::clone(&location_manager),
 				event_bus_tx: event_bus.0.clone(),
+				thumbnail_requests: Default::default(),
+				metrics: Arc::clone(&metrics),
 			},
 		)
-		.await?;
+		.await
+		{
+			Ok(library_manager) => library_manager,
+			Err(e) => return Err(Self::abandon(data_dir, created, e.into()).await),
+		};
 
 		// Adding already existing locations for location management
 		for library_ctx in library_manager.get_all_libraries_ctx().await {
-			for location in library_ctx
-				.db
-				.location()
-				.find_many(vec![])
-				.exec()
-				.await
-				.unwrap_or_else(|e| {
-					error!(
-						"Failed to get locations from database for location manager: {:#?}",
-						e
-					);
-					vec![]
-				}) {
-				if let Err(e) = location_manager.add(location.id, library_ctx.clone()).await {
-					error!("Failed to add location to location manager: {:#?}", e);
-				}
-			}
+			library::start_library(&library_ctx).await;
 		}
 
 		debug!("Watching locations");
@@ -163,19 +203,38 @@ impl Node {
 			}
 		});
 
+		let (paused_tx, paused_rx) = watch::channel(false);
+		let volume_watcher = volume::watch_volumes(event_bus.0.clone(), paused_rx);
+
 		let router = api::mount();
 		let node = Node {
 			config,
 			library_manager,
 			jobs,
+			location_manager,
 			event_bus,
 			secure_temp_keystore,
+			metrics,
+			paused_tx,
+			volume_watcher,
 		};
 
 		info!("Spacedrive online.");
 		Ok((Arc::new(node), router))
 	}
 
+	/// Removes the data directory if it was created for a node that then failed to start, so the next attempt
+	/// starts from scratch instead of from a half-initialised folder.
+	async fn abandon(data_dir: &Path, created: bool, error: NodeError) -> NodeError {
+		if created {
+			if let Err(e) = fs::remove_dir_all(data_dir).await {
+				warn!("Failed to remove data directory after failed initialization: {e:?}");
+			}
+		}
+
+		error
+	}
+
 	pub fn get_request_context(&self) -> Ctx {
 		Ctx {
 			library_manager: Arc::clone(&self.library_manager),
@@ -186,22 +245,188 @@ impl Node {
 		}
 	}
 
+	/// Returns a snapshot of the node's counters, for observability integrations to export.
+	pub async fn metrics(&self) -> Metrics {
+		self.metrics
+			.snapshot(self.jobs.running_count().await as u64)
+	}
+
+	/// Changes how many jobs of each category can run at once, and saves it to the node's config.
+	///
+	/// Jobs that are already running aren't affected, while queued jobs start as soon as there's room.
+	pub async fn set_job_concurrency(
+		&self,
+		concurrency: JobConcurrency,
+	) -> Result<(), NodeConfigError> {
+		self.config
+			.write(|mut config| config.job_concurrency = concurrency)
+			.await?;
+		Arc::clone(&self.jobs).set_concurrency(concurrency).await;
+
+		Ok(())
+	}
+
+	/// Pauses all background activity, until [`Node::resume`] is called.
+	///
+	/// No new jobs start, running jobs are paused at their next step, file system events are
+	/// kept without being applied to the locations, and volumes aren't checked for changes.
+	pub async fn pause(&self) {
+		self.jobs.suspend().await;
+		self.location_manager.pause();
+		self.paused_tx.send_replace(true);
+
+		info!("Background activity paused");
+		self.emit(CoreEvent::ActivityPaused);
+	}
+
+	/// Picks up everything paused by [`Node::pause`], including the file system events received
+	/// in the meantime.
+	pub async fn resume(&self) {
+		self.location_manager.resume();
+		self.paused_tx.send_replace(false);
+		Arc::clone(&self.jobs)
+			.resume(self.library_manager.get_all_libraries_ctx().await)
+			.await;
+
+		info!("Background activity resumed");
+		self.emit(CoreEvent::ActivityResumed);
+	}
+
+	/// Returns a channel that receives the core events `filter` returns true for, so a consumer that only
+	/// cares about a few kinds of events doesn't have to go through all of them.
+	///
+	/// Each subscriber has its own buffer, and events that don't fit in it are dropped for that subscriber only.
+	pub fn subscribe(
+		&self,
+		filter: impl Fn(&CoreEvent) -> bool + Send + 'static,
+	) -> mpsc::Receiver<CoreEvent> {
+		api::utils::filtered_events(
+			self.event_bus.0.subscribe(),
+			filter,
+			api::utils::SUBSCRIBER_CAPACITY,
+		)
+	}
+
+	fn emit(&self, event: CoreEvent) {
+		self.library_manager.node_context.emit(event);
+	}
+
 	pub async fn shutdown(&self) {
 		info!("Spacedrive shutting down...");
 		self.jobs.pause().await;
+		// the backup schedules aren't tied to the node, so they'd otherwise keep running without it
+		for library_ctx in self.library_manager.get_all_libraries_ctx().await {
+			library::backup::unschedule(library_ctx.id).await;
+		}
+		self.volume_watcher.abort();
 		info!("Spacedrive Core shutdown successful!");
 	}
 }
 
+impl Drop for Node {
+	fn drop(&mut self) {
+		// the watcher would otherwise outlive the node, e.g. when several nodes are created in the same process
+		self.volume_watcher.abort();
+	}
+}
+
 /// Error type for Node related errors.
 #[derive(Error, Debug)]
 pub enum NodeError {
 	#[error("Failed to create data directory: {0}")]
 	FailedToCreateDataDirectory(#[from] std::io::Error),
 	#[error("Failed to initialize config: {0}")]
-	FailedToInitializeConfig(#[from] node::NodeConfigError),
+	FailedToInitializeConfig(#[from] NodeConfigError),
 	#[error("Failed to initialize library manager: {0}")]
 	FailedToInitializeLibraryManager(#[from] library::LibraryManagerError),
 	#[error("Location manager error: {0}")]
 	LocationManager(#[from] LocationManagerError),
 }
+
+#[cfg(test)]
+mod tests {
+	use uuid::Uuid;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn nodes_in_different_app_folders() {
+		let data_dir = tempfile::tempdir().unwrap();
+
+		let (first, _) = Node::new_in(data_dir.path(), "first").await.unwrap();
+		let (second, _) = Node::new_in(data_dir.path(), "second").await.unwrap();
+
+		let first_dir = first.config.data_directory();
+		let second_dir = second.config.data_directory();
+
+		assert_eq!(first_dir, data_dir.path().join("first"));
+		assert_eq!(second_dir, data_dir.path().join("second"));
+
+		// library databases are kept in each node's libraries folder, so they can't collide
+		assert!(first_dir.join("libraries").is_dir());
+		assert!(second_dir.join("libraries").is_dir());
+	}
+
+	#[tokio::test]
+	async fn volume_watcher_stops_on_shutdown() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+
+		node.shutdown().await;
+		tokio::time::timeout(std::time::Duration::from_secs(5), async {
+			while !node.volume_watcher.is_finished() {
+				tokio::task::yield_now().await;
+			}
+		})
+		.await
+		.expect("the volume watcher is still running");
+	}
+
+	#[tokio::test]
+	async fn data_dir_that_cant_be_created() {
+		let data_dir = tempfile::tempdir().unwrap();
+		// a file in the way can't be written through, unlike permissions which don't apply to root
+		let blocker = data_dir.path().join("blocker");
+		std::fs::write(&blocker, []).unwrap();
+
+		let result = Node::new_in(&blocker, "node").await;
+		assert!(matches!(
+			result,
+			Err(NodeError::FailedToCreateDataDirectory(_))
+		));
+		assert!(std::fs::metadata(&blocker).unwrap().is_file());
+	}
+
+	#[tokio::test]
+	async fn unavailable_library_database_doesnt_stop_the_node() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let libraries_dir = data_dir.path().join("node").join("libraries");
+		std::fs::create_dir_all(&libraries_dir).unwrap();
+
+		let library_id = Uuid::new_v4();
+		std::fs::write(
+			libraries_dir.join(format!("{library_id}.sdlibrary")),
+			serde_json::to_string(&library::LibraryConfig::default()).unwrap(),
+		)
+		.unwrap();
+		// a folder where the database should be can't be read, like a database on a drive that isn't mounted yet
+		std::fs::create_dir(libraries_dir.join(format!("{library_id}.db"))).unwrap();
+
+		let (mut node, _) = Node::new_in(data_dir.path(), "node").await.unwrap();
+		assert!(node
+			.library_manager
+			.get_all_libraries_ctx()
+			.await
+			.is_empty());
+
+		let events = &mut Arc::get_mut(&mut node).unwrap().event_bus.1;
+		let mut unavailable = false;
+		while let Ok(event) = events.try_recv() {
+			unavailable |= matches!(
+				event,
+				CoreEvent::LibraryDatabaseUnavailable { library_id: id, .. } if id == library_id
+			);
+		}
+		assert!(unavailable);
+	}
+}
@@ -0,0 +1,545 @@
+use crate::{
+	api::CoreEvent,
+	library::LibraryContext,
+	location::LocationError,
+	object::bundle::{write_bundle, BundleEntry, BundleError},
+	prisma::{location, PrismaClient},
+};
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use prisma_client_rust::{raw, PrismaValue, QueryError};
+use rspc::{ErrorCode, Type};
+use sd_crypto::{fs::temp::SecureTempFile, Protected};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+	fs, io,
+	sync::Mutex,
+	task::JoinHandle,
+	time::{self, MissedTickBehavior},
+};
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub const BACKUP_EXTENSION: &str = "sdbackup";
+
+/// Backups are written to this directory in the root of the destination location.
+pub const BACKUP_DIR_NAME: &str = "Spacedrive Backups";
+
+/// How often a schedule checks whether a backup is due, which is also the shortest interval it can keep.
+const BACKUP_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The running schedules, by library id. There's at most one for each library.
+static SCHEDULES: Lazy<Mutex<HashMap<Uuid, JoinHandle<()>>>> =
+	Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+	#[error("Location error")]
+	LocationError(#[from] LocationError),
+	#[error("Bundle error")]
+	BundleError(#[from] BundleError),
+	#[error("Crypto error (error: {0:?})")]
+	CryptoError(#[from] sd_crypto::Error),
+	#[error("I/O error (error: {0:?})")]
+	IOError(#[from] io::Error),
+	#[error("Database error (error: {0:?})")]
+	DatabaseError(#[from] QueryError),
+}
+
+impl From<BackupError> for rspc::Error {
+	fn from(err: BackupError) -> Self {
+		match err {
+			BackupError::LocationError(e) => e.into(),
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy)]
+pub struct BackupSchedule {
+	/// The location the backups are written to.
+	pub location_id: i32,
+	pub interval_secs: u64,
+	/// How many backups are kept, the oldest ones are deleted after every backup.
+	pub keep: u32,
+}
+
+impl BackupSchedule {
+	fn interval(&self) -> chrono::Duration {
+		chrono::Duration::seconds(self.interval_secs.try_into().unwrap_or(i64::MAX))
+	}
+}
+
+/// Where the current time comes from, so schedules can be tested without waiting for it to pass.
+pub trait Clock: Send + Sync + 'static {
+	fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> DateTime<Utc> {
+		Utc::now()
+	}
+}
+
+/// Keeps track of when the last backup was taken, to tell whether the next one is due.
+struct BackupTimer<C> {
+	clock: C,
+	interval: chrono::Duration,
+	last_backup: Option<DateTime<Utc>>,
+}
+
+impl<C: Clock> BackupTimer<C> {
+	fn new(clock: C, interval: chrono::Duration) -> Self {
+		Self {
+			clock,
+			interval,
+			last_backup: None,
+		}
+	}
+
+	/// Returns the current time if a backup is due. The first backup is due as soon as the schedule starts.
+	fn due(&self) -> Option<DateTime<Utc>> {
+		let now = self.clock.now();
+
+		match self.last_backup {
+			Some(last_backup) if now - last_backup < self.interval => None,
+			_ => Some(now),
+		}
+	}
+
+	fn backed_up(&mut self, at: DateTime<Utc>) {
+		self.last_backup = Some(at);
+	}
+}
+
+/// Starts backing up the library's database to a location, replacing the library's previous schedule if it had one.
+///
+/// Every backup is an encrypted bundle of a snapshot of the database and the library's config, encrypted with the
+/// library's default key. The schedule itself is kept in the library's config (see [`LibraryManager::set_backup_schedule`]),
+/// so it's restarted when the node starts.
+///
+/// [`LibraryManager::set_backup_schedule`]: super::LibraryManager::set_backup_schedule
+pub async fn schedule(library: LibraryContext, schedule: BackupSchedule) {
+	schedule_with_clock(library, schedule, SystemClock).await
+}
+
+pub(crate) async fn schedule_with_clock(
+	library: LibraryContext,
+	schedule: BackupSchedule,
+	clock: impl Clock,
+) {
+	let library_id = library.id;
+
+	let handle = tokio::spawn(async move {
+		let mut timer = BackupTimer::new(clock, schedule.interval());
+		let mut interval = time::interval(BACKUP_POLL_INTERVAL);
+		interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		// skipping is only reported once, rather than every time the offline destination is polled
+		let mut skipped = false;
+
+		loop {
+			interval.tick().await;
+
+			let Some(now) = timer.due() else {
+				continue;
+			};
+
+			match run_backup(&library, &schedule, now).await {
+				Ok(Some(path)) => {
+					timer.backed_up(now);
+					skipped = false;
+
+					library.emit(CoreEvent::BackupCreated {
+						library_id: library.id,
+						path,
+					});
+				}
+				// the backup is taken as soon as the destination is back online
+				Ok(None) => {
+					if !skipped {
+						info!(
+							"Skipping backup of library {}, location {} is offline",
+							library.id, schedule.location_id
+						);
+						library.emit(CoreEvent::BackupSkipped {
+							library_id: library.id,
+							location_id: schedule.location_id,
+						});
+					}
+					skipped = true;
+				}
+				// the backup isn't retried until the next one is due, so a persistent failure isn't reported every minute
+				Err(e) => {
+					error!("Failed to back up library {}: {e:#?}", library.id);
+					timer.backed_up(now);
+					skipped = false;
+
+					library.emit(CoreEvent::BackupFailed {
+						library_id: library.id,
+						message: e.to_string(),
+					});
+				}
+			}
+		}
+	});
+
+	if let Some(previous) = SCHEDULES.lock().await.insert(library_id, handle) {
+		previous.abort();
+	}
+}
+
+/// Stops backing up the library, returning `false` if it wasn't being backed up.
+pub async fn unschedule(library_id: Uuid) -> bool {
+	match SCHEDULES.lock().await.remove(&library_id) {
+		Some(handle) => {
+			handle.abort();
+			true
+		}
+		None => false,
+	}
+}
+
+/// Takes a backup, returning its path, or `None` if the destination location is offline.
+async fn run_backup(
+	library: &LibraryContext,
+	schedule: &BackupSchedule,
+	now: DateTime<Utc>,
+) -> Result<Option<PathBuf>, BackupError> {
+	let location = library
+		.db
+		.location()
+		.find_unique(location::id::equals(schedule.location_id))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(schedule.location_id))?;
+
+	let location_path = PathBuf::from(location.path);
+	if !fs::metadata(&location_path)
+		.await
+		.map(|metadata| metadata.is_dir())
+		.unwrap_or(false)
+	{
+		return Ok(None);
+	}
+
+	let key = library
+		.key_manager
+		.get_key(library.key_manager.get_default().await?)
+		.await?;
+	let password = Protected::new(key.expose().as_bytes().to_vec());
+
+	let libraries_dir = library.config().data_directory().join("libraries");
+
+	let snapshot = snapshot(&library.db, &libraries_dir).await?;
+
+	let config_path = libraries_dir.join(format!("{}.sdlibrary", library.id));
+	let dst_dir = location_path.join(BACKUP_DIR_NAME);
+
+	let path = write_backup(
+		library.id,
+		&[
+			("library.db", snapshot.path()),
+			("library.sdlibrary", config_path.as_path()),
+		],
+		&dst_dir,
+		password,
+		now,
+	)
+	.await?;
+	snapshot.erase().await?;
+
+	let pruned = prune(&dst_dir, library.id, schedule.keep).await?;
+
+	info!(
+		"Backed up library {} to {}, pruning {} old backups",
+		library.id,
+		path.display(),
+		pruned.len()
+	);
+
+	Ok(Some(path))
+}
+
+/// Writes a consistent copy of the database into `dir`, which can be taken while the library is in use.
+///
+/// The snapshot is plaintext, so it must be erased as soon as it's been encrypted.
+async fn snapshot(db: &PrismaClient, dir: &Path) -> Result<SecureTempFile, BackupError> {
+	let snapshot = SecureTempFile::new_in(dir).await?;
+	db._execute_raw(raw!(
+		"VACUUM INTO {}",
+		PrismaValue::String(snapshot.path().to_string_lossy().to_string())
+	))
+	.exec()
+	.await?;
+
+	Ok(snapshot)
+}
+
+/// Backups are named after their library and when they were taken, so sorting them by name sorts them by age.
+fn backup_file_name(library_id: Uuid, taken_at: DateTime<Utc>) -> String {
+	format!(
+		"{library_id}-{}.{BACKUP_EXTENSION}",
+		taken_at.format("%Y%m%dT%H%M%SZ")
+	)
+}
+
+/// Encrypts the files into a new backup in `dst_dir`, which only appears once it's been completely written.
+async fn write_backup(
+	library_id: Uuid,
+	files: &[(&str, &Path)],
+	dst_dir: &Path,
+	password: Protected<Vec<u8>>,
+	taken_at: DateTime<Utc>,
+) -> Result<PathBuf, BackupError> {
+	fs::create_dir_all(dst_dir).await?;
+
+	let mut entries = Vec::with_capacity(files.len());
+	for (name, path) in files {
+		entries.push((
+			BundleEntry {
+				path: name.to_string(),
+				size: fs::metadata(path).await?.len(),
+				tags: vec![],
+			},
+			path.to_path_buf(),
+		));
+	}
+
+	let mut temp = SecureTempFile::new_in(dst_dir).await?;
+	write_bundle(entries, &mut temp, password).await?;
+
+	let path = dst_dir.join(backup_file_name(library_id, taken_at));
+	temp.persist(&path).await?;
+
+	Ok(path)
+}
+
+/// Deletes all but the `keep` newest backups of the library in `dir`, returning the paths of the ones that were deleted.
+async fn prune(dir: &Path, library_id: Uuid, keep: u32) -> io::Result<Vec<PathBuf>> {
+	let prefix = format!("{library_id}-");
+	let suffix = format!(".{BACKUP_EXTENSION}");
+
+	let mut backups = vec![];
+	let mut read_dir = fs::read_dir(dir).await?;
+	while let Some(entry) = read_dir.next_entry().await? {
+		let file_name = entry.file_name().to_string_lossy().to_string();
+		if file_name.starts_with(&prefix) && file_name.ends_with(&suffix) {
+			backups.push(file_name);
+		}
+	}
+	backups.sort();
+
+	let pruned = backups.len().saturating_sub(keep.max(1) as usize);
+
+	let mut removed = Vec::with_capacity(pruned);
+	for file_name in backups.into_iter().take(pruned) {
+		let path = dir.join(file_name);
+		fs::remove_file(&path).await?;
+		removed.push(path);
+	}
+
+	Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::object::bundle::open_bundle;
+
+	use std::sync::{Arc, Mutex};
+
+	use chrono::TimeZone;
+	use sd_crypto::header::file::is_encrypted_file;
+	use tokio::fs::File;
+
+	#[derive(Clone)]
+	struct FakeClock(Arc<Mutex<DateTime<Utc>>>);
+
+	impl FakeClock {
+		fn advance(&self, by: chrono::Duration) {
+			*self.0.lock().unwrap() += by;
+		}
+	}
+
+	impl Clock for FakeClock {
+		fn now(&self) -> DateTime<Utc> {
+			*self.0.lock().unwrap()
+		}
+	}
+
+	fn password() -> Protected<Vec<u8>> {
+		Protected::new(b"correct horse battery staple".to_vec())
+	}
+
+	#[tokio::test]
+	async fn backups_are_taken_when_due_and_pruned() {
+		let library = tempfile::tempdir().unwrap();
+		let location = tempfile::tempdir().unwrap();
+		let dst_dir = location.path().join(BACKUP_DIR_NAME);
+
+		let (db, config) = (
+			library.path().join("library.db"),
+			library.path().join("library.sdlibrary"),
+		);
+		fs::write(&db, b"SQLite format 3\0not really")
+			.await
+			.unwrap();
+		fs::write(&config, br#"{"name":"Library"}"#).await.unwrap();
+		let files = [
+			("library.db", db.as_path()),
+			("library.sdlibrary", config.as_path()),
+		];
+
+		let library_id = Uuid::new_v4();
+		let clock = FakeClock(Arc::new(Mutex::new(
+			Utc.with_ymd_and_hms(2023, 3, 10, 12, 0, 0).unwrap(),
+		)));
+		let mut timer = BackupTimer::new(clock.clone(), chrono::Duration::hours(1));
+
+		let mut taken = vec![];
+		for _ in 0..3 {
+			let now = timer.due().expect("a backup is due");
+			taken.push(
+				write_backup(library_id, &files, &dst_dir, password(), now)
+					.await
+					.unwrap(),
+			);
+			timer.backed_up(now);
+			prune(&dst_dir, library_id, 2).await.unwrap();
+
+			clock.advance(chrono::Duration::minutes(30));
+			assert!(timer.due().is_none());
+			clock.advance(chrono::Duration::minutes(30));
+		}
+
+		assert!(!taken[0].exists());
+		for path in &taken[1..] {
+			assert!(is_encrypted_file(&mut File::open(path).await.unwrap())
+				.await
+				.unwrap());
+
+			let (manifest, _) = open_bundle(File::open(path).await.unwrap(), password())
+				.await
+				.unwrap();
+			assert_eq!(
+				manifest
+					.entries
+					.iter()
+					.map(|entry| entry.path.as_str())
+					.collect::<Vec<_>>(),
+				vec!["library.db", "library.sdlibrary"]
+			);
+		}
+
+		// the plaintext never ends up next to the backups
+		let mut read_dir = fs::read_dir(&dst_dir).await.unwrap();
+		let mut names = vec![];
+		while let Some(entry) = read_dir.next_entry().await.unwrap() {
+			names.push(entry.file_name().to_string_lossy().to_string());
+		}
+		names.sort();
+		assert_eq!(
+			names,
+			vec![
+				backup_file_name(
+					library_id,
+					Utc.with_ymd_and_hms(2023, 3, 10, 13, 0, 0).unwrap()
+				),
+				backup_file_name(
+					library_id,
+					Utc.with_ymd_and_hms(2023, 3, 10, 14, 0, 0).unwrap()
+				),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn other_libraries_backups_are_not_pruned() {
+		let dir = tempfile::tempdir().unwrap();
+		let (library_id, other_id) = (Uuid::new_v4(), Uuid::new_v4());
+		let taken_at = Utc.with_ymd_and_hms(2023, 3, 10, 12, 0, 0).unwrap();
+
+		for id in [library_id, other_id] {
+			fs::write(dir.path().join(backup_file_name(id, taken_at)), b"")
+				.await
+				.unwrap();
+		}
+		fs::write(dir.path().join("notes.txt"), b"").await.unwrap();
+
+		assert!(prune(dir.path(), library_id, 1).await.unwrap().is_empty());
+		assert!(prune(dir.path(), other_id, 1).await.unwrap().is_empty());
+		assert!(dir.path().join("notes.txt").exists());
+	}
+
+	#[tokio::test]
+	async fn deleting_a_library_stops_its_backups() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = crate::Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+
+		schedule(
+			library.clone(),
+			BackupSchedule {
+				location_id: 0,
+				interval_secs: 3600,
+				keep: 1,
+			},
+		)
+		.await;
+		assert!(SCHEDULES.lock().await.contains_key(&library.id));
+
+		node.library_manager
+			.delete_library(library.id)
+			.await
+			.unwrap();
+		assert!(!unschedule(library.id).await);
+	}
+
+	#[tokio::test]
+	async fn snapshot_is_a_copy_of_the_database() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = crate::Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+
+		let tag = library
+			.db
+			.tag()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				vec![crate::prisma::tag::name::set(Some("Taxes".to_string()))],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		let snapshot = snapshot(&library.db, data_dir.path()).await.unwrap();
+		let path = snapshot.path().to_path_buf();
+
+		let copy = crate::prisma::new_client_with_url(&format!("file:{}", path.display()))
+			.await
+			.unwrap();
+		let copied = copy.tag().find_many(vec![]).exec().await.unwrap();
+		assert_eq!(
+			copied
+				.into_iter()
+				.map(|copied| (copied.pub_id, copied.name))
+				.collect::<Vec<_>>(),
+			[(tag.pub_id, tag.name)]
+		);
+		drop(copy);
+
+		snapshot.erase().await.unwrap();
+		assert!(!path.exists());
+	}
+}
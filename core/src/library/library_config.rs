@@ -11,7 +11,7 @@ use uuid::Uuid;
 
 use crate::node::ConfigMetadata;
 
-use super::LibraryManagerError;
+use super::{backup::BackupSchedule, LibraryManagerError};
 
 /// LibraryConfig holds the configuration for a specific library. This is stored as a '{uuid}.sdlibrary' file.
 #[derive(Debug, Serialize, Deserialize, Clone, Type, Default)]
@@ -22,6 +22,9 @@ pub struct LibraryConfig {
 	pub name: String,
 	/// description is a user set description of the library. This is used in the UI and is set by the user.
 	pub description: String,
+	/// backup_schedule is the schedule the library's backups are taken on, which is restarted when the node starts.
+	#[serde(default)]
+	pub backup_schedule: Option<BackupSchedule>,
 	// /// is_encrypted is a flag that is set to true if the library is encrypted.
 	// #[serde(default)]
 	// pub is_encrypted: bool,
@@ -1,16 +1,24 @@
 use crate::{
-	api::CoreEvent, job::DynJob, location::LocationManager, node::NodeConfigManager,
-	object::preview::THUMBNAIL_CACHE_DIR_NAME, prisma::PrismaClient, sync::SyncManager,
+	api::CoreEvent,
+	job::DynJob,
+	location::LocationManager,
+	node::{NodeConfigManager, NodeMetrics},
+	object::{
+		preview::{request_thumbnail, ThumbnailError, ThumbnailRequests, THUMBNAIL_CACHE_DIR_NAME},
+		recents::RecentAccesses,
+	},
+	prisma::PrismaClient,
+	sync::SyncManager,
 	NodeContext,
 };
 
 use std::{
 	fmt::{Debug, Formatter},
+	path::PathBuf,
 	sync::Arc,
 };
 
 use sd_crypto::keys::keymanager::KeyManager;
-use tracing::warn;
 use uuid::Uuid;
 
 use super::LibraryConfig;
@@ -31,6 +39,8 @@ pub struct LibraryContext {
 	pub key_manager: Arc<KeyManager>,
 	/// node_local_id holds the local ID of the node which is running the library.
 	pub node_local_id: i32,
+	/// recents holds the accesses to objects which are waiting to be written, see [`crate::object::recents::record_access`].
+	pub(crate) recents: Arc<RecentAccesses>,
 	/// node_context holds the node context for the node which this library is running on.
 	pub(super) node_context: NodeContext,
 }
@@ -54,13 +64,19 @@ impl LibraryContext {
 	}
 
 	pub(crate) async fn queue_job(&self, job: Box<dyn DynJob>) {
-		self.node_context.jobs.ingest_queue(job).await;
+		self.node_context.jobs.ingest_queue(self, job).await;
 	}
 
+	/// Sends an event to everyone subscribed to the event bus.
+	///
+	/// This never blocks or awaits, as the event bus is a broadcast channel which drops the oldest events
+	/// for subscribers that fall behind, so it's fine to call from hot paths such as progress reporting.
 	pub(crate) fn emit(&self, event: CoreEvent) {
-		if let Err(e) = self.node_context.event_bus_tx.send(event) {
-			warn!("Error sending event to event bus: {e:?}");
-		}
+		self.node_context.emit(event);
+	}
+
+	pub(crate) fn metrics(&self) -> &NodeMetrics {
+		&self.node_context.metrics
 	}
 
 	pub(crate) fn config(&self) -> Arc<NodeConfigManager> {
@@ -71,6 +87,15 @@ impl LibraryContext {
 		&self.node_context.location_manager
 	}
 
+	pub(crate) fn thumbnail_requests(&self) -> &Arc<ThumbnailRequests> {
+		&self.node_context.thumbnail_requests
+	}
+
+	/// request_thumbnail returns the path to an object's thumbnail, lazily generating it if it doesn't exist yet.
+	pub async fn request_thumbnail(&self, object_id: i32) -> Result<PathBuf, ThumbnailError> {
+		request_thumbnail(self, object_id).await
+	}
+
 	pub async fn thumbnail_exists(&self, cas_id: &str) -> tokio::io::Result<bool> {
 		let thumb_path = self
 			.config()
@@ -1,10 +1,11 @@
 use crate::{
+	api::CoreEvent,
 	invalidate_query,
 	node::Platform,
 	prisma::{node, PrismaClient},
 	sync::SyncManager,
 	util::{
-		db::{load_and_migrate, write_storedkey_to_db},
+		db::{load_and_migrate, prepare_database_file, write_storedkey_to_db, MigrationError},
 		seeder::{indexer_rules_seeder, SeederError},
 	},
 	NodeContext,
@@ -19,13 +20,17 @@ use std::{
 	path::{Path, PathBuf},
 	str::FromStr,
 	sync::Arc,
+	time::Duration,
 };
 use thiserror::Error;
 use tokio::sync::RwLock;
-use tracing::debug;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use super::{LibraryConfig, LibraryConfigWrapped, LibraryContext};
+use super::{
+	backup::{self, BackupSchedule},
+	LibraryConfig, LibraryConfigWrapped, LibraryContext,
+};
 
 /// LibraryManager is a singleton that manages all libraries for a node.
 pub struct LibraryManager {
@@ -57,8 +62,29 @@ pub enum LibraryManagerError {
 	Seeder(#[from] SeederError),
 	#[error("failed to initialise the key manager")]
 	KeyManager(#[from] sd_crypto::Error),
+	#[error("failed to load the database: {0}")]
+	DatabaseLoad(#[from] MigrationError),
+}
+
+impl LibraryManagerError {
+	/// Whether the library's database couldn't be reached, in a way that may be fixed by trying again (e.g. it's locked or on a drive that isn't mounted yet).
+	///
+	/// Corrupt databases and invalid configs won't get better on their own, so they aren't transient.
+	pub(crate) fn is_transient(&self) -> bool {
+		matches!(
+			self,
+			Self::IO(_)
+				| Self::Database(_)
+				| Self::DatabaseLoad(MigrationError::NewClient(_) | MigrationError::IO(_))
+		)
+	}
 }
 
+/// How long to wait before the first attempt to load a library whose database was unavailable at startup again, which doubles after each failure.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// The longest wait between attempts, so a drive that's mounted hours later is still picked up soon after.
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
 impl From<LibraryManagerError> for rspc::Error {
 	fn from(error: LibraryManagerError) -> Self {
 		rspc::Error::with_cause(
@@ -130,6 +156,7 @@ impl LibraryManager {
 		fs::create_dir_all(&libraries_dir)?;
 
 		let mut libraries = Vec::new();
+		let mut unavailable = Vec::new();
 		for entry in fs::read_dir(&libraries_dir)?
 			.into_iter()
 			.filter_map(|entry| entry.ok())
@@ -163,7 +190,21 @@ impl LibraryManager {
 			}
 
 			let config = LibraryConfig::read(config_path).await?;
-			libraries.push(Self::load(library_id, &db_path, config, node_context.clone()).await?);
+			match Self::load(library_id, &db_path, config.clone(), node_context.clone()).await {
+				Ok(library) => libraries.push(library),
+				// the rest of the node keeps running without this library, and it's loaded once its database is back
+				Err(e) if e.is_transient() => {
+					error!(
+						"Failed to load library '{library_id}', retrying in the background: {e}"
+					);
+					node_context.emit(CoreEvent::LibraryDatabaseUnavailable {
+						library_id,
+						reason: e.to_string(),
+					});
+					unavailable.push((library_id, db_path, config));
+				}
+				Err(e) => return Err(e),
+			}
 		}
 
 		let this = Arc::new(Self {
@@ -172,11 +213,57 @@ impl LibraryManager {
 			node_context,
 		});
 
+		for (library_id, db_path, config) in unavailable {
+			tokio::spawn(Arc::clone(&this).reconnect(library_id, db_path, config));
+		}
+
 		debug!("LibraryManager initialized");
 
 		Ok(this)
 	}
 
+	/// Tries to load a library whose database was unavailable at startup again, with an increasing delay between attempts, up to `RECONNECT_MAX_DELAY`.
+	///
+	/// Once it loads, the library is started the same way as the ones that loaded at startup.
+	async fn reconnect(self: Arc<Self>, library_id: Uuid, db_path: PathBuf, config: LibraryConfig) {
+		let mut delay = RECONNECT_DELAY;
+
+		for attempt in 1.. {
+			tokio::time::sleep(delay).await;
+			delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+
+			match Self::load(
+				library_id,
+				&db_path,
+				config.clone(),
+				self.node_context.clone(),
+			)
+			.await
+			{
+				Ok(library) => {
+					info!("Loaded library '{library_id}' after its database became available");
+
+					self.libraries.write().await.push(library.clone());
+
+					start_library(&library).await;
+					if let Err(e) = Arc::clone(&self.node_context.jobs)
+						.resume_jobs(&library)
+						.await
+					{
+						error!("Failed to resume jobs for library. {:#?}", e);
+					}
+
+					self.node_context
+						.emit(CoreEvent::LibraryDatabaseRecovered { library_id });
+					invalidate_query!(library, "library.list");
+
+					return;
+				}
+				Err(e) => warn!("Attempt {attempt} to load library '{library_id}' failed: {e}"),
+			}
+		}
+	}
+
 	/// create creates a new library with the given config and mounts it into the running [LibraryManager].
 	pub(crate) async fn create(
 		&self,
@@ -263,6 +350,31 @@ impl LibraryManager {
 		Ok(())
 	}
 
+	/// Saves the schedule the library is backed up on, or that it isn't backed up anymore, so it survives restarts.
+	pub(crate) async fn set_backup_schedule(
+		&self,
+		id: Uuid,
+		schedule: Option<BackupSchedule>,
+	) -> Result<(), LibraryManagerError> {
+		let mut libraries = self.libraries.write().await;
+		let library = libraries
+			.iter_mut()
+			.find(|lib| lib.id == id)
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		library.config.backup_schedule = schedule;
+
+		LibraryConfig::save(
+			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
+			&library.config,
+		)
+		.await?;
+
+		invalidate_query!(library, "library.list");
+
+		Ok(())
+	}
+
 	pub async fn delete_library(&self, id: Uuid) -> Result<(), LibraryManagerError> {
 		let mut libraries = self.libraries.write().await;
 
@@ -271,6 +383,9 @@ impl LibraryManager {
 			.find(|l| l.id == id)
 			.ok_or(LibraryManagerError::LibraryNotFound)?;
 
+		// the schedule holds on to the library's database, so it would keep backing it up
+		backup::unschedule(id).await;
+
 		fs::remove_file(Path::new(&self.libraries_dir).join(format!("{}.db", library.id)))?;
 		fs::remove_file(Path::new(&self.libraries_dir).join(format!("{}.sdlibrary", library.id)))?;
 
@@ -299,6 +414,9 @@ impl LibraryManager {
 		node_context: NodeContext,
 	) -> Result<LibraryContext, LibraryManagerError> {
 		let db_path = db_path.as_ref();
+
+		prepare_database_file(db_path).await?;
+
 		let db = Arc::new(
 			load_and_migrate(&format!(
 				"file:{}",
@@ -306,8 +424,7 @@ impl LibraryManager {
 					LibraryManagerError::InvalidDatabasePath(db_path.to_path_buf())
 				})?
 			))
-			.await
-			.unwrap(),
+			.await?,
 		);
 
 		let node_config = node_context.config.get().await;
@@ -347,7 +464,111 @@ impl LibraryManager {
 			sync: Arc::new(sync_manager),
 			db,
 			node_local_id: node_data.id,
+			recents: Default::default(),
 			node_context,
 		})
 	}
 }
+
+#[cfg(test)]
+impl LibraryManager {
+	/// Creates a library for tests which need a real database.
+	pub(crate) async fn create_test_library(&self) -> LibraryContext {
+		use sd_crypto::{
+			crypto::stream::Algorithm,
+			keys::hashing::{HashingAlgorithm, Params},
+			Protected,
+		};
+
+		let library = self
+			.create(
+				LibraryConfig {
+					name: "Test".to_string(),
+					..Default::default()
+				},
+				OnboardingConfig {
+					password: Protected::new("password".to_string()),
+					algorithm: Algorithm::XChaCha20Poly1305,
+					hashing_algorithm: HashingAlgorithm::Argon2id(Params::Standard),
+				},
+			)
+			.await
+			.unwrap();
+
+		self.get_ctx(library.uuid).await.unwrap()
+	}
+}
+
+/// Creates a node with a test library, see [`LibraryManager::create_test_library`].
+///
+/// The node and its data directory are returned too, as the library can't be used once they're dropped.
+#[cfg(test)]
+pub(crate) async fn test_library() -> (tempfile::TempDir, Arc<crate::Node>, LibraryContext) {
+	let data_dir = tempfile::tempdir().unwrap();
+	let (node, _) = crate::Node::new(data_dir.path()).await.unwrap();
+	let library = node.library_manager.create_test_library().await;
+
+	(data_dir, node, library)
+}
+
+/// Creates an object with `params` set, returning its id.
+#[cfg(test)]
+pub(crate) async fn create_test_object(
+	db: &PrismaClient,
+	params: Vec<crate::prisma::object::SetParam>,
+) -> i32 {
+	db.object()
+		.create(Uuid::new_v4().as_bytes().to_vec(), params)
+		.exec()
+		.await
+		.unwrap()
+		.id
+}
+
+/// Creates a location at `path` on the library's node, returning its id. Nothing in it is indexed.
+#[cfg(test)]
+pub(crate) async fn create_test_location(library: &LibraryContext, path: &Path) -> i32 {
+	library
+		.db
+		.location()
+		.create(
+			Uuid::new_v4().as_bytes().to_vec(),
+			path.file_name().unwrap().to_str().unwrap().to_string(),
+			path.to_str().unwrap().to_string(),
+			node::id::equals(library.node_local_id),
+			vec![],
+		)
+		.exec()
+		.await
+		.unwrap()
+		.id
+}
+
+/// Adds a library's locations to the location manager and restarts its backup schedule, which is done for every library once it's loaded.
+pub(crate) async fn start_library(library_ctx: &LibraryContext) {
+	for location in library_ctx
+		.db
+		.location()
+		.find_many(vec![])
+		.exec()
+		.await
+		.unwrap_or_else(|e| {
+			error!(
+				"Failed to get locations from database for location manager: {:#?}",
+				e
+			);
+			vec![]
+		}) {
+		if let Err(e) = library_ctx
+			.location_manager()
+			.add(location.id, library_ctx.clone())
+			.await
+		{
+			error!("Failed to add location to location manager: {:#?}", e);
+		}
+	}
+
+	if let Some(schedule) = library_ctx.config.backup_schedule {
+		backup::schedule(library_ctx.clone(), schedule).await;
+	}
+}
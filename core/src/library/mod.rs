@@ -1,3 +1,4 @@
+pub mod backup;
 mod library_config;
 mod library_ctx;
 mod library_manager;
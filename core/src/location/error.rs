@@ -25,6 +25,8 @@ pub enum LocationError {
 	NotDirectory(PathBuf),
 	#[error("Could not find directory in Location (path: {0:?})")]
 	DirectoryNotFound(String),
+	#[error("Path must be relative to the Location and stay inside it (path: {0:?})")]
+	InvalidSubpath(PathBuf),
 	#[error("Library exists in the location metadata file, must relink: (old_path: {old_path:?}, new_path: {new_path:?})")]
 	NeedRelink {
 		old_path: PathBuf,
@@ -71,6 +73,7 @@ impl From<LocationError> for rspc::Error {
 			// User's fault errors
 			LocationError::NotDirectory(_)
 			// | LocationError::MissingLocalPath(_)
+			| LocationError::InvalidSubpath(_)
 			| LocationError::NeedRelink { .. }
 			| LocationError::AddLibraryToMetadata(_) => {
 				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
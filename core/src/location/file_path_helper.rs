@@ -1,6 +1,9 @@
-use crate::{library::LibraryContext, prisma::file_path};
+use crate::{library::LibraryContext, location::LocationError, prisma::file_path};
 
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::{
+	path::{Component, Path},
+	sync::atomic::{AtomicI32, Ordering},
+};
 
 use prisma_client_rust::{Direction, QueryError};
 
@@ -35,7 +38,6 @@ async fn fetch_max_file_path_id(library_ctx: &LibraryContext) -> Result<i32, Que
 		.unwrap_or(0))
 }
 
-#[cfg(feature = "location-watcher")]
 pub async fn create_file_path(
 	library_ctx: &LibraryContext,
 	location_id: i32,
@@ -80,3 +82,16 @@ pub async fn create_file_path(
 
 	Ok(created_path)
 }
+
+/// Checks that a path given relative to a location can't point outside of it, which an absolute path or a `..`
+/// component would.
+pub fn check_subpath(subpath: &Path) -> Result<(), LocationError> {
+	if subpath
+		.components()
+		.all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+	{
+		Ok(())
+	} else {
+		Err(LocationError::InvalidSubpath(subpath.to_path_buf()))
+	}
+}
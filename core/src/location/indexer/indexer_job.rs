@@ -10,25 +10,33 @@ use std::{
 	collections::HashMap,
 	ffi::OsStr,
 	hash::{Hash, Hasher},
-	path::PathBuf,
+	path::{Path, PathBuf},
 	time::Duration,
 };
 
 use chrono::{DateTime, Utc};
-use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::time::Instant;
+use tokio::{
+	sync::{mpsc, Mutex},
+	task::JoinHandle,
+};
 use tracing::info;
 
 use super::{
 	super::file_path_helper::{get_max_file_path_id, set_max_file_path_id},
 	rules::IndexerRule,
-	walk::{walk, WalkEntry},
+	stream::{walk_in_batches, StreamLimits},
+	walk::WalkEntry,
+	IndexerError,
 };
 
 /// BATCH_SIZE is the number of files to index at each step, writing the chunk of files metadata in the database.
 const BATCH_SIZE: usize = 1000;
+/// How many entries can wait between the stages of the walk, on top of the batch being written.
+const CHANNEL_CAPACITY: usize = 256;
+/// How many batches the walk can get ahead of the step writing them.
+const BATCHES_AHEAD: usize = 1;
 pub const INDEXER_JOB_NAME: &str = "indexer";
 
 #[derive(Clone)]
@@ -39,9 +47,88 @@ pub enum ScanProgress {
 }
 
 /// A `IndexerJob` is a stateful job that walks a directory and indexes all files.
-/// First it walks the directory and generates a list of files to index, chunked into
-/// batches of [`BATCH_SIZE`]. Then for each chunk it write the file metadata to the database.
-pub struct IndexerJob;
+/// The directory is walked in the background while each step writes the next batch of
+/// [`BATCH_SIZE`] files to the database, so the job never holds the whole list of files in memory.
+#[derive(Default)]
+pub struct IndexerJob {
+	walker: Mutex<Option<Walker>>,
+}
+
+/// A walk of the location running in the background, which hands its batches out to the steps.
+///
+/// The walk is stopped when this is dropped, so it doesn't outlive a paused or canceled job.
+struct Walker {
+	batches: mpsc::Receiver<Result<WalkedBatch, IndexerError>>,
+	handle: JoinHandle<()>,
+}
+
+/// A batch of entries, along with the checkpoint to resume the walk from once it's written.
+type WalkedBatch = (Vec<WalkEntry>, Option<PathBuf>);
+
+impl Walker {
+	fn spawn(
+		ctx: WorkerContext,
+		location: &indexer_job_location::Data,
+		checkpoint: Option<PathBuf>,
+	) -> Result<Self, JobError> {
+		let mut indexer_rules_by_kind: HashMap<RuleKind, Vec<IndexerRule>> =
+			HashMap::with_capacity(location.indexer_rules.len());
+		for location_rule in &location.indexer_rules {
+			let indexer_rule = IndexerRule::try_from(&location_rule.indexer_rule)?;
+
+			indexer_rules_by_kind
+				.entry(indexer_rule.kind)
+				.or_default()
+				.push(indexer_rule);
+		}
+
+		let root = PathBuf::from(&location.path);
+		let (batches_tx, batches) = mpsc::channel(BATCHES_AHEAD);
+
+		let handle = tokio::spawn(async move {
+			let walked = walk_in_batches(
+				root,
+				&indexer_rules_by_kind,
+				checkpoint.as_deref(),
+				StreamLimits {
+					batch_size: BATCH_SIZE,
+					channel_capacity: CHANNEL_CAPACITY,
+				},
+				|path, total_entries| {
+					IndexerJobData::on_scan_progress(
+						ctx.clone(),
+						vec![
+							ScanProgress::Message(format!("Scanning {}", path.display())),
+							ScanProgress::ChunkCount(total_entries / BATCH_SIZE),
+						],
+					);
+				},
+				|entry| entry,
+				|batch, checkpoint| {
+					let batches_tx = batches_tx.clone();
+					async move {
+						// The receiver lives as long as this task, as dropping it aborts the task
+						batches_tx.send(Ok((batch, checkpoint))).await.ok();
+						Ok::<_, IndexerError>(())
+					}
+				},
+			)
+			.await;
+
+			if let Err(e) = walked {
+				batches_tx.send(Err(e)).await.ok();
+			}
+		});
+
+		Ok(Self { batches, handle })
+	}
+}
+
+impl Drop for Walker {
+	fn drop(&mut self) {
+		self.handle.abort();
+	}
+}
 
 location::include!(indexer_job_location {
 	indexer_rules: select { indexer_rule }
@@ -66,11 +153,24 @@ pub struct IndexerJobData {
 	db_write_start: DateTime<Utc>,
 	scan_read_time: Duration,
 	total_paths: usize,
+	/// The ids given to the directories written so far, by which their children find their parent
+	#[serde(default)]
+	dirs_ids: HashMap<PathBuf, i32>,
+	/// The last directory whose entries were all written, which the walk resumes after
+	#[serde(default)]
+	checkpoint: Option<PathBuf>,
 }
 
-/// `IndexerJobStep` is a type alias, specifying that each step of the [`IndexerJob`] is a vector of
-/// `IndexerJobStepEntry`. The size of this vector is given by the [`BATCH_SIZE`] constant.
-pub type IndexerJobStep = Vec<IndexerJobStepEntry>;
+/// `IndexerJobStep` is a single batch of the [`IndexerJob`], of up to [`BATCH_SIZE`] entries.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IndexerJobStep {
+	/// Writes the next batch handed out by the walk, adding a step for the batch after it
+	NextBatch,
+	/// A batch that was already walked, as jobs paused by older versions walked the whole
+	/// location before writing anything
+	Entries(Vec<IndexerJobStepEntry>),
+}
 
 /// `IndexerJobStepEntry` represents a single file to be indexed, given its metadata to be written
 /// on the `file_path` table in the database
@@ -104,110 +204,24 @@ impl StatefulJob for IndexerJob {
 	type Data = IndexerJobData;
 	type Step = IndexerJobStep;
 
+	const CHECKPOINT_STEPS: bool = true;
+
 	fn name(&self) -> &'static str {
 		INDEXER_JOB_NAME
 	}
 
-	/// Creates a vector of valid path buffers from a directory, chunked into batches of `BATCH_SIZE`.
+	/// Starts walking the location, the batches it hands out are written by the steps.
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
-		// grab the next id so we can increment in memory for batch inserting
-		let first_file_id = get_max_file_path_id(&ctx.library_ctx).await?;
-
-		let mut indexer_rules_by_kind: HashMap<RuleKind, Vec<IndexerRule>> =
-			HashMap::with_capacity(state.init.location.indexer_rules.len());
-		for location_rule in &state.init.location.indexer_rules {
-			let indexer_rule = IndexerRule::try_from(&location_rule.indexer_rule)?;
-
-			indexer_rules_by_kind
-				.entry(indexer_rule.kind)
-				.or_default()
-				.push(indexer_rule);
-		}
-
-		let scan_start = Instant::now();
-		let inner_ctx = ctx.clone();
-		let paths = walk(
-			&state.init.location.path,
-			&indexer_rules_by_kind,
-			move |path, total_entries| {
-				IndexerJobData::on_scan_progress(
-					inner_ctx.clone(),
-					vec![
-						ScanProgress::Message(format!("Scanning {}", path.display())),
-						ScanProgress::ChunkCount(total_entries / BATCH_SIZE),
-					],
-				);
-			},
-		)
-		.await?;
-
-		let total_paths = paths.len();
-		let last_file_id = first_file_id + total_paths as i32;
-
-		// Setting our global state for file_path ids
-		set_max_file_path_id(last_file_id);
-
-		let mut dirs_ids = HashMap::new();
-		let paths_entries = paths
-			.into_iter()
-			.zip(first_file_id..last_file_id)
-			.map(
-				|(
-					WalkEntry {
-						path,
-						is_dir,
-						created_at,
-					},
-					file_id,
-				)| {
-					let parent_id = if let Some(parent_dir) = path.parent() {
-						dirs_ids.get(parent_dir).copied()
-					} else {
-						None
-					};
-
-					dirs_ids.insert(path.clone(), file_id);
-
-					IndexerJobStepEntry {
-						path,
-						created_at,
-						file_id,
-						parent_id,
-						is_dir,
-					}
-				},
-			)
-			.collect::<Vec<_>>();
-
-		let total_entries = paths_entries.len();
+		*self.walker.lock().await = Some(Walker::spawn(ctx, &state.init.location, None)?);
 
 		state.data = Some(IndexerJobData {
 			db_write_start: Utc::now(),
-			scan_read_time: scan_start.elapsed(),
-			total_paths: total_entries,
+			scan_read_time: Duration::ZERO,
+			total_paths: 0,
+			dirs_ids: HashMap::new(),
+			checkpoint: None,
 		});
-
-		state.steps = paths_entries
-			.into_iter()
-			.chunks(BATCH_SIZE)
-			.into_iter()
-			.enumerate()
-			.map(|(i, chunk)| {
-				let chunk_steps = chunk.collect::<Vec<_>>();
-				IndexerJobData::on_scan_progress(
-					ctx.clone(),
-					vec![
-						ScanProgress::SavedChunks(i),
-						ScanProgress::Message(format!(
-							"Writing {} of {} to db",
-							i * chunk_steps.len(),
-							total_entries,
-						)),
-					],
-				);
-				chunk_steps
-			})
-			.collect();
+		state.steps.push_back(IndexerJobStep::NextBatch);
 
 		Ok(())
 	}
@@ -218,95 +232,136 @@ impl StatefulJob for IndexerJob {
 		ctx: WorkerContext,
 		state: &mut JobState<Self>,
 	) -> Result<(), JobError> {
-		let LibraryContext { sync, db, .. } = &ctx.library_ctx;
-
 		let location = &state.init.location;
 
-		let (sync_stuff, paths): (Vec<_>, Vec<_>) = state.steps[0]
-			.iter()
-			.map(|entry| {
-				let name;
-				let extension;
-
-				// if 'entry.path' is a directory, set extension to an empty string to
-				// avoid periods in folder names being interpreted as file extensions
-				if entry.is_dir {
-					extension = "".to_string();
-					name = extract_name(entry.path.file_name());
-				} else {
-					// if the 'entry.path' is not a directory, then get the extension and name.
-					extension = extract_name(entry.path.extension()).to_lowercase();
-					name = extract_name(entry.path.file_stem());
-				}
+		match &state.steps[0] {
+			IndexerJobStep::Entries(entries) => {
+				write_entries(&ctx.library_ctx, location, entries).await
+			}
+			IndexerJobStep::NextBatch => {
+				let data = state
+					.data
+					.as_mut()
+					.expect("critical error: missing data on job state");
+
+				let mut walker = self.walker.lock().await;
+				// A resumed job has no walk running, so it's started again from the checkpoint
+				let walker = match &mut *walker {
+					Some(walker) => walker,
+					None => walker.insert(Walker::spawn(
+						ctx.clone(),
+						location,
+						data.checkpoint.clone(),
+					)?),
+				};
+
+				let Some(batch) = walker.batches.recv().await else {
+					data.scan_read_time = (Utc::now() - data.db_write_start)
+						.to_std()
+						.expect("critical error: non-negative duration");
+					return Ok(());
+				};
+				let (batch, checkpoint) = batch?;
+
+				// The entries after the checkpoint are handed out again when the walk is resumed,
+				// so the ones which were already written are skipped, keeping their ids
+				let existing = ctx
+					.library_ctx
+					.db
+					.file_path()
+					.find_many(vec![
+						file_path::location_id::equals(location.id),
+						file_path::materialized_path::in_vec(
+							batch
+								.iter()
+								.map(|entry| materialized_path(location, &entry.path, entry.is_dir))
+								.collect(),
+						),
+					])
+					.select(file_path::select!({ id materialized_path }))
+					.exec()
+					.await?
+					.into_iter()
+					.map(|file_path| (file_path.materialized_path, file_path.id))
+					.collect::<HashMap<_, _>>();
+
+				// grab the next id so we can increment in memory for batch inserting
+				let mut next_file_id = get_max_file_path_id(&ctx.library_ctx).await?;
+
+				let entries = batch
+					.into_iter()
+					.filter_map(
+						|WalkEntry {
+						     path,
+						     is_dir,
+						     created_at,
+						 }| {
+							if let Some(&file_id) =
+								existing.get(&materialized_path(location, &path, is_dir))
+							{
+								if is_dir {
+									data.dirs_ids.insert(path, file_id);
+								}
+								return None;
+							}
+
+							next_file_id += 1;
+							let file_id = next_file_id;
+							// Setting our global state for file_path ids, before anyone else can take this one
+							set_max_file_path_id(file_id);
+
+							let parent_id = path
+								.parent()
+								.and_then(|parent_dir| data.dirs_ids.get(parent_dir).copied());
+
+							if is_dir {
+								data.dirs_ids.insert(path.clone(), file_id);
+							}
+
+							Some(IndexerJobStepEntry {
+								path,
+								created_at,
+								file_id,
+								parent_id,
+								is_dir,
+							})
+						},
+					)
+					.collect::<Vec<_>>();
 
-				let mut materialized_path = entry
-					.path
-					.strip_prefix(&location.path)
-					.unwrap()
-					.to_str()
-					.expect("Found non-UTF-8 path")
-					.to_string();
+				let saved_chunks = data.total_paths / BATCH_SIZE;
+				data.total_paths += entries.len();
 
-				if entry.is_dir && !materialized_path.ends_with('/') {
-					materialized_path += "/";
-				}
+				IndexerJobData::on_scan_progress(
+					ctx.clone(),
+					vec![
+						ScanProgress::SavedChunks(saved_chunks),
+						ScanProgress::Message(format!("Writing {} paths to db", data.total_paths)),
+					],
+				);
 
-				use file_path::*;
+				write_entries(&ctx.library_ctx, location, &entries).await?;
 
-				(
-					sync.unique_shared_create(
-						sync::file_path::SyncId {
-							id: entry.file_id,
-							location: sync::location::SyncId {
-								pub_id: state.init.location.pub_id.clone(),
-							},
-						},
-						[
-							("materialized_path", json!(materialized_path.clone())),
-							("name", json!(name.clone())),
-							("is_dir", json!(entry.is_dir)),
-							("extension", json!(extension.clone())),
-							("parent_id", json!(entry.parent_id)),
-							("date_created", json!(entry.created_at)),
-						],
-					),
-					file_path::create_unchecked(
-						entry.file_id,
-						location.id,
-						materialized_path,
-						name,
-						extension,
-						vec![
-							is_dir::set(entry.is_dir),
-							parent_id::set(entry.parent_id),
-							date_created::set(entry.created_at.into()),
-						],
-					),
-				)
-			})
-			.unzip();
-
-		let count = sync
-			.write_ops(
-				db,
-				(
-					sync_stuff,
-					db.file_path().create_many(paths).skip_duplicates(),
-				),
-			)
-			.await?;
-
-		info!("Inserted {count} records");
+				// Only moving the checkpoint forward once its entries are written
+				if checkpoint.is_some() {
+					data.checkpoint = checkpoint;
+				}
+				state.steps.push_back(IndexerJobStep::NextBatch);
 
-		Ok(())
+				Ok(())
+			}
+		}
 	}
 
 	/// Logs some metadata about the indexer job
 	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
 		let data = state
 			.data
-			.as_ref()
+			.as_mut()
 			.expect("critical error: missing data on job state");
+		// The directories' ids were only needed while writing, they don't belong in the metadata
+		data.dirs_ids = HashMap::new();
+
 		info!(
 			"scan of {} completed in {:?}. {:?} files found. db write completed in {:?}",
 			state.init.location.path,
@@ -321,6 +376,99 @@ impl StatefulJob for IndexerJob {
 	}
 }
 
+/// Writes a batch of entries to the `file_path` table
+async fn write_entries(
+	library_ctx: &LibraryContext,
+	location: &indexer_job_location::Data,
+	entries: &[IndexerJobStepEntry],
+) -> Result<(), JobError> {
+	let LibraryContext { sync, db, .. } = library_ctx;
+
+	let (sync_stuff, paths): (Vec<_>, Vec<_>) = entries
+		.iter()
+		.map(|entry| {
+			let name;
+			let extension;
+
+			// if 'entry.path' is a directory, set extension to an empty string to
+			// avoid periods in folder names being interpreted as file extensions
+			if entry.is_dir {
+				extension = "".to_string();
+				name = extract_name(entry.path.file_name());
+			} else {
+				// if the 'entry.path' is not a directory, then get the extension and name.
+				extension = extract_name(entry.path.extension()).to_lowercase();
+				name = extract_name(entry.path.file_stem());
+			}
+
+			let materialized_path = materialized_path(location, &entry.path, entry.is_dir);
+
+			use file_path::*;
+
+			(
+				sync.unique_shared_create(
+					sync::file_path::SyncId {
+						id: entry.file_id,
+						location: sync::location::SyncId {
+							pub_id: location.pub_id.clone(),
+						},
+					},
+					[
+						("materialized_path", json!(materialized_path.clone())),
+						("name", json!(name.clone())),
+						("is_dir", json!(entry.is_dir)),
+						("extension", json!(extension.clone())),
+						("parent_id", json!(entry.parent_id)),
+						("date_created", json!(entry.created_at)),
+					],
+				),
+				file_path::create_unchecked(
+					entry.file_id,
+					location.id,
+					materialized_path,
+					name,
+					extension,
+					vec![
+						is_dir::set(entry.is_dir),
+						parent_id::set(entry.parent_id),
+						date_created::set(entry.created_at.into()),
+					],
+				),
+			)
+		})
+		.unzip();
+
+	let count = sync
+		.write_ops(
+			db,
+			(
+				sync_stuff,
+				db.file_path().create_many(paths).skip_duplicates(),
+			),
+		)
+		.await?;
+
+	info!("Inserted {count} records");
+
+	Ok(())
+}
+
+/// The path of an entry relative to the location, as it's stored in the `file_path` table
+fn materialized_path(location: &indexer_job_location::Data, path: &Path, is_dir: bool) -> String {
+	let mut materialized_path = path
+		.strip_prefix(&location.path)
+		.unwrap()
+		.to_str()
+		.expect("Found non-UTF-8 path")
+		.to_string();
+
+	if is_dir && !materialized_path.ends_with('/') {
+		materialized_path += "/";
+	}
+
+	materialized_path
+}
+
 /// Extract name from OsStr returned by PathBuff
 fn extract_name(os_string: Option<&OsStr>) -> String {
 	os_string
@@ -1,5 +1,7 @@
 pub mod indexer_job;
+pub mod reconcile;
 pub mod rules;
+mod stream;
 mod walk;
 
 use globset::Error;
@@ -0,0 +1,850 @@
+use crate::{
+	invalidate_query,
+	job::Job,
+	library::LibraryContext,
+	location::{fetch_location, file_path_helper::create_file_path, LocationError},
+	object::identifier_job::{
+		full_identifier_job::{FullFileIdentifierJob, FullFileIdentifierJobInit},
+		FileMetadata,
+	},
+	prisma::{file_path, object},
+	sync,
+	util::db::update_file_paths_batch,
+};
+
+use std::{
+	cmp::Ordering,
+	collections::HashMap,
+	ffi::OsStr,
+	future,
+	ops::ControlFlow,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use rspc::Type;
+use serde::Serialize;
+use serde_json::json;
+use tokio::{fs, io::ErrorKind};
+use tracing::info;
+
+use super::{
+	indexer_job::indexer_job_location,
+	rules::{IndexerRule, RuleKind},
+	walk::{walk_from, WalkEntry},
+	IndexerError,
+};
+
+file_path::include!(file_path_with_object { object });
+
+/// What `reconcile` found to be out of sync between a location's index and its files, by materialized path.
+///
+/// Every discrepancy in the report has been repaired by the time it's returned.
+#[derive(Serialize, Type, Debug, Default, PartialEq, Eq)]
+pub struct ReconcileReport {
+	/// Indexed, but gone from the disk. These were soft-deleted, along with the objects that have no other path left.
+	pub missing_on_disk: Vec<String>,
+	/// On the disk, but not indexed (or soft-deleted). These were indexed, or restored.
+	pub missing_in_index: Vec<String>,
+	/// Indexed, but changed on the disk since. These were unlinked from their objects, to be identified again.
+	pub stale: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedEntry {
+	id: i32,
+	object_id: Option<i32>,
+	materialized_path: String,
+	is_dir: bool,
+	deleted: bool,
+	/// Only known once the file has been identified
+	size: Option<u64>,
+	date_modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct DiskEntry {
+	materialized_path: String,
+	is_dir: bool,
+	size: u64,
+	modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct Drift {
+	missing_on_disk: Vec<IndexedEntry>,
+	missing_in_index: Vec<DiskEntry>,
+	/// Soft-deleted, but back on the disk
+	restored: Vec<IndexedEntry>,
+	stale: Vec<(IndexedEntry, DiskEntry)>,
+}
+
+impl From<&Drift> for ReconcileReport {
+	fn from(drift: &Drift) -> Self {
+		Self {
+			missing_on_disk: drift
+				.missing_on_disk
+				.iter()
+				.map(|entry| entry.materialized_path.clone())
+				.collect(),
+			missing_in_index: {
+				let mut paths = drift
+					.missing_in_index
+					.iter()
+					.map(|entry| entry.materialized_path.clone())
+					.chain(
+						drift
+							.restored
+							.iter()
+							.map(|entry| entry.materialized_path.clone()),
+					)
+					.collect::<Vec<_>>();
+				paths.sort();
+				paths
+			},
+			stale: drift
+				.stale
+				.iter()
+				.map(|(entry, _)| entry.materialized_path.clone())
+				.collect(),
+		}
+	}
+}
+
+/// Brings a location's index back in line with its files, for when they drifted apart while the watcher wasn't
+/// looking (e.g. files that changed while the app was closed).
+///
+/// The location is walked with its indexer rules, and both the walk and the index are sorted by materialized path, so
+/// they're diffed in a single pass rather than looking every path up. Paths that are gone from the disk are
+/// soft-deleted, so they (and their objects' tags, favorites and so on) come back if the files do. New paths are
+/// indexed and queued for identification, and files whose size or modification time changed are unlinked from the
+/// object of their old content and identified again.
+pub async fn reconcile(
+	library_ctx: &LibraryContext,
+	location_id: i32,
+) -> Result<ReconcileReport, LocationError> {
+	let location = fetch_location(library_ctx, location_id)
+		.include(indexer_job_location::include())
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	if location.node_id != library_ctx.node_local_id {
+		return Ok(ReconcileReport::default());
+	}
+
+	let mut rules_per_kind: HashMap<RuleKind, Vec<IndexerRule>> = HashMap::new();
+	for location_rule in &location.indexer_rules {
+		let indexer_rule = IndexerRule::try_from(&location_rule.indexer_rule)?;
+
+		rules_per_kind
+			.entry(indexer_rule.kind)
+			.or_default()
+			.push(indexer_rule);
+	}
+
+	let on_disk = walk_disk(&location.path, &rules_per_kind).await?;
+
+	let mut indexed = library_ctx
+		.db
+		.file_path()
+		.find_many(vec![file_path::location_id::equals(location.id)])
+		.include(file_path_with_object::include())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| IndexedEntry {
+			id: file_path.id,
+			object_id: file_path.object_id,
+			size: file_path
+				.object
+				.and_then(|object| object.size_in_bytes.parse().ok()),
+			materialized_path: file_path.materialized_path,
+			is_dir: file_path.is_dir,
+			deleted: file_path.date_deleted.is_some(),
+			date_modified: file_path.date_modified.into(),
+		})
+		.collect::<Vec<_>>();
+	indexed.sort_by(|a, b| a.materialized_path.cmp(&b.materialized_path));
+
+	let mut dirs_ids = indexed
+		.iter()
+		.filter(|entry| entry.is_dir)
+		.map(|entry| (entry.materialized_path.clone(), entry.id))
+		.collect::<HashMap<_, _>>();
+
+	let drift = diff(indexed, on_disk);
+	let report = ReconcileReport::from(&drift);
+
+	let now = Utc::now();
+	set_date_deleted(library_ctx, &location, &drift.missing_on_disk, Some(now)).await?;
+	set_date_deleted(library_ctx, &location, &drift.restored, None).await?;
+
+	// New directories are created before their children, which find them as their parent
+	for entry in &drift.missing_in_index {
+		let path = Path::new(&entry.materialized_path);
+		let (name, extension) = if entry.is_dir {
+			(name_part(path.file_name()), String::new())
+		} else {
+			(
+				name_part(path.file_stem()),
+				name_part(path.extension()).to_lowercase(),
+			)
+		};
+
+		let created = create_file_path(
+			library_ctx,
+			location.id,
+			entry.materialized_path.clone(),
+			name,
+			extension,
+			dirs_ids.get(&parent_materialized_path(path)).copied(),
+			entry.is_dir,
+		)
+		.await?;
+
+		if entry.is_dir {
+			dirs_ids.insert(entry.materialized_path.clone(), created.id);
+		}
+	}
+
+	// unlinked first, so the size of their old content isn't overwritten on its object
+	unlink_stale(library_ctx, &location, &drift.stale, now).await?;
+	update_file_paths_batch(
+		&library_ctx.db,
+		location.id,
+		&drift
+			.stale
+			.iter()
+			.map(|(indexed, on_disk)| {
+				(indexed.id, on_disk.size, SystemTime::from(on_disk.modified))
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await?;
+
+	// the new and unlinked paths become objects through the identifier, like anything else the indexer finds
+	if !drift.missing_in_index.is_empty() || !drift.stale.is_empty() {
+		library_ctx
+			.queue_job(Job::new(
+				FullFileIdentifierJobInit {
+					location_id: location.id,
+					sub_path: None,
+				},
+				FullFileIdentifierJob {},
+			))
+			.await;
+	}
+
+	info!(
+		"Reconciled location {}: {} missing on disk, {} missing in index, {} stale",
+		location.id,
+		report.missing_on_disk.len(),
+		report.missing_in_index.len(),
+		report.stale.len()
+	);
+
+	invalidate_query!(library_ctx, "locations.getExplorerData");
+
+	Ok(report)
+}
+
+/// Walks the location, returning what's on the disk sorted by materialized path.
+async fn walk_disk(
+	root: impl AsRef<Path>,
+	rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
+) -> Result<Vec<DiskEntry>, LocationError> {
+	let root = root.as_ref();
+
+	let mut walked: Vec<WalkEntry> = vec![];
+	walk_from(
+		root,
+		rules_per_kind,
+		None,
+		|_, _| {},
+		|_, entries| {
+			walked.extend(entries);
+			future::ready(ControlFlow::Continue(()))
+		},
+	)
+	.await?;
+
+	let mut on_disk = Vec::with_capacity(walked.len());
+	for WalkEntry { path, is_dir, .. } in walked {
+		let metadata = match fs::metadata(&path).await {
+			Ok(metadata) => metadata,
+			// removed while we were walking
+			Err(e) if e.kind() == ErrorKind::NotFound => continue,
+			Err(e) => return Err(LocationError::IOError(e)),
+		};
+
+		on_disk.push(DiskEntry {
+			materialized_path: materialized_path(root, &path, is_dir),
+			is_dir,
+			size: if is_dir { 0 } else { metadata.len() },
+			modified: metadata.modified().map_err(LocationError::IOError)?.into(),
+		});
+	}
+	on_disk.sort_by(|a, b| a.materialized_path.cmp(&b.materialized_path));
+	// ancestors of accepted entries can be handed out more than once
+	on_disk.dedup_by(|a, b| a.materialized_path == b.materialized_path);
+
+	Ok(on_disk)
+}
+
+/// Diffs the index against the disk. Both have to be sorted by materialized path.
+fn diff(
+	indexed: impl IntoIterator<Item = IndexedEntry>,
+	on_disk: impl IntoIterator<Item = DiskEntry>,
+) -> Drift {
+	let mut drift = Drift::default();
+	let mut indexed = indexed.into_iter().peekable();
+	let mut on_disk = on_disk.into_iter().peekable();
+
+	loop {
+		let ordering = match (indexed.peek(), on_disk.peek()) {
+			(Some(indexed), Some(on_disk)) => {
+				indexed.materialized_path.cmp(&on_disk.materialized_path)
+			}
+			(Some(_), None) => Ordering::Less,
+			(None, Some(_)) => Ordering::Greater,
+			(None, None) => break,
+		};
+
+		match ordering {
+			Ordering::Less => drift
+				.missing_on_disk
+				.extend(indexed.next().filter(|indexed| !indexed.deleted)),
+			Ordering::Greater => drift.missing_in_index.extend(on_disk.next()),
+			Ordering::Equal => {
+				let (Some(indexed), Some(on_disk)) = (indexed.next(), on_disk.next()) else {
+					unreachable!("both sides were peeked");
+				};
+
+				if indexed.deleted {
+					drift.restored.push(indexed.clone());
+				}
+
+				if !indexed.is_dir && is_stale(&indexed, &on_disk) {
+					drift.stale.push((indexed, on_disk));
+				}
+			}
+		}
+	}
+
+	drift
+}
+
+/// The index keeps the time a file was last seen to change, so anything modified after that has changed since.
+/// Only whole seconds are compared, as that's all some file systems keep.
+fn is_stale(indexed: &IndexedEntry, on_disk: &DiskEntry) -> bool {
+	indexed.size.map_or(false, |size| size != on_disk.size)
+		|| on_disk.modified.timestamp() > indexed.date_modified.timestamp()
+}
+
+/// Materialized paths are relative to the location, and directories end with '/', like the indexer writes them.
+fn materialized_path(root: &Path, path: &Path, is_dir: bool) -> String {
+	let mut materialized_path = path
+		.strip_prefix(root)
+		.expect("walked paths are below the root")
+		.to_str()
+		.expect("Found non-UTF-8 path")
+		.to_string();
+
+	if is_dir && !materialized_path.ends_with('/') {
+		materialized_path += "/";
+	}
+
+	materialized_path
+}
+
+fn parent_materialized_path(path: &Path) -> String {
+	match path.parent().and_then(Path::to_str) {
+		Some("") | None => "/".to_string(),
+		Some(parent) => format!("{parent}/"),
+	}
+}
+
+fn name_part(part: Option<&OsStr>) -> String {
+	part.unwrap_or_default()
+		.to_str()
+		.expect("Found non-UTF-8 path")
+		.to_string()
+}
+
+/// Soft-deletes file paths (or restores them, with `None`), along with the objects that have no other path left (or
+/// that have one again).
+async fn set_date_deleted(
+	library_ctx: &LibraryContext,
+	location: &indexer_job_location::Data,
+	entries: &[IndexedEntry],
+	date_deleted: Option<DateTime<Utc>>,
+) -> Result<(), LocationError> {
+	let LibraryContext { db, sync, .. } = library_ctx;
+
+	if entries.is_empty() {
+		return Ok(());
+	}
+
+	sync.write_ops(
+		db,
+		entries
+			.iter()
+			.map(|entry| {
+				(
+					sync.shared_update(
+						file_path_sync_id(location, entry.id),
+						"date_deleted",
+						json!(date_deleted),
+					),
+					db.file_path().update(
+						file_path::location_id_id(location.id, entry.id),
+						vec![file_path::date_deleted::set(date_deleted.map(Into::into))],
+					),
+				)
+			})
+			.unzip::<_, _, Vec<_>, Vec<_>>(),
+	)
+	.await?;
+
+	update_objects_date_deleted(
+		library_ctx,
+		entries.iter().filter_map(|entry| entry.object_id).collect(),
+		date_deleted.unwrap_or_else(Utc::now),
+	)
+	.await
+}
+
+/// The identifier only looks at paths without an object, so files whose content changed are unlinked from the object
+/// of their old content, which is soft-deleted if no other path points to it anymore.
+async fn unlink_stale(
+	library_ctx: &LibraryContext,
+	location: &indexer_job_location::Data,
+	stale: &[(IndexedEntry, DiskEntry)],
+	now: DateTime<Utc>,
+) -> Result<(), LocationError> {
+	let LibraryContext { db, sync, .. } = library_ctx;
+
+	if stale.is_empty() {
+		return Ok(());
+	}
+
+	let mut ops = Vec::with_capacity(stale.len() * 2);
+	let mut updates = Vec::with_capacity(stale.len());
+	for (indexed, _) in stale {
+		let FileMetadata { cas_id, .. } =
+			FileMetadata::new(&location.path, &indexed.materialized_path)
+				.await
+				.map_err(LocationError::IOError)?;
+
+		ops.extend([
+			sync.shared_update(
+				file_path_sync_id(location, indexed.id),
+				"cas_id",
+				json!(cas_id),
+			),
+			sync.shared_update(
+				file_path_sync_id(location, indexed.id),
+				"object",
+				json!(null),
+			),
+		]);
+		updates.push(db.file_path().update(
+			file_path::location_id_id(location.id, indexed.id),
+			vec![
+				file_path::cas_id::set(Some(cas_id)),
+				file_path::object_id::set(None),
+			],
+		));
+	}
+	sync.write_ops(db, (ops, updates)).await?;
+
+	update_objects_date_deleted(
+		library_ctx,
+		stale
+			.iter()
+			.filter_map(|(indexed, _)| indexed.object_id)
+			.collect(),
+		now,
+	)
+	.await
+}
+
+/// Soft-deletes the objects which have no path left that isn't soft-deleted, and restores the ones which have one again.
+async fn update_objects_date_deleted(
+	library_ctx: &LibraryContext,
+	object_ids: Vec<i32>,
+	now: DateTime<Utc>,
+) -> Result<(), LocationError> {
+	let LibraryContext { db, sync, .. } = library_ctx;
+
+	if object_ids.is_empty() {
+		return Ok(());
+	}
+
+	let (ops, updates): (Vec<_>, Vec<_>) = db
+		.object()
+		.find_many(vec![object::id::in_vec(object_ids)])
+		.select(object::select!({ id pub_id date_deleted file_paths: select { date_deleted } }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|object| {
+			let has_paths = object
+				.file_paths
+				.iter()
+				.any(|file_path| file_path.date_deleted.is_none());
+
+			let date_deleted = match (has_paths, object.date_deleted) {
+				(true, Some(_)) => None,
+				(false, None) => Some(now),
+				// already soft-deleted, or not
+				_ => return None,
+			};
+
+			Some((
+				sync.shared_update(
+					sync::object::SyncId {
+						pub_id: object.pub_id,
+					},
+					"date_deleted",
+					json!(date_deleted),
+				),
+				db.object().update(
+					object::id::equals(object.id),
+					vec![object::date_deleted::set(date_deleted.map(Into::into))],
+				),
+			))
+		})
+		.unzip();
+
+	if !ops.is_empty() {
+		sync.write_ops(db, (ops, updates)).await?;
+	}
+
+	Ok(())
+}
+
+fn file_path_sync_id(location: &indexer_job_location::Data, id: i32) -> sync::file_path::SyncId {
+	sync::file_path::SyncId {
+		id,
+		location: sync::location::SyncId {
+			pub_id: location.pub_id.clone(),
+		},
+	}
+}
+
+impl From<IndexerError> for LocationError {
+	fn from(err: IndexerError) -> Self {
+		match err {
+			IndexerError::DatabaseError(e) => Self::DatabaseError(e),
+			IndexerError::IOError(e) => Self::IOError(e),
+			e => Self::IOError(std::io::Error::new(ErrorKind::Other, e)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		prisma::{location, node},
+		Node,
+	};
+
+	use tempfile::tempdir;
+	use uuid::Uuid;
+
+	/// Indexes the disk as it is now, the way the indexer and identifier would have.
+	fn index(on_disk: &[DiskEntry]) -> Vec<IndexedEntry> {
+		on_disk
+			.iter()
+			.enumerate()
+			.map(|(id, entry)| IndexedEntry {
+				id: id as i32 + 1,
+				object_id: (!entry.is_dir).then_some(id as i32 + 1),
+				materialized_path: entry.materialized_path.clone(),
+				is_dir: entry.is_dir,
+				deleted: false,
+				size: (!entry.is_dir).then_some(entry.size),
+				date_modified: entry.modified,
+			})
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn drift_is_classified() {
+		let dir = tempdir().unwrap();
+		let root = dir.path();
+		fs::create_dir(root.join("photos")).await.unwrap();
+		fs::write(root.join("photos/deleted.jpg"), b"deleted")
+			.await
+			.unwrap();
+		fs::write(root.join("photos/modified.jpg"), b"modified")
+			.await
+			.unwrap();
+		fs::write(root.join("unchanged.txt"), b"unchanged")
+			.await
+			.unwrap();
+
+		let rules = HashMap::new();
+		let indexed = index(&walk_disk(root, &rules).await.unwrap());
+		assert_eq!(
+			indexed
+				.iter()
+				.map(|entry| entry.materialized_path.as_str())
+				.collect::<Vec<_>>(),
+			vec![
+				"/",
+				"photos/",
+				"photos/deleted.jpg",
+				"photos/modified.jpg",
+				"unchanged.txt"
+			]
+		);
+
+		fs::remove_file(root.join("photos/deleted.jpg"))
+			.await
+			.unwrap();
+		fs::write(root.join("photos/added.jpg"), b"added")
+			.await
+			.unwrap();
+		fs::write(root.join("photos/modified.jpg"), b"modified, and longer")
+			.await
+			.unwrap();
+
+		let drift = diff(indexed, walk_disk(root, &rules).await.unwrap());
+
+		assert_eq!(
+			ReconcileReport::from(&drift),
+			ReconcileReport {
+				missing_on_disk: vec!["photos/deleted.jpg".to_string()],
+				missing_in_index: vec!["photos/added.jpg".to_string()],
+				stale: vec!["photos/modified.jpg".to_string()],
+			}
+		);
+	}
+
+	#[test]
+	fn files_modified_after_indexing_are_stale() {
+		let indexed_at = Utc::now();
+		let indexed = IndexedEntry {
+			id: 1,
+			object_id: None,
+			materialized_path: "notes.txt".to_string(),
+			is_dir: false,
+			deleted: false,
+			// not identified yet
+			size: None,
+			date_modified: indexed_at,
+		};
+		let on_disk = |modified| DiskEntry {
+			materialized_path: "notes.txt".to_string(),
+			is_dir: false,
+			size: 10,
+			modified,
+		};
+
+		assert!(!is_stale(&indexed, &on_disk(indexed_at)));
+		assert!(!is_stale(
+			&indexed,
+			&on_disk(indexed_at - chrono::Duration::days(1))
+		));
+		assert!(is_stale(
+			&indexed,
+			&on_disk(indexed_at + chrono::Duration::seconds(2))
+		));
+	}
+
+	#[test]
+	fn parents_of_materialized_paths() {
+		assert_eq!(parent_materialized_path(Path::new("a.txt")), "/");
+		assert_eq!(parent_materialized_path(Path::new("photos/")), "/");
+		assert_eq!(
+			parent_materialized_path(Path::new("photos/2023/a.jpg")),
+			"photos/2023/"
+		);
+	}
+
+	/// Indexes a file at the root of a location as identified, with an object of its own.
+	async fn index_file(
+		library: &LibraryContext,
+		location_id: i32,
+		root_id: i32,
+		file_name: &str,
+		size: usize,
+	) -> file_path::Data {
+		let (name, extension) = file_name.rsplit_once('.').unwrap();
+		let file_path = create_file_path(
+			library,
+			location_id,
+			file_name.to_string(),
+			name.to_string(),
+			extension.to_string(),
+			Some(root_id),
+			false,
+		)
+		.await
+		.unwrap();
+
+		let object = library
+			.db
+			.object()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				vec![object::size_in_bytes::set(size.to_string())],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		library
+			.db
+			.file_path()
+			.update(
+				file_path::location_id_id(location_id, file_path.id),
+				vec![file_path::object_id::set(Some(object.id))],
+			)
+			.exec()
+			.await
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn drift_is_repaired() {
+		let data_dir = tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+		let db = &library.db;
+
+		// the trash is next to the location, so files are moved there and back without changing
+		let dir = tempdir().unwrap();
+		let (root, trash) = (dir.path().join("location"), dir.path().join("trash"));
+		fs::create_dir(&root).await.unwrap();
+		fs::create_dir(&trash).await.unwrap();
+		for (name, contents) in [
+			("deleted.txt", "deleted"),
+			("modified.txt", "modified"),
+			("unchanged.txt", "unchanged"),
+		] {
+			fs::write(root.join(name), contents).await.unwrap();
+		}
+
+		let location = db
+			.location()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				"location".to_string(),
+				root.to_str().unwrap().to_string(),
+				node::id::equals(library.node_local_id),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap();
+		let root_id = create_file_path(
+			&library,
+			location.id,
+			"/".to_string(),
+			String::new(),
+			String::new(),
+			None,
+			true,
+		)
+		.await
+		.unwrap()
+		.id;
+		let deleted = index_file(&library, location.id, root_id, "deleted.txt", 7).await;
+		let modified = index_file(&library, location.id, root_id, "modified.txt", 8).await;
+		let unchanged = index_file(&library, location.id, root_id, "unchanged.txt", 9).await;
+
+		let location_id = location.id;
+		let file_path = |id| async move {
+			db.file_path()
+				.find_unique(file_path::location_id_id(location_id, id))
+				.exec()
+				.await
+				.unwrap()
+				.unwrap()
+		};
+		let object = |id| async move {
+			db.object()
+				.find_unique(object::id::equals(id))
+				.exec()
+				.await
+				.unwrap()
+				.unwrap()
+		};
+
+		fs::rename(root.join("deleted.txt"), trash.join("deleted.txt"))
+			.await
+			.unwrap();
+		fs::write(root.join("added.txt"), b"added").await.unwrap();
+		fs::write(root.join("modified.txt"), b"modified, and longer")
+			.await
+			.unwrap();
+
+		assert_eq!(
+			reconcile(&library, location.id).await.unwrap(),
+			ReconcileReport {
+				missing_on_disk: vec!["deleted.txt".to_string()],
+				missing_in_index: vec!["added.txt".to_string()],
+				stale: vec!["modified.txt".to_string()],
+			}
+		);
+
+		// the deleted file and its object are kept, but hidden
+		assert!(file_path(deleted.id).await.date_deleted.is_some());
+		assert!(object(deleted.object_id.unwrap())
+			.await
+			.date_deleted
+			.is_some());
+
+		// the added file is indexed, to be identified
+		assert!(db
+			.file_path()
+			.find_first(vec![
+				file_path::location_id::equals(location.id),
+				file_path::materialized_path::equals("added.txt".to_string()),
+				file_path::date_deleted::equals(None),
+			])
+			.exec()
+			.await
+			.unwrap()
+			.is_some());
+
+		// the modified file no longer belongs to the object of its old content, which has no path left
+		let relinked = file_path(modified.id).await;
+		assert_ne!(relinked.object_id, modified.object_id);
+		assert_ne!(relinked.cas_id, modified.cas_id);
+		assert!(object(modified.object_id.unwrap())
+			.await
+			.date_deleted
+			.is_some());
+
+		let untouched = file_path(unchanged.id).await;
+		assert_eq!(untouched.object_id, unchanged.object_id);
+		assert!(object(unchanged.object_id.unwrap())
+			.await
+			.date_deleted
+			.is_none());
+
+		// the deleted file comes back, with its object
+		fs::rename(trash.join("deleted.txt"), root.join("deleted.txt"))
+			.await
+			.unwrap();
+		let report = reconcile(&library, location.id).await.unwrap();
+		assert_eq!(report.missing_in_index, vec!["deleted.txt".to_string()]);
+		assert!(report.missing_on_disk.is_empty());
+
+		let restored = file_path(deleted.id).await;
+		assert!(restored.date_deleted.is_none());
+		assert_eq!(restored.object_id, deleted.object_id);
+		assert!(object(deleted.object_id.unwrap())
+			.await
+			.date_deleted
+			.is_none());
+	}
+}
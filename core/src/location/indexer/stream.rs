@@ -0,0 +1,303 @@
+use std::{
+	collections::HashMap,
+	future::Future,
+	mem,
+	ops::ControlFlow,
+	path::{Path, PathBuf},
+};
+
+use tokio::sync::mpsc;
+
+use super::{
+	rules::{IndexerRule, RuleKind},
+	walk::{walk_from, WalkEntry},
+	IndexerError,
+};
+
+/// How much of the walk can be held in memory at once.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct StreamLimits {
+	/// How many entries are persisted at once
+	pub(super) batch_size: usize,
+	/// How many entries can wait on each stage, before the stage feeding it has to wait too
+	pub(super) channel_capacity: usize,
+}
+
+/// What the walk hands to the next stage.
+enum Walked<T> {
+	Entry(T),
+	/// Every entry of this directory was handed out before this
+	Dir(PathBuf),
+}
+
+/// Walks `root` like [`walk_from`], streaming the accepted entries through `prepare` and then to
+/// `persist` in batches, as they are found.
+///
+/// Each batch is persisted along with the checkpoint of the walk, the last directory whose entries
+/// are all in this batch or in the ones before it. Walking again from that checkpoint hands out
+/// the entries that weren't persisted, along with some which were, so they should be upserted.
+///
+/// Each stage runs concurrently and waits on the next one when its channel is full, so a slow
+/// `persist` holds back the walk instead of letting entries pile up. Besides the directory being
+/// walked, at most `batch_size` plus two channels worth of entries are in memory at any time.
+///
+/// The entries of each directory are handed to `prepare` in `Path` order, after their parent
+/// directory, so it can give them ids by which children find their parent.
+pub(super) async fn walk_in_batches<T, E, Fut>(
+	root: impl AsRef<Path>,
+	rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
+	checkpoint: Option<&Path>,
+	limits: StreamLimits,
+	update_notifier: impl Fn(&Path, usize),
+	mut prepare: impl FnMut(WalkEntry) -> T,
+	mut persist: impl FnMut(Vec<T>, Option<PathBuf>) -> Fut,
+) -> Result<(), E>
+where
+	E: From<IndexerError>,
+	Fut: Future<Output = Result<(), E>>,
+{
+	let (entries_tx, mut entries_rx) = mpsc::channel(limits.channel_capacity);
+	let (prepared_tx, mut prepared_rx) = mpsc::channel(limits.channel_capacity);
+
+	let walker = async move {
+		walk_from(
+			root,
+			rules_per_kind,
+			checkpoint,
+			update_notifier,
+			|dir, mut entries| {
+				let entries_tx = entries_tx.clone();
+				async move {
+					// Ancestors are added after the entry which accepted them, but they need an id first
+					entries.sort();
+
+					for walked in entries
+						.into_iter()
+						.map(Walked::Entry)
+						.chain([Walked::Dir(dir)])
+					{
+						if entries_tx.send(walked).await.is_err() {
+							// The later stages stopped on an error, so there is no point in walking on
+							return ControlFlow::Break(());
+						}
+					}
+
+					ControlFlow::Continue(())
+				}
+			},
+		)
+		.await
+		.map_err(E::from)
+	};
+
+	let preparer = async move {
+		while let Some(walked) = entries_rx.recv().await {
+			let prepared = match walked {
+				Walked::Entry(entry) => Walked::Entry(prepare(entry)),
+				Walked::Dir(dir) => Walked::Dir(dir),
+			};
+
+			if prepared_tx.send(prepared).await.is_err() {
+				break;
+			}
+		}
+
+		Ok::<_, E>(())
+	};
+
+	let batcher = async move {
+		let mut batch = Vec::with_capacity(limits.batch_size);
+		let mut checkpoint = None;
+
+		while let Some(prepared) = prepared_rx.recv().await {
+			match prepared {
+				Walked::Entry(prepared) => batch.push(prepared),
+				Walked::Dir(dir) => checkpoint = Some(dir),
+			}
+
+			if batch.len() == limits.batch_size {
+				persist(
+					mem::replace(&mut batch, Vec::with_capacity(limits.batch_size)),
+					checkpoint.clone(),
+				)
+				.await?;
+			}
+		}
+
+		if !batch.is_empty() {
+			persist(batch, checkpoint).await?;
+		}
+
+		Ok::<_, E>(())
+	};
+
+	tokio::try_join!(walker, preparer, batcher).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::walk::walk;
+	use super::*;
+
+	use std::{cell::Cell, collections::HashSet, path::PathBuf, time::Duration};
+
+	use tempfile::tempdir;
+	use tokio::{fs, time::sleep};
+
+	#[tokio::test]
+	async fn memory_stays_bounded_on_large_trees() {
+		let root = tempdir().unwrap();
+		for dir in 0..20 {
+			let dir = root.path().join(format!("dir{dir:02}"));
+			fs::create_dir(&dir).await.unwrap();
+			for file in 0..100 {
+				fs::File::create(dir.join(format!("file{file:03}")))
+					.await
+					.unwrap();
+			}
+		}
+
+		let limits = StreamLimits {
+			batch_size: 50,
+			channel_capacity: 16,
+		};
+
+		let prepared = Cell::new(0);
+		let persisted = Cell::new(0);
+		let max_in_flight = Cell::new(0);
+		let mut ids = HashMap::<PathBuf, usize>::new();
+		let mut batches = Vec::new();
+
+		walk_in_batches(
+			root.path(),
+			&HashMap::new(),
+			None,
+			limits,
+			|_, _| {},
+			|entry| {
+				let id = prepared.get();
+				prepared.set(id + 1);
+				max_in_flight.set(max_in_flight.get().max(id + 1 - persisted.get()));
+
+				// Every parent was prepared before its children
+				let parent_id = entry
+					.path
+					.parent()
+					.and_then(|parent| ids.get(parent).copied());
+				assert_eq!(parent_id.is_some(), entry.path != root.path());
+				ids.insert(entry.path.clone(), id);
+
+				entry.path
+			},
+			|batch, _| {
+				batches.push(batch.len());
+				persisted.set(persisted.get() + batch.len());
+				// A slow database, so the walk would get ahead of it without back-pressure
+				async {
+					sleep(Duration::from_millis(1)).await;
+					Ok::<_, IndexerError>(())
+				}
+			},
+		)
+		.await
+		.unwrap();
+
+		let expected = walk(root.path(), &HashMap::new(), |_, _| {})
+			.await
+			.unwrap()
+			.into_iter()
+			.map(|entry| entry.path)
+			.collect::<HashSet<_>>();
+		assert_eq!(ids.into_keys().collect::<HashSet<_>>(), expected);
+
+		assert!(batches.iter().all(|len| *len <= limits.batch_size));
+		assert_eq!(batches.iter().sum::<usize>(), expected.len());
+
+		// The batch being filled, what waits in the channel between preparing and persisting, and
+		// the entry waiting for room in it
+		assert!(max_in_flight.get() <= limits.batch_size + limits.channel_capacity + 1);
+	}
+
+	#[tokio::test]
+	async fn resume_from_the_last_persisted_checkpoint() {
+		let root = tempdir().unwrap();
+		for dir in 0..10 {
+			let dir = root.path().join(format!("dir{dir}"));
+			fs::create_dir(&dir).await.unwrap();
+			for file in 0..7 {
+				fs::File::create(dir.join(format!("file{file}")))
+					.await
+					.unwrap();
+			}
+		}
+
+		let limits = StreamLimits {
+			batch_size: 10,
+			channel_capacity: 4,
+		};
+
+		// Upserted by path, like the indexer does
+		let mut indexed = HashSet::new();
+		let mut checkpoint = None;
+
+		// The node crashes while persisting the fourth batch
+		let mut persisted = 0;
+		let crashed = walk_in_batches(
+			root.path(),
+			&HashMap::new(),
+			None,
+			limits,
+			|_, _| {},
+			|entry| entry.path,
+			|batch, batch_checkpoint| {
+				persisted += 1;
+				let crash = persisted == 4;
+				if !crash {
+					indexed.extend(batch);
+					checkpoint = batch_checkpoint;
+				}
+
+				async move {
+					if crash {
+						Err(IndexerError::IOError(std::io::Error::new(
+							std::io::ErrorKind::Other,
+							"crashed",
+						)))
+					} else {
+						Ok(())
+					}
+				}
+			},
+		)
+		.await;
+		assert!(crashed.is_err());
+
+		let checkpoint = checkpoint.expect("a directory was fully persisted before the crash");
+		let mut handed_again = 0;
+		walk_in_batches(
+			root.path(),
+			&HashMap::new(),
+			Some(&checkpoint),
+			limits,
+			|_, _| {},
+			|entry| entry.path,
+			|batch, _| {
+				handed_again += batch.len();
+				indexed.extend(batch);
+				async { Ok::<_, IndexerError>(()) }
+			},
+		)
+		.await
+		.unwrap();
+
+		let expected = walk(root.path(), &HashMap::new(), |_, _| {})
+			.await
+			.unwrap()
+			.into_iter()
+			.map(|entry| entry.path)
+			.collect::<HashSet<_>>();
+		// Every path is indexed exactly once, without walking again what was before the checkpoint
+		assert_eq!(indexed, expected);
+		assert!(handed_again < expected.len());
+	}
+}
@@ -1,8 +1,10 @@
 use chrono::{DateTime, Utc};
 use std::{
 	cmp::Ordering,
-	collections::{HashMap, VecDeque},
+	collections::{HashMap, HashSet},
+	future::Future,
 	hash::{Hash, Hasher},
+	ops::ControlFlow,
 	path::{Path, PathBuf},
 };
 use tokio::fs;
@@ -49,47 +51,104 @@ impl Ord for WalkEntry {
 }
 
 /// This function walks through the filesystem, applying the rules to each entry and then returning
-/// a list of accepted entries. There are some useful comments in the implementation of this function
+/// a list of accepted entries. There are some useful comments in the implementation of [`walk_from`]
 /// in case of doubts.
+///
+/// The indexer itself streams the entries through [`super::stream::walk_in_batches`] instead, so
+/// it never holds the whole list.
+#[cfg(test)]
 pub(super) async fn walk(
 	root: impl AsRef<Path>,
 	rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
 	update_notifier: impl Fn(&Path, usize),
 ) -> Result<Vec<WalkEntry>, IndexerError> {
+	let mut indexed_paths = Vec::new();
+
+	walk_from(root, rules_per_kind, None, update_notifier, |_, entries| {
+		indexed_paths.extend(entries);
+		std::future::ready(ControlFlow::Continue(()))
+	})
+	.await?;
+
+	// Sorting so we can give each path a crescent id given the filesystem hierarchy
+	indexed_paths.sort();
+
+	Ok(indexed_paths)
+}
+
+/// This function walks through the filesystem like [`walk`], but hands out the accepted entries
+/// as each directory is walked, so the caller can persist them and checkpoint the walk.
+///
+/// Directories are walked depth first with their entries sorted, which means they are walked in
+/// `Path` order. As the order is deterministic, the last directory passed to `on_walked_dir` is a
+/// checkpoint: when given back, the walk resumes right after it, skipping the subtrees walked before.
+/// `on_walked_dir` can break to interrupt the walk.
+///
+/// Entries are handed out at most once per walk, but the directories added as ancestors of
+/// accepted entries may be handed out again after resuming, so they should be upserted by path.
+///
+/// `on_walked_dir` can also wait before returning, so the walk keeps pace with whoever consumes
+/// the entries. Only the accepted directories are kept around while walking, so the memory used
+/// doesn't grow with the number of files.
+pub(super) async fn walk_from<Fut: Future<Output = ControlFlow<()>>>(
+	root: impl AsRef<Path>,
+	rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
+	checkpoint: Option<&Path>,
+	update_notifier: impl Fn(&Path, usize),
+	mut on_walked_dir: impl FnMut(PathBuf, Vec<WalkEntry>) -> Fut,
+) -> Result<(), IndexerError> {
 	let root = root.as_ref().to_path_buf();
 
-	let mut to_walk = VecDeque::with_capacity(1);
-	to_walk.push_back((root.clone(), None));
-	let mut indexed_paths = HashMap::new();
+	let mut to_walk = vec![(root.clone(), None)];
+	// Files can't be the ancestor of anything, so only directories have to be remembered
+	let mut indexed_dirs = HashSet::new();
+	let mut indexed_count = 0;
+
+	while let Some((current_path, parent_dir_accepted_by_its_children)) = to_walk.pop() {
+		// Directories up to the checkpoint already had their entries handed out, we only walk them
+		// again to know which of their entries were accepted and which subdirectories are left
+		let already_walked =
+			checkpoint.map_or(false, |checkpoint| current_path.as_path() <= checkpoint);
+
+		let mut walked_entries = Vec::new();
+		if current_path == root && !already_walked {
+			// Also adding the root location path
+			walked_entries.push(WalkEntry {
+				path: root.clone(),
+				is_dir: true,
+				created_at: fs::metadata(&root).await?.created()?.into(),
+			});
+		}
 
-	while let Some((current_path, parent_dir_accepted_by_its_children)) = to_walk.pop_front() {
-		let mut read_dir = match fs::read_dir(&current_path).await {
-			Ok(read_dir) => read_dir,
+		let mut dir_entries = Vec::new();
+		match fs::read_dir(&current_path).await {
+			Ok(mut read_dir) => loop {
+				match read_dir.next_entry().await {
+					Ok(Some(entry)) => dir_entries.push(entry),
+					Ok(None) => break,
+					Err(e) => {
+						error!(
+							"Error reading entry in {}: {:#?}",
+							current_path.display(),
+							e
+						);
+					}
+				}
+			},
 			Err(e) => {
 				error!(
 					"Error reading directory {}: {:#?}",
 					current_path.display(),
 					e
 				);
-				continue;
 			}
-		};
+		}
+		dir_entries.sort_by_key(|entry| entry.path());
 
-		// Marking with a loop label here in case of rejection or erros, to continue with next entry
-		'entries: loop {
-			let entry = match read_dir.next_entry().await {
-				Ok(Some(entry)) => entry,
-				Ok(None) => break,
-				Err(e) => {
-					error!(
-						"Error reading entry in {}: {:#?}",
-						current_path.display(),
-						e
-					);
-					continue;
-				}
-			};
+		let mut subdirs = Vec::new();
 
+		// Marking with a loop label here in case of rejection or erros, to continue with next entry
+		'entries: for entry in dir_entries {
 			// Accept by children has three states,
 			// None if we don't now yet or if this check doesn't apply
 			// Some(true) if this check applies and it passes
@@ -99,7 +158,7 @@ pub(super) async fn walk(
 
 			let current_path = entry.path();
 
-			update_notifier(&current_path, indexed_paths.len());
+			update_notifier(&current_path, indexed_count);
 
 			debug!(
 				"Current filesystem path: {}, accept_by_children_dir: {:#?}",
@@ -191,8 +250,13 @@ pub(super) async fn walk(
 					}
 				}
 
-				// Then we mark this directory the be walked in too
-				to_walk.push_back((entry.path(), accept_by_children_dir));
+				// Then we mark this directory the be walked in too, unless its whole subtree was
+				// walked before the checkpoint
+				if !checkpoint.map_or(false, |checkpoint| {
+					current_path.as_path() < checkpoint && !checkpoint.starts_with(&current_path)
+				}) {
+					subdirs.push((entry.path(), accept_by_children_dir));
+				}
 			}
 
 			let mut accept_by_glob = false;
@@ -224,14 +288,17 @@ pub(super) async fn walk(
 			if accept_by_glob
 				&& (accept_by_children_dir.is_none() || accept_by_children_dir.unwrap())
 			{
-				indexed_paths.insert(
-					current_path.clone(),
-					WalkEntry {
+				if is_dir {
+					indexed_dirs.insert(current_path.clone());
+				}
+				indexed_count += 1;
+				if !already_walked {
+					walked_entries.push(WalkEntry {
 						path: current_path.clone(),
 						is_dir,
 						created_at: metadata.created()?.into(),
-					},
-				);
+					});
+				}
 
 				// If the ancestors directories wasn't indexed before, now we do
 				for ancestor in current_path
@@ -240,37 +307,33 @@ pub(super) async fn walk(
 					.take_while(|&ancestor| ancestor != root)
 				{
 					debug!("Indexing ancestor {}", ancestor.display());
-					if !indexed_paths.contains_key(ancestor) {
-						indexed_paths.insert(
-							ancestor.to_path_buf(),
-							WalkEntry {
+					if indexed_dirs.insert(ancestor.to_path_buf()) {
+						indexed_count += 1;
+						if !already_walked {
+							walked_entries.push(WalkEntry {
 								path: ancestor.to_path_buf(),
 								is_dir: true,
 								created_at: fs::metadata(ancestor).await?.created()?.into(),
-							},
-						);
+							});
+						}
 					} else {
-						// If indexed_paths contains the current ancestors, then it will contain
+						// If indexed_dirs contains the current ancestors, then it will contain
 						// also all if its ancestors too, so we can stop here
 						break;
 					}
 				}
 			}
 		}
-	}
 
-	let mut indexed_paths = indexed_paths.into_values().collect::<Vec<_>>();
-	// Also adding the root location path
-	let root_created_at = fs::metadata(&root).await?.created()?.into();
-	indexed_paths.push(WalkEntry {
-		path: root,
-		is_dir: true,
-		created_at: root_created_at,
-	});
-	// Sorting so we can give each path a crescent id given the filesystem hierarchy
-	indexed_paths.sort();
+		// Reversed, so the first subdirectory is the next one to be walked
+		to_walk.extend(subdirs.into_iter().rev());
 
-	Ok(indexed_paths)
+		if !already_walked && on_walked_dir(current_path, walked_entries).await.is_break() {
+			break;
+		}
+	}
+
+	Ok(())
 }
 
 #[cfg(test)]
@@ -279,7 +342,7 @@ mod tests {
 	use super::*;
 	use chrono::Utc;
 	use globset::Glob;
-	use std::collections::BTreeSet;
+	use std::{collections::BTreeSet, future::ready};
 	use tempfile::{tempdir, TempDir};
 	use tokio::fs;
 	use tracing_test::traced_test;
@@ -551,4 +614,68 @@ mod tests {
 
 		assert_eq!(actual, expected);
 	}
+
+	#[tokio::test]
+	async fn resume_walk_from_checkpoint() {
+		let root = prepare_location().await;
+		let root_path = root.path();
+
+		let expected = walk(root_path, &HashMap::new(), |_, _| {}).await.unwrap();
+
+		// Interrupting the walk after a few directories, as if the node crashed
+		let mut walked = Vec::new();
+		let mut walked_dirs = Vec::new();
+		walk_from(
+			root_path,
+			&HashMap::new(),
+			None,
+			|_, _| {},
+			|dir, entries| {
+				walked.extend(entries);
+				walked_dirs.push(dir);
+
+				ready(if walked_dirs.len() == 3 {
+					ControlFlow::Break(())
+				} else {
+					ControlFlow::Continue(())
+				})
+			},
+		)
+		.await
+		.unwrap();
+
+		// The walk order is deterministic, so the checkpoint is always the same
+		assert_eq!(
+			walked_dirs,
+			[
+				root_path.to_path_buf(),
+				root_path.join("inner"),
+				root_path.join("inner/node_project"),
+			]
+		);
+		assert!(walked.len() < expected.len());
+
+		walk_from(
+			root_path,
+			&HashMap::new(),
+			walked_dirs.last().map(PathBuf::as_path),
+			|_, _| {},
+			|_, entries| {
+				walked.extend(entries);
+				ready(ControlFlow::Continue(()))
+			},
+		)
+		.await
+		.unwrap();
+
+		// Every entry was handed out exactly once across both walks
+		let unique_paths = walked
+			.iter()
+			.map(|entry| entry.path.clone())
+			.collect::<HashSet<_>>();
+		assert_eq!(unique_paths.len(), walked.len());
+
+		walked.sort();
+		assert_eq!(walked, expected);
+	}
 }
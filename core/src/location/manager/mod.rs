@@ -12,7 +12,7 @@ use tokio::{
 	io,
 	sync::{
 		broadcast::{self, Receiver},
-		oneshot, RwLock,
+		oneshot, watch, RwLock,
 	},
 };
 use tracing::{debug, error};
@@ -103,6 +103,7 @@ type OnlineLocations = BTreeSet<Vec<u8>>;
 pub struct LocationManager {
 	online_locations: RwLock<OnlineLocations>,
 	pub online_tx: broadcast::Sender<OnlineLocations>,
+	paused_tx: watch::Sender<bool>,
 	#[cfg(feature = "location-watcher")]
 	location_management_tx: mpsc::Sender<LocationManagementMessage>,
 	#[cfg(feature = "location-watcher")]
@@ -132,6 +133,7 @@ impl LocationManager {
 			Arc::new(Self {
 				online_locations: Default::default(),
 				online_tx,
+				paused_tx: watch::channel(false).0,
 				location_management_tx,
 				watcher_management_tx,
 				stop_tx: Some(stop_tx),
@@ -144,6 +146,7 @@ impl LocationManager {
 			Arc::new(Self {
 				online_tx,
 				online_locations: Default::default(),
+				paused_tx: watch::channel(false).0,
 				stop_tx: None,
 			})
 		}
@@ -249,6 +252,22 @@ impl LocationManager {
 		.await
 	}
 
+	/// Stops applying file system events to the locations being watched, which are kept until
+	/// [`LocationManager::resume`] is called.
+	pub fn pause(&self) {
+		self.paused_tx.send_replace(true);
+	}
+
+	/// Applies the file system events received while paused, and the ones received from now on.
+	pub fn resume(&self) {
+		self.paused_tx.send_replace(false);
+	}
+
+	#[cfg(feature = "location-watcher")]
+	fn paused_rx(&self) -> watch::Receiver<bool> {
+		self.paused_tx.subscribe()
+	}
+
 	pub async fn temporary_stop(
 		&self,
 		location_id: LocationId,
@@ -0,0 +1,107 @@
+use crate::util::debounce::Debouncer;
+
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+
+use tokio::time::Instant;
+
+/// How long a file has to go without being modified before its modifications are flushed to the database
+pub(super) const MODIFIED_FILES_QUIET_PERIOD: Duration = Duration::from_millis(200);
+/// The longest a file's modifications are held back, so a file that's written constantly is still updated
+pub(super) const MODIFIED_FILES_MAX_WAIT: Duration = Duration::from_secs(2);
+
+/// Buffers the paths of files that were modified in a burst (e.g. a build writing thousands of files),
+/// so their size/mtime can be updated in a single transaction and each of them is re-identified
+/// only once, no matter how many times it was written in the meantime.
+#[derive(Debug)]
+pub(super) struct ModifiedFilesBuffer {
+	debouncer: Debouncer<PathBuf, ()>,
+}
+
+impl Default for ModifiedFilesBuffer {
+	fn default() -> Self {
+		Self {
+			debouncer: Debouncer::new(MODIFIED_FILES_QUIET_PERIOD, MODIFIED_FILES_MAX_WAIT),
+		}
+	}
+}
+
+impl ModifiedFilesBuffer {
+	pub(super) fn push(&mut self, path: PathBuf, now: Instant) {
+		self.debouncer.push_at(path, (), now);
+	}
+
+	/// Returns when the next file is due to be flushed.
+	pub(super) fn flush_at(&self) -> Option<Instant> {
+		self.debouncer.next_deadline()
+	}
+
+	/// Takes the files that are due at `now`. Files that are still being written stay in the buffer.
+	pub(super) fn take_ready(&mut self, now: Instant) -> HashSet<PathBuf> {
+		self.debouncer
+			.take_ready(now)
+			.into_iter()
+			.map(|(path, _)| path)
+			.collect()
+	}
+
+	/// Takes every buffered file, whether it's due or not, e.g. when the watcher stops.
+	pub(super) fn take_all(&mut self) -> HashSet<PathBuf> {
+		self.debouncer
+			.drain()
+			.into_iter()
+			.map(|(path, _)| path)
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn burst_is_deduplicated_and_flushed_once_quiet() {
+		let mut buffer = ModifiedFilesBuffer::default();
+		let start = Instant::now();
+
+		for i in 0..10 {
+			buffer.push(
+				PathBuf::from("/location/target/build.o"),
+				start + Duration::from_millis(i * 10),
+			);
+		}
+		buffer.push(
+			PathBuf::from("/location/target/build.d"),
+			start + Duration::from_millis(150),
+		);
+
+		// each file is due once it hasn't been written for the quiet period
+		let build_o_due = start + Duration::from_millis(90) + MODIFIED_FILES_QUIET_PERIOD;
+		assert_eq!(buffer.flush_at(), Some(build_o_due));
+		assert_eq!(
+			buffer.take_ready(build_o_due),
+			HashSet::from([PathBuf::from("/location/target/build.o")])
+		);
+
+		let build_d_due = start + Duration::from_millis(150) + MODIFIED_FILES_QUIET_PERIOD;
+		assert_eq!(buffer.flush_at(), Some(build_d_due));
+		assert!(buffer.take_ready(build_o_due).is_empty());
+		assert_eq!(buffer.take_ready(build_d_due).len(), 1);
+
+		assert!(buffer.flush_at().is_none());
+		assert!(buffer.take_all().is_empty());
+	}
+
+	#[test]
+	fn constantly_written_file_is_still_flushed() {
+		let mut buffer = ModifiedFilesBuffer::default();
+		let start = Instant::now();
+
+		let mut now = start;
+		while now < start + MODIFIED_FILES_MAX_WAIT * 2 {
+			buffer.push(PathBuf::from("/location/app.log"), now);
+			now += MODIFIED_FILES_QUIET_PERIOD / 2;
+		}
+
+		assert_eq!(buffer.flush_at(), Some(start + MODIFIED_FILES_MAX_WAIT));
+	}
+}
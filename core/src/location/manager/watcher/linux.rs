@@ -12,7 +12,7 @@ use tracing::trace;
 
 use super::{
 	utils::{create_dir, file_creation_or_update, remove_event, rename_both_event},
-	EventHandler,
+	EventHandler, ModifiedFilesBuffer,
 };
 
 #[derive(Debug)]
@@ -29,19 +29,20 @@ impl EventHandler for LinuxEventHandler {
 		location: indexer_job_location::Data,
 		library_ctx: &LibraryContext,
 		event: Event,
+		modified_files: &mut ModifiedFilesBuffer,
 	) -> Result<(), LocationManagerError> {
 		trace!("Received Linux event: {:#?}", event);
 
 		match event.kind {
 			EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
 				// If a file was closed with write mode, then it was updated or created
-				file_creation_or_update(&location, &event, library_ctx).await?;
+				file_creation_or_update(&location, &event, library_ctx, modified_files).await?;
 			}
 			EventKind::Create(CreateKind::Folder) => {
 				create_dir(&location, &event, library_ctx).await?;
 			}
 			EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
-				rename_both_event(&location, &event, library_ctx).await?;
+				rename_both_event(&location, &event, library_ctx, modified_files).await?;
 			}
 			EventKind::Remove(remove_kind) => {
 				remove_event(&location, &event, remove_kind, library_ctx).await?;
@@ -12,7 +12,7 @@ use tracing::trace;
 
 use super::{
 	utils::{create_dir, file_creation_or_update, remove_event, rename},
-	EventHandler,
+	EventHandler, ModifiedFilesBuffer,
 };
 
 #[derive(Debug, Default)]
@@ -35,6 +35,7 @@ impl EventHandler for MacOsEventHandler {
 		location: indexer_job_location::Data,
 		library_ctx: &LibraryContext,
 		event: Event,
+		modified_files: &mut ModifiedFilesBuffer,
 	) -> Result<(), LocationManagerError> {
 		trace!("Received MacOS event: {:#?}", event);
 
@@ -55,7 +56,7 @@ impl EventHandler for MacOsEventHandler {
 			}
 			EventKind::Modify(ModifyKind::Data(DataChange::Content)) => {
 				// If a file had its content modified, then it was updated or created
-				file_creation_or_update(&location, &event, library_ctx).await?;
+				file_creation_or_update(&location, &event, library_ctx, modified_files).await?;
 			}
 			EventKind::Modify(ModifyKind::Name(RenameMode::Any)) => {
 				match self.rename_stack.take() {
@@ -68,6 +69,7 @@ impl EventHandler for MacOsEventHandler {
 							&from_event.paths[0],
 							&location,
 							library_ctx,
+							modified_files,
 						)
 						.await?;
 					}
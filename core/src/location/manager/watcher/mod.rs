@@ -6,6 +6,7 @@ use crate::{
 use std::{
 	collections::HashSet,
 	path::{Path, PathBuf},
+	sync::Arc,
 };
 
 use async_trait::async_trait;
@@ -13,8 +14,9 @@ use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
 use tokio::{
 	runtime::Handle,
 	select,
-	sync::{mpsc, oneshot},
+	sync::{mpsc, oneshot, watch},
 	task::{block_in_place, JoinHandle},
+	time::{sleep_until, Instant},
 };
 use tracing::{debug, error, warn};
 
@@ -27,9 +29,13 @@ mod linux;
 mod macos;
 mod windows;
 
+mod batch;
+mod overflow;
 mod utils;
 
-use utils::check_event;
+use batch::ModifiedFilesBuffer;
+use overflow::{EventSender, Overflow, EVENT_CHANNEL_CAPACITY};
+use utils::{check_event, flush_modified_files, resync_subtree};
 
 #[cfg(target_os = "linux")]
 type Handler = linux::LinuxEventHandler;
@@ -55,6 +61,7 @@ trait EventHandler {
 		location: indexer_job_location::Data,
 		library_ctx: &LibraryContext,
 		event: Event,
+		modified_files: &mut ModifiedFilesBuffer,
 	) -> Result<(), LocationManagerError>;
 }
 
@@ -72,34 +79,22 @@ impl LocationWatcher {
 		location: location::Data,
 		library_ctx: LibraryContext,
 	) -> Result<Self, LocationManagerError> {
-		let (events_tx, events_rx) = mpsc::unbounded_channel();
+		let (events_tx, events_rx, overflow) =
+			EventSender::new(PathBuf::from(&location.path), EVENT_CHANNEL_CAPACITY);
 		let (ignore_path_tx, ignore_path_rx) = mpsc::unbounded_channel();
 		let (stop_tx, stop_rx) = oneshot::channel();
+		let paused_rx = library_ctx.location_manager().paused_rx();
 
-		let watcher = RecommendedWatcher::new(
-			move |result| {
-				if !events_tx.is_closed() {
-					if events_tx.send(result).is_err() {
-						error!(
-						"Unable to send watcher event to location manager for location: <id='{}'>",
-						location.id
-					);
-					}
-				} else {
-					error!(
-						"Tried to send location file system events to a closed channel: <id='{}'",
-						location.id
-					);
-				}
-			},
-			Config::default(),
-		)?;
+		let watcher =
+			RecommendedWatcher::new(move |result| events_tx.send(result), Config::default())?;
 
 		let handle = tokio::spawn(Self::handle_watch_events(
 			location.id,
 			library_ctx,
 			events_rx,
+			overflow,
 			ignore_path_rx,
+			paused_rx,
 			stop_rx,
 		));
 
@@ -115,17 +110,24 @@ impl LocationWatcher {
 	async fn handle_watch_events(
 		location_id: LocationId,
 		library_ctx: LibraryContext,
-		mut events_rx: mpsc::UnboundedReceiver<notify::Result<Event>>,
+		mut events_rx: mpsc::Receiver<notify::Result<Event>>,
+		overflow: Arc<Overflow>,
 		mut ignore_path_rx: mpsc::UnboundedReceiver<IgnorePath>,
+		mut paused_rx: watch::Receiver<bool>,
 		mut stop_rx: oneshot::Receiver<()>,
 	) {
 		let mut event_handler = Handler::new();
 
 		let mut paths_to_ignore = HashSet::new();
+		let mut modified_files = ModifiedFilesBuffer::default();
 
 		loop {
+			let flush_at = modified_files.flush_at();
+			// While paused, events wait in the channel until they can be applied
+			let paused = *paused_rx.borrow();
+
 			select! {
-				Some(event) = events_rx.recv() => {
+				Some(event) = events_rx.recv(), if !paused => {
 					match event {
 						Ok(event) => {
 							if let Err(e) = Self::handle_single_event(
@@ -134,6 +136,7 @@ impl LocationWatcher {
 								&mut event_handler,
 								&library_ctx,
 								&paths_to_ignore,
+								&mut modified_files,
 							).await {
 								error!("Failed to handle location file system event: \
 									<id='{location_id}', error='{e:#?}'>",
@@ -146,6 +149,23 @@ impl LocationWatcher {
 					}
 				}
 
+				// Events were dropped during a burst, so we look at the disk instead
+				subtrees = overflow.overflowed(), if !paused => {
+					for subtree in subtrees {
+						if let Err(e) = Self::resync(
+							location_id,
+							&subtree,
+							&library_ctx,
+							&mut modified_files,
+						).await {
+							error!("Failed to resync location subtree: \
+								<id='{location_id}', path='{}', error='{e:#?}'>",
+								subtree.display(),
+							);
+						}
+					}
+				}
+
 				Some((path, ignore)) = ignore_path_rx.recv() => {
 					if ignore {
 						paths_to_ignore.insert(path);
@@ -154,8 +174,33 @@ impl LocationWatcher {
 					}
 				}
 
+				Ok(()) = paused_rx.changed() => {
+					debug!("Location watcher <id='{location_id}'> paused: {}", *paused_rx.borrow());
+				}
+
+				_ = sleep_until(flush_at.unwrap_or_else(Instant::now)), if flush_at.is_some() && !paused => {
+					if let Err(e) = flush_modified_files(
+						location_id,
+						modified_files.take_ready(Instant::now()),
+						&library_ctx,
+					).await {
+						error!("Failed to flush modified files for location: \
+							<id='{location_id}', error='{e:#?}'>",
+						);
+					}
+				}
+
 				_ = &mut stop_rx => {
 					debug!("Stop Location Manager event handler for location: <id='{}'>", location_id);
+					if let Err(e) = flush_modified_files(
+						location_id,
+						modified_files.take_all(),
+						&library_ctx,
+					).await {
+						error!("Failed to flush modified files for location: \
+							<id='{location_id}', error='{e:#?}'>",
+						);
+					}
 					break
 				}
 			}
@@ -168,6 +213,7 @@ impl LocationWatcher {
 		event_handler: &mut impl EventHandler,
 		library_ctx: &LibraryContext,
 		ignore_paths: &HashSet<PathBuf>,
+		modified_files: &mut ModifiedFilesBuffer,
 	) -> Result<(), LocationManagerError> {
 		if !check_event(&event, ignore_paths) {
 			return Ok(());
@@ -192,8 +238,35 @@ impl LocationWatcher {
 		}
 
 		event_handler
-			.handle_event(location, library_ctx, event)
+			.handle_event(location, library_ctx, event, modified_files)
+			.await
+	}
+
+	async fn resync(
+		location_id: LocationId,
+		subtree: &Path,
+		library_ctx: &LibraryContext,
+		modified_files: &mut ModifiedFilesBuffer,
+	) -> Result<(), LocationManagerError> {
+		let Some(location) = fetch_location(library_ctx, location_id)
+			.include(indexer_job_location::include())
+			.exec()
+			.await?
+		else {
+			warn!("Tried to resync unknown location: <id='{location_id}'>");
+			return Ok(());
+		};
+
+		if !library_ctx
+			.location_manager()
+			.is_online(&location.pub_id)
 			.await
+		{
+			warn!("Tried to resync offline location: <id='{location_id}'>");
+			return Ok(());
+		}
+
+		resync_subtree(&location, subtree, library_ctx, modified_files).await
 	}
 
 	pub(super) fn ignore_path(
@@ -589,6 +662,58 @@ mod tests {
 		}
 	}
 
+	#[tokio::test]
+	#[traced_test]
+	async fn atomic_save_rename_event() {
+		let (root_dir, mut watcher, events_rx) = setup_watcher().await;
+
+		let file_path = root_dir.path().join("test.txt");
+		fs::write(&file_path, "test").await.unwrap();
+
+		watcher
+			.watch(root_dir.path(), notify::RecursiveMode::Recursive)
+			.expect("Failed to watch root directory");
+		debug!("Now watching {}", root_dir.path().display());
+
+		// This is how most editors save: write a temporary file, then rename it over the original
+		let temporary_file_path = root_dir.path().join(".test.txt.swp");
+		fs::write(&temporary_file_path, "test\nanother test")
+			.await
+			.unwrap();
+		fs::rename(&temporary_file_path, &file_path)
+			.await
+			.expect("Failed to rename file");
+
+		#[cfg(target_os = "windows")]
+		expect_event(
+			events_rx,
+			&file_path,
+			EventKind::Modify(ModifyKind::Name(RenameMode::To)),
+		)
+		.await;
+
+		#[cfg(target_os = "macos")]
+		expect_event(
+			events_rx,
+			&temporary_file_path,
+			EventKind::Modify(ModifyKind::Name(RenameMode::Any)),
+		)
+		.await;
+
+		#[cfg(target_os = "linux")]
+		expect_event(
+			events_rx,
+			&temporary_file_path,
+			EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+		)
+		.await;
+
+		debug!("Unwatching root directory: {}", root_dir.path().display());
+		if let Err(e) = watcher.unwatch(root_dir.path()) {
+			error!("Failed to unwatch root directory: {e:#?}");
+		}
+	}
+
 	#[tokio::test]
 	#[traced_test]
 	async fn update_dir_event() {
@@ -0,0 +1,196 @@
+use std::{
+	mem,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+use notify::Event;
+use tokio::sync::{
+	mpsc::{self, error::TrySendError},
+	Notify,
+};
+use tracing::{error, warn};
+
+/// How many file system events can wait to be handled before we stop buffering them
+pub(super) const EVENT_CHANNEL_CAPACITY: usize = 4096;
+
+/// How many separate subtrees we keep track of, before collapsing them into their common ancestor
+const MAX_OVERFLOWED_SUBTREES: usize = 64;
+
+/// The subtrees that had events dropped because the channel was full, which need to be resynced.
+///
+/// A burst (e.g. extracting a huge archive) usually happens under a single directory, so this stays tiny
+/// no matter how many events were dropped.
+#[derive(Debug, Default)]
+pub(super) struct OverflowedSubtrees {
+	roots: Vec<PathBuf>,
+}
+
+impl OverflowedSubtrees {
+	pub(super) fn push(&mut self, subtree: &Path) {
+		if self.roots.iter().any(|root| subtree.starts_with(root)) {
+			return;
+		}
+
+		self.roots.retain(|root| !root.starts_with(subtree));
+		self.roots.push(subtree.to_path_buf());
+
+		if self.roots.len() > MAX_OVERFLOWED_SUBTREES {
+			self.roots = vec![common_ancestor(&self.roots)];
+		}
+	}
+
+	pub(super) fn take(&mut self) -> Vec<PathBuf> {
+		mem::take(&mut self.roots)
+	}
+}
+
+fn common_ancestor(paths: &[PathBuf]) -> PathBuf {
+	let mut ancestor = paths[0].clone();
+	while !paths.iter().all(|path| path.starts_with(&ancestor)) {
+		if !ancestor.pop() {
+			break;
+		}
+	}
+	ancestor
+}
+
+/// Subtrees that need a resync, and a way to wake up the event handler when there are some.
+#[derive(Debug, Default)]
+pub(super) struct Overflow {
+	subtrees: Mutex<OverflowedSubtrees>,
+	notify: Notify,
+}
+
+impl Overflow {
+	fn push(&self, subtree: &Path) {
+		self.subtrees
+			.lock()
+			.expect("poisoned overflowed subtrees lock")
+			.push(subtree);
+		self.notify.notify_one();
+	}
+
+	/// Waits until events have been dropped, and returns the subtrees that they happened in.
+	pub(super) async fn overflowed(&self) -> Vec<PathBuf> {
+		self.notify.notified().await;
+		self.subtrees
+			.lock()
+			.expect("poisoned overflowed subtrees lock")
+			.take()
+	}
+}
+
+/// Sends file system events to the event handler through a bounded channel.
+///
+/// When the handler can't keep up and the channel is full, the event is dropped and the directory it
+/// happened in is marked for a resync instead, so a massive burst can't exhaust memory.
+pub(super) struct EventSender {
+	events_tx: mpsc::Sender<notify::Result<Event>>,
+	overflow: Arc<Overflow>,
+	location_path: PathBuf,
+}
+
+impl EventSender {
+	pub(super) fn new(
+		location_path: PathBuf,
+		capacity: usize,
+	) -> (Self, mpsc::Receiver<notify::Result<Event>>, Arc<Overflow>) {
+		let (events_tx, events_rx) = mpsc::channel(capacity);
+		let overflow = Arc::new(Overflow::default());
+
+		(
+			Self {
+				events_tx,
+				overflow: Arc::clone(&overflow),
+				location_path,
+			},
+			events_rx,
+			overflow,
+		)
+	}
+
+	pub(super) fn send(&self, result: notify::Result<Event>) {
+		match self.events_tx.try_send(result) {
+			Ok(()) => {}
+			Err(TrySendError::Full(Ok(event))) => {
+				if event.paths.is_empty() {
+					self.overflow.push(&self.location_path);
+				}
+
+				for path in &event.paths {
+					self.overflow
+						.push(path.parent().unwrap_or(&self.location_path));
+				}
+			}
+			Err(TrySendError::Full(Err(e))) => {
+				warn!("Dropped watcher error as the event channel is full: {e:#?}");
+			}
+			Err(TrySendError::Closed(_)) => {
+				error!(
+					"Tried to send location file system events to a closed channel: <path='{}'>",
+					self.location_path.display()
+				);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use notify::EventKind;
+
+	#[test]
+	fn overflowed_subtrees_are_merged() {
+		let mut subtrees = OverflowedSubtrees::default();
+
+		subtrees.push(Path::new("/location/archive/a/b"));
+		subtrees.push(Path::new("/location/archive/a"));
+		subtrees.push(Path::new("/location/archive/a/c"));
+		subtrees.push(Path::new("/location/other"));
+
+		assert_eq!(
+			subtrees.take(),
+			vec![
+				PathBuf::from("/location/archive/a"),
+				PathBuf::from("/location/other")
+			]
+		);
+
+		for i in 0..=MAX_OVERFLOWED_SUBTREES {
+			subtrees.push(&Path::new("/location/archive").join(i.to_string()));
+		}
+
+		assert_eq!(subtrees.take(), vec![PathBuf::from("/location/archive")]);
+	}
+
+	#[tokio::test]
+	async fn flood_beyond_capacity_signals_resync() {
+		let capacity = 8;
+		let (sender, mut events_rx, overflow) =
+			EventSender::new(PathBuf::from("/location"), capacity);
+
+		for i in 0..10_000 {
+			sender.send(Ok(Event::new(EventKind::Any).add_path(PathBuf::from(
+				format!("/location/archive/dir{}/file{i}", i % 8),
+			))));
+		}
+
+		let mut buffered = 0;
+		while events_rx.try_recv().is_ok() {
+			buffered += 1;
+		}
+		assert_eq!(buffered, capacity);
+
+		let mut subtrees = overflow.overflowed().await;
+		subtrees.sort();
+		assert_eq!(
+			subtrees,
+			(0..8)
+				.map(|i| PathBuf::from(format!("/location/archive/dir{i}")))
+				.collect::<Vec<_>>()
+		);
+	}
+}
@@ -2,7 +2,7 @@ use crate::{
 	invalidate_query,
 	library::LibraryContext,
 	location::{
-		delete_directory,
+		delete_directory, fetch_location,
 		file_path_helper::create_file_path,
 		indexer::indexer_job::indexer_job_location,
 		manager::{helpers::subtract_location_path, LocationId, LocationManagerError},
@@ -10,11 +10,13 @@ use crate::{
 	object::{
 		identifier_job::FileMetadata,
 		preview::{
-			can_generate_thumbnail_for_image, generate_image_thumbnail, THUMBNAIL_CACHE_DIR_NAME,
+			can_generate_thumbnail_for_image, generate_image_thumbnail, regenerate_thumbnail,
+			THUMBNAIL_CACHE_DIR_NAME,
 		},
 		validation::hash::file_checksum,
 	},
 	prisma::{file_path, object},
+	util::db::update_file_paths_batch,
 };
 
 use std::{
@@ -26,14 +28,17 @@ use std::{
 
 use chrono::{DateTime, FixedOffset, Local, Utc};
 use int_enum::IntEnum;
-use notify::{event::RemoveKind, Event};
+use notify::{
+	event::{CreateKind, RemoveKind},
+	Event, EventKind,
+};
 use prisma_client_rust::{raw, PrismaValue};
 use sd_file_ext::extensions::ImageExtension;
-use tokio::{fs, io::ErrorKind};
+use tokio::{fs, io::ErrorKind, time::Instant};
 use tracing::{error, info, trace, warn};
 use uuid::Uuid;
 
-use super::file_path_with_object;
+use super::{file_path_with_object, ModifiedFilesBuffer};
 
 pub(super) fn check_event(event: &Event, ignore_paths: &HashSet<PathBuf>) -> bool {
 	// if path includes .DS_Store, .spacedrive or is in the `ignore_paths` set, we ignore
@@ -153,7 +158,9 @@ pub(super) async fn create_file(
 	let FileMetadata {
 		cas_id,
 		kind,
+		mime,
 		fs_metadata,
+		..
 	} = FileMetadata::new(&location.path, &created_file.materialized_path).await?;
 
 	let existing_object = db
@@ -191,6 +198,7 @@ pub(super) async fn create_file(
 						DateTime::<Local>::from(fs_metadata.created().unwrap()).into(),
 					),
 					object::kind::set(kind.int_value()),
+					object::mime::set(Some(mime.to_string())),
 					object::size_in_bytes::set(size_str.clone()),
 				],
 			)
@@ -227,11 +235,14 @@ pub(super) async fn file_creation_or_update(
 	location: &indexer_job_location::Data,
 	event: &Event,
 	library_ctx: &LibraryContext,
+	modified_files: &mut ModifiedFilesBuffer,
 ) -> Result<(), LocationManagerError> {
-	if let Some(ref file_path) =
-		get_existing_file_path(location, &event.paths[0], false, library_ctx).await?
+	if get_existing_file_path(location, &event.paths[0], false, library_ctx)
+		.await?
+		.is_some()
 	{
-		inner_update_file(location, file_path, event, library_ctx).await
+		modified_files.push(event.paths[0].clone(), Instant::now());
+		Ok(())
 	} else {
 		// We received None because it is a new file
 		create_file(location, event, library_ctx).await
@@ -242,14 +253,15 @@ pub(super) async fn update_file(
 	location: &indexer_job_location::Data,
 	event: &Event,
 	library_ctx: &LibraryContext,
+	modified_files: &mut ModifiedFilesBuffer,
 ) -> Result<(), LocationManagerError> {
 	if location.node_id == library_ctx.node_local_id {
-		if let Some(ref file_path) =
-			get_existing_file_path(location, &event.paths[0], false, library_ctx).await?
+		if get_existing_file_path(location, &event.paths[0], false, library_ctx)
+			.await?
+			.is_some()
 		{
-			let ret = inner_update_file(location, file_path, event, library_ctx).await;
-			invalidate_query!(library_ctx, "locations.getExplorerData");
-			ret
+			modified_files.push(event.paths[0].clone(), Instant::now());
+			Ok(())
 		} else {
 			Err(LocationManagerError::UpdateNonExistingFile(
 				event.paths[0].clone(),
@@ -260,23 +272,73 @@ pub(super) async fn update_file(
 	}
 }
 
+/// Applies all the buffered modifications of a location at once: sizes and modification times are
+/// written in a single batch, and then each modified file is re-identified exactly once.
+pub(super) async fn flush_modified_files(
+	location_id: LocationId,
+	paths: HashSet<PathBuf>,
+	library_ctx: &LibraryContext,
+) -> Result<(), LocationManagerError> {
+	if paths.is_empty() {
+		return Ok(());
+	}
+
+	let Some(location) = fetch_location(library_ctx, location_id)
+		.include(indexer_job_location::include())
+		.exec()
+		.await?
+	else {
+		warn!("Tried to flush modified files for unknown location: <id='{location_id}'>");
+		return Ok(());
+	};
+
+	let mut updates = Vec::with_capacity(paths.len());
+	let mut file_paths = Vec::with_capacity(paths.len());
+
+	for path in paths {
+		let Some(file_path) = get_existing_file_path(&location, &path, false, library_ctx).await?
+		else {
+			// The file was removed before we got to flush its modifications
+			continue;
+		};
+
+		let metadata = match fs::metadata(&path).await {
+			Ok(metadata) => metadata,
+			Err(e) if e.kind() == ErrorKind::NotFound => continue,
+			Err(e) => return Err(e.into()),
+		};
+
+		updates.push((file_path.id, metadata.len(), metadata.modified()?));
+		file_paths.push((file_path, path));
+	}
+
+	update_file_paths_batch(&library_ctx.db, location.id, &updates).await?;
+
+	for (file_path, path) in file_paths {
+		inner_update_file(&location, &file_path, &path, library_ctx).await?;
+	}
+
+	invalidate_query!(library_ctx, "locations.getExplorerData");
+
+	Ok(())
+}
+
 async fn inner_update_file(
 	location: &indexer_job_location::Data,
 	file_path: &file_path_with_object::Data,
-	event: &Event,
+	path: impl AsRef<Path>,
 	library_ctx: &LibraryContext,
 ) -> Result<(), LocationManagerError> {
+	let path = path.as_ref();
+
 	trace!(
 		"Location: <root_path ='{}'> updating file: {}",
 		&location.path,
-		event.paths[0].display()
+		path.display()
 	);
 
-	let FileMetadata {
-		cas_id,
-		fs_metadata,
-		..
-	} = FileMetadata::new(&location.path, &file_path.materialized_path).await?;
+	let FileMetadata { cas_id, .. } =
+		FileMetadata::new(&location.path, &file_path.materialized_path).await?;
 
 	if let Some(old_cas_id) = &file_path.cas_id {
 		if old_cas_id != &cas_id {
@@ -287,16 +349,13 @@ async fn inner_update_file(
 				.update(
 					file_path::location_id_id(location.id, file_path.id),
 					vec![
-						file_path::cas_id::set(Some(old_cas_id.clone())),
+						file_path::cas_id::set(Some(cas_id.clone())),
 						// file_path::size_in_bytes::set(fs_metadata.len().to_string()),
 						// file_path::kind::set(kind.int_value()),
-						file_path::date_modified::set(
-							DateTime::<Local>::from(fs_metadata.created().unwrap()).into(),
-						),
 						file_path::integrity_checksum::set(
 							if file_path.integrity_checksum.is_some() {
 								// If a checksum was already computed, we need to recompute it
-								Some(file_checksum(&event.paths[0]).await?)
+								Some(file_checksum(path).await?)
 							} else {
 								None
 							},
@@ -306,16 +365,19 @@ async fn inner_update_file(
 				.exec()
 				.await?;
 
-			if file_path
-				.object
-				.as_ref()
-				.map(|o| o.has_thumbnail)
-				.unwrap_or_default()
-			{
-				// if this file had a thumbnail previously, we update it to match the new content
-				if !file_path.extension.is_empty() {
-					generate_thumbnail(&file_path.extension, &cas_id, &event.paths[0], library_ctx)
-						.await;
+			// the old thumbnail shows the old content, so it's replaced with one of the new content
+			if let Some(object) = &file_path.object {
+				if let Err(e) = regenerate_thumbnail(
+					library_ctx,
+					object.id,
+					&file_path.extension,
+					path.to_path_buf(),
+					old_cas_id,
+					&cas_id,
+				)
+				.await
+				{
+					error!("Failed to regenerate thumbnail on location manager: {e:#?}");
 				}
 			}
 		}
@@ -330,8 +392,16 @@ pub(super) async fn rename_both_event(
 	location: &indexer_job_location::Data,
 	event: &Event,
 	library_ctx: &LibraryContext,
+	modified_files: &mut ModifiedFilesBuffer,
 ) -> Result<(), LocationManagerError> {
-	rename(&event.paths[1], &event.paths[0], location, library_ctx).await
+	rename(
+		&event.paths[1],
+		&event.paths[0],
+		location,
+		library_ctx,
+		modified_files,
+	)
+	.await
 }
 
 pub(super) async fn rename(
@@ -339,13 +409,46 @@ pub(super) async fn rename(
 	old_path: impl AsRef<Path>,
 	location: &indexer_job_location::Data,
 	library_ctx: &LibraryContext,
+	modified_files: &mut ModifiedFilesBuffer,
 ) -> Result<(), LocationManagerError> {
+	// Many editors save by writing to a temporary file and renaming it over the original.
+	// When the destination is already indexed, we treat it as a modification of the destination
+	// instead, so it keeps its object, and drop whatever we've indexed for the temporary file.
+	// The lookup can match a path that only differs in case (the extension is `COLLATE NOCASE`), so a
+	// case-only rename may find the file being renamed, which we tell apart from a real destination
+	// by comparing the paths again here.
+	let new_path_materialized = extract_materialized_path(location, new_path.as_ref())?;
+	if let Some(destination) =
+		get_existing_file_path(location, new_path.as_ref(), false, library_ctx)
+			.await?
+			.filter(|destination| {
+				Path::new(&destination.materialized_path) == new_path_materialized
+			}) {
+		trace!(
+			"Location: <root_path ='{}'> atomic save onto: {}",
+			location.path,
+			new_path.as_ref().display()
+		);
+
+		if let Some(temporary) =
+			get_existing_file_path(location, old_path.as_ref(), false, library_ctx).await?
+		{
+			if temporary.id != destination.id {
+				remove_file_path(location.id, temporary.id, temporary.object_id, library_ctx)
+					.await?;
+			}
+		}
+
+		modified_files.push(new_path.as_ref().to_path_buf(), Instant::now());
+
+		return Ok(());
+	}
+
 	let mut old_path_materialized = extract_materialized_path(location, old_path.as_ref())?
 		.to_str()
 		.expect("Found non-UTF-8 path")
 		.to_string();
 
-	let new_path_materialized = extract_materialized_path(location, new_path.as_ref())?;
 	let mut new_path_materialized_str = new_path_materialized
 		.to_str()
 		.expect("Found non-UTF-8 path")
@@ -435,25 +538,8 @@ pub(super) async fn remove_event(
 					delete_directory(library_ctx, location.id, Some(file_path.materialized_path))
 						.await?;
 				} else {
-					library_ctx
-						.db
-						.file_path()
-						.delete(file_path::location_id_id(location.id, file_path.id))
-						.exec()
+					remove_file_path(location.id, file_path.id, file_path.object_id, library_ctx)
 						.await?;
-
-					if let Some(object_id) = file_path.object_id {
-						library_ctx
-							.db
-							.object()
-							.delete_many(vec![
-								object::id::equals(object_id),
-								// https://www.prisma.io/docs/reference/api-reference/prisma-client-reference#none
-								object::file_paths::none(vec![]),
-							])
-							.exec()
-							.await?;
-					}
 				}
 			}
 			Err(e) => return Err(e.into()),
@@ -465,6 +551,139 @@ pub(super) async fn remove_event(
 	Ok(())
 }
 
+/// Brings the index of a subtree in line with what's on disk, for when its events had to be dropped.
+///
+/// New files and directories are created, existing files are treated as modified, and whatever is
+/// indexed under the subtree but gone from disk is removed.
+pub(super) async fn resync_subtree(
+	location: &indexer_job_location::Data,
+	subtree: impl AsRef<Path>,
+	library_ctx: &LibraryContext,
+	modified_files: &mut ModifiedFilesBuffer,
+) -> Result<(), LocationManagerError> {
+	let subtree = subtree.as_ref();
+	trace!(
+		"Location: <root_path ='{}'> resyncing subtree: {}",
+		location.path,
+		subtree.display()
+	);
+
+	let mut on_disk = HashSet::new();
+	let mut dirs = vec![subtree.to_path_buf()];
+
+	// Directories are created before we walk into them, so their children always find their parent
+	while let Some(dir) = dirs.pop() {
+		let mut entries = match fs::read_dir(&dir).await {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == ErrorKind::NotFound => continue,
+			Err(e) => return Err(e.into()),
+		};
+
+		while let Some(entry) = entries.next_entry().await? {
+			let path = entry.path();
+			let event = Event::new(EventKind::Create(CreateKind::Any)).add_path(path.clone());
+			if !check_event(&event, &HashSet::new()) {
+				continue;
+			}
+
+			if entry.file_type().await?.is_dir() {
+				if get_existing_file_path(location, &path, true, library_ctx)
+					.await?
+					.is_none()
+				{
+					create_dir(location, &event, library_ctx).await?;
+				}
+				dirs.push(path.clone());
+			} else {
+				file_creation_or_update(location, &event, library_ctx, modified_files).await?;
+			}
+
+			on_disk.insert(path);
+		}
+	}
+
+	let mut subtree_prefix = extract_materialized_path(location, subtree)?
+		.to_str()
+		.expect("Found non-UTF-8 path")
+		.to_string();
+	if !subtree_prefix.is_empty() && !subtree_prefix.ends_with('/') {
+		subtree_prefix += "/";
+	}
+	let subtree_exists = fs::metadata(subtree).await.is_ok();
+
+	let mut indexed = library_ctx
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(location.id),
+			file_path::materialized_path::starts_with(subtree_prefix.clone()),
+		])
+		.exec()
+		.await?;
+	// Parents sort before their children, so a removed directory takes its children with it
+	indexed.sort_by(|a, b| a.materialized_path.cmp(&b.materialized_path));
+
+	let mut removed_dirs: Vec<String> = vec![];
+	for file_path in indexed {
+		// The location's root, and the subtree's own directory if it's still there
+		if file_path.materialized_path == "/"
+			|| (subtree_exists && file_path.materialized_path == subtree_prefix)
+			|| on_disk.contains(&Path::new(&location.path).join(&file_path.materialized_path))
+			|| removed_dirs
+				.iter()
+				.any(|dir| file_path.materialized_path.starts_with(dir))
+		{
+			continue;
+		}
+
+		if file_path.is_dir {
+			delete_directory(
+				library_ctx,
+				location.id,
+				Some(file_path.materialized_path.clone()),
+			)
+			.await?;
+			removed_dirs.push(file_path.materialized_path);
+		} else {
+			remove_file_path(location.id, file_path.id, file_path.object_id, library_ctx).await?;
+		}
+	}
+
+	invalidate_query!(library_ctx, "locations.getExplorerData");
+
+	Ok(())
+}
+
+/// Removes a file's path, along with its object if no other path points to it anymore.
+async fn remove_file_path(
+	location_id: LocationId,
+	file_path_id: i32,
+	object_id: Option<i32>,
+	library_ctx: &LibraryContext,
+) -> Result<(), LocationManagerError> {
+	library_ctx
+		.db
+		.file_path()
+		.delete(file_path::location_id_id(location_id, file_path_id))
+		.exec()
+		.await?;
+
+	if let Some(object_id) = object_id {
+		library_ctx
+			.db
+			.object()
+			.delete_many(vec![
+				object::id::equals(object_id),
+				// https://www.prisma.io/docs/reference/api-reference/prisma-client-reference#none
+				object::file_paths::none(vec![]),
+			])
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
+
 fn extract_materialized_path(
 	location: &indexer_job_location::Data,
 	path: impl AsRef<Path>,
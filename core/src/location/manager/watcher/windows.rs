@@ -13,7 +13,7 @@ use tracing::{trace, warn};
 
 use super::{
 	utils::{create_dir, create_file, remove_event, rename, update_file},
-	EventHandler,
+	EventHandler, ModifiedFilesBuffer,
 };
 
 #[derive(Debug, Default)]
@@ -36,6 +36,7 @@ impl EventHandler for WindowsEventHandler {
 		location: indexer_job_location::Data,
 		library_ctx: &LibraryContext,
 		event: Event,
+		modified_files: &mut ModifiedFilesBuffer,
 	) -> Result<(), LocationManagerError> {
 		trace!("Received Windows event: {:#?}", event);
 
@@ -54,7 +55,7 @@ impl EventHandler for WindowsEventHandler {
 					if let Some(create_file_event) = self.create_file_stack.take() {
 						create_file(&location, &create_file_event, library_ctx).await?;
 					} else {
-						update_file(&location, &event, library_ctx).await?;
+						update_file(&location, &event, library_ctx, modified_files).await?;
 					}
 				} else {
 					warn!("Unexpected Windows modify event on a directory");
@@ -73,6 +74,7 @@ impl EventHandler for WindowsEventHandler {
 					&from_event.paths[0],
 					&location,
 					library_ctx,
+					modified_files,
 				)
 				.await?;
 			}
@@ -3,8 +3,12 @@ use crate::{
 	job::Job,
 	library::LibraryContext,
 	object::{
-		identifier_job::full_identifier_job::{FullFileIdentifierJob, FullFileIdentifierJobInit},
+		identifier_job::{
+			full_identifier_job::{FullFileIdentifierJob, FullFileIdentifierJobInit},
+			perceptual_hash_job::backfill_perceptual_hashes,
+		},
 		preview::{ThumbnailJob, ThumbnailJobInit},
+		validation::integrity_job::{IntegrityReport, ObjectIntegrityJob, ObjectIntegrityJobInit},
 	},
 	prisma::{file_path, indexer_rules_in_location, location, node, object},
 	sync,
@@ -20,7 +24,7 @@ use std::{
 };
 
 use prisma_client_rust::QueryError;
-use tokio::{fs, io};
+use tokio::{fs, io, sync::oneshot};
 use tracing::{debug, info};
 use uuid::Uuid;
 
@@ -32,6 +36,7 @@ mod metadata;
 
 pub use error::LocationError;
 use indexer::indexer_job::{indexer_job_location, IndexerJob, IndexerJobInit};
+pub use indexer::reconcile::{reconcile, ReconcileReport};
 pub use manager::{LocationManager, LocationManagerError};
 use metadata::SpacedriveLocationMetadataFile;
 
@@ -158,6 +163,7 @@ pub struct LocationUpdateArgs {
 	pub name: Option<String>,
 	pub generate_preview_media: Option<bool>,
 	pub sync_preview_media: Option<bool>,
+	pub perceptual_hashing: Option<bool>,
 	pub hidden: Option<bool>,
 	pub indexer_rules_ids: Vec<i32>,
 }
@@ -189,6 +195,12 @@ impl LocationUpdateArgs {
 					location::sync_preview_media::set(v),
 				)
 			}),
+			self.perceptual_hashing.map(|v| {
+				(
+					("perceptual_hashing", json!(v)),
+					location::perceptual_hashing::set(v),
+				)
+			}),
 			self.hidden
 				.map(|v| (("hidden", json!(v)), location::hidden::set(v))),
 		]
@@ -227,6 +239,11 @@ impl LocationUpdateArgs {
 			}
 		}
 
+		// the identifier only hashes new images, so the ones identified before hashing was enabled are hashed now
+		if self.perceptual_hashing == Some(true) && !location.perceptual_hashing {
+			backfill_perceptual_hashes(ctx, self.id).await;
+		}
+
 		let current_rules_ids = location
 			.indexer_rules
 			.iter()
@@ -317,12 +334,42 @@ pub async fn scan_location(
 	))
 	.await;
 
-	ctx.spawn_job(Job::new(IndexerJobInit { location }, IndexerJob {}))
+	ctx.spawn_job(Job::new(IndexerJobInit { location }, IndexerJob::default()))
 		.await;
 
 	Ok(())
 }
 
+/// Runs an [`ObjectIntegrityJob`] that re-hashes every checksummed file in the location, and waits for its report.
+/// The report is also stored as the job's metadata.
+///
+/// There's no report if the location is on another node, or if the scan didn't finish (e.g. it was paused, or the
+/// location was already being scanned).
+pub async fn integrity_scan(
+	ctx: &LibraryContext,
+	location_id: i32,
+) -> Result<Option<IntegrityReport>, LocationError> {
+	let location = fetch_location(ctx, location_id)
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	if location.node_id != ctx.node_local_id {
+		return Ok(None);
+	}
+
+	let (report_tx, report_rx) = oneshot::channel();
+	ctx.spawn_job(Job::new(
+		ObjectIntegrityJobInit { location_id },
+		ObjectIntegrityJob {
+			report_tx: Some(report_tx),
+		},
+	))
+	.await;
+
+	Ok(report_rx.await.ok())
+}
+
 pub async fn relink_location(
 	ctx: &LibraryContext,
 	location_path: impl AsRef<Path>,
@@ -1,3 +1,5 @@
+use crate::job::JobConcurrency;
+
 use rspc::Type;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -40,6 +42,9 @@ pub struct NodeConfig {
 	pub name: String,
 	// the port this node uses for peer to peer communication. By default a random free port will be chosen each time the application is started.
 	pub p2p_port: Option<u32>,
+	/// How many jobs of each category can run at the same time.
+	#[serde(default)]
+	pub job_concurrency: JobConcurrency,
 	// /// The P2P identity public key
 	// pub p2p_cert: Vec<u8>,
 	// /// The P2P identity private key
@@ -71,6 +76,7 @@ impl NodeConfig {
 				}
 			},
 			p2p_port: None,
+			job_concurrency: JobConcurrency::default(),
 			metadata: ConfigMetadata {
 				version: Some(env!("CARGO_PKG_VERSION").into()),
 			},
@@ -100,7 +106,6 @@ impl NodeConfigManager {
 	}
 
 	/// write allows the user to update the configuration. This is done in a closure while a Mutex lock is held so that the user can't cause a race condition if the config were to be updated in multiple parts of the app at the same time.
-	#[allow(unused)]
 	pub(crate) async fn write<F: FnOnce(RwLockWriteGuard<NodeConfig>)>(
 		&self,
 		mutation_fn: F,
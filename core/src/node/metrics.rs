@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters which are updated as the node runs, and read through [`crate::Node::metrics`].
+///
+/// These are plain atomics so updating them is cheap enough for hot paths such as event emission.
+#[derive(Debug, Default)]
+pub struct NodeMetrics {
+	events_emitted: AtomicU64,
+	events_dropped: AtomicU64,
+	jobs_total: AtomicU64,
+	jobs_failed: AtomicU64,
+	bytes_encrypted: AtomicU64,
+}
+
+/// A point in time snapshot of the node's metrics, so the host can render it in whichever format it needs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+	pub events_emitted: u64,
+	/// Events which couldn't be delivered, because nobody was subscribed to the event bus
+	pub events_dropped: u64,
+	pub jobs_total: u64,
+	pub jobs_running: u64,
+	pub jobs_failed: u64,
+	pub bytes_encrypted: u64,
+}
+
+impl NodeMetrics {
+	pub(crate) fn event_emitted(&self, delivered: bool) {
+		self.events_emitted.fetch_add(1, Ordering::Relaxed);
+		if !delivered {
+			self.events_dropped.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	pub(crate) fn job_started(&self) {
+		self.jobs_total.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub(crate) fn job_failed(&self) {
+		self.jobs_failed.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub(crate) fn bytes_encrypted(&self, bytes: u64) {
+		self.bytes_encrypted.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	/// The running jobs are a gauge which the job manager already tracks, so it's passed in rather than counted twice.
+	pub(crate) fn snapshot(&self, jobs_running: u64) -> Metrics {
+		Metrics {
+			events_emitted: self.events_emitted.load(Ordering::Relaxed),
+			events_dropped: self.events_dropped.load(Ordering::Relaxed),
+			jobs_total: self.jobs_total.load(Ordering::Relaxed),
+			jobs_running,
+			jobs_failed: self.jobs_failed.load(Ordering::Relaxed),
+			bytes_encrypted: self.bytes_encrypted.load(Ordering::Relaxed),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn counters_advance() {
+		let metrics = NodeMetrics::default();
+		assert_eq!(metrics.snapshot(0), Metrics::default());
+
+		metrics.event_emitted(true);
+		metrics.event_emitted(false);
+		metrics.job_started();
+		metrics.job_started();
+		metrics.job_failed();
+		metrics.bytes_encrypted(1024);
+		metrics.bytes_encrypted(512);
+
+		assert_eq!(
+			metrics.snapshot(1),
+			Metrics {
+				events_emitted: 2,
+				events_dropped: 1,
+				jobs_total: 2,
+				jobs_running: 1,
+				jobs_failed: 1,
+				bytes_encrypted: 1536,
+			}
+		);
+	}
+}
@@ -7,8 +7,12 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 mod config;
+mod metrics;
+mod signal;
 
 pub use config::*;
+pub use metrics::*;
+pub use signal::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub struct LibraryNode {
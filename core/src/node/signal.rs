@@ -0,0 +1,137 @@
+use crate::Node;
+
+use std::time::Duration;
+
+use tokio::{
+	signal,
+	sync::mpsc,
+	time::{sleep, Instant},
+};
+use tracing::{error, info, warn};
+
+/// How long the node gets to shut down after a termination signal, before we stop waiting for it.
+pub const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// The grace period once a second termination signal is received, as the user wants out now.
+pub const FORCED_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+impl Node {
+	/// Waits for the process to be asked to terminate (Ctrl+C, or SIGTERM on Unix), then shuts the node down.
+	///
+	/// This is meant for headless hosts, which can return from `main` once this does.
+	/// Returns `false` if the node didn't finish shutting down within its grace period.
+	pub async fn run_until_signal(&self) -> bool {
+		self.shutdown_on(termination_signals()).await
+	}
+
+	/// Shuts the node down once the first signal is received from `signals`.
+	///
+	/// Shutting down is given [`SHUTDOWN_GRACE_PERIOD`] to finish, which a second signal shortens to
+	/// [`FORCED_SHUTDOWN_GRACE_PERIOD`]. Returns `false` if the shutdown didn't finish in time, or if `signals`
+	/// was closed without ever receiving one.
+	pub async fn shutdown_on(&self, mut signals: mpsc::Receiver<()>) -> bool {
+		if signals.recv().await.is_none() {
+			return false;
+		}
+
+		info!("Termination signal received, shutting down gracefully...");
+
+		let shutdown = self.shutdown();
+		let deadline = sleep(SHUTDOWN_GRACE_PERIOD);
+		tokio::pin!(shutdown, deadline);
+
+		let mut signals_open = true;
+
+		loop {
+			tokio::select! {
+				_ = &mut shutdown => return true,
+				_ = &mut deadline => {
+					error!("Node didn't shut down within its grace period, giving up on it");
+					return false;
+				}
+				signal = signals.recv(), if signals_open => match signal {
+					Some(()) => {
+						warn!("Second termination signal received, forcing shutdown...");
+						deadline
+							.as_mut()
+							.reset(Instant::now() + FORCED_SHUTDOWN_GRACE_PERIOD);
+					}
+					None => signals_open = false,
+				},
+			}
+		}
+	}
+}
+
+/// Forwards every Ctrl+C (and SIGTERM on Unix) the process receives.
+fn termination_signals() -> mpsc::Receiver<()> {
+	let (tx, rx) = mpsc::channel(1);
+
+	tokio::spawn(async move {
+		#[cfg(unix)]
+		let mut terminate = match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+			Ok(terminate) => Some(terminate),
+			Err(e) => {
+				error!("Failed to install SIGTERM handler: {e:#?}");
+				None
+			}
+		};
+
+		loop {
+			#[cfg(unix)]
+			let terminated = async {
+				match terminate.as_mut() {
+					Some(terminate) => terminate.recv().await,
+					None => std::future::pending().await,
+				}
+			};
+
+			#[cfg(not(unix))]
+			let terminated = std::future::pending::<Option<()>>();
+
+			tokio::select! {
+				result = signal::ctrl_c() => {
+					if let Err(e) = result {
+						error!("Failed to listen for Ctrl+C: {e:#?}");
+						return;
+					}
+				}
+				_ = terminated => {}
+			}
+
+			// a full channel already has a signal waiting to be handled
+			if let Err(mpsc::error::TrySendError::Closed(())) = tx.try_send(()) {
+				return;
+			}
+		}
+	});
+
+	rx
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn signal_triggers_shutdown() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+
+		let (signals_tx, signals_rx) = mpsc::channel(2);
+		signals_tx.send(()).await.unwrap();
+
+		assert!(node.shutdown_on(signals_rx).await);
+	}
+
+	#[tokio::test]
+	async fn closed_channel_does_not_shut_down() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+
+		let (signals_tx, signals_rx) = mpsc::channel(1);
+		drop(signals_tx);
+
+		assert!(!node.shutdown_on(signals_rx).await);
+	}
+}
@@ -0,0 +1,722 @@
+use crate::{
+	library::LibraryContext,
+	location::{
+		file_path_helper::{check_subpath, create_file_path},
+		LocationError, LocationManagerError,
+	},
+	object::{
+		identifier_job::FileMetadata,
+		tag::{assign_tag, find_or_create_tag, TagError},
+	},
+	prisma::{file_path, location, object},
+};
+
+use std::{
+	collections::HashSet,
+	io::Cursor,
+	path::{Component, Path, PathBuf},
+	pin::Pin,
+	task::{Context, Poll},
+};
+
+use chrono::{DateTime, Local};
+use int_enum::IntEnum;
+use prisma_client_rust::QueryError;
+use rspc::{ErrorCode, Type};
+use sd_crypto::{
+	crypto::{
+		file::{encrypt, EncryptOptions},
+		reader::DecryptReader,
+	},
+	Protected,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+	fs::{self, File, OpenOptions},
+	io::{self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncWriteExt, ReadBuf, Take},
+};
+use tracing::info;
+use uuid::Uuid;
+
+pub const BUNDLE_EXTENSION: &str = "sdbundle";
+
+/// Manifests are read into memory before anything else, so a corrupt length can't make us allocate gigabytes.
+const MAX_MANIFEST_LEN: u64 = 16 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum BundleError {
+	#[error("Object has no file to export (id: {0})")]
+	ObjectNotFound(i32),
+	#[error("Bundle entry has an invalid path: {0}")]
+	InvalidPath(String),
+	#[error("Bundle manifest is invalid (error: {0})")]
+	InvalidManifest(#[from] serde_json::Error),
+	#[error("Bundle manifest is too large ({0} bytes)")]
+	ManifestTooLarge(u64),
+	#[error("Crypto error (error: {0:?})")]
+	CryptoError(#[from] sd_crypto::Error),
+	#[error("Location error")]
+	LocationError(#[from] LocationError),
+	#[error("Location manager error")]
+	LocationManagerError(#[from] LocationManagerError),
+	#[error("Tag error")]
+	TagError(#[from] TagError),
+	#[error("I/O error (error: {0:?})")]
+	IOError(#[from] io::Error),
+	#[error("Database error (error: {0:?})")]
+	DatabaseError(#[from] QueryError),
+}
+
+impl From<BundleError> for rspc::Error {
+	fn from(err: BundleError) -> Self {
+		match err {
+			BundleError::LocationError(e) => e.into(),
+			BundleError::ObjectNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			BundleError::InvalidPath(_)
+			| BundleError::InvalidManifest(_)
+			| BundleError::ManifestTooLarge(_)
+			| BundleError::CryptoError(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// Describes the files in a bundle, in the order their contents follow it.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BundleManifest {
+	pub entries: Vec<BundleEntry>,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+pub struct BundleEntry {
+	/// Relative to the directory the bundle is imported into, always separated by '/'.
+	pub path: String,
+	pub size: u64,
+	pub tags: Vec<BundleTag>,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+pub struct BundleTag {
+	pub name: String,
+	pub color: Option<String>,
+}
+
+object::select!(object_for_bundle {
+	id
+	file_paths: select {
+		is_dir
+		materialized_path
+		location: select { name path }
+	}
+	tags: select {
+		tag: select { name color }
+	}
+});
+
+/// Exports the files of the objects to a single encrypted bundle at `dst`, along with their tags.
+///
+/// Every file keeps its path within its location, under a directory named after the location, so files from
+/// different locations can't collide.
+pub async fn export_bundle(
+	library: &LibraryContext,
+	object_ids: &[i32],
+	dst: impl AsRef<Path>,
+	password: Protected<Vec<u8>>,
+) -> Result<BundleManifest, BundleError> {
+	let objects = library
+		.db
+		.object()
+		.find_many(vec![object::id::in_vec(object_ids.to_vec())])
+		.select(object_for_bundle::select())
+		.exec()
+		.await?;
+
+	let mut seen = HashSet::new();
+	let mut files = Vec::with_capacity(objects.len());
+	for &object_id in object_ids.iter().filter(|id| seen.insert(**id)) {
+		let object = objects
+			.iter()
+			.find(|object| object.id == object_id)
+			.ok_or(BundleError::ObjectNotFound(object_id))?;
+		let file_path = object
+			.file_paths
+			.iter()
+			.find(|file_path| !file_path.is_dir)
+			.ok_or(BundleError::ObjectNotFound(object_id))?;
+
+		let source = Path::new(&file_path.location.path).join(&file_path.materialized_path);
+
+		files.push((
+			BundleEntry {
+				path: format!(
+					"{}/{}",
+					file_path.location.name.replace('/', "_"),
+					file_path.materialized_path
+				),
+				size: fs::metadata(&source).await?.len(),
+				tags: object
+					.tags
+					.iter()
+					.filter_map(|tag_on_object| {
+						Some(BundleTag {
+							name: tag_on_object.tag.name.clone()?,
+							color: tag_on_object.tag.color.clone(),
+						})
+					})
+					.collect(),
+			},
+			source,
+		));
+	}
+
+	let dst = dst.as_ref();
+	let mut writer = File::create(dst).await?;
+	let manifest = match write_bundle(files, &mut writer, password).await {
+		Ok(manifest) => manifest,
+		Err(e) => {
+			drop(writer);
+			fs::remove_file(dst).await.ok();
+			return Err(e);
+		}
+	};
+	writer.sync_all().await?;
+
+	info!(
+		"Exported {} files to {}",
+		manifest.entries.len(),
+		dst.display()
+	);
+
+	Ok(manifest)
+}
+
+/// Unpacks a bundle into a directory of a location, and indexes its files.
+///
+/// Nothing that already exists is overwritten. If `restore_tags` is set, the tags that were stored in the bundle are
+/// assigned to the imported files, creating any that the library doesn't have yet.
+pub async fn import_bundle(
+	library: &LibraryContext,
+	src: impl AsRef<Path>,
+	location_id: i32,
+	subpath: impl AsRef<Path>,
+	password: Protected<Vec<u8>>,
+	restore_tags: bool,
+) -> Result<BundleManifest, BundleError> {
+	let subpath = subpath.as_ref();
+	check_subpath(subpath)?;
+
+	let location = library
+		.db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	let dst_dir = Path::new(&location.path).join(subpath);
+	if !dst_dir.is_dir() {
+		return Err(LocationError::DirectoryNotFound(subpath.display().to_string()).into());
+	}
+
+	let (manifest, mut reader) = open_bundle(File::open(src.as_ref()).await?, password).await?;
+
+	// the files are indexed here, so the watcher shouldn't index them too
+	let mut guards = Vec::new();
+	for path in paths_to_create(&manifest, &dst_dir)? {
+		guards.push(
+			library
+				.location_manager()
+				.temporary_ignore_events_for_path(location_id, library.clone(), path)
+				.await?,
+		);
+	}
+
+	unpack(&manifest, &mut reader, &dst_dir).await?;
+
+	for entry in &manifest.entries {
+		let materialized_path = subpath.join(&entry.path);
+		let object_id = index_file(library, &location, &materialized_path).await?;
+
+		if restore_tags {
+			for tag in &entry.tags {
+				let (tag_id, _) =
+					find_or_create_tag(library, tag.name.clone(), tag.color.clone()).await?;
+				assign_tag(&library.db, object_id, tag_id).await?;
+			}
+		}
+	}
+
+	info!(
+		"Imported {} files into {}",
+		manifest.entries.len(),
+		dst_dir.display()
+	);
+
+	Ok(manifest)
+}
+
+/// Encrypts the manifest and the contents of the files into a bundle.
+///
+/// The plaintext is the length of the manifest (as a little-endian `u64`), the JSON manifest, and then the contents
+/// of every file in the manifest's order. It's streamed from the files, so it's never written to the disk unencrypted.
+pub(crate) async fn write_bundle<W>(
+	files: Vec<(BundleEntry, PathBuf)>,
+	writer: &mut W,
+	password: Protected<Vec<u8>>,
+) -> Result<BundleManifest, BundleError>
+where
+	W: AsyncWriteExt + Unpin + Send,
+{
+	let manifest = BundleManifest {
+		entries: files.iter().map(|(entry, _)| entry.clone()).collect(),
+	};
+
+	let manifest_bytes = serde_json::to_vec(&manifest)?;
+	let mut payload: Box<dyn AsyncRead + Unpin + Send> = Box::new(Cursor::new(
+		[
+			(manifest_bytes.len() as u64).to_le_bytes().to_vec(),
+			manifest_bytes,
+		]
+		.concat(),
+	));
+
+	// the files are read back by their size, so every one of them must be exactly the size that was recorded
+	for (entry, source) in files {
+		payload = Box::new(payload.chain(SizedFile {
+			inner: File::open(&source).await?.take(entry.size),
+			path: source,
+		}));
+	}
+
+	encrypt(payload, writer, password, EncryptOptions::default()).await?;
+
+	Ok(manifest)
+}
+
+/// Reads exactly as many bytes from a file as its entry in the manifest says it has. Extra bytes are left out, and a
+/// file which has shrunk since is an error, as it would shift the contents of every file after it.
+struct SizedFile {
+	inner: Take<File>,
+	path: PathBuf,
+}
+
+impl AsyncRead for SizedFile {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		let filled = buf.filled().len();
+
+		let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+		if let Poll::Ready(Ok(())) = result {
+			let missing = this.inner.limit();
+			if buf.filled().len() == filled && buf.remaining() > 0 && missing > 0 {
+				return Poll::Ready(Err(io::Error::new(
+					io::ErrorKind::UnexpectedEof,
+					format!(
+						"{} is {missing} bytes smaller than when it was added to the bundle",
+						this.path.display()
+					),
+				)));
+			}
+		}
+
+		result
+	}
+}
+
+/// Decrypts the start of a bundle, returning its manifest and a reader that's positioned at the first file's contents.
+pub(crate) async fn open_bundle<R>(
+	reader: R,
+	password: Protected<Vec<u8>>,
+) -> Result<(BundleManifest, DecryptReader<R>), BundleError>
+where
+	R: AsyncRead + AsyncSeek + Unpin + Send,
+{
+	let (_, mut reader) = DecryptReader::open(reader, password).await?;
+
+	let manifest_len = reader.read_u64_le().await?;
+	if manifest_len > MAX_MANIFEST_LEN {
+		return Err(BundleError::ManifestTooLarge(manifest_len));
+	}
+
+	let mut manifest_bytes = vec![0u8; manifest_len as usize];
+	reader.read_exact(&mut manifest_bytes).await?;
+
+	let manifest: BundleManifest = serde_json::from_slice(&manifest_bytes)?;
+	for entry in &manifest.entries {
+		entry_path(Path::new(""), entry)?;
+	}
+
+	Ok((manifest, reader))
+}
+
+/// Writes the contents of every file in the manifest below `dst_dir`, creating any directories they're in.
+async fn unpack<R>(
+	manifest: &BundleManifest,
+	reader: &mut R,
+	dst_dir: &Path,
+) -> Result<(), BundleError>
+where
+	R: AsyncRead + Unpin,
+{
+	for entry in &manifest.entries {
+		let path = entry_path(dst_dir, entry)?;
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent).await?;
+		}
+
+		let mut file = OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&path)
+			.await?;
+
+		let copied = io::copy(&mut (&mut *reader).take(entry.size), &mut file).await?;
+		if copied != entry.size {
+			return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+		}
+
+		file.sync_all().await?;
+	}
+
+	Ok(())
+}
+
+/// The paths that unpacking the bundle will create, including any directories that don't exist yet.
+fn paths_to_create(manifest: &BundleManifest, dst_dir: &Path) -> Result<Vec<PathBuf>, BundleError> {
+	let mut paths = Vec::new();
+	for entry in &manifest.entries {
+		let path = entry_path(dst_dir, entry)?;
+
+		paths.extend(
+			path.ancestors()
+				.skip(1)
+				.take_while(|dir| *dir != dst_dir && !dir.exists())
+				.map(Path::to_path_buf),
+		);
+		paths.push(path);
+	}
+
+	paths.sort();
+	paths.dedup();
+
+	Ok(paths)
+}
+
+/// Resolves the path of an entry below `dst_dir`, refusing any that would escape it.
+fn entry_path(dst_dir: &Path, entry: &BundleEntry) -> Result<PathBuf, BundleError> {
+	let path = Path::new(&entry.path);
+
+	if entry.path.is_empty()
+		|| path
+			.components()
+			.any(|component| !matches!(component, Component::Normal(_)))
+	{
+		return Err(BundleError::InvalidPath(entry.path.clone()));
+	}
+
+	Ok(dst_dir.join(path))
+}
+
+/// Creates the `file_path` (and any missing parent directories) and object for a file that was just unpacked.
+async fn index_file(
+	library: &LibraryContext,
+	location: &location::Data,
+	materialized_path: &Path,
+) -> Result<i32, BundleError> {
+	let db = &library.db;
+
+	let mut parent_id = db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(location.id),
+			file_path::materialized_path::equals("/".to_string()),
+		])
+		.exec()
+		.await?
+		.map(|root| root.id);
+
+	let mut dirs = materialized_path.ancestors().skip(1).collect::<Vec<_>>();
+	dirs.pop(); // the location itself
+	for dir in dirs.into_iter().rev() {
+		let dir_str = dir.to_str().expect("Found non-UTF-8 path");
+
+		let existing = db
+			.file_path()
+			.find_first(vec![
+				file_path::location_id::equals(location.id),
+				file_path::materialized_path::equals(format!("{dir_str}/")),
+			])
+			.exec()
+			.await?;
+
+		parent_id = Some(match existing {
+			Some(existing) => existing.id,
+			None => {
+				create_file_path(
+					library,
+					location.id,
+					dir_str.to_string(),
+					file_name_part(dir.file_name()),
+					"".to_string(),
+					parent_id,
+					true,
+				)
+				.await?
+				.id
+			}
+		});
+	}
+
+	let created_file = create_file_path(
+		library,
+		location.id,
+		materialized_path
+			.to_str()
+			.expect("Found non-UTF-8 path")
+			.to_string(),
+		file_name_part(materialized_path.file_stem()),
+		file_name_part(materialized_path.extension()),
+		parent_id,
+		false,
+	)
+	.await?;
+
+	let FileMetadata {
+		cas_id,
+		kind,
+		mime,
+		fs_metadata,
+		..
+	} = FileMetadata::new(&location.path, &created_file.materialized_path).await?;
+
+	// the contents may already be in the library, in which case the file joins that object
+	let existing_object = db
+		.object()
+		.find_first(vec![object::file_paths::some(vec![
+			file_path::cas_id::equals(Some(cas_id.clone())),
+		])])
+		.exec()
+		.await?;
+
+	let object_id = match existing_object {
+		Some(object) => object.id,
+		None => {
+			db.object()
+				.create(
+					Uuid::new_v4().as_bytes().to_vec(),
+					vec![
+						object::date_created::set(
+							DateTime::<Local>::from(
+								fs_metadata.created().or_else(|_| fs_metadata.modified())?,
+							)
+							.into(),
+						),
+						object::kind::set(kind.int_value()),
+						object::mime::set(Some(mime.to_string())),
+						object::size_in_bytes::set(fs_metadata.len().to_string()),
+					],
+				)
+				.exec()
+				.await?
+				.id
+		}
+	};
+
+	db.file_path()
+		.update(
+			file_path::location_id_id(location.id, created_file.id),
+			vec![
+				file_path::cas_id::set(Some(cas_id)),
+				file_path::object_id::set(Some(object_id)),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok(object_id)
+}
+
+fn file_name_part(part: Option<&std::ffi::OsStr>) -> String {
+	part.and_then(|part| part.to_str())
+		.expect("Found non-UTF-8 path")
+		.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::Node;
+
+	fn entry(path: &str, size: usize, tags: &[&str]) -> BundleEntry {
+		BundleEntry {
+			path: path.to_string(),
+			size: size as u64,
+			tags: tags
+				.iter()
+				.map(|name| BundleTag {
+					name: name.to_string(),
+					color: Some("#FF0000".to_string()),
+				})
+				.collect(),
+		}
+	}
+
+	fn password() -> Protected<Vec<u8>> {
+		Protected::new(b"correct horse battery staple".to_vec())
+	}
+
+	#[tokio::test]
+	async fn round_trip() {
+		let src = tempfile::tempdir().unwrap();
+		let dst = tempfile::tempdir().unwrap();
+
+		let contents: [&[u8]; 3] = [b"first", &[0xAB; 100 * 1024], b""];
+		let entries = [
+			entry("Photos/holiday/beach.jpg", contents[0].len(), &["Holiday"]),
+			entry("Photos/scan.pdf", contents[1].len(), &["Holiday", "Work"]),
+			entry("Documents/empty.txt", contents[2].len(), &[]),
+		];
+
+		let mut files = Vec::new();
+		for (entry, contents) in entries.iter().zip(contents) {
+			let source = src.path().join(entry.path.replace('/', "_"));
+			fs::write(&source, contents).await.unwrap();
+			files.push((entry.clone(), source));
+		}
+
+		let mut bundle = Cursor::new(Vec::new());
+		let exported = write_bundle(files, &mut bundle, password()).await.unwrap();
+
+		bundle.set_position(0);
+		let (manifest, mut reader) = open_bundle(bundle, password()).await.unwrap();
+		assert_eq!(manifest, exported);
+		assert_eq!(manifest.entries, entries);
+
+		unpack(&manifest, &mut reader, dst.path()).await.unwrap();
+
+		for (entry, contents) in entries.iter().zip(contents) {
+			assert_eq!(
+				fs::read(dst.path().join(&entry.path)).await.unwrap(),
+				contents
+			);
+		}
+	}
+
+	#[tokio::test]
+	async fn wrong_password() {
+		let src = tempfile::tempdir().unwrap();
+		let source = src.path().join("notes.txt");
+		fs::write(&source, b"secret").await.unwrap();
+
+		let mut bundle = Cursor::new(Vec::new());
+		write_bundle(
+			vec![(entry("notes.txt", 6, &[]), source)],
+			&mut bundle,
+			password(),
+		)
+		.await
+		.unwrap();
+
+		bundle.set_position(0);
+		assert!(matches!(
+			open_bundle(bundle, Protected::new(b"wrong".to_vec())).await,
+			Err(BundleError::CryptoError(_))
+		));
+	}
+
+	#[tokio::test]
+	async fn shrunk_files_fail_the_export() {
+		let src = tempfile::tempdir().unwrap();
+		let (first, second) = (src.path().join("first.txt"), src.path().join("second.txt"));
+		fs::write(&first, b"short").await.unwrap();
+		fs::write(&second, b"second").await.unwrap();
+
+		// the first file was longer when its size was recorded
+		let result = write_bundle(
+			vec![
+				(entry("first.txt", 10, &[]), first),
+				(entry("second.txt", 6, &[]), second),
+			],
+			&mut Cursor::new(Vec::new()),
+			password(),
+		)
+		.await;
+
+		assert!(matches!(
+			result,
+			Err(BundleError::CryptoError(sd_crypto::Error::Io(e))) if e.kind() == io::ErrorKind::UnexpectedEof
+		));
+	}
+
+	#[tokio::test]
+	async fn import_subpath_cant_escape() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+
+		for subpath in ["../outside", "/etc"] {
+			let result =
+				import_bundle(&library, "missing.sdbundle", 1, subpath, password(), false).await;
+			assert!(matches!(
+				result,
+				Err(BundleError::LocationError(LocationError::InvalidSubpath(path))) if path == Path::new(subpath)
+			));
+		}
+	}
+
+	#[test]
+	fn entries_cant_escape() {
+		let dst = Path::new("/imports");
+
+		for path in [
+			"",
+			"../outside.txt",
+			"/etc/passwd",
+			"photos/../../outside.txt",
+		] {
+			assert!(matches!(
+				entry_path(dst, &entry(path, 0, &[])),
+				Err(BundleError::InvalidPath(_))
+			));
+		}
+
+		assert_eq!(
+			entry_path(dst, &entry("photos/beach.jpg", 0, &[])).unwrap(),
+			dst.join("photos/beach.jpg")
+		);
+	}
+
+	#[test]
+	fn new_directories_are_created() {
+		let dst = tempfile::tempdir().unwrap();
+		std::fs::create_dir(dst.path().join("Photos")).unwrap();
+
+		let manifest = BundleManifest {
+			entries: vec![
+				entry("Photos/holiday/beach.jpg", 0, &[]),
+				entry("Photos/holiday/pier.jpg", 0, &[]),
+				entry("Photos/scan.pdf", 0, &[]),
+			],
+		};
+
+		assert_eq!(
+			paths_to_create(&manifest, dst.path()).unwrap(),
+			vec![
+				dst.path().join("Photos/holiday"),
+				dst.path().join("Photos/holiday/beach.jpg"),
+				dst.path().join("Photos/holiday/pier.jpg"),
+				dst.path().join("Photos/scan.pdf"),
+			]
+		);
+	}
+}
@@ -1,5 +1,8 @@
 use blake3::Hasher;
-use std::path::Path;
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+};
 use tokio::{
 	fs::File,
 	io::{self, AsyncReadExt, AsyncSeekExt, SeekFrom},
@@ -7,6 +10,7 @@ use tokio::{
 
 static SAMPLE_COUNT: u64 = 4;
 static SAMPLE_SIZE: u64 = 10000;
+static FINGERPRINT_SAMPLE_SIZE: u64 = 64 * 1024;
 
 async fn read_at(file: &mut File, offset: u64, size: u64) -> Result<Vec<u8>, io::Error> {
 	let mut buf = vec![0u8; size as usize];
@@ -47,3 +51,150 @@ pub async fn generate_cas_id(path: impl AsRef<Path>, size: u64) -> Result<String
 	id.truncate(16);
 	Ok(id)
 }
+
+/// Computes a cheap fingerprint from the file size and its first and last 64KiB.
+///
+/// This is NOT a security hash and it is not unique: two files of the same size which only differ
+/// in their middle bytes will share a fingerprint. It's only meant to be used as a pre-filter, to
+/// find candidate duplicates that then need a full checksum to be confirmed.
+pub async fn quick_fingerprint(path: impl AsRef<Path>) -> Result<u64, io::Error> {
+	let mut file = File::open(path).await?;
+	let size = file.metadata().await?.len();
+
+	let mut hasher = Hasher::new();
+	hasher.update(&size.to_le_bytes());
+
+	if size <= FINGERPRINT_SAMPLE_SIZE * 2 {
+		hasher.update(&read_at(&mut file, 0, size).await?);
+	} else {
+		hasher.update(&read_at(&mut file, 0, FINGERPRINT_SAMPLE_SIZE).await?);
+		hasher.update(
+			&read_at(
+				&mut file,
+				size - FINGERPRINT_SAMPLE_SIZE,
+				FINGERPRINT_SAMPLE_SIZE,
+			)
+			.await?,
+		);
+	}
+
+	let mut fingerprint = [0u8; 8];
+	fingerprint.copy_from_slice(&hasher.finalize().as_bytes()[..8]);
+
+	Ok(u64::from_le_bytes(fingerprint))
+}
+
+/// Files that may be duplicates of each other, as found by [`duplicate_candidates`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+	pub paths: Vec<PathBuf>,
+}
+
+/// Groups files that may be duplicates of each other, given their paths and sizes.
+///
+/// Files with a unique size are discarded without being read, and the remaining ones are grouped by
+/// their [`quick_fingerprint`]. Only groups with more than one file are returned, and their contents
+/// still have to be fully hashed to confirm that they really are duplicates.
+pub async fn duplicate_candidates(
+	files: impl IntoIterator<Item = (PathBuf, u64)>,
+) -> Result<Vec<DuplicateGroup>, io::Error> {
+	let mut by_size = HashMap::<_, Vec<_>>::new();
+	for (path, size) in files {
+		by_size.entry(size).or_default().push(path);
+	}
+
+	let mut candidates = Vec::new();
+	for paths in by_size.into_values().filter(|paths| paths.len() > 1) {
+		let mut by_fingerprint = HashMap::<_, Vec<_>>::new();
+		for path in paths {
+			by_fingerprint
+				.entry(quick_fingerprint(&path).await?)
+				.or_default()
+				.push(path);
+		}
+
+		candidates.extend(
+			by_fingerprint
+				.into_values()
+				.filter(|paths| paths.len() > 1)
+				.map(|paths| DuplicateGroup { paths }),
+		);
+	}
+
+	Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tempfile::tempdir;
+	use tokio::fs;
+
+	#[tokio::test]
+	async fn identical_files_share_fingerprint() {
+		let dir = tempdir().unwrap();
+		let contents = vec![0x5Au8; 300 * 1024];
+
+		fs::write(dir.path().join("a"), &contents).await.unwrap();
+		fs::write(dir.path().join("b"), &contents).await.unwrap();
+
+		assert_eq!(
+			quick_fingerprint(dir.path().join("a")).await.unwrap(),
+			quick_fingerprint(dir.path().join("b")).await.unwrap()
+		);
+	}
+
+	#[tokio::test]
+	async fn differing_endpoints_change_fingerprint() {
+		let dir = tempdir().unwrap();
+		let contents = vec![0x5Au8; 300 * 1024];
+		let mut other = contents.clone();
+		*other.last_mut().unwrap() = 0x00;
+
+		fs::write(dir.path().join("a"), &contents).await.unwrap();
+		fs::write(dir.path().join("b"), &other).await.unwrap();
+
+		assert_ne!(
+			quick_fingerprint(dir.path().join("a")).await.unwrap(),
+			quick_fingerprint(dir.path().join("b")).await.unwrap()
+		);
+	}
+
+	#[tokio::test]
+	async fn differing_middle_shares_fingerprint() {
+		// Only the endpoints are sampled, so this is a known false positive which
+		// has to be caught by the full checksum afterwards
+		let dir = tempdir().unwrap();
+		let contents = vec![0x5Au8; 300 * 1024];
+		let mut other = contents.clone();
+		other[150 * 1024] = 0x00;
+
+		fs::write(dir.path().join("a"), &contents).await.unwrap();
+		fs::write(dir.path().join("b"), &other).await.unwrap();
+
+		assert_eq!(
+			quick_fingerprint(dir.path().join("a")).await.unwrap(),
+			quick_fingerprint(dir.path().join("b")).await.unwrap()
+		);
+	}
+
+	#[tokio::test]
+	async fn unique_sizes_are_not_candidates() {
+		let dir = tempdir().unwrap();
+
+		fs::write(dir.path().join("a"), b"same").await.unwrap();
+		fs::write(dir.path().join("b"), b"same").await.unwrap();
+		fs::write(dir.path().join("c"), b"different").await.unwrap();
+
+		let candidates = duplicate_candidates(vec![
+			(dir.path().join("a"), 4),
+			(dir.path().join("b"), 4),
+			(dir.path().join("c"), 9),
+		])
+		.await
+		.unwrap();
+
+		assert_eq!(candidates.len(), 1);
+		assert_eq!(candidates[0].paths.len(), 2);
+	}
+}
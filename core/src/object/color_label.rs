@@ -0,0 +1,164 @@
+use crate::prisma::{object, PrismaClient};
+
+use int_enum::IntEnum;
+use prisma_client_rust::{Direction, QueryError};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+
+/// A Finder-style color label, stored in `object.color_label`.
+///
+/// Unlike tags, labels have no name and an object has at most one, so they're a single column rather than a join.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum)]
+pub enum ColorLabel {
+	Red = 0,
+	Orange = 1,
+	Yellow = 2,
+	Green = 3,
+	Blue = 4,
+	Purple = 5,
+	Gray = 6,
+}
+
+/// Sets (or clears) an object's color label.
+pub async fn set_color_label(
+	db: &PrismaClient,
+	id: i32,
+	label: Option<ColorLabel>,
+) -> Result<object::Data, QueryError> {
+	db.object()
+		.update(
+			object::id::equals(id),
+			vec![object::color_label::set(label.map(IntEnum::int_value))],
+		)
+		.exec()
+		.await
+}
+
+/// Lists the objects with a color label, most recently modified first.
+pub async fn list_by_color(
+	db: &PrismaClient,
+	label: ColorLabel,
+) -> Result<Vec<object::Data>, QueryError> {
+	db.object()
+		.find_many(vec![
+			object::color_label::equals(Some(label.int_value())),
+			object::pending_review::equals(false),
+			object::date_deleted::equals(None),
+		])
+		.order_by(object::date_modified::order(Direction::Desc))
+		.exec()
+		.await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		library::{create_test_object, test_library},
+		object::tag::assign_tag,
+		prisma::tag,
+	};
+
+	use uuid::Uuid;
+
+	fn ids(objects: Vec<object::Data>) -> Vec<i32> {
+		objects.into_iter().map(|object| object.id).collect()
+	}
+
+	#[test]
+	fn labels_are_stored_as_stable_integers() {
+		// these are persisted in the database, so they must never be renumbered
+		assert_eq!(ColorLabel::Red.int_value(), 0);
+		assert_eq!(ColorLabel::Gray.int_value(), 6);
+		assert_eq!(ColorLabel::from_int(4).unwrap(), ColorLabel::Blue);
+		assert!(ColorLabel::from_int(7).is_err());
+	}
+
+	#[tokio::test]
+	async fn labels_are_set_cleared_and_listed_by_color() {
+		let (_data_dir, _node, library) = test_library().await;
+		let db = &library.db;
+
+		let (red, blue, unlabeled) = (
+			create_test_object(db, vec![]).await,
+			create_test_object(db, vec![]).await,
+			create_test_object(db, vec![]).await,
+		);
+		set_color_label(db, red, Some(ColorLabel::Red))
+			.await
+			.unwrap();
+		let labeled = set_color_label(db, blue, Some(ColorLabel::Blue))
+			.await
+			.unwrap();
+		assert_eq!(labeled.color_label, Some(ColorLabel::Blue.int_value()));
+
+		assert_eq!(
+			ids(list_by_color(db, ColorLabel::Red).await.unwrap()),
+			[red]
+		);
+		assert_eq!(
+			ids(list_by_color(db, ColorLabel::Blue).await.unwrap()),
+			[blue]
+		);
+		assert!(list_by_color(db, ColorLabel::Gray)
+			.await
+			.unwrap()
+			.is_empty());
+
+		// relabeling replaces the label, as an object has at most one
+		set_color_label(db, red, Some(ColorLabel::Blue))
+			.await
+			.unwrap();
+		assert!(list_by_color(db, ColorLabel::Red).await.unwrap().is_empty());
+		assert_eq!(list_by_color(db, ColorLabel::Blue).await.unwrap().len(), 2);
+
+		let cleared = set_color_label(db, red, None).await.unwrap();
+		assert_eq!(cleared.color_label, None);
+		assert_eq!(
+			ids(list_by_color(db, ColorLabel::Blue).await.unwrap()),
+			[blue]
+		);
+
+		let untouched = db
+			.object()
+			.find_unique(object::id::equals(unlabeled))
+			.exec()
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(untouched.color_label, None);
+	}
+
+	#[tokio::test]
+	async fn labels_are_independent_of_tags() {
+		let (_data_dir, _node, library) = test_library().await;
+		let db = &library.db;
+
+		let id = create_test_object(db, vec![]).await;
+		let tag_id = db
+			.tag()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				vec![tag::name::set(Some("Red".to_string()))],
+			)
+			.exec()
+			.await
+			.unwrap()
+			.id;
+		assert!(assign_tag(db, id, tag_id).await.unwrap());
+
+		// a tag named after a color isn't a label
+		assert!(list_by_color(db, ColorLabel::Red).await.unwrap().is_empty());
+
+		set_color_label(db, id, Some(ColorLabel::Red))
+			.await
+			.unwrap();
+		set_color_label(db, id, None).await.unwrap();
+
+		// and clearing the label leaves the tag alone
+		assert_eq!(db.tag_on_object().count(vec![]).exec().await.unwrap(), 1);
+		assert_eq!(db.tag().count(vec![]).exec().await.unwrap(), 1);
+	}
+}
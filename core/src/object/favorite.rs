@@ -0,0 +1,136 @@
+use crate::prisma::{object, PrismaClient};
+
+use chrono::Utc;
+use prisma_client_rust::{Direction, QueryError};
+
+/// Marks (or unmarks) an object as a favorite.
+///
+/// The time it was favorited is recorded, so the favorites list can show the most recent ones first.
+pub async fn set_favorite(
+	db: &PrismaClient,
+	id: i32,
+	favorite: bool,
+) -> Result<object::Data, QueryError> {
+	db.object()
+		.update(
+			object::id::equals(id),
+			vec![
+				object::favorite::set(favorite),
+				object::date_favorited::set(favorite.then(|| Utc::now().into())),
+			],
+		)
+		.exec()
+		.await
+}
+
+/// Lists the favorite objects across all locations, most recently favorited first.
+pub async fn list_favorites(
+	db: &PrismaClient,
+	limit: i32,
+	offset: i32,
+) -> Result<Vec<object::Data>, QueryError> {
+	db.object()
+		.find_many(vec![
+			object::favorite::equals(true),
+			object::pending_review::equals(false),
+			object::date_deleted::equals(None),
+		])
+		.order_by(object::date_favorited::order(Direction::Desc))
+		.skip(offset.into())
+		.take(limit.into())
+		.exec()
+		.await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::library::{create_test_object, test_library};
+
+	use std::time::Duration;
+
+	use tokio::time::sleep;
+
+	fn ids(objects: Vec<object::Data>) -> Vec<i32> {
+		objects.into_iter().map(|object| object.id).collect()
+	}
+
+	#[tokio::test]
+	async fn favorites_are_listed_most_recently_favorited_first() {
+		let (_data_dir, _node, library) = test_library().await;
+		let db = &library.db;
+
+		let (first, second, third) = (
+			create_test_object(db, vec![]).await,
+			create_test_object(db, vec![]).await,
+			create_test_object(db, vec![]).await,
+		);
+		for id in [second, first, third] {
+			let favorited = set_favorite(db, id, true).await.unwrap();
+			assert!(favorited.favorite);
+			assert!(favorited.date_favorited.is_some());
+
+			// the database only keeps milliseconds
+			sleep(Duration::from_millis(5)).await;
+		}
+
+		assert_eq!(
+			ids(list_favorites(db, 10, 0).await.unwrap()),
+			[third, first, second]
+		);
+		assert_eq!(ids(list_favorites(db, 1, 1).await.unwrap()), [first]);
+
+		let unfavorited = set_favorite(db, first, false).await.unwrap();
+		assert!(!unfavorited.favorite);
+		assert_eq!(unfavorited.date_favorited, None);
+		assert_eq!(
+			ids(list_favorites(db, 10, 0).await.unwrap()),
+			[third, second]
+		);
+
+		// favoriting it again moves it to the top
+		set_favorite(db, first, true).await.unwrap();
+		assert_eq!(
+			ids(list_favorites(db, 10, 0).await.unwrap()),
+			[first, third, second]
+		);
+	}
+
+	#[tokio::test]
+	async fn soft_deleted_favorites_are_hidden_until_restored() {
+		let (_data_dir, _node, library) = test_library().await;
+		let db = &library.db;
+
+		let (kept, deleted) = (
+			create_test_object(db, vec![]).await,
+			create_test_object(db, vec![]).await,
+		);
+		set_favorite(db, kept, true).await.unwrap();
+		sleep(Duration::from_millis(5)).await;
+		set_favorite(db, deleted, true).await.unwrap();
+
+		let set_date_deleted = |date_deleted: Option<chrono::DateTime<Utc>>| async move {
+			db.object()
+				.update(
+					object::id::equals(deleted),
+					vec![object::date_deleted::set(date_deleted.map(Into::into))],
+				)
+				.exec()
+				.await
+				.unwrap()
+		};
+
+		let soft_deleted = set_date_deleted(Some(Utc::now())).await;
+		assert!(soft_deleted.favorite);
+		assert_eq!(ids(list_favorites(db, 10, 0).await.unwrap()), [kept]);
+
+		// restoring it brings it back where it was, as it's still a favorite
+		let restored = set_date_deleted(None).await;
+		assert!(restored.favorite);
+		assert_eq!(
+			ids(list_favorites(db, 10, 0).await.unwrap()),
+			[deleted, kept]
+		);
+	}
+}
@@ -1,5 +1,7 @@
 use sd_crypto::{
-	crypto::stream::StreamDecryption, header::file::FileHeader, primitives::types::Password,
+	crypto::stream::{Framing, StreamDecryption},
+	header::file::FileHeader,
+	primitives::types::Password,
 	Protected,
 };
 use serde::{Deserialize, Serialize};
@@ -7,7 +9,9 @@ use specta::Type;
 use std::{collections::VecDeque, path::PathBuf};
 use tokio::fs::File;
 
-use crate::job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext};
+use crate::job::{
+	JobCategory, JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+};
 
 use super::{context_menu_fs_info, FsInfo, BYTES_EXT};
 pub struct FileDecryptorJob;
@@ -38,6 +42,9 @@ impl StatefulJob for FileDecryptorJob {
 	type Init = FileDecryptorJobInit;
 	type Step = FileDecryptorJobStep;
 
+	const CATEGORY: JobCategory = JobCategory::Encryption;
+	const WRITES_TO_DB: bool = false;
+
 	fn name(&self) -> &'static str {
 		JOB_NAME
 	}
@@ -137,9 +144,18 @@ impl StatefulJob for FileDecryptorJob {
 
 		let decryptor = StreamDecryption::new(master_key, header.nonce, header.algorithm)?;
 
-		decryptor
-			.decrypt_streams(&mut reader, &mut writer, &aad)
-			.await?;
+		match header.framing {
+			Framing::Fixed => {
+				decryptor
+					.decrypt_streams(&mut reader, &mut writer, &aad)
+					.await?
+			}
+			Framing::LengthPrefixed => {
+				decryptor
+					.decrypt_streams_framed(&mut reader, &mut writer, &aad)
+					.await?
+			}
+		}
 
 		// need to decrypt preview media/metadata, and maybe add an option in the UI so the user can chosoe to restore these values
 		// for now this can't easily be implemented, as we don't know what the new object id for the file will be (we know the old one, but it may differ)
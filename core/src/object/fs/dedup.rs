@@ -0,0 +1,326 @@
+use crate::{
+	library::LibraryContext,
+	object::{cas::DuplicateGroup, fs::hardlink::FileId, validation::hash::file_checksum},
+	prisma::{file_path, location},
+};
+
+use std::{
+	io,
+	path::{Path, PathBuf},
+};
+
+use prisma_client_rust::QueryError;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::info;
+
+use super::error::VirtualFSError;
+
+/// How duplicates are replaced, so the space they take up is reclaimed while their paths stay where they were.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupStrategy {
+	/// Replace every duplicate with a hardlink to the canonical copy (unix only).
+	Hardlink,
+	/// Replace every duplicate with an alias (a symbolic link) to the canonical copy, which unlike a hardlink can
+	/// point to another file system.
+	Alias,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Default, PartialEq, Eq)]
+pub struct DedupReport {
+	/// The paths that now point at the canonical copy.
+	pub linked: Vec<PathBuf>,
+	pub bytes_reclaimed: u64,
+}
+
+/// Replaces the duplicates in a group with links to its first file, which is kept as the canonical copy.
+///
+/// A group only holds candidates, so every file is fully hashed and compared to the canonical copy before anything
+/// is touched, and nothing is linked if any of them differ. Files which already point at the canonical copy are left
+/// as they are.
+///
+/// The linked paths now share the canonical copy's device and inode, which is recorded on their `file_path`s.
+pub async fn dedup_link(
+	library_ctx: &LibraryContext,
+	group: &DuplicateGroup,
+	strategy: DedupStrategy,
+) -> Result<DedupReport, VirtualFSError> {
+	let Some((canonical, duplicates)) = group.paths.split_first() else {
+		return Ok(DedupReport::default());
+	};
+
+	if strategy == DedupStrategy::Hardlink && !cfg!(unix) {
+		return Err(VirtualFSError::DedupUnsupported(strategy));
+	}
+
+	let canonical_metadata = fs::metadata(canonical).await?;
+	let canonical_id = FileId::from_metadata(&canonical_metadata);
+	let canonical_checksum = file_checksum(canonical).await?;
+
+	let mut to_link = Vec::with_capacity(duplicates.len());
+	for duplicate in duplicates {
+		let metadata = fs::metadata(duplicate).await?;
+		if canonical_id.is_some() && FileId::from_metadata(&metadata) == canonical_id {
+			continue;
+		}
+
+		if metadata.len() != canonical_metadata.len()
+			|| file_checksum(duplicate).await? != canonical_checksum
+		{
+			return Err(VirtualFSError::NotIdentical(duplicate.clone()));
+		}
+
+		to_link.push((duplicate, metadata.len()));
+	}
+
+	let mut report = DedupReport::default();
+	for (duplicate, size) in to_link {
+		replace_with_link(canonical, duplicate, strategy).await?;
+
+		report.linked.push(duplicate.clone());
+		report.bytes_reclaimed += size;
+	}
+
+	if let Some(canonical_id) = canonical_id {
+		record_file_id(library_ctx, &report.linked, canonical_id).await?;
+	}
+
+	info!(
+		"Linked {} duplicates of {}, reclaiming {} bytes",
+		report.linked.len(),
+		canonical.display(),
+		report.bytes_reclaimed
+	);
+
+	Ok(report)
+}
+
+/// Links the canonical copy next to the duplicate first, so the duplicate is atomically replaced by renaming over it.
+async fn replace_with_link(
+	canonical: &Path,
+	duplicate: &Path,
+	strategy: DedupStrategy,
+) -> Result<(), VirtualFSError> {
+	let mut link_name = duplicate.file_name().unwrap_or_default().to_os_string();
+	link_name.push(".sd-dedup");
+	let link = duplicate.with_file_name(link_name);
+
+	match strategy {
+		DedupStrategy::Hardlink => fs::hard_link(canonical, &link).await?,
+		// the alias is resolved from wherever it is, so it must point at an absolute path
+		DedupStrategy::Alias => symlink(&fs::canonicalize(canonical).await?, &link).await?,
+	}
+
+	if let Err(e) = fs::rename(&link, duplicate).await {
+		fs::remove_file(&link).await.ok();
+		return Err(e.into());
+	}
+
+	Ok(())
+}
+
+#[cfg(unix)]
+async fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+	fs::symlink(original, link).await
+}
+
+#[cfg(windows)]
+async fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+	fs::symlink_file(original, link).await
+}
+
+/// Records the device and inode of the file the linked paths now resolve to on their `file_path`s, in whichever of the
+/// library's locations they are, so they're known to be the same file without being re-identified.
+async fn record_file_id(
+	library_ctx: &LibraryContext,
+	paths: &[PathBuf],
+	file_id: FileId,
+) -> Result<(), QueryError> {
+	let LibraryContext { db, .. } = library_ctx;
+
+	let locations = db
+		.location()
+		.find_many(vec![location::node_id::equals(library_ctx.node_local_id)])
+		.select(location::select!({ id path }))
+		.exec()
+		.await?;
+
+	let (device, inode) = file_id.to_db();
+	let updates = paths
+		.iter()
+		.flat_map(|path| {
+			locations.iter().filter_map(|location| {
+				let materialized_path = path.strip_prefix(&location.path).ok()?.to_str()?;
+
+				// The inode only means something on this node, so it isn't synced
+				Some(db.file_path().update_many(
+					vec![
+						file_path::location_id::equals(location.id),
+						file_path::materialized_path::equals(materialized_path.to_string()),
+					],
+					vec![
+						file_path::device::set(Some(device.clone())),
+						file_path::inode::set(Some(inode.clone())),
+					],
+				))
+			})
+		})
+		.collect::<Vec<_>>();
+
+	if !updates.is_empty() {
+		db._batch(updates).await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+
+	use crate::library::{create_test_location, test_library};
+
+	use std::os::unix::fs::MetadataExt;
+
+	/// Indexes `dir` as a location holding a single file.
+	async fn create_location(library: &LibraryContext, dir: &Path, file_name: &str) {
+		let location_id = create_test_location(library, dir).await;
+
+		let (name, extension) = file_name.rsplit_once('.').unwrap();
+		library
+			.db
+			.file_path()
+			.create(
+				1,
+				location::id::equals(location_id),
+				file_name.to_string(),
+				name.to_string(),
+				extension.to_string(),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn duplicates_become_hardlinks() {
+		let (_data_dir, _node, library) = test_library().await;
+
+		// hardlinks can't cross file systems, so both locations are in the same directory
+		let dir = tempfile::tempdir().unwrap();
+		let (location_a, location_b) = (dir.path().join("a"), dir.path().join("b"));
+		fs::create_dir(&location_a).await.unwrap();
+		fs::create_dir(&location_b).await.unwrap();
+		create_location(&library, &location_a, "photo.jpg").await;
+		create_location(&library, &location_b, "photo (copy).jpg").await;
+
+		let contents = vec![0x5Au8; 300 * 1024];
+		let (original, duplicate) = (
+			location_a.join("photo.jpg"),
+			location_b.join("photo (copy).jpg"),
+		);
+		fs::write(&original, &contents).await.unwrap();
+		fs::write(&duplicate, &contents).await.unwrap();
+
+		let report = dedup_link(
+			&library,
+			&DuplicateGroup {
+				paths: vec![original.clone(), duplicate.clone()],
+			},
+			DedupStrategy::Hardlink,
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(report.linked, vec![duplicate.clone()]);
+		assert_eq!(report.bytes_reclaimed, contents.len() as u64);
+
+		let (original_metadata, duplicate_metadata) = (
+			fs::metadata(&original).await.unwrap(),
+			fs::metadata(&duplicate).await.unwrap(),
+		);
+		assert_eq!(original_metadata.ino(), duplicate_metadata.ino());
+		assert_eq!(original_metadata.nlink(), 2);
+
+		assert_eq!(fs::read(&original).await.unwrap(), contents);
+		assert_eq!(fs::read(&duplicate).await.unwrap(), contents);
+
+		// the duplicate's file_path now has the canonical copy's inode
+		let linked = library
+			.db
+			.file_path()
+			.find_first(vec![file_path::name::equals("photo (copy)".to_string())])
+			.exec()
+			.await
+			.unwrap()
+			.unwrap();
+		let (device, inode) = FileId::from_metadata(&original_metadata).unwrap().to_db();
+		assert_eq!(linked.device, Some(device));
+		assert_eq!(linked.inode, Some(inode));
+	}
+
+	#[tokio::test]
+	async fn duplicates_become_aliases() {
+		let (_data_dir, _node, library) = test_library().await;
+
+		let dir = tempfile::tempdir().unwrap();
+		let contents = vec![0x5Au8; 300 * 1024];
+		let (original, duplicate) = (dir.path().join("a.bin"), dir.path().join("b.bin"));
+		fs::write(&original, &contents).await.unwrap();
+		fs::write(&duplicate, &contents).await.unwrap();
+
+		let report = dedup_link(
+			&library,
+			&DuplicateGroup {
+				paths: vec![original.clone(), duplicate.clone()],
+			},
+			DedupStrategy::Alias,
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(report.linked, vec![duplicate.clone()]);
+		assert!(fs::symlink_metadata(&duplicate)
+			.await
+			.unwrap()
+			.file_type()
+			.is_symlink());
+		assert_eq!(
+			fs::read_link(&duplicate).await.unwrap(),
+			fs::canonicalize(&original).await.unwrap()
+		);
+		assert_eq!(fs::read(&duplicate).await.unwrap(), contents);
+		// the canonical copy is still the one real file
+		assert_eq!(fs::metadata(&original).await.unwrap().nlink(), 1);
+	}
+
+	#[tokio::test]
+	async fn differing_files_are_not_linked() {
+		let (_data_dir, _node, library) = test_library().await;
+
+		let dir = tempfile::tempdir().unwrap();
+		let contents = vec![0x5Au8; 300 * 1024];
+		let mut other = contents.clone();
+		// the quick fingerprint can't tell these apart
+		other[150 * 1024] = 0x00;
+
+		let (original, duplicate) = (dir.path().join("a"), dir.path().join("b"));
+		fs::write(&original, &contents).await.unwrap();
+		fs::write(&duplicate, &other).await.unwrap();
+
+		let result = dedup_link(
+			&library,
+			&DuplicateGroup {
+				paths: vec![original.clone(), duplicate.clone()],
+			},
+			DedupStrategy::Hardlink,
+		)
+		.await;
+
+		assert!(matches!(result, Err(VirtualFSError::NotIdentical(path)) if path == duplicate));
+		assert_eq!(fs::read(&duplicate).await.unwrap(), other);
+		assert_eq!(fs::metadata(&original).await.unwrap().nlink(), 1);
+	}
+}
@@ -4,7 +4,10 @@ use std::path::PathBuf;
 
 use chrono::FixedOffset;
 use sd_crypto::{
-	crypto::stream::{Algorithm, StreamEncryption},
+	crypto::{
+		progress::Progress,
+		stream::{Algorithm, StreamEncryption},
+	},
 	header::{file::FileHeader, keyslot::Keyslot},
 	primitives::{
 		types::Key, LATEST_FILE_HEADER, LATEST_KEYSLOT, LATEST_METADATA, LATEST_PREVIEW_MEDIA,
@@ -30,6 +33,8 @@ pub struct FileEncryptorJobInit {
 	pub algorithm: Algorithm,
 	pub metadata: bool,
 	pub preview_media: bool,
+	/// Commits to the plaintext with the library's dedup key, so the file can be compared with other encrypted files
+	pub plaintext_commitment: bool,
 	pub output_path: Option<PathBuf>,
 }
 
@@ -53,6 +58,9 @@ impl StatefulJob for FileEncryptorJob {
 	type Data = FileEncryptorJobState;
 	type Step = FsInfo;
 
+	const CATEGORY: JobCategory = JobCategory::Encryption;
+	const WRITES_TO_DB: bool = false;
+
 	fn name(&self) -> &'static str {
 		JOB_NAME
 	}
@@ -150,6 +158,8 @@ impl StatefulJob for FileEncryptorJob {
 				],
 			)?;
 
+			header.add_key_commitment(&master_key)?;
+
 			if state.init.metadata || state.init.preview_media {
 				// if any are requested, we can make the query as it'll be used at least once
 				if let Some(object) = info.path_data.object.clone() {
@@ -208,13 +218,43 @@ impl StatefulJob for FileEncryptorJob {
 				}
 			}
 
+			if state.init.plaintext_commitment {
+				header
+					.add_plaintext_commitment(&key_manager.get_dedup_key().await?, &mut reader)
+					.await?;
+			}
+
 			header.write(&mut writer).await?;
 
 			let encryptor = StreamEncryption::new(master_key, header.nonce, header.algorithm)?;
 
+			let len = reader.metadata().await?.len();
+			let name = &info.path_data.materialized_path;
+			let mut reported_percent = None;
+
 			encryptor
-				.encrypt_streams(&mut reader, &mut writer, &header.generate_aad())
+				.encrypt_streams(
+					Progress::new(&mut reader, |read| {
+						// only whole percentages are reported, so large files don't flood the job report
+						let percent = (read * 100).checked_div(len).unwrap_or(100).min(100);
+						if reported_percent != Some(percent) {
+							reported_percent = Some(percent);
+							ctx.progress_debounced(vec![JobReportUpdate::Message(format!(
+								"Encrypting {name}: {percent}%"
+							))]);
+						}
+					}),
+					&mut writer,
+					&header.generate_aad(),
+				)
 				.await?;
+
+			// nothing is read from empty files, so they'd never reach 100% otherwise
+			ctx.progress(vec![JobReportUpdate::Message(format!(
+				"Encrypting {name}: 100%"
+			))]);
+
+			ctx.library_ctx.metrics().bytes_encrypted(len);
 		} else {
 			warn!(
 				"encryption is skipping {} as it isn't a file",
@@ -1,14 +1,45 @@
+use rspc::ErrorCode;
 use thiserror::Error;
 
-use crate::location::LocationError;
+use crate::location::{LocationError, LocationManagerError};
+
+use std::path::PathBuf;
+
+use super::dedup::DedupStrategy;
 
 /// Error type for location related errors
 #[derive(Error, Debug)]
 pub enum VirtualFSError {
 	#[error("Location error")]
 	LocationError(#[from] LocationError),
+	#[error("Location manager error (error: {0:?})")]
+	LocationManagerError(#[from] LocationManagerError),
 	#[error("Failed to create file or folder on disk at path (path: {0:?})")]
 	CreateFileOrFolder(#[from] std::io::Error),
 	#[error("Database error (error: {0:?})")]
 	DatabaseError(#[from] prisma_client_rust::QueryError),
+	#[error("Object has no file on disk (id: {0})")]
+	ObjectNotFound(i32),
+	#[error("File isn't identical to the one it would be linked to (path: {0:?})")]
+	NotIdentical(PathBuf),
+	#[error("Deduplicating with {0:?} isn't supported")]
+	DedupUnsupported(DedupStrategy),
+}
+
+impl From<VirtualFSError> for rspc::Error {
+	fn from(err: VirtualFSError) -> Self {
+		match err {
+			VirtualFSError::LocationError(e) => e.into(),
+			VirtualFSError::ObjectNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			VirtualFSError::NotIdentical(_) => {
+				rspc::Error::with_cause(ErrorCode::Conflict, err.to_string(), err)
+			}
+			VirtualFSError::DedupUnsupported(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
 }
@@ -0,0 +1,86 @@
+use std::{collections::HashMap, fs::Metadata, hash::Hash};
+
+/// Identifies the file a path points to, which every hardlink to that file shares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+	pub device: u64,
+	pub inode: u64,
+}
+
+impl FileId {
+	#[cfg(unix)]
+	pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+		use std::os::unix::fs::MetadataExt;
+
+		Some(Self {
+			device: metadata.dev(),
+			inode: metadata.ino(),
+		})
+	}
+
+	/// The standard library only exposes inodes on unix, so hardlinks aren't detected elsewhere.
+	#[cfg(not(unix))]
+	pub fn from_metadata(_metadata: &Metadata) -> Option<Self> {
+		None
+	}
+
+	/// The device and inode as they are stored on a `file_path`.
+	pub fn to_db(self) -> (Vec<u8>, Vec<u8>) {
+		(
+			self.device.to_le_bytes().to_vec(),
+			self.inode.to_le_bytes().to_vec(),
+		)
+	}
+}
+
+/// Maps every path which is a hardlink to a file seen earlier, to the first path seen for that file.
+///
+/// Paths that aren't hardlinks to an earlier one aren't in the map, so they are the canonical ones.
+pub fn hardlinks<K: Copy + Eq + Hash>(
+	paths: impl IntoIterator<Item = (K, Option<FileId>)>,
+) -> HashMap<K, K> {
+	let mut canonical = HashMap::new();
+
+	paths
+		.into_iter()
+		.filter_map(|(key, file_id)| {
+			let first = *canonical.entry(file_id?).or_insert(key);
+			(first != key).then_some((key, first))
+		})
+		.collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+
+	use crate::api::utils::get_size;
+
+	#[tokio::test]
+	async fn hardlinks_are_one_file() {
+		let dir = tempfile::tempdir().unwrap();
+		let (original, link, copy) = (
+			dir.path().join("original"),
+			dir.path().join("link"),
+			dir.path().join("copy"),
+		);
+		std::fs::write(&original, [1; 1024]).unwrap();
+		std::fs::hard_link(&original, &link).unwrap();
+		std::fs::copy(&original, &copy).unwrap();
+
+		let file_id = |path| FileId::from_metadata(&std::fs::metadata(path).unwrap());
+
+		// the link is connected to the object of the path seen first, while the copy gets its own
+		assert_eq!(
+			hardlinks([
+				(1, file_id(&original)),
+				(2, file_id(&link)),
+				(3, file_id(&copy)),
+				(4, None),
+			]),
+			HashMap::from([(2, 1)])
+		);
+
+		assert_eq!(get_size(dir.path()).await.unwrap(), 2048);
+	}
+}
@@ -14,6 +14,8 @@ use super::preview::file_path_with_object;
 pub mod copy;
 pub mod cut;
 
+pub mod dedup;
+
 pub mod decrypt;
 pub mod delete;
 pub mod encrypt;
@@ -22,6 +24,10 @@ pub mod error;
 
 pub mod erase;
 
+pub mod hardlink;
+
+pub mod relocate;
+
 pub const BYTES_EXT: &str = ".bytes";
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
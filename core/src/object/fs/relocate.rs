@@ -0,0 +1,406 @@
+use crate::{
+	library::LibraryContext,
+	location::{file_path_helper::check_subpath, LocationError},
+	prisma::{file_path, location, PrismaClient},
+	sync,
+};
+
+use std::{io, path::Path};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::fs;
+use tracing::trace;
+use uuid::Uuid;
+
+use super::error::VirtualFSError;
+
+file_path::include!(file_path_with_object { object });
+location::select!(location_for_move { id path pub_id });
+
+/// What to do when the destination directory already has a file with the same name.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameConflict {
+	/// Replace the file at the destination.
+	Overwrite,
+	/// Keep both, giving the moved file a name like `photo (1).jpg`.
+	Rename,
+}
+
+/// Moves the file of an object into a directory of another location.
+///
+/// The `file_path` is moved along with it instead of being re-indexed, so the object keeps its identity,
+/// and with it its tags, spaces and favorite status.
+pub async fn move_to_location(
+	library_ctx: &LibraryContext,
+	object_id: i32,
+	dst_location_id: i32,
+	dst_subpath: impl AsRef<Path>,
+	on_conflict: NameConflict,
+) -> Result<file_path::Data, VirtualFSError> {
+	let LibraryContext { db, sync, .. } = library_ctx;
+
+	let dst_subpath = dst_subpath.as_ref();
+	check_subpath(dst_subpath)?;
+
+	let source = db
+		.file_path()
+		.find_first(vec![
+			file_path::object_id::equals(Some(object_id)),
+			file_path::is_dir::equals(false),
+		])
+		.include(file_path_with_object::include())
+		.exec()
+		.await?
+		.ok_or(VirtualFSError::ObjectNotFound(object_id))?;
+	let object = source
+		.object
+		.as_ref()
+		.ok_or(VirtualFSError::ObjectNotFound(object_id))?;
+
+	let src_location = get_location(db, source.location_id).await?;
+	let source_path = Path::new(&src_location.path).join(&source.materialized_path);
+
+	let dst_location = get_location(db, dst_location_id).await?;
+	let dst_dir = Path::new(&dst_location.path).join(dst_subpath);
+	if !dst_dir.is_dir() {
+		return Err(LocationError::DirectoryNotFound(dst_subpath.display().to_string()).into());
+	}
+
+	let name = match on_conflict {
+		NameConflict::Overwrite => source.name.clone(),
+		NameConflict::Rename => available_name(&dst_dir, &source.name, &source.extension),
+	};
+	let file_name = join_extension(&name, &source.extension);
+	let dst_path = dst_dir.join(&file_name);
+
+	let dst_dir_materialized = materialized_dir(dst_subpath);
+	let dst_materialized = if dst_dir_materialized == "/" {
+		file_name
+	} else {
+		format!("{dst_dir_materialized}{file_name}")
+	};
+
+	let _source_guard = library_ctx
+		.location_manager()
+		.temporary_ignore_events_for_path(source.location_id, library_ctx.clone(), &source_path)
+		.await?;
+	let _dst_guard = library_ctx
+		.location_manager()
+		.temporary_ignore_events_for_path(dst_location_id, library_ctx.clone(), &dst_path)
+		.await?;
+
+	move_file(&source_path, &dst_path).await?;
+
+	// When overwriting, the replaced file's path is gone from disk, so it goes from the index too
+	let replaced = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(dst_location_id),
+			file_path::materialized_path::equals(dst_materialized.clone()),
+			file_path::id::not(source.id),
+		])
+		.select(file_path::select!({ id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| file_path.id)
+		.collect::<Vec<_>>();
+	if !replaced.is_empty() {
+		sync.write_ops(
+			db,
+			(
+				replaced
+					.iter()
+					.map(|id| sync.shared_delete(file_path_sync_id(*id, &dst_location)))
+					.collect(),
+				db.file_path().delete_many(vec![
+					file_path::location_id::equals(dst_location_id),
+					file_path::id::in_vec(replaced),
+				]),
+			),
+		)
+		.await?;
+	}
+
+	let parent_id = db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(dst_location_id),
+			file_path::materialized_path::equals(dst_dir_materialized),
+		])
+		.exec()
+		.await?
+		.map(|parent| parent.id);
+
+	trace!(
+		"Moved object {object_id} from {} to {}",
+		source_path.display(),
+		dst_path.display()
+	);
+
+	// A file path is identified by its location, so moving it to another one is synced as a new file path
+	// pointing at the same object
+	let ops = if source.location_id == dst_location_id {
+		[
+			("materialized_path", json!(dst_materialized)),
+			("name", json!(name)),
+			("parent_id", json!(parent_id)),
+		]
+		.into_iter()
+		.map(|(field, value)| {
+			sync.shared_update(file_path_sync_id(source.id, &dst_location), field, value)
+		})
+		.collect::<Vec<_>>()
+	} else {
+		vec![
+			sync.shared_delete(file_path_sync_id(source.id, &src_location)),
+			sync.unique_shared_create(
+				file_path_sync_id(source.id, &dst_location),
+				[
+					("materialized_path", json!(dst_materialized)),
+					("name", json!(name)),
+					("is_dir", json!(false)),
+					("extension", json!(source.extension)),
+					("parent_id", json!(parent_id)),
+					("cas_id", json!(source.cas_id)),
+					("date_created", json!(source.date_created)),
+				],
+			),
+			sync.shared_update(
+				file_path_sync_id(source.id, &dst_location),
+				"object",
+				json!({ "pub_id": Uuid::from_slice(&object.pub_id).unwrap() }),
+			),
+		]
+	};
+
+	Ok(sync
+		.write_ops(
+			db,
+			(
+				ops,
+				db.file_path().update(
+					file_path::location_id_id(source.location_id, source.id),
+					vec![
+						file_path::location::connect(location::id::equals(dst_location_id)),
+						file_path::materialized_path::set(dst_materialized),
+						file_path::name::set(name),
+						file_path::parent_id::set(parent_id),
+					],
+				),
+			),
+		)
+		.await?)
+}
+
+async fn get_location(
+	db: &PrismaClient,
+	location_id: i32,
+) -> Result<location_for_move::Data, VirtualFSError> {
+	Ok(db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location_for_move::select())
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?)
+}
+
+fn file_path_sync_id(id: i32, location: &location_for_move::Data) -> sync::file_path::SyncId {
+	sync::file_path::SyncId {
+		id,
+		location: sync::location::SyncId {
+			pub_id: location.pub_id.clone(),
+		},
+	}
+}
+
+/// The `materialized_path` of a directory inside a location, which always ends with '/'.
+fn materialized_dir(subpath: &Path) -> String {
+	let mut materialized = subpath
+		.to_str()
+		.expect("Found non-UTF-8 path")
+		.trim_matches('/')
+		.to_string();
+	materialized.push('/');
+	materialized
+}
+
+pub(crate) fn join_extension(name: &str, extension: &str) -> String {
+	if extension.is_empty() {
+		name.to_string()
+	} else {
+		format!("{name}.{extension}")
+	}
+}
+
+/// Finds a name which isn't taken in `dir`, numbering it the way file managers do.
+pub(crate) fn available_name(dir: &Path, name: &str, extension: &str) -> String {
+	if !dir.join(join_extension(name, extension)).exists() {
+		return name.to_string();
+	}
+
+	(1..)
+		.map(|n| format!("{name} ({n})"))
+		.find(|candidate| !dir.join(join_extension(candidate, extension)).exists())
+		.expect("ran out of numbers for a file name")
+}
+
+/// Renames the file, replacing whatever is at `to`, and falls back to a copy when the locations are on different
+/// filesystems.
+pub(crate) async fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+	// unlike on unix, a rename on Windows fails when the destination exists
+	if cfg!(windows) && fs::metadata(to).await.is_ok() {
+		fs::remove_file(to).await?;
+	}
+
+	if let Err(e) = fs::rename(from, to).await {
+		trace!(
+			"Failed to rename {} to {}, copying it instead: {e}",
+			from.display(),
+			to.display()
+		);
+		fs::copy(from, to).await?;
+		fs::remove_file(from).await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		library::{create_test_location, create_test_object, test_library},
+		object::tag::assign_tag,
+		prisma::{object, shared_operation, tag, tag_on_object},
+	};
+
+	#[test]
+	fn materialized_dirs() {
+		assert_eq!(materialized_dir(Path::new("")), "/");
+		assert_eq!(materialized_dir(Path::new("photos/2023")), "photos/2023/");
+		assert_eq!(materialized_dir(Path::new("photos/")), "photos/");
+	}
+
+	#[tokio::test]
+	async fn rename_on_conflict() {
+		let src = tempfile::tempdir().unwrap();
+		let dst = tempfile::tempdir().unwrap();
+		std::fs::write(src.path().join("photo.jpg"), b"moved").unwrap();
+		std::fs::write(dst.path().join("photo.jpg"), b"existing").unwrap();
+		std::fs::write(dst.path().join("photo (1).jpg"), b"existing").unwrap();
+
+		let name = available_name(dst.path(), "photo", "jpg");
+		assert_eq!(name, "photo (2)");
+
+		let to = dst.path().join(join_extension(&name, "jpg"));
+		move_file(&src.path().join("photo.jpg"), &to).await.unwrap();
+
+		assert!(!src.path().join("photo.jpg").exists());
+		assert_eq!(std::fs::read(to).unwrap(), b"moved");
+		assert_eq!(
+			std::fs::read(dst.path().join("photo.jpg")).unwrap(),
+			b"existing"
+		);
+	}
+
+	#[test]
+	fn no_conflict_keeps_name() {
+		let dir = tempfile::tempdir().unwrap();
+		assert_eq!(available_name(dir.path(), "notes", ""), "notes");
+	}
+
+	#[tokio::test]
+	async fn tagged_object_survives_a_move() {
+		let (_data_dir, _node, library) = test_library().await;
+		let db = &library.db;
+
+		let dir = tempfile::tempdir().unwrap();
+		let (src, dst) = (dir.path().join("src"), dir.path().join("dst"));
+		std::fs::create_dir(&src).unwrap();
+		std::fs::create_dir(&dst).unwrap();
+		std::fs::write(src.join("photo.jpg"), b"moved").unwrap();
+		let src_location_id = create_test_location(&library, &src).await;
+		let dst_location_id = create_test_location(&library, &dst).await;
+
+		let object_id = create_test_object(db, vec![]).await;
+		db.file_path()
+			.create(
+				1,
+				location::id::equals(src_location_id),
+				"photo.jpg".to_string(),
+				"photo".to_string(),
+				"jpg".to_string(),
+				vec![file_path::object::connect(object::id::equals(object_id))],
+			)
+			.exec()
+			.await
+			.unwrap();
+		let tag_id = db
+			.tag()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				vec![tag::name::set(Some("Holiday".to_string()))],
+			)
+			.exec()
+			.await
+			.unwrap()
+			.id;
+		assert!(assign_tag(db, object_id, tag_id).await.unwrap());
+
+		let moved = move_to_location(
+			&library,
+			object_id,
+			dst_location_id,
+			"",
+			NameConflict::Rename,
+		)
+		.await
+		.unwrap();
+
+		assert!(!src.join("photo.jpg").exists());
+		assert_eq!(std::fs::read(dst.join("photo.jpg")).unwrap(), b"moved");
+		assert_eq!(moved.location_id, dst_location_id);
+		assert_eq!(moved.materialized_path, "photo.jpg");
+		assert_eq!(moved.object_id, Some(object_id));
+
+		// the tag is on the object, which the moved path still points at
+		assert_eq!(
+			db.tag_on_object()
+				.count(vec![
+					tag_on_object::tag_id::equals(tag_id),
+					tag_on_object::object_id::equals(object_id),
+				])
+				.exec()
+				.await
+				.unwrap(),
+			1
+		);
+
+		// the other nodes are told the path left its old location
+		assert_eq!(
+			db.shared_operation()
+				.count(vec![shared_operation::kind::equals("d".to_string())])
+				.exec()
+				.await
+				.unwrap(),
+			1
+		);
+	}
+
+	#[tokio::test]
+	async fn subpaths_must_stay_in_the_location() {
+		let (_data_dir, _node, library) = test_library().await;
+
+		for subpath in ["../escape", "photos/../../escape", "/tmp"] {
+			let result = move_to_location(&library, 1, 1, subpath, NameConflict::Rename).await;
+			assert!(
+				matches!(result, Err(VirtualFSError::LocationError(LocationError::InvalidSubpath(path))) if path == Path::new(subpath))
+			);
+		}
+	}
+}
@@ -1,6 +1,8 @@
 use crate::{
 	invalidate_query,
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{
+		JobCategory, JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
 	library::LibraryContext,
 	prisma::{file_path, location},
 };
@@ -60,6 +62,8 @@ impl StatefulJob for FullFileIdentifierJob {
 	type Data = FullFileIdentifierJobState;
 	type Step = ();
 
+	const CATEGORY: JobCategory = JobCategory::Hashing;
+
 	fn name(&self) -> &'static str {
 		FULL_IDENTIFIER_JOB_NAME
 	}
@@ -189,6 +193,7 @@ fn orphan_path_filters(location_id: i32, file_path_id: Option<i32>) -> Vec<file_
 		file_path::object_id::equals(None),
 		file_path::is_dir::equals(false),
 		file_path::location_id::equals(location_id),
+		file_path::date_deleted::equals(None),
 	];
 	// this is a workaround for the cursor not working properly
 	if let Some(file_path_id) = file_path_id {
@@ -208,6 +213,7 @@ async fn count_orphan_file_paths(
 			file_path::object_id::equals(None),
 			file_path::is_dir::equals(false),
 			file_path::location_id::equals(location_id),
+			file_path::date_deleted::equals(None),
 		])
 		.exec()
 		.await? as usize)
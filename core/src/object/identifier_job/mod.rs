@@ -1,13 +1,21 @@
 use crate::{
 	job::JobError,
 	library::LibraryContext,
-	object::cas::generate_cas_id,
+	object::{
+		cas::generate_cas_id,
+		fs::hardlink::{hardlinks, FileId},
+		similar::perceptual_hash,
+	},
 	prisma::{file_path, location, object, PrismaClient},
 	sync,
 	sync::SyncManager,
 };
 
-use sd_file_ext::{extensions::Extension, kind::ObjectKind};
+use sd_crypto::header::file::is_encrypted_file;
+use sd_file_ext::{
+	kind::{ObjectKind, CLASSIFIER_VERSION},
+	mime,
+};
 use sd_sync::CRDTOperation;
 
 use futures::future::join_all;
@@ -23,6 +31,8 @@ use tracing::{error, info};
 use uuid::Uuid;
 
 pub mod full_identifier_job;
+pub mod perceptual_hash_job;
+pub mod reidentifier_job;
 
 // we break these jobs into chunks of 100 to improve performance
 const CHUNK_SIZE: usize = 100;
@@ -39,7 +49,10 @@ pub enum IdentifierJobError {
 pub struct FileMetadata {
 	pub cas_id: String,
 	pub kind: ObjectKind,
+	pub mime: &'static str,
 	pub fs_metadata: std::fs::Metadata,
+	/// Shared by every hardlink to the same file
+	pub file_id: Option<FileId>,
 }
 
 impl FileMetadata {
@@ -57,24 +70,41 @@ impl FileMetadata {
 			"We can't generate cas_id for directories"
 		);
 
-		// derive Object kind
-		let kind = Extension::resolve_conflicting(&path, false)
-			.await
-			.map(Into::into)
-			.unwrap_or(ObjectKind::Unknown);
+		let kind = identify_kind(&path).await?;
+		let mime = mime::identify_file(&path).await?;
 
 		let cas_id = generate_cas_id(&path, fs_metadata.len()).await?;
 
-		info!("Analyzed file: {:?} {:?} {:?}", path, cas_id, kind);
+		info!("Analyzed file: {:?} {:?} {:?} {}", path, cas_id, kind, mime);
 
 		Ok(FileMetadata {
 			cas_id,
 			kind,
+			mime,
+			file_id: FileId::from_metadata(&fs_metadata),
 			fs_metadata,
 		})
 	}
 }
 
+/// Derives the kind of the file at `path`, from its extension and (where that isn't enough) its contents
+pub async fn identify_kind(path: impl AsRef<Path>) -> Result<ObjectKind, io::Error> {
+	let path = path.as_ref();
+
+	let kind = ObjectKind::from_path(path).await?;
+
+	// our encrypted files may have any extension, so we peek at their magic bytes instead
+	if kind == ObjectKind::Unknown
+		&& matches!(
+			is_encrypted_file(&mut fs::File::open(path).await?).await,
+			Ok(true)
+		) {
+		return Ok(ObjectKind::Encrypted);
+	}
+
+	Ok(kind)
+}
+
 async fn identifier_job_step(
 	LibraryContext { db, sync, .. }: &LibraryContext,
 	location: &location::Data,
@@ -115,7 +145,17 @@ async fn identifier_job_step(
 					),
 					db.file_path().update(
 						file_path::location_id_id(location.id, *id),
-						vec![file_path::cas_id::set(Some(meta.cas_id.clone()))],
+						// The inode only means something on this node, so it isn't synced
+						[file_path::cas_id::set(Some(meta.cas_id.clone()))]
+							.into_iter()
+							.chain(meta.file_id.into_iter().flat_map(|file_id| {
+								let (device, inode) = file_id.to_db();
+								[
+									file_path::device::set(Some(device)),
+									file_path::inode::set(Some(inode)),
+								]
+							}))
+							.collect(),
 					),
 				)
 			})
@@ -185,10 +225,23 @@ async fn identifier_job_step(
 	);
 
 	// extract objects that don't already exist in the database
-	let file_paths_requiring_new_object = file_path_metas
+	let mut file_paths_requiring_new_object = file_path_metas
 		.into_iter()
 		.filter(|(_, (meta, _))| !existing_object_cas_ids.contains(&meta.cas_id))
 		.collect::<Vec<_>>();
+	file_paths_requiring_new_object.sort_by_key(|(id, _)| *id);
+
+	// Hardlinks are the same file, so only the first path indexed for it gets a new object,
+	// and the others are connected to that one
+	let hardlinked_to = hardlinks(
+		file_paths_requiring_new_object
+			.iter()
+			.map(|(id, (meta, _))| (*id, meta.file_id)),
+	);
+	let (file_paths_requiring_new_object, hardlinked_file_paths): (Vec<_>, Vec<_>) =
+		file_paths_requiring_new_object
+			.into_iter()
+			.partition(|(id, _)| !hardlinked_to.contains_key(id));
 
 	let total_created = if !file_paths_requiring_new_object.is_empty() {
 		let new_objects_cas_ids = file_paths_requiring_new_object
@@ -202,11 +255,22 @@ async fn identifier_job_step(
 			new_objects_cas_ids
 		);
 
-		let (object_create_args, file_path_update_args): (Vec<_>, Vec<_>) =
+		let pub_ids = file_paths_requiring_new_object
+			.iter()
+			.map(|(id, _)| (*id, Uuid::new_v4()))
+			.collect::<HashMap<_, _>>();
+
+		let perceptual_hashes = if location.perceptual_hashing {
+			perceptual_hashes(&location.path, &file_paths_requiring_new_object)
+		} else {
+			HashMap::new()
+		};
+
+		let (object_create_args, mut file_path_update_args): (Vec<_>, Vec<_>) =
 			file_paths_requiring_new_object
 				.iter()
 				.map(|(id, (meta, fp))| {
-					let pub_id = Uuid::new_v4();
+					let pub_id = pub_ids[id];
 					let pub_id_vec = pub_id.as_bytes().to_vec();
 
 					let sync_id = || sync::object::SyncId {
@@ -215,6 +279,7 @@ async fn identifier_job_step(
 
 					let size = meta.fs_metadata.len().to_string();
 					let kind = meta.kind.int_value();
+					let perceptual_hash = perceptual_hashes.get(id).cloned();
 
 					let object_creation_args = (
 						[sync.shared_create(sync_id())]
@@ -223,9 +288,15 @@ async fn identifier_job_step(
 								[
 									("date_created", json!(fp.date_created)),
 									("kind", json!(kind)),
+									("mime", json!(meta.mime)),
 									("size_in_bytes", json!(size)),
 								]
 								.into_iter()
+								.chain(
+									perceptual_hash
+										.as_ref()
+										.map(|hash| ("perceptual_hash", json!(hash))),
+								)
 								.map(|(f, v)| sync.shared_update(sync_id(), f, v)),
 							)
 							.collect::<Vec<_>>(),
@@ -234,6 +305,9 @@ async fn identifier_job_step(
 							vec![
 								object::date_created::set(fp.date_created),
 								object::kind::set(kind),
+								object::kind_version::set(CLASSIFIER_VERSION),
+								object::mime::set(Some(meta.mime.to_string())),
+								object::perceptual_hash::set(perceptual_hash),
 								object::size_in_bytes::set(size),
 							],
 						),
@@ -246,6 +320,10 @@ async fn identifier_job_step(
 				})
 				.unzip();
 
+		file_path_update_args.extend(hardlinked_file_paths.iter().map(|(id, _)| {
+			file_path_object_connect_ops(*id, pub_ids[&hardlinked_to[id]], location, sync, db)
+		}));
+
 		// create new object records with assembled values
 		let total_created_files = sync
 			.write_ops(db, {
@@ -280,6 +358,27 @@ async fn identifier_job_step(
 
 file_path::select!(file_path_only_id { id });
 
+/// Computes the perceptual hashes of the images among the file paths, keyed by file path id.
+///
+/// Images that can't be decoded are skipped, as they're still worth an object.
+fn perceptual_hashes(
+	location_path: &str,
+	file_paths: &[(i32, (FileMetadata, &file_path::Data))],
+) -> HashMap<i32, Vec<u8>> {
+	file_paths
+		.iter()
+		.filter(|(_, (meta, _))| meta.kind == ObjectKind::Image)
+		.filter_map(|(id, (_, file_path))| {
+			let path = Path::new(location_path).join(&file_path.materialized_path);
+
+			perceptual_hash(&path)
+				.map_err(|e| error!("Error computing perceptual hash for {:?}: {e}", path))
+				.ok()
+				.map(|hash| (*id, hash))
+		})
+		.collect()
+}
+
 fn file_path_object_connect_ops<'db>(
 	file_path_id: i32,
 	object_id: Uuid,
@@ -0,0 +1,318 @@
+use crate::{
+	invalidate_query,
+	job::{
+		Job, JobCategory, JobError, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	library::LibraryContext,
+	object::similar::perceptual_hash,
+	prisma::{file_path, object},
+	sync,
+};
+
+use std::path::Path;
+
+use int_enum::IntEnum;
+use prisma_client_rust::Direction;
+use sd_file_ext::kind::ObjectKind;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{error, info};
+
+use super::CHUNK_SIZE;
+
+pub const PERCEPTUAL_HASH_JOB_NAME: &str = "perceptual_hasher";
+
+// The identifier only hashes images while perceptual hashing is enabled on their location, so this job hashes the
+// images that were identified before it was
+pub struct PerceptualHashJob {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
+pub struct PerceptualHashJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PerceptualHashJobState {
+	// the id of the last object that was processed
+	cursor: i32,
+	report: PerceptualHashReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PerceptualHashReport {
+	total_unhashed_objects: usize,
+	total_hashed: usize,
+}
+
+object::select!(object_for_perceptual_hash {
+	id
+	pub_id
+	file_paths: select {
+		location_id
+		materialized_path
+		location: select { path }
+	}
+});
+
+/// Queues a job that computes the perceptual hash of every image in a location that doesn't have one yet.
+pub async fn backfill_perceptual_hashes(library: &LibraryContext, location_id: i32) {
+	library
+		.spawn_job(Job::new(
+			PerceptualHashJobInit { location_id },
+			PerceptualHashJob {},
+		))
+		.await;
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for PerceptualHashJob {
+	type Init = PerceptualHashJobInit;
+	type Data = PerceptualHashJobState;
+	type Step = ();
+
+	const CATEGORY: JobCategory = JobCategory::Hashing;
+
+	fn name(&self) -> &'static str {
+		PERCEPTUAL_HASH_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let unhashed_count = ctx
+			.library_ctx
+			.db
+			.object()
+			.count(unhashed_object_filters(state.init.location_id, None))
+			.exec()
+			.await? as usize;
+
+		let task_count = (unhashed_count as f64 / CHUNK_SIZE as f64).ceil() as usize;
+		info!(
+			"Found {} images without a perceptual hash. Will execute {} tasks...",
+			unhashed_count, task_count
+		);
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(task_count)]);
+
+		state.data = Some(PerceptualHashJobState {
+			report: PerceptualHashReport {
+				total_unhashed_objects: unhashed_count,
+				..Default::default()
+			},
+			..Default::default()
+		});
+
+		state.steps = (0..task_count).map(|_| ()).collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let LibraryContext { db, sync, .. } = &ctx.library_ctx;
+		let location_id = state.init.location_id;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		let objects = db
+			.object()
+			.find_many(unhashed_object_filters(location_id, Some(data.cursor)))
+			.order_by(object::id::order(Direction::Asc))
+			.take(CHUNK_SIZE as i64)
+			.select(object_for_perceptual_hash::select())
+			.exec()
+			.await?;
+
+		let Some(last) = objects.last() else {
+			return Err(JobError::EarlyFinish {
+				name: self.name().to_string(),
+				reason: "Expected unhashed objects not returned from database query for this chunk"
+					.to_string(),
+			});
+		};
+		data.cursor = last.id;
+
+		// images that can't be decoded are left without a hash, as the identifier does
+		let hashes = objects
+			.into_iter()
+			.filter_map(|object| {
+				let file_path = object
+					.file_paths
+					.iter()
+					.find(|file_path| file_path.location_id == location_id)?;
+				let path = Path::new(&file_path.location.path).join(&file_path.materialized_path);
+
+				perceptual_hash(&path)
+					.map_err(|e| error!("Error computing perceptual hash for {:?}: {e}", path))
+					.ok()
+					.map(|hash| (object.id, object.pub_id, hash))
+			})
+			.collect::<Vec<_>>();
+
+		data.report.total_hashed += hashes.len();
+
+		if !hashes.is_empty() {
+			sync.write_ops(
+				db,
+				hashes
+					.into_iter()
+					.map(|(id, pub_id, hash)| {
+						(
+							sync.shared_update(
+								sync::object::SyncId { pub_id },
+								"perceptual_hash",
+								json!(hash),
+							),
+							db.object().update(
+								object::id::equals(id),
+								vec![object::perceptual_hash::set(Some(hash))],
+							),
+						)
+					})
+					.unzip::<_, _, Vec<_>, Vec<_>>(),
+			)
+			.await?;
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::Message(format!(
+				"Hashed {} of {} images",
+				((state.step_number + 1) * CHUNK_SIZE).min(data.report.total_unhashed_objects),
+				data.report.total_unhashed_objects
+			)),
+		]);
+
+		invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing perceptual hash job: {:#?}", data.report);
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+fn unhashed_object_filters(location_id: i32, cursor: Option<i32>) -> Vec<object::WhereParam> {
+	let mut params = vec![
+		object::kind::equals(ObjectKind::Image.int_value()),
+		object::perceptual_hash::equals(None),
+		object::file_paths::some(vec![file_path::location_id::equals(location_id)]),
+	];
+	if let Some(cursor) = cursor {
+		params.push(object::id::gt(cursor));
+	}
+	params
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		location::LocationUpdateArgs,
+		prisma::{location, node},
+		Node,
+	};
+
+	use std::time::Duration;
+
+	use image::{ImageBuffer, Rgb};
+	use tokio::time::{sleep, timeout};
+	use uuid::Uuid;
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn enabling_hashing_backfills_existing_images() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+		let db = &library.db;
+
+		let dir = tempfile::tempdir().unwrap();
+		ImageBuffer::from_fn(32, 32, |x, y| Rgb([(x * 8) as u8, (y * 8) as u8, 0]))
+			.save(dir.path().join("photo.png"))
+			.unwrap();
+
+		let location = db
+			.location()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				"Photos".to_string(),
+				dir.path().to_str().unwrap().to_string(),
+				node::id::equals(library.node_local_id),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap();
+		assert!(!location.perceptual_hashing);
+
+		// identified while hashing was off
+		let object = db
+			.object()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				vec![object::kind::set(ObjectKind::Image.int_value())],
+			)
+			.exec()
+			.await
+			.unwrap();
+		db.file_path()
+			.create(
+				1,
+				location::id::equals(location.id),
+				"photo.png".to_string(),
+				"photo".to_string(),
+				"png".to_string(),
+				vec![file_path::object::connect(object::id::equals(object.id))],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		LocationUpdateArgs {
+			id: location.id,
+			name: None,
+			generate_preview_media: None,
+			sync_preview_media: None,
+			perceptual_hashing: Some(true),
+			hidden: None,
+			indexer_rules_ids: vec![],
+		}
+		.update(&library)
+		.await
+		.unwrap();
+
+		let hash = timeout(Duration::from_secs(10), async {
+			loop {
+				let object = db
+					.object()
+					.find_unique(object::id::equals(object.id))
+					.exec()
+					.await
+					.unwrap()
+					.unwrap();
+				if let Some(hash) = object.perceptual_hash {
+					break hash;
+				}
+				sleep(Duration::from_millis(50)).await;
+			}
+		})
+		.await
+		.expect("the image was never hashed");
+
+		assert_eq!(hash, perceptual_hash(dir.path().join("photo.png")).unwrap());
+	}
+}
@@ -0,0 +1,395 @@
+use crate::{
+	invalidate_query,
+	job::{
+		Job, JobCategory, JobError, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	library::LibraryContext,
+	prisma::{file_path, object},
+	sync,
+};
+
+use std::{
+	ffi::OsStr,
+	future::Future,
+	path::{Path, PathBuf},
+};
+
+use int_enum::IntEnum;
+use prisma_client_rust::Direction;
+use sd_file_ext::{
+	extensions::Extension,
+	kind::{ObjectKind, CLASSIFIER_VERSION},
+	magic::ExtensionPossibility,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{error, info};
+
+use super::{identify_kind, CHUNK_SIZE};
+
+pub const REIDENTIFIER_JOB_NAME: &str = "object_reidentifier";
+
+// The re-identifier runs the current classifier over objects that were identified by an older one,
+// and updates the kinds that changed
+pub struct ObjectReidentifierJob {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
+pub struct ObjectReidentifierJobInit {
+	// only objects with a path in this location are re-identified, or every object if it's `None`
+	pub location_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ObjectReidentifierJobState {
+	// the id of the last object that was processed
+	cursor: i32,
+	report: ObjectReidentifierReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ObjectReidentifierReport {
+	total_stale_objects: usize,
+	total_kinds_changed: usize,
+	// how many files had to be opened, as their extension didn't settle their kind
+	total_contents_read: usize,
+}
+
+object::select!(object_for_reidentify {
+	id
+	pub_id
+	kind
+	file_paths: select {
+		location_id
+		materialized_path
+		location: select { path }
+	}
+});
+
+/// Queues a job that re-identifies every object that the current classifier hasn't seen yet.
+///
+/// Objects record the `CLASSIFIER_VERSION` that derived their kind, so it only does work after the classifier has been upgraded.
+pub async fn reidentify_all(library: &LibraryContext, location_id: Option<i32>) {
+	library
+		.spawn_job(Job::new(
+			ObjectReidentifierJobInit { location_id },
+			ObjectReidentifierJob {},
+		))
+		.await;
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ObjectReidentifierJob {
+	type Init = ObjectReidentifierJobInit;
+	type Data = ObjectReidentifierJobState;
+	type Step = ();
+
+	const CATEGORY: JobCategory = JobCategory::Hashing;
+
+	fn name(&self) -> &'static str {
+		REIDENTIFIER_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let stale_count = ctx
+			.library_ctx
+			.db
+			.object()
+			.count(stale_object_filters(state.init.location_id, None))
+			.exec()
+			.await? as usize;
+
+		let task_count = (stale_count as f64 / CHUNK_SIZE as f64).ceil() as usize;
+		info!(
+			"Found {} objects identified by an older classifier. Will execute {} tasks...",
+			stale_count, task_count
+		);
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(task_count)]);
+
+		state.data = Some(ObjectReidentifierJobState {
+			report: ObjectReidentifierReport {
+				total_stale_objects: stale_count,
+				..Default::default()
+			},
+			..Default::default()
+		});
+
+		state.steps = (0..task_count).map(|_| ()).collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let LibraryContext { db, sync, .. } = &ctx.library_ctx;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		let objects = db
+			.object()
+			.find_many(stale_object_filters(
+				state.init.location_id,
+				Some(data.cursor),
+			))
+			.order_by(object::id::order(Direction::Asc))
+			.take(CHUNK_SIZE as i64)
+			.select(object_for_reidentify::select())
+			.exec()
+			.await?;
+
+		let Some(last) = objects.last() else {
+			return Err(JobError::EarlyFinish {
+				name: self.name().to_string(),
+				reason: "Expected stale objects not returned from database query for this chunk"
+					.to_string(),
+			});
+		};
+		data.cursor = last.id;
+
+		let stale = objects
+			.iter()
+			.filter_map(|object| {
+				// objects without a path (in the location) can't be looked at, so they're left for a later run
+				let file_path = object.file_paths.iter().find(|file_path| {
+					state
+						.init
+						.location_id
+						.map_or(true, |id| file_path.location_id == id)
+				})?;
+
+				Some(StaleObject {
+					id: object.id,
+					kind: ObjectKind::from_int(object.kind).unwrap_or(ObjectKind::Unknown),
+					path: Path::new(&file_path.location.path).join(&file_path.materialized_path),
+				})
+			})
+			.collect::<Vec<_>>();
+
+		let reclassified = reclassify(&stale, kind_from_extension, identify_kind).await;
+
+		data.report.total_contents_read += reclassified.contents_read;
+		data.report.total_kinds_changed += reclassified.changed.len();
+
+		if !reclassified.changed.is_empty() {
+			sync.write_ops(
+				db,
+				reclassified
+					.changed
+					.iter()
+					.map(|(id, kind)| {
+						let pub_id = objects
+							.iter()
+							.find(|object| object.id == *id)
+							.map(|object| object.pub_id.clone())
+							.expect("changed objects come from this chunk");
+
+						(
+							sync.shared_update(
+								sync::object::SyncId { pub_id },
+								"kind",
+								json!(kind.int_value()),
+							),
+							db.object().update(
+								object::id::equals(*id),
+								vec![
+									object::kind::set(kind.int_value()),
+									object::kind_version::set(CLASSIFIER_VERSION),
+								],
+							),
+						)
+					})
+					.unzip::<_, _, Vec<_>, Vec<_>>(),
+			)
+			.await?;
+		}
+
+		// the kind version is specific to this node's classifier, so it isn't synced
+		db.object()
+			.update_many(
+				vec![object::id::in_vec(reclassified.unchanged)],
+				vec![object::kind_version::set(CLASSIFIER_VERSION)],
+			)
+			.exec()
+			.await?;
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::Message(format!(
+				"Re-identified {} of {} objects",
+				((state.step_number + 1) * CHUNK_SIZE).min(data.report.total_stale_objects),
+				data.report.total_stale_objects
+			)),
+		]);
+
+		invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing re-identifier job: {:#?}", data.report);
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+fn stale_object_filters(location_id: Option<i32>, cursor: Option<i32>) -> Vec<object::WhereParam> {
+	let mut params = vec![object::kind_version::lt(CLASSIFIER_VERSION)];
+	if let Some(location_id) = location_id {
+		params.push(object::file_paths::some(vec![
+			file_path::location_id::equals(location_id),
+		]));
+	}
+	if let Some(cursor) = cursor {
+		params.push(object::id::gt(cursor));
+	}
+	params
+}
+
+/// Derives a file's kind from its extension alone, which is only possible when the extension maps to a single kind
+fn kind_from_extension(path: &Path) -> Option<ObjectKind> {
+	match Extension::from_str(path.extension().and_then(OsStr::to_str)?)? {
+		ExtensionPossibility::Known(extension) => Some(extension.into()),
+		ExtensionPossibility::Conflicts(_) => None,
+	}
+}
+
+struct StaleObject {
+	id: i32,
+	kind: ObjectKind,
+	path: PathBuf,
+}
+
+#[derive(Debug, Default)]
+struct Reclassified {
+	// the objects whose kind changed, with their new kind
+	changed: Vec<(i32, ObjectKind)>,
+	unchanged: Vec<i32>,
+	contents_read: usize,
+}
+
+/// Runs the classifier over stale objects again.
+///
+/// Most files are settled by their extension, so `read_kind` (which opens the file) is only used when it isn't enough.
+/// Objects whose file can't be read are left out, so they stay stale.
+async fn reclassify<F, Fut>(
+	stale: &[StaleObject],
+	from_extension: impl Fn(&Path) -> Option<ObjectKind>,
+	read_kind: F,
+) -> Reclassified
+where
+	F: Fn(PathBuf) -> Fut,
+	Fut: Future<Output = Result<ObjectKind, std::io::Error>>,
+{
+	let mut reclassified = Reclassified::default();
+
+	for object in stale {
+		let kind = match from_extension(&object.path) {
+			Some(kind) => kind,
+			None => {
+				reclassified.contents_read += 1;
+				match read_kind(object.path.clone()).await {
+					Ok(kind) => kind,
+					Err(e) => {
+						error!("Failed to re-identify {}: {e:#?}", object.path.display());
+						continue;
+					}
+				}
+			}
+		};
+
+		if kind == object.kind {
+			reclassified.unchanged.push(object.id);
+		} else {
+			reclassified.changed.push((object.id, kind));
+		}
+	}
+
+	reclassified
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	fn stale(id: i32, kind: ObjectKind, path: &str) -> StaleObject {
+		StaleObject {
+			id,
+			kind,
+			path: PathBuf::from(path),
+		}
+	}
+
+	#[tokio::test]
+	async fn changed_kinds_are_updated() {
+		// an older classifier that didn't know about source code
+		let old_classifier = |path: &Path| match kind_from_extension(path) {
+			Some(ObjectKind::Code) => Some(ObjectKind::Unknown),
+			kind => kind,
+		};
+
+		let objects = [
+			("/location/main.rs", 1),
+			("/location/photo.png", 2),
+			("/location/notes.txt", 3),
+		]
+		.into_iter()
+		.map(|(path, id)| stale(id, old_classifier(Path::new(path)).unwrap(), path))
+		.collect::<Vec<_>>();
+
+		assert_eq!(objects[0].kind, ObjectKind::Unknown);
+
+		let reads = AtomicUsize::new(0);
+		let reclassified = reclassify(&objects, kind_from_extension, |_| {
+			reads.fetch_add(1, Ordering::Relaxed);
+			async { Ok(ObjectKind::Unknown) }
+		})
+		.await;
+
+		assert_eq!(reclassified.changed, vec![(1, ObjectKind::Code)]);
+		assert_eq!(reclassified.unchanged, vec![2, 3]);
+
+		// all of these are settled by their extension, so none of them are opened
+		assert_eq!(reads.load(Ordering::Relaxed), 0);
+		assert_eq!(reclassified.contents_read, 0);
+	}
+
+	#[tokio::test]
+	async fn ambiguous_extensions_are_read() {
+		let objects = vec![
+			stale(1, ObjectKind::Code, "/location/clip.ts"),
+			stale(2, ObjectKind::Unknown, "/location/secret"),
+			stale(3, ObjectKind::Unknown, "/location/missing"),
+		];
+
+		let reclassified = reclassify(&objects, kind_from_extension, |path| async move {
+			match path.file_name().and_then(OsStr::to_str) {
+				Some("clip.ts") => Ok(ObjectKind::Video),
+				Some("secret") => Ok(ObjectKind::Encrypted),
+				_ => Err(std::io::ErrorKind::NotFound.into()),
+			}
+		})
+		.await;
+
+		assert_eq!(
+			reclassified.changed,
+			vec![(1, ObjectKind::Video), (2, ObjectKind::Encrypted)]
+		);
+		// the missing file stays stale, so it's neither changed nor unchanged
+		assert!(reclassified.unchanged.is_empty());
+		assert_eq!(reclassified.contents_read, 3);
+	}
+}
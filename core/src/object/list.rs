@@ -0,0 +1,487 @@
+use crate::prisma::{file_path, object, PrismaClient};
+
+use chrono::{DateTime, FixedOffset};
+use prisma_client_rust::{and, not, or, Direction, PrismaValue, QueryError, Raw};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum SortField {
+	Name,
+	/// `size_in_bytes` is stored as a string, so it's cast to an integer in a raw query to get a
+	/// numeric order, where a size that isn't a number counts as 0.
+	Size,
+	DateModified,
+	/// Objects without a date taken come after the dated ones in both directions, ordered by their
+	/// modification date instead.
+	DateTaken,
+	Kind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum SortDirection {
+	Asc,
+	Desc,
+}
+
+impl From<SortDirection> for Direction {
+	fn from(direction: SortDirection) -> Self {
+		match direction {
+			SortDirection::Asc => Direction::Asc,
+			SortDirection::Desc => Direction::Desc,
+		}
+	}
+}
+
+/// The sort key of the last object in a page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum CursorKey {
+	Name(Option<String>),
+	Size(i64),
+	DateModified(DateTime<FixedOffset>),
+	DateTaken {
+		date_taken: Option<DateTime<FixedOffset>>,
+		date_modified: DateTime<FixedOffset>,
+	},
+	Kind(i32),
+}
+
+/// Points at the last object of a page, so the next page can start right after it.
+///
+/// As the id is used to break ties between objects with the same sort key, the order is stable
+/// and no object can show up on two pages.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct ListCursor {
+	pub key: CursorKey,
+	pub id: i32,
+}
+
+impl ListCursor {
+	fn new(sort: SortField, object: &object::Data) -> Self {
+		let key = match sort {
+			SortField::Name => CursorKey::Name(object.name.clone()),
+			SortField::Size => CursorKey::Size(numeric_size(&object.size_in_bytes)),
+			SortField::DateModified => CursorKey::DateModified(object.date_modified),
+			SortField::DateTaken => CursorKey::DateTaken {
+				date_taken: object.date_taken,
+				date_modified: object.date_modified,
+			},
+			SortField::Kind => CursorKey::Kind(object.kind),
+		};
+
+		Self { key, id: object.id }
+	}
+
+	/// Matches the objects that come after this cursor in the given direction.
+	///
+	/// SQLite sorts `NULL` before any other value, so nameless objects are the first ones when
+	/// sorting by name in ascending order, and the last ones in descending order.
+	///
+	/// Dated and undated objects are fetched separately when sorting by date taken, so those
+	/// filters only cover the part of the list the cursor is in.
+	fn after(self, direction: SortDirection) -> object::WhereParam {
+		let id_after = match direction {
+			SortDirection::Asc => object::id::gt(self.id),
+			SortDirection::Desc => object::id::lt(self.id),
+		};
+
+		// The equality branch goes first, so it can clone the sort key before it's moved into the comparison
+		macro_rules! keyset {
+			($field:ident, $equals:expr, $value:expr) => {
+				or![
+					and![object::$field::equals($equals), id_after],
+					match direction {
+						SortDirection::Asc => object::$field::gt($value),
+						SortDirection::Desc => object::$field::lt($value),
+					}
+				]
+			};
+		}
+
+		match self.key {
+			CursorKey::Name(Some(name)) => match direction {
+				SortDirection::Asc => keyset!(name, Some(name.clone()), name),
+				SortDirection::Desc => or![
+					keyset!(name, Some(name.clone()), name),
+					object::name::equals(None)
+				],
+			},
+			CursorKey::Name(None) => match direction {
+				SortDirection::Asc => or![
+					and![object::name::equals(None), id_after],
+					not![object::name::equals(None)]
+				],
+				SortDirection::Desc => and![object::name::equals(None), id_after],
+			},
+			CursorKey::Size(_) => unreachable!("sorting by size is done in a raw query"),
+			CursorKey::DateModified(date) => keyset!(date_modified, date, date),
+			CursorKey::DateTaken {
+				date_taken: Some(date),
+				..
+			} => keyset!(date_taken, Some(date), date),
+			CursorKey::DateTaken {
+				date_taken: None,
+				date_modified,
+			} => keyset!(date_modified, date_modified, date_modified),
+			CursorKey::Kind(kind) => keyset!(kind, kind, kind),
+		}
+	}
+
+	fn is_undated(&self) -> bool {
+		matches!(
+			self.key,
+			CursorKey::DateTaken {
+				date_taken: None,
+				..
+			}
+		)
+	}
+}
+
+/// An inclusive range of dates.
+#[derive(Debug, Clone, Copy, Deserialize, Type)]
+pub struct DateRange {
+	pub from: DateTime<FixedOffset>,
+	pub to: DateTime<FixedOffset>,
+}
+
+#[derive(Debug, Clone, Deserialize, Type)]
+pub struct ListQuery {
+	/// Only list objects which have a path in this location, otherwise objects from every location are listed
+	pub location_id: Option<i32>,
+	/// Only list objects which were taken in this range, which excludes every undated object
+	pub date_taken: Option<DateRange>,
+	pub sort: SortField,
+	pub direction: SortDirection,
+	pub cursor: Option<ListCursor>,
+	pub limit: i32,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct ListPage {
+	pub objects: Vec<object::Data>,
+	pub next_cursor: Option<ListCursor>,
+}
+
+/// Lists a page of objects, using keyset pagination so deep pages are as fast as the first one.
+pub async fn list(db: &PrismaClient, query: ListQuery) -> Result<ListPage, QueryError> {
+	let ListQuery {
+		location_id,
+		date_taken,
+		sort,
+		direction,
+		cursor,
+		limit,
+	} = query;
+
+	let filters = || {
+		// quarantined objects aren't part of the library until they're reviewed, and soft-deleted ones until they're restored
+		let mut params = vec![
+			object::pending_review::equals(false),
+			object::date_deleted::equals(None),
+		];
+		if let Some(location_id) = location_id {
+			params.push(object::file_paths::some(vec![
+				file_path::location_id::equals(location_id),
+			]));
+		}
+		if let Some(DateRange { from, to }) = date_taken {
+			params.push(object::date_taken::gte(from));
+			params.push(object::date_taken::lte(to));
+		}
+		params
+	};
+
+	let limit = limit.max(1);
+	// We fetch an extra object, just to know if there is a next page
+	let take = limit as usize + 1;
+
+	let objects = if sort == SortField::Size {
+		let ids = size_ordered_ids(db, location_id, date_taken, cursor, direction, take).await?;
+
+		let mut objects = db
+			.object()
+			.find_many(vec![object::id::in_vec(ids.clone())])
+			.exec()
+			.await?;
+		objects.sort_by_key(|object| ids.iter().position(|id| *id == object.id));
+
+		objects
+	} else if sort == SortField::DateTaken {
+		let mut cursor = cursor;
+		let mut objects = Vec::with_capacity(take);
+
+		for phase in DateTakenPhase::remaining(cursor.as_ref()) {
+			let mut params = filters();
+			params.push(match phase {
+				DateTakenPhase::Dated => not![object::date_taken::equals(None)],
+				DateTakenPhase::Undated => object::date_taken::equals(None),
+			});
+			// The cursor only applies to the phase it's in, the next one starts from the beginning
+			if let Some(cursor) = cursor.take() {
+				params.push(cursor.after(direction));
+			}
+
+			let order = match phase {
+				DateTakenPhase::Dated => object::date_taken::order(direction.into()),
+				DateTakenPhase::Undated => object::date_modified::order(direction.into()),
+			};
+
+			objects.extend(
+				db.object()
+					.find_many(params)
+					.order_by(order)
+					.order_by(object::id::order(direction.into()))
+					.take((take - objects.len()) as i64)
+					.exec()
+					.await?,
+			);
+
+			if objects.len() == take {
+				break;
+			}
+		}
+
+		objects
+	} else {
+		let mut params = filters();
+		if let Some(cursor) = cursor {
+			params.push(cursor.after(direction));
+		}
+
+		let order = match sort {
+			SortField::Name => object::name::order(direction.into()),
+			SortField::Size => unreachable!("sorting by size is done in a raw query"),
+			SortField::DateModified => object::date_modified::order(direction.into()),
+			SortField::DateTaken => unreachable!("sorting by date taken is done in phases"),
+			SortField::Kind => object::kind::order(direction.into()),
+		};
+
+		db.object()
+			.find_many(params)
+			.order_by(order)
+			.order_by(object::id::order(direction.into()))
+			.take(take as i64)
+			.exec()
+			.await?
+	};
+
+	let (objects, next_cursor) = into_page(objects, limit as usize, |object| {
+		ListCursor::new(sort, object)
+	});
+
+	Ok(ListPage {
+		objects,
+		next_cursor,
+	})
+}
+
+/// Mirrors how SQLite casts `size_in_bytes` to an integer, for the sizes we write, which are always numbers.
+fn numeric_size(size_in_bytes: &str) -> i64 {
+	size_in_bytes.parse().unwrap_or(0)
+}
+
+#[derive(Deserialize)]
+struct IdRow {
+	id: i32,
+}
+
+/// Fetches the ids of a page of objects sorted by their numeric size.
+///
+/// Prisma can only order by the `size_in_bytes` string as it is, so the same filters as the query
+/// builder path are written out in SQL, with `CAST` for both the order and the keyset.
+async fn size_ordered_ids(
+	db: &PrismaClient,
+	location_id: Option<i32>,
+	date_taken: Option<DateRange>,
+	cursor: Option<ListCursor>,
+	direction: SortDirection,
+	take: usize,
+) -> Result<Vec<i32>, QueryError> {
+	const SIZE: &str = "CAST(size_in_bytes AS INTEGER)";
+
+	let (comparison, order) = match direction {
+		SortDirection::Asc => (">", "ASC"),
+		SortDirection::Desc => ("<", "DESC"),
+	};
+
+	// quarantined objects aren't part of the library until they're reviewed, and soft-deleted ones until they're restored
+	let mut sql =
+		String::from("SELECT id FROM object WHERE pending_review = false AND date_deleted IS NULL");
+	let mut params = vec![];
+
+	if let Some(location_id) = location_id {
+		sql.push_str(
+			" AND EXISTS (SELECT 1 FROM file_path \
+			WHERE file_path.object_id = object.id AND file_path.location_id = {})",
+		);
+		params.push(PrismaValue::Int(location_id as i64));
+	}
+	if let Some(DateRange { from, to }) = date_taken {
+		sql.push_str(" AND date_taken >= {} AND date_taken <= {}");
+		params.push(PrismaValue::DateTime(from));
+		params.push(PrismaValue::DateTime(to));
+	}
+	if let Some(ListCursor {
+		key: CursorKey::Size(size),
+		id,
+	}) = cursor
+	{
+		sql.push_str(&format!(
+			" AND ({SIZE} {comparison} {{}} OR ({SIZE} = {{}} AND id {comparison} {{}}))"
+		));
+		params.push(PrismaValue::BigInt(size));
+		params.push(PrismaValue::BigInt(size));
+		params.push(PrismaValue::Int(id as i64));
+	}
+
+	sql.push_str(&format!(" ORDER BY {SIZE} {order}, id {order} LIMIT {{}}"));
+	params.push(PrismaValue::Int(take as i64));
+
+	Ok(db
+		._query_raw::<IdRow>(Raw::new(&sql, params))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|row| row.id)
+		.collect())
+}
+
+/// SQLite can't be told to sort `NULL` last, so when sorting by date taken the dated and undated
+/// objects are fetched one after the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DateTakenPhase {
+	Dated,
+	Undated,
+}
+
+impl DateTakenPhase {
+	/// The phases that are left to fetch, starting with the one the cursor is in.
+	fn remaining(cursor: Option<&ListCursor>) -> &'static [Self] {
+		match cursor {
+			Some(cursor) if cursor.is_undated() => &[Self::Undated],
+			_ => &[Self::Dated, Self::Undated],
+		}
+	}
+}
+
+/// Splits the extra item we fetched off the page, and points the cursor at the last item we keep.
+fn into_page<T>(
+	mut items: Vec<T>,
+	limit: usize,
+	cursor: impl Fn(&T) -> ListCursor,
+) -> (Vec<T>, Option<ListCursor>) {
+	let next_cursor = if items.len() > limit {
+		items.truncate(limit);
+		items.last().map(cursor)
+	} else {
+		None
+	};
+
+	(items, next_cursor)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn kind_cursor(item: &(i32, i32)) -> ListCursor {
+		ListCursor {
+			key: CursorKey::Kind(item.1),
+			id: item.0,
+		}
+	}
+
+	#[test]
+	fn page_with_more_items() {
+		let (page, next_cursor) = into_page(vec![(1, 5), (2, 5), (3, 7)], 2, kind_cursor);
+
+		assert_eq!(page, vec![(1, 5), (2, 5)]);
+		assert_eq!(
+			next_cursor,
+			Some(ListCursor {
+				key: CursorKey::Kind(5),
+				id: 2,
+			})
+		);
+	}
+
+	#[test]
+	fn last_page_has_no_cursor() {
+		let (page, next_cursor) = into_page(vec![(1, 5), (2, 5)], 2, kind_cursor);
+
+		assert_eq!(page.len(), 2);
+		assert!(next_cursor.is_none());
+
+		let (page, next_cursor) = into_page(Vec::<(i32, i32)>::new(), 2, kind_cursor);
+
+		assert!(page.is_empty());
+		assert!(next_cursor.is_none());
+	}
+
+	#[test]
+	fn sizes_are_compared_as_numbers() {
+		let mut sizes = vec!["10", "9", "100", "0"];
+		sizes.sort_by_key(|size| numeric_size(size));
+
+		assert_eq!(sizes, ["0", "9", "10", "100"]);
+		assert_eq!(numeric_size("not a size"), 0);
+	}
+
+	#[test]
+	fn undated_objects_come_after_dated_ones() {
+		let date = |s| DateTime::parse_from_rfc3339(s).unwrap();
+		let date_taken_cursor = |item: &(i32, Option<&str>, &str)| ListCursor {
+			key: CursorKey::DateTaken {
+				date_taken: item.1.map(date),
+				date_modified: date(item.2),
+			},
+			id: item.0,
+		};
+
+		assert_eq!(
+			DateTakenPhase::remaining(None),
+			[DateTakenPhase::Dated, DateTakenPhase::Undated]
+		);
+
+		// the dated phase ran out after one photo, so the page is filled with undated ones
+		let (page, next_cursor) = into_page(
+			vec![
+				(1, Some("2019-06-01T12:00:00Z"), "2023-01-01T00:00:00Z"),
+				(2, None, "2018-01-01T00:00:00Z"),
+				(3, None, "2020-01-01T00:00:00Z"),
+			],
+			2,
+			date_taken_cursor,
+		);
+		assert_eq!(page.iter().map(|item| item.0).collect::<Vec<_>>(), [1, 2]);
+
+		// the fallback date of the last undated object is where the next page starts
+		let next_cursor = next_cursor.unwrap();
+		assert_eq!(
+			next_cursor.key,
+			CursorKey::DateTaken {
+				date_taken: None,
+				date_modified: date("2018-01-01T00:00:00Z"),
+			}
+		);
+		assert_eq!(
+			DateTakenPhase::remaining(Some(&next_cursor)),
+			[DateTakenPhase::Undated]
+		);
+
+		// a cursor on a dated object still has the undated objects left to go
+		let (_, next_cursor) = into_page(
+			vec![
+				(1, Some("2019-06-01T12:00:00Z"), "2023-01-01T00:00:00Z"),
+				(4, Some("2021-06-01T12:00:00Z"), "2023-01-01T00:00:00Z"),
+			],
+			1,
+			date_taken_cursor,
+		);
+		assert_eq!(
+			DateTakenPhase::remaining(next_cursor.as_ref()),
+			[DateTakenPhase::Dated, DateTakenPhase::Undated]
+		);
+	}
+}
@@ -1,7 +1,15 @@
+pub mod bundle;
 pub mod cas;
+pub mod color_label;
+pub mod favorite;
 pub mod fs;
 pub mod identifier_job;
+pub mod list;
+pub mod native_tags;
 pub mod preview;
+pub mod quarantine;
+pub mod recents;
+pub mod similar;
 pub mod tag;
 pub mod validation;
 
@@ -14,6 +22,17 @@ use serde::{Deserialize, Serialize};
 
 use crate::prisma;
 
+pub use bundle::{export_bundle, import_bundle};
+pub use cas::{duplicate_candidates, quick_fingerprint, DuplicateGroup};
+pub use fs::{
+	dedup::{dedup_link, DedupStrategy},
+	relocate::{move_to_location, NameConflict},
+};
+pub use identifier_job::reidentifier_job::reidentify_all;
+pub use native_tags::import_native_tags;
+pub use quarantine::{list_quarantine, review, Decision};
+pub use similar::find_similar;
+
 // The response to provide the Explorer when looking at Objects
 #[derive(Debug, Serialize, Deserialize, Type)]
 pub struct ObjectsForExplorer {
@@ -34,3 +53,10 @@ pub enum ObjectData {
 	Object(Box<prisma::object::Data>),
 	Path(Box<prisma::file_path::Data>),
 }
+
+/// The precise content type of an object (e.g. `image/png` rather than just an image), as derived by the identifier.
+///
+/// Objects that were identified before it was recorded don't have one.
+pub fn mime(object: &prisma::object::Data) -> Option<&str> {
+	object.mime.as_deref()
+}
@@ -0,0 +1,367 @@
+use crate::{
+	invalidate_query,
+	job::{
+		Job, JobCategory, JobError, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	library::LibraryContext,
+	location::LocationError,
+	prisma::{file_path, location},
+};
+
+use std::{
+	io,
+	path::{Path, PathBuf},
+};
+
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use tracing::{info, warn};
+
+use super::tag::{assign_tag, find_or_create_tag};
+
+pub const NATIVE_TAGS_IMPORT_JOB_NAME: &str = "native_tags_import";
+
+const CHUNK_SIZE: usize = 100;
+
+/// Finder keeps a file's tags in this extended attribute, as a binary plist of strings.
+#[cfg(target_os = "macos")]
+const FINDER_TAGS_XATTR: &str = "com.apple.metadata:_kMDItemUserTags";
+
+/// File managers that follow the freedesktop.org conventions (e.g. Dolphin) keep tags in this extended attribute,
+/// separated by commas.
+#[cfg(target_os = "linux")]
+const XDG_TAGS_XATTR: &str = "user.xdg.tags";
+
+// The native tags importer is a one-shot migration for users coming from their platform's file manager,
+// which creates a Spacedrive tag for every native tag it finds and assigns it to the file's object
+pub struct NativeTagsImportJob {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
+pub struct NativeTagsImportJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct NativeTagsImportJobState {
+	location_path: PathBuf,
+	// the id of the last file path that was processed
+	cursor: i32,
+	report: NativeTagsImportReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct NativeTagsImportReport {
+	total_files: usize,
+	total_tagged_files: usize,
+	total_tags_created: usize,
+	total_tags_assigned: usize,
+}
+
+/// A tag that was set on a file with the platform's file manager.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeTag {
+	pub name: String,
+	pub color: Option<&'static str>,
+}
+
+file_path::select!(file_path_for_native_tags {
+	id
+	materialized_path
+	object_id
+});
+
+/// Queues a job that imports the native tags of every file in a location.
+pub async fn import_native_tags(library: &LibraryContext, location_id: i32) {
+	library
+		.spawn_job(Job::new(
+			NativeTagsImportJobInit { location_id },
+			NativeTagsImportJob {},
+		))
+		.await;
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for NativeTagsImportJob {
+	type Init = NativeTagsImportJobInit;
+	type Data = NativeTagsImportJobState;
+	type Step = ();
+
+	const CATEGORY: JobCategory = JobCategory::General;
+
+	fn name(&self) -> &'static str {
+		NATIVE_TAGS_IMPORT_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let db = &ctx.library_ctx.db;
+
+		let location = db
+			.location()
+			.find_unique(location::id::equals(state.init.location_id))
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(state.init.location_id))?;
+
+		let file_count = db
+			.file_path()
+			.count(file_path_filters(state.init.location_id, None))
+			.exec()
+			.await? as usize;
+
+		let task_count = (file_count as f64 / CHUNK_SIZE as f64).ceil() as usize;
+		info!(
+			"Looking for native tags on {} files. Will execute {} tasks...",
+			file_count, task_count
+		);
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(task_count)]);
+
+		state.data = Some(NativeTagsImportJobState {
+			location_path: location.path.into(),
+			report: NativeTagsImportReport {
+				total_files: file_count,
+				..Default::default()
+			},
+			..Default::default()
+		});
+
+		state.steps = (0..task_count).map(|_| ()).collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let db = &ctx.library_ctx.db;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		let file_paths = db
+			.file_path()
+			.find_many(file_path_filters(state.init.location_id, Some(data.cursor)))
+			.order_by(file_path::id::order(Direction::Asc))
+			.take(CHUNK_SIZE as i64)
+			.select(file_path_for_native_tags::select())
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			return Err(JobError::EarlyFinish {
+				name: self.name().to_string(),
+				reason: "Expected file paths not returned from database query for this chunk"
+					.to_string(),
+			});
+		};
+		data.cursor = last.id;
+
+		// reading extended attributes is blocking I/O
+		let location_path = data.location_path.clone();
+		let tagged = spawn_blocking(move || {
+			file_paths
+				.into_iter()
+				.filter_map(|file_path| {
+					let path = location_path.join(&file_path.materialized_path);
+
+					match read_native_tags(&path) {
+						Ok(tags) if !tags.is_empty() => Some((file_path.object_id?, tags)),
+						Ok(_) => None,
+						Err(e) => {
+							warn!("Failed to read native tags of {}: {e:#?}", path.display());
+							None
+						}
+					}
+				})
+				.collect::<Vec<_>>()
+		})
+		.await?;
+
+		for (object_id, tags) in tagged {
+			data.report.total_tagged_files += 1;
+
+			for native_tag in tags {
+				let (tag_id, created) = find_or_create_tag(
+					&ctx.library_ctx,
+					native_tag.name,
+					native_tag.color.map(str::to_string),
+				)
+				.await?;
+
+				if created {
+					data.report.total_tags_created += 1;
+				}
+
+				if assign_tag(db, object_id, tag_id).await? {
+					data.report.total_tags_assigned += 1;
+				}
+			}
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::Message(format!(
+				"Looked for native tags on {} of {} files",
+				((state.step_number + 1) * CHUNK_SIZE).min(data.report.total_files),
+				data.report.total_files
+			)),
+		]);
+
+		invalidate_query!(ctx.library_ctx, "tags.list");
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing native tags import job: {:#?}", data.report);
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+fn file_path_filters(location_id: i32, cursor: Option<i32>) -> Vec<file_path::WhereParam> {
+	let mut params = vec![
+		file_path::location_id::equals(location_id),
+		file_path::is_dir::equals(false),
+		file_path::object_id::not(None),
+	];
+	if let Some(cursor) = cursor {
+		params.push(file_path::id::gt(cursor));
+	}
+	params
+}
+
+/// Reads the tags that were set on a file with Finder.
+#[cfg(target_os = "macos")]
+pub fn read_native_tags(path: &Path) -> io::Result<Vec<NativeTag>> {
+	xattr::get(path, FINDER_TAGS_XATTR)?.map_or(Ok(vec![]), |value| parse_finder_tags(&value))
+}
+
+/// Reads the tags that were set on a file with a file manager that follows the freedesktop.org conventions.
+#[cfg(target_os = "linux")]
+pub fn read_native_tags(path: &Path) -> io::Result<Vec<NativeTag>> {
+	Ok(xattr::get(path, XDG_TAGS_XATTR)?
+		.map(|value| parse_xdg_tags(&value))
+		.unwrap_or_default())
+}
+
+/// There is no native tagging on this platform that we know how to read, so no file has any tags.
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn read_native_tags(_path: &Path) -> io::Result<Vec<NativeTag>> {
+	Ok(vec![])
+}
+
+/// Each Finder tag is its name, optionally followed by a newline and the index of its color.
+#[cfg(target_os = "macos")]
+fn parse_finder_tags(value: &[u8]) -> io::Result<Vec<NativeTag>> {
+	let tags = plist::from_bytes::<Vec<String>>(value)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+	Ok(tags
+		.into_iter()
+		.filter_map(|tag| {
+			let (name, color) = match tag.split_once('\n') {
+				Some((name, color)) => (name, color.parse().ok().and_then(finder_color)),
+				None => (tag.as_str(), None),
+			};
+
+			(!name.is_empty()).then(|| NativeTag {
+				name: name.to_string(),
+				color,
+			})
+		})
+		.collect())
+}
+
+/// Maps the index of a Finder tag color to the hex color that Finder shows for it.
+#[cfg(target_os = "macos")]
+fn finder_color(index: u8) -> Option<&'static str> {
+	match index {
+		1 => Some("#8E8E93"), // gray
+		2 => Some("#34C759"), // green
+		3 => Some("#AF52DE"), // purple
+		4 => Some("#007AFF"), // blue
+		5 => Some("#FFCC00"), // yellow
+		6 => Some("#FF3B30"), // red
+		7 => Some("#FF9500"), // orange
+		_ => None,
+	}
+}
+
+#[cfg(target_os = "linux")]
+fn parse_xdg_tags(value: &[u8]) -> Vec<NativeTag> {
+	String::from_utf8_lossy(value)
+		.split(',')
+		.map(str::trim)
+		.filter(|name| !name.is_empty())
+		.map(|name| NativeTag {
+			name: name.to_string(),
+			color: None,
+		})
+		.collect()
+}
+
+#[cfg(all(test, any(target_os = "macos", target_os = "linux")))]
+mod tests {
+	use super::*;
+
+	#[cfg(target_os = "macos")]
+	#[test]
+	fn read_finder_tags() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("report.pdf");
+		std::fs::write(&path, b"report").unwrap();
+
+		assert!(read_native_tags(&path).unwrap().is_empty());
+
+		let mut value = Vec::new();
+		plist::to_writer_binary(
+			&mut value,
+			&vec!["Work\n6".to_string(), "Later".to_string()],
+		)
+		.unwrap();
+		xattr::set(&path, FINDER_TAGS_XATTR, &value).unwrap();
+
+		assert_eq!(
+			read_native_tags(&path).unwrap(),
+			vec![
+				NativeTag {
+					name: "Work".to_string(),
+					color: Some("#FF3B30"),
+				},
+				NativeTag {
+					name: "Later".to_string(),
+					color: None,
+				},
+			]
+		);
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn parse_xdg_tag_list() {
+		assert_eq!(
+			parse_xdg_tags(b"work, later,,"),
+			vec![
+				NativeTag {
+					name: "work".to_string(),
+					color: None,
+				},
+				NativeTag {
+					name: "later".to_string(),
+					color: None,
+				},
+			]
+		);
+	}
+}
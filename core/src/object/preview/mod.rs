@@ -1,5 +1,7 @@
 mod media_data;
+mod request;
 mod thumb;
 
 pub use media_data::*;
+pub use request::*;
 pub use thumb::*;
@@ -0,0 +1,441 @@
+use crate::{api::CoreEvent, invalidate_query, library::LibraryContext, prisma::file_path};
+
+use std::{
+	collections::HashMap,
+	error::Error,
+	future::Future,
+	hash::Hash,
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::{Arc, Mutex},
+};
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+use sd_file_ext::extensions::ImageExtension;
+use tokio::{fs, io};
+use tracing::{error, info};
+
+use super::{
+	can_generate_thumbnail_for_image, generate_image_thumbnail, is_decode_error,
+	set_thumbnail_status, ThumbnailError, ThumbnailStatus, THUMBNAIL_CACHE_DIR_NAME,
+};
+
+/// Coalesces concurrent requests for the same key, so the work for a given key is only done once
+/// no matter how many callers are waiting on it.
+pub struct Coalescer<K, V> {
+	in_flight: Arc<Mutex<HashMap<K, Shared<BoxFuture<'static, V>>>>>,
+}
+
+impl<K, V> Default for Coalescer<K, V> {
+	fn default() -> Self {
+		Self {
+			in_flight: Default::default(),
+		}
+	}
+}
+
+impl<K, V> Coalescer<K, V>
+where
+	K: Hash + Eq + Clone + Send + 'static,
+	V: Clone + Send + Sync + 'static,
+{
+	/// Runs the future created by `work`, unless there is already one in flight for `key`, in which
+	/// case we just wait for that one to finish and share its result.
+	pub async fn run<F, Fut>(&self, key: K, work: F) -> V
+	where
+		F: FnOnce() -> Fut,
+		Fut: Future<Output = V> + Send + 'static,
+	{
+		let shared = self
+			.in_flight
+			.lock()
+			.expect("poisoned in flight requests lock")
+			.entry(key.clone())
+			.or_insert_with(|| {
+				let in_flight = Arc::clone(&self.in_flight);
+				let fut = work();
+
+				async move {
+					let value = fut.await;
+					in_flight
+						.lock()
+						.expect("poisoned in flight requests lock")
+						.remove(&key);
+					value
+				}
+				.boxed()
+				.shared()
+			})
+			.clone();
+
+		shared.await
+	}
+}
+
+pub type ThumbnailRequests = Coalescer<String, Result<PathBuf, String>>;
+
+file_path::include!(file_path_with_location { location });
+
+/// Returns the path of the thumbnail for the given object, generating it first if it doesn't exist yet.
+///
+/// Concurrent requests for the same object are coalesced into a single generation.
+pub async fn request_thumbnail(
+	library_ctx: &LibraryContext,
+	object_id: i32,
+) -> Result<PathBuf, ThumbnailError> {
+	let file_path = library_ctx
+		.db
+		.file_path()
+		.find_first(vec![file_path::object_id::equals(Some(object_id))])
+		.include(file_path_with_location::include())
+		.exec()
+		.await?
+		.ok_or(ThumbnailError::MissingObject(object_id))?;
+
+	let cas_id = file_path
+		.cas_id
+		.clone()
+		.ok_or(ThumbnailError::MissingObject(object_id))?;
+
+	let output_path = thumbnail_path(library_ctx, &cas_id);
+
+	if library_ctx.thumbnail_exists(&cas_id).await? {
+		return Ok(output_path);
+	}
+
+	let path = PathBuf::from(&file_path.location.path).join(&file_path.materialized_path);
+	let extension = file_path.extension.clone();
+	let requests = Arc::clone(library_ctx.thumbnail_requests());
+	let library_ctx = library_ctx.clone();
+
+	requests
+		.run(cas_id.clone(), move || {
+			generate_and_record(library_ctx, object_id, cas_id, extension, path)
+		})
+		.await
+		.map_err(ThumbnailError::Generation)
+}
+
+/// Replaces an object's thumbnail after its file's content changed, from `old_cas_id` to `cas_id`.
+///
+/// The object is marked as pending until the new thumbnail is written, and the old thumbnail is only deleted once it
+/// has been, so there's always a thumbnail to show. It's kept if another file still has the old content.
+pub async fn regenerate_thumbnail(
+	library_ctx: &LibraryContext,
+	object_id: i32,
+	extension: &str,
+	path: PathBuf,
+	old_cas_id: &str,
+	cas_id: &str,
+) -> Result<(), ThumbnailError> {
+	if !can_generate_thumbnail(extension) {
+		return Ok(());
+	}
+
+	set_thumbnail_status(&library_ctx.db, object_id, ThumbnailStatus::Pending).await?;
+
+	let requests = Arc::clone(library_ctx.thumbnail_requests());
+	let (ctx, cas_id, extension) = (
+		library_ctx.clone(),
+		cas_id.to_string(),
+		extension.to_string(),
+	);
+	requests
+		.run(cas_id.clone(), move || {
+			generate_and_record(ctx, object_id, cas_id, extension, path)
+		})
+		.await
+		.map_err(ThumbnailError::Generation)?;
+
+	let still_used = library_ctx
+		.db
+		.file_path()
+		.count(vec![file_path::cas_id::equals(Some(
+			old_cas_id.to_string(),
+		))])
+		.exec()
+		.await?
+		> 0;
+
+	if !still_used {
+		remove_thumbnail(&thumbnail_path(library_ctx, old_cas_id)).await?;
+	}
+
+	Ok(())
+}
+
+fn thumbnail_path(library_ctx: &LibraryContext, cas_id: &str) -> PathBuf {
+	library_ctx
+		.config()
+		.data_directory()
+		.join(THUMBNAIL_CACHE_DIR_NAME)
+		.join(cas_id)
+		.with_extension("webp")
+}
+
+/// Generates a thumbnail and records the outcome on its object, which is the work that's shared by coalesced requests.
+async fn generate_and_record(
+	library_ctx: LibraryContext,
+	object_id: i32,
+	cas_id: String,
+	extension: String,
+	path: PathBuf,
+) -> Result<PathBuf, String> {
+	let output_path = thumbnail_path(&library_ctx, &cas_id);
+
+	let result = generate_thumbnail(&extension, path, output_path.clone()).await;
+	if let Some(status) =
+		ThumbnailStatus::after(result.as_ref().err().map(|e| e as &(dyn Error + 'static)))
+	{
+		if let Err(e) = set_thumbnail_status(&library_ctx.db, object_id, status).await {
+			error!("Failed to record the thumbnail status of object {object_id}: {e:#?}");
+		}
+	}
+	if let Some(event) = thumbnail_event(&cas_id, &result) {
+		library_ctx.emit(event);
+	}
+	result.map_err(|e| e.to_string())?;
+
+	info!("Generated requested thumbnail for {cas_id}");
+
+	invalidate_query!(library_ctx, "locations.getExplorerData");
+
+	Ok(output_path)
+}
+
+/// Returns the event that announces the outcome of generating a thumbnail, so the UI can show it or stop waiting for it.
+///
+/// Files that thumbnails aren't generated for (anything but images and videos) don't get an event.
+fn thumbnail_event(cas_id: &str, result: &Result<(), ThumbnailError>) -> Option<CoreEvent> {
+	let cas_id = cas_id.to_string();
+	match result {
+		Ok(()) => Some(CoreEvent::NewThumbnail { cas_id }),
+		Err(ThumbnailError::UnsupportedExtension(_)) => None,
+		Err(_) => Some(CoreEvent::ThumbnailFailed { cas_id }),
+	}
+}
+
+fn can_generate_thumbnail(extension: &str) -> bool {
+	if let Ok(image_extension) = ImageExtension::from_str(extension) {
+		if can_generate_thumbnail_for_image(&image_extension) {
+			return true;
+		}
+	}
+
+	#[cfg(feature = "ffmpeg")]
+	{
+		use super::can_generate_thumbnail_for_video;
+		use sd_file_ext::extensions::VideoExtension;
+
+		if let Ok(video_extension) = VideoExtension::from_str(extension) {
+			return can_generate_thumbnail_for_video(&video_extension);
+		}
+	}
+
+	false
+}
+
+async fn remove_thumbnail(path: &Path) -> io::Result<()> {
+	match fs::remove_file(path).await {
+		Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+		_ => Ok(()),
+	}
+}
+
+/// Thumbnails are generated into a temporary file that's renamed over `output_path`, so a half-written thumbnail is
+/// never served, and a failed generation leaves whatever was at `output_path` as it was.
+async fn generate_thumbnail(
+	extension: &str,
+	path: PathBuf,
+	output_path: PathBuf,
+) -> Result<(), ThumbnailError> {
+	if let Some(parent) = output_path.parent() {
+		fs::create_dir_all(parent).await?;
+	}
+
+	let temp_path = output_path.with_extension("tmp.webp");
+	match generate_thumbnail_at(extension, path, temp_path.clone()).await {
+		Ok(()) => Ok(fs::rename(&temp_path, &output_path).await?),
+		Err(e) => {
+			remove_thumbnail(&temp_path).await.ok();
+			Err(e)
+		}
+	}
+}
+
+async fn generate_thumbnail_at(
+	extension: &str,
+	path: PathBuf,
+	output_path: PathBuf,
+) -> Result<(), ThumbnailError> {
+	if let Ok(image_extension) = ImageExtension::from_str(extension) {
+		if can_generate_thumbnail_for_image(&image_extension) {
+			return generate_image_thumbnail(path, output_path)
+				.await
+				.map_err(into_thumbnail_error);
+		}
+	}
+
+	#[cfg(feature = "ffmpeg")]
+	{
+		use super::{can_generate_thumbnail_for_video, generate_video_thumbnail};
+		use sd_file_ext::extensions::VideoExtension;
+
+		if let Ok(video_extension) = VideoExtension::from_str(extension) {
+			if can_generate_thumbnail_for_video(&video_extension) {
+				return generate_video_thumbnail(path, output_path)
+					.await
+					.map_err(into_thumbnail_error);
+			}
+		}
+	}
+
+	Err(ThumbnailError::UnsupportedExtension(extension.to_string()))
+}
+
+/// Keeps whether the file couldn't be decoded, which decides the status that's recorded for its object.
+fn into_thumbnail_error(e: Box<dyn Error>) -> ThumbnailError {
+	match e.downcast::<ThumbnailError>() {
+		Ok(e) => *e,
+		Err(e) if is_decode_error(e.as_ref()) => ThumbnailError::Undecodable(e.to_string()),
+		Err(e) => ThumbnailError::Generation(e.to_string()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::{
+		sync::atomic::{AtomicUsize, Ordering},
+		time::Duration,
+	};
+
+	#[tokio::test]
+	async fn concurrent_requests_are_coalesced() {
+		let requests = Arc::new(ThumbnailRequests::default());
+		let generations = Arc::new(AtomicUsize::new(0));
+
+		let handles = (0..10)
+			.map(|_| {
+				let requests = Arc::clone(&requests);
+				let generations = Arc::clone(&generations);
+
+				tokio::spawn(async move {
+					requests
+						.run("cas_id".to_string(), move || async move {
+							generations.fetch_add(1, Ordering::SeqCst);
+							tokio::time::sleep(Duration::from_millis(50)).await;
+							Ok(PathBuf::from("thumbnails/cas_id.webp"))
+						})
+						.await
+				})
+			})
+			.collect::<Vec<_>>();
+
+		for handle in handles {
+			assert_eq!(
+				handle.await.unwrap(),
+				Ok(PathBuf::from("thumbnails/cas_id.webp"))
+			);
+		}
+
+		assert_eq!(generations.load(Ordering::SeqCst), 1);
+
+		// once finished, a new request does the work again
+		requests
+			.run("cas_id".to_string(), || async {
+				Ok(PathBuf::from("thumbnails/cas_id.webp"))
+			})
+			.await
+			.unwrap();
+		assert!(requests.in_flight.lock().unwrap().is_empty());
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn modified_image_replaces_thumbnail() {
+		use image::{Rgb, RgbImage};
+
+		let dir = tempfile::tempdir().unwrap();
+		let (image_path, output_path) = (
+			dir.path().join("photo.png"),
+			dir.path().join("thumbnails").join("cas_id.webp"),
+		);
+
+		RgbImage::from_pixel(64, 64, Rgb([200, 30, 30]))
+			.save(&image_path)
+			.unwrap();
+		generate_thumbnail("png", image_path.clone(), output_path.clone())
+			.await
+			.unwrap();
+		let old_thumbnail = fs::read(&output_path).await.unwrap();
+
+		RgbImage::from_fn(64, 64, |x, _| Rgb([0, (x * 4) as u8, 255]))
+			.save(&image_path)
+			.unwrap();
+		generate_thumbnail("png", image_path.clone(), output_path.clone())
+			.await
+			.unwrap();
+
+		assert_ne!(fs::read(&output_path).await.unwrap(), old_thumbnail);
+		assert!(!output_path.with_extension("tmp.webp").exists());
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn failed_generation_keeps_thumbnail() {
+		let dir = tempfile::tempdir().unwrap();
+		let (image_path, output_path) =
+			(dir.path().join("photo.png"), dir.path().join("cas_id.webp"));
+
+		fs::write(&output_path, b"old thumbnail").await.unwrap();
+		fs::write(&image_path, b"\x89PNG\r\n\x1a\nnot really")
+			.await
+			.unwrap();
+
+		assert!(generate_thumbnail("png", image_path, output_path.clone())
+			.await
+			.is_err());
+		assert_eq!(fs::read(&output_path).await.unwrap(), b"old thumbnail");
+		assert!(!output_path.with_extension("tmp.webp").exists());
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn generation_outcome_is_announced() {
+		use image::{Rgb, RgbImage};
+
+		let dir = tempfile::tempdir().unwrap();
+		let output_path = dir.path().join("cas_id.webp");
+
+		let image_path = dir.path().join("photo.png");
+		RgbImage::from_pixel(64, 64, Rgb([200, 30, 30]))
+			.save(&image_path)
+			.unwrap();
+		let result = generate_thumbnail("png", image_path, output_path.clone()).await;
+		assert!(matches!(
+			thumbnail_event("cas_id", &result),
+			Some(CoreEvent::NewThumbnail { cas_id }) if cas_id == "cas_id"
+		));
+
+		let broken_path = dir.path().join("broken.png");
+		fs::write(&broken_path, b"\x89PNG\r\n\x1a\nnot really")
+			.await
+			.unwrap();
+		let result = generate_thumbnail("png", broken_path, output_path.clone()).await;
+		assert!(matches!(
+			thumbnail_event("cas_id", &result),
+			Some(CoreEvent::ThumbnailFailed { cas_id }) if cas_id == "cas_id"
+		));
+
+		let text_path = dir.path().join("notes.txt");
+		fs::write(&text_path, b"hello").await.unwrap();
+		let result = generate_thumbnail("txt", text_path, output_path).await;
+		assert!(thumbnail_event("cas_id", &result).is_none());
+	}
+
+	#[test]
+	fn thumbnails_are_only_generated_for_supported_extensions() {
+		assert!(can_generate_thumbnail("png"));
+		assert!(!can_generate_thumbnail("txt"));
+		assert!(!can_generate_thumbnail(""));
+	}
+}
@@ -1,24 +1,31 @@
 use crate::{
 	api::CoreEvent,
 	invalidate_query,
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{
+		JobCategory, JobError, JobReportUpdate, JobResult, JobScope, JobState, StatefulJob,
+		WorkerContext,
+	},
 	library::LibraryContext,
-	prisma::{file_path, location},
+	prisma::{file_path, location, object, PrismaClient},
 };
 
 use std::{
 	collections::VecDeque,
 	error::Error,
 	ops::Deref,
+	panic::{self, AssertUnwindSafe},
 	path::{Path, PathBuf},
 };
 
 use image::{self, imageops, DynamicImage, GenericImageView};
+use int_enum::IntEnum;
+use rspc::Type;
 use sd_file_ext::extensions::{Extension, ImageExtension, VideoExtension};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::{fs, task::block_in_place};
 use tracing::{error, info, trace, warn};
+use uuid::Uuid;
 use webp::Encoder;
 
 static THUMBNAIL_SIZE_FACTOR: f32 = 0.2;
@@ -47,6 +54,73 @@ pub enum ThumbnailError {
 	MissingLocation(i32),
 	#[error("Root file path not found: <path = '{0}'>")]
 	MissingRootFilePath(PathBuf),
+	#[error("Object not found or not identified yet: <id = '{0}'>")]
+	MissingObject(i32),
+	#[error("Thumbnails can't be generated for this extension: <extension = '{0}'>")]
+	UnsupportedExtension(String),
+	#[error("Failed to generate thumbnail: {0}")]
+	Generation(String),
+	#[error("Decoder panicked on malformed image: <path = '{0}'>")]
+	DecodeFailed(PathBuf),
+	#[error("File can't be decoded: {0}")]
+	Undecodable(String),
+	#[error("Database error: {0}")]
+	DatabaseError(#[from] prisma_client_rust::QueryError),
+	#[error("I/O error: {0}")]
+	IOError(#[from] std::io::Error),
+}
+
+/// Whether a thumbnail has been generated for an object, stored in `object.thumbnail_status`.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum)]
+pub enum ThumbnailStatus {
+	Pending = 0,
+	Generated = 1,
+	// the file couldn't be decoded (e.g. it's corrupt or malformed)
+	Failed = 2,
+}
+
+impl ThumbnailStatus {
+	/// The status an attempt at generating a thumbnail leaves its object in, given the error it failed with (if any).
+	///
+	/// An object is only marked as failed if its file can't be decoded, which won't change until the file does. Other
+	/// errors (like failing to read the file, or to write the thumbnail) may not happen again, so the object is left as
+	/// it was, to be tried again.
+	pub fn after(error: Option<&(dyn Error + 'static)>) -> Option<Self> {
+		match error {
+			None => Some(Self::Generated),
+			Some(e) if is_decode_error(e) => Some(Self::Failed),
+			Some(_) => None,
+		}
+	}
+}
+
+/// Whether an error means the file itself can't be made into a thumbnail, because it's corrupt, malformed or in a
+/// format that isn't supported.
+pub(crate) fn is_decode_error(e: &(dyn Error + 'static)) -> bool {
+	if let Some(e) = e.downcast_ref::<image::ImageError>() {
+		return !matches!(e, image::ImageError::IoError(_));
+	}
+
+	matches!(
+		e.downcast_ref::<ThumbnailError>(),
+		Some(ThumbnailError::DecodeFailed(_) | ThumbnailError::Undecodable(_))
+	)
+}
+
+pub async fn set_thumbnail_status(
+	db: &PrismaClient,
+	object_id: i32,
+	status: ThumbnailStatus,
+) -> Result<(), prisma_client_rust::QueryError> {
+	db.object()
+		.update(
+			object::id::equals(object_id),
+			vec![object::thumbnail_status::set(status.int_value())],
+		)
+		.exec()
+		.await
+		.map(|_| ())
 }
 
 file_path::include!(file_path_with_object { object });
@@ -71,10 +145,20 @@ impl StatefulJob for ThumbnailJob {
 	type Data = ThumbnailJobState;
 	type Step = ThumbnailJobStep;
 
+	const CATEGORY: JobCategory = JobCategory::Thumbnails;
+
 	fn name(&self) -> &'static str {
 		THUMBNAIL_JOB_NAME
 	}
 
+	fn scope(library_id: Uuid, init: &Self::Init) -> Option<JobScope> {
+		Some(JobScope {
+			library_id,
+			location_id: init.location_id,
+			path: init.root_path.clone(),
+		})
+	}
+
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		let LibraryContext { db, .. } = &ctx.library_ctx;
 
@@ -219,9 +303,24 @@ impl StatefulJob for ThumbnailJob {
 
 			match step.kind {
 				ThumbnailJobStepKind::Image => {
-					if let Err(e) = generate_image_thumbnail(&path, &output_path).await {
+					let result = generate_image_thumbnail(&path, &output_path).await;
+					if let Err(e) = &result {
 						error!("Error generating thumb for image {:#?}", e);
 					}
+
+					if let Some(status) =
+						ThumbnailStatus::after(result.as_ref().err().map(AsRef::as_ref))
+					{
+						// the thumbnail is still there if it was written, so this isn't worth failing the job over
+						if let Err(e) =
+							set_thumbnail_status(&ctx.library_ctx.db, step.object_id, status).await
+						{
+							error!(
+								"Failed to record the thumbnail status of object {}: {e:#?}",
+								step.object_id
+							);
+						}
+					}
 				}
 				#[cfg(feature = "ffmpeg")]
 				ThumbnailJobStepKind::Video => {
@@ -307,32 +406,47 @@ pub async fn generate_image_thumbnail<P: AsRef<Path>>(
 	output_path: P,
 ) -> Result<(), Box<dyn Error>> {
 	// Webp creation has blocking code
-	let webp = block_in_place(|| -> Result<Vec<u8>, Box<dyn Error>> {
-		// Using `image` crate, open the included .jpg file
-		let img = image::open(file_path)?;
-		let (w, h) = img.dimensions();
-		// Optionally, resize the existing photo and convert back into DynamicImage
-		let img = DynamicImage::ImageRgba8(imageops::resize(
-			&img,
-			// FIXME : Think of a better heuristic to get the thumbnail size
-			(w as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			(h as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			imageops::FilterType::Triangle,
-		));
-		// Create the WebP encoder for the above image
-		let encoder = Encoder::from_image(&img)?;
-
-		// Encode the image at a specified quality 0-100
-
-		// Type WebPMemory is !Send, which makes the Future in this function !Send,
-		// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
-		// which implies on a unwanted clone...
-		Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
+	let webp = block_in_place(|| {
+		catch_decoder_panic(file_path.as_ref(), || -> Result<Vec<u8>, Box<dyn Error>> {
+			// Using `image` crate, open the included .jpg file
+			let img = image::open(&file_path)?;
+			let (w, h) = img.dimensions();
+			// Optionally, resize the existing photo and convert back into DynamicImage
+			let img = DynamicImage::ImageRgba8(imageops::resize(
+				&img,
+				// FIXME : Think of a better heuristic to get the thumbnail size
+				(w as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
+				(h as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
+				imageops::FilterType::Triangle,
+			));
+			// Create the WebP encoder for the above image
+			let encoder = Encoder::from_image(&img)
+				.map_err(|e| ThumbnailError::Undecodable(e.to_string()))?;
+
+			// Encode the image at a specified quality 0-100
+
+			// Type WebPMemory is !Send, which makes the Future in this function !Send,
+			// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
+			// which implies on a unwanted clone...
+			Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
+		})
 	})?;
 
 	fs::write(output_path, &webp).await.map_err(Into::into)
 }
 
+/// Runs an image decoder, turning a panic into `ThumbnailError::DecodeFailed`.
+///
+/// Decoders can panic on crafted or corrupt files, and as thumbnails are generated for untrusted files
+/// (including ones from peers), a bad file mustn't take down the worker running the job.
+pub(crate) fn catch_decoder_panic<T>(
+	path: &Path,
+	decode: impl FnOnce() -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+	panic::catch_unwind(AssertUnwindSafe(decode))
+		.unwrap_or_else(|_| Err(ThumbnailError::DecodeFailed(path.to_path_buf()).into()))
+}
+
 #[cfg(feature = "ffmpeg")]
 pub async fn generate_video_thumbnail<P: AsRef<Path>>(
 	file_path: P,
@@ -383,3 +497,62 @@ pub fn can_generate_thumbnail_for_image(image_extension: &ImageExtension) -> boo
 	use ImageExtension::*;
 	matches!(image_extension, Jpg | Jpeg | Png | Webp | Gif)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decoder_panic_fails_thumbnail() {
+		let path = Path::new("malformed.png");
+
+		// stands in for a decoder that panics on a crafted file
+		let result = catch_decoder_panic(path, || -> Result<Vec<u8>, Box<dyn Error>> {
+			panic!("corrupt chunk length")
+		});
+
+		assert!(matches!(
+			result.as_ref().unwrap_err().downcast_ref::<ThumbnailError>(),
+			Some(ThumbnailError::DecodeFailed(p)) if p == path
+		));
+		assert_eq!(
+			ThumbnailStatus::after(result.as_ref().err().map(AsRef::as_ref)),
+			Some(ThumbnailStatus::Failed)
+		);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn malformed_image_fails_thumbnail() {
+		let dir = tempfile::tempdir().unwrap();
+		let (path, output_path) = (dir.path().join("bad.png"), dir.path().join("bad.webp"));
+		// a PNG signature followed by garbage
+		std::fs::write(
+			&path,
+			[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0xFF, 0xFF],
+		)
+		.unwrap();
+
+		let result = generate_image_thumbnail(&path, &output_path).await;
+
+		assert_eq!(
+			ThumbnailStatus::after(result.as_ref().err().map(AsRef::as_ref)),
+			Some(ThumbnailStatus::Failed)
+		);
+		assert!(!output_path.exists());
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn unreadable_image_stays_pending() {
+		let dir = tempfile::tempdir().unwrap();
+		let (path, output_path) = (dir.path().join("gone.png"), dir.path().join("gone.webp"));
+
+		let result = generate_image_thumbnail(&path, &output_path).await;
+
+		// the file may well be readable next time, so nothing is recorded
+		assert!(result.is_err());
+		assert_eq!(
+			ThumbnailStatus::after(result.as_ref().err().map(AsRef::as_ref)),
+			None
+		);
+	}
+}
@@ -0,0 +1,471 @@
+use crate::{
+	library::LibraryContext,
+	location::{
+		file_path_helper::{get_max_file_path_id, set_max_file_path_id},
+		LocationError, LocationManagerError,
+	},
+	object::{
+		cas::generate_cas_id,
+		fs::relocate::{available_name, join_extension, move_file},
+	},
+	prisma::{file_path, location, object, PrismaClient},
+	sync,
+};
+
+use std::path::{Path, PathBuf};
+
+use int_enum::IntEnum;
+use prisma_client_rust::{Direction, QueryError};
+use rspc::{ErrorCode, Type};
+use sd_file_ext::{kind::ObjectKind, magic::read_header, mime};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tokio::{fs, io};
+use tracing::info;
+use uuid::Uuid;
+
+pub static QUARANTINE_DIR_NAME: &str = "quarantine";
+
+#[derive(Error, Debug)]
+pub enum QuarantineError {
+	#[error("Object isn't waiting to be reviewed (id: {0})")]
+	NotPending(i32),
+	#[error("Location error")]
+	LocationError(#[from] LocationError),
+	#[error("Location manager error")]
+	LocationManagerError(#[from] LocationManagerError),
+	#[error("I/O error (error: {0:?})")]
+	IOError(#[from] io::Error),
+	#[error("Database error (error: {0:?})")]
+	DatabaseError(#[from] QueryError),
+}
+
+impl From<QuarantineError> for rspc::Error {
+	fn from(err: QuarantineError) -> Self {
+		match err {
+			QuarantineError::LocationError(e) => e.into(),
+			QuarantineError::NotPending(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// What to do with a file that a peer sent, once the user has reviewed it.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+	/// Move the file into the root of a location, where it becomes a normal part of the library.
+	Accept { location_id: i32 },
+	/// Delete the file and its object.
+	Reject,
+}
+
+/// Puts a file that was received from a peer in quarantine, where it waits for the user to review it.
+///
+/// The file is moved out of `received` into the library's quarantine directory, and gets an object flagged
+/// `pending_review`. Those objects aren't in the normal listings, and aren't synced until they're accepted.
+pub async fn quarantine_received(
+	library: &LibraryContext,
+	received: impl AsRef<Path>,
+	file_name: &str,
+) -> Result<object::Data, QuarantineError> {
+	let pub_id = Uuid::new_v4();
+	let (name, extension) = split_file_name(file_name);
+
+	let quarantined = store(&quarantine_dir(library), received.as_ref(), pub_id).await?;
+	let size = fs::metadata(&quarantined).await?.len();
+
+	// the quarantined file has no extension, so the one the peer named it with is used
+	let header = read_header(&quarantined).await?;
+	let kind = ObjectKind::from_extension(extension)
+		.or_else(|| ObjectKind::from_magic_bytes(&header))
+		.unwrap_or(ObjectKind::Unknown);
+	let mime = mime::identify(Some(extension), &header);
+
+	info!(
+		"Quarantined {file_name} ({size} bytes) at {}",
+		quarantined.display()
+	);
+
+	Ok(library
+		.db
+		.object()
+		.create(
+			pub_id.as_bytes().to_vec(),
+			vec![
+				object::name::set(Some(name.to_string())),
+				object::extension::set(Some(extension.to_string())),
+				object::kind::set(kind.int_value()),
+				object::mime::set(Some(mime.to_string())),
+				object::size_in_bytes::set(size.to_string()),
+				object::pending_review::set(true),
+			],
+		)
+		.exec()
+		.await?)
+}
+
+/// Lists the objects that are waiting to be reviewed, most recently received first.
+pub async fn list_quarantine(db: &PrismaClient) -> Result<Vec<object::Data>, QueryError> {
+	db.object()
+		.find_many(vec![object::pending_review::equals(true)])
+		.order_by(object::date_indexed::order(Direction::Desc))
+		.exec()
+		.await
+}
+
+/// Either moves a quarantined file into the library, or deletes it.
+pub async fn review(
+	library: &LibraryContext,
+	object_id: i32,
+	decision: Decision,
+) -> Result<(), QuarantineError> {
+	let LibraryContext { db, sync, .. } = library;
+
+	let object = db
+		.object()
+		.find_first(vec![
+			object::id::equals(object_id),
+			object::pending_review::equals(true),
+		])
+		.exec()
+		.await?
+		.ok_or(QuarantineError::NotPending(object_id))?;
+
+	let pub_id = Uuid::from_slice(&object.pub_id).unwrap();
+	let quarantined = quarantine_dir(library).join(quarantined_file_name(pub_id));
+
+	let location_id = match decision {
+		Decision::Accept { location_id } => location_id,
+		Decision::Reject => {
+			match fs::remove_file(&quarantined).await {
+				Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+				_ => {}
+			}
+
+			// pending objects were never synced, so there's nothing to tell the other nodes
+			db.object()
+				.delete(object::id::equals(object_id))
+				.exec()
+				.await?;
+
+			return Ok(());
+		}
+	};
+
+	let location = db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+	let location_path = PathBuf::from(&location.path);
+
+	let extension = object.extension.clone().unwrap_or_default();
+	let name = available_name(
+		&location_path,
+		object.name.as_deref().unwrap_or("Received"),
+		&extension,
+	);
+	let file_name = join_extension(&name, &extension);
+	let dst_path = location_path.join(&file_name);
+
+	let _guard = library
+		.location_manager()
+		.temporary_ignore_events_for_path(location_id, library.clone(), &dst_path)
+		.await?;
+
+	release(&quarantined, &dst_path).await?;
+
+	let root = db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(location_id),
+			file_path::materialized_path::equals("/".to_string()),
+		])
+		.exec()
+		.await?;
+	let parent_id = root.map(|root| root.id);
+
+	let size = fs::metadata(&dst_path).await?.len();
+	let cas_id = generate_cas_id(&dst_path, size).await?;
+
+	// accepted objects are shared with the library's other nodes, like any other object, and it has to exist there
+	// before a file path can point at it
+	let sync_id = || sync::object::SyncId {
+		pub_id: object.pub_id.clone(),
+	};
+	sync.write_ops(
+		db,
+		(
+			[sync.shared_create(sync_id())]
+				.into_iter()
+				.chain(
+					[
+						("date_created", json!(object.date_created)),
+						("kind", json!(object.kind)),
+						("mime", json!(object.mime)),
+						("size_in_bytes", json!(object.size_in_bytes)),
+					]
+					.into_iter()
+					.map(|(f, v)| sync.shared_update(sync_id(), f, v)),
+				)
+				.collect(),
+			db.object().update(
+				object::id::equals(object_id),
+				vec![object::pending_review::set(false)],
+			),
+		),
+	)
+	.await?;
+
+	let file_path_id = get_max_file_path_id(library).await? + 1;
+	let file_path_sync_id = || sync::file_path::SyncId {
+		id: file_path_id,
+		location: sync::location::SyncId {
+			pub_id: location.pub_id.clone(),
+		},
+	};
+	sync.write_ops(
+		db,
+		(
+			vec![
+				sync.unique_shared_create(
+					file_path_sync_id(),
+					[
+						("materialized_path", json!(file_name)),
+						("name", json!(name)),
+						("is_dir", json!(false)),
+						("extension", json!(extension)),
+						("parent_id", json!(parent_id)),
+						("cas_id", json!(cas_id)),
+					],
+				),
+				sync.shared_update(file_path_sync_id(), "object", json!({ "pub_id": pub_id })),
+			],
+			db.file_path().create(
+				file_path_id,
+				location::id::equals(location_id),
+				file_name,
+				name,
+				extension,
+				vec![
+					file_path::parent_id::set(parent_id),
+					file_path::cas_id::set(Some(cas_id)),
+					file_path::object::connect(object::id::equals(object_id)),
+				],
+			),
+		),
+	)
+	.await?;
+	set_max_file_path_id(file_path_id);
+
+	info!("Accepted {} into {}", object_id, dst_path.display());
+
+	Ok(())
+}
+
+fn quarantine_dir(library: &LibraryContext) -> PathBuf {
+	library
+		.config()
+		.data_directory()
+		.join(QUARANTINE_DIR_NAME)
+		.join(library.id.to_string())
+}
+
+/// Quarantined files are named after their object, so nothing the peer chose ends up in the path.
+fn quarantined_file_name(pub_id: Uuid) -> String {
+	pub_id.simple().to_string()
+}
+
+/// Splits a name the peer sent into a name and an extension, ignoring any directories that came with it.
+fn split_file_name(file_name: &str) -> (&str, &str) {
+	let file_name = Path::new(file_name)
+		.file_name()
+		.and_then(|name| name.to_str())
+		.unwrap_or("Received");
+
+	match Path::new(file_name)
+		.extension()
+		.and_then(|ext| ext.to_str())
+	{
+		Some(extension) => (
+			&file_name[..file_name.len() - extension.len() - 1],
+			extension,
+		),
+		None => (file_name, ""),
+	}
+}
+
+async fn store(quarantine_dir: &Path, received: &Path, pub_id: Uuid) -> io::Result<PathBuf> {
+	fs::create_dir_all(quarantine_dir).await?;
+
+	let quarantined = quarantine_dir.join(quarantined_file_name(pub_id));
+	move_file(received, &quarantined).await?;
+
+	Ok(quarantined)
+}
+
+async fn release(quarantined: &Path, dst_path: &Path) -> io::Result<()> {
+	move_file(quarantined, dst_path).await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		prisma::{node, shared_operation},
+		Node,
+	};
+
+	const PNG: [u8; 9] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00];
+
+	/// Quarantines a png sent by a peer as `photo.png`.
+	async fn receive_photo(library: &LibraryContext) -> object::Data {
+		let received = tempfile::tempdir().unwrap();
+		let received_path = received.path().join("upload");
+		fs::write(&received_path, PNG).await.unwrap();
+
+		quarantine_received(library, &received_path, "photo.png")
+			.await
+			.unwrap()
+	}
+
+	async fn shared_creates(db: &PrismaClient) -> i64 {
+		db.shared_operation()
+			.count(vec![shared_operation::kind::equals("c".to_string())])
+			.exec()
+			.await
+			.unwrap()
+	}
+
+	#[test]
+	fn file_names_are_split() {
+		assert_eq!(split_file_name("photo.jpg"), ("photo", "jpg"));
+		assert_eq!(split_file_name("archive.tar.gz"), ("archive.tar", "gz"));
+		assert_eq!(split_file_name("README"), ("README", ""));
+		assert_eq!(split_file_name(".bashrc"), (".bashrc", ""));
+		assert_eq!(split_file_name("../../.ssh/id.pub"), ("id", "pub"));
+		assert_eq!(split_file_name(".."), ("Received", ""));
+	}
+
+	#[tokio::test]
+	async fn stored_and_released() {
+		let received = tempfile::tempdir().unwrap();
+		let data = tempfile::tempdir().unwrap();
+		let location = tempfile::tempdir().unwrap();
+
+		let received_path = received.path().join("upload");
+		fs::write(&received_path, PNG).await.unwrap();
+
+		let pub_id = Uuid::new_v4();
+		let quarantine_dir = data.path().join(QUARANTINE_DIR_NAME);
+		let quarantined = store(&quarantine_dir, &received_path, pub_id)
+			.await
+			.unwrap();
+
+		assert!(!received_path.exists());
+		assert_eq!(quarantined.parent().unwrap(), quarantine_dir);
+		assert_eq!(
+			quarantined.file_name().unwrap().to_str().unwrap(),
+			pub_id.simple().to_string()
+		);
+		assert_eq!(read_header(&quarantined).await.unwrap(), PNG);
+
+		let dst_path = location.path().join("photo.png");
+		release(&quarantined, &dst_path).await.unwrap();
+
+		assert!(!quarantined.exists());
+		assert_eq!(fs::read(&dst_path).await.unwrap(), PNG);
+	}
+
+	#[tokio::test]
+	async fn accepted_into_a_location() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+		let db = &library.db;
+
+		let dir = tempfile::tempdir().unwrap();
+		let location = db
+			.location()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				"Inbox".to_string(),
+				dir.path().to_str().unwrap().to_string(),
+				node::id::equals(library.node_local_id),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		let object = receive_photo(&library).await;
+		assert!(object.pending_review);
+		assert_eq!(object.kind, ObjectKind::Image.int_value());
+		let creates_before = shared_creates(db).await;
+
+		review(
+			&library,
+			object.id,
+			Decision::Accept {
+				location_id: location.id,
+			},
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(fs::read(dir.path().join("photo.png")).await.unwrap(), PNG);
+		assert!(list_quarantine(db).await.unwrap().is_empty());
+
+		let file_path = db
+			.file_path()
+			.find_first(vec![file_path::location_id::equals(location.id)])
+			.exec()
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(file_path.materialized_path, "photo.png");
+		assert_eq!(file_path.object_id, Some(object.id));
+		assert!(file_path.cas_id.is_some());
+
+		// both the object and its file path are created on the other nodes
+		assert_eq!(shared_creates(db).await - creates_before, 2);
+
+		// it's been reviewed, so it can't be reviewed again
+		assert!(matches!(
+			review(&library, object.id, Decision::Reject).await,
+			Err(QuarantineError::NotPending(id)) if id == object.id
+		));
+	}
+
+	#[tokio::test]
+	async fn rejected_and_deleted() {
+		let data_dir = tempfile::tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+		let db = &library.db;
+
+		let object = receive_photo(&library).await;
+		let quarantined = quarantine_dir(&library).join(quarantined_file_name(
+			Uuid::from_slice(&object.pub_id).unwrap(),
+		));
+		assert!(quarantined.exists());
+		let creates_before = shared_creates(db).await;
+
+		review(&library, object.id, Decision::Reject).await.unwrap();
+
+		assert!(!quarantined.exists());
+		assert!(db
+			.object()
+			.find_unique(object::id::equals(object.id))
+			.exec()
+			.await
+			.unwrap()
+			.is_none());
+		assert_eq!(shared_creates(db).await, creates_before);
+	}
+}
@@ -0,0 +1,238 @@
+use crate::{
+	invalidate_query,
+	library::LibraryContext,
+	prisma::{object, PrismaClient},
+};
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::{or, Direction, QueryError};
+use thiserror::Error;
+use tokio::time::sleep;
+use tracing::error;
+
+/// Opening an object again within this window doesn't write its access time straight away.
+const ACCESS_DEBOUNCE: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum RecentsError {
+	#[error("Invalid limit for the recents list: {0}")]
+	InvalidLimit(i32),
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+}
+
+impl From<RecentsError> for rspc::Error {
+	fn from(e: RecentsError) -> Self {
+		let code = match e {
+			RecentsError::InvalidLimit(_) => rspc::ErrorCode::BadRequest,
+			RecentsError::Database(_) => rspc::ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, e.to_string(), e)
+	}
+}
+
+/// The accesses made within the debounce window of an object's last written access, which are
+/// coalesced into a single write of the latest one once the window is over.
+pub struct RecentAccesses {
+	window: Duration,
+	pending: Mutex<HashMap<i32, DateTime<Utc>>>,
+}
+
+impl RecentAccesses {
+	pub fn new(window: Duration) -> Self {
+		Self {
+			window,
+			pending: Mutex::new(HashMap::new()),
+		}
+	}
+}
+
+impl Default for RecentAccesses {
+	fn default() -> Self {
+		Self::new(ACCESS_DEBOUNCE)
+	}
+}
+
+/// Records that an object was opened, so it shows up in the recents list.
+///
+/// The first access is written straight away, by a single `UPDATE` which skips objects that were
+/// already accessed within the debounce window. The accesses it skips are held back, and only the
+/// latest of them is written once the window is over, so rapidly re-opening a file doesn't write
+/// to the database every time, while the most recent access still ends up in the list.
+pub async fn record_access(library: &LibraryContext, id: i32) -> Result<(), QueryError> {
+	let now = Utc::now();
+	let recents = &library.recents;
+
+	// A write is already scheduled for this object, it just has to carry this access instead
+	if let Some(latest) = recents.pending.lock().unwrap().get_mut(&id) {
+		*latest = now;
+		return Ok(());
+	}
+
+	let window =
+		chrono::Duration::from_std(recents.window).expect("the window fits a chrono duration");
+	let updated = library
+		.db
+		.object()
+		.update_many(
+			vec![
+				object::id::equals(id),
+				or![
+					object::date_accessed::equals(None),
+					object::date_accessed::lt((now - window).into()),
+				],
+			],
+			vec![object::date_accessed::set(Some(now.into()))],
+		)
+		.exec()
+		.await?;
+
+	if updated == 0 && recents.pending.lock().unwrap().insert(id, now).is_none() {
+		let library = library.clone();
+		tokio::spawn(async move {
+			sleep(library.recents.window).await;
+
+			let Some(latest) = library.recents.pending.lock().unwrap().remove(&id) else {
+				return;
+			};
+
+			if let Err(e) = write_access(&library.db, id, latest).await {
+				error!("Failed to write the access time of object {id}: {e:#?}");
+			}
+
+			invalidate_query!(library, "files.listRecents");
+		});
+	}
+
+	Ok(())
+}
+
+async fn write_access(db: &PrismaClient, id: i32, at: DateTime<Utc>) -> Result<(), QueryError> {
+	db.object()
+		.update_many(
+			vec![object::id::equals(id)],
+			vec![object::date_accessed::set(Some(at.into()))],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// Lists the objects that were opened through Spacedrive, most recently accessed first.
+///
+/// The access time lives on the object itself, so each object shows up only once.
+pub async fn list_recents(
+	db: &PrismaClient,
+	limit: i32,
+) -> Result<Vec<object::Data>, RecentsError> {
+	if limit < 0 {
+		return Err(RecentsError::InvalidLimit(limit));
+	}
+
+	Ok(db
+		.object()
+		.find_many(vec![
+			object::date_accessed::not(None),
+			object::pending_review::equals(false),
+			object::date_deleted::equals(None),
+		])
+		.order_by(object::date_accessed::order(Direction::Desc))
+		.take(limit.into())
+		.exec()
+		.await?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		library::{create_test_object, test_library},
+		Node,
+	};
+
+	use std::sync::Arc;
+
+	const TEST_WINDOW: Duration = Duration::from_millis(200);
+
+	/// A test library which coalesces accesses over `TEST_WINDOW`, so the tests don't wait for the real one.
+	async fn windowed_library() -> (tempfile::TempDir, Arc<Node>, LibraryContext) {
+		let (data_dir, node, mut library) = test_library().await;
+		library.recents = Arc::new(RecentAccesses::new(TEST_WINDOW));
+
+		(data_dir, node, library)
+	}
+
+	async fn accessed_at(library: &LibraryContext, id: i32) -> Option<DateTime<Utc>> {
+		library
+			.db
+			.object()
+			.find_unique(object::id::equals(id))
+			.exec()
+			.await
+			.unwrap()
+			.unwrap()
+			.date_accessed
+			.map(Into::into)
+	}
+
+	#[tokio::test]
+	async fn first_access_is_written_straight_away() {
+		let (_data_dir, _node, library) = windowed_library().await;
+		let id = create_test_object(&library.db, vec![]).await;
+
+		// The database only keeps milliseconds
+		let before = Utc::now() - chrono::Duration::milliseconds(1);
+		record_access(&library, id).await.unwrap();
+
+		assert!(accessed_at(&library, id).await.unwrap() >= before);
+	}
+
+	#[tokio::test]
+	async fn accesses_within_the_window_coalesce_into_the_latest() {
+		let (_data_dir, _node, library) = windowed_library().await;
+		let id = create_test_object(&library.db, vec![]).await;
+
+		record_access(&library, id).await.unwrap();
+		let first = accessed_at(&library, id).await.unwrap();
+
+		record_access(&library, id).await.unwrap();
+		sleep(TEST_WINDOW / 4).await;
+		let last_access = Utc::now() - chrono::Duration::milliseconds(1);
+		record_access(&library, id).await.unwrap();
+
+		// Held back until the window is over
+		assert_eq!(accessed_at(&library, id).await.unwrap(), first);
+
+		sleep(TEST_WINDOW * 2).await;
+		assert!(accessed_at(&library, id).await.unwrap() >= last_access);
+	}
+
+	#[tokio::test]
+	async fn recents_are_listed_most_recent_first() {
+		let (_data_dir, _node, library) = windowed_library().await;
+		let older = create_test_object(&library.db, vec![]).await;
+		let newer = create_test_object(&library.db, vec![]).await;
+
+		record_access(&library, older).await.unwrap();
+		record_access(&library, newer).await.unwrap();
+		// Opening the older one again within its window still moves it to the top eventually
+		record_access(&library, older).await.unwrap();
+		sleep(TEST_WINDOW * 2).await;
+
+		let recents = list_recents(&library.db, 10).await.unwrap();
+		assert_eq!(
+			recents.iter().map(|object| object.id).collect::<Vec<_>>(),
+			[older, newer]
+		);
+
+		assert!(matches!(
+			list_recents(&library.db, -1).await,
+			Err(RecentsError::InvalidLimit(-1))
+		));
+	}
+}
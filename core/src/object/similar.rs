@@ -0,0 +1,231 @@
+use crate::{
+	object::preview::catch_decoder_panic,
+	prisma::{object, PrismaClient},
+};
+
+use std::{error::Error, path::Path};
+
+use image::{imageops::FilterType, DynamicImage};
+use prisma_client_rust::QueryError;
+use rspc::Type;
+use serde::Serialize;
+use tokio::task::block_in_place;
+
+/// The image is shrunk to one more column than row, so every row gives 8 comparisons between neighbours.
+const DHASH_WIDTH: u32 = 9;
+const DHASH_HEIGHT: u32 = 8;
+
+#[derive(Serialize, Type, Debug)]
+pub struct SimilarObject {
+	pub object: object::Data,
+	/// The number of bits that differ between the perceptual hashes, where 0 is visually identical.
+	pub distance: u32,
+}
+
+/// Computes the difference hash (dHash) of an image.
+///
+/// Every bit records whether a pixel is brighter than its right neighbour in a tiny grayscale copy of the image, so
+/// the hash survives resizing and re-encoding, and visually similar images only differ in a few bits.
+pub fn dhash(image: &DynamicImage) -> u64 {
+	let gray = image
+		.resize_exact(DHASH_WIDTH, DHASH_HEIGHT, FilterType::Triangle)
+		.into_luma8();
+
+	(0..DHASH_HEIGHT)
+		.flat_map(|y| (0..DHASH_WIDTH - 1).map(move |x| (x, y)))
+		.fold(0, |hash, (x, y)| {
+			let brighter = gray.get_pixel(x, y).0[0] > gray.get_pixel(x + 1, y).0[0];
+			(hash << 1) | brighter as u64
+		})
+}
+
+/// Decodes the image at `path` and computes its perceptual hash, as it's stored on the object.
+///
+/// Decoding the whole image is expensive, which is why this is opt-in for each location.
+pub fn perceptual_hash(path: impl AsRef<Path>) -> Result<Vec<u8>, Box<dyn Error>> {
+	let path = path.as_ref();
+
+	block_in_place(|| {
+		catch_decoder_panic(path, || {
+			Ok(dhash(&image::open(path)?).to_be_bytes().to_vec())
+		})
+	})
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+	(a ^ b).count_ones()
+}
+
+fn hash_from_db(bytes: &[u8]) -> Option<u64> {
+	Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Finds the images that look like an object, closest first.
+///
+/// This is a linear scan over every hashed object, which is fine for now as only 8 bytes are read for each.
+/// Objects without a perceptual hash have no similar objects.
+pub async fn find_similar(
+	db: &PrismaClient,
+	object_id: i32,
+	max_distance: u32,
+) -> Result<Vec<SimilarObject>, QueryError> {
+	let hash = db
+		.object()
+		.find_unique(object::id::equals(object_id))
+		.select(object::select!({ perceptual_hash }))
+		.exec()
+		.await?
+		.and_then(|object| hash_from_db(&object.perceptual_hash?));
+
+	let Some(hash) = hash else {
+		return Ok(vec![]);
+	};
+
+	let mut distances = db
+		.object()
+		.find_many(vec![
+			object::perceptual_hash::not(None),
+			object::id::not(object_id),
+			object::pending_review::equals(false),
+			object::date_deleted::equals(None),
+		])
+		.select(object::select!({ id perceptual_hash }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|object| {
+			let distance = hamming_distance(hash, hash_from_db(&object.perceptual_hash?)?);
+			(distance <= max_distance).then_some((object.id, distance))
+		})
+		.collect::<Vec<_>>();
+	distances.sort_by_key(|(_, distance)| *distance);
+
+	let mut objects = db
+		.object()
+		.find_many(vec![object::id::in_vec(
+			distances.iter().map(|(id, _)| *id).collect(),
+		)])
+		.exec()
+		.await?;
+
+	Ok(distances
+		.into_iter()
+		.filter_map(|(id, distance)| {
+			let index = objects.iter().position(|object| object.id == id)?;
+			Some(SimilarObject {
+				object: objects.swap_remove(index),
+				distance,
+			})
+		})
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::library::{create_test_object, test_library};
+
+	use image::{imageops, ImageBuffer, Rgb, RgbImage};
+
+	/// A diagonal gradient with a bright disc in one corner.
+	fn landscape() -> RgbImage {
+		ImageBuffer::from_fn(640, 480, |x, y| {
+			let (dx, dy) = (x as i32 - 480, y as i32 - 120);
+			if dx * dx + dy * dy < 80 * 80 {
+				Rgb([255, 240, 200])
+			} else {
+				let shade = ((x + y) * 255 / (640 + 480)) as u8;
+				Rgb([shade / 2, shade, 255 - shade])
+			}
+		})
+	}
+
+	/// A gradient that darkens from left to right, with a dark square - nothing like `landscape`.
+	fn night() -> RgbImage {
+		ImageBuffer::from_fn(640, 480, |x, y| {
+			if (80..240).contains(&x) && (240..400).contains(&y) {
+				Rgb([10, 10, 60])
+			} else {
+				let shade = (255 - x * 255 / 640) as u8;
+				Rgb([shade, shade / 4 * 3, shade / 2])
+			}
+		})
+	}
+
+	#[test]
+	fn resized_copy_is_similar() {
+		let original = dhash(&DynamicImage::ImageRgb8(landscape()));
+		let resized = dhash(&DynamicImage::ImageRgb8(imageops::resize(
+			&landscape(),
+			200,
+			150,
+			FilterType::Lanczos3,
+		)));
+		let unrelated = dhash(&DynamicImage::ImageRgb8(night()));
+
+		assert!(hamming_distance(original, resized) <= 4);
+		assert!(hamming_distance(original, unrelated) > 16);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn re_encoded_copy_is_similar() {
+		let dir = tempfile::tempdir().unwrap();
+		let (png, jpg) = (dir.path().join("photo.png"), dir.path().join("photo.jpg"));
+		landscape().save(&png).unwrap();
+		landscape().save(&jpg).unwrap();
+
+		let png_hash = hash_from_db(&perceptual_hash(&png).unwrap()).unwrap();
+		let jpg_hash = hash_from_db(&perceptual_hash(&jpg).unwrap()).unwrap();
+
+		assert!(hamming_distance(png_hash, jpg_hash) <= 4);
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn corrupt_image_is_an_error() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("broken.png");
+		std::fs::write(&path, b"\x89PNG\r\n\x1a\nnot really").unwrap();
+
+		assert!(perceptual_hash(&path).is_err());
+	}
+
+	async fn create_hashed_object(
+		db: &PrismaClient,
+		hash: Option<u64>,
+		pending_review: bool,
+	) -> i32 {
+		create_test_object(
+			db,
+			vec![
+				object::perceptual_hash::set(hash.map(|hash| hash.to_be_bytes().to_vec())),
+				object::pending_review::set(pending_review),
+			],
+		)
+		.await
+	}
+
+	#[tokio::test]
+	async fn finds_similar_objects_closest_first() {
+		let (_data_dir, _node, library) = test_library().await;
+		let db = &library.db;
+
+		let original = create_hashed_object(db, Some(0xFF00), false).await;
+		let far = create_hashed_object(db, Some(0xFF0F), false).await;
+		let close = create_hashed_object(db, Some(0xFF01), false).await;
+		let _unrelated = create_hashed_object(db, Some(!0xFF00), false).await;
+		let _pending = create_hashed_object(db, Some(0xFF00), true).await;
+		let unhashed = create_hashed_object(db, None, false).await;
+
+		let similar = find_similar(db, original, 8).await.unwrap();
+		assert_eq!(
+			similar
+				.iter()
+				.map(|similar| (similar.object.id, similar.distance))
+				.collect::<Vec<_>>(),
+			[(close, 1), (far, 4)]
+		);
+
+		assert!(find_similar(db, unhashed, 64).await.unwrap().is_empty());
+	}
+}
@@ -1,10 +1,15 @@
-use prisma_client_rust::QueryError;
+use prisma_client_rust::{raw, PrismaValue, QueryError};
 use rspc::Type;
 use serde::Deserialize;
-
+use serde_json::json;
+use thiserror::Error;
 use uuid::Uuid;
 
-use crate::prisma::{tag, PrismaClient};
+use crate::{
+	library::LibraryContext,
+	prisma::{tag, PrismaClient},
+	sync,
+};
 
 #[derive(Type, Deserialize)]
 pub struct Tag {
@@ -34,3 +39,146 @@ impl Tag {
 		Ok(())
 	}
 }
+
+#[derive(Error, Debug)]
+pub enum TagError {
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+}
+
+impl From<TagError> for rspc::Error {
+	fn from(e: TagError) -> Self {
+		rspc::Error::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e)
+	}
+}
+
+/// Assigns a tag to an object, returning `false` if it was already assigned.
+///
+/// This is a single `INSERT OR IGNORE`, rather than a lookup followed by an insert, so two concurrent
+/// assignments can't both think they were the one to add the tag.
+pub async fn assign_tag(db: &PrismaClient, object_id: i32, tag_id: i32) -> Result<bool, TagError> {
+	let inserted = db
+		._execute_raw(raw!(
+			"INSERT OR IGNORE INTO tag_on_object (tag_id, object_id) VALUES ({}, {})",
+			PrismaValue::Int(tag_id as i64),
+			PrismaValue::Int(object_id as i64)
+		))
+		.exec()
+		.await?;
+
+	Ok(inserted > 0)
+}
+
+/// Returns the id of the tag with this name, creating it if there isn't one yet, along with whether it was created.
+///
+/// Tags brought in from outside the library (e.g. native tags, or the tags in a bundle) are matched by name, so
+/// importing them again doesn't create duplicates.
+pub async fn find_or_create_tag(
+	library_ctx: &LibraryContext,
+	name: String,
+	color: Option<String>,
+) -> Result<(i32, bool), QueryError> {
+	let LibraryContext { db, sync, .. } = library_ctx;
+
+	if let Some(existing) = db
+		.tag()
+		.find_first(vec![tag::name::equals(Some(name.clone()))])
+		.exec()
+		.await?
+	{
+		return Ok((existing.id, false));
+	}
+
+	let pub_id = Uuid::new_v4().as_bytes().to_vec();
+
+	let created = sync
+		.write_op(
+			db,
+			sync.unique_shared_create(
+				sync::tag::SyncId {
+					pub_id: pub_id.clone(),
+				},
+				[("name", json!(name)), ("color", json!(color))],
+			),
+			db.tag().create(
+				pub_id,
+				vec![tag::name::set(Some(name)), tag::color::set(color)],
+			),
+		)
+		.await?;
+
+	Ok((created.id, true))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		library::{create_test_object, test_library},
+		prisma::tag_on_object,
+	};
+
+	async fn create_tag(library: &LibraryContext, name: &str) -> i32 {
+		library
+			.db
+			.tag()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				vec![tag::name::set(Some(name.to_string()))],
+			)
+			.exec()
+			.await
+			.unwrap()
+			.id
+	}
+
+	async fn tags_on(library: &LibraryContext, object_id: i32) -> Vec<i32> {
+		library
+			.db
+			.tag_on_object()
+			.find_many(vec![tag_on_object::object_id::equals(object_id)])
+			.exec()
+			.await
+			.unwrap()
+			.into_iter()
+			.map(|tag_on_object| tag_on_object.tag_id)
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn assigning_a_tag_twice_only_adds_it_once() {
+		let (_data_dir, _node, library) = test_library().await;
+		let object_id = create_test_object(&library.db, vec![]).await;
+		let tag_id = create_tag(&library, "Work").await;
+
+		assert!(assign_tag(&library.db, object_id, tag_id).await.unwrap());
+		assert!(!assign_tag(&library.db, object_id, tag_id).await.unwrap());
+
+		assert_eq!(tags_on(&library, object_id).await, [tag_id]);
+	}
+
+	#[tokio::test]
+	async fn imported_tags_are_created_once_and_assigned() {
+		let (_data_dir, _node, library) = test_library().await;
+		let object_id = create_test_object(&library.db, vec![]).await;
+
+		let (tag_id, created) =
+			find_or_create_tag(&library, "Red".to_string(), Some("#FF0000".to_string()))
+				.await
+				.unwrap();
+		assert!(created);
+
+		// Importing the same tag again finds the one that was just created
+		assert_eq!(
+			find_or_create_tag(&library, "Red".to_string(), None)
+				.await
+				.unwrap(),
+			(tag_id, false)
+		);
+		assert_eq!(library.db.tag().count(vec![]).exec().await.unwrap(), 1);
+
+		assert!(assign_tag(&library.db, object_id, tag_id).await.unwrap());
+		assert_eq!(tags_on(&library, object_id).await, [tag_id]);
+	}
+}
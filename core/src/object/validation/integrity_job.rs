@@ -0,0 +1,371 @@
+use serde::{Deserialize, Serialize};
+
+use std::{
+	collections::VecDeque,
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	job::{
+		JobCategory, JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	prisma::{file_path, location},
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use rspc::Type;
+use tokio::{fs, io, sync::oneshot};
+use tracing::{error, info, warn};
+
+use super::hash::file_checksum;
+
+pub const INTEGRITY_JOB_NAME: &str = "object_integrity_scan";
+
+// The integrity scanner re-hashes every file that already has a full checksum and compares it
+// against the stored value. It's meant for backup locations, where content changing without
+// the file's metadata changing points to bit-rot or tampering.
+pub struct ObjectIntegrityJob {
+	pub report_tx: Option<oneshot::Sender<IntegrityReport>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Type)]
+pub enum IntegrityStatus {
+	// The content matches the stored checksum
+	Unchanged,
+	// The content changed, but so did the mtime or size - this is an expected edit
+	Modified,
+	// The content changed while the mtime and size stayed the same - this is suspicious
+	Corrupted,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Type)]
+pub struct IntegrityReport {
+	pub unchanged: usize,
+	pub modified: usize,
+	pub missing: usize,
+	pub corrupted_object_ids: Vec<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ObjectIntegrityJobState {
+	pub root_path: PathBuf,
+	pub task_count: usize,
+	pub report: IntegrityReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Hash)]
+pub struct ObjectIntegrityJobInit {
+	pub location_id: i32,
+}
+
+file_path::select!(file_path_for_integrity {
+	id
+	materialized_path
+	integrity_checksum
+	date_modified
+	object: select {
+		id
+		size_in_bytes
+	}
+});
+
+/// Re-hashes the file at `path` and classifies it against what we know about it.
+///
+/// The mtime is only considered changed if the file on disk is newer than the stored
+/// `date_modified`, as the stored value may be the time of indexing rather than the file's mtime.
+pub async fn check_file_integrity(
+	path: impl AsRef<Path>,
+	stored_checksum: &str,
+	stored_modified: DateTime<FixedOffset>,
+	stored_size: Option<u64>,
+) -> Result<IntegrityStatus, io::Error> {
+	let path = path.as_ref();
+
+	let checksum = file_checksum(path).await?;
+	if checksum == stored_checksum {
+		return Ok(IntegrityStatus::Unchanged);
+	}
+
+	let fs_metadata = fs::metadata(path).await?;
+	let fs_modified: DateTime<Utc> = fs_metadata.modified()?.into();
+
+	let mtime_changed = fs_modified.timestamp() > stored_modified.timestamp();
+	let size_changed = stored_size.map_or(false, |size| size != fs_metadata.len());
+
+	Ok(if mtime_changed || size_changed {
+		IntegrityStatus::Modified
+	} else {
+		IntegrityStatus::Corrupted
+	})
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ObjectIntegrityJob {
+	type Init = ObjectIntegrityJobInit;
+	type Data = ObjectIntegrityJobState;
+	type Step = file_path_for_integrity::Data;
+
+	const CATEGORY: JobCategory = JobCategory::Hashing;
+
+	fn name(&self) -> &'static str {
+		INTEGRITY_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let db = &ctx.library_ctx.db;
+
+		let location = db
+			.location()
+			.find_unique(location::id::equals(state.init.location_id))
+			.exec()
+			.await?
+			.ok_or(JobError::MissingData {
+				value: format!("location <id='{}'>", state.init.location_id),
+			})?;
+
+		state.steps = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(state.init.location_id),
+				file_path::is_dir::equals(false),
+				file_path::integrity_checksum::not(None),
+			])
+			.select(file_path_for_integrity::select())
+			.exec()
+			.await?
+			.into_iter()
+			.collect::<VecDeque<_>>();
+
+		state.data = Some(ObjectIntegrityJobState {
+			root_path: location.path.into(),
+			task_count: state.steps.len(),
+			report: IntegrityReport::default(),
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let file_path = &state.steps[0];
+		let data = state.data.as_mut().expect("fatal: missing job state");
+
+		// SAFETY: we only queried file paths with a checksum
+		let stored_checksum = file_path
+			.integrity_checksum
+			.as_ref()
+			.expect("file path without integrity checksum");
+
+		let stored_size = file_path
+			.object
+			.as_ref()
+			.and_then(|object| object.size_in_bytes.parse::<u64>().ok());
+
+		match check_file_integrity(
+			data.root_path.join(&file_path.materialized_path),
+			stored_checksum,
+			file_path.date_modified,
+			stored_size,
+		)
+		.await
+		{
+			Ok(IntegrityStatus::Unchanged) => data.report.unchanged += 1,
+			Ok(IntegrityStatus::Modified) => data.report.modified += 1,
+			Ok(IntegrityStatus::Corrupted) => {
+				warn!(
+					"Possible corruption detected on file: {}",
+					file_path.materialized_path
+				);
+				if let Some(object) = &file_path.object {
+					data.report.corrupted_object_ids.push(object.id);
+				}
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => data.report.missing += 1,
+			Err(e) => return Err(e.into()),
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+		info!(
+			"finalizing integrity scan job at {}: {} tasks, {} corrupted",
+			data.root_path.display(),
+			data.task_count,
+			data.report.corrupted_object_ids.len()
+		);
+
+		if let Some(report_tx) = self.report_tx.take() {
+			if report_tx.send(data.report.clone()).is_err() {
+				error!("Failed to send report on ObjectIntegrityJob");
+			}
+		}
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		library::{create_test_location, create_test_object, test_library, LibraryContext},
+		location::integrity_scan,
+		prisma::object,
+	};
+	use chrono::TimeZone;
+	use tempfile::tempdir;
+
+	/// Indexes a file in the location with `checksum` as its full checksum, returning its object's id.
+	async fn create_checksummed_file(
+		library: &LibraryContext,
+		location_id: i32,
+		id: i32,
+		file_name: &str,
+		checksum: String,
+		size: u64,
+	) -> i32 {
+		let object_id = create_test_object(
+			&library.db,
+			vec![object::size_in_bytes::set(size.to_string())],
+		)
+		.await;
+
+		let (name, extension) = file_name.rsplit_once('.').unwrap();
+		library
+			.db
+			.file_path()
+			.create(
+				id,
+				location::id::equals(location_id),
+				file_name.to_string(),
+				name.to_string(),
+				extension.to_string(),
+				vec![
+					file_path::integrity_checksum::set(Some(checksum)),
+					file_path::date_modified::set(Utc::now().into()),
+					file_path::object::connect(object::id::equals(object_id)),
+				],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		object_id
+	}
+
+	#[tokio::test]
+	async fn scan_reports_every_file_in_the_location() {
+		let (_data_dir, _node, library) = test_library().await;
+		let dir = tempdir().unwrap();
+		let location_id = create_test_location(&library, dir.path()).await;
+
+		let (untouched, tampered) = (dir.path().join("photo.raw"), dir.path().join("backup.bin"));
+		fs::write(&untouched, b"raw sensor data").await.unwrap();
+		fs::write(&tampered, b"original contents").await.unwrap();
+		let (untouched_checksum, tampered_checksum) = (
+			file_checksum(&untouched).await.unwrap(),
+			file_checksum(&tampered).await.unwrap(),
+		);
+		// same length, different bytes
+		fs::write(&tampered, b"0riginal contents").await.unwrap();
+
+		create_checksummed_file(
+			&library,
+			location_id,
+			1,
+			"photo.raw",
+			untouched_checksum,
+			15,
+		)
+		.await;
+		let tampered_id = create_checksummed_file(
+			&library,
+			location_id,
+			2,
+			"backup.bin",
+			tampered_checksum,
+			17,
+		)
+		.await;
+		create_checksummed_file(&library, location_id, 3, "gone.txt", "0".repeat(64), 4).await;
+
+		let report = integrity_scan(&library, location_id)
+			.await
+			.unwrap()
+			.expect("the scan didn't finish");
+
+		assert_eq!(report.unchanged, 1);
+		assert_eq!(report.modified, 0);
+		assert_eq!(report.missing, 1);
+		assert_eq!(report.corrupted_object_ids, [tampered_id]);
+	}
+
+	#[tokio::test]
+	async fn tampered_file_is_corrupted() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("backup.bin");
+
+		fs::write(&path, b"original contents").await.unwrap();
+		let checksum = file_checksum(&path).await.unwrap();
+
+		// same length, different bytes
+		fs::write(&path, b"0riginal contents").await.unwrap();
+
+		// the index was updated after the last legitimate write, so the mtime hasn't moved since
+		let stored_modified = Utc::now().into();
+
+		let status = check_file_integrity(&path, &checksum, stored_modified, Some(17))
+			.await
+			.unwrap();
+
+		assert_eq!(status, IntegrityStatus::Corrupted);
+	}
+
+	#[tokio::test]
+	async fn edited_file_is_modified() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("notes.txt");
+
+		fs::write(&path, b"first draft").await.unwrap();
+		let checksum = file_checksum(&path).await.unwrap();
+
+		fs::write(&path, b"second, longer draft").await.unwrap();
+
+		let stored_modified = Utc.timestamp_opt(0, 0).unwrap().into();
+
+		let status = check_file_integrity(&path, &checksum, stored_modified, Some(11))
+			.await
+			.unwrap();
+
+		assert_eq!(status, IntegrityStatus::Modified);
+	}
+
+	#[tokio::test]
+	async fn untouched_file_is_unchanged() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("photo.raw");
+
+		fs::write(&path, b"raw sensor data").await.unwrap();
+		let checksum = file_checksum(&path).await.unwrap();
+
+		let status = check_file_integrity(&path, &checksum, Utc::now().into(), Some(15))
+			.await
+			.unwrap();
+
+		assert_eq!(status, IntegrityStatus::Unchanged);
+	}
+}
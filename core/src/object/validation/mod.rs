@@ -1,2 +1,3 @@
 pub mod hash;
+pub mod integrity_job;
 pub mod validator_job;
@@ -4,7 +4,9 @@ use serde_json::json;
 use std::{collections::VecDeque, path::PathBuf};
 
 use crate::{
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{
+		JobCategory, JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
 	library::LibraryContext,
 	prisma::{file_path, location},
 	sync,
@@ -55,6 +57,8 @@ impl StatefulJob for ObjectValidatorJob {
 	type Data = ObjectValidatorJobState;
 	type Step = file_path_and_object::Data;
 
+	const CATEGORY: JobCategory = JobCategory::Hashing;
+
 	fn name(&self) -> &'static str {
 		VALIDATOR_JOB_NAME
 	}
@@ -0,0 +1,261 @@
+//! Bootstrapping a library on a new device, by pulling the index of a peer's copy of it in one
+//! transfer, instead of reindexing locations the device may not even have.
+//!
+//! This is a single request on a stream the peers already opened, which is separate from
+//! continuous sync. The index is the sync operations that built it, so merging it goes through
+//! the same path as operations received from sync. The peer answers with batches of them, and
+//! an empty batch once it's done, so neither side has to hold the whole index in memory.
+
+use std::{future::Future, io};
+
+use sd_sync::CRDTOperation;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::info;
+use uhlc::NTP64;
+
+use super::SyncManager;
+
+/// How many operations are sent in a message.
+const BATCH_SIZE: usize = 1000;
+
+/// Messages are read into memory, so a peer can't make us allocate more than this.
+const MAX_MESSAGE_LEN: u64 = 16 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum BootstrapError {
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("I/O error: {0}")]
+	Io(#[from] io::Error),
+	#[error("malformed bootstrap message: {0}")]
+	Serialization(#[from] serde_json::Error),
+	#[error("bootstrap message is too large ({0} bytes)")]
+	MessageTooLarge(u64),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexRequest {
+	since: Option<NTP64>,
+}
+
+/// Some of the operations which built a library's index, in the order they were made.
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexBatch {
+	ops: Vec<CRDTOperation>,
+}
+
+/// What was merged from a peer's index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexReport {
+	/// How many operations the peer sent.
+	pub ops: usize,
+	/// Where the next incremental request should start from.
+	pub latest: Option<NTP64>,
+}
+
+impl SyncManager {
+	/// Merges a batch of a peer's operations into this library.
+	pub async fn ingest_ops(&self, ops: Vec<CRDTOperation>) -> prisma_client_rust::Result<()> {
+		for op in ops {
+			self.ingest_op(op).await?;
+		}
+
+		Ok(())
+	}
+
+	/// Asks the peer on the other end of the stream for its index, and merges it into this library.
+	///
+	/// Without `since` this is the whole index, otherwise only what changed after it, which is
+	/// usually the [`IndexReport::latest`] of the previous request.
+	pub async fn request_index(
+		&self,
+		stream: (impl AsyncRead + Unpin, impl AsyncWrite + Unpin),
+		since: Option<NTP64>,
+	) -> Result<IndexReport, BootstrapError> {
+		let report = request(stream, since, |ops| self.ingest_ops(ops)).await?;
+		info!("Bootstrapped from {} operations", report.ops);
+
+		Ok(report)
+	}
+
+	/// Answers a peer's [`SyncManager::request_index`] on the stream.
+	pub async fn answer_index_request(
+		&self,
+		stream: (impl AsyncRead + Unpin, impl AsyncWrite + Unpin),
+	) -> Result<(), BootstrapError> {
+		answer(stream, |after| self.get_ops_after(after, BATCH_SIZE)).await
+	}
+}
+
+async fn request<Fut>(
+	(mut recv, mut send): (impl AsyncRead + Unpin, impl AsyncWrite + Unpin),
+	since: Option<NTP64>,
+	mut ingest: impl FnMut(Vec<CRDTOperation>) -> Fut,
+) -> Result<IndexReport, BootstrapError>
+where
+	Fut: Future<Output = prisma_client_rust::Result<()>>,
+{
+	write_message(&mut send, &IndexRequest { since }).await?;
+
+	let mut report = IndexReport {
+		ops: 0,
+		latest: since,
+	};
+	loop {
+		let IndexBatch { ops } = read_message(&mut recv).await?;
+		if ops.is_empty() {
+			return Ok(report);
+		}
+
+		report.ops += ops.len();
+		report.latest = report.latest.max(ops.iter().map(|op| op.timestamp).max());
+
+		ingest(ops).await?;
+	}
+}
+
+async fn answer<Fut>(
+	(mut recv, mut send): (impl AsyncRead + Unpin, impl AsyncWrite + Unpin),
+	mut ops_after: impl FnMut(Option<NTP64>) -> Fut,
+) -> Result<(), BootstrapError>
+where
+	Fut: Future<Output = prisma_client_rust::Result<Vec<CRDTOperation>>>,
+{
+	let IndexRequest { since } = read_message(&mut recv).await?;
+
+	let mut after = since;
+	loop {
+		let ops = ops_after(after).await?;
+		let last = ops.last().map(|op| op.timestamp);
+
+		write_message(&mut send, &IndexBatch { ops }).await?;
+
+		match last {
+			Some(last) => after = Some(last),
+			None => return Ok(()),
+		}
+	}
+}
+
+/// Messages are JSON, like the operations are everywhere else, prefixed by their length.
+async fn write_message(
+	writer: &mut (impl AsyncWrite + Unpin),
+	message: &impl Serialize,
+) -> Result<(), BootstrapError> {
+	let bytes = serde_json::to_vec(message)?;
+	if bytes.len() as u64 > MAX_MESSAGE_LEN {
+		return Err(BootstrapError::MessageTooLarge(bytes.len() as u64));
+	}
+
+	writer.write_u64_le(bytes.len() as u64).await?;
+	writer.write_all(&bytes).await?;
+	writer.flush().await?;
+
+	Ok(())
+}
+
+async fn read_message<T: DeserializeOwned>(
+	reader: &mut (impl AsyncRead + Unpin),
+) -> Result<T, BootstrapError> {
+	let len = reader.read_u64_le().await?;
+	if len > MAX_MESSAGE_LEN {
+		return Err(BootstrapError::MessageTooLarge(len));
+	}
+
+	let mut bytes = vec![0; len as usize];
+	reader.read_exact(&mut bytes).await?;
+
+	Ok(serde_json::from_slice(&bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use sd_sync::{
+		CRDTOperationType, SharedOperation, SharedOperationCreateData, SharedOperationData,
+	};
+	use serde_json::json;
+	use tokio::io::{duplex, split};
+	use uuid::Uuid;
+
+	fn create_op(model: &str, timestamp: u64) -> CRDTOperation {
+		CRDTOperation {
+			node: Uuid::nil(),
+			timestamp: NTP64(timestamp),
+			id: Uuid::new_v4(),
+			typ: CRDTOperationType::Shared(SharedOperation {
+				record_id: json!({ "pub_id": Uuid::new_v4() }),
+				model: model.to_string(),
+				data: SharedOperationData::Create(SharedOperationCreateData::Unique(
+					Default::default(),
+				)),
+			}),
+		}
+	}
+
+	fn count(ops: &[CRDTOperation], model: &str) -> usize {
+		ops.iter()
+			.filter(|op| matches!(&op.typ, CRDTOperationType::Shared(op) if op.model == model))
+			.count()
+	}
+
+	#[tokio::test]
+	async fn bootstrap_from_a_peer() {
+		let index = [
+			create_op("Object", 1),
+			create_op("Object", 2),
+			create_op("Tag", 3),
+			create_op("Object", 4),
+			create_op("Tag", 5),
+		];
+
+		for (since, objects, tags) in [(None, 3, 2), (Some(NTP64(3)), 1, 1)] {
+			let (requester, peer) = duplex(64);
+
+			// the peer sends its index two operations at a time
+			let mut batches = 0;
+			let peer = answer(split(peer), |after| {
+				batches += 1;
+				let ops = index
+					.iter()
+					.filter(|op| after.map_or(true, |after| op.timestamp > after))
+					.take(2)
+					.cloned()
+					.collect();
+				async move { Ok(ops) }
+			});
+
+			let mut received = Vec::new();
+			let (report, answered) = tokio::join!(
+				request(split(requester), since, |ops| {
+					received.extend(ops);
+					async { Ok(()) }
+				}),
+				peer
+			);
+			answered.unwrap();
+			let report = report.unwrap();
+
+			// the requester ends up with the same objects and tags as the peer has since then
+			assert_eq!(count(&received, "Object"), objects);
+			assert_eq!(count(&received, "Tag"), tags);
+			assert_eq!(report.ops, objects + tags);
+			assert_eq!(report.latest, Some(NTP64(5)));
+			assert_eq!(batches, (objects + tags + 1) / 2 + 1);
+		}
+	}
+
+	#[tokio::test]
+	async fn oversized_messages_are_refused() {
+		let (mut requester, mut peer) = duplex(64);
+		peer.write_u64_le(u64::MAX).await.unwrap();
+
+		assert!(matches!(
+			read_message::<IndexBatch>(&mut requester).await,
+			Err(BootstrapError::MessageTooLarge(u64::MAX))
+		));
+	}
+}
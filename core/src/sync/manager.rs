@@ -1,4 +1,5 @@
 use crate::prisma::*;
+use prisma_client_rust::Direction;
 use sd_sync::*;
 use serde_json::{from_value, json, to_vec, Value};
 use std::{collections::HashMap, sync::Arc};
@@ -140,11 +141,72 @@ impl SyncManager {
 	}
 
 	pub async fn get_ops(&self) -> prisma_client_rust::Result<Vec<CRDTOperation>> {
+		self.find_ops(vec![], vec![], None).await
+	}
+
+	/// The `count` oldest operations made after `since`, or from the first one without it.
+	///
+	/// Operations made at the same time aren't split between calls, so there can be more than `count` of them.
+	pub async fn get_ops_after(
+		&self,
+		since: Option<NTP64>,
+		count: usize,
+	) -> prisma_client_rust::Result<Vec<CRDTOperation>> {
+		let since = since.map(|since| since.0 as i64);
+
+		let mut ops = self
+			.find_ops(
+				since
+					.map(owned_operation::timestamp::gt)
+					.into_iter()
+					.collect(),
+				since
+					.map(shared_operation::timestamp::gt)
+					.into_iter()
+					.collect(),
+				Some(count as i64),
+			)
+			.await?;
+		ops.truncate(count);
+
+		// the next call starts after the last timestamp, so everything made at that time has to be in this one
+		if let Some(last) = ops.last().map(|op| op.timestamp) {
+			ops.retain(|op| op.timestamp != last);
+			ops.extend(
+				self.find_ops(
+					vec![owned_operation::timestamp::equals(last.0 as i64)],
+					vec![shared_operation::timestamp::equals(last.0 as i64)],
+					None,
+				)
+				.await?,
+			);
+		}
+
+		Ok(ops)
+	}
+
+	async fn find_ops(
+		&self,
+		owned_params: Vec<owned_operation::WhereParam>,
+		shared_params: Vec<shared_operation::WhereParam>,
+		take: Option<i64>,
+	) -> prisma_client_rust::Result<Vec<CRDTOperation>> {
 		let db = &self.db;
 
-		let owned = db
+		let mut owned = db
 			.owned_operation()
-			.find_many(vec![])
+			.find_many(owned_params)
+			.order_by(owned_operation::timestamp::order(Direction::Asc));
+		let mut shared = db
+			.shared_operation()
+			.find_many(shared_params)
+			.order_by(shared_operation::timestamp::order(Direction::Asc));
+		if let Some(take) = take {
+			owned = owned.take(take);
+			shared = shared.take(take);
+		}
+
+		let owned = owned
 			.include(owned_operation::include!({ node }))
 			.exec()
 			.await?
@@ -161,9 +223,7 @@ impl SyncManager {
 				})
 			});
 
-		let shared = db
-			.shared_operation()
-			.find_many(vec![])
+		let shared = shared
 			.include(shared_operation::include!({ node }))
 			.exec()
 			.await?
@@ -233,6 +293,12 @@ impl SyncManager {
 							.exec()
 							.await?;
 					}
+					SharedOperationData::Delete => {
+						db.file_path()
+							.delete(file_path::location_id_id(location.id, id.id))
+							.exec()
+							.await?;
+					}
 					_ => todo!(),
 				}
 			}
@@ -456,4 +522,17 @@ impl SyncManager {
 			},
 		}))
 	}
+	pub fn shared_delete<
+		TSyncId: SyncId<ModelTypes = TModel>,
+		TModel: SyncType<Marker = SharedSyncType>,
+	>(
+		&self,
+		id: TSyncId,
+	) -> CRDTOperation {
+		self.new_op(CRDTOperationType::Shared(SharedOperation {
+			model: TModel::MODEL.to_string(),
+			record_id: json!(id),
+			data: SharedOperationData::Delete,
+		}))
+	}
 }
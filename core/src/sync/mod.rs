@@ -1,4 +1,6 @@
+mod bootstrap;
 mod manager;
 
 pub use crate::prisma_sync::*;
+pub use bootstrap::{BootstrapError, IndexReport};
 pub use manager::SyncManager;
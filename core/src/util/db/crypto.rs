@@ -0,0 +1,103 @@
+//! Field-level encryption for small, sensitive column values (such as a note, or an object's original path).
+//!
+//! Each field is encrypted with a subkey derived from the library key and the field's `purpose`,
+//! and the purpose is also used as the AAD. This means a value encrypted for one column can't be
+//! transplanted into (and decrypted as) another.
+//!
+//! The output is suitable for a `Bytes` column, and is laid out as `version || nonce || ciphertext`.
+
+use sd_crypto::{
+	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
+	primitives::{
+		types::{Key, Nonce, Salt},
+		SALT_LEN,
+	},
+	Error, Protected,
+};
+
+const FIELD_KEY_CONTEXT: &str = "spacedrive 2023-02-21 12:00:00 field encryption key derivation";
+const FIELD_VERSION_V1: u8 = 0x01;
+const FIELD_ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+
+fn derive_field_key(library_key: Key, purpose: &str) -> Key {
+	let mut salt = [0u8; SALT_LEN];
+	salt.copy_from_slice(&blake3::hash(purpose.as_bytes()).as_bytes()[..SALT_LEN]);
+
+	Key::derive(library_key, Salt(salt), FIELD_KEY_CONTEXT)
+}
+
+pub async fn encrypt_field(
+	library_key: Key,
+	plaintext: &[u8],
+	purpose: &str,
+) -> Result<Vec<u8>, Error> {
+	let nonce = Nonce::generate(FIELD_ALGORITHM)?;
+
+	let ciphertext = StreamEncryption::encrypt_bytes(
+		derive_field_key(library_key, purpose),
+		nonce,
+		FIELD_ALGORITHM,
+		plaintext,
+		purpose.as_bytes(),
+	)
+	.await?;
+
+	let mut bytes = Vec::with_capacity(1 + nonce.len() + ciphertext.len());
+	bytes.push(FIELD_VERSION_V1);
+	bytes.extend_from_slice(&nonce);
+	bytes.extend_from_slice(&ciphertext);
+
+	Ok(bytes)
+}
+
+pub async fn decrypt_field(
+	library_key: Key,
+	bytes: &[u8],
+	purpose: &str,
+) -> Result<Protected<Vec<u8>>, Error> {
+	let nonce_len = FIELD_ALGORITHM.nonce_len();
+
+	match bytes.first() {
+		Some(&FIELD_VERSION_V1) if bytes.len() > 1 + nonce_len => {}
+		_ => return Err(Error::Serialization),
+	}
+
+	let nonce = Nonce::try_from(bytes[1..=nonce_len].to_vec())?;
+
+	StreamDecryption::decrypt_bytes(
+		derive_field_key(library_key, purpose),
+		nonce,
+		FIELD_ALGORITHM,
+		&bytes[1 + nonce_len..],
+		purpose.as_bytes(),
+	)
+	.await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn field_roundtrip() {
+		let key = Key::generate();
+
+		let encrypted = encrypt_field(key.clone(), b"/Users/me/secret.txt", "object_path")
+			.await
+			.unwrap();
+		let decrypted = decrypt_field(key, &encrypted, "object_path").await.unwrap();
+
+		assert_eq!(decrypted.expose(), b"/Users/me/secret.txt");
+	}
+
+	#[tokio::test]
+	async fn field_cross_purpose() {
+		let key = Key::generate();
+
+		let encrypted = encrypt_field(key.clone(), b"a private note", "object_note")
+			.await
+			.unwrap();
+
+		assert!(decrypt_field(key, &encrypted, "object_path").await.is_err());
+	}
+}
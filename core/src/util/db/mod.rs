@@ -0,0 +1,285 @@
+pub mod crypto;
+
+use crate::library::LibraryManagerError;
+use crate::prisma::{self, file_path, object, PrismaClient};
+use chrono::{DateTime, Utc};
+use prisma_client_rust::{migrations::*, not, NewClientError, QueryError};
+use sd_crypto::keys::keymanager::StoredKey;
+use std::{
+	io::ErrorKind,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+use thiserror::Error;
+use tokio::{fs, io::AsyncReadExt};
+use tracing::warn;
+
+/// MigrationError represents an error that occurring while opening a initialising and running migrations on the database.
+#[derive(Error, Debug)]
+pub enum MigrationError {
+	#[error("An error occurred while initialising a new database connection: {0}")]
+	NewClient(#[from] Box<NewClientError>),
+	#[cfg(debug_assertions)]
+	#[error("An error occurred during migration: {0}")]
+	MigrateFailed(#[from] DbPushError),
+	#[cfg(not(debug_assertions))]
+	#[error("An error occurred during migration: {0}")]
+	MigrateFailed(#[from] MigrateDeployError),
+	#[error("The database file is corrupt and can't be opened: {0}")]
+	Corrupt(PathBuf),
+	#[error("An error occurred while checking the database file: {0}")]
+	IO(#[from] std::io::Error),
+}
+
+/// Every SQLite database file starts with this header string.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// prepare_database_file checks a database file before it's opened, so an interrupted first run doesn't prevent the library from loading.
+///
+/// An empty file, or one that stops partway through the SQLite header, was never initialised and is removed so it can be created again.
+/// A file that doesn't start with the SQLite header at all is reported as corrupt, and is never deleted.
+/// Any valid SQLite database is left as is, even if it still needs migrating.
+pub async fn prepare_database_file(path: impl AsRef<Path>) -> Result<(), MigrationError> {
+	let path = path.as_ref();
+
+	let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+	match fs::File::open(path).await {
+		Ok(file) => {
+			file.take(SQLITE_HEADER.len() as u64)
+				.read_to_end(&mut header)
+				.await?;
+		}
+		Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+		Err(e) => return Err(e.into()),
+	}
+
+	if header.as_slice() == SQLITE_HEADER {
+		Ok(())
+	} else if SQLITE_HEADER.starts_with(&header) {
+		warn!("Removing uninitialised database file: {}", path.display());
+		fs::remove_file(path).await.map_err(Into::into)
+	} else {
+		Err(MigrationError::Corrupt(path.to_path_buf()))
+	}
+}
+
+/// load_and_migrate will load the database from the given path and migrate it to the latest version of the schema.
+pub async fn load_and_migrate(db_url: &str) -> Result<PrismaClient, MigrationError> {
+	let client = prisma::new_client_with_url(db_url)
+		.await
+		.map_err(Box::new)?;
+
+	#[cfg(debug_assertions)]
+	{
+		let mut builder = client._db_push();
+
+		if std::env::var("SD_FORCE_RESET_DB")
+			.map(|v| v == "true")
+			.unwrap_or(false)
+		{
+			builder = builder.accept_data_loss().force_reset();
+		}
+
+		builder.await?;
+	}
+
+	#[cfg(not(debug_assertions))]
+	client._migrate_deploy().await?;
+
+	Ok(client)
+}
+
+/// This writes a `StoredKey` to prisma
+/// If the key is marked as memory-only, it is skipped
+pub async fn write_storedkey_to_db(
+	db: &PrismaClient,
+	key: &StoredKey,
+) -> Result<(), LibraryManagerError> {
+	if !key.memory_only {
+		db.key()
+			.create(
+				key.uuid.to_string(),
+				serde_json::to_string(&key.version)?,
+				serde_json::to_string(&key.key_type)?,
+				serde_json::to_string(&key.algorithm)?,
+				serde_json::to_string(&key.hashing_algorithm)?,
+				key.content_salt.0.to_vec(),
+				key.master_key.to_vec(),
+				key.master_key_nonce.to_vec(),
+				key.key_nonce.to_vec(),
+				key.key.to_vec(),
+				key.salt.to_vec(),
+				vec![],
+			)
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// update_file_paths_batch applies size and modification time updates to many file paths of a location in a single transaction.
+/// Each update is a tuple of `(file_path_id, size_in_bytes, date_modified)`. The size is stored on the file path's object, if it has one
+/// and no other file path shares it, as the object still describes the content of the others.
+pub async fn update_file_paths_batch(
+	db: &PrismaClient,
+	location_id: i32,
+	updates: &[(i32, u64, SystemTime)],
+) -> Result<(), QueryError> {
+	if updates.is_empty() {
+		return Ok(());
+	}
+
+	let (file_paths, objects): (Vec<_>, Vec<_>) = updates
+		.iter()
+		.map(|(id, size, date_modified)| {
+			(
+				db.file_path().update(
+					file_path::location_id_id(location_id, *id),
+					vec![file_path::date_modified::set(
+						DateTime::<Utc>::from(*date_modified).into(),
+					)],
+				),
+				db.object().update_many(
+					vec![
+						object::file_paths::some(vec![
+							file_path::location_id::equals(location_id),
+							file_path::id::equals(*id),
+						]),
+						object::file_paths::none(vec![not![
+							file_path::location_id::equals(location_id),
+							file_path::id::equals(*id)
+						]]),
+					],
+					vec![object::size_in_bytes::set(size.to_string())],
+				),
+			)
+		})
+		.unzip();
+
+	db._batch((file_paths, objects)).await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		prisma::{location, node},
+		Node,
+	};
+
+	use tempfile::tempdir;
+	use uuid::Uuid;
+
+	#[tokio::test]
+	async fn empty_database_file_is_removed() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("library.db");
+		fs::write(&path, b"").await.unwrap();
+
+		prepare_database_file(&path).await.unwrap();
+
+		assert!(!path.exists());
+	}
+
+	#[tokio::test]
+	async fn valid_database_file_is_kept() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("library.db");
+		let mut contents = SQLITE_HEADER.to_vec();
+		contents.extend_from_slice(&[0x10, 0x00, 0x01, 0x01]);
+		fs::write(&path, &contents).await.unwrap();
+
+		prepare_database_file(&path).await.unwrap();
+
+		assert_eq!(fs::read(&path).await.unwrap(), contents);
+	}
+
+	#[tokio::test]
+	async fn garbage_database_file_is_corrupt() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("library.db");
+		fs::write(&path, b"definitely not a database")
+			.await
+			.unwrap();
+
+		assert!(matches!(
+			prepare_database_file(&path).await,
+			Err(MigrationError::Corrupt(_))
+		));
+		assert!(path.exists());
+	}
+
+	#[tokio::test]
+	async fn shared_objects_keep_their_size() {
+		let data_dir = tempdir().unwrap();
+		let (node, _) = Node::new(data_dir.path()).await.unwrap();
+		let library = node.library_manager.create_test_library().await;
+		let db = &library.db;
+
+		let location = db
+			.location()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				"Test".to_string(),
+				data_dir.path().to_string_lossy().to_string(),
+				node::id::equals(library.node_local_id),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		let mut objects = vec![];
+		for _ in 0..2 {
+			objects.push(
+				db.object()
+					.create(
+						Uuid::new_v4().as_bytes().to_vec(),
+						vec![object::size_in_bytes::set("100".to_string())],
+					)
+					.exec()
+					.await
+					.unwrap()
+					.id,
+			);
+		}
+		let (shared, own) = (objects[0], objects[1]);
+
+		for (id, name, object_id) in [(1, "a", shared), (2, "b", shared), (3, "c", own)] {
+			db.file_path()
+				.create(
+					id,
+					location::id::equals(location.id),
+					format!("{name}.txt"),
+					name.to_string(),
+					"txt".to_string(),
+					vec![file_path::object::connect(object::id::equals(object_id))],
+				)
+				.exec()
+				.await
+				.unwrap();
+		}
+
+		let now = SystemTime::now();
+		update_file_paths_batch(db, location.id, &[(1, 200, now), (3, 300, now)])
+			.await
+			.unwrap();
+
+		let size_of = |id| async move {
+			db.object()
+				.find_unique(object::id::equals(id))
+				.exec()
+				.await
+				.unwrap()
+				.unwrap()
+				.size_in_bytes
+		};
+		// `b.txt` still has the content the shared object describes
+		assert_eq!(size_of(shared).await, "100");
+		assert_eq!(size_of(own).await, "300");
+	}
+}
@@ -0,0 +1,154 @@
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+use tokio::time::Instant;
+
+/// Collapses bursts of values into a single batch per key.
+///
+/// A key is flushed once it hasn't received a new value for `quiet_period`, or once `max_wait`
+/// has passed since its first pending value, whichever comes first. The `max_wait` cap ensures that
+/// a key receiving a continuous stream of values is still flushed periodically.
+#[derive(Debug)]
+pub struct Debouncer<K, V> {
+	quiet_period: Duration,
+	max_wait: Duration,
+	pending: HashMap<K, Pending<V>>,
+}
+
+#[derive(Debug)]
+struct Pending<V> {
+	values: Vec<V>,
+	first_at: Instant,
+	last_at: Instant,
+}
+
+impl<V> Pending<V> {
+	fn deadline(&self, quiet_period: Duration, max_wait: Duration) -> Instant {
+		(self.last_at + quiet_period).min(self.first_at + max_wait)
+	}
+}
+
+impl<K: Hash + Eq + Clone, V> Debouncer<K, V> {
+	pub fn new(quiet_period: Duration, max_wait: Duration) -> Self {
+		Self {
+			quiet_period,
+			max_wait,
+			pending: HashMap::new(),
+		}
+	}
+
+	pub fn push_at(&mut self, key: K, value: V, now: Instant) {
+		let pending = self.pending.entry(key).or_insert_with(|| Pending {
+			values: Vec::new(),
+			first_at: now,
+			last_at: now,
+		});
+
+		pending.values.push(value);
+		pending.last_at = now;
+	}
+
+	/// Returns when the next key is due to be flushed, if there are any pending values at all.
+	pub fn next_deadline(&self) -> Option<Instant> {
+		self.pending
+			.values()
+			.map(|pending| pending.deadline(self.quiet_period, self.max_wait))
+			.min()
+	}
+
+	/// Removes and returns the batches of every key which is due at `now`.
+	pub fn take_ready(&mut self, now: Instant) -> Vec<(K, Vec<V>)> {
+		let ready = self
+			.pending
+			.iter()
+			.filter(|(_, pending)| pending.deadline(self.quiet_period, self.max_wait) <= now)
+			.map(|(key, _)| key.clone())
+			.collect::<Vec<_>>();
+
+		ready
+			.into_iter()
+			.filter_map(|key| {
+				self.pending
+					.remove(&key)
+					.map(|pending| (key, pending.values))
+			})
+			.collect()
+	}
+
+	/// Removes and returns every pending batch, regardless of whether it's due or not.
+	/// Useful to flush everything that's left on shutdown.
+	pub fn drain(&mut self) -> Vec<(K, Vec<V>)> {
+		self.pending
+			.drain()
+			.map(|(key, pending)| (key, pending.values))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const QUIET_PERIOD: Duration = Duration::from_millis(100);
+	const MAX_WAIT: Duration = Duration::from_millis(500);
+
+	#[test]
+	fn flushes_after_quiet_period() {
+		let mut debouncer = Debouncer::new(QUIET_PERIOD, MAX_WAIT);
+		let start = Instant::now();
+
+		debouncer.push_at("key", 1, start);
+		debouncer.push_at("key", 2, start + Duration::from_millis(50));
+
+		assert_eq!(
+			debouncer.next_deadline(),
+			Some(start + Duration::from_millis(150))
+		);
+		assert!(debouncer
+			.take_ready(start + Duration::from_millis(149))
+			.is_empty());
+
+		assert_eq!(
+			debouncer.take_ready(start + Duration::from_millis(150)),
+			vec![("key", vec![1, 2])]
+		);
+		assert!(debouncer.next_deadline().is_none());
+	}
+
+	#[test]
+	fn max_wait_caps_continuous_input() {
+		let mut debouncer = Debouncer::new(QUIET_PERIOD, MAX_WAIT);
+		let start = Instant::now();
+
+		// a value every 50ms never leaves a quiet period, so only max_wait can flush it
+		for i in 0..20 {
+			debouncer.push_at("key", i, start + Duration::from_millis(i * 50));
+		}
+
+		assert_eq!(debouncer.next_deadline(), Some(start + MAX_WAIT));
+
+		let ready = debouncer.take_ready(start + MAX_WAIT);
+		assert_eq!(ready.len(), 1);
+		assert_eq!(ready[0].1.len(), 20);
+	}
+
+	#[test]
+	fn keys_are_flushed_independently() {
+		let mut debouncer = Debouncer::new(QUIET_PERIOD, MAX_WAIT);
+		let start = Instant::now();
+
+		debouncer.push_at("a", 1, start);
+		debouncer.push_at("b", 2, start + Duration::from_millis(80));
+
+		assert_eq!(
+			debouncer.take_ready(start + QUIET_PERIOD),
+			vec![("a", vec![1])]
+		);
+		assert_eq!(
+			debouncer.next_deadline(),
+			Some(start + Duration::from_millis(180))
+		);
+
+		assert_eq!(debouncer.drain(), vec![("b", vec![2])]);
+		assert!(debouncer.next_deadline().is_none());
+	}
+}
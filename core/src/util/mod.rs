@@ -1,3 +1,4 @@
 pub mod db;
+pub mod debounce;
 pub mod secure_temp_keystore;
 pub mod seeder;
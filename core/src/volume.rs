@@ -1,11 +1,20 @@
-use crate::{library::LibraryContext, prisma::volume::*};
+use crate::{api::CoreEvent, library::LibraryContext, prisma::volume::*};
 
 use rspc::Type;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
-use std::process::Command;
-use sysinfo::{DiskExt, System, SystemExt};
+use std::{process::Command, time::Duration};
+use sysinfo::{DiskExt, RefreshKind, System, SystemExt};
 use thiserror::Error;
+use tokio::{
+	sync::{broadcast, watch},
+	task::{self, JoinHandle},
+	time,
+};
+use tracing::error;
+
+/// How often we check for volumes being mounted or unmounted
+const VOLUME_POLL_INTERVAL: Duration = Duration::from_secs(5);
 
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Default, Clone, Type)]
@@ -79,7 +88,7 @@ pub async fn save_volume(ctx: &LibraryContext) -> Result<(), VolumeError> {
 
 // TODO: Error handling in this function
 pub fn get_volumes() -> Result<Vec<Volume>, VolumeError> {
-	System::new_all()
+	System::new_with_specifics(RefreshKind::new().with_disks_list())
 		.disks()
 		.iter()
 		.filter_map(|disk| {
@@ -116,10 +125,8 @@ pub fn get_volumes() -> Result<Vec<Volume>, VolumeError> {
 					.output()
 					.expect("failed to execute process");
 				let wmic_process_output = String::from_utf8(wmic_process.stdout).ok()?;
-				let parsed_size =
-					wmic_process_output.split("\r\r\n").collect::<Vec<&str>>()[1].to_string();
 
-				if let Ok(n) = parsed_size.trim().parse::<u64>() {
+				if let Some(n) = parse_wmic_size(&wmic_process_output) {
 					total_capacity = n;
 				}
 			}
@@ -138,6 +145,124 @@ pub fn get_volumes() -> Result<Vec<Volume>, VolumeError> {
 		.collect::<Result<Vec<_>, _>>()
 }
 
+/// Parses the output of `wmic logicaldisk get Size`, which is a `Size` header followed by the size in bytes.
+fn parse_wmic_size(output: &str) -> Option<u64> {
+	output.split("\r\r\n").nth(1)?.trim().parse().ok()
+}
+
+/// Returns the volumes which were mounted and unmounted between two listings, in that order.
+fn diff_volumes(previous: &[Volume], current: &[Volume]) -> (Vec<Volume>, Vec<Volume>) {
+	fn contains(volumes: &[Volume], volume: &Volume) -> bool {
+		volumes
+			.iter()
+			.any(|v| v.mount_point == volume.mount_point && v.name == volume.name)
+	}
+
+	let mounted = current
+		.iter()
+		.filter(|volume| !contains(previous, volume))
+		.cloned()
+		.collect();
+	let unmounted = previous
+		.iter()
+		.filter(|volume| !contains(current, volume))
+		.cloned()
+		.collect();
+
+	(mounted, unmounted)
+}
+
+/// Watches for volumes being mounted or unmounted, emitting an event for each of them.
+///
+/// Locations on an unmounted volume are marked offline by the location manager's own online checks.
+///
+/// Volumes aren't listed while `paused_rx` is `true`, so changes made in the meantime are reported once it's `false` again.
+/// The watcher runs until the returned handle is aborted.
+pub(crate) fn watch_volumes(
+	event_bus_tx: broadcast::Sender<CoreEvent>,
+	paused_rx: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+	tokio::spawn(async move {
+		let mut volumes: Option<Vec<Volume>> = None;
+		let mut interval = time::interval(VOLUME_POLL_INTERVAL);
+
+		loop {
+			interval.tick().await;
+
+			if *paused_rx.borrow() {
+				continue;
+			}
+
+			// Listing volumes is blocking, and may even spawn a process on Windows
+			let current = match task::spawn_blocking(get_volumes).await {
+				Ok(Ok(current)) => current,
+				Ok(Err(e)) => {
+					error!("Failed to list volumes: {e:#?}");
+					continue;
+				}
+				Err(e) => {
+					error!("Failed to join volume listing task: {e:#?}");
+					continue;
+				}
+			};
+
+			// The first listing is what was already mounted when the node started, so it isn't reported
+			if let Some(previous) = &volumes {
+				let (mounted, unmounted) = diff_volumes(previous, &current);
+
+				for volume in mounted {
+					let _ = event_bus_tx.send(CoreEvent::VolumeMounted { volume });
+				}
+				for volume in unmounted {
+					let _ = event_bus_tx.send(CoreEvent::VolumeUnmounted { volume });
+				}
+			}
+
+			volumes = Some(current);
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn volume(name: &str, mount_point: &str) -> Volume {
+		Volume {
+			name: name.to_string(),
+			mount_point: mount_point.to_string(),
+			is_removable: true,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn wmic_size_output() {
+		assert_eq!(
+			parse_wmic_size("Size  \r\r\n15923150848  \r\r\n\r\r\n"),
+			Some(15923150848)
+		);
+
+		// e.g. a card reader without a card in it
+		assert_eq!(parse_wmic_size("Size  \r\r\n  \r\r\n\r\r\n"), None);
+		assert_eq!(parse_wmic_size(""), None);
+	}
+
+	#[test]
+	fn mounted_and_unmounted_volumes() {
+		let root = volume("Macintosh HD", "/");
+		let usb = volume("USB", "/Volumes/USB");
+		let camera = volume("EOS_DIGITAL", "/Volumes/EOS_DIGITAL");
+
+		let (mounted, unmounted) = diff_volumes(&[root.clone(), usb.clone()], &[root, camera]);
+
+		assert_eq!(mounted.len(), 1);
+		assert_eq!(mounted[0].mount_point, "/Volumes/EOS_DIGITAL");
+		assert_eq!(unmounted.len(), 1);
+		assert_eq!(unmounted[0].name, "USB");
+	}
+}
+
 // #[test]
 // fn test_get_volumes() {
 //   let volumes = get_volumes()?;
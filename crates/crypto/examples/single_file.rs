@@ -43,7 +43,10 @@ async fn encrypt() {
 	.unwrap()];
 
 	// Create the header for the encrypted file
-	let header = FileHeader::new(LATEST_FILE_HEADER, ALGORITHM, keyslots).unwrap();
+	let mut header = FileHeader::new(LATEST_FILE_HEADER, ALGORITHM, keyslots).unwrap();
+
+	// Commit the header to the master key, so it's verified on decryption
+	header.add_key_commitment(&master_key).unwrap();
 
 	// Write the header to the file
 	header.write(&mut writer).await.unwrap();
@@ -0,0 +1,118 @@
+//! This module picks the block size that encrypts fastest on the current machine.
+//!
+//! Smaller blocks mean more calls (and length prefixes) per byte, while larger blocks mean larger buffers that don't fit in cache.
+//! Where the balance lies depends on the machine, so `recommend_block_size()` measures it instead of guessing.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let block_len = recommend_block_size();
+//!
+//! StreamEncryption::new(master_key, header.nonce, header.algorithm)?
+//! 	.encrypt_streams_framed(reader, writer, &aad, block_len)
+//! 	.await?;
+//! ```
+use std::{
+	sync::atomic::{AtomicUsize, Ordering},
+	time::{Duration, Instant},
+};
+
+use aead::Payload;
+
+use crate::{
+	crypto::stream::{Algorithm, StreamEncryption, MAX_FRAMED_BLOCK_LEN},
+	primitives::{
+		types::{Key, Nonce},
+		BLOCK_LEN,
+	},
+};
+
+/// The smallest block size that may be recommended.
+pub const MIN_RECOMMENDED_BLOCK_LEN: usize = BLOCK_LEN / 16;
+
+/// These are the block sizes that are benchmarked - every one is a power of two between `MIN_RECOMMENDED_BLOCK_LEN` and `MAX_FRAMED_BLOCK_LEN`.
+pub const CANDIDATE_BLOCK_LENS: [usize; 7] = [
+	BLOCK_LEN / 16,
+	BLOCK_LEN / 8,
+	BLOCK_LEN / 4,
+	BLOCK_LEN / 2,
+	BLOCK_LEN,
+	BLOCK_LEN * 2,
+	BLOCK_LEN * 4,
+];
+
+/// Every candidate encrypts this many bytes, which is a multiple of all of them so none of them end with a partial block.
+const SAMPLE_LEN: usize = BLOCK_LEN * 4;
+
+// zero means that nothing has been benchmarked yet
+static RECOMMENDED: AtomicUsize = AtomicUsize::new(0);
+
+/// This returns the candidate block size that encrypts the fastest on this machine.
+///
+/// The benchmark only runs once (it takes a fraction of a second in release builds), and the result is reused afterwards.
+///
+/// If the benchmark can't run, `BLOCK_LEN` is returned.
+#[must_use]
+pub fn recommend_block_size() -> usize {
+	match RECOMMENDED.load(Ordering::Relaxed) {
+		0 => {
+			let recommended = benchmark().unwrap_or(BLOCK_LEN);
+			RECOMMENDED.store(recommended, Ordering::Relaxed);
+			recommended
+		}
+		recommended => recommended,
+	}
+}
+
+fn benchmark() -> Option<usize> {
+	let sample = vec![0x5A; SAMPLE_LEN];
+
+	CANDIDATE_BLOCK_LENS
+		.into_iter()
+		.map(|block_len| Some((time_encryption(&sample, block_len)?, block_len)))
+		.collect::<Option<Vec<_>>>()?
+		.into_iter()
+		.min_by_key(|(elapsed, _)| *elapsed)
+		.map(|(_, block_len)| block_len)
+}
+
+/// This encrypts the sample in blocks of `block_len`, the same way as `StreamEncryption::encrypt_streams_framed()` (minus the I/O).
+fn time_encryption(sample: &[u8], block_len: usize) -> Option<Duration> {
+	debug_assert!(block_len <= MAX_FRAMED_BLOCK_LEN);
+
+	let algorithm = Algorithm::XChaCha20Poly1305;
+	let mut stream =
+		StreamEncryption::new(Key::generate(), Nonce::generate(algorithm).ok()?, algorithm).ok()?;
+
+	let start = Instant::now();
+
+	for block in sample.chunks(block_len) {
+		stream
+			.encrypt_next(Payload {
+				aad: &[],
+				msg: block,
+			})
+			.ok()?;
+	}
+
+	stream.encrypt_last(Payload { aad: &[], msg: &[] }).ok()?;
+
+	Some(start.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn recommendation_is_a_candidate() {
+		let block_len = recommend_block_size();
+
+		assert!(CANDIDATE_BLOCK_LENS.contains(&block_len));
+		assert!(block_len.is_power_of_two());
+		assert!((MIN_RECOMMENDED_BLOCK_LEN..=MAX_FRAMED_BLOCK_LEN).contains(&block_len));
+
+		// the result is reused
+		assert_eq!(recommend_block_size(), block_len);
+	}
+}
@@ -0,0 +1,293 @@
+//! This module contains helpers for encrypting a file with a second, "duress" password that decrypts to decoy data.
+//!
+//! This is for plausible deniability: if a user is forced to give up a password, they can give up the duress password
+//! and only the decoy is revealed. Each password unwraps its own master key, and each master key decrypts its own body.
+//!
+//! Nothing in the clear says which keyslot belongs to which body:
+//!
+//! - The keyslots are written in a random order.
+//! - The order of the bodies is chosen by the master keys themselves (see `body_index()`), so it can only be worked out once a key has been unwrapped.
+//! - Neither body can be decrypted (or told apart from random data) without its key.
+//!
+//! This feature is opt-in, and it has limits that users should be made aware of:
+//!
+//! - It's obvious that a file has two bodies. The file is larger than one body would make it, the length of the first body is stored in the clear,
+//! and a file in this format can't be decrypted by `DecryptReader::open()`. What's hidden is which password opens the real data.
+//! - The lengths of both bodies are revealed, so a decoy of a believable size should be chosen.
+//! - The header can't commit to a single master key, so keyslots aren't protected against being swapped out.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let mut writer = File::create("test.encrypted").await?;
+//! encrypt_with_decoy(real, decoy, &mut writer, password, duress_password, EncryptOptions::default()).await?;
+//!
+//! // This writes the real data with `password`, and the decoy with `duress_password`
+//! let mut reader = File::open("test.encrypted").await?;
+//! decrypt_with_decoy(&mut reader, &mut output, password).await?;
+//! ```
+use std::io::SeekFrom;
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::{
+	crypto::{
+		file::{check_strength, EncryptOptions},
+		stream::{StreamDecryption, StreamEncryption},
+	},
+	header::{file::FileHeader, keyslot::Keyslot},
+	primitives::{
+		types::{Key, Salt},
+		DECOY_BODY_CONTEXT, LATEST_FILE_HEADER, LATEST_KEYSLOT,
+	},
+	Error, Protected, Result,
+};
+
+/// This is the length of the first body's length, which is stored (as a little-endian `u64`) right after the header.
+const BODY_LEN_LEN: usize = std::mem::size_of::<u64>();
+
+/// This returns which body (0 or 1) a master key decrypts.
+///
+/// It's derived from the key, so the body can be found once the key has been unwrapped, but not before.
+fn body_index(master_key: &Key) -> usize {
+	usize::from(blake3::derive_key(DECOY_BODY_CONTEXT, master_key.expose())[0] & 1)
+}
+
+/// This encrypts `reader` and `decoy` as two bodies of the same file, under two fresh master keys.
+///
+/// `password` unlocks the real data, and `decoy_password` unlocks the decoy. They must be different, otherwise
+/// only one of the bodies could ever be reached.
+///
+/// The writer must be seekable, as the length of the first body is written in front of it once it's known.
+///
+/// You receive an error if the passwords are the same, or if either is weaker than `options.min_strength`.
+pub async fn encrypt_with_decoy<R, D, W>(
+	reader: R,
+	decoy: D,
+	writer: &mut W,
+	password: Protected<Vec<u8>>,
+	decoy_password: Protected<Vec<u8>>,
+	options: EncryptOptions,
+) -> Result<FileHeader>
+where
+	R: AsyncReadExt + Unpin + Send,
+	D: AsyncReadExt + Unpin + Send,
+	W: AsyncWriteExt + AsyncSeekExt + Unpin + Send,
+{
+	if password.expose() == decoy_password.expose() {
+		return Err(Error::DecoyPasswordMatches);
+	}
+
+	check_strength(&password, options.min_strength)?;
+	check_strength(&decoy_password, options.min_strength)?;
+
+	// the two keys have to select different bodies, which takes two attempts on average
+	let master_key = Key::generate();
+	let decoy_master_key = loop {
+		let key = Key::generate();
+		if body_index(&key) != body_index(&master_key) {
+			break key;
+		}
+	};
+
+	let mut keyslots = Vec::with_capacity(2);
+	for (password, master_key) in [(password, &master_key), (decoy_password, &decoy_master_key)] {
+		let content_salt = Salt::generate();
+		let hashing_algorithm = options.hashing_algorithm;
+		let hashed_password = tokio::task::spawn_blocking(move || {
+			hashing_algorithm.hash(password, content_salt, None)
+		})
+		.await
+		.map_err(|_| Error::PasswordHash)?
+		.map_err(|_| Error::PasswordHash)?;
+
+		keyslots.push(
+			Keyslot::new(
+				LATEST_KEYSLOT,
+				options.algorithm,
+				options.hashing_algorithm,
+				content_salt,
+				hashed_password,
+				master_key.clone(),
+			)
+			.await?,
+		);
+	}
+
+	// the keyslots are always created in the same order, so they're written in a random one
+	if rand::random() {
+		keyslots.reverse();
+	}
+
+	let header = FileHeader::new(LATEST_FILE_HEADER, options.algorithm, keyslots)?;
+	let aad = header.generate_aad();
+	header.write(writer).await?;
+
+	// this is overwritten with the first body's length once it has been written
+	let len_position = writer.stream_position().await?;
+	writer.write_all(&[0u8; BODY_LEN_LEN]).await?;
+
+	if body_index(&master_key) == 0 {
+		write_bodies(
+			writer,
+			&header,
+			&aad,
+			len_position,
+			(master_key, reader),
+			(decoy_master_key, decoy),
+		)
+		.await?;
+	} else {
+		write_bodies(
+			writer,
+			&header,
+			&aad,
+			len_position,
+			(decoy_master_key, decoy),
+			(master_key, reader),
+		)
+		.await?;
+	}
+
+	Ok(header)
+}
+
+/// This encrypts both bodies in order, and writes the first one's length at `len_position` once it's known.
+async fn write_bodies<A, B, W>(
+	writer: &mut W,
+	header: &FileHeader,
+	aad: &[u8],
+	len_position: u64,
+	first: (Key, A),
+	second: (Key, B),
+) -> Result<()>
+where
+	A: AsyncReadExt + Unpin + Send,
+	B: AsyncReadExt + Unpin + Send,
+	W: AsyncWriteExt + AsyncSeekExt + Unpin + Send,
+{
+	StreamEncryption::new(first.0, header.nonce, header.algorithm)?
+		.encrypt_streams(first.1, &mut *writer, aad)
+		.await?;
+
+	let second_position = writer.stream_position().await?;
+	let first_len = second_position - len_position - BODY_LEN_LEN as u64;
+
+	writer.seek(SeekFrom::Start(len_position)).await?;
+	writer.write_all(&first_len.to_le_bytes()).await?;
+	writer.seek(SeekFrom::Start(second_position)).await?;
+
+	StreamEncryption::new(second.0, header.nonce, header.algorithm)?
+		.encrypt_streams(second.1, &mut *writer, aad)
+		.await
+}
+
+/// This decrypts whichever body of a file from `encrypt_with_decoy()` the password unlocks, and writes it to the writer.
+///
+/// You receive an error if the password doesn't match either keyslot.
+pub async fn decrypt_with_decoy<R, W>(
+	reader: &mut R,
+	writer: &mut W,
+	password: Protected<Vec<u8>>,
+) -> Result<FileHeader>
+where
+	R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+	W: AsyncWriteExt + Unpin + Send,
+{
+	let (header, aad) = FileHeader::from_reader(reader).await?;
+	let master_key = header.decrypt_master_key(password).await?;
+
+	let mut first_len = [0u8; BODY_LEN_LEN];
+	reader.read_exact(&mut first_len).await?;
+	let first_len = u64::from_le_bytes(first_len);
+
+	let decryptor = StreamDecryption::new(master_key.clone(), header.nonce, header.algorithm)?;
+
+	if body_index(&master_key) == 0 {
+		decryptor
+			.decrypt_streams((&mut *reader).take(first_len), writer, &aad)
+			.await?;
+	} else {
+		#[allow(clippy::cast_possible_wrap)]
+		reader.seek(SeekFrom::Current(first_len as i64)).await?;
+		decryptor.decrypt_streams(reader, writer, &aad).await?;
+	}
+
+	Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+
+	const REAL: &[u8] = b"the real data";
+	const DECOY: &[u8] = b"a shopping list, with more items on it";
+
+	fn password(password: &[u8]) -> Protected<Vec<u8>> {
+		Protected::new(password.to_vec())
+	}
+
+	#[tokio::test]
+	async fn encrypt_and_decrypt_with_decoy() {
+		let mut writer = Cursor::new(Vec::new());
+
+		encrypt_with_decoy(
+			REAL,
+			DECOY,
+			&mut writer,
+			password(b"real password"),
+			password(b"duress password"),
+			EncryptOptions::default(),
+		)
+		.await
+		.unwrap();
+
+		for (password, expected) in [
+			(password(b"real password"), REAL),
+			(password(b"duress password"), DECOY),
+		] {
+			let mut reader = Cursor::new(writer.get_ref().clone());
+			let mut output = Vec::new();
+
+			decrypt_with_decoy(&mut reader, &mut output, password)
+				.await
+				.unwrap();
+
+			assert_eq!(output, expected);
+		}
+
+		let mut reader = Cursor::new(writer.into_inner());
+		let result = decrypt_with_decoy(&mut reader, &mut Vec::new(), password(b"wrong")).await;
+		assert!(matches!(result, Err(Error::IncorrectPassword)));
+	}
+
+	#[tokio::test]
+	async fn passwords_must_differ() {
+		let mut writer = Cursor::new(Vec::new());
+
+		let result = encrypt_with_decoy(
+			REAL,
+			DECOY,
+			&mut writer,
+			password(b"real password"),
+			password(b"real password"),
+			EncryptOptions::default(),
+		)
+		.await;
+
+		assert!(matches!(result, Err(Error::DecoyPasswordMatches)));
+		assert!(writer.into_inner().is_empty());
+	}
+
+	#[test]
+	fn keys_select_both_bodies() {
+		let indices = (0..64)
+			.map(|_| body_index(&Key::generate()))
+			.collect::<Vec<_>>();
+
+		assert!(indices.contains(&0));
+		assert!(indices.contains(&1));
+	}
+}
@@ -0,0 +1,366 @@
+//! This module contains helpers that encrypt and decrypt a whole file with a user's password, in a single call.
+//!
+//! They take care of the master key, the keyslot and the header, so the caller only deals with the plaintext and the encrypted file.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let reader = File::open("test").await?;
+//! let mut writer = File::create("test.encrypted").await?;
+//!
+//! // Refuse the password if it's easy to guess
+//! let options = EncryptOptions {
+//! 	min_strength: Some(Strength::Fair),
+//! 	..Default::default()
+//! };
+//!
+//! encrypt(reader, &mut writer, password.clone(), options).await?;
+//!
+//! // Later on
+//! let reader = File::open("test.encrypted").await?;
+//! let mut writer = File::create("test.decrypted").await?;
+//!
+//! decrypt(reader, &mut writer, password).await?;
+//! ```
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::{
+	crypto::{
+		bench::recommend_block_size,
+		reader::DecryptReader,
+		stream::{Algorithm, Framing, StreamEncryption},
+		tee::Tee,
+	},
+	header::{file::FileHeader, keyslot::Keyslot},
+	keys::hashing::{HashingAlgorithm, Params},
+	password::{estimate_strength, Strength},
+	primitives::{
+		types::{Key, Salt},
+		BLOCK_LEN, LATEST_FILE_HEADER, LATEST_KEYSLOT,
+	},
+	Error, Protected, Result,
+};
+
+/// These are the settings that `encrypt()` uses.
+#[derive(Clone, Copy)]
+pub struct EncryptOptions {
+	pub algorithm: Algorithm,
+	pub hashing_algorithm: HashingAlgorithm,
+	/// If this is set, passwords that are rated below it (by `estimate_strength()`) are refused.
+	pub min_strength: Option<Strength>,
+	/// This is the size of the blocks that the body is encrypted in, which is benchmarked with `recommend_block_size()` if it isn't set.
+	///
+	/// It needs to be supported by `FileHeader::supports_block_len()`, so that it can be recorded in the header.
+	///
+	/// `encrypt_with_decoy()` doesn't use this, as its bodies are always encrypted in blocks of `BLOCK_LEN`.
+	pub block_len: Option<usize>,
+}
+
+impl Default for EncryptOptions {
+	fn default() -> Self {
+		Self {
+			algorithm: Algorithm::XChaCha20Poly1305,
+			hashing_algorithm: HashingAlgorithm::Argon2id(Params::Standard),
+			min_strength: None,
+			block_len: None,
+		}
+	}
+}
+
+/// This encrypts everything from the reader under a fresh master key, and writes the header followed by the body to the writer.
+///
+/// The master key is stored in a single keyslot, which is unlocked with the password.
+///
+/// The body uses `LengthPrefixed` framing, and the block size is recorded in the header (which is why `LATEST_FILE_HEADER` is used, as older headers can't store either).
+///
+/// You receive an error if the password is weaker than `options.min_strength`, or if `options.block_len` can't be recorded in the header - these are checked before anything is written.
+pub async fn encrypt<R, W>(
+	reader: R,
+	writer: &mut W,
+	password: Protected<Vec<u8>>,
+	options: EncryptOptions,
+) -> Result<FileHeader>
+where
+	R: AsyncReadExt + Unpin + Send,
+	W: AsyncWriteExt + Unpin + Send,
+{
+	check_strength(&password, options.min_strength)?;
+
+	let block_len = match options.block_len {
+		Some(block_len) => block_len,
+		// the benchmark is CPU-bound, so it's kept off of the async threads (it only runs the first time)
+		None => tokio::task::spawn_blocking(recommend_block_size)
+			.await
+			.unwrap_or(BLOCK_LEN),
+	};
+	if !FileHeader::supports_block_len(block_len) {
+		return Err(Error::UnsupportedBlockLen(block_len));
+	}
+
+	let master_key = Key::generate();
+	let content_salt = Salt::generate();
+	let hashed_password = options
+		.hashing_algorithm
+		.hash(password, content_salt, None)
+		.map_err(|_| Error::PasswordHash)?;
+
+	let mut header = FileHeader::new(
+		LATEST_FILE_HEADER,
+		options.algorithm,
+		vec![
+			Keyslot::new(
+				LATEST_KEYSLOT,
+				options.algorithm,
+				options.hashing_algorithm,
+				content_salt,
+				hashed_password,
+				master_key.clone(),
+			)
+			.await?,
+		],
+	)?;
+
+	header.framing = Framing::LengthPrefixed;
+	header.block_len = Some(block_len);
+	header.add_key_commitment(&master_key)?;
+	header.write(writer).await?;
+
+	StreamEncryption::new(master_key, header.nonce, header.algorithm)?
+		.encrypt_streams_framed(reader, &mut *writer, &header.generate_aad(), block_len)
+		.await?;
+
+	Ok(header)
+}
+
+/// This decrypts a file that was encrypted with `encrypt()`, and writes the plaintext to the writer.
+///
+/// It's a thin wrapper around `DecryptReader::open()`, for when the plaintext is wanted in full rather than read through (or seeked) bit by bit.
+///
+/// You receive an error if the header is invalid or if the password doesn't match - these are checked before anything is written.
+/// Blocks are written as soon as they're decrypted though, so if the body is corrupted, the writer will contain the plaintext up until that point.
+pub async fn decrypt<R, W>(
+	reader: R,
+	writer: &mut W,
+	password: Protected<Vec<u8>>,
+) -> Result<FileHeader>
+where
+	R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+	W: AsyncWriteExt + Unpin + Send,
+{
+	let (header, mut reader) = DecryptReader::open(reader, password).await?;
+
+	tokio::io::copy(&mut reader, writer).await?;
+	writer.flush().await?;
+
+	Ok(header)
+}
+
+/// These are the BLAKE3 hashes of a file that `encrypt_with_digests()` encrypted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Digests {
+	/// This is the hash of the plaintext, which can be used to address the content.
+	pub plaintext: blake3::Hash,
+	/// This is the hash of everything that was written (the header and the encrypted body), for integrity manifests.
+	pub ciphertext: blake3::Hash,
+}
+
+/// This encrypts in the same way as `encrypt()`, while hashing the plaintext and the ciphertext.
+///
+/// Both are hashed as they pass through, so the reader is still only read once.
+pub async fn encrypt_with_digests<R, W>(
+	reader: R,
+	writer: &mut W,
+	password: Protected<Vec<u8>>,
+	options: EncryptOptions,
+) -> Result<(FileHeader, Digests)>
+where
+	R: AsyncReadExt + Unpin + Send,
+	W: AsyncWriteExt + Unpin + Send,
+{
+	let mut reader = Tee::new(reader);
+	let mut writer = Tee::new(writer);
+
+	let header = encrypt(&mut reader, &mut writer, password, options).await?;
+
+	Ok((
+		header,
+		Digests {
+			plaintext: reader.finalize(),
+			ciphertext: writer.finalize(),
+		},
+	))
+}
+
+/// This refuses passwords that are rated below `min_strength` (if it's set).
+pub(crate) fn check_strength(
+	password: &Protected<Vec<u8>>,
+	min_strength: Option<Strength>,
+) -> Result<()> {
+	match min_strength {
+		Some(min_strength)
+			if estimate_strength(&String::from_utf8_lossy(password.expose())).rating
+				< min_strength =>
+		{
+			Err(Error::WeakPassword)
+		}
+		_ => Ok(()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+	use crate::crypto::stream::MAX_FRAMED_BLOCK_LEN;
+
+	#[tokio::test]
+	async fn encrypt_with_weak_password() {
+		let mut writer = Cursor::new(Vec::new());
+
+		let options = EncryptOptions {
+			min_strength: Some(Strength::Fair),
+			..Default::default()
+		};
+
+		let result = encrypt(
+			[0x5A; 32].as_slice(),
+			&mut writer,
+			Protected::new(b"password123".to_vec()),
+			options,
+		)
+		.await;
+
+		assert!(matches!(result, Err(Error::WeakPassword)));
+		assert!(writer.into_inner().is_empty());
+	}
+
+	#[tokio::test]
+	async fn encrypt_with_block_len() {
+		let mut writer = Cursor::new(Vec::new());
+		let plaintext = vec![0x5A; BLOCK_LEN / 4];
+
+		let options = EncryptOptions {
+			block_len: Some(BLOCK_LEN / 16),
+			..Default::default()
+		};
+
+		encrypt(
+			plaintext.as_slice(),
+			&mut writer,
+			Protected::new(b"password".to_vec()),
+			options,
+		)
+		.await
+		.unwrap();
+
+		writer.rewind().await.unwrap();
+
+		let (header, mut reader) =
+			DecryptReader::open(writer, Protected::new(b"password".to_vec()))
+				.await
+				.unwrap();
+
+		assert!(header.framing == Framing::LengthPrefixed);
+		assert_eq!(header.block_len, Some(BLOCK_LEN / 16));
+
+		let mut output = Vec::new();
+		reader.read_to_end(&mut output).await.unwrap();
+		assert_eq!(output, plaintext);
+	}
+
+	#[tokio::test]
+	async fn encrypt_with_unsupported_block_len() {
+		// too small, not a power of two, and too big
+		for block_len in [16, BLOCK_LEN - 16, MAX_FRAMED_BLOCK_LEN * 2] {
+			let mut writer = Cursor::new(Vec::new());
+
+			let options = EncryptOptions {
+				block_len: Some(block_len),
+				..Default::default()
+			};
+
+			let result = encrypt(
+				[0x5A; 32].as_slice(),
+				&mut writer,
+				Protected::new(b"password".to_vec()),
+				options,
+			)
+			.await;
+
+			assert!(matches!(result, Err(Error::UnsupportedBlockLen(len)) if len == block_len));
+			assert!(writer.into_inner().is_empty());
+		}
+	}
+
+	#[tokio::test]
+	async fn encrypt_and_decrypt_file() {
+		let dir = tempfile::tempdir().unwrap();
+		let (plaintext_path, encrypted_path, decrypted_path) = (
+			dir.path().join("plaintext"),
+			dir.path().join("plaintext.encrypted"),
+			dir.path().join("plaintext.decrypted"),
+		);
+
+		let plaintext = vec![0x5A; BLOCK_LEN * 3 + 7];
+		tokio::fs::write(&plaintext_path, &plaintext).await.unwrap();
+
+		let options = EncryptOptions {
+			block_len: Some(BLOCK_LEN),
+			..Default::default()
+		};
+
+		let encrypted_header = encrypt(
+			tokio::fs::File::open(&plaintext_path).await.unwrap(),
+			&mut tokio::fs::File::create(&encrypted_path).await.unwrap(),
+			Protected::new(b"password".to_vec()),
+			options,
+		)
+		.await
+		.unwrap();
+
+		let wrong_password = decrypt(
+			tokio::fs::File::open(&encrypted_path).await.unwrap(),
+			&mut tokio::fs::File::create(&decrypted_path).await.unwrap(),
+			Protected::new(b"wrong password".to_vec()),
+		)
+		.await;
+		assert!(wrong_password.is_err());
+		assert!(tokio::fs::read(&decrypted_path).await.unwrap().is_empty());
+
+		let decrypted_header = decrypt(
+			tokio::fs::File::open(&encrypted_path).await.unwrap(),
+			&mut tokio::fs::File::create(&decrypted_path).await.unwrap(),
+			Protected::new(b"password".to_vec()),
+		)
+		.await
+		.unwrap();
+
+		let decrypted = tokio::fs::read(&decrypted_path).await.unwrap();
+		assert_eq!(decrypted, plaintext);
+		assert!(decrypted_header.nonce == encrypted_header.nonce);
+	}
+
+	#[tokio::test]
+	async fn encrypt_with_matching_digests() {
+		let mut writer = Cursor::new(Vec::new());
+		let plaintext = vec![0x5A; BLOCK_LEN * 2 + 7];
+
+		let options = EncryptOptions {
+			block_len: Some(BLOCK_LEN / 16),
+			..Default::default()
+		};
+
+		let (_, digests) = encrypt_with_digests(
+			plaintext.as_slice(),
+			&mut writer,
+			Protected::new(b"password".to_vec()),
+			options,
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(digests.plaintext, blake3::hash(&plaintext));
+		assert_eq!(digests.ciphertext, blake3::hash(writer.get_ref()));
+	}
+}
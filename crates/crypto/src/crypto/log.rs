@@ -0,0 +1,501 @@
+//! This module contains an append-only encrypted log, for data that keeps growing (e.g. a journal).
+//!
+//! An AEAD stream can't be resumed once its last block has been written, so every append is encrypted as its own
+//! segment: a separate STREAM, under a key that's derived from the root key, the segment's index and a random salt that's
+//! stored at the start of the segment. Earlier segments are never rewritten, so appending costs the same no matter how large the log is.
+//!
+//! Each segment is followed by a checkpoint, which is a flush point: the number of segments so far and the segment's length,
+//! authenticated with a key derived from the root key. Appending resumes from the checkpoint at the end of the log.
+//!
+//! The layout is:
+//!
+//! - The magic bytes, the algorithm and the nonce (which are also the AAD for every segment)
+//! - A checkpoint for zero segments, which is used to check the key
+//! - For every segment: its length, its salt, its ciphertext and its checkpoint
+//!
+//! If an append is interrupted, everything up to the last complete checkpoint can still be read, and the next append
+//! overwrites the incomplete segment. It does so with a fresh salt, so the key and nonce of the interrupted segment are never
+//! used for different plaintext. As that looks exactly like a log that was cut off at a flush point, truncating a log
+//! to one of its flush points can't be detected.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let file = OpenOptions::new().read(true).write(true).create(true).open("journal.log").await?;
+//! let mut log = LogWriter::create(file, root_key.clone(), Algorithm::XChaCha20Poly1305).await?;
+//! log.append(b"first entry".as_slice()).await?;
+//!
+//! // Later on, more entries are added without touching the ones that are already there
+//! let file = OpenOptions::new().read(true).write(true).open("journal.log").await?;
+//! let mut log = LogWriter::open(file, root_key.clone()).await?;
+//! log.append(b"second entry".as_slice()).await?;
+//!
+//! let mut output = Vec::new();
+//! decrypt_log(&mut File::open("journal.log").await?, &mut output, root_key).await?;
+//! ```
+use std::io::SeekFrom;
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use zeroize::Zeroize;
+
+use crate::{
+	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
+	primitives::{
+		to_array,
+		types::{Key, Nonce, Salt},
+		LOG_CHECKPOINT_CONTEXT, LOG_SEGMENT_KEY_CONTEXT, SALT_LEN,
+	},
+	Error, Result,
+};
+
+/// These are the first bytes of every log, so they can't be mistaken for an encrypted file.
+pub const LOG_MAGIC_BYTES: [u8; 7] = [0x62, 0x61, 0x6C, 0x6C, 0x6C, 0x6F, 0x67];
+
+const SEGMENT_LEN_LEN: usize = std::mem::size_of::<u64>();
+
+/// A checkpoint is the segment count, the segment's length and a 32 byte MAC.
+const CHECKPOINT_LEN: usize = 8 + 8 + 32;
+
+/// This derives the key of a segment, from the root key, the segment's index and the salt that's stored at the start of the segment.
+///
+/// Every append generates a new salt, so a segment that overwrites an interrupted one is encrypted under a different key.
+fn segment_key(root_key: &Key, segment: u64, salt: Salt) -> Key {
+	let mut input = root_key.expose().to_vec();
+	input.extend_from_slice(&segment.to_le_bytes());
+	input.extend_from_slice(&salt);
+	let key = blake3::derive_key(LOG_SEGMENT_KEY_CONTEXT, &input);
+
+	input.zeroize();
+
+	Key::new(key)
+}
+
+/// This is the part of the log that's written before any segments.
+struct LogHeader {
+	algorithm: Algorithm,
+	nonce: Nonce,
+}
+
+impl LogHeader {
+	fn to_bytes(&self) -> Vec<u8> {
+		[
+			LOG_MAGIC_BYTES.as_ref(),
+			&self.algorithm.to_bytes(),
+			&self.nonce,
+		]
+		.concat()
+	}
+
+	async fn from_reader<R>(reader: &mut R) -> Result<Self>
+	where
+		R: AsyncReadExt + Unpin + Send,
+	{
+		let mut magic_bytes = [0u8; LOG_MAGIC_BYTES.len()];
+		reader.read_exact(&mut magic_bytes).await?;
+
+		if magic_bytes != LOG_MAGIC_BYTES {
+			return Err(Error::Serialization);
+		}
+
+		let mut algorithm = [0u8; 2];
+		reader.read_exact(&mut algorithm).await?;
+		let algorithm = Algorithm::from_bytes(algorithm)?;
+
+		let mut nonce = vec![0u8; algorithm.nonce_len()];
+		reader.read_exact(&mut nonce).await?;
+
+		Ok(Self {
+			algorithm,
+			nonce: Nonce::try_from(nonce)?,
+		})
+	}
+}
+
+/// This authenticates the checkpoints of a log.
+struct Checkpoints {
+	key: [u8; 32],
+	aad: Vec<u8>,
+}
+
+impl Checkpoints {
+	fn new(root_key: &Key, header: &LogHeader) -> Self {
+		Self {
+			key: blake3::derive_key(LOG_CHECKPOINT_CONTEXT, root_key.expose()),
+			aad: header.to_bytes(),
+		}
+	}
+
+	fn mac(&self, segments: u64, segment_len: u64) -> blake3::Hash {
+		let mut hasher = blake3::Hasher::new_keyed(&self.key);
+		hasher.update(&self.aad);
+		hasher.update(&segments.to_le_bytes());
+		hasher.update(&segment_len.to_le_bytes());
+		hasher.finalize()
+	}
+
+	fn to_bytes(&self, segments: u64, segment_len: u64) -> [u8; CHECKPOINT_LEN] {
+		let mut checkpoint = [0u8; CHECKPOINT_LEN];
+		checkpoint[..8].copy_from_slice(&segments.to_le_bytes());
+		checkpoint[8..16].copy_from_slice(&segment_len.to_le_bytes());
+		checkpoint[16..].copy_from_slice(self.mac(segments, segment_len).as_bytes());
+		checkpoint
+	}
+
+	/// This returns the segment count and the segment length of a checkpoint, if it's authentic.
+	fn verify(&self, checkpoint: &[u8; CHECKPOINT_LEN]) -> Option<(u64, u64)> {
+		let segments = u64::from_le_bytes(to_array(&checkpoint[..8]).ok()?);
+		let segment_len = u64::from_le_bytes(to_array(&checkpoint[8..16]).ok()?);
+		let mac = blake3::Hash::from(to_array::<32>(&checkpoint[16..]).ok()?);
+
+		// `blake3::Hash` compares in constant time
+		(self.mac(segments, segment_len) == mac).then_some((segments, segment_len))
+	}
+
+	/// This reads the next segment's length and skips over it, and returns the length if the segment is complete.
+	///
+	/// The reader is left right after the segment's checkpoint.
+	async fn next_segment<R>(&self, reader: &mut R, segments: u64) -> Result<Option<u64>>
+	where
+		R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+	{
+		let mut segment_len = [0u8; SEGMENT_LEN_LEN];
+		if read_exact_or_eof(reader, &mut segment_len).await?.is_none() {
+			return Ok(None);
+		}
+		let segment_len = u64::from_le_bytes(segment_len);
+
+		let Ok(offset) = i64::try_from(segment_len) else {
+			return Ok(None);
+		};
+		reader.seek(SeekFrom::Current(offset)).await?;
+
+		let mut checkpoint = [0u8; CHECKPOINT_LEN];
+		if read_exact_or_eof(reader, &mut checkpoint).await?.is_none() {
+			return Ok(None);
+		}
+
+		Ok(self
+			.verify(&checkpoint)
+			.filter(|checkpoint| *checkpoint == (segments + 1, segment_len))
+			.map(|_| segment_len))
+	}
+}
+
+/// This appends segments to an encrypted log.
+pub struct LogWriter<W> {
+	inner: W,
+	root_key: Key,
+	header: LogHeader,
+	checkpoints: Checkpoints,
+	segments: u64,
+}
+
+impl<W> LogWriter<W>
+where
+	W: AsyncReadExt + AsyncWriteExt + AsyncSeekExt + Unpin + Send,
+{
+	/// This writes a new, empty log at the start of `inner`.
+	pub async fn create(mut inner: W, root_key: Key, algorithm: Algorithm) -> Result<Self> {
+		let header = LogHeader {
+			algorithm,
+			nonce: Nonce::generate(algorithm)?,
+		};
+		let checkpoints = Checkpoints::new(&root_key, &header);
+
+		inner.seek(SeekFrom::Start(0)).await?;
+		inner.write_all(&header.to_bytes()).await?;
+		inner.write_all(&checkpoints.to_bytes(0, 0)).await?;
+		inner.flush().await?;
+
+		Ok(Self {
+			inner,
+			root_key,
+			header,
+			checkpoints,
+			segments: 0,
+		})
+	}
+
+	/// This opens an existing log, so more segments can be appended to it.
+	///
+	/// It resumes from the checkpoint at the end of the log. If there isn't a valid one (as the last append was interrupted),
+	/// it resumes from the last complete segment instead.
+	///
+	/// You receive `Error::Decrypt` if the root key isn't the one that the log was created with.
+	pub async fn open(mut inner: W, root_key: Key) -> Result<Self> {
+		inner.seek(SeekFrom::Start(0)).await?;
+		let header = LogHeader::from_reader(&mut inner).await?;
+		let checkpoints = Checkpoints::new(&root_key, &header);
+		let body_start = read_first_checkpoint(&mut inner, &checkpoints).await?;
+
+		// the fast path, where the last append was completed
+		let end = inner.seek(SeekFrom::End(0)).await?;
+		let mut checkpoint = [0u8; CHECKPOINT_LEN];
+		inner
+			.seek(SeekFrom::Start(end.saturating_sub(CHECKPOINT_LEN as u64)))
+			.await?;
+		inner.read_exact(&mut checkpoint).await?;
+
+		let segments = match checkpoints.verify(&checkpoint) {
+			Some((segments, _)) => segments,
+			None => {
+				inner.seek(SeekFrom::Start(body_start)).await?;
+
+				let mut segments = 0;
+				let mut last_flush_point = body_start;
+				while checkpoints
+					.next_segment(&mut inner, segments)
+					.await?
+					.is_some()
+				{
+					segments += 1;
+					last_flush_point = inner.stream_position().await?;
+				}
+
+				inner.seek(SeekFrom::Start(last_flush_point)).await?;
+				segments
+			}
+		};
+
+		Ok(Self {
+			inner,
+			root_key,
+			header,
+			checkpoints,
+			segments,
+		})
+	}
+
+	/// This encrypts `reader` as a new segment at the end of the log, and writes its checkpoint.
+	///
+	/// Once this returns, the segment is flushed and will be there when the log is next opened.
+	pub async fn append<R>(&mut self, reader: R) -> Result<()>
+	where
+		R: AsyncReadExt + Unpin + Send,
+	{
+		let start = self.inner.stream_position().await?;
+		self.inner.write_all(&[0u8; SEGMENT_LEN_LEN]).await?;
+
+		let salt = Salt::generate();
+		self.inner.write_all(&salt).await?;
+
+		StreamEncryption::new(
+			segment_key(&self.root_key, self.segments, salt),
+			self.header.nonce,
+			self.header.algorithm,
+		)?
+		.encrypt_streams(reader, &mut self.inner, &self.checkpoints.aad)
+		.await?;
+
+		let end = self.inner.stream_position().await?;
+		let segment_len = end - start - SEGMENT_LEN_LEN as u64;
+
+		// the length is only known once the segment has been encrypted
+		self.inner.seek(SeekFrom::Start(start)).await?;
+		self.inner.write_all(&segment_len.to_le_bytes()).await?;
+		self.inner.seek(SeekFrom::Start(end)).await?;
+
+		self.inner
+			.write_all(&self.checkpoints.to_bytes(self.segments + 1, segment_len))
+			.await?;
+		self.inner.flush().await?;
+
+		self.segments += 1;
+
+		Ok(())
+	}
+
+	/// This returns how many segments the log has.
+	pub const fn segments(&self) -> u64 {
+		self.segments
+	}
+
+	/// This consumes the `LogWriter`, and returns the underlying writer.
+	pub fn into_inner(self) -> W {
+		self.inner
+	}
+}
+
+/// This decrypts every complete segment of a log, in order, and writes the plaintext to `writer`.
+///
+/// It returns how many segments were decrypted.
+///
+/// You receive `Error::Decrypt` if the root key isn't the one that the log was created with, or if a segment has been modified.
+pub async fn decrypt_log<R, W>(reader: &mut R, mut writer: W, root_key: Key) -> Result<u64>
+where
+	R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+	W: AsyncWriteExt + Unpin + Send,
+{
+	reader.seek(SeekFrom::Start(0)).await?;
+	let header = LogHeader::from_reader(reader).await?;
+	let checkpoints = Checkpoints::new(&root_key, &header);
+	read_first_checkpoint(reader, &checkpoints).await?;
+
+	let mut segments = 0;
+	while let Some(segment_len) = checkpoints.next_segment(reader, segments).await? {
+		// the segment is only decrypted once we know that it's complete
+		let end = reader.stream_position().await?;
+		reader
+			.seek(SeekFrom::Start(end - CHECKPOINT_LEN as u64 - segment_len))
+			.await?;
+
+		let ciphertext_len = segment_len
+			.checked_sub(SALT_LEN as u64)
+			.ok_or(Error::Serialization)?;
+
+		let mut salt = [0u8; SALT_LEN];
+		reader.read_exact(&mut salt).await?;
+
+		StreamDecryption::new(
+			segment_key(&root_key, segments, Salt(salt)),
+			header.nonce,
+			header.algorithm,
+		)?
+		.decrypt_streams(
+			(&mut *reader).take(ciphertext_len),
+			&mut writer,
+			&checkpoints.aad,
+		)
+		.await?;
+
+		reader.seek(SeekFrom::Start(end)).await?;
+		segments += 1;
+	}
+
+	Ok(segments)
+}
+
+/// This checks the checkpoint that's written when a log is created, and returns where the first segment starts.
+async fn read_first_checkpoint<R>(reader: &mut R, checkpoints: &Checkpoints) -> Result<u64>
+where
+	R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+{
+	let mut checkpoint = [0u8; CHECKPOINT_LEN];
+	reader.read_exact(&mut checkpoint).await?;
+
+	match checkpoints.verify(&checkpoint) {
+		Some((0, 0)) => Ok(reader.stream_position().await?),
+		_ => Err(Error::Decrypt),
+	}
+}
+
+/// This fills `buf`, or returns `None` if the reader reaches EOF first.
+async fn read_exact_or_eof<R>(reader: &mut R, buf: &mut [u8]) -> Result<Option<()>>
+where
+	R: AsyncReadExt + Unpin + Send,
+{
+	match reader.read_exact(buf).await {
+		Ok(_) => Ok(Some(())),
+		Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+		Err(e) => Err(e.into()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+	use crate::primitives::BLOCK_LEN;
+
+	const ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+
+	#[tokio::test]
+	async fn append_after_reopening() {
+		let root_key = Key::generate();
+		let first = vec![0x5A; BLOCK_LEN + 17];
+		let second = b"appended later".to_vec();
+
+		let mut log = LogWriter::create(Cursor::new(Vec::new()), root_key.clone(), ALGORITHM)
+			.await
+			.unwrap();
+		log.append(first.as_slice()).await.unwrap();
+		let closed = log.into_inner().into_inner();
+
+		let mut log = LogWriter::open(Cursor::new(closed.clone()), root_key.clone())
+			.await
+			.unwrap();
+		assert_eq!(log.segments(), 1);
+		log.append(second.as_slice()).await.unwrap();
+		let mut file = log.into_inner();
+
+		// nothing that was already in the log has been rewritten
+		assert_eq!(file.get_ref()[..closed.len()], closed);
+
+		let mut output = Vec::new();
+		let segments = decrypt_log(&mut file, &mut output, root_key).await.unwrap();
+
+		assert_eq!(segments, 2);
+		assert_eq!(output, [first.as_slice(), &second].concat());
+	}
+
+	#[tokio::test]
+	async fn resume_after_interrupted_append() {
+		let root_key = Key::generate();
+
+		let mut log = LogWriter::create(Cursor::new(Vec::new()), root_key.clone(), ALGORITHM)
+			.await
+			.unwrap();
+		log.append(b"complete".as_slice()).await.unwrap();
+
+		// an append that never got to write its checkpoint
+		let mut file = log.into_inner().into_inner();
+		file.extend_from_slice(&[0xFF; 100]);
+
+		let mut log = LogWriter::open(Cursor::new(file), root_key.clone())
+			.await
+			.unwrap();
+		assert_eq!(log.segments(), 1);
+		log.append(b" and more".as_slice()).await.unwrap();
+
+		let mut output = Vec::new();
+		decrypt_log(&mut log.into_inner(), &mut output, root_key)
+			.await
+			.unwrap();
+		assert_eq!(output, b"complete and more");
+	}
+
+	#[tokio::test]
+	async fn reappended_segments_use_fresh_keys() {
+		let root_key = Key::generate();
+
+		let mut log = LogWriter::create(Cursor::new(Vec::new()), root_key.clone(), ALGORITHM)
+			.await
+			.unwrap();
+		log.append(b"complete".as_slice()).await.unwrap();
+		let base = log.into_inner().into_inner();
+
+		// as if the same append had been interrupted, and then tried again
+		let mut appended = Vec::new();
+		for _ in 0..2 {
+			let mut log = LogWriter::open(Cursor::new(base.clone()), root_key.clone())
+				.await
+				.unwrap();
+			log.append(b"same entry".as_slice()).await.unwrap();
+			appended.push(log.into_inner().into_inner());
+		}
+
+		assert_eq!(appended[0].len(), appended[1].len());
+		assert_ne!(appended[0][base.len()..], appended[1][base.len()..]);
+
+		for file in appended {
+			let mut output = Vec::new();
+			decrypt_log(&mut Cursor::new(file), &mut output, root_key.clone())
+				.await
+				.unwrap();
+			assert_eq!(output, b"completesame entry");
+		}
+	}
+
+	#[tokio::test]
+	async fn open_with_wrong_key() {
+		let log = LogWriter::create(Cursor::new(Vec::new()), Key::generate(), ALGORITHM)
+			.await
+			.unwrap();
+
+		assert!(matches!(
+			LogWriter::open(log.into_inner(), Key::generate()).await,
+			Err(Error::Decrypt)
+		));
+	}
+}
@@ -1,2 +1,12 @@
 //! This module contains all encryption and decryption items. These are used throughout the crate for all encryption/decryption needs.
+pub mod bench;
+pub mod decoy;
+pub mod file;
+pub mod log;
+pub mod progress;
+pub mod reader;
 pub mod stream;
+pub mod tee;
+
+// Comparing encrypted files only needs their headers, but it's an operation on the files themselves
+pub use crate::header::plaintext_commitment::same_plaintext;
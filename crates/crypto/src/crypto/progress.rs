@@ -0,0 +1,97 @@
+//! This module contains a wrapper that reports how much has been read from the stream it wraps.
+//!
+//! It allows progress to be reported while a stream is being encrypted or decrypted, without knowing anything about its blocks.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let len = file.metadata().await?.len();
+//! let reader = Progress::new(file, |read| println!("{}%", read * 100 / len));
+//!
+//! encryptor.encrypt_streams(reader, &mut writer, &aad).await?;
+//! ```
+use std::{
+	io,
+	pin::Pin,
+	task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// This passes reads through to the inner stream, and calls `on_progress` with the total amount of bytes that have been read so far.
+///
+/// `on_progress` is only called when something was read, so it isn't called at the end of the stream.
+pub struct Progress<R, F> {
+	inner: R,
+	read: u64,
+	on_progress: F,
+}
+
+impl<R, F> Progress<R, F>
+where
+	F: FnMut(u64),
+{
+	#[must_use]
+	pub const fn new(inner: R, on_progress: F) -> Self {
+		Self {
+			inner,
+			read: 0,
+			on_progress,
+		}
+	}
+
+	/// This returns the amount of bytes that have been read so far.
+	#[must_use]
+	pub const fn bytes_read(&self) -> u64 {
+		self.read
+	}
+
+	/// This consumes the `Progress`, and returns the underlying stream.
+	pub fn into_inner(self) -> R {
+		self.inner
+	}
+}
+
+impl<R: AsyncRead + Unpin, F: FnMut(u64) + Unpin> AsyncRead for Progress<R, F> {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		let filled = buf.filled().len();
+
+		let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+		if let Poll::Ready(Ok(())) = result {
+			let read = buf.filled().len() - filled;
+			if read > 0 {
+				this.read += read as u64;
+				(this.on_progress)(this.read);
+			}
+		}
+
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use tokio::io::AsyncReadExt;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn progress_is_reported_as_bytes_are_read() {
+		let mut reported = Vec::new();
+		let mut reader = Progress::new(Cursor::new(vec![0x5A; 10]), |read| reported.push(read));
+
+		let mut buf = [0u8; 4];
+		while reader.read(&mut buf).await.unwrap() != 0 {}
+
+		assert_eq!(reader.bytes_read(), 10);
+		drop(reader);
+		assert_eq!(reported, vec![4, 8, 10]);
+	}
+}
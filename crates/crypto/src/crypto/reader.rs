@@ -0,0 +1,925 @@
+//! This module contains a reader that decrypts a stream lazily, one block at a time.
+//!
+//! It's meant for consumers that only need part of a file at once (e.g. a media player), so nothing has to be decrypted up front.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let file = File::open("test.encrypted").await?;
+//!
+//! // The header is read, and the reader is left positioned at the start of the ciphertext
+//! let (header, mut reader) = DecryptReader::open(file, password).await?;
+//!
+//! // Only the blocks that are needed to fill the buffer are read and decrypted
+//! let mut buffer = vec![0u8; 4096];
+//! reader.read_exact(&mut buffer).await?;
+//!
+//! // Seeking works too, but blocks before the target offset have to be decrypted (and discarded) to get there
+//! reader.seek(SeekFrom::Start(1_000_000)).await?;
+//! reader.read_exact(&mut buffer).await?;
+//! ```
+use std::{
+	io::{self, SeekFrom},
+	num::NonZeroU32,
+	pin::Pin,
+	task::{ready, Context, Poll},
+};
+
+use aead::Payload;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, ReadBuf};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+	crypto::stream::{
+		Algorithm, BlockGroups, Framing, StreamDecryption, FRAME_PREFIX_LEN, MAX_FRAMED_BLOCK_LEN,
+	},
+	header::file::FileHeader,
+	primitives::{
+		types::{Key, Nonce, Salt},
+		AEAD_TAG_LEN, BLOCK_LEN, SALT_LEN,
+	},
+	Error, Protected, Result,
+};
+
+/// This wraps a reader of ciphertext, and implements `AsyncRead` for the plaintext.
+///
+/// At most one encrypted block (plus the next block's length prefix, with `LengthPrefixed` framing) is buffered at a time.
+///
+/// Errors are returned as `io::ErrorKind::InvalidData`, with the crate's error as the message.
+pub struct DecryptReader<R> {
+	inner: R,
+	// this is taken once the last block has been decrypted (and isn't there until the first group has started, for rekeyed streams)
+	decryptor: Option<StreamDecryption>,
+	// this is only set for rekeyed streams, so we can switch to the next group's key
+	rekeying: Option<(BlockGroups, Nonce, Algorithm)>,
+	// with rekeying, this is the salt at the start of the group that's being started
+	salt: [u8; SALT_LEN],
+	salt_filled: usize,
+	// this is set once the last block has been decrypted
+	finished: bool,
+	aad: Vec<u8>,
+	framing: Framing,
+	block: Vec<u8>,
+	filled: usize,
+	// with `LengthPrefixed` framing, this is the length of the block that's being read (once we've read its prefix)
+	block_len: Option<usize>,
+	prefix: [u8; FRAME_PREFIX_LEN],
+	prefix_filled: usize,
+	// this is zeroized whenever it's replaced, and when the reader is dropped
+	plaintext: Zeroizing<Vec<u8>>,
+	position: usize,
+	// how many plaintext bytes came before the ones in `plaintext`
+	consumed: u64,
+	// this is only set for seekable readers
+	rewind: Option<Rewind>,
+	seek: Option<SeekState>,
+}
+
+/// This is everything that's needed to start decrypting from the beginning again, when seeking backwards.
+struct Rewind {
+	start: u64,
+	key: Key,
+	nonce: Nonce,
+	algorithm: Algorithm,
+	rekey_interval: Option<NonZeroU32>,
+}
+
+impl Rewind {
+	fn decryptor(
+		&self,
+	) -> Result<(
+		Option<StreamDecryption>,
+		Option<(BlockGroups, Nonce, Algorithm)>,
+	)> {
+		if let Some(rekey_interval) = self.rekey_interval {
+			// the first group's key depends on its salt, so there's no decryptor until that's been read
+			let groups = BlockGroups::new(self.key.clone(), rekey_interval);
+
+			Ok((None, Some((groups, self.nonce, self.algorithm))))
+		} else {
+			let decryptor = StreamDecryption::new(self.key.clone(), self.nonce, self.algorithm)?;
+
+			Ok((Some(decryptor), None))
+		}
+	}
+}
+
+enum SeekState {
+	To(u64),
+	// the plaintext length isn't known until the last block has been decrypted
+	FromEnd(i64),
+	// the inner reader is seeking back to the start of the ciphertext
+	Rewinding(u64),
+}
+
+impl<R> DecryptReader<R>
+where
+	R: AsyncRead + Unpin,
+{
+	/// This creates a reader from a stream that's positioned at the start of the ciphertext.
+	///
+	/// The AAD and framing must be the same as the ones that were used for encryption.
+	#[must_use]
+	pub fn new(inner: R, decryptor: StreamDecryption, aad: Vec<u8>, framing: Framing) -> Self {
+		Self::with_decryptor(inner, Some(decryptor), None, aad, framing)
+	}
+
+	fn with_decryptor(
+		inner: R,
+		decryptor: Option<StreamDecryption>,
+		rekeying: Option<(BlockGroups, Nonce, Algorithm)>,
+		aad: Vec<u8>,
+		framing: Framing,
+	) -> Self {
+		// length-prefixed blocks are sized once we've read their prefix
+		let block = match framing {
+			Framing::Fixed => vec![0u8; BLOCK_LEN + AEAD_TAG_LEN],
+			Framing::LengthPrefixed => Vec::new(),
+		};
+
+		Self {
+			inner,
+			decryptor,
+			rekeying,
+			salt: [0u8; SALT_LEN],
+			salt_filled: 0,
+			finished: false,
+			aad,
+			framing,
+			block,
+			filled: 0,
+			block_len: None,
+			prefix: [0u8; FRAME_PREFIX_LEN],
+			prefix_filled: 0,
+			plaintext: Zeroizing::new(Vec::new()),
+			position: 0,
+			consumed: 0,
+			rewind: None,
+			seek: None,
+		}
+	}
+
+	/// This creates a reader from a stream that was encrypted with `StreamEncryption::encrypt_streams_rekeyed()`.
+	///
+	/// The root key and rekey interval must be the same as the ones that were used for encryption.
+	pub fn new_rekeyed(
+		inner: R,
+		root_key: Key,
+		nonce: Nonce,
+		algorithm: Algorithm,
+		rekey_interval: NonZeroU32,
+		aad: Vec<u8>,
+	) -> Result<Self> {
+		// each group's decryptor is only created once its salt has been read, so the nonce is checked up front
+		if nonce.len() != algorithm.nonce_len() {
+			return Err(Error::NonceLengthMismatch);
+		}
+
+		let groups = BlockGroups::new(root_key, rekey_interval);
+
+		Ok(Self::with_decryptor(
+			inner,
+			None,
+			Some((groups, nonce, algorithm)),
+			aad,
+			Framing::Fixed,
+		))
+	}
+
+	/// This returns a reference to the underlying reader.
+	pub const fn get_ref(&self) -> &R {
+		&self.inner
+	}
+
+	/// This consumes the `DecryptReader`, and returns the underlying reader.
+	pub fn into_inner(self) -> R {
+		self.inner
+	}
+
+	/// This decrypts the next block, and makes it the one that's being read from.
+	fn poll_advance(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		let plaintext = ready!(self.poll_next_block(cx))?;
+
+		self.consumed += self.plaintext.len() as u64;
+		self.plaintext = Zeroizing::new(plaintext);
+		self.position = 0;
+
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_next_block(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Vec<u8>>> {
+		match self.framing {
+			Framing::Fixed => {
+				if let Some((groups, nonce, algorithm)) = &mut self.rekeying {
+					if groups.starts_group() {
+						// every group starts with its salt, and has at least one block after it
+						if !ready!(poll_fill(
+							&mut self.inner,
+							cx,
+							&mut self.salt,
+							&mut self.salt_filled
+						))? {
+							return Poll::Ready(Err(io_error(&Error::TruncatedTag)));
+						}
+
+						self.salt_filled = 0;
+						let key = groups.start_group(Salt(self.salt));
+						self.decryptor = Some(
+							StreamDecryption::new(key, *nonce, *algorithm)
+								.map_err(|e| io_error(&e))?,
+						);
+					}
+				}
+
+				let full = ready!(poll_fill(
+					&mut self.inner,
+					cx,
+					&mut self.block,
+					&mut self.filled
+				))?;
+				let read_count = std::mem::take(&mut self.filled);
+
+				// the final block always contains at least the tag, so anything shorter has been cut off
+				if !full && read_count < AEAD_TAG_LEN {
+					return Poll::Ready(Err(io_error(&Error::TruncatedTag)));
+				}
+
+				Poll::Ready(self.decrypt(read_count, !full))
+			}
+			Framing::LengthPrefixed => {
+				if self.block_len.is_none() {
+					// there is always at least one block, even for empty plaintexts
+					let full = ready!(poll_fill(
+						&mut self.inner,
+						cx,
+						&mut self.prefix,
+						&mut self.prefix_filled
+					))?;
+
+					if !full {
+						return Poll::Ready(Err(io_error(&Error::TruncatedTag)));
+					}
+
+					self.start_block()?;
+				}
+
+				if !ready!(poll_fill(
+					&mut self.inner,
+					cx,
+					&mut self.block,
+					&mut self.filled
+				))? {
+					return Poll::Ready(Err(io_error(&Error::TruncatedTag)));
+				}
+
+				// the last block is the one that's followed by EOF, so we need to try reading the next prefix first
+				let has_next = ready!(poll_fill(
+					&mut self.inner,
+					cx,
+					&mut self.prefix,
+					&mut self.prefix_filled
+				))?;
+
+				if has_next {
+					let plaintext = self.decrypt(self.block.len(), false)?;
+					self.start_block()?;
+					Poll::Ready(Ok(plaintext))
+				} else if self.prefix_filled == 0 {
+					Poll::Ready(self.decrypt(self.block.len(), true))
+				} else {
+					Poll::Ready(Err(io_error(&Error::TruncatedTag)))
+				}
+			}
+		}
+	}
+
+	/// This parses the length prefix that was just read, and prepares the buffer for the block that follows it.
+	fn start_block(&mut self) -> io::Result<()> {
+		let block_len = u32::from_le_bytes(self.prefix) as usize;
+
+		if block_len < AEAD_TAG_LEN {
+			return Err(io_error(&Error::TruncatedTag));
+		} else if block_len > MAX_FRAMED_BLOCK_LEN + AEAD_TAG_LEN {
+			return Err(io_error(&Error::Serialization));
+		}
+
+		self.block.resize(block_len, 0);
+		self.block_len = Some(block_len);
+		self.filled = 0;
+		self.prefix_filled = 0;
+
+		Ok(())
+	}
+
+	fn decrypt(&mut self, len: usize, last: bool) -> io::Result<Vec<u8>> {
+		let payload = Payload {
+			aad: &self.aad,
+			msg: &self.block[..len],
+		};
+
+		let decrypted_data = if last {
+			self.finished = true;
+			self.decryptor
+				.take()
+				.expect("blocks are only read until the last one has been decrypted")
+				.decrypt_last(payload)
+		} else {
+			self.decryptor
+				.as_mut()
+				.expect("blocks are only read until the last one has been decrypted")
+				.decrypt_next(payload)
+		};
+
+		if let Some((groups, ..)) = &mut self.rekeying {
+			groups.finish_block();
+		}
+
+		decrypted_data.map_err(|_| io_error(&Error::Decrypt))
+	}
+}
+
+impl<R> DecryptReader<R>
+where
+	R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+{
+	/// This reads the header from the start of an encrypted file, and decrypts the master key with the user's password.
+	///
+	/// The header is returned alongside a reader that's positioned right after it, which yields the plaintext.
+	/// The reader is seekable.
+	///
+	/// The body is read with the framing that the header records. Rekeyed bodies are only written with `Fixed` framing, so a header that
+	/// claims otherwise is rejected rather than being read with the wrong framing.
+	///
+	/// You receive an error if the header is invalid, if its body parameters aren't supported, or if the password doesn't match.
+	pub async fn open(mut reader: R, password: Protected<Vec<u8>>) -> Result<(FileHeader, Self)> {
+		let (header, aad) = FileHeader::from_reader(&mut reader).await?;
+
+		if header.rekey_interval.is_some() && header.framing != Framing::Fixed {
+			return Err(Error::UnsupportedBodyParams);
+		}
+
+		let master_key = header.decrypt_master_key(password).await?;
+
+		let rewind = Rewind {
+			start: reader.stream_position().await?,
+			key: master_key,
+			nonce: header.nonce,
+			algorithm: header.algorithm,
+			rekey_interval: header.rekey_interval,
+		};
+
+		let framing = header.framing;
+
+		Ok((header, Self::with_rewind(reader, rewind, aad, framing)?))
+	}
+
+	/// This creates a seekable reader from a stream that's positioned at the start of the ciphertext.
+	///
+	/// The key is kept, so decryption can start over when seeking backwards.
+	pub async fn new_seekable(
+		mut inner: R,
+		key: Key,
+		nonce: Nonce,
+		algorithm: Algorithm,
+		aad: Vec<u8>,
+		framing: Framing,
+	) -> Result<Self> {
+		let rewind = Rewind {
+			start: inner.stream_position().await?,
+			key,
+			nonce,
+			algorithm,
+			rekey_interval: None,
+		};
+
+		Self::with_rewind(inner, rewind, aad, framing)
+	}
+
+	fn with_rewind(inner: R, rewind: Rewind, aad: Vec<u8>, framing: Framing) -> Result<Self> {
+		let (decryptor, rekeying) = rewind.decryptor()?;
+
+		let mut reader = Self::with_decryptor(inner, decryptor, rekeying, aad, framing);
+		reader.rewind = Some(rewind);
+
+		Ok(reader)
+	}
+}
+
+impl<R> DecryptReader<R>
+where
+	R: AsyncRead + AsyncSeek + Unpin,
+{
+	/// This returns the position within the plaintext.
+	fn plaintext_position(&self) -> u64 {
+		self.consumed + self.position as u64
+	}
+
+	/// This goes back to the start of the plaintext, once the inner reader has been seeked to the start of the ciphertext.
+	fn restart(&mut self) -> io::Result<()> {
+		let (decryptor, rekeying) = self
+			.rewind
+			.as_ref()
+			.expect("only seekable readers are rewound")
+			.decryptor()
+			.map_err(|e| io_error(&e))?;
+
+		self.decryptor = decryptor;
+		self.rekeying = rekeying;
+		self.salt_filled = 0;
+		self.finished = false;
+		self.filled = 0;
+		self.block_len = None;
+		self.prefix_filled = 0;
+		self.plaintext.zeroize();
+		self.position = 0;
+		self.consumed = 0;
+
+		Ok(())
+	}
+
+	fn poll_seek(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+		loop {
+			match self.seek {
+				None => return Poll::Ready(Ok(self.plaintext_position())),
+				Some(SeekState::FromEnd(offset)) => {
+					if !self.finished {
+						ready!(self.poll_advance(cx))?;
+						continue;
+					}
+
+					let end = self.consumed + self.plaintext.len() as u64;
+					self.seek = Some(SeekState::To(offset_position(end, offset)?));
+				}
+				Some(SeekState::Rewinding(target)) => {
+					ready!(Pin::new(&mut self.inner).poll_complete(cx))?;
+					self.restart()?;
+					self.seek = Some(SeekState::To(target));
+				}
+				Some(SeekState::To(target)) => {
+					if target < self.consumed {
+						let start = self
+							.rewind
+							.as_ref()
+							.expect("only seekable readers are rewound")
+							.start;
+
+						Pin::new(&mut self.inner).start_seek(SeekFrom::Start(start))?;
+						self.seek = Some(SeekState::Rewinding(target));
+					} else if target <= self.consumed + self.plaintext.len() as u64 {
+						#[allow(clippy::cast_possible_truncation)]
+						let position = (target - self.consumed) as usize;
+						self.position = position;
+
+						return Poll::Ready(Ok(target));
+					} else if self.finished {
+						return Poll::Ready(Err(io::Error::new(
+							io::ErrorKind::UnexpectedEof,
+							"tried to seek past the end of the plaintext",
+						)));
+					} else {
+						ready!(self.poll_advance(cx))?;
+					}
+				}
+			}
+		}
+	}
+}
+
+impl<R> AsyncRead for DecryptReader<R>
+where
+	R: AsyncRead + Unpin,
+{
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+
+		loop {
+			if this.position < this.plaintext.len() {
+				let len = buf.remaining().min(this.plaintext.len() - this.position);
+				buf.put_slice(&this.plaintext[this.position..this.position + len]);
+				this.position += len;
+
+				return Poll::Ready(Ok(()));
+			}
+
+			// the last block has been decrypted and fully read, so this is EOF
+			if this.finished {
+				return Poll::Ready(Ok(()));
+			}
+
+			ready!(this.poll_advance(cx))?;
+		}
+	}
+}
+
+/// Seeking decrypts (and discards) every block between the current position and the target, as each block's nonce
+/// depends on the ones before it.
+///
+/// This makes seeking forwards O(distance), and seeking backwards O(offset), as decryption has to start over from the
+/// first block. Seeking from the end decrypts the rest of the stream first, as the plaintext length isn't known until then.
+/// A format with counter-based nonces (where any block can be decrypted on its own) could seek in O(1), but the stream
+/// format is sequential.
+///
+/// Only readers that were created with `DecryptReader::open()` or `DecryptReader::new_seekable()` can seek.
+impl<R> AsyncSeek for DecryptReader<R>
+where
+	R: AsyncRead + AsyncSeek + Unpin,
+{
+	fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+		let this = self.get_mut();
+
+		if this.rewind.is_none() {
+			return Err(io::Error::new(
+				io::ErrorKind::Unsupported,
+				"this reader was created without a key to rewind with",
+			));
+		} else if this.seek.is_some() {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				"other seek operation is in progress",
+			));
+		}
+
+		this.seek = Some(match position {
+			SeekFrom::Start(offset) => SeekState::To(offset),
+			SeekFrom::Current(offset) => {
+				SeekState::To(offset_position(this.plaintext_position(), offset)?)
+			}
+			SeekFrom::End(offset) => SeekState::FromEnd(offset),
+		});
+
+		Ok(())
+	}
+
+	fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+		let this = self.get_mut();
+
+		let result = ready!(this.poll_seek(cx));
+		this.seek = None;
+
+		Poll::Ready(result)
+	}
+}
+
+fn offset_position(base: u64, offset: i64) -> io::Result<u64> {
+	base.checked_add_signed(offset).ok_or_else(|| {
+		io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"tried to seek before the start of the plaintext",
+		)
+	})
+}
+
+/// This reads into `buf[*filled..]` until it's full or the reader is at EOF, and returns whether it's full.
+///
+/// The progress is kept in `filled`, so this can be polled again after it returns `Poll::Pending`.
+fn poll_fill<R>(
+	reader: &mut R,
+	cx: &mut Context<'_>,
+	buf: &mut [u8],
+	filled: &mut usize,
+) -> Poll<io::Result<bool>>
+where
+	R: AsyncRead + Unpin,
+{
+	while *filled < buf.len() {
+		let mut read_buf = ReadBuf::new(&mut buf[*filled..]);
+		ready!(Pin::new(&mut *reader).poll_read(cx, &mut read_buf))?;
+
+		let read_count = read_buf.filled().len();
+		if read_count == 0 {
+			return Poll::Ready(Ok(false));
+		}
+
+		*filled += read_count;
+	}
+
+	Poll::Ready(Ok(true))
+}
+
+fn io_error(error: &Error) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use rand::{RngCore, SeedableRng};
+	use rand_chacha::ChaCha20Rng;
+
+	use crate::{
+		crypto::stream::{Algorithm, StreamEncryption},
+		header::keyslot::Keyslot,
+		keys::hashing::{HashingAlgorithm, Params},
+		primitives::{
+			types::{Key, Nonce, Salt},
+			LATEST_FILE_HEADER, LATEST_KEYSLOT,
+		},
+	};
+
+	use super::*;
+
+	const AAD: [u8; 16] = [0x92; 16];
+
+	/// This counts how many bytes have been read from the inner reader.
+	struct CountingReader<R> {
+		inner: R,
+		count: usize,
+	}
+
+	impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+		fn poll_read(
+			mut self: Pin<&mut Self>,
+			cx: &mut Context<'_>,
+			buf: &mut ReadBuf<'_>,
+		) -> Poll<io::Result<()>> {
+			let before = buf.filled().len();
+			ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+			self.count += buf.filled().len() - before;
+
+			Poll::Ready(Ok(()))
+		}
+	}
+
+	#[tokio::test]
+	async fn read_first_block_lazily() {
+		let key = Key::generate();
+		let nonce = Nonce::generate(Algorithm::XChaCha20Poly1305).unwrap();
+
+		let mut buf = vec![0u8; BLOCK_LEN * 3 + 1];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+
+		let mut writer = Cursor::new(Vec::new());
+		StreamEncryption::new(key.clone(), nonce, Algorithm::XChaCha20Poly1305)
+			.unwrap()
+			.encrypt_streams(buf.as_slice(), &mut writer, &AAD)
+			.await
+			.unwrap();
+
+		let inner = CountingReader {
+			inner: Cursor::new(writer.into_inner()),
+			count: 0,
+		};
+		let decryptor = StreamDecryption::new(key, nonce, Algorithm::XChaCha20Poly1305).unwrap();
+		let mut reader = DecryptReader::new(inner, decryptor, AAD.to_vec(), Framing::Fixed);
+
+		let mut output = vec![0u8; BLOCK_LEN];
+		reader.read_exact(&mut output).await.unwrap();
+
+		assert_eq!(output, buf[..BLOCK_LEN]);
+		assert_eq!(reader.get_ref().count, BLOCK_LEN + AEAD_TAG_LEN);
+
+		// the rest is still there for the taking
+		let mut output = Vec::new();
+		reader.read_to_end(&mut output).await.unwrap();
+
+		assert_eq!(output, buf[BLOCK_LEN..]);
+	}
+
+	#[tokio::test]
+	async fn read_length_prefixed_blocks() {
+		let key = Key::generate();
+		let nonce = Nonce::generate(Algorithm::XChaCha20Poly1305).unwrap();
+
+		let mut buf = vec![0u8; 4096 * 3 + 1];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+
+		let mut writer = Cursor::new(Vec::new());
+		StreamEncryption::new(key.clone(), nonce, Algorithm::XChaCha20Poly1305)
+			.unwrap()
+			.encrypt_streams_framed(buf.as_slice(), &mut writer, &AAD, 4096)
+			.await
+			.unwrap();
+
+		let decryptor = StreamDecryption::new(key, nonce, Algorithm::XChaCha20Poly1305).unwrap();
+		let mut reader = DecryptReader::new(
+			Cursor::new(writer.into_inner()),
+			decryptor,
+			AAD.to_vec(),
+			Framing::LengthPrefixed,
+		);
+
+		let mut output = Vec::new();
+		reader.read_to_end(&mut output).await.unwrap();
+
+		assert_eq!(buf, output);
+	}
+
+	#[tokio::test]
+	async fn seek_within_plaintext() {
+		let key = Key::generate();
+		let nonce = Nonce::generate(Algorithm::XChaCha20Poly1305).unwrap();
+
+		let mut buf = vec![0u8; BLOCK_LEN * 3 + 1];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+
+		for framing in [Framing::Fixed, Framing::LengthPrefixed] {
+			// the ciphertext doesn't start at the beginning of the stream, just like it would after a header
+			let mut writer = Cursor::new(vec![0xFF; 64]);
+			writer.set_position(64);
+
+			let encryptor =
+				StreamEncryption::new(key.clone(), nonce, Algorithm::XChaCha20Poly1305).unwrap();
+			match framing {
+				Framing::Fixed => {
+					encryptor
+						.encrypt_streams(buf.as_slice(), &mut writer, &AAD)
+						.await
+				}
+				Framing::LengthPrefixed => {
+					encryptor
+						.encrypt_streams_framed(buf.as_slice(), &mut writer, &AAD, 4096)
+						.await
+				}
+			}
+			.unwrap();
+
+			writer.set_position(64);
+			let mut reader = DecryptReader::new_seekable(
+				writer,
+				key.clone(),
+				nonce,
+				Algorithm::XChaCha20Poly1305,
+				AAD.to_vec(),
+				framing,
+			)
+			.await
+			.unwrap();
+
+			let middle = buf.len() / 2;
+			let mut output = vec![0u8; 100];
+
+			assert_eq!(
+				reader.seek(SeekFrom::Start(middle as u64)).await.unwrap(),
+				middle as u64
+			);
+			reader.read_exact(&mut output).await.unwrap();
+			assert_eq!(output, buf[middle..middle + 100]);
+
+			// backwards, which starts decrypting from the first block again
+			let position = reader
+				.seek(SeekFrom::Current(-(BLOCK_LEN as i64)))
+				.await
+				.unwrap();
+			assert_eq!(position, (middle + 100 - BLOCK_LEN) as u64);
+			reader.read_exact(&mut output).await.unwrap();
+			assert_eq!(output, buf[position as usize..position as usize + 100]);
+
+			reader.seek(SeekFrom::End(-100)).await.unwrap();
+			reader.read_exact(&mut output).await.unwrap();
+			assert_eq!(output, buf[buf.len() - 100..]);
+
+			assert!(reader.seek(SeekFrom::Current(1)).await.is_err());
+		}
+	}
+
+	#[tokio::test]
+	async fn read_rekeyed_stream() {
+		let key = Key::generate();
+		let nonce = Nonce::generate(Algorithm::XChaCha20Poly1305).unwrap();
+		let rekey_interval = NonZeroU32::new(2).unwrap();
+
+		let mut buf = vec![0u8; BLOCK_LEN * 5 + 1];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+
+		let mut writer = Cursor::new(Vec::new());
+		StreamEncryption::encrypt_streams_rekeyed(
+			key.clone(),
+			nonce,
+			Algorithm::XChaCha20Poly1305,
+			rekey_interval,
+			buf.as_slice(),
+			&mut writer,
+			&AAD,
+		)
+		.await
+		.unwrap();
+		let encrypted = writer.into_inner();
+
+		let rewind = Rewind {
+			start: 0,
+			key: key.clone(),
+			nonce,
+			algorithm: Algorithm::XChaCha20Poly1305,
+			rekey_interval: Some(rekey_interval),
+		};
+		let mut reader = DecryptReader::with_rewind(
+			Cursor::new(encrypted.clone()),
+			rewind,
+			AAD.to_vec(),
+			Framing::Fixed,
+		)
+		.unwrap();
+
+		let mut output = Vec::new();
+		reader.read_to_end(&mut output).await.unwrap();
+		assert_eq!(buf, output);
+
+		// seeking backwards starts again from the first group's salt
+		let mut output = vec![0u8; 100];
+		let position = (BLOCK_LEN * 2 - 50) as u64;
+		reader.seek(SeekFrom::Start(position)).await.unwrap();
+		reader.read_exact(&mut output).await.unwrap();
+		assert_eq!(output, buf[BLOCK_LEN * 2 - 50..BLOCK_LEN * 2 + 50]);
+
+		// a stream that's cut off at a group boundary is missing its final block
+		let group_len = SALT_LEN + (BLOCK_LEN + AEAD_TAG_LEN) * 2;
+		let mut reader = DecryptReader::new_rekeyed(
+			Cursor::new(encrypted[..group_len].to_vec()),
+			key,
+			nonce,
+			Algorithm::XChaCha20Poly1305,
+			rekey_interval,
+			AAD.to_vec(),
+		)
+		.unwrap();
+
+		assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn open_with_header_framing() {
+		let mk = Key::generate();
+		let password = Protected::new(b"password".to_vec());
+		let hashing_algorithm = HashingAlgorithm::Argon2id(Params::Standard);
+		let content_salt = Salt::generate();
+
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			Algorithm::XChaCha20Poly1305,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				Algorithm::XChaCha20Poly1305,
+				hashing_algorithm,
+				content_salt,
+				hashing_algorithm
+					.hash(password.clone(), content_salt, None)
+					.unwrap(),
+				mk.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+		header.framing = Framing::LengthPrefixed;
+
+		let mut buf = vec![0u8; 4096 * 3 + 1];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+
+		let mut writer = Cursor::new(Vec::new());
+		header.write(&mut writer).await.unwrap();
+		StreamEncryption::new(mk, header.nonce, header.algorithm)
+			.unwrap()
+			.encrypt_streams_framed(buf.as_slice(), &mut writer, &header.generate_aad(), 4096)
+			.await
+			.unwrap();
+
+		let (_, mut reader) =
+			DecryptReader::open(Cursor::new(writer.into_inner()), password.clone())
+				.await
+				.unwrap();
+
+		let mut output = Vec::new();
+		reader.read_to_end(&mut output).await.unwrap();
+		assert_eq!(buf, output);
+
+		// rekeyed bodies are never length-prefixed, so this is rejected instead of being read as fixed-size blocks
+		header.rekey_interval = NonZeroU32::new(2);
+		let mut writer = Cursor::new(Vec::new());
+		header.write(&mut writer).await.unwrap();
+		writer.rewind().await.unwrap();
+
+		assert!(matches!(
+			DecryptReader::open(writer, password).await,
+			Err(Error::UnsupportedBodyParams)
+		));
+	}
+
+	#[tokio::test]
+	#[should_panic(expected = "InvalidData")]
+	async fn read_with_wrong_aad() {
+		let key = Key::generate();
+		let nonce = Nonce::generate(Algorithm::XChaCha20Poly1305).unwrap();
+
+		let mut writer = Cursor::new(Vec::new());
+		StreamEncryption::new(key.clone(), nonce, Algorithm::XChaCha20Poly1305)
+			.unwrap()
+			.encrypt_streams([0x5A; 32].as_slice(), &mut writer, &AAD)
+			.await
+			.unwrap();
+
+		let decryptor = StreamDecryption::new(key, nonce, Algorithm::XChaCha20Poly1305).unwrap();
+		let mut reader = DecryptReader::new(
+			Cursor::new(writer.into_inner()),
+			decryptor,
+			Vec::new(),
+			Framing::Fixed,
+		);
+
+		reader.read_to_end(&mut Vec::new()).await.unwrap();
+	}
+}
@@ -1,22 +1,30 @@
 //! This module contains the crate's STREAM implementation, and wrappers that allow us to support multiple AEADs.
 #![allow(clippy::use_self)] // I think: https://github.com/rust-lang/rust-clippy/issues/3909
 
-use std::io::Cursor;
+use std::{
+	io::{Cursor, SeekFrom},
+	num::{NonZeroU32, NonZeroUsize},
+	ops::Range,
+	sync::Arc,
+};
 
 use crate::{
+	header::file::FileHeader,
 	primitives::{
-		types::{Key, Nonce},
-		AEAD_TAG_LEN, BLOCK_LEN,
+		to_array,
+		types::{Key, Nonce, Salt},
+		AEAD_TAG_LEN, AES_128_KEY_CONTEXT, BLOCK_GROUP_KEY_CONTEXT, BLOCK_LEN, SALT_LEN,
 	},
 	Error, Protected, Result,
 };
 use aead::{
-	stream::{DecryptorLE31, EncryptorLE31},
+	stream::{DecryptorLE31, EncryptorLE31, NewStream, StreamLE31, StreamPrimitive},
 	KeyInit, Payload,
 };
-use aes_gcm::Aes256Gcm;
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
 use chacha20poly1305::XChaCha20Poly1305;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use zeroize::{Zeroize, Zeroizing};
 
 /// These are all possible algorithms that can be used for encryption and decryption
 #[derive(Clone, Copy, Eq, PartialEq, Hash)]
@@ -29,15 +37,173 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 pub enum Algorithm {
 	XChaCha20Poly1305,
 	Aes256Gcm,
+	/// This is cheaper than the other algorithms on devices with AES acceleration, for bulk data where 128-bit security is enough (e.g. thumbnails).
+	///
+	/// Keys are still 256 bits everywhere else, and the AES-128 key is derived from them with `aes_128_key()` when the cipher is initialised.
+	Aes128Gcm,
+}
+
+/// This defines how the encrypted blocks are laid out after the header.
+///
+/// With `Fixed` framing, blocks are concatenated and the reader needs to know `BLOCK_LEN` to split them.
+///
+/// With `LengthPrefixed` framing, each block is preceded by its length (as a little-endian `u32`), so the
+/// blocks are self-describing and may be of any size up to `MAX_FRAMED_BLOCK_LEN`.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Framing {
+	Fixed,
+	LengthPrefixed,
+}
+
+/// The maximum plaintext length of a single length-prefixed block.
+///
+/// This prevents a corrupted length prefix from making us allocate an arbitrary amount of memory.
+pub const MAX_FRAMED_BLOCK_LEN: usize = BLOCK_LEN * 16;
+
+pub(crate) const FRAME_PREFIX_LEN: usize = std::mem::size_of::<u32>();
+
+/// This derives the 128-bit key that `Algorithm::Aes128Gcm` is initialised with, from a regular 256-bit key.
+///
+/// This means that keyslots, key derivation and the key manager don't need to know about shorter keys.
+pub(crate) fn aes_128_key(key: &Key) -> Zeroizing<[u8; 16]> {
+	let mut derived = blake3::derive_key(AES_128_KEY_CONTEXT, key.expose());
+	let mut aes_key = Zeroizing::new([0u8; 16]);
+	aes_key.copy_from_slice(&derived[..16]);
+
+	derived.zeroize();
+
+	aes_key
+}
+
+/// This derives the key for a group of blocks in a rekeyed stream, from the stream's root key, the group's index and the salt that's stored at the start of the group.
+///
+/// Every group gets its own key (including the first), so a compromised block key only exposes `rekey_interval` blocks.
+/// The salt is generated whenever a group is encrypted, so encrypting a group again (e.g. when resuming) never reuses a key.
+#[must_use]
+pub fn block_group_key(root_key: &Key, group: u64, salt: Salt) -> Key {
+	let mut input = root_key.expose().to_vec();
+	input.extend_from_slice(&group.to_le_bytes());
+	input.extend_from_slice(&salt);
+	let key = blake3::derive_key(BLOCK_GROUP_KEY_CONTEXT, &input);
+
+	input.zeroize();
+
+	Key::new(key)
+}
+
+/// This keeps track of the block groups in a rekeyed stream.
+///
+/// Each group starts with a random salt (`SALT_LEN` bytes), followed by its blocks. It's its own STREAM under the group's key, and only the
+/// final block of the whole stream is marked as the last one. As the group index is part of the key derivation, groups can't be reordered or
+/// dropped without decryption failing.
+pub(crate) struct BlockGroups {
+	root_key: Key,
+	rekey_interval: NonZeroU32,
+	// this is the index of the group that `start_group()` starts next
+	next_group: u64,
+	// this is how many more blocks fit in the current group, so the next block starts a group once it's zero
+	blocks_left: u32,
+}
+
+impl BlockGroups {
+	pub(crate) const fn new(root_key: Key, rekey_interval: NonZeroU32) -> Self {
+		Self::starting_at(root_key, rekey_interval, 0)
+	}
+
+	/// This starts keeping track from the beginning of `group`, rather than the beginning of the stream.
+	pub(crate) const fn starting_at(root_key: Key, rekey_interval: NonZeroU32, group: u64) -> Self {
+		Self {
+			root_key,
+			rekey_interval,
+			next_group: group,
+			blocks_left: 0,
+		}
+	}
+
+	/// This returns whether the next block starts a group, in which case the group's salt comes before it.
+	pub(crate) const fn starts_group(&self) -> bool {
+		self.blocks_left == 0
+	}
+
+	pub(crate) const fn next_group(&self) -> u64 {
+		self.next_group
+	}
+
+	/// This starts the next group with the salt that's stored at its start, and returns the group's key.
+	pub(crate) fn start_group(&mut self, salt: Salt) -> Key {
+		let key = block_group_key(&self.root_key, self.next_group, salt);
+
+		self.next_group += 1;
+		self.blocks_left = self.rekey_interval.get();
+
+		key
+	}
+
+	/// This should be called after each block.
+	pub(crate) fn finish_block(&mut self) {
+		self.blocks_left = self.blocks_left.saturating_sub(1);
+	}
+}
+
+/// This is a point in a rekeyed stream that encryption can be resumed from, if it was interrupted.
+///
+/// The internal state of a STREAM (its block counter) can't be exported, so encryption can't be picked up from any block.
+/// Each block group is its own STREAM though, under a key that only depends on the root key, the group's index and the group's salt,
+/// so the start of every group is a checkpoint. Resuming doesn't need anything besides what's already in the header.
+///
+/// The group that's resumed from gets a fresh salt (and so a fresh key), as part of it may already have been written under the old one.
+///
+/// Checkpoints are only valid for the `rekey_interval` that they were created with.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Checkpoint {
+	pub group: u64,
+}
+
+impl Checkpoint {
+	/// This returns how many bytes of plaintext came before the checkpoint.
+	#[must_use]
+	pub fn plaintext_offset(&self, rekey_interval: NonZeroU32) -> u64 {
+		self.group * u64::from(rekey_interval.get()) * BLOCK_LEN as u64
+	}
+
+	/// This returns how many bytes of ciphertext came before the checkpoint, from the start of the body.
+	///
+	/// Every group before the checkpoint is a salt followed by `rekey_interval` full blocks.
+	#[must_use]
+	pub fn ciphertext_offset(&self, rekey_interval: NonZeroU32) -> u64 {
+		self.group
+			* (SALT_LEN as u64
+				+ u64::from(rekey_interval.get()) * (BLOCK_LEN + AEAD_TAG_LEN) as u64)
+	}
+
+	#[must_use]
+	pub const fn to_bytes(&self) -> [u8; 8] {
+		self.group.to_le_bytes()
+	}
+
+	#[must_use]
+	pub const fn from_bytes(bytes: [u8; 8]) -> Self {
+		Self {
+			group: u64::from_le_bytes(bytes),
+		}
+	}
 }
 
 impl Algorithm {
 	/// This function allows us to calculate the nonce length for a given algorithm
+	///
+	/// These are the nonce lengths required by the STREAM-LE31 construction, which reserves 4 bytes
+	/// of the AEAD's nonce for the block counter and "last block" flag:
+	///
+	/// - `XChaCha20Poly1305`: 20 bytes (24 - 4)
+	/// - `Aes256Gcm` and `Aes128Gcm`: 8 bytes (12 - 4)
+	///
+	/// The header and keyslot padding is calculated from these values, so they must never change.
 	#[must_use]
 	pub const fn nonce_len(&self) -> usize {
 		match self {
 			Self::XChaCha20Poly1305 => 20,
-			Self::Aes256Gcm => 8,
+			Self::Aes256Gcm | Self::Aes128Gcm => 8,
 		}
 	}
 }
@@ -45,11 +211,13 @@ impl Algorithm {
 pub enum StreamEncryption {
 	XChaCha20Poly1305(Box<EncryptorLE31<XChaCha20Poly1305>>),
 	Aes256Gcm(Box<EncryptorLE31<Aes256Gcm>>),
+	Aes128Gcm(Box<EncryptorLE31<Aes128Gcm>>),
 }
 
 pub enum StreamDecryption {
 	Aes256Gcm(Box<DecryptorLE31<Aes256Gcm>>),
 	XChaCha20Poly1305(Box<DecryptorLE31<XChaCha20Poly1305>>),
+	Aes128Gcm(Box<DecryptorLE31<Aes128Gcm>>),
 }
 
 impl StreamEncryption {
@@ -77,28 +245,45 @@ impl StreamEncryption {
 				let stream = EncryptorLE31::from_aead(cipher, (&*nonce).into());
 				Self::Aes256Gcm(Box::new(stream))
 			}
+			Algorithm::Aes128Gcm => {
+				let cipher = Aes128Gcm::new_from_slice(&*aes_128_key(&key))
+					.map_err(|_| Error::StreamModeInit)?;
+
+				let stream = EncryptorLE31::from_aead(cipher, (&*nonce).into());
+				Self::Aes128Gcm(Box::new(stream))
+			}
 		};
 
 		Ok(encryption_object)
 	}
 
-	fn encrypt_next<'msg, 'aad>(
+	/// This initializes a stream encryption object with a freshly generated nonce of the correct length for the algorithm.
+	///
+	/// The nonce is returned alongside it, as it needs to be stored (e.g. in the header) for decryption.
+	pub fn new_with_random_nonce(key: Key, algorithm: Algorithm) -> Result<(Self, Nonce)> {
+		let nonce = Nonce::generate(algorithm)?;
+		Ok((Self::new(key, nonce, algorithm)?, nonce))
+	}
+
+	pub(crate) fn encrypt_next<'msg, 'aad>(
 		&mut self,
 		payload: impl Into<Payload<'msg, 'aad>>,
 	) -> aead::Result<Vec<u8>> {
 		match self {
 			Self::XChaCha20Poly1305(s) => s.encrypt_next(payload),
 			Self::Aes256Gcm(s) => s.encrypt_next(payload),
+			Self::Aes128Gcm(s) => s.encrypt_next(payload),
 		}
 	}
 
-	fn encrypt_last<'msg, 'aad>(
+	pub(crate) fn encrypt_last<'msg, 'aad>(
 		self,
 		payload: impl Into<Payload<'msg, 'aad>>,
 	) -> aead::Result<Vec<u8>> {
 		match self {
 			Self::XChaCha20Poly1305(s) => s.encrypt_last(payload),
 			Self::Aes256Gcm(s) => s.encrypt_last(payload),
+			Self::Aes128Gcm(s) => s.encrypt_last(payload),
 		}
 	}
 
@@ -119,7 +304,8 @@ impl StreamEncryption {
 		R: AsyncReadExt + Unpin + Send,
 		W: AsyncWriteExt + Unpin + Send,
 	{
-		let mut read_buffer = vec![0u8; BLOCK_LEN].into_boxed_slice();
+		// this holds plaintext, so it's zeroized when dropped (including when we return early with an error)
+		let mut read_buffer = Zeroizing::new(vec![0u8; BLOCK_LEN]);
 
 		loop {
 			let mut read_count = 0;
@@ -158,6 +344,326 @@ impl StreamEncryption {
 		Ok(())
 	}
 
+	/// This function encrypts a stream in the same way as `encrypt_streams()`, but with a fresh key for every `rekey_interval` blocks.
+	///
+	/// Each group starts with a random salt, and its key is derived from the root key, the group's index and the salt with `block_group_key()`.
+	/// The same derivation is done again for decryption with `decrypt_streams_rekeyed()`. This is meant for huge amounts of data,
+	/// where a single key would otherwise be used for billions of blocks.
+	///
+	/// The interval should be recorded in the header, so the stream can be decrypted later on.
+	///
+	/// The AAD will be authenticated with each block of data.
+	pub async fn encrypt_streams_rekeyed<R, W>(
+		root_key: Key,
+		nonce: Nonce,
+		algorithm: Algorithm,
+		rekey_interval: NonZeroU32,
+		reader: R,
+		writer: W,
+		aad: &[u8],
+	) -> Result<()>
+	where
+		R: AsyncReadExt + Unpin + Send,
+		W: AsyncWriteExt + Unpin + Send,
+	{
+		Self::resume_streams_rekeyed(
+			root_key,
+			nonce,
+			algorithm,
+			rekey_interval,
+			Checkpoint::default(),
+			reader,
+			writer,
+			aad,
+			|_| {},
+		)
+		.await
+	}
+
+	/// This function encrypts a stream in the same way as `encrypt_streams_rekeyed()`, but starting from a `Checkpoint`.
+	///
+	/// The reader should be positioned at the checkpoint's `plaintext_offset()`, and the writer at its `ciphertext_offset()`
+	/// (anything that was written past that by an interrupted attempt should be discarded). `Checkpoint::default()` is the start of the stream.
+	///
+	/// Once a block group has been written and flushed, `on_checkpoint` is called with the checkpoint that comes after it,
+	/// so it can be persisted and encryption can be resumed from there if it's interrupted again.
+	///
+	/// The group at the checkpoint is encrypted under a fresh salt, so none of its blocks are encrypted twice under the same key and nonce
+	/// (even if the plaintext has changed since the interrupted attempt). The output is laid out as if the stream had been encrypted in one go,
+	/// so it can be decrypted with `decrypt_streams_rekeyed()`.
+	#[allow(clippy::too_many_arguments)]
+	pub async fn resume_streams_rekeyed<R, W, F>(
+		root_key: Key,
+		nonce: Nonce,
+		algorithm: Algorithm,
+		rekey_interval: NonZeroU32,
+		checkpoint: Checkpoint,
+		mut reader: R,
+		mut writer: W,
+		aad: &[u8],
+		mut on_checkpoint: F,
+	) -> Result<()>
+	where
+		R: AsyncReadExt + Unpin + Send,
+		W: AsyncWriteExt + Unpin + Send,
+		F: FnMut(Checkpoint) + Send,
+	{
+		let mut groups = BlockGroups::starting_at(root_key, rekey_interval, checkpoint.group);
+		let mut stream = None;
+		let mut read_buffer = Zeroizing::new(vec![0u8; BLOCK_LEN]);
+
+		loop {
+			let mut read_count = 0;
+			loop {
+				let i = reader.read(&mut read_buffer[read_count..]).await?;
+				read_count += i;
+				if i == 0 || read_count == BLOCK_LEN {
+					// if we're EOF or the buffer is filled
+					break;
+				}
+			}
+
+			if groups.starts_group() {
+				if stream.is_some() {
+					// everything before this group has been written, so encryption can be resumed from here
+					writer.flush().await?;
+					on_checkpoint(Checkpoint {
+						group: groups.next_group(),
+					});
+				}
+
+				let salt = Salt::generate();
+				writer.write_all(&salt).await?;
+				stream = Some(Self::new(groups.start_group(salt), nonce, algorithm)?);
+			}
+
+			groups.finish_block();
+
+			if read_count == BLOCK_LEN {
+				let payload = Payload {
+					aad,
+					msg: &read_buffer,
+				};
+
+				let encrypted_data = stream
+					.as_mut()
+					.expect("every block is in a group")
+					.encrypt_next(payload)
+					.map_err(|_| Error::Encrypt)?;
+				writer.write_all(&encrypted_data).await?;
+			} else {
+				let payload = Payload {
+					aad,
+					msg: &read_buffer[..read_count],
+				};
+
+				let encrypted_data = stream
+					.take()
+					.expect("every block is in a group")
+					.encrypt_last(payload)
+					.map_err(|_| Error::Encrypt)?;
+				writer.write_all(&encrypted_data).await?;
+				break;
+			}
+		}
+
+		writer.flush().await?;
+
+		Ok(())
+	}
+
+	/// This function encrypts a stream in the same way as `encrypt_streams_rekeyed()`, but encrypts up to `parallelism` block groups at once.
+	///
+	/// Block groups are independent of each other, as each one is its own STREAM under its own key (derived from the root key, the group's index and the group's salt).
+	/// Sharing the nonce between groups is fine for this reason - a nonce is never reused under the same key, and as the group index is part of the key,
+	/// groups can't be reordered or dropped without decryption failing. Only the final block of the whole stream is marked as the last one, so truncating
+	/// the stream at a group boundary is detected too.
+	///
+	/// The output is laid out exactly like with `encrypt_streams_rekeyed()` (only the random salts differ), so it's decrypted with `decrypt_streams_rekeyed()`
+	/// and the header only needs the `rekey_interval`.
+	///
+	/// Up to `parallelism` groups are buffered at once, so the `rekey_interval` shouldn't be too large. Blocks are only allocated as they're read though,
+	/// so a stream that's shorter than a group doesn't allocate a whole group.
+	#[allow(clippy::too_many_arguments)]
+	pub async fn encrypt_streams_rekeyed_parallel<R, W>(
+		root_key: Key,
+		nonce: Nonce,
+		algorithm: Algorithm,
+		rekey_interval: NonZeroU32,
+		parallelism: NonZeroUsize,
+		mut reader: R,
+		mut writer: W,
+		aad: &[u8],
+	) -> Result<()>
+	where
+		R: AsyncReadExt + Unpin + Send,
+		W: AsyncWriteExt + Unpin + Send,
+	{
+		let aad: Arc<[u8]> = aad.into();
+		let mut group = 0;
+		let mut last = false;
+
+		while !last {
+			let mut tasks = Vec::with_capacity(parallelism.get());
+
+			while !last && tasks.len() < parallelism.get() {
+				let mut blocks = Vec::new();
+
+				while blocks.len() < rekey_interval.get() as usize {
+					// this holds plaintext, so it's zeroized when dropped
+					let mut block = Zeroizing::new(vec![0u8; BLOCK_LEN]);
+					let mut read_count = 0;
+					loop {
+						let i = reader.read(&mut block[read_count..]).await?;
+						read_count += i;
+						if i == 0 || read_count == BLOCK_LEN {
+							// if we're EOF or the buffer is filled
+							break;
+						}
+					}
+
+					block.truncate(read_count);
+					blocks.push(block);
+
+					// a full group is always followed by another, as the final block is shorter than `BLOCK_LEN` (and may be empty)
+					if read_count < BLOCK_LEN {
+						last = true;
+						break;
+					}
+				}
+
+				let salt = Salt::generate();
+				let key = block_group_key(&root_key, group, salt);
+				let aad = Arc::clone(&aad);
+				tasks.push(tokio::task::spawn_blocking(move || {
+					Self::encrypt_group(key, salt, nonce, algorithm, &blocks, &aad, last)
+				}));
+
+				group += 1;
+			}
+
+			// the groups are written in order, regardless of which finishes first
+			for task in tasks {
+				let encrypted_data = task.await.map_err(|_| Error::Encrypt)??;
+				writer.write_all(&encrypted_data).await?;
+			}
+		}
+
+		writer.flush().await?;
+
+		Ok(())
+	}
+
+	/// This encrypts a single block group of a rekeyed stream, which has been read into memory in full.
+	///
+	/// The group's salt is written first, and the final block is only encrypted as the last one if this is the final group of the stream.
+	fn encrypt_group(
+		key: Key,
+		salt: Salt,
+		nonce: Nonce,
+		algorithm: Algorithm,
+		blocks: &[Zeroizing<Vec<u8>>],
+		aad: &[u8],
+		last: bool,
+	) -> Result<Vec<u8>> {
+		let mut stream = Self::new(key, nonce, algorithm)?;
+		let mut encrypted_data = Vec::with_capacity(
+			SALT_LEN
+				+ blocks
+					.iter()
+					.map(|block| block.len() + AEAD_TAG_LEN)
+					.sum::<usize>(),
+		);
+		encrypted_data.extend_from_slice(&salt);
+
+		// every group but the final one is made up of full blocks, so none of them are the last one
+		let (final_block, blocks) = match blocks.split_last() {
+			Some((final_block, blocks)) if last => (Some(final_block), blocks),
+			_ => (None, blocks),
+		};
+
+		for block in blocks {
+			let payload = Payload {
+				aad,
+				msg: block.as_slice(),
+			};
+			encrypted_data.extend(stream.encrypt_next(payload).map_err(|_| Error::Encrypt)?);
+		}
+
+		if let Some(final_block) = final_block {
+			let payload = Payload {
+				aad,
+				msg: final_block.as_slice(),
+			};
+			encrypted_data.extend(stream.encrypt_last(payload).map_err(|_| Error::Encrypt)?);
+		}
+
+		Ok(encrypted_data)
+	}
+
+	/// This function encrypts a stream with `LengthPrefixed` framing, using blocks of `block_len` bytes.
+	///
+	/// Each encrypted block is written with a little-endian `u32` length prefix, so `decrypt_streams_framed()`
+	/// doesn't need to know the block length that was used.
+	///
+	/// Nothing is seeked, so this can encrypt data in transit (e.g. to a socket) without buffering it all first.
+	/// The last block is only detected by EOF once decrypting, so the writer should be shut down afterwards - passing it by value does this when it's dropped.
+	///
+	/// The AAD will be authenticated with each block of data.
+	pub async fn encrypt_streams_framed<R, W>(
+		mut self,
+		mut reader: R,
+		mut writer: W,
+		aad: &[u8],
+		block_len: usize,
+	) -> Result<()>
+	where
+		R: AsyncReadExt + Unpin + Send,
+		W: AsyncWriteExt + Unpin + Send,
+	{
+		if block_len == 0 || block_len > MAX_FRAMED_BLOCK_LEN {
+			return Err(Error::Encrypt);
+		}
+
+		let mut read_buffer = Zeroizing::new(vec![0u8; block_len]);
+
+		loop {
+			let mut read_count = 0;
+			loop {
+				let i = reader.read(&mut read_buffer[read_count..]).await?;
+				read_count += i;
+				if i == 0 || read_count == block_len {
+					// if we're EOF or the buffer is filled
+					break;
+				}
+			}
+
+			if read_count == block_len {
+				let payload = Payload {
+					aad,
+					msg: &read_buffer,
+				};
+
+				let encrypted_data = self.encrypt_next(payload).map_err(|_| Error::Encrypt)?;
+				write_frame(&mut writer, &encrypted_data).await?;
+			} else {
+				// we use `..read_count` in order to only use the read data, and not zeroes also
+				let payload = Payload {
+					aad,
+					msg: &read_buffer[..read_count],
+				};
+
+				let encrypted_data = self.encrypt_last(payload).map_err(|_| Error::Encrypt)?;
+				write_frame(&mut writer, &encrypted_data).await?;
+				break;
+			}
+		}
+
+		writer.flush().await?;
+
+		Ok(())
+	}
+
 	/// This should ideally only be used for small amounts of data
 	///
 	/// It is just a thin wrapper around `encrypt_streams()`, but reduces the amount of code needed elsewhere.
@@ -204,28 +710,37 @@ impl StreamDecryption {
 				let stream = DecryptorLE31::from_aead(cipher, (&*nonce).into());
 				Self::Aes256Gcm(Box::new(stream))
 			}
+			Algorithm::Aes128Gcm => {
+				let cipher = Aes128Gcm::new_from_slice(&*aes_128_key(&key))
+					.map_err(|_| Error::StreamModeInit)?;
+
+				let stream = DecryptorLE31::from_aead(cipher, (&*nonce).into());
+				Self::Aes128Gcm(Box::new(stream))
+			}
 		};
 
 		Ok(decryption_object)
 	}
 
-	fn decrypt_next<'msg, 'aad>(
+	pub(crate) fn decrypt_next<'msg, 'aad>(
 		&mut self,
 		payload: impl Into<Payload<'msg, 'aad>>,
 	) -> aead::Result<Vec<u8>> {
 		match self {
 			Self::XChaCha20Poly1305(s) => s.decrypt_next(payload),
 			Self::Aes256Gcm(s) => s.decrypt_next(payload),
+			Self::Aes128Gcm(s) => s.decrypt_next(payload),
 		}
 	}
 
-	fn decrypt_last<'msg, 'aad>(
+	pub(crate) fn decrypt_last<'msg, 'aad>(
 		self,
 		payload: impl Into<Payload<'msg, 'aad>>,
 	) -> aead::Result<Vec<u8>> {
 		match self {
 			Self::XChaCha20Poly1305(s) => s.decrypt_last(payload),
 			Self::Aes256Gcm(s) => s.decrypt_last(payload),
+			Self::Aes128Gcm(s) => s.decrypt_last(payload),
 		}
 	}
 
@@ -265,15 +780,269 @@ impl StreamDecryption {
 					msg: &read_buffer,
 				};
 
-				let decrypted_data = self.decrypt_next(payload).map_err(|_| Error::Decrypt)?;
+				let decrypted_data =
+					Zeroizing::new(self.decrypt_next(payload).map_err(|_| Error::Decrypt)?);
 				writer.write_all(&decrypted_data).await?;
 			} else {
+				// the final block always contains at least the tag, so anything shorter has been cut off
+				if read_count < AEAD_TAG_LEN {
+					return Err(Error::TruncatedTag);
+				}
+
 				let payload = Payload {
 					aad,
 					msg: &read_buffer[..read_count],
 				};
 
-				let decrypted_data = self.decrypt_last(payload).map_err(|_| Error::Decrypt)?;
+				let decrypted_data =
+					Zeroizing::new(self.decrypt_last(payload).map_err(|_| Error::Decrypt)?);
+				writer.write_all(&decrypted_data).await?;
+				break;
+			}
+		}
+
+		writer.flush().await?;
+
+		Ok(())
+	}
+
+	/// This function decrypts a stream that was encrypted with `encrypt_streams_rekeyed()`.
+	///
+	/// The key of each block group is derived again from the root key and the salt at the start of the group, so the `rekey_interval`
+	/// must match the one that was used for encryption.
+	///
+	/// The AAD will be authenticated with each block of data - if the AAD doesn't match what was used during encryption, an error will be returned.
+	pub async fn decrypt_streams_rekeyed<R, W>(
+		root_key: Key,
+		nonce: Nonce,
+		algorithm: Algorithm,
+		rekey_interval: NonZeroU32,
+		mut reader: R,
+		mut writer: W,
+		aad: &[u8],
+	) -> Result<()>
+	where
+		R: AsyncReadExt + Unpin + Send,
+		W: AsyncWriteExt + Unpin + Send,
+	{
+		let mut groups = BlockGroups::new(root_key, rekey_interval);
+		let mut stream = None;
+		let mut read_buffer = vec![0u8; BLOCK_LEN + AEAD_TAG_LEN].into_boxed_slice();
+
+		loop {
+			if groups.starts_group() {
+				let salt = read_salt(&mut reader).await?;
+				stream = Some(Self::new(groups.start_group(salt), nonce, algorithm)?);
+			}
+
+			groups.finish_block();
+
+			let mut read_count = 0;
+			loop {
+				let i = reader.read(&mut read_buffer[read_count..]).await?;
+				read_count += i;
+				if i == 0 || read_count == (BLOCK_LEN + AEAD_TAG_LEN) {
+					// if we're EOF or the buffer is filled
+					break;
+				}
+			}
+
+			if read_count == (BLOCK_LEN + AEAD_TAG_LEN) {
+				let payload = Payload {
+					aad,
+					msg: &read_buffer,
+				};
+
+				let decrypted_data = Zeroizing::new(
+					stream
+						.as_mut()
+						.expect("every block is in a group")
+						.decrypt_next(payload)
+						.map_err(|_| Error::Decrypt)?,
+				);
+				writer.write_all(&decrypted_data).await?;
+			} else {
+				// the final block always contains at least the tag, so anything shorter has been cut off
+				if read_count < AEAD_TAG_LEN {
+					return Err(Error::TruncatedTag);
+				}
+
+				let payload = Payload {
+					aad,
+					msg: &read_buffer[..read_count],
+				};
+
+				let decrypted_data = Zeroizing::new(
+					stream
+						.take()
+						.expect("every block is in a group")
+						.decrypt_last(payload)
+						.map_err(|_| Error::Decrypt)?,
+				);
+				writer.write_all(&decrypted_data).await?;
+				break;
+			}
+		}
+
+		writer.flush().await?;
+
+		Ok(())
+	}
+
+	/// This function decrypts only the plaintext bytes in `range`, from the body that `header` describes.
+	///
+	/// Every block is at a known offset, and a STREAM block can be decrypted on its own given its position, so only the blocks that
+	/// overlap `range` are read and decrypted. Blocks of rekeyed bodies are decrypted under their group's key (with the salt at the start of
+	/// the group).
+	///
+	/// The header's framing, block length and rekey interval are honoured: `Fixed` bodies (rekeyed or not) and `LengthPrefixed` bodies with a
+	/// recorded block length (like the ones that `crate::crypto::file::encrypt()` writes) are supported. Anything else is rejected with
+	/// `Error::UnsupportedBodyParams`, as finding a block would mean reading every length prefix before it.
+	///
+	/// The reader should be positioned at the start of the ciphertext, which should run until the end of the reader.
+	///
+	/// As with reading a file, a range that ends past the end of the plaintext is cut short, and one that starts past it is empty.
+	///
+	/// The AAD will be authenticated with each block of data - if the AAD doesn't match what was used during encryption, an error will be returned.
+	pub async fn decrypt_range<R>(
+		key: Key,
+		header: &FileHeader,
+		reader: &mut R,
+		range: Range<u64>,
+		aad: &[u8],
+	) -> Result<Protected<Vec<u8>>>
+	where
+		R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+	{
+		let layout = BodyLayout::new(header)?;
+
+		let start = reader.stream_position().await?;
+		let ciphertext_len = reader.seek(SeekFrom::End(0)).await? - start;
+
+		let (last_index, last_len) = layout.final_block(ciphertext_len)?;
+		let plaintext_len = last_index * layout.block_len + last_len - layout.overhead;
+
+		let range = range.start.min(plaintext_len)..range.end.min(plaintext_len);
+		if range.is_empty() {
+			return Ok(Protected::new(Vec::new()));
+		}
+
+		let first_block = range.start / layout.block_len;
+		let last_block = (range.end - 1) / layout.block_len;
+
+		let mut plaintext = Zeroizing::new(Vec::new());
+		let mut block = Vec::new();
+		// this is the current group's index and key, so its salt is only read once
+		let mut group_key: Option<(u64, Key)> = None;
+
+		for index in first_block..=last_block {
+			let (block_key, position) = match layout.groups {
+				Some((blocks, group_len)) => {
+					let (group, position) = (index / blocks, index % blocks);
+
+					if group_key.as_ref().map_or(true, |(g, _)| *g != group) {
+						reader
+							.seek(SeekFrom::Start(start + group * group_len))
+							.await?;
+						let salt = read_salt(reader).await?;
+						group_key = Some((group, block_group_key(&key, group, salt)));
+					}
+
+					let (_, block_key) = group_key
+						.as_ref()
+						.expect("the group's key was just derived");
+					(block_key.clone(), position)
+				}
+				None => (key.clone(), index),
+			};
+
+			reader
+				.seek(SeekFrom::Start(start + layout.block_offset(index)))
+				.await?;
+
+			block.clear();
+			(&mut *reader)
+				.take(layout.encrypted_block_len)
+				.read_to_end(&mut block)
+				.await?;
+
+			let ciphertext = layout.block_ciphertext(&block)?;
+
+			let decrypted_data = Zeroizing::new(
+				decrypt_block(
+					&block_key,
+					header.nonce,
+					header.algorithm,
+					position,
+					index == last_index,
+					aad,
+					ciphertext,
+				)
+				.ok_or(Error::Decrypt)?,
+			);
+			plaintext.extend_from_slice(&decrypted_data);
+		}
+
+		// the first block may start before the range, and the last one may end after it
+		#[allow(clippy::cast_possible_truncation)]
+		// these can't truncate, as they're no larger than the plaintext that's already in memory
+		let (offset, len) = (
+			(range.start - first_block * layout.block_len) as usize,
+			(range.end - range.start) as usize,
+		);
+
+		Ok(Protected::new(plaintext[offset..offset + len].to_vec()))
+	}
+
+	/// This function decrypts a stream that was encrypted with `encrypt_streams_framed()`.
+	///
+	/// The length of each block is read from its prefix, and the last block is the one that's followed by EOF.
+	///
+	/// As with encryption, the reader doesn't need to be seekable, so blocks are decrypted as soon as they arrive.
+	///
+	/// The AAD will be authenticated with each block of data - if the AAD doesn't match what was used during encryption, an error will be returned.
+	pub async fn decrypt_streams_framed<R, W>(
+		mut self,
+		mut reader: R,
+		mut writer: W,
+		aad: &[u8],
+	) -> Result<()>
+	where
+		R: AsyncReadExt + Unpin + Send,
+		W: AsyncWriteExt + Unpin + Send,
+	{
+		// there is always at least one block, even for empty plaintexts
+		let mut frame_len = read_frame_len(&mut reader)
+			.await?
+			.ok_or(Error::TruncatedTag)?;
+
+		loop {
+			if frame_len < AEAD_TAG_LEN {
+				return Err(Error::TruncatedTag);
+			} else if frame_len > MAX_FRAMED_BLOCK_LEN + AEAD_TAG_LEN {
+				return Err(Error::Serialization);
+			}
+
+			let mut frame = vec![0u8; frame_len];
+			reader.read_exact(&mut frame).await.map_err(|e| {
+				if e.kind() == std::io::ErrorKind::UnexpectedEof {
+					Error::TruncatedTag
+				} else {
+					Error::Io(e)
+				}
+			})?;
+
+			let payload = Payload { aad, msg: &frame };
+
+			if let Some(next_frame_len) = read_frame_len(&mut reader).await? {
+				let decrypted_data =
+					Zeroizing::new(self.decrypt_next(payload).map_err(|_| Error::Decrypt)?);
+				writer.write_all(&decrypted_data).await?;
+
+				frame_len = next_frame_len;
+			} else {
+				let decrypted_data =
+					Zeroizing::new(self.decrypt_last(payload).map_err(|_| Error::Decrypt)?);
 				writer.write_all(&decrypted_data).await?;
 				break;
 			}
@@ -298,10 +1067,200 @@ impl StreamDecryption {
 		let mut writer = Cursor::new(Vec::<u8>::new());
 		let decryptor = Self::new(key, nonce, algorithm)?;
 
-		decryptor
-			.decrypt_streams(bytes, &mut writer, aad)
-			.await
-			.map_or_else(Err, |_| Ok(Protected::new(writer.into_inner())))
+		decryptor
+			.decrypt_streams(bytes, &mut writer, aad)
+			.await
+			.map_or_else(Err, |_| Ok(Protected::new(writer.into_inner())))
+	}
+}
+
+/// This describes where the blocks of a body are, so any of them can be found without reading the ones before it.
+///
+/// Every block but the final one has `block_len` bytes of plaintext, and the final one always has fewer (it may be empty).
+/// With rekeying, every group is a salt followed by its blocks, and only the final group is cut short.
+struct BodyLayout {
+	block_len: u64,
+	// this is the length of every block but the final one, including its tag (and its length prefix, if there is one)
+	encrypted_block_len: u64,
+	// this is how much longer a block's ciphertext is than its plaintext
+	overhead: u64,
+	prefix_len: usize,
+	// with rekeying, these are how many blocks are in a group, and how long a full group is (including its salt)
+	groups: Option<(u64, u64)>,
+}
+
+impl BodyLayout {
+	/// You receive `Error::UnsupportedBodyParams` if blocks of the header's body can't be found without reading the whole body.
+	fn new(header: &FileHeader) -> Result<Self> {
+		let (block_len, prefix_len) =
+			match (header.framing, header.block_len, header.rekey_interval) {
+				(Framing::Fixed, None | Some(BLOCK_LEN), _) => (BLOCK_LEN, 0),
+				(Framing::LengthPrefixed, Some(block_len), None) => (block_len, FRAME_PREFIX_LEN),
+				_ => return Err(Error::UnsupportedBodyParams),
+			};
+
+		let overhead = (prefix_len + AEAD_TAG_LEN) as u64;
+		let encrypted_block_len = block_len as u64 + overhead;
+
+		Ok(Self {
+			block_len: block_len as u64,
+			encrypted_block_len,
+			overhead,
+			prefix_len,
+			groups: header.rekey_interval.map(|rekey_interval| {
+				let blocks = u64::from(rekey_interval.get());
+				(blocks, SALT_LEN as u64 + blocks * encrypted_block_len)
+			}),
+		})
+	}
+
+	/// This returns the index of the final block, and the length of its ciphertext, from the length of the whole body.
+	fn final_block(&self, ciphertext_len: u64) -> Result<(u64, u64)> {
+		let (last_index, last_len) = match self.groups {
+			Some((blocks, group_len)) => {
+				// the final group always has a salt and at least one block
+				let last_group_len = (ciphertext_len % group_len)
+					.checked_sub(SALT_LEN as u64)
+					.ok_or(Error::TruncatedTag)?;
+
+				(
+					ciphertext_len / group_len * blocks + last_group_len / self.encrypted_block_len,
+					last_group_len % self.encrypted_block_len,
+				)
+			}
+			None => (
+				ciphertext_len / self.encrypted_block_len,
+				ciphertext_len % self.encrypted_block_len,
+			),
+		};
+
+		// the final block is always shorter than the others, and contains at least the tag
+		if last_len < self.overhead {
+			return Err(Error::TruncatedTag);
+		}
+
+		Ok((last_index, last_len))
+	}
+
+	/// This returns where a block starts, from the start of the body.
+	fn block_offset(&self, index: u64) -> u64 {
+		match self.groups {
+			Some((blocks, group_len)) => {
+				index / blocks * group_len
+					+ SALT_LEN as u64
+					+ index % blocks * self.encrypted_block_len
+			}
+			None => index * self.encrypted_block_len,
+		}
+	}
+
+	/// This strips a block's length prefix (if it has one), after checking that it matches the block's actual length.
+	fn block_ciphertext<'a>(&self, block: &'a [u8]) -> Result<&'a [u8]> {
+		if self.prefix_len == 0 {
+			return Ok(block);
+		}
+
+		let (prefix, ciphertext) = block.split_at(self.prefix_len.min(block.len()));
+		if prefix.len() != self.prefix_len
+			|| u32::from_le_bytes(to_array(prefix)?) as usize != ciphertext.len()
+		{
+			return Err(Error::Serialization);
+		}
+
+		Ok(ciphertext)
+	}
+}
+
+/// This decrypts a single block of a STREAM, at the given position.
+///
+/// It returns `None` if the block can't be decrypted, or if the position doesn't fit in a STREAM.
+pub(crate) fn decrypt_block(
+	key: &Key,
+	nonce: Nonce,
+	algorithm: Algorithm,
+	position: u64,
+	last: bool,
+	aad: &[u8],
+	block: &[u8],
+) -> Option<Vec<u8>> {
+	let position = u32::try_from(position).ok()?;
+	let payload = Payload { aad, msg: block };
+
+	match algorithm {
+		Algorithm::XChaCha20Poly1305 => {
+			let cipher = XChaCha20Poly1305::new_from_slice(key.expose()).ok()?;
+			StreamLE31::from_aead(cipher, (&*nonce).into())
+				.decrypt(position, last, payload)
+				.ok()
+		}
+		Algorithm::Aes256Gcm => {
+			let cipher = Aes256Gcm::new_from_slice(key.expose()).ok()?;
+			StreamLE31::from_aead(cipher, (&*nonce).into())
+				.decrypt(position, last, payload)
+				.ok()
+		}
+		Algorithm::Aes128Gcm => {
+			let cipher = Aes128Gcm::new_from_slice(&*aes_128_key(key)).ok()?;
+			StreamLE31::from_aead(cipher, (&*nonce).into())
+				.decrypt(position, last, payload)
+				.ok()
+		}
+	}
+}
+
+/// This reads the salt at the start of a block group, in a rekeyed stream.
+///
+/// Every group has at least one block after its salt, so reaching EOF first means that the stream has been cut off.
+async fn read_salt<R>(reader: &mut R) -> Result<Salt>
+where
+	R: AsyncReadExt + Unpin + Send,
+{
+	let mut salt = [0u8; SALT_LEN];
+	reader.read_exact(&mut salt).await.map_err(|e| {
+		if e.kind() == std::io::ErrorKind::UnexpectedEof {
+			Error::TruncatedTag
+		} else {
+			Error::Io(e)
+		}
+	})?;
+
+	Ok(Salt(salt))
+}
+
+/// This writes an encrypted block, prefixed by its length.
+async fn write_frame<W>(writer: &mut W, encrypted_data: &[u8]) -> Result<()>
+where
+	W: AsyncWriteExt + Unpin + Send,
+{
+	#[allow(clippy::cast_possible_truncation)]
+	// this can't truncate, as the block length is capped at `MAX_FRAMED_BLOCK_LEN`
+	let frame_len = encrypted_data.len() as u32;
+
+	writer.write_all(&frame_len.to_le_bytes()).await?;
+	writer.write_all(encrypted_data).await?;
+
+	Ok(())
+}
+
+/// This reads the length prefix of the next frame, or returns `None` if the reader is already at EOF.
+async fn read_frame_len<R>(reader: &mut R) -> Result<Option<usize>>
+where
+	R: AsyncReadExt + Unpin + Send,
+{
+	let mut prefix = [0u8; FRAME_PREFIX_LEN];
+	let mut read_count = 0;
+	loop {
+		let i = reader.read(&mut prefix[read_count..]).await?;
+		read_count += i;
+		if i == 0 || read_count == FRAME_PREFIX_LEN {
+			break;
+		}
+	}
+
+	match read_count {
+		0 => Ok(None),
+		FRAME_PREFIX_LEN => Ok(Some(u32::from_le_bytes(prefix) as usize)),
+		_ => Err(Error::TruncatedTag),
 	}
 }
 
@@ -310,6 +1269,8 @@ mod tests {
 	use rand::{RngCore, SeedableRng};
 	use rand_chacha::ChaCha20Rng;
 
+	use crate::primitives::LATEST_FILE_HEADER;
+
 	use super::*;
 
 	const KEY: Key = Key::new([
@@ -363,6 +1324,43 @@ mod tests {
 		],
 	];
 
+	#[test]
+	fn nonce_len() {
+		assert_eq!(Algorithm::XChaCha20Poly1305.nonce_len(), 20);
+		assert_eq!(Algorithm::Aes256Gcm.nonce_len(), 8);
+		assert_eq!(Algorithm::Aes128Gcm.nonce_len(), 8);
+
+		// ensure these still match what the STREAM-LE31 construction requires
+		assert_eq!(
+			Algorithm::XChaCha20Poly1305.nonce_len(),
+			aead::stream::Nonce::<XChaCha20Poly1305, StreamLE31<XChaCha20Poly1305>>::default()
+				.len()
+		);
+		assert_eq!(
+			Algorithm::Aes256Gcm.nonce_len(),
+			aead::stream::Nonce::<Aes256Gcm, StreamLE31<Aes256Gcm>>::default().len()
+		);
+		assert_eq!(
+			Algorithm::Aes128Gcm.nonce_len(),
+			aead::stream::Nonce::<Aes128Gcm, StreamLE31<Aes128Gcm>>::default().len()
+		);
+	}
+
+	#[test]
+	fn new_with_random_nonce() {
+		for algorithm in [
+			Algorithm::XChaCha20Poly1305,
+			Algorithm::Aes256Gcm,
+			Algorithm::Aes128Gcm,
+		] {
+			let (_, a) = StreamEncryption::new_with_random_nonce(KEY, algorithm).unwrap();
+			let (_, b) = StreamEncryption::new_with_random_nonce(KEY, algorithm).unwrap();
+
+			assert_eq!(a.len(), algorithm.nonce_len());
+			assert!(a != b);
+		}
+	}
+
 	#[tokio::test]
 	async fn aes_encrypt_bytes() {
 		let ciphertext =
@@ -456,6 +1454,40 @@ mod tests {
 		assert_eq!(buf, output);
 	}
 
+	#[tokio::test]
+	async fn aes_128_encrypt_and_decrypt_5_blocks_with_aad() {
+		let mut buf = vec![0u8; BLOCK_LEN * 5];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+		let mut reader = Cursor::new(buf.clone());
+		let mut writer = Cursor::new(Vec::new());
+
+		let encryptor = StreamEncryption::new(KEY, AES_NONCE, Algorithm::Aes128Gcm).unwrap();
+
+		encryptor
+			.encrypt_streams(&mut reader, &mut writer, &AAD)
+			.await
+			.unwrap();
+
+		let ciphertext = writer.into_inner();
+		let mut writer = Cursor::new(Vec::new());
+
+		let decryptor = StreamDecryption::new(KEY, AES_NONCE, Algorithm::Aes128Gcm).unwrap();
+
+		decryptor
+			.decrypt_streams(ciphertext.as_slice(), &mut writer, &AAD)
+			.await
+			.unwrap();
+
+		assert_eq!(buf, writer.into_inner());
+
+		// the same key and nonce under AES-256 are a different cipher, and can't decrypt it
+		let result = StreamDecryption::new(KEY, AES_NONCE, Algorithm::Aes256Gcm)
+			.unwrap()
+			.decrypt_streams(ciphertext.as_slice(), Cursor::new(Vec::new()), &AAD)
+			.await;
+		assert!(matches!(result, Err(Error::Decrypt)));
+	}
+
 	#[tokio::test]
 	async fn aes_encrypt_and_decrypt_5_blocks_with_aad() {
 		let mut buf = vec![0u8; BLOCK_LEN * 5];
@@ -559,6 +1591,542 @@ mod tests {
 		.unwrap();
 	}
 
+	#[tokio::test]
+	#[should_panic(expected = "TruncatedTag")]
+	async fn xchacha_decrypt_truncated_tag() {
+		// a whole block of plaintext means the final block only contains the tag
+		let ciphertext = StreamEncryption::encrypt_bytes(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			&vec![0u8; BLOCK_LEN],
+			&[],
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(ciphertext.len(), BLOCK_LEN + (AEAD_TAG_LEN * 2));
+
+		StreamDecryption::decrypt_bytes(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			&ciphertext[..ciphertext.len() - (AEAD_TAG_LEN / 2)],
+			&[],
+		)
+		.await
+		.unwrap();
+	}
+
+	#[tokio::test]
+	async fn xchacha_encrypt_and_decrypt_framed() {
+		let mut buf = vec![0u8; BLOCK_LEN * 3 + 1];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+
+		// the decryptor doesn't need to know the block length that was used
+		for block_len in [BLOCK_LEN, 4096] {
+			let mut reader = Cursor::new(buf.clone());
+			let mut writer = Cursor::new(Vec::new());
+
+			let encryptor =
+				StreamEncryption::new(KEY, XCHACHA_NONCE, Algorithm::XChaCha20Poly1305).unwrap();
+
+			encryptor
+				.encrypt_streams_framed(&mut reader, &mut writer, &AAD, block_len)
+				.await
+				.unwrap();
+
+			let mut reader = Cursor::new(writer.into_inner());
+			let mut writer = Cursor::new(Vec::new());
+
+			let decryptor =
+				StreamDecryption::new(KEY, XCHACHA_NONCE, Algorithm::XChaCha20Poly1305).unwrap();
+
+			decryptor
+				.decrypt_streams_framed(&mut reader, &mut writer, &AAD)
+				.await
+				.unwrap();
+
+			let output = writer.into_inner();
+
+			assert_eq!(buf, output);
+		}
+	}
+
+	#[tokio::test]
+	async fn xchacha_encrypt_and_decrypt_framed_over_duplex() {
+		let mut buf = vec![0u8; BLOCK_LEN * 3 + 1];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+
+		// the pipe is much smaller than the data, so both sides have to run at the same time
+		let (sender, receiver) = tokio::io::duplex(1024);
+		let mut output = Vec::new();
+
+		let encryptor =
+			StreamEncryption::new(KEY, XCHACHA_NONCE, Algorithm::XChaCha20Poly1305).unwrap();
+		let decryptor =
+			StreamDecryption::new(KEY, XCHACHA_NONCE, Algorithm::XChaCha20Poly1305).unwrap();
+
+		let (encrypted, decrypted) = tokio::join!(
+			// the sender is dropped once it's done, which is the EOF that marks the last block
+			encryptor.encrypt_streams_framed(buf.as_slice(), sender, &AAD, 4096),
+			decryptor.decrypt_streams_framed(receiver, &mut output, &AAD),
+		);
+
+		encrypted.unwrap();
+		decrypted.unwrap();
+
+		assert_eq!(buf, output);
+	}
+
+	#[tokio::test]
+	async fn xchacha_encrypt_and_decrypt_rekeyed() {
+		let mut buf = vec![0u8; BLOCK_LEN * 5 + 1];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+		let mut writer = Cursor::new(Vec::new());
+
+		// three groups, with the final (short) block starting the last one
+		let rekey_interval = NonZeroU32::new(2).unwrap();
+
+		StreamEncryption::encrypt_streams_rekeyed(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			rekey_interval,
+			buf.as_slice(),
+			&mut writer,
+			&AAD,
+		)
+		.await
+		.unwrap();
+
+		let encrypted = writer.into_inner();
+		let mut output = Vec::new();
+
+		StreamDecryption::decrypt_streams_rekeyed(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			rekey_interval,
+			encrypted.as_slice(),
+			&mut output,
+			&AAD,
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(buf, output);
+
+		// a different interval derives the wrong keys for the later groups
+		let result = StreamDecryption::decrypt_streams_rekeyed(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			NonZeroU32::new(3).unwrap(),
+			encrypted.as_slice(),
+			&mut Vec::new(),
+			&AAD,
+		)
+		.await;
+
+		assert!(matches!(result, Err(Error::Decrypt)));
+	}
+
+	#[tokio::test]
+	async fn rekeyed_groups_use_different_keys() {
+		let salt = Salt::generate();
+		let keys = (0..3)
+			.map(|group| block_group_key(&KEY, group, salt))
+			.collect::<Vec<_>>();
+
+		assert_ne!(keys[0].expose(), KEY.expose());
+		assert_ne!(keys[0].expose(), keys[1].expose());
+		assert_ne!(keys[1].expose(), keys[2].expose());
+
+		// the same group under a different salt gets a different key too
+		assert_ne!(
+			keys[0].expose(),
+			block_group_key(&KEY, 0, Salt::generate()).expose()
+		);
+
+		// the same block in two different groups encrypts differently, even with the same nonce
+		let mut writer = Cursor::new(Vec::new());
+		StreamEncryption::encrypt_streams_rekeyed(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			NonZeroU32::new(1).unwrap(),
+			vec![0x5A; BLOCK_LEN * 2].as_slice(),
+			&mut writer,
+			&[],
+		)
+		.await
+		.unwrap();
+
+		let encrypted = writer.into_inner();
+		let group_len = SALT_LEN + BLOCK_LEN + AEAD_TAG_LEN;
+
+		assert_ne!(
+			encrypted[SALT_LEN..group_len],
+			encrypted[group_len + SALT_LEN..group_len * 2]
+		);
+	}
+
+	/// This fails every read, as if the source of the plaintext had gone away.
+	struct FailingReader;
+
+	impl tokio::io::AsyncRead for FailingReader {
+		fn poll_read(
+			self: std::pin::Pin<&mut Self>,
+			_: &mut std::task::Context<'_>,
+			_: &mut tokio::io::ReadBuf<'_>,
+		) -> std::task::Poll<std::io::Result<()>> {
+			std::task::Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()))
+		}
+	}
+
+	#[tokio::test]
+	async fn resume_rekeyed_encryption_from_checkpoint() {
+		let mut buf = vec![0u8; BLOCK_LEN * 7 + 1];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+		let rekey_interval = NonZeroU32::new(2).unwrap();
+
+		let mut expected = Vec::new();
+		StreamEncryption::encrypt_streams_rekeyed(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			rekey_interval,
+			buf.as_slice(),
+			&mut expected,
+			&AAD,
+		)
+		.await
+		.unwrap();
+
+		// the first attempt dies part-way through the third group, once it's written that group's salt and first block
+		let mut writer = Vec::new();
+		let mut checkpoint = Checkpoint::default();
+		let result = StreamEncryption::resume_streams_rekeyed(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			rekey_interval,
+			checkpoint,
+			(&buf[..BLOCK_LEN * 5 + 17]).chain(FailingReader),
+			&mut writer,
+			&AAD,
+			|c| checkpoint = c,
+		)
+		.await;
+		assert!(matches!(result, Err(Error::Io(_))));
+		assert_eq!(checkpoint, Checkpoint { group: 2 });
+		assert_eq!(checkpoint, Checkpoint::from_bytes(checkpoint.to_bytes()));
+
+		// the partially-written group is discarded, and encryption starts again from the checkpoint
+		#[allow(clippy::cast_possible_truncation)]
+		let (ciphertext_offset, plaintext_offset) = (
+			checkpoint.ciphertext_offset(rekey_interval) as usize,
+			checkpoint.plaintext_offset(rekey_interval) as usize,
+		);
+		let interrupted_salt = writer[ciphertext_offset..ciphertext_offset + SALT_LEN].to_vec();
+		writer.truncate(ciphertext_offset);
+
+		StreamEncryption::resume_streams_rekeyed(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			rekey_interval,
+			checkpoint,
+			&buf[plaintext_offset..],
+			&mut writer,
+			&AAD,
+			|_| {},
+		)
+		.await
+		.unwrap();
+
+		// the resumed group is encrypted under a fresh salt, so its key (and nonce) never encrypts anything else
+		assert_eq!(writer.len(), expected.len());
+		assert_ne!(
+			writer[ciphertext_offset..ciphertext_offset + SALT_LEN],
+			interrupted_salt
+		);
+
+		let mut output = Vec::new();
+		StreamDecryption::decrypt_streams_rekeyed(
+			KEY,
+			XCHACHA_NONCE,
+			Algorithm::XChaCha20Poly1305,
+			rekey_interval,
+			writer.as_slice(),
+			&mut output,
+			&AAD,
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(buf, output);
+	}
+
+	#[tokio::test]
+	async fn parallel_rekeyed_encryption_matches_sequential() {
+		let rekey_interval = NonZeroU32::new(2).unwrap();
+		let group_len = BLOCK_LEN * 2;
+		let lengths = [
+			0,
+			BLOCK_LEN,
+			group_len,
+			group_len + 1,
+			group_len * 7 + BLOCK_LEN + 3,
+		];
+
+		for (algorithm, nonce) in [
+			(Algorithm::XChaCha20Poly1305, XCHACHA_NONCE),
+			(Algorithm::Aes256Gcm, AES_NONCE),
+		] {
+			for len in lengths {
+				let mut buf = vec![0u8; len];
+				ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+
+				let mut sequential = Vec::new();
+				StreamEncryption::encrypt_streams_rekeyed(
+					KEY,
+					nonce,
+					algorithm,
+					rekey_interval,
+					buf.as_slice(),
+					&mut sequential,
+					&AAD,
+				)
+				.await
+				.unwrap();
+
+				let mut parallel = Vec::new();
+				StreamEncryption::encrypt_streams_rekeyed_parallel(
+					KEY,
+					nonce,
+					algorithm,
+					rekey_interval,
+					NonZeroUsize::new(3).unwrap(),
+					buf.as_slice(),
+					&mut parallel,
+					&AAD,
+				)
+				.await
+				.unwrap();
+
+				// the salts are random, but everything is in the same place
+				assert_eq!(parallel.len(), sequential.len());
+
+				let mut output = Vec::new();
+				StreamDecryption::decrypt_streams_rekeyed(
+					KEY,
+					nonce,
+					algorithm,
+					rekey_interval,
+					parallel.as_slice(),
+					&mut output,
+					&AAD,
+				)
+				.await
+				.unwrap();
+
+				assert_eq!(buf, output);
+			}
+		}
+	}
+
+	/// This encrypts `plaintext` as the body that `header` describes, and returns it after some unrelated bytes (as if they were the header).
+	async fn encrypt_body(header: &FileHeader, plaintext: &[u8]) -> Vec<u8> {
+		let mut encrypted = vec![0xFF; 7];
+
+		match (header.framing, header.rekey_interval) {
+			(Framing::Fixed, Some(rekey_interval)) => {
+				StreamEncryption::encrypt_streams_rekeyed(
+					KEY,
+					header.nonce,
+					header.algorithm,
+					rekey_interval,
+					plaintext,
+					&mut encrypted,
+					&AAD,
+				)
+				.await
+			}
+			(Framing::Fixed, None) => {
+				StreamEncryption::new(KEY, header.nonce, header.algorithm)
+					.unwrap()
+					.encrypt_streams(plaintext, &mut encrypted, &AAD)
+					.await
+			}
+			(Framing::LengthPrefixed, _) => {
+				StreamEncryption::new(KEY, header.nonce, header.algorithm)
+					.unwrap()
+					.encrypt_streams_framed(
+						plaintext,
+						&mut encrypted,
+						&AAD,
+						header.block_len.unwrap(),
+					)
+					.await
+			}
+		}
+		.unwrap();
+
+		encrypted
+	}
+
+	fn range_header(
+		framing: Framing,
+		block_len: Option<usize>,
+		rekey_interval: Option<NonZeroU32>,
+	) -> FileHeader {
+		let mut header =
+			FileHeader::new(LATEST_FILE_HEADER, Algorithm::XChaCha20Poly1305, Vec::new()).unwrap();
+		header.framing = framing;
+		header.block_len = block_len;
+		header.rekey_interval = rekey_interval;
+
+		header
+	}
+
+	#[tokio::test]
+	async fn decrypt_range_matches_full_decryption() {
+		let mut buf = vec![0u8; BLOCK_LEN * 5 + 100];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+		let len = buf.len() as u64;
+		let block_len = BLOCK_LEN as u64;
+
+		for header in [
+			range_header(Framing::Fixed, None, None),
+			range_header(Framing::Fixed, None, NonZeroU32::new(2)),
+			range_header(Framing::LengthPrefixed, Some(BLOCK_LEN / 16), None),
+		] {
+			let file = encrypt_body(&header, &buf).await;
+
+			for range in [
+				// within a single block
+				10..20,
+				// straddling a block boundary, and a group boundary when rekeyed
+				block_len * 2 - 5..block_len * 2 + 5,
+				// most of the file
+				1..len - 1,
+				// including the final block
+				block_len * 5 + 50..len,
+				// past the end, which is cut short
+				block_len * 4..len + 1000,
+				// starting past the end
+				len + 1..len + 10,
+				0..0,
+			] {
+				let mut reader = Cursor::new(file.as_slice());
+				reader.set_position(7);
+
+				let decrypted =
+					StreamDecryption::decrypt_range(KEY, &header, &mut reader, range.clone(), &AAD)
+						.await
+						.unwrap();
+
+				#[allow(clippy::cast_possible_truncation)]
+				let expected = &buf[(range.start.min(len) as usize)..(range.end.min(len) as usize)];
+				assert_eq!(decrypted.expose(), expected);
+			}
+
+			// blocks are still authenticated
+			let mut reader = Cursor::new(file.as_slice());
+			reader.set_position(7);
+			let result =
+				StreamDecryption::decrypt_range(KEY, &header, &mut reader, 0..10, &[]).await;
+			assert!(matches!(result, Err(Error::Decrypt)));
+		}
+	}
+
+	#[tokio::test]
+	async fn decrypt_range_with_tampered_block() {
+		let mut buf = vec![0u8; BLOCK_LEN * 3 + 100];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+		let block_len = BLOCK_LEN as u64;
+
+		for header in [
+			range_header(Framing::Fixed, None, None),
+			range_header(Framing::Fixed, None, NonZeroU32::new(2)),
+		] {
+			let mut file = encrypt_body(&header, &buf).await;
+
+			// a bit is flipped in the middle of the third block
+			let encrypted_block_len = BLOCK_LEN + AEAD_TAG_LEN;
+			let offset = match header.rekey_interval {
+				Some(_) => 7 + SALT_LEN * 2 + encrypted_block_len * 2 + 100,
+				None => 7 + encrypted_block_len * 2 + 100,
+			};
+			file[offset] ^= 0x01;
+
+			// only the blocks that are read are checked, so the rest of the file can still be read
+			let mut reader = Cursor::new(file.as_slice());
+			reader.set_position(7);
+			let decrypted =
+				StreamDecryption::decrypt_range(KEY, &header, &mut reader, 0..block_len * 2, &AAD)
+					.await
+					.unwrap();
+			assert_eq!(decrypted.expose(), &buf[..BLOCK_LEN * 2]);
+
+			for range in [block_len * 2..block_len * 2 + 1, block_len..block_len * 3] {
+				let mut reader = Cursor::new(file.as_slice());
+				reader.set_position(7);
+				let result =
+					StreamDecryption::decrypt_range(KEY, &header, &mut reader, range, &AAD).await;
+				assert!(matches!(result, Err(Error::Decrypt)));
+			}
+		}
+
+		// the salt is part of the group's key, so tampering with it is caught too
+		let header = range_header(Framing::Fixed, None, NonZeroU32::new(2));
+		let mut file = encrypt_body(&header, &buf).await;
+		file[7] ^= 0x01;
+
+		let mut reader = Cursor::new(file.as_slice());
+		reader.set_position(7);
+		let result = StreamDecryption::decrypt_range(KEY, &header, &mut reader, 0..10, &AAD).await;
+		assert!(matches!(result, Err(Error::Decrypt)));
+
+		// length prefixes are checked against the header's block length
+		let header = range_header(Framing::LengthPrefixed, Some(BLOCK_LEN / 16), None);
+		let mut file = encrypt_body(&header, &buf).await;
+		file[7] ^= 0x01;
+
+		let mut reader = Cursor::new(file.as_slice());
+		reader.set_position(7);
+		let result = StreamDecryption::decrypt_range(KEY, &header, &mut reader, 0..10, &AAD).await;
+		assert!(matches!(result, Err(Error::Serialization)));
+	}
+
+	#[tokio::test]
+	async fn decrypt_range_with_unsupported_body_params() {
+		for header in [
+			// the blocks can't be found without reading every length prefix
+			range_header(Framing::LengthPrefixed, None, None),
+			// rekeyed bodies are never length-prefixed
+			range_header(
+				Framing::LengthPrefixed,
+				Some(BLOCK_LEN / 16),
+				NonZeroU32::new(2),
+			),
+			// fixed blocks are always `BLOCK_LEN`
+			range_header(Framing::Fixed, Some(BLOCK_LEN / 16), None),
+		] {
+			let result = StreamDecryption::decrypt_range(
+				KEY,
+				&header,
+				&mut Cursor::new(vec![0u8; 64]),
+				0..10,
+				&AAD,
+			)
+			.await;
+			assert!(matches!(result, Err(Error::UnsupportedBodyParams)));
+		}
+	}
+
 	#[tokio::test]
 	async fn xchacha_encrypt_and_decrypt_5_blocks() {
 		let mut buf = vec![0u8; BLOCK_LEN * 5];
@@ -590,6 +2158,122 @@ mod tests {
 		assert_eq!(buf, output);
 	}
 
+	#[tokio::test]
+	async fn encrypt_and_decrypt_around_block_boundaries() {
+		let lengths = [
+			0,
+			BLOCK_LEN - 1,
+			BLOCK_LEN,
+			BLOCK_LEN + 1,
+			BLOCK_LEN * 2,
+			BLOCK_LEN * 2 + 1,
+			BLOCK_LEN * 3,
+		];
+
+		for (algorithm, nonce) in [
+			(Algorithm::XChaCha20Poly1305, XCHACHA_NONCE),
+			(Algorithm::Aes256Gcm, AES_NONCE),
+		] {
+			for len in lengths {
+				let mut buf = vec![0u8; len];
+				ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+				let mut reader = Cursor::new(buf.clone());
+				let mut writer = Cursor::new(Vec::new());
+
+				StreamEncryption::new(KEY, nonce, algorithm)
+					.unwrap()
+					.encrypt_streams(&mut reader, &mut writer, &[])
+					.await
+					.unwrap();
+
+				// every full block is followed by a final (possibly empty) one, and each has a tag
+				let encrypted = writer.into_inner();
+				assert_eq!(encrypted.len(), len + (len / BLOCK_LEN + 1) * AEAD_TAG_LEN);
+
+				let mut reader = Cursor::new(encrypted);
+				let mut writer = Cursor::new(Vec::new());
+
+				StreamDecryption::new(KEY, nonce, algorithm)
+					.unwrap()
+					.decrypt_streams(&mut reader, &mut writer, &[])
+					.await
+					.unwrap();
+
+				assert_eq!(buf, writer.into_inner());
+			}
+		}
+	}
+
+	/// This accepts `remaining` bytes, and then fails every write.
+	struct FailingWriter {
+		remaining: usize,
+	}
+
+	impl tokio::io::AsyncWrite for FailingWriter {
+		fn poll_write(
+			mut self: std::pin::Pin<&mut Self>,
+			_: &mut std::task::Context<'_>,
+			buf: &[u8],
+		) -> std::task::Poll<std::io::Result<usize>> {
+			if self.remaining == 0 {
+				return std::task::Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+			}
+
+			let len = buf.len().min(self.remaining);
+			self.remaining -= len;
+			std::task::Poll::Ready(Ok(len))
+		}
+
+		fn poll_flush(
+			self: std::pin::Pin<&mut Self>,
+			_: &mut std::task::Context<'_>,
+		) -> std::task::Poll<std::io::Result<()>> {
+			std::task::Poll::Ready(Ok(()))
+		}
+
+		fn poll_shutdown(
+			self: std::pin::Pin<&mut Self>,
+			_: &mut std::task::Context<'_>,
+		) -> std::task::Poll<std::io::Result<()>> {
+			std::task::Poll::Ready(Ok(()))
+		}
+	}
+
+	#[tokio::test]
+	async fn encrypt_and_decrypt_with_failing_writer() {
+		let mut buf = vec![0u8; BLOCK_LEN * 3];
+		ChaCha20Rng::from_entropy().fill_bytes(&mut buf);
+
+		// the writer fails part-way through the second block, and the error is returned rather than swallowed
+		let mut writer = FailingWriter {
+			remaining: BLOCK_LEN + AEAD_TAG_LEN + 17,
+		};
+
+		let result = StreamEncryption::new(KEY, XCHACHA_NONCE, Algorithm::XChaCha20Poly1305)
+			.unwrap()
+			.encrypt_streams(Cursor::new(buf.clone()), &mut writer, &[])
+			.await;
+		assert!(matches!(result, Err(Error::Io(_))));
+
+		let mut encrypted = Cursor::new(Vec::new());
+		StreamEncryption::new(KEY, XCHACHA_NONCE, Algorithm::XChaCha20Poly1305)
+			.unwrap()
+			.encrypt_streams(Cursor::new(buf), &mut encrypted, &[])
+			.await
+			.unwrap();
+		encrypted.set_position(0);
+
+		let mut writer = FailingWriter {
+			remaining: BLOCK_LEN + 17,
+		};
+
+		let result = StreamDecryption::new(KEY, XCHACHA_NONCE, Algorithm::XChaCha20Poly1305)
+			.unwrap()
+			.decrypt_streams(encrypted, &mut writer, &[])
+			.await;
+		assert!(matches!(result, Err(Error::Io(_))));
+	}
+
 	#[tokio::test]
 	async fn xchacha_encrypt_and_decrypt_5_blocks_with_aad() {
 		let mut buf = vec![0u8; BLOCK_LEN * 5];
@@ -0,0 +1,128 @@
+//! This module contains a wrapper that hashes everything that's read from, or written to, the stream it wraps.
+//!
+//! It allows content to be hashed while it's being encrypted or decrypted, without reading it a second time.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let mut reader = Tee::new(File::open("test").await?);
+//! let mut writer = Tee::new(File::create("test.encrypted").await?);
+//!
+//! encryptor.encrypt_streams(&mut reader, &mut writer, &aad).await?;
+//!
+//! let plaintext_hash = reader.finalize();
+//! let ciphertext_hash = writer.finalize();
+//! ```
+use std::{
+	io,
+	pin::Pin,
+	task::{Context, Poll},
+};
+
+use blake3::{Hash, Hasher};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// This passes reads and writes through to the inner stream, and feeds every byte that's read or written into a BLAKE3 hasher.
+pub struct Tee<S> {
+	inner: S,
+	hasher: Hasher,
+}
+
+impl<S> Tee<S> {
+	#[must_use]
+	pub fn new(inner: S) -> Self {
+		Self {
+			inner,
+			hasher: Hasher::new(),
+		}
+	}
+
+	/// This returns the hash of everything that has passed through so far.
+	#[must_use]
+	pub fn finalize(&self) -> Hash {
+		self.hasher.finalize()
+	}
+
+	/// This consumes the `Tee`, and returns the underlying stream.
+	pub fn into_inner(self) -> S {
+		self.inner
+	}
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Tee<R> {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		let filled = buf.filled().len();
+
+		let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+		if let Poll::Ready(Ok(())) = result {
+			this.hasher.update(&buf.filled()[filled..]);
+		}
+
+		result
+	}
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Tee<W> {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+
+		let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+		// only what the inner writer accepted has been written
+		if let Poll::Ready(Ok(written)) = result {
+			this.hasher.update(&buf[..written]);
+		}
+
+		result
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use crate::{
+		crypto::stream::{Algorithm, StreamEncryption},
+		primitives::{types::Key, BLOCK_LEN},
+	};
+
+	use super::*;
+
+	#[tokio::test]
+	async fn plaintext_is_hashed_while_encrypting() {
+		let plaintext = vec![0x5A; BLOCK_LEN + 17];
+		let mut reader = Tee::new(plaintext.as_slice());
+		let mut writer = Tee::new(Cursor::new(Vec::new()));
+
+		let (encryptor, _) =
+			StreamEncryption::new_with_random_nonce(Key::generate(), Algorithm::XChaCha20Poly1305)
+				.unwrap();
+		encryptor
+			.encrypt_streams(&mut reader, &mut writer, &[])
+			.await
+			.unwrap();
+
+		// a single pass gives the same hash as hashing the whole plaintext up front
+		assert_eq!(reader.finalize(), blake3::hash(&plaintext));
+		assert_eq!(
+			writer.finalize(),
+			blake3::hash(writer.into_inner().get_ref())
+		);
+	}
+}
@@ -4,6 +4,8 @@ use std::string::FromUtf8Error;
 
 use thiserror::Error;
 
+use crate::keys::source::KeySourceKind;
+
 #[cfg(feature = "rspc")]
 impl From<Error> for rspc::Error {
 	fn from(err: Error) -> Self {
@@ -14,71 +16,215 @@ impl From<Error> for rspc::Error {
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// This enum defines all possible errors that this crate can give
+///
+/// The `Display` implementation is meant to be shown to end users, and intentionally leaves out
+/// any internal details. Use [`Error::developer_detail`] when logging.
 #[derive(Error, Debug)]
 pub enum Error {
 	// crypto primitive errors (STREAM, hashing)
-	#[error("there was an error while password hashing")]
+	#[error("Unable to process the password, please try again.")]
 	PasswordHash,
-	#[error("error while encrypting")]
+	#[error("A password can't be generated with these settings.")]
+	PasswordPolicy,
+	#[error("This password is too easy to guess, please choose a stronger one.")]
+	WeakPassword,
+	#[error("The duress password must be different from the password.")]
+	DecoyPasswordMatches,
+	#[error("The data could not be encrypted.")]
 	Encrypt,
-	#[error("error while decrypting")]
+	#[error("The password is incorrect or the file is corrupted.")]
 	Decrypt,
-	#[error("nonce length mismatch")]
+	#[error("The file is incomplete, it may not have finished copying or downloading.")]
+	TruncatedTag,
+	#[error("The file is corrupted or uses an unsupported format.")]
 	NonceLengthMismatch,
-	#[error("error initialising stream encryption/decryption")]
+	#[error("Unable to start encryption or decryption, please try again.")]
 	StreamModeInit,
+	#[error("These encryption settings aren't supported.")]
+	UnsupportedBlockLen(usize),
 
 	// header errors
-	#[error("no keyslots available")]
+	#[error("This file's header is corrupted.")]
+	HeaderCorrupt,
+	#[error("The file is corrupted or uses an unsupported format.")]
+	InconsistentHeader,
+	#[error("These encryption settings aren't supported.")]
+	UnsupportedBodyParams,
+	#[error("This file has no keys that are able to unlock it.")]
 	NoKeyslots,
-	#[error("no preview media found")]
+	#[error("This file has no preview media.")]
 	NoPreviewMedia,
-	#[error("no metadata found")]
+	#[error("This file has no metadata.")]
 	NoMetadata,
-	#[error("tried adding too many keyslots to a header")]
+	#[error("This file can't be compared with others.")]
+	NoPlaintextCommitment,
+	#[error("This file already has the maximum number of keys.")]
 	TooManyKeyslots,
+	#[error("This is the only key that can unlock the file, so it can't be removed.")]
+	LastKeyslot,
+	#[error("This file was made by a newer version of Spacedrive, please update to open it.")]
+	UnsupportedHeaderVersion { found: u8, max_supported: u8 },
+	#[error(
+		"This file's keys were made by a newer version of Spacedrive, please update to open it."
+	)]
+	UnsupportedKeyslotVersion { found: u8, max_supported: u8 },
+	#[error("This key can't be unlocked this way, it needs a different kind of key.")]
+	KeySourceMismatch(KeySourceKind),
 
 	// key manager
-	#[error("requested key wasn't found in the key manager")]
+	#[error("The requested key could not be found.")]
 	KeyNotFound,
-	#[error("key is already mounted")]
+	#[error("This key is already mounted.")]
 	KeyAlreadyMounted,
-	#[error("key not mounted")]
+	#[error("This key is not mounted.")]
 	KeyNotMounted,
-	#[error("key isn't in the queue")]
+	#[error("This key is not currently being mounted.")]
 	KeyNotQueued,
-	#[error("key is already in the queue")]
+	#[error("This key is already being mounted.")]
 	KeyAlreadyQueued,
-	#[error("no default key has been set")]
+	#[error("No default key has been set.")]
 	NoDefaultKeySet,
-	#[error("keymanager is not unlocked")]
+	#[error("The key manager is locked, please unlock it and try again.")]
 	NotUnlocked,
-	#[error("no verification key")]
+	#[error("The key manager has not been set up yet.")]
 	NoVerificationKey,
-	#[error("key isn't flagged as memory only")]
+	#[error("This key is already saved to the library.")]
 	KeyNotMemoryOnly,
 
 	// general errors
-	#[error("I/O error: {0}")]
+	#[error("There was a problem reading or writing the file.")]
 	Io(#[from] std::io::Error),
-	#[error("mismatched data length while converting vec to array")]
+	#[error("The data is corrupted or in an unexpected format.")]
 	VecArrSizeMismatch,
-	#[error("incorrect password/details were provided")]
+	#[error("The password or secret key is incorrect.")]
 	IncorrectPassword,
-	#[error("error while serializing/deserializing an item")]
+	#[error("The file's keys have been tampered with.")]
+	KeyCommitmentFailed,
+	#[error("The data is corrupted or in an unexpected format.")]
 	Serialization,
-	#[error("string parse error")]
+	#[error("The data contains invalid text.")]
 	StringParse(#[from] FromUtf8Error),
 
 	// keyring
 	#[cfg(target_os = "linux")]
-	#[error("error with the linux keyring: {0}")]
+	#[error("There was a problem accessing the system keyring.")]
 	LinuxKeyringError(#[from] secret_service::Error),
 	#[cfg(any(target_os = "macos", target_os = "ios"))]
-	#[error("error with the apple keyring: {0}")]
+	#[error("There was a problem accessing the system keyring.")]
 	AppleKeyringError(#[from] security_framework::base::Error),
-	#[error("generic keyring error")]
+	#[error("There was a problem accessing the system keyring.")]
 	KeyringError,
-	#[error("keyring not available on this platform")]
+	#[error("A system keyring is not available on this platform.")]
 	KeyringNotSupported,
+
+	// hardware tokens
+	#[cfg(feature = "pkcs11")]
+	#[error("There was a problem accessing the hardware token.")]
+	HardwareToken(#[from] cryptoki::error::Error),
+}
+
+impl Error {
+	/// This returns a description of the error that is suitable for logs.
+	///
+	/// Unlike the `Display` implementation, this includes any internal specifics (such as the underlying
+	/// I/O or keyring error).
+	#[must_use]
+	pub fn developer_detail(&self) -> String {
+		match self {
+			Self::PasswordHash => "there was an error while password hashing".to_string(),
+			Self::PasswordPolicy => "the password policy allows no characters or words".to_string(),
+			Self::WeakPassword => "the password is rated below the minimum strength".to_string(),
+			Self::DecoyPasswordMatches => {
+				"the decoy password is the same as the real password".to_string()
+			}
+			Self::Encrypt => "error while encrypting (AEAD encryption failure)".to_string(),
+			Self::Decrypt => "error while decrypting (AEAD tag verification failure)".to_string(),
+			Self::TruncatedTag => "the final block is shorter than an AEAD tag".to_string(),
+			Self::NonceLengthMismatch => "nonce length mismatch".to_string(),
+			Self::StreamModeInit => "error initialising stream encryption/decryption".to_string(),
+			Self::UnsupportedBlockLen(len) => {
+				format!("block length {len} can't be recorded in a header (see FileHeader::supports_block_len)")
+			}
+			Self::HeaderCorrupt => "the header checksum doesn't match the header".to_string(),
+			Self::InconsistentHeader => {
+				"a keyslot's algorithm doesn't match the header's".to_string()
+			}
+			Self::UnsupportedBodyParams => {
+				"the body's framing, block length or rekey interval can't be stored in (or decrypted from) this header version".to_string()
+			}
+			Self::NoKeyslots => "no keyslots available".to_string(),
+			Self::NoPreviewMedia => "no preview media found".to_string(),
+			Self::NoMetadata => "no metadata found".to_string(),
+			Self::NoPlaintextCommitment => "no plaintext commitment found".to_string(),
+			Self::TooManyKeyslots => "tried adding too many keyslots to a header".to_string(),
+			Self::LastKeyslot => "tried removing the last keyslot from a header".to_string(),
+			Self::UnsupportedHeaderVersion {
+				found,
+				max_supported,
+			} => format!("header version {found} is newer than the supported {max_supported}"),
+			Self::UnsupportedKeyslotVersion {
+				found,
+				max_supported,
+			} => format!("keyslot version {found} is newer than the supported {max_supported}"),
+			Self::KeySourceMismatch(expected) => {
+				format!("the keyslot needs a key from a {expected:?} source")
+			}
+			Self::KeyNotFound => "requested key wasn't found in the key manager".to_string(),
+			Self::KeyAlreadyMounted => "key is already mounted".to_string(),
+			Self::KeyNotMounted => "key not mounted".to_string(),
+			Self::KeyNotQueued => "key isn't in the queue".to_string(),
+			Self::KeyAlreadyQueued => "key is already in the queue".to_string(),
+			Self::NoDefaultKeySet => "no default key has been set".to_string(),
+			Self::NotUnlocked => "keymanager is not unlocked".to_string(),
+			Self::NoVerificationKey => "no verification key".to_string(),
+			Self::KeyNotMemoryOnly => "key isn't flagged as memory only".to_string(),
+			Self::Io(e) => format!("I/O error: {e:?}"),
+			Self::VecArrSizeMismatch => {
+				"mismatched data length while converting vec to array".to_string()
+			}
+			Self::IncorrectPassword => {
+				"incorrect password/details were provided (IncorrectPassword)".to_string()
+			}
+			Self::KeyCommitmentFailed => {
+				"the master key doesn't match the header's key commitment".to_string()
+			}
+			Self::Serialization => "error while serializing/deserializing an item".to_string(),
+			Self::StringParse(e) => format!("string parse error: {e}"),
+			#[cfg(target_os = "linux")]
+			Self::LinuxKeyringError(e) => format!("error with the linux keyring: {e}"),
+			#[cfg(any(target_os = "macos", target_os = "ios"))]
+			Self::AppleKeyringError(e) => format!("error with the apple keyring: {e}"),
+			Self::KeyringError => "generic keyring error".to_string(),
+			Self::KeyringNotSupported => "keyring not available on this platform".to_string(),
+			#[cfg(feature = "pkcs11")]
+			Self::HardwareToken(e) => format!("error with the PKCS#11 hardware token: {e}"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Error;
+
+	#[test]
+	fn incorrect_password_display() {
+		let error = Error::IncorrectPassword;
+
+		let display = error.to_string();
+		assert_eq!(display, "The password or secret key is incorrect.");
+		assert!(!display.contains("IncorrectPassword"));
+
+		assert!(error.developer_detail().contains("IncorrectPassword"));
+	}
+
+	#[test]
+	fn io_display_hides_inner_error() {
+		let error = Error::Io(std::io::Error::new(
+			std::io::ErrorKind::PermissionDenied,
+			"/secret/path",
+		));
+
+		assert!(!error.to_string().contains("/secret/path"));
+		assert!(error.developer_detail().contains("/secret/path"));
+	}
 }
@@ -0,0 +1,269 @@
+//! This module contains a temporary file for content that shouldn't outlive it, such as plaintext that's being verified or encrypted.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let mut temp = SecureTempFile::new_in("/path/to").await?;
+//! encrypt(reader, &mut temp, password, EncryptOptions::default()).await?;
+//!
+//! // Atomically replace the destination, or have the temporary file erased if anything above failed
+//! temp.persist("/path/to/file.bytes").await?;
+//! ```
+//!
+//! A temporary file that isn't persisted should be cleaned up with `erase()`:
+//!
+//! ```rust,ignore
+//! let mut temp = SecureTempFile::new().await?;
+//! decrypt(reader, &mut temp, password).await?;
+//! verify(&mut temp).await?;
+//!
+//! temp.erase().await?;
+//! ```
+use std::{
+	env,
+	fs::OpenOptions,
+	io::{self, SeekFrom, Write},
+	path::{Path, PathBuf},
+	pin::Pin,
+	task::{Context, Poll},
+};
+
+use tokio::{
+	fs::{self, File},
+	io::{AsyncRead, AsyncSeek, AsyncWrite, AsyncWriteExt, ReadBuf},
+};
+use uuid::Uuid;
+
+use crate::{primitives::BLOCK_LEN, Result};
+
+/// This is a temporary file that's overwritten with zeroes and deleted once it's no longer needed.
+///
+/// It should be cleaned up with `erase()` (or `persist()`). If it's dropped instead, it's erased in the background on
+/// tokio's blocking thread pool, as it may be large and dropping can't wait for it.
+///
+/// Overwriting is best-effort, and it's not guaranteed to erase anything on modern storage. Copy-on-write and journaling
+/// file systems, and the wear-levelling that's built into the firmware of solid-state drives, may keep the old content
+/// elsewhere on the disk. See `crate::fs::erase::erase()` for more information.
+///
+/// It implements `AsyncRead`, `AsyncWrite` and `AsyncSeek`, so it can be used with the existing stream APIs.
+pub struct SecureTempFile {
+	// this is only `None` once the file is being persisted, erased or dropped
+	file: Option<File>,
+	path: PathBuf,
+	// this is set once the file has been persisted or erased, so there's nothing left to clean up
+	finished: bool,
+}
+
+impl SecureTempFile {
+	/// This creates a new temporary file within the system's temporary directory.
+	pub async fn new() -> Result<Self> {
+		Self::new_in(env::temp_dir()).await
+	}
+
+	/// This creates a new temporary file within the provided directory.
+	///
+	/// It should be on the same file system as the destination, if the file will be persisted.
+	pub async fn new_in(dir: impl AsRef<Path>) -> Result<Self> {
+		let path = dir.as_ref().join(format!(".sd-{}.tmp", Uuid::new_v4()));
+
+		let mut options = fs::OpenOptions::new();
+		options.read(true).write(true).create_new(true);
+
+		// nobody else should be able to read this
+		#[cfg(unix)]
+		options.mode(0o600);
+
+		let file = options.open(&path).await?;
+
+		Ok(Self {
+			file: Some(file),
+			path,
+			finished: false,
+		})
+	}
+
+	#[must_use]
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// This atomically moves the temporary file to `to` (replacing anything that's already there), so it won't be erased.
+	///
+	/// If this fails, the temporary file is erased as usual.
+	pub async fn persist(mut self, to: impl AsRef<Path>) -> Result<()> {
+		let mut file = self.file.take().expect("the file is only taken once");
+		file.flush().await?;
+		file.sync_all().await?;
+
+		// the handle is closed first, as open files can't be renamed on Windows
+		drop(file);
+		fs::rename(&self.path, to).await?;
+
+		self.finished = true;
+
+		Ok(())
+	}
+
+	/// This overwrites the temporary file with zeroes and deletes it, once it's no longer needed.
+	pub async fn erase(mut self) -> Result<()> {
+		// the handle is closed first, as open files can't be removed on Windows
+		drop(self.file.take());
+		self.finished = true;
+
+		let path = self.path.clone();
+		tokio::task::spawn_blocking(move || erase_file(&path))
+			.await
+			.map_err(io::Error::from)??;
+
+		Ok(())
+	}
+
+	fn file_mut(&mut self) -> &mut File {
+		self.file
+			.as_mut()
+			.expect("the file is only taken when it's persisted, erased or dropped")
+	}
+}
+
+/// This overwrites the entire file with zeroes, flushes it to the disk and then deletes it.
+fn erase_file(path: &Path) -> io::Result<()> {
+	overwrite(path)?;
+	std::fs::remove_file(path)
+}
+
+/// This overwrites the entire file with zeroes, and flushes it to the disk.
+fn overwrite(path: &Path) -> io::Result<()> {
+	let mut file = OpenOptions::new().write(true).open(path)?;
+	let len = file.metadata()?.len();
+
+	let buf = vec![0u8; BLOCK_LEN];
+	let mut remaining = len;
+
+	while remaining > 0 {
+		#[allow(clippy::cast_possible_truncation)]
+		let n = remaining.min(BLOCK_LEN as u64) as usize;
+		file.write_all(&buf[..n])?;
+		remaining -= n as u64;
+	}
+
+	file.sync_all()
+}
+
+impl Drop for SecureTempFile {
+	fn drop(&mut self) {
+		// the handle is closed first, as open files can't be removed on Windows
+		drop(self.file.take());
+
+		if self.finished {
+			return;
+		}
+
+		// we can't do anything about errors while dropping, and removing the file is still worth trying
+		let path = std::mem::take(&mut self.path);
+		let cleanup = move || {
+			overwrite(&path).ok();
+			std::fs::remove_file(&path).ok();
+		};
+
+		// this may be dropped within an async task, which mustn't be blocked by the disk
+		match tokio::runtime::Handle::try_current() {
+			Ok(handle) => drop(handle.spawn_blocking(cleanup)),
+			Err(_) => cleanup(),
+		}
+	}
+}
+
+impl AsyncRead for SecureTempFile {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		Pin::new(self.get_mut().file_mut()).poll_read(cx, buf)
+	}
+}
+
+impl AsyncWrite for SecureTempFile {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		Pin::new(self.get_mut().file_mut()).poll_write(cx, buf)
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Pin::new(self.get_mut().file_mut()).poll_flush(cx)
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Pin::new(self.get_mut().file_mut()).poll_shutdown(cx)
+	}
+}
+
+impl AsyncSeek for SecureTempFile {
+	fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+		Pin::new(self.get_mut().file_mut()).start_seek(position)
+	}
+
+	fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+		Pin::new(self.get_mut().file_mut()).poll_complete(cx)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+	use super::*;
+
+	#[tokio::test]
+	async fn removed_when_erased() {
+		let mut temp = SecureTempFile::new().await.unwrap();
+		let path = temp.path().to_path_buf();
+
+		temp.write_all(b"plaintext").await.unwrap();
+		temp.rewind().await.unwrap();
+
+		let mut buf = Vec::new();
+		temp.read_to_end(&mut buf).await.unwrap();
+		assert_eq!(buf, b"plaintext");
+		assert!(path.exists());
+
+		temp.erase().await.unwrap();
+
+		assert!(!path.exists());
+	}
+
+	#[tokio::test]
+	async fn removed_in_the_background_when_dropped() {
+		let mut temp = SecureTempFile::new().await.unwrap();
+		let path = temp.path().to_path_buf();
+
+		temp.write_all(b"plaintext").await.unwrap();
+		drop(temp);
+
+		tokio::time::timeout(Duration::from_secs(5), async {
+			while path.exists() {
+				tokio::time::sleep(Duration::from_millis(10)).await;
+			}
+		})
+		.await
+		.expect("the file should be erased soon after being dropped");
+	}
+
+	#[tokio::test]
+	async fn persisted_file_is_kept() {
+		let mut temp = SecureTempFile::new().await.unwrap();
+		let to = temp.path().with_extension("persisted");
+
+		temp.write_all(b"ciphertext").await.unwrap();
+		temp.persist(&to).await.unwrap();
+
+		assert_eq!(fs::read(&to).await.unwrap(), b"ciphertext");
+
+		fs::remove_file(&to).await.unwrap();
+	}
+}
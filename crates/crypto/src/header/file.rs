@@ -14,7 +14,7 @@
 //! let mut keyslots: Vec<Keyslot> = Vec::new();
 //! keyslots.push(
 //!     Keyslot::new(
-//!         KeyslotVersion::V1,
+//!         LATEST_KEYSLOT,
 //!         ALGORITHM,
 //!         HASHING_ALGORITHM,
 //!         password,
@@ -29,19 +29,34 @@
 //! // Write the header to the file
 //! header.write(&mut writer).unwrap();
 //! ```
-use std::io::{Cursor, SeekFrom};
+use std::{
+	io::{Cursor, SeekFrom},
+	num::NonZeroU32,
+};
 
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
 use crate::{
-	crypto::stream::Algorithm,
-	primitives::types::{Key, Nonce},
+	crypto::{
+		bench::MIN_RECOMMENDED_BLOCK_LEN,
+		stream::{Algorithm, Framing, MAX_FRAMED_BLOCK_LEN},
+	},
+	keys::{
+		hashing::{HashingAlgorithm, KdfCost},
+		source::{KeySource, KeySourceKind},
+	},
+	primitives::{
+		to_array,
+		types::{Key, Nonce, Salt},
+		KEY_COMMITMENT_CONTEXT, KEY_LEN,
+	},
 	Error, Protected, Result,
 };
 
 use super::{
 	keyslot::{Keyslot, KEYSLOT_SIZE},
 	metadata::Metadata,
+	plaintext_commitment::PlaintextCommitment,
 	preview_media::PreviewMedia,
 };
 
@@ -49,11 +64,62 @@ use super::{
 /// These currently are set as "ballapp"
 pub const MAGIC_BYTES: [u8; 7] = [0x62, 0x61, 0x6C, 0x6C, 0x61, 0x70, 0x70];
 
+/// This checks if the bytes start with the magic bytes of a Spacedrive-encrypted file.
+///
+/// Only the magic bytes are compared, so this does not guarantee that the rest of the header is valid.
+#[must_use]
+pub fn is_encrypted_bytes(bytes: &[u8]) -> bool {
+	bytes.starts_with(&MAGIC_BYTES)
+}
+
+/// This peeks at the start of a reader to check if it's a Spacedrive-encrypted file, without parsing the header.
+///
+/// The reader is seeked back to where it was, so it can still be passed to `FileHeader::from_reader()` afterwards.
+///
+/// Inputs that are shorter than the magic bytes are not encrypted files, and will not return an error.
+pub async fn is_encrypted_file<R>(reader: &mut R) -> Result<bool>
+where
+	R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+{
+	let position = reader.stream_position().await?;
+
+	let mut magic_bytes = [0u8; MAGIC_BYTES.len()];
+	let mut read_count = 0;
+	loop {
+		let i = reader.read(&mut magic_bytes[read_count..]).await?;
+		read_count += i;
+		if i == 0 || read_count == MAGIC_BYTES.len() {
+			break;
+		}
+	}
+
+	reader.seek(SeekFrom::Start(position)).await?;
+
+	Ok(is_encrypted_bytes(&magic_bytes[..read_count]))
+}
+
+/// This is the first byte of an empty keyslot, which is used to pad V1 and V2 headers out to two keyslots.
+const EMPTY_KEYSLOT_MARKER: u8 = 0x00;
+
+/// This is the maximum amount of keyslots that a V3 or V4 header can hold, so each user or device can unlock a file with their own password.
+pub const MAX_KEYSLOTS: usize = 8;
+
+/// This is the length of the checksum that V4 and V5 headers store after their keyslots.
+pub const HEADER_CHECKSUM_LEN: usize = 8;
+
+/// This is the length of the body parameters that V5 headers store after the key commitment.
+///
+/// They're the framing, the block length (see `block_len_to_bits()`), the rekey interval (as a little-endian `u32`) and a byte of flags.
+pub const BODY_PARAMS_LEN: usize = 7;
+
+/// This flag is set in a V5 header's body parameters if a `PlaintextCommitment` follows the other header items.
+const FLAG_PLAINTEXT_COMMITMENT: u8 = 0x01;
+
 /// This header is primarily used for encrypting/decrypting single files.
 ///
-/// It has support for 2 keyslots (maximum).
+/// V1 and V2 headers support 2 keyslots (maximum), while V3, V4 and V5 headers support up to `MAX_KEYSLOTS`.
 ///
-/// You may optionally attach `Metadata` and `PreviewMedia` structs to this header, and they will be accessible on deserialization.
+/// You may optionally attach `Metadata`, `PreviewMedia` and `PlaintextCommitment` structs to this header, and they will be accessible on deserialization.
 ///
 /// This contains everything necessary for decryption, and the entire header can be flaunted with no worries (provided a suitable password was selected by the user).
 #[derive(Clone)]
@@ -61,25 +127,118 @@ pub struct FileHeader {
 	pub version: FileHeaderVersion,
 	pub algorithm: Algorithm,
 	pub nonce: Nonce,
+	/// Only V5 headers can store a framing other than `Framing::Fixed`.
+	pub framing: Framing,
+	/// If this is set, the body was encrypted with `StreamEncryption::encrypt_streams_rekeyed()` and a fresh key for every `rekey_interval` blocks.
+	pub rekey_interval: Option<NonZeroU32>,
+	/// This records the block size that a `LengthPrefixed` body was encrypted with (e.g. from `recommend_block_size()`).
+	///
+	/// Every block is prefixed with its own length, so decrypting the whole body doesn't depend on it. `StreamDecryption::decrypt_range()` needs it
+	/// to find blocks without reading every prefix before them though.
+	/// Only powers of two between `MIN_RECOMMENDED_BLOCK_LEN` and `MAX_FRAMED_BLOCK_LEN` can be recorded, and only in V5 headers.
+	pub block_len: Option<usize>,
+	/// This commits the header to a single master key, so a keyslot can't be swapped out for one that unwraps a different key.
+	///
+	/// It's only stored in V2 (and newer) headers, and is set with `FileHeader::add_key_commitment()`.
+	pub key_commitment: Option<[u8; KEY_LEN]>,
 	pub keyslots: Vec<Keyslot>,
 	pub metadata: Option<Metadata>,
 	pub preview_media: Option<PreviewMedia>,
+	pub plaintext_commitment: Option<PlaintextCommitment>,
 }
 
 /// This defines the main file header version.
 #[derive(Clone, Copy)]
 pub enum FileHeaderVersion {
 	V1,
+	/// This is the same as V1, with a master key commitment after the nonce padding.
+	V2,
+	/// This is the same as V2, with the number of keyslots stored before them (so they aren't padded out to two).
+	V3,
+	/// This is the same as V3, with a checksum of everything up to the end of the keyslots stored after them.
+	///
+	/// It allows corruption (e.g. a flipped bit in the nonce) to be reported as such, before anything is decrypted.
+	V4,
+	/// This is the same as V4, with the body's parameters (framing, block length and rekey interval) stored after the key commitment.
+	///
+	/// They're part of the AAD, and older builds refuse to read a V5 header - rather than decrypting a body they don't know the layout of.
+	V5,
+}
+
+impl FileHeaderVersion {
+	/// This returns how many keyslots a header of this version can hold.
+	#[must_use]
+	pub const fn max_keyslots(self) -> usize {
+		match self {
+			Self::V1 | Self::V2 => 2,
+			Self::V3 | Self::V4 | Self::V5 => MAX_KEYSLOTS,
+		}
+	}
+
+	/// This returns the length of the checksum after the keyslots, which only V4 and V5 headers have.
+	#[must_use]
+	pub const fn checksum_len(self) -> usize {
+		match self {
+			Self::V1 | Self::V2 | Self::V3 => 0,
+			Self::V4 | Self::V5 => HEADER_CHECKSUM_LEN,
+		}
+	}
+
+	/// This returns whether the header stores the body's parameters, which only V5 headers do.
+	///
+	/// Bodies described by older headers always use `Framing::Fixed`, with no rekeying.
+	#[must_use]
+	pub const fn has_body_params(self) -> bool {
+		matches!(self, Self::V5)
+	}
+}
+
+/// This derives the commitment of a master key, which is what gets stored in the header.
+fn key_commitment(master_key: &Key) -> [u8; KEY_LEN] {
+	blake3::derive_key(KEY_COMMITMENT_CONTEXT, master_key.expose())
+}
+
+/// This is a truncated BLAKE3 hash of the header bytes up to the end of the keyslots.
+///
+/// It isn't keyed, so it only catches accidental corruption - tampering is still caught by the AAD and the key commitment.
+fn header_checksum(bytes: &[u8]) -> [u8; HEADER_CHECKSUM_LEN] {
+	let mut checksum = [0u8; HEADER_CHECKSUM_LEN];
+	checksum.copy_from_slice(&blake3::hash(bytes).as_bytes()[..HEADER_CHECKSUM_LEN]);
+	checksum
+}
+
+/// This stores a block length as its power of two above `MIN_RECOMMENDED_BLOCK_LEN`, plus one (so zero means it wasn't recorded).
+///
+/// Lengths that can't be stored this way aren't recorded.
+#[allow(clippy::cast_possible_truncation)] // there are only a handful of powers of two between the bounds
+fn block_len_to_bits(block_len: Option<usize>) -> u8 {
+	block_len
+		.filter(|len| FileHeader::supports_block_len(*len))
+		.map_or(0, |len| {
+			(len / MIN_RECOMMENDED_BLOCK_LEN).trailing_zeros() as u8 + 1
+		})
+}
+
+fn block_len_from_bits(bits: u8) -> Result<Option<usize>> {
+	match bits {
+		0 => Ok(None),
+		bits => Some(MIN_RECOMMENDED_BLOCK_LEN << (bits - 1))
+			.filter(|len| *len <= MAX_FRAMED_BLOCK_LEN)
+			.map(Some)
+			.ok_or(Error::Serialization),
+	}
 }
 
 impl FileHeader {
 	/// This function is used for creating a file header.
+	///
+	/// Every keyslot needs to use the same algorithm as the header (see `FileHeader::validate()`).
 	pub fn new(
 		version: FileHeaderVersion,
 		algorithm: Algorithm,
 		keyslots: Vec<Keyslot>,
 	) -> Result<Self> {
-		if keyslots.len() > 2 {
+		if keyslots.len() > version.max_keyslots() {
 			return Err(Error::TooManyKeyslots);
 		}
 
@@ -87,14 +246,45 @@ impl FileHeader {
 			version,
 			algorithm,
 			nonce: Nonce::generate(algorithm)?,
+			framing: Framing::Fixed,
+			rekey_interval: None,
+			block_len: None,
+			key_commitment: None,
 			keyslots,
 			metadata: None,
 			preview_media: None,
+			plaintext_commitment: None,
 		};
 
+		f.validate()?;
+
 		Ok(f)
 	}
 
+	/// This checks that every keyslot uses the same algorithm as the header.
+	///
+	/// Keyslots store their own algorithm, but they're meant to inherit the header's, so a header that mixes them has either been crafted or corrupted.
+	pub fn validate(&self) -> Result<()> {
+		if self
+			.keyslots
+			.iter()
+			.all(|keyslot| keyslot.algorithm == self.algorithm)
+		{
+			Ok(())
+		} else {
+			Err(Error::InconsistentHeader)
+		}
+	}
+
+	/// This returns whether a block length can be recorded in a header, which is the case for powers of two between `MIN_RECOMMENDED_BLOCK_LEN` and `MAX_FRAMED_BLOCK_LEN`.
+	///
+	/// All of them are multiples of the underlying AES and ChaCha20 block sizes.
+	#[must_use]
+	pub fn supports_block_len(block_len: usize) -> bool {
+		block_len.is_power_of_two()
+			&& (MIN_RECOMMENDED_BLOCK_LEN..=MAX_FRAMED_BLOCK_LEN).contains(&block_len)
+	}
+
 	/// This includes the magic bytes at the start of the file, and remainder of the header itself (excluding keyslots, metadata, and preview media as these can all change)
 	///
 	/// This can be used for getting the length of the AAD
@@ -102,31 +292,152 @@ impl FileHeader {
 	pub const fn size(version: FileHeaderVersion) -> usize {
 		match version {
 			FileHeaderVersion::V1 => 36,
+			FileHeaderVersion::V2 | FileHeaderVersion::V3 | FileHeaderVersion::V4 => 36 + KEY_LEN,
+			FileHeaderVersion::V5 => 36 + KEY_LEN + BODY_PARAMS_LEN,
+		}
+	}
+
+	/// This is where the keyslots start, which is right after the AAD (and the number of keyslots, for V3 and newer headers).
+	#[must_use]
+	pub const fn keyslots_offset(version: FileHeaderVersion) -> usize {
+		match version {
+			FileHeaderVersion::V1 | FileHeaderVersion::V2 => Self::size(version),
+			FileHeaderVersion::V3 | FileHeaderVersion::V4 | FileHeaderVersion::V5 => {
+				Self::size(version) + 1
+			}
+		}
+	}
+
+	/// This commits the header to the master key, which is then verified each time the master key is decrypted.
+	///
+	/// It needs to be called before `FileHeader::generate_aad()`, as the commitment is part of the AAD.
+	///
+	/// An error will be returned if the header version doesn't support key commitments.
+	pub fn add_key_commitment(&mut self, master_key: &Key) -> Result<()> {
+		match self.version {
+			FileHeaderVersion::V1 => Err(Error::Serialization),
+			FileHeaderVersion::V2
+			| FileHeaderVersion::V3
+			| FileHeaderVersion::V4
+			| FileHeaderVersion::V5 => {
+				self.key_commitment = Some(key_commitment(master_key));
+				Ok(())
+			}
+		}
+	}
+
+	/// This checks a decrypted master key against the header's commitment (if there is one).
+	///
+	/// `blake3::Hash` is used for the comparison, as its equality check is constant-time.
+	fn verify_key_commitment(&self, master_key: Key) -> Result<Key> {
+		if let Some(commitment) = self.key_commitment {
+			if blake3::Hash::from(commitment) != blake3::Hash::from(key_commitment(&master_key)) {
+				return Err(Error::KeyCommitmentFailed);
+			}
+		}
+
+		Ok(master_key)
+	}
+
+	/// This picks the master key out of the results of trying every keyslot.
+	///
+	/// Every keyslot is tried, even once one of them has unwrapped the master key, so the time taken doesn't reveal which keyslot (and so which user) the password belongs to.
+	/// A keyslot only matches if its AEAD tag verifies and the master key it unwraps matches the header's key commitment, so the first key that passes both is returned.
+	/// A tampered keyslot therefore can't shadow a valid one that comes after it.
+	///
+	/// If no key passes, you receive `Error::KeyCommitmentFailed` if any keyslot unwrapped a key (which means the header has been tampered with), or `Error::IncorrectPassword` otherwise.
+	///
+	/// Keyslots with costlier hashing algorithms still take longer to try, but those are stored in the header in plaintext anyway.
+	fn unwrapped_master_key(&self, results: Vec<Result<Key>>) -> Result<Key> {
+		let mut unwrapped = false;
+		let mut master_key = None;
+
+		for key in results.into_iter().flatten() {
+			unwrapped = true;
+
+			if master_key.is_none() {
+				master_key = self.verify_key_commitment(key).ok();
+			}
 		}
+
+		master_key.ok_or(if unwrapped {
+			Error::KeyCommitmentFailed
+		} else {
+			Error::IncorrectPassword
+		})
 	}
 
 	/// This is a helper function to decrypt a master key from keyslots that are attached to a header, from a user-supplied password.
 	///
-	/// You receive an error if the password doesn't match or if there are no keyslots.
+	/// Only keyslots that are unlocked with a password are tried (see `FileHeader::decrypt_master_key_from_source()` for the others).
+	/// Every one of them is tried, so this takes the same amount of time regardless of which keyslot the password matches.
+	/// That's one full KDF run per password keyslot (each off the async runtime's threads), so it gets slower as keyslots are added.
+	///
+	/// You receive an error if the password doesn't match or if there are no password keyslots.
 	#[allow(clippy::needless_pass_by_value)]
 	pub async fn decrypt_master_key(&self, password: Protected<Vec<u8>>) -> Result<Key> {
-		if self.keyslots.is_empty() {
+		let mut keyslots = self.password_keyslots().peekable();
+
+		if keyslots.peek().is_none() {
+			return Err(Error::NoKeyslots);
+		}
+
+		let mut results = Vec::with_capacity(self.keyslots.len());
+		for v in keyslots {
+			results.push(v.decrypt_master_key(password.clone()).await);
+		}
+
+		self.unwrapped_master_key(results)
+	}
+
+	/// This is a helper function to decrypt a master key from the keyslots that were created with this kind of source.
+	///
+	/// You receive an error if the key doesn't match or if there are no keyslots for this kind of source.
+	pub async fn decrypt_master_key_from_source(&self, source: &impl KeySource) -> Result<Key> {
+		let mut keyslots = self
+			.keyslots
+			.iter()
+			.filter(|keyslot| keyslot.source == source.kind())
+			.peekable();
+
+		if keyslots.peek().is_none() {
 			return Err(Error::NoKeyslots);
 		}
 
-		for v in &self.keyslots {
-			if let Ok(key) = v.decrypt_master_key(password.clone()).await {
-				return Ok(key);
+		let mut results = Vec::new();
+		for v in keyslots {
+			results.push(v.decrypt_master_key_from_source(source).await);
+		}
+
+		self.unwrapped_master_key(results)
+	}
+
+	/// This returns the keyslots that are unlocked with a password.
+	fn password_keyslots(&self) -> impl Iterator<Item = &Keyslot> {
+		self.keyslots
+			.iter()
+			.filter(|keyslot| keyslot.source == KeySourceKind::Password)
+	}
+
+	/// This returns the kinds of sources that the header's keyslots can be unlocked with, so the user can be asked for one of them.
+	#[must_use]
+	pub fn key_sources(&self) -> Vec<KeySourceKind> {
+		let mut sources = Vec::with_capacity(self.keyslots.len());
+		for keyslot in &self.keyslots {
+			if !sources.contains(&keyslot.source) {
+				sources.push(keyslot.source);
 			}
 		}
 
-		Err(Error::IncorrectPassword)
+		sources
 	}
 
 	/// This is a helper function to decrypt a master key from keyslots that are attached to a header.
 	///
 	/// It takes in a Vec of pre-hashed keys, which is what the key manager returns
 	///
+	/// Every key is tried against every keyslot, so this takes the same amount of time regardless of which pair matches.
+	///
 	/// You receive an error if the password doesn't match or if there are no keyslots.
 	#[allow(clippy::needless_pass_by_value)]
 	pub async fn decrypt_master_key_from_prehashed(&self, hashed_keys: Vec<Key>) -> Result<Key> {
@@ -134,18 +445,17 @@ impl FileHeader {
 			return Err(Error::NoKeyslots);
 		}
 
+		let mut results = Vec::with_capacity(hashed_keys.len() * self.keyslots.len());
 		for hashed_key in hashed_keys {
 			for v in &self.keyslots {
-				if let Ok(key) = v
-					.decrypt_master_key_from_prehashed(hashed_key.clone())
-					.await
-				{
-					return Ok(key);
-				}
+				results.push(
+					v.decrypt_master_key_from_prehashed(hashed_key.clone())
+						.await,
+				);
 			}
 		}
 
-		Err(Error::IncorrectPassword)
+		self.unwrapped_master_key(results)
 	}
 
 	/// This is a helper function to serialize and write a header to a file.
@@ -159,6 +469,8 @@ impl FileHeader {
 
 	/// This is a helper function to find which keyslot a key belongs to.
 	///
+	/// As with `FileHeader::decrypt_master_key()`, only keyslots that are unlocked with a password are tried.
+	///
 	/// You receive an error if the password doesn't match or if there are no keyslots.
 	#[allow(clippy::needless_pass_by_value)]
 	pub async fn find_key_index(&self, password: Protected<Vec<u8>>) -> Result<usize> {
@@ -166,7 +478,12 @@ impl FileHeader {
 			return Err(Error::NoKeyslots);
 		}
 
-		for (i, v) in self.keyslots.iter().enumerate() {
+		for (i, v) in self
+			.keyslots
+			.iter()
+			.enumerate()
+			.filter(|(_, keyslot)| keyslot.source == KeySourceKind::Password)
+		{
 			if let Some(i) = v.decrypt_master_key(password.clone()).await.ok().map(|_| i) {
 				return Ok(i);
 			}
@@ -175,18 +492,154 @@ impl FileHeader {
 		Err(Error::IncorrectPassword)
 	}
 
+	/// This removes a keyslot from the header, so its password or key can no longer unlock the file.
+	///
+	/// Once the header is written back over the old one, the keyslot is zeroed out. The body doesn't need to be re-encrypted, as it doesn't depend on the keyslots.
+	///
+	/// V3 and V4 headers don't pad their keyslots, so they shrink by a keyslot - the header and the body will need to be written out again, rather than in place.
+	///
+	/// This alone doesn't fully revoke access - whoever held the keyslot may have kept the master key, which can still decrypt the file.
+	/// Access is only truly revoked once the file has been re-encrypted under a new master key.
+	///
+	/// You receive an error if there's no keyslot at `index`, or if it's the last one (as the file would become permanently inaccessible).
+	pub fn remove_keyslot(&mut self, index: usize) -> Result<()> {
+		if index >= self.keyslots.len() {
+			return Err(Error::KeyNotFound);
+		} else if self.keyslots.len() == 1 {
+			return Err(Error::LastKeyslot);
+		}
+
+		self.keyslots.remove(index);
+
+		Ok(())
+	}
+
+	/// This changes the password hashing algorithm of a keyslot in place, e.g. to upgrade it to stronger parameters.
+	///
+	/// The master key is decrypted with the keyslot's current hashing algorithm, and encrypted again with the new one (under a fresh salt and nonce).
+	/// The master key itself stays the same, so the body doesn't need to be re-encrypted.
+	///
+	/// You receive an error if there's no keyslot at `index`, if it isn't unlocked with a password, or if the password doesn't match.
+	pub async fn rehash_keyslot(
+		&mut self,
+		index: usize,
+		password: Protected<Vec<u8>>,
+		hashing_algorithm: HashingAlgorithm,
+	) -> Result<()> {
+		let keyslot = self.keyslots.get(index).ok_or(Error::KeyNotFound)?;
+		if keyslot.source != KeySourceKind::Password {
+			return Err(Error::KeySourceMismatch(keyslot.source));
+		}
+		let (version, algorithm) = (keyslot.version, keyslot.algorithm);
+
+		let master_key = keyslot
+			.decrypt_master_key(password.clone())
+			.await
+			.map_err(|_| Error::IncorrectPassword)?;
+		let master_key = self.verify_key_commitment(master_key)?;
+
+		let content_salt = Salt::generate();
+		let hashed_password = tokio::task::spawn_blocking(move || {
+			hashing_algorithm.hash(password, content_salt, None)
+		})
+		.await
+		.map_err(|_| Error::PasswordHash)?
+		.map_err(|_| Error::PasswordHash)?;
+
+		self.keyslots[index] = Keyslot::new(
+			version,
+			algorithm,
+			hashing_algorithm,
+			content_salt,
+			hashed_password,
+			master_key,
+		)
+		.await?;
+
+		Ok(())
+	}
+
+	/// This returns whether every keyslot's password hashing is at least as costly as `min`.
+	///
+	/// It's meant for flagging files whose keyslots should be upgraded with `FileHeader::rehash_keyslot()`.
+	#[must_use]
+	pub fn meets_policy(&self, min: KdfCost) -> bool {
+		self.keyslots
+			.iter()
+			.all(|keyslot| keyslot.kdf_cost().meets(&min))
+	}
+
+	/// This pads the nonce out to 25 bytes with zeroes.
+	fn nonce_padding(&self) -> Vec<u8> {
+		vec![0u8; 25 - self.nonce.len()]
+	}
+
+	/// This returns the key commitment bytes for V2 (and newer) headers, where an empty commitment is stored as zeroes.
+	fn key_commitment_bytes(&self) -> Vec<u8> {
+		match self.version {
+			FileHeaderVersion::V1 => Vec::new(),
+			FileHeaderVersion::V2
+			| FileHeaderVersion::V3
+			| FileHeaderVersion::V4
+			| FileHeaderVersion::V5 => self.key_commitment.unwrap_or([0u8; KEY_LEN]).to_vec(),
+		}
+	}
+
+	/// This returns the body parameters that V5 headers store after the key commitment (see `BODY_PARAMS_LEN`).
+	///
+	/// Older headers don't store them, so they're empty.
+	fn body_params_bytes(&self) -> Vec<u8> {
+		if !self.version.has_body_params() {
+			return Vec::new();
+		}
+
+		let mut params = vec![0u8; BODY_PARAMS_LEN];
+		params[0] = self.framing.to_byte();
+		params[1] = block_len_to_bits(self.block_len);
+		params[2..6].copy_from_slice(&self.rekey_interval.map_or(0, NonZeroU32::get).to_le_bytes());
+
+		if self.plaintext_commitment.is_some() {
+			params[6] |= FLAG_PLAINTEXT_COMMITMENT;
+		}
+
+		params
+	}
+
+	/// This checks that the body parameters can be stored in the header's version.
+	///
+	/// Older builds would ignore them (and decrypt the body incorrectly), so only V5 headers may describe anything other than a `Fixed` body with no rekeying.
+	/// The same goes for plaintext commitments, as only V5 headers can record that one is present.
+	fn check_body_params(&self) -> Result<()> {
+		if self.version.has_body_params()
+			|| (self.framing == Framing::Fixed
+				&& self.block_len.is_none()
+				&& self.rekey_interval.is_none()
+				&& self.plaintext_commitment.is_none())
+		{
+			Ok(())
+		} else {
+			Err(Error::UnsupportedBodyParams)
+		}
+	}
+
 	/// This function should be used for generating AAD before encryption
 	///
 	/// Use the return value from `FileHeader::deserialize()` for decryption
 	#[must_use]
 	pub fn generate_aad(&self) -> Vec<u8> {
 		match self.version {
-			FileHeaderVersion::V1 => [
+			FileHeaderVersion::V1
+			| FileHeaderVersion::V2
+			| FileHeaderVersion::V3
+			| FileHeaderVersion::V4
+			| FileHeaderVersion::V5 => [
 				MAGIC_BYTES.as_ref(),
 				&self.version.to_bytes(),
 				&self.algorithm.to_bytes(),
 				&self.nonce,
-				&vec![0u8; 25 - self.nonce.len()],
+				&self.nonce_padding(),
+				&self.key_commitment_bytes(),
+				&self.body_params_bytes(),
 			]
 			.into_iter()
 			.flatten()
@@ -199,21 +652,38 @@ impl FileHeader {
 	///
 	/// This will include keyslots, metadata and preview media (if provided)
 	///
-	/// An error will be returned if there are no keyslots/more than two keyslots attached.
+	/// V4 and V5 headers also include a checksum of everything up to the end of the keyslots.
+	///
+	/// An error will be returned if there are no keyslots/more keyslots attached than the header's version can hold, or if the body parameters can't be stored in the header's version.
 	pub fn to_bytes(&self) -> Result<Vec<u8>> {
 		match self.version {
-			FileHeaderVersion::V1 => {
-				if self.keyslots.len() > 2 {
+			FileHeaderVersion::V1
+			| FileHeaderVersion::V2
+			| FileHeaderVersion::V3
+			| FileHeaderVersion::V4
+			| FileHeaderVersion::V5 => {
+				if self.keyslots.len() > self.version.max_keyslots() {
 					return Err(Error::TooManyKeyslots);
 				} else if self.keyslots.is_empty() {
 					return Err(Error::NoKeyslots);
 				}
 
-				let mut keyslots: Vec<Vec<u8>> =
-					self.keyslots.iter().map(Keyslot::to_bytes).collect();
-
-				if keyslots.len() == 1 {
-					keyslots.push(vec![0u8; KEYSLOT_SIZE]);
+				self.check_body_params()?;
+
+				let mut keyslots: Vec<u8> =
+					self.keyslots.iter().flat_map(Keyslot::to_bytes).collect();
+
+				match self.version {
+					FileHeaderVersion::V1 | FileHeaderVersion::V2 => {
+						keyslots.resize(KEYSLOT_SIZE * 2, EMPTY_KEYSLOT_MARKER);
+					}
+					FileHeaderVersion::V3 | FileHeaderVersion::V4 | FileHeaderVersion::V5 => {
+						keyslots.insert(
+							0,
+							u8::try_from(self.keyslots.len())
+								.map_err(|_| Error::TooManyKeyslots)?,
+						)
+					}
 				}
 
 				let metadata = self
@@ -226,22 +696,37 @@ impl FileHeader {
 					.as_ref()
 					.map_or(Vec::new(), PreviewMedia::to_bytes);
 
-				let header = [
+				let plaintext_commitment = self
+					.plaintext_commitment
+					.as_ref()
+					.map_or(Vec::new(), PlaintextCommitment::to_bytes);
+
+				let mut header: Vec<u8> = [
 					MAGIC_BYTES.as_ref(),
 					&self.version.to_bytes(),
 					&self.algorithm.to_bytes(),
 					&self.nonce,
-					&vec![0u8; 25 - self.nonce.len()],
-					&keyslots[0],
-					&keyslots[1],
-					&metadata,
-					&preview_media,
+					&self.nonce_padding(),
+					&self.key_commitment_bytes(),
+					&self.body_params_bytes(),
+					&keyslots,
 				]
 				.into_iter()
 				.flatten()
 				.copied()
 				.collect();
 
+				if self.version.checksum_len() != 0 {
+					let checksum = header_checksum(&header);
+					header.extend_from_slice(&checksum);
+				}
+
+				header.extend(
+					[metadata, preview_media, plaintext_commitment]
+						.into_iter()
+						.flatten(),
+				);
+
 				Ok(header)
 			}
 		}
@@ -268,6 +753,12 @@ impl FileHeader {
 		reader.read_exact(&mut version).await?;
 		let version = FileHeaderVersion::from_bytes(version)?;
 
+		// V4 and V5 headers are checked for corruption before anything else is parsed
+		if version.checksum_len() != 0 {
+			reader.rewind().await?;
+			Self::verify_checksum(reader, version).await?;
+		}
+
 		// Rewind so we can get the AAD
 		reader.rewind().await?;
 
@@ -282,7 +773,11 @@ impl FileHeader {
 
 		// read the header
 		let header = match version {
-			FileHeaderVersion::V1 => {
+			FileHeaderVersion::V1
+			| FileHeaderVersion::V2
+			| FileHeaderVersion::V3
+			| FileHeaderVersion::V4
+			| FileHeaderVersion::V5 => {
 				let mut algorithm = [0u8; 2];
 				reader.read_exact(&mut algorithm).await?;
 				let algorithm = Algorithm::from_bytes(algorithm)?;
@@ -291,29 +786,81 @@ impl FileHeader {
 				reader.read_exact(&mut nonce).await?;
 				let nonce = Nonce::try_from(nonce)?;
 
-				// read and discard the padding
-				reader.read_exact(&mut vec![0u8; 25 - nonce.len()]).await?;
-
-				let mut keyslot_bytes = vec![0u8; KEYSLOT_SIZE * 2]; // length of 2x keyslots
+				// the padding is discarded
+				let mut padding = vec![0u8; 25 - nonce.len()];
+				reader.read_exact(&mut padding).await?;
+
+				let key_commitment = match version {
+					FileHeaderVersion::V1 => None,
+					FileHeaderVersion::V2
+					| FileHeaderVersion::V3
+					| FileHeaderVersion::V4
+					| FileHeaderVersion::V5 => {
+						let mut commitment = [0u8; KEY_LEN];
+						reader.read_exact(&mut commitment).await?;
+						Some(commitment).filter(|c| c != &[0u8; KEY_LEN])
+					}
+				};
+
+				// bodies described by older headers are always fixed-size blocks, without rekeying
+				let (framing, block_len, rekey_interval, flags) = if version.has_body_params() {
+					let mut params = [0u8; BODY_PARAMS_LEN];
+					reader.read_exact(&mut params).await?;
+
+					// unknown flags could describe items that we'd otherwise misread
+					if params[6] & !FLAG_PLAINTEXT_COMMITMENT != 0 {
+						return Err(Error::Serialization);
+					}
+
+					(
+						Framing::from_byte(params[0])?,
+						block_len_from_bits(params[1])?,
+						NonZeroU32::new(u32::from_le_bytes(to_array(&params[2..6])?)),
+						params[6],
+					)
+				} else {
+					(Framing::Fixed, None, None, 0)
+				};
+
+				// V1 and V2 headers always have two keyslots, while newer headers store how many there are
+				let keyslot_count = match version {
+					FileHeaderVersion::V1 | FileHeaderVersion::V2 => 2,
+					FileHeaderVersion::V3 | FileHeaderVersion::V4 | FileHeaderVersion::V5 => {
+						let mut keyslot_count = [0u8; 1];
+						reader.read_exact(&mut keyslot_count).await?;
+
+						match usize::from(keyslot_count[0]) {
+							count if count > MAX_KEYSLOTS => return Err(Error::TooManyKeyslots),
+							count => count,
+						}
+					}
+				};
+
+				let mut keyslot_bytes = vec![0u8; KEYSLOT_SIZE * keyslot_count];
 				let mut keyslots: Vec<Keyslot> = Vec::new();
 
 				reader.read_exact(&mut keyslot_bytes).await?;
-				let mut keyslot_reader = Cursor::new(keyslot_bytes);
 
-				for _ in 0..2 {
-					Keyslot::from_reader(&mut keyslot_reader)
-						.map(|k| keyslots.push(k))
-						.ok();
+				// the checksum has already been verified, so it's skipped over
+				let mut checksum = vec![0u8; version.checksum_len()];
+				reader.read_exact(&mut checksum).await?;
+
+				// this is where the optional header items start
+				let keyslots_end =
+					(Self::keyslots_offset(version) + keyslot_bytes.len() + checksum.len()) as u64;
+
+				// a version byte of `0x00` marks an empty keyslot (this is what `to_bytes()` pads with)
+				for keyslot in keyslot_bytes
+					.chunks_exact(KEYSLOT_SIZE)
+					.filter(|k| k[0] != EMPTY_KEYSLOT_MARKER)
+				{
+					keyslots.push(Keyslot::from_reader(&mut Cursor::new(keyslot))?);
 				}
 
 				let metadata = if let Ok(metadata) = Metadata::from_reader(reader).await {
 					Ok::<Option<Metadata>, Error>(Some(metadata))
 				} else {
-					reader
-						.seek(SeekFrom::Start(
-							Self::size(version) as u64 + (KEYSLOT_SIZE * 2) as u64,
-						))
-						.await?;
+					reader.seek(SeekFrom::Start(keyslots_end)).await?;
 					Ok(None)
 				}?;
 
@@ -321,32 +868,71 @@ impl FileHeader {
 					if let Ok(preview_media) = PreviewMedia::from_reader(reader).await {
 						Ok::<Option<PreviewMedia>, Error>(Some(preview_media))
 					} else {
-						let seek_len = metadata.as_ref().map_or_else(
-							|| Self::size(version) as u64 + (KEYSLOT_SIZE * 2) as u64,
-							|metadata| {
-								Self::size(version) as u64
-									+ (KEYSLOT_SIZE * 2) as u64 + metadata.size() as u64
-							},
-						);
+						let seek_len =
+							keyslots_end + metadata.as_ref().map_or(0, Metadata::size) as u64;
 
 						reader.seek(SeekFrom::Start(seek_len)).await?;
 
 						Ok(None)
 					}?;
 
+				// this is only read if the header says that it's there
+				let plaintext_commitment = if flags & FLAG_PLAINTEXT_COMMITMENT == 0 {
+					None
+				} else {
+					Some(PlaintextCommitment::from_reader(reader).await?)
+				};
+
 				Self {
 					version,
 					algorithm,
 					nonce,
+					framing,
+					rekey_interval,
+					block_len,
+					key_commitment,
 					keyslots,
 					metadata,
 					preview_media,
+					plaintext_commitment,
 				}
 			}
 		};
 
+		header.validate()?;
+
 		Ok((header, aad))
 	}
+
+	/// This checks the checksum of a V4 or V5 header, which covers everything from the magic bytes to the end of the keyslots.
+	///
+	/// The reader needs to be at the start of the header.
+	async fn verify_checksum<R>(reader: &mut R, version: FileHeaderVersion) -> Result<()>
+	where
+		R: AsyncReadExt + Unpin + Send,
+	{
+		let keyslots_offset = Self::keyslots_offset(version);
+		let mut bytes = vec![0u8; keyslots_offset];
+		reader.read_exact(&mut bytes).await?;
+
+		// the number of keyslots is stored right before them
+		let keyslot_count = usize::from(bytes[keyslots_offset - 1]);
+		if keyslot_count > MAX_KEYSLOTS {
+			return Err(Error::HeaderCorrupt);
+		}
+
+		bytes.resize(keyslots_offset + KEYSLOT_SIZE * keyslot_count, 0);
+		reader.read_exact(&mut bytes[keyslots_offset..]).await?;
+
+		let mut checksum = [0u8; HEADER_CHECKSUM_LEN];
+		reader.read_exact(&mut checksum).await?;
+
+		if checksum == header_checksum(&bytes) {
+			Ok(())
+		} else {
+			Err(Error::HeaderCorrupt)
+		}
+	}
 }
 
 #[cfg(test)]
@@ -354,8 +940,14 @@ mod tests {
 	use std::io::Cursor;
 
 	use crate::{
-		keys::hashing::{HashingAlgorithm, Params},
-		primitives::{types::Salt, LATEST_FILE_HEADER, LATEST_KEYSLOT, LATEST_PREVIEW_MEDIA},
+		crypto::stream::{StreamDecryption, StreamEncryption},
+		keys::{
+			hashing::{HashingAlgorithm, Params},
+			source::KeyfileSource,
+		},
+		primitives::{
+			types::Salt, BLOCK_LEN, LATEST_FILE_HEADER, LATEST_KEYSLOT, LATEST_PREVIEW_MEDIA,
+		},
 	};
 
 	use super::*;
@@ -394,23 +986,26 @@ mod tests {
 
 		FileHeader::from_reader(&mut writer).await.unwrap();
 
-		assert!(writer.position() == 260);
+		assert!(writer.position() == 188 + HEADER_CHECKSUM_LEN as u64);
 	}
 
 	#[tokio::test]
-	async fn serialize_and_deserialize_header_with_preview_media() {
+	async fn deserialize_v1_header() {
 		let mk = Key::generate();
+		let hashed_pw = Key::generate(); // not hashed, but that'd be expensive
+		let hashing_algorithm = HashingAlgorithm::Argon2id(Params::Hardened);
+
 		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
 
-		let mut header = FileHeader::new(
-			LATEST_FILE_HEADER,
+		let header = FileHeader::new(
+			FileHeaderVersion::V1,
 			ALGORITHM,
 			vec![Keyslot::new(
 				LATEST_KEYSLOT,
 				ALGORITHM,
-				HASHING_ALGORITHM,
+				hashing_algorithm,
 				Salt::generate(),
-				Key::generate(),
+				hashed_pw.clone(),
 				mk.clone(),
 			)
 			.await
@@ -418,39 +1013,30 @@ mod tests {
 		)
 		.unwrap();
 
-		header
-			.add_preview_media(LATEST_PREVIEW_MEDIA, ALGORITHM, mk, &PVM_BYTES)
-			.await
-			.unwrap();
-
 		header.write(&mut writer).await.unwrap();
 
 		writer.rewind().await.unwrap();
 
-		let (header, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+		// older headers must keep working now that they're no longer the latest
+		let (header, aad) = FileHeader::from_reader(&mut writer).await.unwrap();
 
-		assert!(header.preview_media.is_some());
-		assert!(header.metadata.is_none());
-		assert!(header.keyslots.len() == 1);
+		assert!(matches!(header.version, FileHeaderVersion::V1));
+		assert_eq!(header.generate_aad(), aad);
+		assert!(header.key_commitment.is_none());
+		// the hashing parameters are stored per-keyslot, so they're still known
+		assert!(header.keyslots[0].hashing_algorithm == hashing_algorithm);
+		assert_eq!(
+			header
+				.decrypt_master_key_from_prehashed(vec![hashed_pw])
+				.await
+				.unwrap()
+				.expose(),
+			mk.expose()
+		);
 	}
 
-	#[cfg(feature = "serde")]
 	#[tokio::test]
-	async fn serialize_and_deserialize_header_with_metadata() {
-		use crate::primitives::LATEST_METADATA;
-
-		#[derive(serde::Serialize)]
-		struct Metadata {
-			pub name: String,
-			pub favorite: bool,
-		}
-
-		let mk = Key::generate();
-		let md = Metadata {
-			name: "file.txt".to_string(),
-			favorite: true,
-		};
-
+	async fn serialize_and_deserialize_header_with_framing() {
 		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
 
 		let mut header = FileHeader::new(
@@ -462,70 +1048,931 @@ mod tests {
 				HASHING_ALGORITHM,
 				Salt::generate(),
 				Key::generate(),
-				mk.clone(),
+				Key::generate(),
 			)
 			.await
 			.unwrap()],
 		)
 		.unwrap();
 
-		header
-			.add_metadata(LATEST_METADATA, ALGORITHM, mk, &md)
-			.await
-			.unwrap();
+		assert!(header.framing == Framing::Fixed);
+		header.framing = Framing::LengthPrefixed;
+		header.block_len = Some(BLOCK_LEN * 2);
 
 		header.write(&mut writer).await.unwrap();
 
 		writer.rewind().await.unwrap();
 
-		let (header, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+		let (header, aad) = FileHeader::from_reader(&mut writer).await.unwrap();
 
-		assert!(header.metadata.is_some());
-		assert!(header.preview_media.is_none());
-		assert!(header.keyslots.len() == 1);
+		assert!(header.framing == Framing::LengthPrefixed);
+		assert_eq!(header.block_len, Some(BLOCK_LEN * 2));
+		assert_eq!(header.generate_aad(), aad);
 	}
 
 	#[tokio::test]
-	async fn serialize_and_deserialize_header_with_two_keyslots() {
+	async fn serialize_and_deserialize_header_with_rekey_interval() {
 		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
-		let mk = Key::generate();
 
-		let header = FileHeader::new(
+		let mut header = FileHeader::new(
 			LATEST_FILE_HEADER,
 			ALGORITHM,
-			vec![
-				Keyslot::new(
-					LATEST_KEYSLOT,
-					ALGORITHM,
-					HASHING_ALGORITHM,
-					Salt::generate(),
-					Key::generate(),
-					mk.clone(),
-				)
-				.await
-				.unwrap(),
-				Keyslot::new(
-					LATEST_KEYSLOT,
-					ALGORITHM,
-					HASHING_ALGORITHM,
-					Salt::generate(),
-					Key::generate(),
-					mk,
-				)
-				.await
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		assert!(header.rekey_interval.is_none());
+		header.rekey_interval = NonZeroU32::new(1024);
+
+		header.write(&mut writer).await.unwrap();
+
+		writer.rewind().await.unwrap();
+
+		let (header, aad) = FileHeader::from_reader(&mut writer).await.unwrap();
+
+		assert_eq!(header.rekey_interval, NonZeroU32::new(1024));
+		assert_eq!(header.generate_aad(), aad);
+	}
+
+	#[tokio::test]
+	async fn serialize_older_header_with_body_params() {
+		let mut header = FileHeader::new(
+			FileHeaderVersion::V4,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		// the padding is all zeroes, as older builds would ignore anything that's stored there
+		let bytes = header.to_bytes().unwrap();
+		let padding_start = MAGIC_BYTES.len() + 4 + ALGORITHM.nonce_len();
+		assert!(bytes[padding_start..36].iter().all(|b| *b == 0));
+
+		header.framing = Framing::LengthPrefixed;
+		assert!(matches!(
+			header.to_bytes(),
+			Err(Error::UnsupportedBodyParams)
+		));
+
+		header.framing = Framing::Fixed;
+		header.rekey_interval = NonZeroU32::new(1024);
+		assert!(matches!(
+			header.to_bytes(),
+			Err(Error::UnsupportedBodyParams)
+		));
+	}
+
+	#[tokio::test]
+	async fn serialize_and_deserialize_header_with_empty_keyslot() {
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let header = FileHeader::new(
+			FileHeaderVersion::V2,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		// the second keyslot should be entirely zeroed padding
+		let start = FileHeader::size(FileHeaderVersion::V2) + KEYSLOT_SIZE;
+		assert!(writer.get_ref()[start..start + KEYSLOT_SIZE]
+			.iter()
+			.all(|b| *b == 0));
+
+		writer.rewind().await.unwrap();
+
+		let (header, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+
+		assert_eq!(header.keyslots.len(), 1);
+	}
+
+	#[tokio::test]
+	#[should_panic(expected = "Serialization")]
+	async fn deserialize_header_with_corrupt_keyslot() {
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let header = FileHeader::new(
+			FileHeaderVersion::V2,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		// this is neither a valid keyslot, nor an empty one
+		let start = FileHeader::size(FileHeaderVersion::V2) + KEYSLOT_SIZE;
+		writer.get_mut()[start] = 0xFF;
+
+		writer.rewind().await.unwrap();
+
+		FileHeader::from_reader(&mut writer).await.unwrap();
+	}
+
+	#[tokio::test]
+	async fn deserialize_header_with_newer_version() {
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		// the version number is the byte after the magic bytes and the version marker
+		writer.get_mut()[MAGIC_BYTES.len() + 1] = 0x09;
+		writer.rewind().await.unwrap();
+
+		let result = FileHeader::from_reader(&mut writer).await;
+		assert!(matches!(
+			result,
+			Err(Error::UnsupportedHeaderVersion {
+				found: 0x09,
+				max_supported: 5
+			})
+		));
+
+		// a newer keyslot is also reported, rather than being treated as corrupt
+		// (a newer build would have written a matching checksum)
+		let keyslots_end = FileHeader::keyslots_offset(LATEST_FILE_HEADER) + KEYSLOT_SIZE;
+		let bytes = writer.get_mut();
+		bytes[MAGIC_BYTES.len() + 1] = LATEST_FILE_HEADER.number();
+		bytes[FileHeader::keyslots_offset(LATEST_FILE_HEADER) + 1] = 0x07;
+		let checksum = header_checksum(&bytes[..keyslots_end]);
+		bytes[keyslots_end..keyslots_end + HEADER_CHECKSUM_LEN].copy_from_slice(&checksum);
+		writer.rewind().await.unwrap();
+
+		let result = FileHeader::from_reader(&mut writer).await;
+		assert!(matches!(
+			result,
+			Err(Error::UnsupportedKeyslotVersion {
+				found: 0x07,
+				max_supported: 2
+			})
+		));
+	}
+
+	#[tokio::test]
+	async fn deserialize_header_with_flipped_bits() {
+		let mk = Key::generate();
+
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				mk.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.add_key_commitment(&mk).unwrap();
+		let bytes = header.to_bytes().unwrap();
+
+		let nonce_start = MAGIC_BYTES.len() + 4;
+		let keyslots_offset = FileHeader::keyslots_offset(LATEST_FILE_HEADER);
+
+		// the algorithm, nonce, nonce padding, key commitment, keyslot count, keyslot and the checksum itself
+		for offset in [
+			MAGIC_BYTES.len() + 2,
+			nonce_start,
+			nonce_start + ALGORITHM.nonce_len(),
+			FileHeader::size(LATEST_FILE_HEADER) - 1,
+			keyslots_offset - 1,
+			keyslots_offset + KEYSLOT_SIZE / 2,
+			keyslots_offset + KEYSLOT_SIZE,
+		] {
+			let mut corrupted = bytes.clone();
+			corrupted[offset] ^= 0x01;
+
+			let result = FileHeader::from_reader(&mut Cursor::new(corrupted)).await;
+			assert!(
+				matches!(result, Err(Error::HeaderCorrupt)),
+				"flipped bit at {offset} wasn't detected"
+			);
+		}
+
+		// the untouched header still parses
+		assert!(FileHeader::from_reader(&mut Cursor::new(bytes))
+			.await
+			.is_ok());
+	}
+
+	#[tokio::test]
+	async fn deserialize_header_with_inconsistent_algorithm() {
+		let keyslot = |algorithm| {
+			Keyslot::new(
+				LATEST_KEYSLOT,
+				algorithm,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(), // not hashed, but that'd be expensive
+				Key::generate(),
+			)
+		};
+
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![keyslot(ALGORITHM).await.unwrap()],
+		)
+		.unwrap();
+
+		let (deserialized, _) =
+			FileHeader::from_reader(&mut Cursor::new(header.to_bytes().unwrap()))
+				.await
+				.unwrap();
+		assert!(deserialized.validate().is_ok());
+
+		// this mirrors a crafted file, as headers can't be created like this
+		let mismatched = keyslot(Algorithm::Aes256Gcm).await.unwrap();
+		assert!(matches!(
+			FileHeader::new(LATEST_FILE_HEADER, ALGORITHM, vec![mismatched.clone()]),
+			Err(Error::InconsistentHeader)
+		));
+
+		header.keyslots.push(mismatched);
+		assert!(matches!(
+			FileHeader::from_reader(&mut Cursor::new(header.to_bytes().unwrap())).await,
+			Err(Error::InconsistentHeader)
+		));
+	}
+
+	#[tokio::test]
+	async fn serialize_and_deserialize_header_with_preview_media() {
+		let mk = Key::generate();
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				mk.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header
+			.add_preview_media(LATEST_PREVIEW_MEDIA, ALGORITHM, mk, &PVM_BYTES)
+			.await
+			.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		writer.rewind().await.unwrap();
+
+		let (header, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+
+		assert!(header.preview_media.is_some());
+		assert!(header.metadata.is_none());
+		assert!(header.keyslots.len() == 1);
+	}
+
+	#[cfg(feature = "serde")]
+	#[tokio::test]
+	async fn serialize_and_deserialize_header_with_metadata() {
+		use crate::primitives::LATEST_METADATA;
+
+		#[derive(serde::Serialize)]
+		struct Metadata {
+			pub name: String,
+			pub favorite: bool,
+		}
+
+		let mk = Key::generate();
+		let md = Metadata {
+			name: "file.txt".to_string(),
+			favorite: true,
+		};
+
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				mk.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header
+			.add_metadata(LATEST_METADATA, ALGORITHM, mk, &md)
+			.await
+			.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		writer.rewind().await.unwrap();
+
+		let (header, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+
+		assert!(header.metadata.is_some());
+		assert!(header.preview_media.is_none());
+		assert!(header.keyslots.len() == 1);
+	}
+
+	#[tokio::test]
+	async fn serialize_and_deserialize_header_with_two_keyslots() {
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+		let mk = Key::generate();
+
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![
+				Keyslot::new(
+					LATEST_KEYSLOT,
+					ALGORITHM,
+					HASHING_ALGORITHM,
+					Salt::generate(),
+					Key::generate(),
+					mk.clone(),
+				)
+				.await
+				.unwrap(),
+				Keyslot::new(
+					LATEST_KEYSLOT,
+					ALGORITHM,
+					HASHING_ALGORITHM,
+					Salt::generate(),
+					Key::generate(),
+					mk,
+				)
+				.await
+				.unwrap(),
+			],
+		)
+		.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		writer.rewind().await.unwrap();
+
+		let (header, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+		assert!(header.keyslots.len() == 2);
+		assert!(header.metadata.is_none());
+		assert!(header.preview_media.is_none());
+	}
+
+	#[tokio::test]
+	async fn serialize_and_deserialize_header_with_aes_256_gcm() {
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			Algorithm::Aes256Gcm,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				Algorithm::Aes256Gcm,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		writer.rewind().await.unwrap();
+
+		// the shorter nonce is padded out, so the rest of the header is at the same offsets
+		let (deserialized, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+		assert!(deserialized.algorithm == Algorithm::Aes256Gcm);
+		assert!(deserialized.nonce.len() == Algorithm::Aes256Gcm.nonce_len());
+		assert!(deserialized.nonce == header.nonce);
+		assert!(deserialized.keyslots.len() == 1);
+		assert!(writer.position() == 188 + HEADER_CHECKSUM_LEN as u64);
+	}
+
+	#[tokio::test]
+	async fn serialize_and_deserialize_header_with_aes_128_gcm() {
+		let mk = Key::generate();
+		let hashed_key = Key::generate(); // not hashed, but that'd be expensive
+
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			Algorithm::Aes128Gcm,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				Algorithm::Aes128Gcm,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				hashed_key.clone(),
+				mk.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		let (deserialized, _) =
+			FileHeader::from_reader(&mut Cursor::new(header.to_bytes().unwrap()))
+				.await
+				.unwrap();
+		assert!(deserialized.algorithm == Algorithm::Aes128Gcm);
+		assert!(deserialized.nonce == header.nonce);
+		assert_eq!(
+			deserialized
+				.decrypt_master_key_from_prehashed(vec![hashed_key])
+				.await
+				.unwrap()
+				.expose(),
+			mk.expose()
+		);
+	}
+
+	#[tokio::test]
+	async fn deserialize_header_with_bad_magic_bytes() {
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		writer.get_mut()[0] ^= 0xFF;
+		writer.rewind().await.unwrap();
+
+		assert!(matches!(
+			FileHeader::from_reader(&mut writer).await,
+			Err(Error::Serialization)
+		));
+	}
+
+	#[tokio::test]
+	async fn decrypt_header_with_keyslots_from_different_kdfs() {
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+		let mk = Key::generate();
+		let content_salt = Salt::generate();
+
+		let argon2id = HashingAlgorithm::Argon2id(Params::Standard);
+		let balloon = HashingAlgorithm::BalloonBlake3(Params::Standard);
+
+		let argon2id_password = Protected::new(b"argon2id password".to_vec());
+		let balloon_password = Protected::new(b"balloon password".to_vec());
+
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![
+				Keyslot::new(
+					LATEST_KEYSLOT,
+					ALGORITHM,
+					argon2id,
+					content_salt,
+					argon2id
+						.hash(argon2id_password.clone(), content_salt, None)
+						.unwrap(),
+					mk.clone(),
+				)
+				.await
+				.unwrap(),
+				Keyslot::new(
+					LATEST_KEYSLOT,
+					ALGORITHM,
+					balloon,
+					content_salt,
+					balloon
+						.hash(balloon_password.clone(), content_salt, None)
+						.unwrap(),
+					mk.clone(),
+				)
+				.await
+				.unwrap(),
+			],
+		)
+		.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		writer.rewind().await.unwrap();
+
+		let (header, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+
+		// the KDF is read back from each keyslot, so both passwords should unlock the same master key
+		assert!(header.keyslots[0].hashing_algorithm == argon2id);
+		assert!(header.keyslots[1].hashing_algorithm == balloon);
+
+		assert_eq!(
+			header
+				.decrypt_master_key(argon2id_password)
+				.await
+				.unwrap()
+				.expose(),
+			mk.expose()
+		);
+		assert_eq!(
+			header
+				.decrypt_master_key(balloon_password)
+				.await
+				.unwrap()
+				.expose(),
+			mk.expose()
+		);
+	}
+
+	#[tokio::test]
+	async fn decrypt_header_with_wrong_keys() {
+		let mk = Key::generate();
+		let hashed_keys = [Key::generate(), Key::generate(), Key::generate()];
+
+		let mut keyslots = Vec::new();
+		for hashed_key in &hashed_keys {
+			keyslots.push(
+				Keyslot::new(
+					LATEST_KEYSLOT,
+					ALGORITHM,
+					HASHING_ALGORITHM,
+					Salt::generate(),
+					hashed_key.clone(),
+					mk.clone(),
+				)
+				.await
+				.unwrap(),
+			);
+		}
+
+		let header = FileHeader::new(LATEST_FILE_HEADER, ALGORITHM, keyslots).unwrap();
+
+		// the error is the same no matter how many keyslots were tried
+		for wrong_keys in [
+			vec![Key::generate()],
+			vec![Key::generate(), Key::generate()],
+		] {
+			assert!(matches!(
+				header.decrypt_master_key_from_prehashed(wrong_keys).await,
+				Err(Error::IncorrectPassword)
+			));
+		}
+		assert!(matches!(
+			header
+				.decrypt_master_key(Protected::new(b"wrong password".to_vec()))
+				.await,
+			Err(Error::IncorrectPassword)
+		));
+
+		// any keyslot can still unlock the file, including when it's tried alongside wrong keys
+		for hashed_key in hashed_keys {
+			assert_eq!(
+				header
+					.decrypt_master_key_from_prehashed(vec![Key::generate(), hashed_key])
+					.await
+					.unwrap()
+					.expose(),
+				mk.expose()
+			);
+		}
+	}
+
+	#[tokio::test]
+	async fn decrypt_header_with_password_only_tries_password_keyslots() {
+		let mk = Key::generate();
+		let password = Protected::new(b"password".to_vec());
+		let content_salt = Salt::generate();
+		let hashed_password = HASHING_ALGORITHM
+			.hash(password.clone(), content_salt, None)
+			.unwrap();
+
+		let keyfile_keyslot = Keyslot::from_source(
+			LATEST_KEYSLOT,
+			ALGORITHM,
+			HASHING_ALGORITHM,
+			&KeyfileSource::from_bytes(b"keyfile"),
+			mk.clone(),
+		)
+		.await
+		.unwrap();
+
+		// a header with only a keyfile keyslot has nothing for a password to unlock
+		let header =
+			FileHeader::new(LATEST_FILE_HEADER, ALGORITHM, vec![keyfile_keyslot.clone()]).unwrap();
+		assert!(matches!(
+			header.decrypt_master_key(password.clone()).await,
+			Err(Error::NoKeyslots)
+		));
+
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![
+				keyfile_keyslot,
+				Keyslot::new(
+					LATEST_KEYSLOT,
+					ALGORITHM,
+					HASHING_ALGORITHM,
+					content_salt,
+					hashed_password,
+					mk.clone(),
+				)
+				.await
+				.unwrap(),
+			],
+		)
+		.unwrap();
+
+		assert_eq!(
+			header
+				.decrypt_master_key(password.clone())
+				.await
+				.unwrap()
+				.expose(),
+			mk.expose()
+		);
+		assert_eq!(header.find_key_index(password).await.unwrap(), 1);
+	}
+
+	#[tokio::test]
+	async fn decrypt_header_with_key_commitment() {
+		let mk = Key::generate();
+		let hashed_pw = Key::generate(); // not hashed, but that'd be expensive
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				hashed_pw.clone(),
+				mk.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.add_key_commitment(&mk).unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		writer.rewind().await.unwrap();
+
+		let (header, aad) = FileHeader::from_reader(&mut writer).await.unwrap();
+
+		assert!(header.key_commitment.is_some());
+		assert_eq!(header.generate_aad(), aad);
+		assert_eq!(
+			header
+				.decrypt_master_key_from_prehashed(vec![hashed_pw])
+				.await
+				.unwrap()
+				.expose(),
+			mk.expose()
+		);
+	}
+
+	#[tokio::test]
+	#[should_panic(expected = "KeyCommitmentFailed")]
+	async fn decrypt_header_with_tampered_key_commitment() {
+		let mk = Key::generate();
+		let hashed_pw = Key::generate(); // not hashed, but that'd be expensive
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		// V4 headers would catch this with their checksum, so this checks the key commitment on its own
+		let mut header = FileHeader::new(
+			FileHeaderVersion::V3,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				hashed_pw.clone(),
+				mk.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.add_key_commitment(&mk).unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		// the commitment is the last part of the AAD
+		writer.get_mut()[FileHeader::size(FileHeaderVersion::V3) - 1] ^= 0xFF;
+
+		writer.rewind().await.unwrap();
+
+		let (header, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+
+		header
+			.decrypt_master_key_from_prehashed(vec![hashed_pw])
+			.await
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn decrypt_header_skips_keyslots_that_fail_key_commitment() {
+		let mk = Key::generate();
+		let hashed_pw = Key::generate(); // not hashed, but that'd be expensive
+
+		// the first keyslot unwraps with the same key, but to a master key that doesn't match the commitment
+		let mut keyslots = Vec::new();
+		for master_key in [Key::generate(), mk.clone()] {
+			keyslots.push(
+				Keyslot::new(
+					LATEST_KEYSLOT,
+					ALGORITHM,
+					HASHING_ALGORITHM,
+					Salt::generate(),
+					hashed_pw.clone(),
+					master_key,
+				)
+				.await
 				.unwrap(),
-			],
-		)
-		.unwrap();
+			);
+		}
+
+		let mut header = FileHeader::new(LATEST_FILE_HEADER, ALGORITHM, keyslots).unwrap();
+		header.add_key_commitment(&mk).unwrap();
+
+		assert_eq!(
+			header
+				.decrypt_master_key_from_prehashed(vec![hashed_pw.clone()])
+				.await
+				.unwrap()
+				.expose(),
+			mk.expose()
+		);
+
+		// if only the bad keyslot is left, it's reported as tampering rather than a wrong password
+		header.remove_keyslot(1).unwrap();
+		assert!(matches!(
+			header
+				.decrypt_master_key_from_prehashed(vec![hashed_pw])
+				.await,
+			Err(Error::KeyCommitmentFailed)
+		));
+	}
+
+	#[tokio::test]
+	async fn remove_keyslot_from_header() {
+		let mk = Key::generate();
+		let content_salt = Salt::generate();
+		let removed_key = Key::generate(); // not hashed, but that'd be expensive
+		let kept_key = Key::generate();
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let mut keyslots = Vec::new();
+		for hashed_key in [removed_key.clone(), kept_key.clone()] {
+			keyslots.push(
+				Keyslot::new(
+					LATEST_KEYSLOT,
+					ALGORITHM,
+					HASHING_ALGORITHM,
+					content_salt,
+					hashed_key,
+					mk.clone(),
+				)
+				.await
+				.unwrap(),
+			);
+		}
+
+		let mut header = FileHeader::new(FileHeaderVersion::V2, ALGORITHM, keyslots).unwrap();
+		let aad = header.generate_aad();
+
+		header.remove_keyslot(0).unwrap();
+
+		// the body doesn't need re-encrypting, as the AAD doesn't cover the keyslots
+		assert_eq!(header.generate_aad(), aad);
 
 		header.write(&mut writer).await.unwrap();
 
+		let start = FileHeader::size(FileHeaderVersion::V2) + KEYSLOT_SIZE;
+		assert!(writer.get_ref()[start..start + KEYSLOT_SIZE]
+			.iter()
+			.all(|b| *b == 0));
+
 		writer.rewind().await.unwrap();
 
 		let (header, _) = FileHeader::from_reader(&mut writer).await.unwrap();
-		assert!(header.keyslots.len() == 2);
-		assert!(header.metadata.is_none());
-		assert!(header.preview_media.is_none());
+
+		assert_eq!(header.keyslots.len(), 1);
+		assert!(header
+			.decrypt_master_key_from_prehashed(vec![removed_key])
+			.await
+			.is_err());
+		assert_eq!(
+			header
+				.decrypt_master_key_from_prehashed(vec![kept_key])
+				.await
+				.unwrap()
+				.expose(),
+			mk.expose()
+		);
+	}
+
+	#[tokio::test]
+	#[should_panic(expected = "LastKeyslot")]
+	async fn remove_last_keyslot_from_header() {
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.remove_keyslot(0).unwrap();
 	}
 
 	#[tokio::test]
@@ -534,7 +1981,7 @@ mod tests {
 		let mk = Key::generate();
 
 		FileHeader::new(
-			LATEST_FILE_HEADER,
+			FileHeaderVersion::V2,
 			ALGORITHM,
 			vec![
 				Keyslot::new(
@@ -572,6 +2019,53 @@ mod tests {
 		.unwrap();
 	}
 
+	#[tokio::test]
+	async fn serialize_and_deserialize_header_with_max_keyslots() {
+		let mk = Key::generate();
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let mut keyslots = Vec::new();
+		for _ in 0..=MAX_KEYSLOTS {
+			keyslots.push(
+				Keyslot::new(
+					LATEST_KEYSLOT,
+					ALGORITHM,
+					HASHING_ALGORITHM,
+					Salt::generate(),
+					Key::generate(),
+					mk.clone(),
+				)
+				.await
+				.unwrap(),
+			);
+		}
+
+		// one keyslot too many
+		assert!(matches!(
+			FileHeader::new(FileHeaderVersion::V3, ALGORITHM, keyslots.clone()),
+			Err(Error::TooManyKeyslots)
+		));
+
+		keyslots.pop();
+		let mut header = FileHeader::new(FileHeaderVersion::V3, ALGORITHM, keyslots).unwrap();
+		header.write(&mut writer).await.unwrap();
+
+		// V3 headers only take up as much space as their keyslots need
+		assert_eq!(
+			writer.get_ref().len(),
+			FileHeader::keyslots_offset(FileHeaderVersion::V3) + KEYSLOT_SIZE * MAX_KEYSLOTS
+		);
+
+		writer.rewind().await.unwrap();
+
+		let (deserialized, _) = FileHeader::from_reader(&mut writer).await.unwrap();
+		assert_eq!(deserialized.keyslots.len(), MAX_KEYSLOTS);
+
+		// keyslots can't be pushed past the limit either
+		header.keyslots.push(header.keyslots[0].clone());
+		assert!(matches!(header.to_bytes(), Err(Error::TooManyKeyslots)));
+	}
+
 	#[cfg(feature = "serde")]
 	#[tokio::test]
 	async fn serialize_and_deserialize_header_with_all() {
@@ -639,6 +2133,55 @@ mod tests {
 		assert!(header.keyslots.len() == 2);
 	}
 
+	#[tokio::test]
+	async fn detect_encrypted_file() {
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		writer.rewind().await.unwrap();
+
+		assert!(is_encrypted_bytes(writer.get_ref()));
+		assert!(is_encrypted_file(&mut writer).await.unwrap());
+
+		// the reader should be left where it was, so the header can still be read
+		assert_eq!(writer.position(), 0);
+		FileHeader::from_reader(&mut writer).await.unwrap();
+	}
+
+	#[tokio::test]
+	async fn detect_unencrypted_file() {
+		let mut reader = Cursor::new(vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00]);
+
+		assert!(!is_encrypted_bytes(reader.get_ref()));
+		assert!(!is_encrypted_file(&mut reader).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn detect_encrypted_file_too_short() {
+		let mut reader = Cursor::new(MAGIC_BYTES[..3].to_vec());
+
+		assert!(!is_encrypted_bytes(reader.get_ref()));
+		assert!(!is_encrypted_file(&mut reader).await.unwrap());
+		assert_eq!(reader.position(), 0);
+	}
+
 	#[tokio::test]
 	async fn aad_validity() {
 		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
@@ -666,6 +2209,201 @@ mod tests {
 		let (header, aad) = FileHeader::from_reader(&mut writer).await.unwrap();
 
 		assert_eq!(header.generate_aad(), aad);
-		assert_eq!(&header.to_bytes().unwrap()[..36], aad);
+		assert_eq!(
+			&header.to_bytes().unwrap()[..FileHeader::size(LATEST_FILE_HEADER)],
+			aad
+		);
+	}
+
+	#[tokio::test]
+	async fn decrypt_body_with_tampered_header() {
+		let mk = Key::generate();
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+
+		// V4 headers would catch this with their checksum, so this checks the AAD on its own
+		let header = FileHeader::new(
+			FileHeaderVersion::V3,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(),
+				mk.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header.write(&mut writer).await.unwrap();
+
+		StreamEncryption::new(mk.clone(), header.nonce, header.algorithm)
+			.unwrap()
+			.encrypt_streams(PVM_BYTES.as_slice(), &mut writer, &header.generate_aad())
+			.await
+			.unwrap();
+
+		// the last byte of the AAD still parses (it's part of the key commitment), so only the AAD catches it
+		writer.get_mut()[FileHeader::size(FileHeaderVersion::V3) - 1] ^= 0xFF;
+		writer.rewind().await.unwrap();
+
+		let (header, aad) = FileHeader::from_reader(&mut writer).await.unwrap();
+
+		let result = StreamDecryption::new(mk, header.nonce, header.algorithm)
+			.unwrap()
+			.decrypt_streams(&mut writer, Cursor::new(Vec::new()), &aad)
+			.await;
+
+		assert!(matches!(result, Err(Error::Decrypt)));
+	}
+
+	#[tokio::test]
+	async fn keyslots_meet_kdf_policy() {
+		let mk = Key::generate();
+		let keyslot = |params| {
+			Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HashingAlgorithm::Argon2id(params),
+				Salt::generate(),
+				Key::generate(), // not hashed, but that'd be expensive
+				mk.clone(),
+			)
+		};
+
+		let policy = HashingAlgorithm::Argon2id(Params::Hardened).kdf_cost();
+		assert_eq!(
+			policy,
+			KdfCost {
+				memory_kib: 262_144,
+				iterations: 8,
+				parallelism: 4,
+			}
+		);
+
+		let above = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![keyslot(Params::Paranoid).await.unwrap()],
+		)
+		.unwrap();
+		assert!(above.meets_policy(policy));
+
+		let at = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![keyslot(Params::Hardened).await.unwrap()],
+		)
+		.unwrap();
+		assert!(at.meets_policy(policy));
+
+		// a single under-hardened keyslot is enough for the header to fall short
+		let below = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![
+				keyslot(Params::Paranoid).await.unwrap(),
+				keyslot(Params::Standard).await.unwrap(),
+			],
+		)
+		.unwrap();
+		assert!(!below.meets_policy(policy));
+		assert!(below.meets_policy(HASHING_ALGORITHM.kdf_cost()));
+	}
+
+	#[tokio::test]
+	async fn rehash_keyslot_in_header() {
+		let mk = Key::generate();
+		let password = Protected::new(b"password".to_vec());
+		let content_salt = Salt::generate();
+		let hashed_password = HASHING_ALGORITHM
+			.hash(password.clone(), content_salt, None)
+			.unwrap();
+
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				content_salt,
+				hashed_password,
+				mk.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+		header.add_key_commitment(&mk).unwrap();
+
+		let body = StreamEncryption::encrypt_bytes(
+			mk,
+			header.nonce,
+			ALGORITHM,
+			&PVM_BYTES,
+			&header.generate_aad(),
+		)
+		.await
+		.unwrap();
+
+		let hashing_algorithm = HashingAlgorithm::BalloonBlake3(Params::Standard);
+		header
+			.rehash_keyslot(0, password.clone(), hashing_algorithm)
+			.await
+			.unwrap();
+
+		let mut writer: Cursor<Vec<u8>> = Cursor::new(vec![]);
+		header.write(&mut writer).await.unwrap();
+		writer.rewind().await.unwrap();
+
+		let (header, aad) = FileHeader::from_reader(&mut writer).await.unwrap();
+
+		assert!(header.keyslots[0].hashing_algorithm == hashing_algorithm);
+
+		// the master key didn't change, so the body decrypts as it did before
+		let mk = header.decrypt_master_key(password).await.unwrap();
+		let plaintext = StreamDecryption::decrypt_bytes(mk, header.nonce, ALGORITHM, &body, &aad)
+			.await
+			.unwrap();
+
+		assert_eq!(plaintext.expose(), &PVM_BYTES);
+	}
+
+	#[tokio::test]
+	#[should_panic(expected = "IncorrectPassword")]
+	async fn rehash_keyslot_with_wrong_password() {
+		let password = Protected::new(b"password".to_vec());
+		let content_salt = Salt::generate();
+		let hashed_password = HASHING_ALGORITHM
+			.hash(password, content_salt, None)
+			.unwrap();
+
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				content_salt,
+				hashed_password,
+				Key::generate(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header
+			.rehash_keyslot(
+				0,
+				Protected::new(b"wrong password".to_vec()),
+				HashingAlgorithm::BalloonBlake3(Params::Standard),
+			)
+			.await
+			.unwrap();
 	}
 }
@@ -25,7 +25,10 @@ use std::io::Read;
 
 use crate::{
 	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
-	keys::hashing::HashingAlgorithm,
+	keys::{
+		hashing::{HashingAlgorithm, KdfCost},
+		source::{KeySource, KeySourceKind},
+	},
 	primitives::{
 		types::{EncryptedKey, Key, Nonce, Salt},
 		ENCRYPTED_KEY_LEN, FILE_KEY_CONTEXT, SALT_LEN,
@@ -33,7 +36,7 @@ use crate::{
 	Error, Protected, Result,
 };
 
-/// A keyslot - 96 bytes (as of V1), and contains all the information for future-proofing while keeping the size reasonable
+/// A keyslot - 112 bytes (as of V1), and contains all the information for future-proofing while keeping the size reasonable
 ///
 /// The algorithm (should) be inherited from the parent (the header, in this case), but that's not a guarantee so we include it here too
 #[derive(Clone)]
@@ -45,16 +48,41 @@ pub struct Keyslot {
 	pub content_salt: Salt,
 	pub master_key: EncryptedKey, // this is encrypted so we can store it
 	pub nonce: Nonce,
+	pub source: KeySourceKind, // what the user needs to provide to unlock this keyslot
 }
 
 pub const KEYSLOT_SIZE: usize = 112;
 
+/// The nonce is padded to this length, so every algorithm's keyslots are the same size
+///
+/// The last byte of a V2 keyslot's padding holds its `KeySourceKind`. V1 keyslots don't record one, as they're always unlocked with a password.
+const KEYSLOT_NONCE_LEN: usize = 26;
+
+// The version, algorithm and hashing algorithm take up 2 bytes each, followed by both salts, the
+// encrypted master key and the padded nonce. A change to any of these lengths breaks the layout.
+const _: () = assert!(6 + SALT_LEN * 2 + ENCRYPTED_KEY_LEN + KEYSLOT_NONCE_LEN == KEYSLOT_SIZE);
+const _: () = assert!(Algorithm::XChaCha20Poly1305.nonce_len() < KEYSLOT_NONCE_LEN);
+const _: () = assert!(Algorithm::Aes256Gcm.nonce_len() < KEYSLOT_NONCE_LEN);
+const _: () = assert!(Algorithm::Aes128Gcm.nonce_len() < KEYSLOT_NONCE_LEN);
+
 /// This defines the keyslot version
 ///
 /// The goal is to not increment this much, but it's here in case we need to make breaking changes
 #[derive(Clone, Copy)]
 pub enum KeyslotVersion {
 	V1,
+	/// This is the same as V1, with the kind of source that the keyslot is unlocked with stored in the last byte of the nonce padding.
+	///
+	/// Older builds would try to unlock these with a password, so only V2 keyslots may be unlocked with anything else.
+	V2,
+}
+
+impl KeyslotVersion {
+	/// This returns whether a keyslot of this version records the kind of source that it's unlocked with.
+	#[must_use]
+	pub const fn records_source(self) -> bool {
+		matches!(self, Self::V2)
+	}
 }
 
 impl Keyslot {
@@ -95,31 +123,81 @@ impl Keyslot {
 			content_salt,
 			master_key: encrypted_master_key,
 			nonce,
+			source: KeySourceKind::Password,
+		})
+	}
+
+	/// This creates a keyslot whose master key is wrapped with a key from `source`, and records the kind of source it needs.
+	///
+	/// An error will be returned if the keyslot version can't record the kind of source (V1 keyslots can only be unlocked with a password).
+	pub async fn from_source(
+		version: KeyslotVersion,
+		algorithm: Algorithm,
+		hashing_algorithm: HashingAlgorithm,
+		source: &impl KeySource,
+		master_key: Key,
+	) -> Result<Self> {
+		if !version.records_source() && source.kind() != KeySourceKind::Password {
+			return Err(Error::Serialization);
+		}
+
+		let content_salt = Salt::generate();
+		let wrapping_key = source.wrapping_key(hashing_algorithm, content_salt)?;
+
+		Ok(Self {
+			source: source.kind(),
+			..Self::new(
+				version,
+				algorithm,
+				hashing_algorithm,
+				content_salt,
+				wrapping_key,
+				master_key,
+			)
+			.await?
 		})
 	}
 
+	/// This returns the cost of hashing a password for this keyslot, with its stored hashing algorithm and parameters.
+	#[must_use]
+	pub fn kdf_cost(&self) -> KdfCost {
+		self.hashing_algorithm.kdf_cost()
+	}
+
 	/// This function should not be used directly, use `header.decrypt_master_key()` instead
 	///
 	/// This attempts to decrypt the master key for a single keyslot
 	///
-	/// An error will be returned on failure.
-	#[allow(clippy::needless_pass_by_value)]
+	/// The password is hashed with the keyslot's hashing algorithm on the blocking thread pool, as it's deliberately expensive.
+	/// This costs one full KDF run per keyslot, so trying a password against every keyslot in a header costs one per password keyslot.
+	///
+	/// You receive `Error::IncorrectPassword` if the password doesn't unwrap the master key.
 	pub async fn decrypt_master_key(&self, password: Protected<Vec<u8>>) -> Result<Key> {
-		let key = self
-			.hashing_algorithm
-			.hash(password, self.content_salt, None)
-			.map_err(|_| Error::PasswordHash)?;
+		let hashing_algorithm = self.hashing_algorithm;
+		let content_salt = self.content_salt;
 
-		Key::try_from(
-			StreamDecryption::decrypt_bytes(
-				Key::derive(key, self.salt, FILE_KEY_CONTEXT),
-				self.nonce,
-				self.algorithm,
-				&self.master_key,
-				&[],
-			)
-			.await?,
+		let key = tokio::task::spawn_blocking(move || {
+			hashing_algorithm.hash(password, content_salt, None)
+		})
+		.await
+		.map_err(|_| Error::PasswordHash)?
+		.map_err(|_| Error::PasswordHash)?;
+
+		self.decrypt_master_key_from_prehashed(key).await
+	}
+
+	/// This attempts to decrypt the master key for a single keyslot, with a key from `source`.
+	///
+	/// You receive an error if the keyslot needs a different kind of source.
+	pub async fn decrypt_master_key_from_source(&self, source: &impl KeySource) -> Result<Key> {
+		if source.kind() != self.source {
+			return Err(Error::KeySourceMismatch(self.source));
+		}
+
+		self.decrypt_master_key_from_prehashed(
+			source.wrapping_key(self.hashing_algorithm, self.content_salt)?,
 		)
+		.await
 	}
 
 	/// This function should not be used directly, use `header.decrypt_master_key()` instead
@@ -128,7 +206,7 @@ impl Keyslot {
 	///
 	/// No hashing is done internally.
 	///
-	/// An error will be returned on failure.
+	/// You receive `Error::IncorrectPassword` if the key doesn't unwrap the master key.
 	pub async fn decrypt_master_key_from_prehashed(&self, key: Key) -> Result<Key> {
 		Key::try_from(
 			StreamDecryption::decrypt_bytes(
@@ -138,15 +216,25 @@ impl Keyslot {
 				&self.master_key,
 				&[],
 			)
-			.await?,
+			.await
+			.map_err(|e| match e {
+				Error::Decrypt => Error::IncorrectPassword,
+				e => e,
+			})?,
 		)
 	}
 
 	/// This function is used to serialize a keyslot into bytes
 	#[must_use]
 	pub fn to_bytes(&self) -> Vec<u8> {
+		let source = if self.version.records_source() {
+			self.source.to_byte()
+		} else {
+			0
+		};
+
 		match self.version {
-			KeyslotVersion::V1 => [
+			KeyslotVersion::V1 | KeyslotVersion::V2 => [
 				self.version.to_bytes().as_ref(),
 				self.algorithm.to_bytes().as_ref(),
 				self.hashing_algorithm.to_bytes().as_ref(),
@@ -154,7 +242,8 @@ impl Keyslot {
 				&self.content_salt,
 				&self.master_key,
 				&self.nonce,
-				&vec![0u8; 26 - self.nonce.len()],
+				&vec![0u8; KEYSLOT_NONCE_LEN - self.nonce.len() - 1],
+				&[source],
 			]
 			.into_iter()
 			.flatten()
@@ -177,7 +266,7 @@ impl Keyslot {
 		let version = KeyslotVersion::from_bytes(version)?;
 
 		match version {
-			KeyslotVersion::V1 => {
+			KeyslotVersion::V1 | KeyslotVersion::V2 => {
 				let mut algorithm = [0u8; 2];
 				reader.read_exact(&mut algorithm)?;
 				let algorithm = Algorithm::from_bytes(algorithm)?;
@@ -199,7 +288,14 @@ impl Keyslot {
 				reader.read_exact(&mut nonce)?;
 				let nonce = Nonce::try_from(nonce)?;
 
-				reader.read_exact(&mut vec![0u8; 26 - nonce.len()])?;
+				let mut padding = vec![0u8; KEYSLOT_NONCE_LEN - nonce.len()];
+				reader.read_exact(&mut padding)?;
+				// V1 keyslots are always unlocked with a password, whatever is in their padding
+				let source = if version.records_source() {
+					KeySourceKind::from_byte(padding[padding.len() - 1])?
+				} else {
+					KeySourceKind::Password
+				};
 
 				let keyslot = Self {
 					version,
@@ -209,6 +305,7 @@ impl Keyslot {
 					content_salt: Salt(content_salt),
 					master_key: EncryptedKey(master_key),
 					nonce,
+					source,
 				};
 
 				Ok(keyslot)
@@ -216,3 +313,64 @@ impl Keyslot {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use crate::{
+		keys::hashing::Params,
+		primitives::{types::Salt, LATEST_KEYSLOT},
+	};
+
+	use super::*;
+
+	const ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+	const HASHING_ALGORITHM: HashingAlgorithm = HashingAlgorithm::Argon2id(Params::Standard);
+
+	#[tokio::test]
+	async fn wrap_and_unwrap_master_key() {
+		let master_key = Key::generate();
+		let hashed_key = Key::generate(); // not hashed, but that'd be expensive
+
+		let keyslot = Keyslot::new(
+			LATEST_KEYSLOT,
+			ALGORITHM,
+			HASHING_ALGORITHM,
+			Salt::generate(),
+			hashed_key.clone(),
+			master_key.clone(),
+		)
+		.await
+		.unwrap();
+
+		let keyslot = Keyslot::from_reader(&mut Cursor::new(keyslot.to_bytes())).unwrap();
+		let unwrapped = keyslot
+			.decrypt_master_key_from_prehashed(hashed_key)
+			.await
+			.unwrap();
+
+		assert_eq!(unwrapped.expose(), master_key.expose());
+	}
+
+	#[tokio::test]
+	async fn unwrap_master_key_with_wrong_key() {
+		let keyslot = Keyslot::new(
+			LATEST_KEYSLOT,
+			ALGORITHM,
+			HASHING_ALGORITHM,
+			Salt::generate(),
+			Key::generate(),
+			Key::generate(),
+		)
+		.await
+		.unwrap();
+
+		assert!(matches!(
+			keyslot
+				.decrypt_master_key_from_prehashed(Key::generate())
+				.await,
+			Err(Error::IncorrectPassword)
+		));
+	}
+}
@@ -1,8 +1,9 @@
 //! This module will contains all header related functions.
 //!
-//! It handles serialisation, deserialisation, AAD, keyslots and metadata, preview media.
+//! It handles serialisation, deserialisation, AAD, keyslots and metadata, preview media and plaintext commitments.
 pub mod file;
 pub mod keyslot;
 pub mod metadata;
+pub mod plaintext_commitment;
 pub mod preview_media;
 pub mod serialization;
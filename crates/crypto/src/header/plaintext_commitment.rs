@@ -0,0 +1,273 @@
+//! This module contains the plaintext commitment header item.
+//!
+//! It is an optional extension to a header, and allows two encrypted files to be compared for equality without decrypting either of them.
+//!
+//! The commitment is a keyed BLAKE3 hash of the plaintext, under a key that's shared by every file in a library (see `KeyManager::get_dedup_key()`).
+//! Files that are encrypted under different master keys (and nonces) still have the same commitment if their plaintext is the same.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! // The plaintext is hashed, and the reader is rewound so it can be encrypted afterwards
+//! header.add_plaintext_commitment(&dedup_key, &mut reader).await?;
+//! header.write(&mut writer).await?;
+//!
+//! // Later on, only the headers are needed to compare two files
+//! if same_plaintext(&header_a, &header_b) == Some(true) {
+//! 	// one of them can be removed
+//! }
+//! ```
+use std::io::SeekFrom;
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{
+	primitives::{types::Key, BLOCK_LEN, KEY_LEN, PLAINTEXT_COMMITMENT_CONTEXT},
+	Error, Result,
+};
+
+use super::file::FileHeader;
+
+/// This is a plaintext commitment header item, which commits to the plaintext of a file's body under a library-wide key.
+///
+/// As anyone with the key can check whether a file contains a given plaintext, the key should never leave the library.
+#[derive(Clone)]
+pub struct PlaintextCommitment {
+	pub version: PlaintextCommitmentVersion,
+	pub commitment: [u8; KEY_LEN],
+}
+
+#[derive(Clone, Copy)]
+pub enum PlaintextCommitmentVersion {
+	V1,
+}
+
+/// This derives the key that the plaintext is hashed with, from the dedup key.
+fn commitment_key(dedup_key: &Key) -> [u8; KEY_LEN] {
+	blake3::derive_key(PLAINTEXT_COMMITMENT_CONTEXT, dedup_key.expose())
+}
+
+impl PlaintextCommitment {
+	/// This reads the plaintext until the end, and commits to it.
+	pub async fn build<R>(dedup_key: &Key, mut reader: R) -> Result<Self>
+	where
+		R: AsyncReadExt + Unpin + Send,
+	{
+		let mut hasher = blake3::Hasher::new_keyed(&commitment_key(dedup_key));
+		let mut block = vec![0u8; BLOCK_LEN].into_boxed_slice();
+
+		loop {
+			let i = reader.read(&mut block).await?;
+			if i == 0 {
+				break;
+			}
+			hasher.update(&block[..i]);
+		}
+
+		Ok(Self {
+			version: PlaintextCommitmentVersion::V1,
+			commitment: hasher.finalize().into(),
+		})
+	}
+
+	#[must_use]
+	pub fn size(&self) -> usize {
+		self.to_bytes().len()
+	}
+
+	/// This function is used to serialize a plaintext commitment header item into bytes
+	#[must_use]
+	pub fn to_bytes(&self) -> Vec<u8> {
+		match self.version {
+			PlaintextCommitmentVersion::V1 => [self.version.to_bytes().as_ref(), &self.commitment]
+				.into_iter()
+				.flatten()
+				.copied()
+				.collect(),
+		}
+	}
+
+	/// This function reads a plaintext commitment header item from a reader
+	///
+	/// The cursor will be left at the end of the plaintext commitment item on success
+	///
+	/// The cursor will not be rewound on error.
+	pub async fn from_reader<R>(reader: &mut R) -> Result<Self>
+	where
+		R: AsyncReadExt + Unpin + Send,
+	{
+		let mut version = [0u8; 2];
+		reader.read_exact(&mut version).await?;
+		let version = PlaintextCommitmentVersion::from_bytes(version)
+			.map_err(|_| Error::NoPlaintextCommitment)?;
+
+		match version {
+			PlaintextCommitmentVersion::V1 => {
+				let mut commitment = [0u8; KEY_LEN];
+				reader.read_exact(&mut commitment).await?;
+
+				Ok(Self {
+					version,
+					commitment,
+				})
+			}
+		}
+	}
+}
+
+impl FileHeader {
+	/// This commits to the plaintext with the library's dedup key, and attaches the commitment to the header.
+	///
+	/// The reader is rewound afterwards, so the same reader can then be encrypted with `StreamEncryption::encrypt_streams()`.
+	///
+	/// Its presence is recorded in the header's body parameters, which are part of the AAD - so it needs to be called before `FileHeader::generate_aad()`.
+	///
+	/// An error will be returned if the header version can't record a plaintext commitment (only V5 headers can).
+	pub async fn add_plaintext_commitment<R>(
+		&mut self,
+		dedup_key: &Key,
+		reader: &mut R,
+	) -> Result<()>
+	where
+		R: AsyncReadExt + AsyncSeekExt + Unpin + Send,
+	{
+		if !self.version.has_body_params() {
+			return Err(Error::UnsupportedBodyParams);
+		}
+
+		let start = reader.stream_position().await?;
+		self.plaintext_commitment =
+			Some(PlaintextCommitment::build(dedup_key, &mut *reader).await?);
+		reader.seek(SeekFrom::Start(start)).await?;
+
+		Ok(())
+	}
+}
+
+/// This checks whether two encrypted files have the same plaintext, from their headers alone.
+///
+/// `blake3::Hash` is used for the comparison, as its equality check is constant-time.
+///
+/// This returns `None` if either header doesn't have a plaintext commitment. Commitments are only comparable if both files
+/// were committed to with the same dedup key (e.g. within the same library) - otherwise they will never match.
+#[must_use]
+pub fn same_plaintext(a: &FileHeader, b: &FileHeader) -> Option<bool> {
+	match (&a.plaintext_commitment, &b.plaintext_commitment) {
+		(Some(a), Some(b)) => {
+			Some(blake3::Hash::from(a.commitment) == blake3::Hash::from(b.commitment))
+		}
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use crate::{
+		crypto::stream::{Algorithm, StreamEncryption},
+		header::keyslot::Keyslot,
+		keys::hashing::{HashingAlgorithm, Params},
+		primitives::{types::Salt, LATEST_FILE_HEADER, LATEST_KEYSLOT},
+	};
+
+	use super::*;
+
+	const ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+	const HASHING_ALGORITHM: HashingAlgorithm = HashingAlgorithm::Argon2id(Params::Standard);
+
+	/// This encrypts the plaintext under a fresh master key, and reads the header back from the encrypted file.
+	async fn encrypt(dedup_key: &Key, plaintext: &[u8]) -> FileHeader {
+		let master_key = Key::generate();
+		let mut reader = Cursor::new(plaintext.to_vec());
+		let mut writer = Cursor::new(Vec::new());
+
+		let mut header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			ALGORITHM,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				Salt::generate(),
+				Key::generate(), // not hashed, but that'd be expensive
+				master_key.clone(),
+			)
+			.await
+			.unwrap()],
+		)
+		.unwrap();
+
+		header
+			.add_plaintext_commitment(dedup_key, &mut reader)
+			.await
+			.unwrap();
+		header.write(&mut writer).await.unwrap();
+
+		StreamEncryption::new(master_key, header.nonce, header.algorithm)
+			.unwrap()
+			.encrypt_streams(&mut reader, &mut writer, &header.generate_aad())
+			.await
+			.unwrap();
+
+		writer.rewind().await.unwrap();
+		FileHeader::from_reader(&mut writer).await.unwrap().0
+	}
+
+	#[tokio::test]
+	async fn same_plaintext_is_detected() {
+		let dedup_key = Key::generate();
+		let plaintext = vec![0x5A; BLOCK_LEN + 17];
+
+		let a = encrypt(&dedup_key, &plaintext).await;
+		let b = encrypt(&dedup_key, &plaintext).await;
+
+		// the nonces (and master keys) differ, but the plaintext doesn't
+		assert!(a.nonce != b.nonce);
+		assert_eq!(same_plaintext(&a, &b), Some(true));
+	}
+
+	#[tokio::test]
+	async fn different_plaintext_is_detected() {
+		let dedup_key = Key::generate();
+
+		let a = encrypt(&dedup_key, &[0x5A; 64]).await;
+		let mut other = [0x5A; 64];
+		other[63] = 0x00;
+		let b = encrypt(&dedup_key, &other).await;
+
+		assert_eq!(same_plaintext(&a, &b), Some(false));
+
+		// the same plaintext under another library's key is a different commitment
+		let c = encrypt(&Key::generate(), &[0x5A; 64]).await;
+		assert_eq!(same_plaintext(&a, &c), Some(false));
+	}
+
+	#[tokio::test]
+	async fn commitment_is_recorded_in_header() {
+		let a = encrypt(&Key::generate(), &[0x5A; 64]).await;
+		let mut b = a.clone();
+		b.plaintext_commitment = None;
+
+		// the flag is part of the AAD, so a commitment can't be added or stripped without it being noticed
+		assert!(a.generate_aad() != b.generate_aad());
+
+		// without the flag, bytes after the other header items aren't mistaken for a commitment
+		let mut bytes = b.to_bytes().unwrap();
+		bytes.extend_from_slice(&a.plaintext_commitment.as_ref().unwrap().to_bytes());
+		let (read, _) = FileHeader::from_reader(&mut Cursor::new(bytes))
+			.await
+			.unwrap();
+		assert!(read.plaintext_commitment.is_none());
+	}
+
+	#[tokio::test]
+	async fn missing_commitment_is_unknown() {
+		let a = encrypt(&Key::generate(), &[0x5A; 64]).await;
+		let mut b = a.clone();
+		b.plaintext_commitment = None;
+
+		assert_eq!(same_plaintext(&a, &b), None);
+		assert_eq!(same_plaintext(&b, &b), None);
+	}
+}
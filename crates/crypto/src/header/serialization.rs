@@ -4,27 +4,52 @@
 use std::fmt::Display;
 
 use crate::{
-	crypto::stream::Algorithm,
+	crypto::stream::{Algorithm, Framing},
 	keys::hashing::{HashingAlgorithm, Params},
+	primitives::{LATEST_FILE_HEADER, LATEST_KEYSLOT},
 	Error, Result,
 };
 
 use super::{
 	file::FileHeaderVersion, keyslot::KeyslotVersion, metadata::MetadataVersion,
-	preview_media::PreviewMediaVersion,
+	plaintext_commitment::PlaintextCommitmentVersion, preview_media::PreviewMediaVersion,
 };
 
 impl FileHeaderVersion {
+	/// This is the newest version that this build is able to read.
+	pub const MAX_SUPPORTED: Self = LATEST_FILE_HEADER;
+
 	#[must_use]
 	pub const fn to_bytes(&self) -> [u8; 2] {
 		match self {
 			Self::V1 => [0x0A, 0x01],
+			Self::V2 => [0x0A, 0x02],
+			Self::V3 => [0x0A, 0x03],
+			Self::V4 => [0x0A, 0x04],
+			Self::V5 => [0x0A, 0x05],
 		}
 	}
 
+	/// This returns the version number, which is the second version byte.
+	#[must_use]
+	pub const fn number(&self) -> u8 {
+		self.to_bytes()[1]
+	}
+
+	/// A version that's newer than `MAX_SUPPORTED` returns `Error::UnsupportedHeaderVersion`, so the user can be told to update.
 	pub const fn from_bytes(bytes: [u8; 2]) -> Result<Self> {
 		match bytes {
 			[0x0A, 0x01] => Ok(Self::V1),
+			[0x0A, 0x02] => Ok(Self::V2),
+			[0x0A, 0x03] => Ok(Self::V3),
+			[0x0A, 0x04] => Ok(Self::V4),
+			[0x0A, 0x05] => Ok(Self::V5),
+			[0x0A, found] if found > Self::MAX_SUPPORTED.number() => {
+				Err(Error::UnsupportedHeaderVersion {
+					found,
+					max_supported: Self::MAX_SUPPORTED.number(),
+				})
+			}
 			_ => Err(Error::Serialization),
 		}
 	}
@@ -34,21 +59,43 @@ impl Display for FileHeaderVersion {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match *self {
 			Self::V1 => write!(f, "V1"),
+			Self::V2 => write!(f, "V2"),
+			Self::V3 => write!(f, "V3"),
+			Self::V4 => write!(f, "V4"),
+			Self::V5 => write!(f, "V5"),
 		}
 	}
 }
 
 impl KeyslotVersion {
+	/// This is the newest version that this build is able to read.
+	pub const MAX_SUPPORTED: Self = LATEST_KEYSLOT;
+
 	#[must_use]
 	pub const fn to_bytes(&self) -> [u8; 2] {
 		match self {
 			Self::V1 => [0x0D, 0x01],
+			Self::V2 => [0x0D, 0x02],
 		}
 	}
 
+	/// This returns the version number, which is the second version byte.
+	#[must_use]
+	pub const fn number(&self) -> u8 {
+		self.to_bytes()[1]
+	}
+
+	/// A version that's newer than `MAX_SUPPORTED` returns `Error::UnsupportedKeyslotVersion`, so the user can be told to update.
 	pub const fn from_bytes(bytes: [u8; 2]) -> Result<Self> {
 		match bytes {
 			[0x0D, 0x01] => Ok(Self::V1),
+			[0x0D, 0x02] => Ok(Self::V2),
+			[0x0D, found] if found > Self::MAX_SUPPORTED.number() => {
+				Err(Error::UnsupportedKeyslotVersion {
+					found,
+					max_supported: Self::MAX_SUPPORTED.number(),
+				})
+			}
 			_ => Err(Error::Serialization),
 		}
 	}
@@ -58,6 +105,7 @@ impl Display for KeyslotVersion {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match *self {
 			Self::V1 => write!(f, "V1"),
+			Self::V2 => write!(f, "V2"),
 		}
 	}
 }
@@ -86,6 +134,30 @@ impl Display for PreviewMediaVersion {
 	}
 }
 
+impl PlaintextCommitmentVersion {
+	#[must_use]
+	pub const fn to_bytes(&self) -> [u8; 2] {
+		match self {
+			Self::V1 => [0x3C, 0x01],
+		}
+	}
+
+	pub const fn from_bytes(bytes: [u8; 2]) -> Result<Self> {
+		match bytes {
+			[0x3C, 0x01] => Ok(Self::V1),
+			_ => Err(Error::Serialization),
+		}
+	}
+}
+
+impl Display for PlaintextCommitmentVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match *self {
+			Self::V1 => write!(f, "V1"),
+		}
+	}
+}
+
 impl MetadataVersion {
 	#[must_use]
 	pub const fn to_bytes(&self) -> [u8; 2] {
@@ -165,6 +237,7 @@ impl Algorithm {
 		match self {
 			Self::XChaCha20Poly1305 => [0x0B, 0x01],
 			Self::Aes256Gcm => [0x0B, 0x02],
+			Self::Aes128Gcm => [0x0B, 0x03],
 		}
 	}
 
@@ -172,6 +245,26 @@ impl Algorithm {
 		match bytes {
 			[0x0B, 0x01] => Ok(Self::XChaCha20Poly1305),
 			[0x0B, 0x02] => Ok(Self::Aes256Gcm),
+			[0x0B, 0x03] => Ok(Self::Aes128Gcm),
+			_ => Err(Error::Serialization),
+		}
+	}
+}
+
+impl Framing {
+	/// This is stored in the first byte of a V5 header's body parameters.
+	#[must_use]
+	pub const fn to_byte(&self) -> u8 {
+		match self {
+			Self::Fixed => 0x00,
+			Self::LengthPrefixed => 0x01,
+		}
+	}
+
+	pub const fn from_byte(byte: u8) -> Result<Self> {
+		match byte {
+			0x00 => Ok(Self::Fixed),
+			0x01 => Ok(Self::LengthPrefixed),
 			_ => Err(Error::Serialization),
 		}
 	}
@@ -182,6 +275,7 @@ impl Display for Algorithm {
 		match *self {
 			Self::XChaCha20Poly1305 => write!(f, "XChaCha20-Poly1305"),
 			Self::Aes256Gcm => write!(f, "AES-256-GCM"),
+			Self::Aes128Gcm => write!(f, "AES-128-GCM"),
 		}
 	}
 }
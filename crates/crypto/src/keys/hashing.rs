@@ -51,7 +51,62 @@ pub enum HashingAlgorithm {
 	BalloonBlake3(Params),
 }
 
+/// This is the cost of hashing a password, which is what makes guessing it expensive.
+///
+/// It's comparable across algorithms, as the memory is always in KiB, and the iterations are passes over that memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize),
+	derive(serde::Deserialize)
+)]
+#[cfg_attr(feature = "rspc", derive(rspc::Type))]
+pub struct KdfCost {
+	pub memory_kib: u32,
+	pub iterations: u32,
+	pub parallelism: u32,
+}
+
+impl KdfCost {
+	/// This returns whether this cost is at least as high as the minimum's.
+	///
+	/// Only the memory and iterations are compared. Parallelism splits the same work across lanes, so it speeds up
+	/// hashing for us without making a guess any cheaper for an attacker - a lower parallelism is never weaker.
+	#[must_use]
+	pub const fn meets(&self, min: &Self) -> bool {
+		self.memory_kib >= min.memory_kib && self.iterations >= min.iterations
+	}
+}
+
 impl HashingAlgorithm {
+	/// This returns the cost of hashing a password with these parameters.
+	///
+	/// BLAKE3-Balloon's space cost is a number of blocks (each the length of a BLAKE3 hash), so it's converted to KiB.
+	#[must_use]
+	pub fn kdf_cost(&self) -> KdfCost {
+		match self {
+			Self::Argon2id(params) => {
+				let params = params.argon2id();
+
+				KdfCost {
+					memory_kib: params.m_cost(),
+					iterations: params.t_cost(),
+					parallelism: params.p_cost(),
+				}
+			}
+			Self::BalloonBlake3(params) => {
+				let params = params.balloon_blake3();
+
+				KdfCost {
+					// every block is a 32 byte BLAKE3 hash
+					memory_kib: params.s_cost.get() * 32 / 1024,
+					iterations: params.t_cost.get(),
+					parallelism: params.p_cost.get(),
+				}
+			}
+		}
+	}
+
 	/// This function should be used to hash passwords. It handles all appropriate parameters, and uses hashing with a secret key (if provided).
 	#[allow(clippy::needless_pass_by_value)]
 	pub fn hash(
@@ -362,4 +417,30 @@ mod tests {
 
 		assert_eq!(&DERIVE_B3_EXPECTED, output.expose());
 	}
+
+	#[test]
+	fn lower_parallelism_meets_the_minimum() {
+		let min = KdfCost {
+			memory_kib: 262_144,
+			iterations: 8,
+			parallelism: 4,
+		};
+
+		assert!(KdfCost {
+			parallelism: 1,
+			..min
+		}
+		.meets(&min));
+		assert!(!KdfCost {
+			memory_kib: 131_072,
+			parallelism: 8,
+			..min
+		}
+		.meets(&min));
+		assert!(!KdfCost {
+			iterations: 4,
+			..min
+		}
+		.meets(&min));
+	}
 }
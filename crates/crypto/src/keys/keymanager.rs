@@ -37,16 +37,17 @@
 
 use std::sync::Arc;
 
-use tokio::sync::Mutex;
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
 
 use crate::{
 	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
+	locked::Locked,
 	primitives::{
 		types::{
 			EncryptedKey, Key, Nonce, OnboardingConfig, Password, Salt, SecretKey, SecretKeyString,
 		},
-		APP_IDENTIFIER, LATEST_STORED_KEY, MASTER_PASSWORD_CONTEXT, ROOT_KEY_CONTEXT,
-		SECRET_KEY_IDENTIFIER,
+		APP_IDENTIFIER, DEDUP_KEY_CONTEXT, KEY_LEN, LATEST_STORED_KEY, MASTER_PASSWORD_CONTEXT,
+		ROOT_KEY_CONTEXT, SECRET_KEY_IDENTIFIER,
 	},
 	Error, Protected, Result,
 };
@@ -113,7 +114,7 @@ pub struct MountedKey {
 ///
 /// Use the associated functions to interact with it.
 pub struct KeyManager {
-	root_key: Mutex<Option<Key>>, // the root key for the vault
+	root_key: Mutex<Option<Locked<[u8; KEY_LEN]>>>, // the root key for the vault
 	verification_key: Mutex<Option<StoredKey>>,
 	keystore: DashMap<Uuid, StoredKey>,
 	keymount: DashMap<Uuid, MountedKey>,
@@ -396,7 +397,6 @@ impl KeyManager {
 		let master_key = Key::generate();
 		let master_key_nonce = Nonce::generate(algorithm)?;
 
-		let root_key = self.get_root_key().await?;
 		let root_key_nonce = Nonce::generate(algorithm)?;
 
 		let salt = Salt::generate();
@@ -413,6 +413,8 @@ impl KeyManager {
 			.await?,
 		)?;
 
+		// the root key is encrypted straight from its locked memory, rather than being copied out of it
+		let root_key = self.lend_root_key().await?;
 		let encrypted_root_key = StreamEncryption::encrypt_bytes(
 			master_key,
 			root_key_nonce,
@@ -421,6 +423,7 @@ impl KeyManager {
 			&[],
 		)
 		.await?;
+		drop(root_key);
 
 		// will update if it's already present
 		self.keyring_insert(
@@ -547,7 +550,7 @@ impl KeyManager {
 					// encrypt the master key with the current root key
 					let encrypted_master_key = EncryptedKey::try_from(
 						StreamEncryption::encrypt_bytes(
-							Key::derive(self.get_root_key().await?, salt, ROOT_KEY_CONTEXT),
+							self.derive_from_root_key(ROOT_KEY_CONTEXT, &salt).await?,
 							master_key_nonce,
 							key.algorithm,
 							master_key.expose(),
@@ -647,22 +650,22 @@ impl KeyManager {
 					Error::IncorrectPassword
 				})?;
 
-				*self.root_key.lock().await = Some(
-					Key::try_from(
-						StreamDecryption::decrypt_bytes(
-							Key::try_from(master_key)?,
-							verification_key.key_nonce,
-							verification_key.algorithm,
-							&verification_key.key,
-							&[],
-						)
-						.await?,
+				let root_key = Key::try_from(
+					StreamDecryption::decrypt_bytes(
+						Key::try_from(master_key)?,
+						verification_key.key_nonce,
+						verification_key.algorithm,
+						&verification_key.key,
+						&[],
 					)
-					.map_err(|e| {
-						self.remove_from_queue(verification_key.uuid).ok();
-						e
-					})?,
-				);
+					.await?,
+				)
+				.map_err(|e| {
+					self.remove_from_queue(verification_key.uuid).ok();
+					e
+				})?;
+
+				*self.root_key.lock().await = Some(Locked::new(*root_key.expose()));
 
 				self.remove_from_queue(verification_key.uuid)?;
 			}
@@ -698,11 +701,8 @@ impl KeyManager {
 					self.mounting_queue.insert(uuid);
 
 					let master_key = StreamDecryption::decrypt_bytes(
-						Key::derive(
-							self.get_root_key().await?,
-							stored_key.salt,
-							ROOT_KEY_CONTEXT,
-						),
+						self.derive_from_root_key(ROOT_KEY_CONTEXT, &stored_key.salt)
+							.await?,
 						stored_key.master_key_nonce,
 						stored_key.algorithm,
 						&stored_key.master_key,
@@ -763,11 +763,8 @@ impl KeyManager {
 
 		if let Some(stored_key) = self.keystore.get(&uuid) {
 			let master_key = StreamDecryption::decrypt_bytes(
-				Key::derive(
-					self.get_root_key().await?,
-					stored_key.salt,
-					ROOT_KEY_CONTEXT,
-				),
+				self.derive_from_root_key(ROOT_KEY_CONTEXT, &stored_key.salt)
+					.await?,
 				stored_key.master_key_nonce,
 				stored_key.algorithm,
 				&stored_key.master_key,
@@ -830,7 +827,7 @@ impl KeyManager {
 		// Encrypt the master key with a derived key (derived from the root key)
 		let encrypted_master_key = EncryptedKey::try_from(
 			StreamEncryption::encrypt_bytes(
-				Key::derive(self.get_root_key().await?, salt, ROOT_KEY_CONTEXT),
+				self.derive_from_root_key(ROOT_KEY_CONTEXT, &salt).await?,
 				master_key_nonce,
 				algorithm,
 				master_key.expose(),
@@ -912,9 +909,34 @@ impl KeyManager {
 		self.default.lock().await.ok_or(Error::NoDefaultKeySet)
 	}
 
-	/// This should ONLY be used internally, for accessing the root key.
-	async fn get_root_key(&self) -> Result<Key> {
-		self.root_key.lock().await.clone().ok_or(Error::NotUnlocked)
+	/// This lends out the root key for as long as the returned guard is held, so it can be used without ever leaving its locked memory.
+	///
+	/// This should ONLY be used internally, and the root key should never be copied out of the guard.
+	async fn lend_root_key(&self) -> Result<MappedMutexGuard<'_, Locked<[u8; KEY_LEN]>>> {
+		MutexGuard::try_map(self.root_key.lock().await, Option::as_mut)
+			.map_err(|_| Error::NotUnlocked)
+	}
+
+	/// This should ONLY be used internally, for deriving keys from the root key.
+	///
+	/// The root key is hashed straight from its locked memory, so it's never copied out of it. This matches `Key::derive()`.
+	async fn derive_from_root_key(&self, context: &str, salt: &[u8]) -> Result<Key> {
+		let root_key = self.lend_root_key().await?;
+
+		Ok(Key::new(
+			blake3::Hasher::new_derive_key(context)
+				.update(root_key.expose())
+				.update(salt)
+				.finalize()
+				.into(),
+		))
+	}
+
+	/// This returns the library's dedup key, which files' plaintext commitments are keyed with (see `FileHeader::add_plaintext_commitment()`).
+	///
+	/// It's derived from the root key, so it's the same for every file in the library and survives master password changes.
+	pub async fn get_dedup_key(&self) -> Result<Key> {
+		self.derive_from_root_key(DEDUP_KEY_CONTEXT, &[]).await
 	}
 
 	pub async fn get_verification_key(&self) -> Result<StoredKey> {
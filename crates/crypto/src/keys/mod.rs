@@ -3,3 +3,4 @@
 pub mod hashing;
 pub mod keymanager;
 pub mod keyring;
+pub mod source;
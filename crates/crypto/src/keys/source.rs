@@ -0,0 +1,415 @@
+//! This module contains the sources that a keyslot's wrapping key can come from.
+//!
+//! Every keyslot records the kind of source it was created with, so the user can be asked for the right thing (e.g. a
+//! password, or a keyfile) when unlocking it. Only V2 keyslots record it, so V1 keyslots are always password keyslots.
+//!
+//! Keys that are held by a PKCS#11 hardware token (e.g. a smart card or an HSM) are supported with the `pkcs11` feature.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let source = KeyfileSource::open("/path/to/keyfile").await?;
+//! let keyslot = Keyslot::from_source(LATEST_KEYSLOT, algorithm, hashing_algorithm, &source, master_key).await?;
+//!
+//! let master_key = keyslot.decrypt_master_key_from_source(&source).await?;
+//! ```
+use std::path::Path;
+
+use tokio::fs;
+
+use crate::{
+	keys::hashing::HashingAlgorithm,
+	primitives::{
+		types::{Key, Salt},
+		KEYFILE_CONTEXT,
+	},
+	Error, Protected, Result,
+};
+
+#[cfg(feature = "pkcs11")]
+use cryptoki::{
+	context::{CInitializeArgs, Pkcs11},
+	mechanism::Mechanism,
+	object::{Attribute, ObjectClass, ObjectHandle},
+	session::{Session, UserType},
+	types::AuthPin,
+};
+
+#[cfg(feature = "pkcs11")]
+use crate::primitives::{to_array, HARDWARE_TOKEN_CONTEXT};
+
+/// This is the kind of source that a keyslot's wrapping key comes from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize),
+	derive(serde::Deserialize)
+)]
+#[cfg_attr(feature = "rspc", derive(rspc::Type))]
+pub enum KeySourceKind {
+	Password,
+	Keyfile,
+	/// This is a key that's held by a hardware token, over PKCS#11 (see `HardwareTokenSource`).
+	HardwareToken,
+}
+
+impl KeySourceKind {
+	#[must_use]
+	pub const fn to_byte(self) -> u8 {
+		match self {
+			Self::Password => 0,
+			Self::Keyfile => 1,
+			Self::HardwareToken => 2,
+		}
+	}
+
+	pub const fn from_byte(byte: u8) -> Result<Self> {
+		match byte {
+			0 => Ok(Self::Password),
+			1 => Ok(Self::Keyfile),
+			2 => Ok(Self::HardwareToken),
+			_ => Err(Error::Serialization),
+		}
+	}
+}
+
+/// This provides the key that a keyslot's master key is wrapped with.
+pub trait KeySource {
+	fn kind(&self) -> KeySourceKind;
+
+	/// This returns the wrapping key for a keyslot with the provided hashing algorithm and content salt.
+	fn wrapping_key(&self, hashing_algorithm: HashingAlgorithm, content_salt: Salt) -> Result<Key>;
+}
+
+/// This hashes a password with the keyslot's hashing algorithm, exactly like password keyslots always have been.
+pub struct PasswordSource(Protected<Vec<u8>>);
+
+impl PasswordSource {
+	#[must_use]
+	pub const fn new(password: Protected<Vec<u8>>) -> Self {
+		Self(password)
+	}
+}
+
+impl KeySource for PasswordSource {
+	fn kind(&self) -> KeySourceKind {
+		KeySourceKind::Password
+	}
+
+	fn wrapping_key(&self, hashing_algorithm: HashingAlgorithm, content_salt: Salt) -> Result<Key> {
+		hashing_algorithm
+			.hash(self.0.clone(), content_salt, None)
+			.map_err(|_| Error::PasswordHash)
+	}
+}
+
+/// This derives the wrapping key from the contents of a file.
+///
+/// A keyfile should be full of random data, so it isn't run through the (slow) password hashing algorithm - the whole
+/// file is hashed with BLAKE3 instead, and the key is derived from that. Changing a single byte of the file changes the key.
+pub struct KeyfileSource(Key);
+
+impl KeyfileSource {
+	/// This reads and hashes the whole keyfile.
+	pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+		let contents = Protected::new(fs::read(path).await?);
+		Ok(Self::from_bytes(contents.expose()))
+	}
+
+	#[must_use]
+	pub fn from_bytes(contents: &[u8]) -> Self {
+		Self(Key::new(blake3::hash(contents).into()))
+	}
+}
+
+impl KeySource for KeyfileSource {
+	fn kind(&self) -> KeySourceKind {
+		KeySourceKind::Keyfile
+	}
+
+	fn wrapping_key(&self, _: HashingAlgorithm, content_salt: Salt) -> Result<Key> {
+		Ok(Key::derive(self.0.clone(), content_salt, KEYFILE_CONTEXT))
+	}
+}
+
+/// This derives the wrapping key with a secret key that never leaves a PKCS#11 token.
+///
+/// The token computes an HMAC-SHA256 of the keyslot's content salt with its key, and the wrapping key is derived from that.
+/// Like keyfiles, the token's key isn't run through the password hashing algorithm (the token's PIN protects it instead).
+///
+/// The key needs to be a generic secret key that's allowed to sign, and is found by its label.
+#[cfg(feature = "pkcs11")]
+pub struct HardwareTokenSource {
+	session: Session,
+	key: ObjectHandle,
+}
+
+#[cfg(feature = "pkcs11")]
+impl HardwareTokenSource {
+	/// This loads the PKCS#11 module, logs in to the token with the provided label, and finds the key with the provided label.
+	///
+	/// You receive `Error::KeyNotFound` if there's no such token, or no such key on it.
+	pub fn open(
+		module: impl AsRef<Path>,
+		token_label: &str,
+		pin: &Protected<String>,
+		key_label: &str,
+	) -> Result<Self> {
+		let pkcs11 = Pkcs11::new(module)?;
+		pkcs11.initialize(CInitializeArgs::OsThreads)?;
+
+		let slot = pkcs11
+			.get_slots_with_token()?
+			.into_iter()
+			.find(|slot| {
+				pkcs11
+					.get_token_info(*slot)
+					.map_or(false, |info| info.label() == token_label)
+			})
+			.ok_or(Error::KeyNotFound)?;
+
+		let session = pkcs11.open_ro_session(slot)?;
+		session.login(UserType::User, Some(&AuthPin::new(pin.expose().clone())))?;
+
+		let key = session
+			.find_objects(&[
+				Attribute::Class(ObjectClass::SECRET_KEY),
+				Attribute::Label(key_label.as_bytes().to_vec()),
+			])?
+			.into_iter()
+			.next()
+			.ok_or(Error::KeyNotFound)?;
+
+		Ok(Self { session, key })
+	}
+}
+
+#[cfg(feature = "pkcs11")]
+impl KeySource for HardwareTokenSource {
+	fn kind(&self) -> KeySourceKind {
+		KeySourceKind::HardwareToken
+	}
+
+	fn wrapping_key(&self, _: HashingAlgorithm, content_salt: Salt) -> Result<Key> {
+		let mac = Protected::new(self.session.sign(
+			&Mechanism::Sha256Hmac,
+			self.key,
+			&content_salt,
+		)?);
+
+		Ok(Key::derive(
+			Key::new(to_array(mac.expose())?),
+			content_salt,
+			HARDWARE_TOKEN_CONTEXT,
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use crate::{
+		crypto::stream::Algorithm,
+		header::keyslot::{Keyslot, KeyslotVersion},
+		keys::hashing::Params,
+		primitives::LATEST_KEYSLOT,
+	};
+
+	use super::*;
+
+	const ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+	const HASHING_ALGORITHM: HashingAlgorithm = HashingAlgorithm::Argon2id(Params::Standard);
+
+	#[tokio::test]
+	async fn keyfile_wraps_and_unwraps_master_key() {
+		let master_key = Key::generate();
+		let source = KeyfileSource::from_bytes(&[0x5A; 64]);
+
+		let keyslot = Keyslot::from_source(
+			LATEST_KEYSLOT,
+			ALGORITHM,
+			HASHING_ALGORITHM,
+			&source,
+			master_key.clone(),
+		)
+		.await
+		.unwrap();
+
+		// the kind survives being written to, and read back from, a header
+		let keyslot = Keyslot::from_reader(&mut Cursor::new(keyslot.to_bytes())).unwrap();
+		assert_eq!(keyslot.source, KeySourceKind::Keyfile);
+
+		let decrypted = keyslot
+			.decrypt_master_key_from_source(&source)
+			.await
+			.unwrap();
+		assert_eq!(decrypted.expose(), master_key.expose());
+
+		let mut other = [0x5A; 64];
+		other[63] = 0x00;
+		assert!(keyslot
+			.decrypt_master_key_from_source(&KeyfileSource::from_bytes(&other))
+			.await
+			.is_err());
+	}
+
+	#[tokio::test]
+	async fn password_source_matches_password_keyslots() {
+		let master_key = Key::generate();
+		let password = Protected::new(b"password".to_vec());
+		let source = PasswordSource::new(password.clone());
+
+		let keyslot = Keyslot::from_source(
+			LATEST_KEYSLOT,
+			ALGORITHM,
+			HASHING_ALGORITHM,
+			&source,
+			master_key.clone(),
+		)
+		.await
+		.unwrap();
+
+		let decrypted = keyslot.decrypt_master_key(password).await.unwrap();
+		assert_eq!(decrypted.expose(), master_key.expose());
+	}
+
+	#[tokio::test]
+	async fn wrong_kind_of_source_is_rejected() {
+		let source = KeyfileSource::from_bytes(b"keyfile");
+
+		let keyslot = Keyslot::from_source(
+			LATEST_KEYSLOT,
+			ALGORITHM,
+			HASHING_ALGORITHM,
+			&source,
+			Key::generate(),
+		)
+		.await
+		.unwrap();
+
+		assert!(matches!(
+			keyslot
+				.decrypt_master_key_from_source(&PasswordSource::new(Protected::new(
+					b"keyfile".to_vec()
+				)))
+				.await,
+			Err(Error::KeySourceMismatch(KeySourceKind::Keyfile))
+		));
+	}
+
+	#[tokio::test]
+	async fn v1_keyslots_are_password_keyslots() {
+		// V1 keyslots can't record their kind of source, so older builds would ask for a password
+		assert!(matches!(
+			Keyslot::from_source(
+				KeyslotVersion::V1,
+				ALGORITHM,
+				HASHING_ALGORITHM,
+				&KeyfileSource::from_bytes(b"keyfile"),
+				Key::generate(),
+			)
+			.await,
+			Err(Error::Serialization)
+		));
+
+		let keyslot = Keyslot::new(
+			KeyslotVersion::V1,
+			ALGORITHM,
+			HASHING_ALGORITHM,
+			Salt::generate(),
+			Key::generate(),
+			Key::generate(),
+		)
+		.await
+		.unwrap();
+
+		// whatever is in a V1 keyslot's padding, it's read back as a password keyslot
+		let mut bytes = keyslot.to_bytes();
+		assert_eq!(bytes.last(), Some(&0));
+		*bytes.last_mut().unwrap() = KeySourceKind::Keyfile.to_byte();
+
+		let keyslot = Keyslot::from_reader(&mut Cursor::new(bytes)).unwrap();
+		assert_eq!(keyslot.source, KeySourceKind::Password);
+
+		assert!(KeySourceKind::from_byte(0xFF).is_err());
+	}
+
+	/// This needs SoftHSM (or another PKCS#11 module), so it's ignored by default.
+	///
+	/// Run it with `PKCS11_MODULE` set to the module's path (and `SOFTHSM2_CONF` pointing at a config with a writable token directory).
+	/// It initializes a token in the first free slot, so it shouldn't be pointed at a real token.
+	#[cfg(feature = "pkcs11")]
+	#[tokio::test]
+	#[ignore = "needs a PKCS#11 module (e.g. SoftHSM)"]
+	async fn hardware_token_wraps_and_unwraps_master_key() {
+		use cryptoki::object::KeyType;
+
+		const TOKEN_LABEL: &str = "sd-crypto test";
+		const KEY_LABEL: &str = "sd-crypto wrapping key";
+
+		let module = std::env::var("PKCS11_MODULE")
+			.unwrap_or_else(|_| "/usr/lib/softhsm/libsofthsm2.so".to_string());
+		let so_pin = AuthPin::new("5678".to_string());
+		let pin = Protected::new("1234".to_string());
+
+		// set up a token with a secret key on it
+		{
+			let pkcs11 = Pkcs11::new(&module).unwrap();
+			pkcs11.initialize(CInitializeArgs::OsThreads).unwrap();
+
+			let slot = pkcs11.get_all_slots().unwrap()[0];
+			pkcs11.init_token(slot, &so_pin, TOKEN_LABEL).unwrap();
+
+			let session = pkcs11.open_rw_session(slot).unwrap();
+			session.login(UserType::So, Some(&so_pin)).unwrap();
+			session
+				.init_pin(&AuthPin::new(pin.expose().clone()))
+				.unwrap();
+			session.logout().unwrap();
+
+			session
+				.login(UserType::User, Some(&AuthPin::new(pin.expose().clone())))
+				.unwrap();
+			session
+				.create_object(&[
+					Attribute::Class(ObjectClass::SECRET_KEY),
+					Attribute::KeyType(KeyType::GENERIC_SECRET),
+					Attribute::Token(true),
+					Attribute::Private(true),
+					Attribute::Sensitive(true),
+					Attribute::Sign(true),
+					Attribute::Label(KEY_LABEL.as_bytes().to_vec()),
+					Attribute::Value(vec![0x5A; 32]),
+				])
+				.unwrap();
+		}
+
+		let source = HardwareTokenSource::open(&module, TOKEN_LABEL, &pin, KEY_LABEL).unwrap();
+		let master_key = Key::generate();
+
+		let keyslot = Keyslot::from_source(
+			LATEST_KEYSLOT,
+			ALGORITHM,
+			HASHING_ALGORITHM,
+			&source,
+			master_key.clone(),
+		)
+		.await
+		.unwrap();
+
+		let keyslot = Keyslot::from_reader(&mut Cursor::new(keyslot.to_bytes())).unwrap();
+		assert_eq!(keyslot.source, KeySourceKind::HardwareToken);
+
+		let decrypted = keyslot
+			.decrypt_master_key_from_source(&source)
+			.await
+			.unwrap();
+		assert_eq!(decrypted.expose(), master_key.expose());
+
+		assert!(matches!(
+			HardwareTokenSource::open(&module, TOKEN_LABEL, &pin, "missing key"),
+			Err(Error::KeyNotFound)
+		));
+	}
+}
@@ -18,6 +18,8 @@ pub mod error;
 pub mod fs;
 pub mod header;
 pub mod keys;
+pub mod locked;
+pub mod password;
 pub mod primitives;
 pub mod protected;
 
@@ -0,0 +1,203 @@
+//! This is a heap-allocated wrapper for long-lived secret values, such as the key manager's root key.
+//!
+//! It behaves similarly to `Protected`, in that it zeroizes on drop and is hidden from `fmt::Debug`.
+//!
+//! With the `mlock` feature enabled, the memory backing the value is also locked, so that it can't be swapped to disk.
+//! Locking may fail (e.g. due to insufficient privileges, or hitting the OS limit on locked memory) - if it does,
+//! a warning is logged and the value is still usable, just without the additional protection.
+//!
+//! Only the inline contents of `T` are locked, so this should be used with types such as `[u8; KEY_LEN]` rather than `Vec<u8>`.
+//! Each value gets pages of its own, so that unlocking one value can never unlock another.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sd_crypto::locked::Locked;
+//!
+//! let secret = Locked::new([0x23u8; 32]);
+//!
+//! // the value is accessed in the same way as a `Protected` value
+//! let value = secret.expose();
+//! ```
+use std::fmt::Debug;
+use zeroize::Zeroize;
+
+pub struct Locked<T>
+where
+	T: Zeroize,
+{
+	#[cfg(not(feature = "mlock"))]
+	data: Box<T>,
+	#[cfg(feature = "mlock")]
+	data: Box<PageAligned<T>>,
+	#[cfg(feature = "mlock")]
+	guard: Option<region::LockGuard>,
+}
+
+/// Memory is locked and unlocked a whole page at a time, so if the value shared a page with another allocation,
+/// unlocking one of them would unlock the other too. Aligning the value to a page (which also pads it to a multiple
+/// of one) gives it pages of its own.
+///
+/// This covers pages of up to 16KiB, which is 4KiB on most platforms and 16KiB on Apple silicon.
+#[cfg(feature = "mlock")]
+#[repr(C, align(16384))]
+struct PageAligned<T>(T);
+
+impl<T> Locked<T>
+where
+	T: Zeroize,
+{
+	#[cfg(not(feature = "mlock"))]
+	#[must_use]
+	pub fn new(value: T) -> Self {
+		Self {
+			data: Box::new(value),
+		}
+	}
+
+	#[cfg(feature = "mlock")]
+	#[must_use]
+	pub fn new(value: T) -> Self {
+		let data = Box::new(PageAligned(value));
+
+		let guard = match region::lock(std::ptr::addr_of!(data.0), std::mem::size_of::<T>()) {
+			Ok(guard) => Some(guard),
+			Err(e) => {
+				tracing::warn!("unable to lock secret memory, it may be swapped to disk: {e}");
+				None
+			}
+		};
+
+		Self { data, guard }
+	}
+
+	#[cfg(not(feature = "mlock"))]
+	#[must_use]
+	pub fn expose(&self) -> &T {
+		&self.data
+	}
+
+	#[cfg(feature = "mlock")]
+	#[must_use]
+	pub fn expose(&self) -> &T {
+		&self.data.0
+	}
+
+	/// This returns whether or not the memory backing the value is currently locked.
+	///
+	/// This will always be `false` if the `mlock` feature is disabled.
+	#[cfg(feature = "mlock")]
+	#[must_use]
+	pub const fn is_locked(&self) -> bool {
+		self.guard.is_some()
+	}
+
+	/// This returns whether or not the memory backing the value is currently locked.
+	///
+	/// This will always be `false` if the `mlock` feature is disabled.
+	#[cfg(not(feature = "mlock"))]
+	#[must_use]
+	pub const fn is_locked(&self) -> bool {
+		false
+	}
+}
+
+impl<T> Clone for Locked<T>
+where
+	T: Zeroize + Clone,
+{
+	fn clone(&self) -> Self {
+		Self::new(self.expose().clone())
+	}
+}
+
+impl<T> Drop for Locked<T>
+where
+	T: Zeroize,
+{
+	fn drop(&mut self) {
+		// the data must be erased before the pages are unlocked
+		#[cfg(not(feature = "mlock"))]
+		self.data.zeroize();
+
+		#[cfg(feature = "mlock")]
+		{
+			self.data.0.zeroize();
+			drop(self.guard.take());
+		}
+	}
+}
+
+impl<T> Debug for Locked<T>
+where
+	T: Zeroize,
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str("[REDACTED]")
+	}
+}
+
+#[cfg(all(test, feature = "mlock", any(target_os = "linux", target_os = "macos")))]
+mod tests {
+	use std::sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	};
+
+	use zeroize::Zeroize;
+
+	use super::Locked;
+
+	struct Tracked {
+		key: [u8; 32],
+		zeroized: Arc<AtomicBool>,
+	}
+
+	impl Zeroize for Tracked {
+		fn zeroize(&mut self) {
+			self.key.zeroize();
+			self.zeroized.store(true, Ordering::SeqCst);
+		}
+	}
+
+	#[test]
+	fn locked_secret_is_usable() {
+		// locking is best-effort (e.g. `RLIMIT_MEMLOCK` may be 0), so only the value itself can be relied on
+		let secret = Locked::new([0x23u8; 32]);
+
+		assert_eq!(secret.expose(), &[0x23u8; 32]);
+	}
+
+	#[test]
+	fn secrets_do_not_share_pages() {
+		let page_of =
+			|secret: &Locked<[u8; 32]>| secret.expose().as_ptr() as usize / region::page::size();
+
+		let first = Locked::new([0x23u8; 32]);
+		let second = Locked::new([0x42u8; 32]);
+
+		// so unlocking one of them when it's dropped can't unlock the other
+		assert_ne!(page_of(&first), page_of(&second));
+		assert_eq!(first.expose().as_ptr() as usize % region::page::size(), 0);
+
+		drop(first);
+		assert_eq!(second.expose(), &[0x42u8; 32]);
+	}
+
+	#[test]
+	fn locked_secret_zeroizes_on_drop() {
+		let zeroized = Arc::new(AtomicBool::new(false));
+
+		let secret = Locked::new(Tracked {
+			key: [0x23u8; 32],
+			zeroized: zeroized.clone(),
+		});
+
+		assert_eq!(secret.expose().key, [0x23u8; 32]);
+		assert!(!zeroized.load(Ordering::SeqCst));
+
+		drop(secret);
+
+		assert!(zeroized.load(Ordering::SeqCst));
+	}
+}
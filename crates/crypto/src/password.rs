@@ -0,0 +1,517 @@
+//! This module contains helpers for passwords chosen by (or for) users.
+//!
+//! Passwords are generated with a CSPRNG, either from random characters or as a diceware-style
+//! passphrase of random words from the EFF's large wordlist.
+//!
+//! The strength of a password that a user chose can be estimated with `estimate_strength()`, so they
+//! can be warned before a weak one is used for encryption.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use sd_crypto::password::{generate, PasswordPolicy};
+//!
+//! let generated = generate(PasswordPolicy::Passphrase {
+//! 	words: 6,
+//! 	separator: '-',
+//! })
+//! .unwrap();
+//!
+//! // six words from a list of 7776 are just over 77 bits of entropy
+//! assert!(generated.entropy > 77.0);
+//! let password = generated.password.expose();
+//! ```
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::{Error, Protected, Result};
+
+/// The EFF's large wordlist, for passphrases.
+const WORDLIST: &str = include_str!("../assets/eff_large_wordlist.txt");
+
+/// The length of the longest word in `WORDLIST`.
+const MAX_WORD_LEN: usize = 9;
+
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"0123456789";
+const SYMBOLS: &[u8] = b"!#$%&*+-=?@^_~";
+
+/// These are the characters a generated password may contain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize),
+	derive(serde::Deserialize)
+)]
+#[cfg_attr(feature = "rspc", derive(rspc::Type))]
+#[allow(clippy::struct_excessive_bools)]
+pub struct CharacterClasses {
+	pub lowercase: bool,
+	pub uppercase: bool,
+	pub digits: bool,
+	pub symbols: bool,
+}
+
+impl Default for CharacterClasses {
+	fn default() -> Self {
+		Self {
+			lowercase: true,
+			uppercase: true,
+			digits: true,
+			symbols: true,
+		}
+	}
+}
+
+impl CharacterClasses {
+	fn alphabet(self) -> Vec<u8> {
+		[
+			(self.lowercase, LOWERCASE),
+			(self.uppercase, UPPERCASE),
+			(self.digits, DIGITS),
+			(self.symbols, SYMBOLS),
+		]
+		.into_iter()
+		.filter(|(enabled, _)| *enabled)
+		.flat_map(|(_, chars)| chars.iter().copied())
+		.collect()
+	}
+}
+
+/// This defines what kind of password `generate` produces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize),
+	derive(serde::Deserialize)
+)]
+#[cfg_attr(feature = "rspc", derive(rspc::Type))]
+pub enum PasswordPolicy {
+	/// A password of `length` random characters, from the enabled classes.
+	Characters {
+		length: usize,
+		classes: CharacterClasses,
+	},
+	/// A passphrase of `words` random words, joined by `separator`.
+	Passphrase { words: usize, separator: char },
+}
+
+pub struct GeneratedPassword {
+	pub password: Protected<String>,
+	/// How many bits of entropy the password has, given the policy used to generate it.
+	pub entropy: f64,
+}
+
+/// This generates a password that follows the policy, using a CSPRNG.
+///
+/// It fails if the policy would produce an empty password.
+pub fn generate(policy: PasswordPolicy) -> Result<GeneratedPassword> {
+	let mut rng = ChaCha20Rng::from_entropy();
+
+	match policy {
+		PasswordPolicy::Characters { length, classes } => {
+			let alphabet = classes.alphabet();
+			if length == 0 || alphabet.is_empty() {
+				return Err(Error::PasswordPolicy);
+			}
+
+			// The capacity is exact, so the password is never reallocated (and copied) while it's built
+			let mut password = String::with_capacity(length);
+			for _ in 0..length {
+				password.push(char::from(
+					*alphabet.choose(&mut rng).expect("alphabet is empty"),
+				));
+			}
+
+			Ok(GeneratedPassword {
+				password: Protected::new(password),
+				entropy: entropy(alphabet.len(), length),
+			})
+		}
+		PasswordPolicy::Passphrase { words, separator } => {
+			if words == 0 {
+				return Err(Error::PasswordPolicy);
+			}
+
+			let wordlist = WORDLIST.lines().collect::<Vec<_>>();
+
+			let mut password = String::with_capacity(words * (MAX_WORD_LEN + separator.len_utf8()));
+			for i in 0..words {
+				if i != 0 {
+					password.push(separator);
+				}
+				password.push_str(wordlist.choose(&mut rng).expect("wordlist is empty"));
+			}
+
+			Ok(GeneratedPassword {
+				password: Protected::new(password),
+				entropy: entropy(wordlist.len(), words),
+			})
+		}
+	}
+}
+
+/// This rates how hard a password is to guess, from weakest to strongest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize),
+	derive(serde::Deserialize)
+)]
+#[cfg_attr(feature = "rspc", derive(rspc::Type))]
+pub enum Strength {
+	VeryWeak,
+	Weak,
+	Fair,
+	Good,
+	Strong,
+}
+
+impl Strength {
+	/// The rating of a password with `entropy` bits of entropy.
+	fn from_entropy(entropy: f64) -> Self {
+		match entropy {
+			e if e < 28.0 => Self::VeryWeak,
+			e if e < 40.0 => Self::Weak,
+			e if e < 60.0 => Self::Fair,
+			e if e < 80.0 => Self::Good,
+			_ => Self::Strong,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize),
+	derive(serde::Deserialize)
+)]
+#[cfg_attr(feature = "rspc", derive(rspc::Type))]
+pub struct StrengthReport {
+	/// The estimated entropy of the password, in bits.
+	pub entropy: f64,
+	pub rating: Strength,
+}
+
+/// These are some of the most common passwords (and the words they're built from), lowercased.
+///
+/// This isn't meant to be exhaustive - it only has to catch the passwords that guessers try first.
+const COMMON_PASSWORDS: &[&str] = &[
+	"password",
+	"passw0rd",
+	"qwerty",
+	"letmein",
+	"welcome",
+	"admin",
+	"login",
+	"master",
+	"dragon",
+	"monkey",
+	"football",
+	"baseball",
+	"soccer",
+	"hockey",
+	"princess",
+	"sunshine",
+	"shadow",
+	"superman",
+	"batman",
+	"trustno1",
+	"iloveyou",
+	"starwars",
+	"whatever",
+	"freedom",
+	"secret",
+	"hello",
+	"charlie",
+	"michael",
+	"jordan",
+	"jennifer",
+	"thomas",
+	"ashley",
+	"daniel",
+	"summer",
+	"winter",
+	"spring",
+	"autumn",
+	"flower",
+	"cheese",
+	"computer",
+	"internet",
+	"mustang",
+	"access",
+	"ninja",
+	"pokemon",
+	"killer",
+	"hunter",
+	"ranger",
+	"tigger",
+	"buster",
+	"pepper",
+	"ginger",
+	"cookie",
+	"banana",
+	"orange",
+	"purple",
+	"silver",
+	"golden",
+	"lovely",
+	"angel",
+	"spacedrive",
+];
+
+/// These are the keyboard rows that people tend to run along.
+const KEYBOARD_ROWS: &[&str] = &["qwertyuiop", "asdfghjkl", "zxcvbnm", "1234567890"];
+
+/// Runs shorter than this aren't treated as patterns.
+const MIN_PATTERN_LEN: usize = 3;
+
+/// This estimates how strong a password is, in the style of zxcvbn (but much cheaper).
+///
+/// The password is split into common passwords, patterns (repeats, sequences and keyboard runs) and
+/// the characters in between. Common passwords and patterns only count for as much entropy as it takes
+/// to guess them, while everything else counts for as much as a random character.
+///
+/// This is only an estimate - it's meant to warn users about weak passwords, not to prove that a password is strong.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn estimate_strength(password: &str) -> StrengthReport {
+	let chars = password.chars().collect::<Vec<_>>();
+	let lowercase = chars
+		.iter()
+		.map(char::to_ascii_lowercase)
+		.collect::<Vec<_>>();
+	let unleeted = lowercase.iter().map(|c| unleet(*c)).collect::<Vec<_>>();
+
+	let char_entropy = pool_size(&chars).log2();
+
+	let mut entropy = 0.0;
+	let mut i = 0;
+	while i < chars.len() {
+		if let Some(word) = common_password_at(&unleeted, i) {
+			let len = word.len();
+
+			// the rank within the list, plus a bit each for capitalisation and substitutions (that the listed password
+			// doesn't have itself)
+			entropy += (COMMON_PASSWORDS.len() as f64).log2();
+			if chars[i..i + len].iter().any(char::is_ascii_uppercase) {
+				entropy += 1.0;
+			}
+			if lowercase[i..i + len] != word[..] {
+				entropy += 1.0;
+			}
+
+			i += len;
+			continue;
+		}
+
+		let len = pattern_at(&lowercase, i);
+		if len >= MIN_PATTERN_LEN {
+			// the first character, and how far the pattern goes
+			entropy += char_entropy + (len as f64).log2();
+
+			i += len;
+			continue;
+		}
+
+		entropy += char_entropy;
+		i += 1;
+	}
+
+	StrengthReport {
+		entropy,
+		rating: Strength::from_entropy(entropy),
+	}
+}
+
+/// This undoes the common "leetspeak" substitutions, so `p@ssw0rd` is recognised as `password`.
+const fn unleet(c: char) -> char {
+	match c {
+		'@' | '4' => 'a',
+		'3' => 'e',
+		'1' | '!' => 'i',
+		'0' => 'o',
+		'$' | '5' => 's',
+		'7' => 't',
+		_ => c,
+	}
+}
+
+/// The number of characters that a guesser has to try for each character, given the classes used.
+fn pool_size(chars: &[char]) -> f64 {
+	let classes: [(fn(&char) -> bool, f64); 5] = [
+		(char::is_ascii_lowercase, 26.0),
+		(char::is_ascii_uppercase, 26.0),
+		(char::is_ascii_digit, 10.0),
+		(char::is_ascii_punctuation, 33.0),
+		(|c| !c.is_ascii(), 100.0),
+	];
+
+	classes
+		.into_iter()
+		.filter(|(class, _)| chars.iter().any(class))
+		.map(|(_, size)| size)
+		.sum::<f64>()
+		.max(1.0)
+}
+
+/// The longest common password that starts at `i`, as it's listed, if there is one.
+fn common_password_at(unleeted: &[char], i: usize) -> Option<Vec<char>> {
+	COMMON_PASSWORDS
+		.iter()
+		.map(|word| word.chars().collect::<Vec<_>>())
+		.filter(|word| {
+			// the listed passwords have substitutions of their own (e.g. `trustno1`), so they're undone on both sides
+			let word = word.iter().copied().map(unleet).collect::<Vec<_>>();
+			unleeted[i..].starts_with(&word)
+		})
+		.max_by_key(Vec::len)
+}
+
+/// The length of the longest repeat, sequence or keyboard run that starts at `i`.
+fn pattern_at(chars: &[char], i: usize) -> usize {
+	// repeats (e.g. "aaa") and sequences (e.g. "abc" or "321") have a constant step between characters
+	let step = |j: usize| i64::from(u32::from(chars[j + 1])) - i64::from(u32::from(chars[j]));
+	let sequence = if i + 1 < chars.len() && step(i).abs() <= 1 {
+		let first_step = step(i);
+		1 + (i..chars.len() - 1)
+			.take_while(|j| step(*j) == first_step)
+			.count()
+	} else {
+		1
+	};
+
+	let keyboard = KEYBOARD_ROWS
+		.iter()
+		.map(|row| {
+			let row = row.chars().collect::<Vec<_>>();
+			(1..=row.len().min(chars.len() - i))
+				.rev()
+				.find(|&len| row.windows(len).any(|w| w == &chars[i..i + len]))
+				.unwrap_or(0)
+		})
+		.max()
+		.unwrap_or(0);
+
+	sequence.max(keyboard)
+}
+
+/// The entropy of `count` independent and uniform choices out of `choices`, in bits.
+#[allow(clippy::cast_precision_loss)]
+fn entropy(choices: usize, count: usize) -> f64 {
+	// Both are far too small for the conversion to lose any precision
+	count as f64 * (choices as f64).log2()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wordlist() {
+		let wordlist = WORDLIST.lines().collect::<Vec<_>>();
+
+		assert_eq!(wordlist.len(), 7776);
+		assert_eq!(
+			wordlist.iter().map(|word| word.len()).max(),
+			Some(MAX_WORD_LEN)
+		);
+	}
+
+	#[test]
+	fn generate_characters() {
+		let policy = PasswordPolicy::Characters {
+			length: 24,
+			classes: CharacterClasses {
+				symbols: false,
+				..Default::default()
+			},
+		};
+
+		let first = generate(policy).unwrap();
+		let second = generate(policy).unwrap();
+
+		assert_eq!(first.password.expose().len(), 24);
+		assert!(first
+			.password
+			.expose()
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric()));
+		assert_ne!(first.password.expose(), second.password.expose());
+
+		// 62 possible characters
+		assert!((first.entropy - 24.0 * 62f64.log2()).abs() < 1e-9);
+	}
+
+	#[test]
+	fn generate_passphrase() {
+		let policy = PasswordPolicy::Passphrase {
+			words: 6,
+			separator: ' ',
+		};
+
+		let first = generate(policy).unwrap();
+		let second = generate(policy).unwrap();
+
+		assert_eq!(first.password.expose().split(' ').count(), 6);
+		assert!(first
+			.password
+			.expose()
+			.split(' ')
+			.all(|word| WORDLIST.lines().any(|w| w == word)));
+		assert_ne!(first.password.expose(), second.password.expose());
+
+		// 12.9 bits per word
+		assert!((first.entropy - 6.0 * 7776f64.log2()).abs() < 1e-9);
+	}
+
+	#[test]
+	fn estimate_common_password() {
+		let report = estimate_strength("password123");
+
+		assert!(report.rating <= Strength::Weak);
+		assert!(estimate_strength("P@ssw0rd!").rating <= Strength::Weak);
+		assert!(estimate_strength("qwertyuiop").rating <= Strength::Weak);
+
+		// listed passwords with digits in them are recognised as they are, and with other substitutions
+		for password in ["trustno1", "passw0rd", "TrustNo1", "pa$$w0rd"] {
+			assert!(
+				estimate_strength(password).rating <= Strength::Weak,
+				"{password}"
+			);
+		}
+		// typed as it's listed, it's just one guess from the list
+		let listed = (COMMON_PASSWORDS.len() as f64).log2();
+		assert!((estimate_strength("trustno1").entropy - listed).abs() < 1e-9);
+	}
+
+	#[test]
+	fn estimate_random_password() {
+		let generated = generate(PasswordPolicy::Characters {
+			length: 32,
+			classes: CharacterClasses::default(),
+		})
+		.unwrap();
+
+		let report = estimate_strength(generated.password.expose());
+
+		assert_eq!(report.rating, Strength::Strong);
+		assert_eq!(estimate_strength("").rating, Strength::VeryWeak);
+	}
+
+	#[test]
+	#[should_panic(expected = "PasswordPolicy")]
+	fn generate_with_no_characters() {
+		generate(PasswordPolicy::Characters {
+			length: 16,
+			classes: CharacterClasses {
+				lowercase: false,
+				uppercase: false,
+				digits: false,
+				symbols: false,
+			},
+		})
+		.unwrap();
+	}
+}
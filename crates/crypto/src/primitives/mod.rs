@@ -35,6 +35,8 @@ pub const ENCRYPTED_KEY_LEN: usize = 48;
 /// The length of plain master/hashed keys
 pub const KEY_LEN: usize = 32;
 
+const _: () = assert!(ENCRYPTED_KEY_LEN == KEY_LEN + AEAD_TAG_LEN);
+
 /// Used for OS keyrings to identify our items.
 pub const APP_IDENTIFIER: &str = "Spacedrive";
 
@@ -42,10 +44,10 @@ pub const APP_IDENTIFIER: &str = "Spacedrive";
 pub const SECRET_KEY_IDENTIFIER: &str = "Secret key";
 
 /// Defines the latest `FileHeaderVersion`
-pub const LATEST_FILE_HEADER: FileHeaderVersion = FileHeaderVersion::V1;
+pub const LATEST_FILE_HEADER: FileHeaderVersion = FileHeaderVersion::V5;
 
 /// Defines the latest `KeyslotVersion`
-pub const LATEST_KEYSLOT: KeyslotVersion = KeyslotVersion::V1;
+pub const LATEST_KEYSLOT: KeyslotVersion = KeyslotVersion::V2;
 
 /// Defines the latest `MetadataVersion`
 pub const LATEST_METADATA: MetadataVersion = MetadataVersion::V1;
@@ -66,6 +68,31 @@ pub const MASTER_PASSWORD_CONTEXT: &str =
 /// Defines the context string for BLAKE3-KDF in regards to file key derivation (for file encryption)
 pub const FILE_KEY_CONTEXT: &str = "spacedrive 2022-12-14 12:54:12 file key derivation";
 
+/// Defines the context string for BLAKE3-KDF in regards to keyfile key derivation (for keyslots that are unlocked with a keyfile)
+pub const KEYFILE_CONTEXT: &str = "spacedrive 2023-03-10 14:06:22 keyfile key derivation";
+
+/// Defines the context string for BLAKE3-KDF in regards to hardware token key derivation (for keyslots that are unlocked with a PKCS#11 token)
+pub const HARDWARE_TOKEN_CONTEXT: &str =
+	"spacedrive 2023-03-15 11:08:36 hardware token key derivation";
+
+/// Defines the context string for BLAKE3-KDF in regards to the master key commitment (stored in the file header)
+pub const KEY_COMMITMENT_CONTEXT: &str = "spacedrive 2023-03-01 10:12:31 master key commitment";
+
+/// Defines the context string for BLAKE3-KDF in regards to block group key derivation (for rekeyed streams)
+pub const BLOCK_GROUP_KEY_CONTEXT: &str =
+	"spacedrive 2023-03-03 16:02:47 block group key derivation";
+
+/// Defines the context string for BLAKE3-KDF in regards to choosing a master key's body (for files with a decoy)
+pub const DECOY_BODY_CONTEXT: &str = "spacedrive 2023-03-04 11:27:05 decoy body selection";
+
+/// Defines the context string for BLAKE3-KDF in regards to the key that an encrypted log's checkpoints are authenticated with
+pub const LOG_CHECKPOINT_CONTEXT: &str =
+	"spacedrive 2023-03-06 10:18:52 log checkpoint key derivation";
+
+/// Defines the context string for BLAKE3-KDF in regards to the key of each segment of an encrypted log
+pub const LOG_SEGMENT_KEY_CONTEXT: &str =
+	"spacedrive 2023-03-06 10:18:52 log segment key derivation";
+
 /// This is used for converting a `&[u8]` to an array of bytes.
 ///
 /// It does `Clone`, with `to_vec()`.
@@ -77,3 +104,13 @@ pub fn to_array<const I: usize>(bytes: &[u8]) -> Result<[u8; I]> {
 		Error::VecArrSizeMismatch
 	})
 }
+
+/// Defines the context string for BLAKE3-KDF in regards to a library's dedup key (derived from the root key)
+pub const DEDUP_KEY_CONTEXT: &str = "spacedrive 2023-03-11 10:02:37 dedup key derivation";
+
+/// Defines the context string for BLAKE3-KDF in regards to the key that a file's plaintext commitment is hashed with
+pub const PLAINTEXT_COMMITMENT_CONTEXT: &str =
+	"spacedrive 2023-03-11 10:04:15 plaintext commitment key derivation";
+
+/// Defines the context string for BLAKE3-KDF in regards to the 128-bit key that `Aes128Gcm` is initialised with
+pub const AES_128_KEY_CONTEXT: &str = "spacedrive 2023-03-14 16:20:48 aes-128 key derivation";
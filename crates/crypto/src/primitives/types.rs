@@ -14,6 +14,8 @@ use serde_big_array::BigArray;
 /// This should be used for providing a nonce to encrypt/decrypt functions.
 ///
 /// You may also generate a nonce for a given algorithm with `Nonce::generate()`
+///
+/// Nonces are told apart by their length, so `Aes128Gcm` nonces are `Nonce::Aes256Gcm` too.
 #[derive(Clone, Copy, Eq, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "rspc", derive(rspc::Type))]
@@ -67,7 +67,8 @@ extension_category_enum! {
 		Jpg = [0xFF, 0xD8],
 		Jpeg = [0xFF, 0xD8],
 		Png = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A],
-		Apng = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52],
+		// an APNG starts exactly like any other PNG, its animation control chunk isn't at a fixed offset
+		Apng = [],
 		Gif = [0x47, 0x49, 0x46, 0x38, _, 0x61],
 		Bmp = [0x42, 0x4D],
 		Tiff = [0x49, 0x49, 0x2A, 0x00],
@@ -1,10 +1,40 @@
 /// Object Kind
 ///
+use std::{collections::HashMap, ffi::OsStr, path::Path};
+
 use int_enum::IntEnum;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use tokio::io;
+
+use crate::{
+	extensions::Extension,
+	magic::{read_header, ExtensionPossibility},
+};
+
+/// The version of the mapping from extensions and magic bytes to an `ObjectKind`.
+///
+/// This must be bumped whenever that mapping changes (e.g. a new extension or variant is added),
+/// so objects that were identified by an older version get re-identified.
+pub const CLASSIFIER_VERSION: i32 = 2;
 
+/// Displayed as a stable snake_case identifier (e.g. `web_page_archive`), which `FromStr` parses back,
+/// so kinds can be passed around as strings (e.g. for filtering in the UI) without using their numbers.
 #[repr(i32)]
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, IntEnum)]
+#[derive(
+	Debug,
+	Clone,
+	Copy,
+	Serialize,
+	Deserialize,
+	Eq,
+	PartialEq,
+	Hash,
+	IntEnum,
+	strum::Display,
+	strum::EnumString,
+)]
+#[strum(serialize_all = "snake_case")]
 pub enum ObjectKind {
 	// A file that can not be identified by the indexer
 	Unknown = 0,
@@ -14,7 +44,7 @@ pub enum ObjectKind {
 	Folder = 2,
 	// A file that contains human-readable text
 	Text = 3,
-	// A virtual directory int
+	// A directory that is presented as a single file, e.g. a macOS app bundle
 	Package = 4,
 	// An image file
 	Image = 5,
@@ -51,3 +81,245 @@ pub enum ObjectKind {
 	// Database file
 	Database = 21,
 }
+
+impl ObjectKind {
+	/// The kind of a file with this extension, without looking at its contents.
+	///
+	/// Extensions that are shared by more than one kind (e.g. `ts` for videos and TypeScript) are ambiguous,
+	/// so `None` is returned for them, as only the magic bytes can tell them apart.
+	pub fn from_extension(extension: &str) -> Option<Self> {
+		match Extension::from_str(extension)? {
+			ExtensionPossibility::Known(ext) => Some(ext.into()),
+			ExtensionPossibility::Conflicts(exts) => {
+				let mut kinds = exts.into_iter().map(Self::from);
+				let first = kinds.next()?;
+				kinds.all(|kind| kind == first).then_some(first)
+			}
+		}
+	}
+
+	/// The kind of a file whose first bytes are `header`, from the magic bytes of every known extension.
+	///
+	/// Signatures that are shared by more than one kind (e.g. ZIP, which documents and packages are built on)
+	/// are ambiguous, so `None` is returned for them, as well as for headers without any known signature.
+	pub fn from_magic_bytes(header: &[u8]) -> Option<Self> {
+		let mut kinds = Extension::from_magic_bytes(header)
+			.into_iter()
+			.map(Self::from);
+		let first = kinds.next()?;
+		kinds.all(|kind| kind == first).then_some(first)
+	}
+
+	/// The kind of a file with this extension whose first bytes are `header`.
+	///
+	/// The extension is trusted when it's enough on its own, and the magic bytes are checked otherwise
+	/// (e.g. when the extension is missing, unknown or ambiguous). Files that can't be identified either way are `Unknown`.
+	pub fn identify(extension: Option<&str>, header: &[u8]) -> Self {
+		if let Some(kind) = extension.and_then(Self::from_extension) {
+			return kind;
+		}
+
+		if let Some(kind) = Self::from_magic_bytes(header) {
+			return kind;
+		}
+
+		// an ambiguous extension without a signature in the header can only be one of its candidates that doesn't have one
+		let exts = match extension.and_then(Extension::from_str) {
+			Some(ExtensionPossibility::Conflicts(exts)) => exts,
+			_ => return Self::Unknown,
+		};
+
+		let mut kinds = exts
+			.into_iter()
+			.filter(|ext| ext.magic_bytes_meta().iter().all(|magic| magic.length == 0))
+			.map(Self::from);
+		match kinds.next() {
+			Some(first) if kinds.all(|kind| kind == first) => first,
+			_ => Self::Unknown,
+		}
+	}
+
+	/// Identifies the kind of the file at `path`, from its extension and first bytes.
+	pub async fn from_path(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+		let path = path.as_ref();
+		let header = read_header(path).await?;
+
+		Ok(Self::identify(
+			path.extension().and_then(OsStr::to_str),
+			&header,
+		))
+	}
+
+	/// The extensions that `from_extension()` maps to this kind, for filtering by kind.
+	///
+	/// Ambiguous extensions aren't listed under any kind.
+	pub fn extensions(&self) -> &'static [&'static str] {
+		static EXTENSIONS: Lazy<HashMap<ObjectKind, Vec<&'static str>>> = Lazy::new(|| {
+			let mut extensions = HashMap::<_, Vec<_>>::new();
+			for ext in Extension::all_strs() {
+				if let Some(kind) = ObjectKind::from_extension(ext) {
+					let kind_extensions = extensions.entry(kind).or_default();
+					if !kind_extensions.contains(&ext) {
+						kind_extensions.push(ext);
+					}
+				}
+			}
+			extensions
+		});
+
+		EXTENSIONS.get(self).map_or(&[], Vec::as_slice)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn kinds_are_stored_as_stable_integers() {
+		// these are persisted in the database, so they must never be renumbered -
+		// this match stops compiling if a kind is added without being given its number here
+		fn frozen_id(kind: ObjectKind) -> i32 {
+			match kind {
+				ObjectKind::Unknown => 0,
+				ObjectKind::Document => 1,
+				ObjectKind::Folder => 2,
+				ObjectKind::Text => 3,
+				ObjectKind::Package => 4,
+				ObjectKind::Image => 5,
+				ObjectKind::Audio => 6,
+				ObjectKind::Video => 7,
+				ObjectKind::Archive => 8,
+				ObjectKind::Executable => 9,
+				ObjectKind::Alias => 10,
+				ObjectKind::Encrypted => 11,
+				ObjectKind::Key => 12,
+				ObjectKind::Link => 13,
+				ObjectKind::WebPageArchive => 14,
+				ObjectKind::Widget => 15,
+				ObjectKind::Album => 16,
+				ObjectKind::Collection => 17,
+				ObjectKind::Font => 18,
+				ObjectKind::Mesh => 19,
+				ObjectKind::Code => 20,
+				ObjectKind::Database => 21,
+			}
+		}
+
+		for value in 0..=21 {
+			let kind = ObjectKind::from_int(value).unwrap();
+			assert_eq!(kind.int_value(), value);
+			assert_eq!(frozen_id(kind), value);
+		}
+
+		assert!(ObjectKind::from_int(22).is_err());
+		assert!(ObjectKind::from_int(-1).is_err());
+	}
+
+	#[test]
+	fn kinds_round_trip_through_strings() {
+		for value in 0..=21 {
+			let kind = ObjectKind::from_int(value).unwrap();
+			let name = kind.to_string();
+
+			assert!(
+				name.chars().all(|c| c.is_ascii_lowercase() || c == '_'),
+				"{name}"
+			);
+			assert_eq!(name.parse::<ObjectKind>(), Ok(kind));
+		}
+
+		assert_eq!(ObjectKind::Text.to_string(), "text");
+		assert_eq!(ObjectKind::WebPageArchive.to_string(), "web_page_archive");
+		assert!("WebPageArchive".parse::<ObjectKind>().is_err());
+		assert!("jeff".parse::<ObjectKind>().is_err());
+	}
+
+	#[test]
+	fn extensions_map_back_to_their_kind() {
+		for value in 0..=21 {
+			let kind = ObjectKind::from_int(value).unwrap();
+			for ext in kind.extensions() {
+				assert_eq!(ObjectKind::from_extension(ext), Some(kind), "{ext}");
+			}
+		}
+
+		assert!(ObjectKind::Image.extensions().contains(&"jpg"));
+		assert!(ObjectKind::Video.extensions().contains(&"3gp"));
+		assert!(ObjectKind::Folder.extensions().is_empty());
+	}
+
+	#[test]
+	fn kind_from_magic_bytes() {
+		assert_eq!(
+			ObjectKind::from_magic_bytes(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+			Some(ObjectKind::Image)
+		);
+		assert_eq!(
+			ObjectKind::from_magic_bytes(b"%PDF-1.7"),
+			Some(ObjectKind::Document)
+		);
+		// shared by archives, documents and executables
+		assert_eq!(ObjectKind::from_magic_bytes(b"PK\x03\x04"), None);
+		assert_eq!(ObjectKind::from_magic_bytes(b"hello"), None);
+	}
+
+	#[test]
+	fn kind_from_extension_and_header() {
+		const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+		let cases: &[(Option<&str>, &[u8], ObjectKind)] = &[
+			(Some("png"), b"", ObjectKind::Image),
+			(Some("jpg"), b"", ObjectKind::Image),
+			(Some("gif"), b"", ObjectKind::Image),
+			(Some("svg"), b"", ObjectKind::Image),
+			(Some("mp4"), b"", ObjectKind::Video),
+			(Some("mov"), b"", ObjectKind::Video),
+			(Some("mp3"), b"", ObjectKind::Audio),
+			(Some("flac"), b"", ObjectKind::Audio),
+			(Some("zip"), b"", ObjectKind::Archive),
+			(Some("7z"), b"", ObjectKind::Archive),
+			(Some("exe"), b"", ObjectKind::Executable),
+			(Some("pdf"), b"", ObjectKind::Document),
+			(Some("txt"), b"", ObjectKind::Text),
+			(Some("md"), b"", ObjectKind::Text),
+			(Some("json"), b"", ObjectKind::Text),
+			(Some("ttf"), b"", ObjectKind::Font),
+			(Some("rs"), b"", ObjectKind::Code),
+			(Some("sqlite"), b"", ObjectKind::Database),
+			// the extension is trusted when it's enough on its own
+			(Some("jpg"), b"%PDF-1.7", ObjectKind::Image),
+			// otherwise the magic bytes are used
+			(None, PNG, ObjectKind::Image),
+			(Some("jeff"), b"%PDF-1.7", ObjectKind::Document),
+			// `ts` is either a video or TypeScript, so the contents decide
+			(Some("ts"), b"G\x40\x11\x10", ObjectKind::Video),
+			(Some("ts"), b"export {}", ObjectKind::Code),
+			(None, b"hello", ObjectKind::Unknown),
+			(Some("jeff"), b"", ObjectKind::Unknown),
+		];
+
+		for (extension, header, kind) in cases {
+			assert_eq!(
+				ObjectKind::identify(*extension, header),
+				*kind,
+				"{extension:?}"
+			);
+		}
+	}
+
+	#[tokio::test]
+	async fn kind_from_path() {
+		let path = std::env::temp_dir().join(format!("sd-file-ext-kind-{}", std::process::id()));
+		tokio::fs::write(&path, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")
+			.await
+			.unwrap();
+
+		// without an extension, only the contents can identify it
+		let kind = ObjectKind::from_path(&path).await;
+		tokio::fs::remove_file(&path).await.unwrap();
+		assert_eq!(kind.unwrap(), ObjectKind::Image);
+
+		assert!(ObjectKind::from_path(&path).await.is_err());
+	}
+}
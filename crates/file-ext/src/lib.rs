@@ -1,3 +1,4 @@
 pub mod extensions;
 pub mod kind;
 pub mod magic;
+pub mod mime;
@@ -5,7 +5,7 @@ use std::{ffi::OsStr, io::SeekFrom, path::Path};
 
 use tokio::{
 	fs::File,
-	io::{AsyncReadExt, AsyncSeekExt},
+	io::{self, AsyncReadExt, AsyncSeekExt},
 };
 
 #[derive(Debug, PartialEq, Eq)]
@@ -21,10 +21,41 @@ pub struct MagicBytesMeta {
 }
 
 pub trait MagicBytes: Sized + PartialEq {
+	/// Whether `buf` is exactly one of this extension's magic bytes signatures
 	fn has_magic_bytes(&self, buf: &[u8]) -> bool;
 	fn magic_bytes_meta(&self) -> Vec<MagicBytesMeta>;
 }
 
+/// How many bytes from the start of a file are enough to check every known magic bytes signature
+pub const MAGIC_BYTES_HEADER_LEN: usize = 64;
+
+/// Reads up to `MAGIC_BYTES_HEADER_LEN` bytes from the start of the file at `path`
+pub async fn read_header(path: &Path) -> Result<Vec<u8>, io::Error> {
+	let mut header = Vec::with_capacity(MAGIC_BYTES_HEADER_LEN);
+	File::open(path)
+		.await?
+		.take(MAGIC_BYTES_HEADER_LEN as u64)
+		.read_to_end(&mut header)
+		.await?;
+
+	Ok(header)
+}
+
+/// The length of the longest magic bytes of `ext` that are in `header` (the first bytes of a file), if any are
+pub fn matching_magic_bytes_len<T: MagicBytes>(ext: &T, header: &[u8]) -> Option<usize> {
+	ext.magic_bytes_meta()
+		.into_iter()
+		// an empty signature would match anything
+		.filter(|magic| magic.length > 0)
+		.filter(|magic| {
+			header
+				.get(magic.offset..magic.offset + magic.length)
+				.map_or(false, |buf| ext.has_magic_bytes(buf))
+		})
+		.map(|magic| magic.length)
+		.max()
+}
+
 #[macro_export]
 macro_rules! magic_byte_value {
 	(_) => {
@@ -75,6 +106,41 @@ macro_rules! extension_enum {
 					_ => Some(ExtensionPossibility::Conflicts(exts))
 				}
 			}
+
+			/// Every known extension, as it's written in file names (the same extension may be in more than one category)
+			pub fn all_strs() -> impl Iterator<Item = &'static str> {
+				::std::iter::empty()
+					$( .chain($type::all().iter().map(<&'static str>::from)) )*
+			}
+
+			/// The extensions with the longest (so most specific) magic bytes in `header` (the first bytes of a file),
+			/// in the order they're declared
+			pub fn from_magic_bytes(header: &[u8]) -> Vec<Self> {
+				let matches = ::std::iter::empty()
+					$( .chain($type::all().iter().map(|ext| Self::$variant(*ext))) )*
+					.filter_map(|ext| Some((ext.matching_magic_bytes_len(header)?, ext)))
+					.collect::<Vec<_>>();
+
+				let longest = matches.iter().map(|(len, _)| *len).max();
+				matches
+					.into_iter()
+					.filter(|(len, _)| Some(*len) == longest)
+					.map(|(_, ext)| ext)
+					.collect()
+			}
+
+			/// The length of the longest of this extension's magic bytes that are in `header`, if any are
+			pub fn matching_magic_bytes_len(&self, header: &[u8]) -> Option<usize> {
+				match self {
+					$( Extension::$variant(x) => $crate::magic::matching_magic_bytes_len(x, header), )*
+				}
+			}
+
+			pub fn magic_bytes_meta(&self) -> Vec<MagicBytesMeta> {
+				match self {
+					$( Extension::$variant(x) => x.magic_bytes_meta(), )*
+				}
+			}
 		}
 		// convert Extension to ObjectKind
 		impl From<Extension> for $crate::kind::ObjectKind {
@@ -105,7 +171,7 @@ macro_rules! extension_category_enum {
 			$($(#[$variant_attr:meta])* $variant:ident $(= $( [$($magic_bytes:tt),*] $(+ $offset:literal)? )|+ )? ,)*
 		}
 	) => {
-		#[derive(Debug, ::serde::Serialize, ::serde::Deserialize, ::strum::Display, Clone, Copy, PartialEq, Eq)]
+		#[derive(Debug, ::serde::Serialize, ::serde::Deserialize, ::strum::Display, ::strum::IntoStaticStr, Clone, Copy, PartialEq, Eq)]
 		#[serde(rename_all = "snake_case")]
 		#[strum(serialize_all = "snake_case")]
 		$(#[$enum_attr])*
@@ -119,6 +185,12 @@ macro_rules! extension_category_enum {
 			$( $enum_name::$variant, )*
 		];
 
+		impl $enum_name {
+			pub fn all() -> &'static [Self] {
+				$static_array_name
+			}
+		}
+
 		$crate::magic::extension_category_enum!(@magic_bytes; $enum_name ( $($(#[$variant_attr])* $variant $(= $( [$($magic_bytes),*] $(+ $offset)? )|+ )? ),* ));
 
 		// convert from string
@@ -134,7 +206,8 @@ macro_rules! extension_category_enum {
 		impl MagicBytes for $enum_name {
 			fn has_magic_bytes(&self, buf: &[u8]) -> bool {
 				match (self, buf) {
-					$( $( ($enum_name::$variant, &[$($magic_bytes,)* ..]) => true, )+ )*
+					// the patterns aren't open-ended, so a short signature can't match a longer one's bytes
+					$( $( ($enum_name::$variant, &[$($magic_bytes),*]) => true, )+ )*
 					_ => false
 				}
 			}
@@ -151,7 +224,16 @@ macro_rules! extension_category_enum {
 			}
 		}
 	};
-	(@magic_bytes; $enum_name:ident ($($(#[$variant_attr:meta])* $variant:ident),*)) => {};
+	(@magic_bytes; $enum_name:ident ($($(#[$variant_attr:meta])* $variant:ident),*)) => {
+		impl MagicBytes for $enum_name {
+			fn has_magic_bytes(&self, _buf: &[u8]) -> bool {
+				false
+			}
+			fn magic_bytes_meta(&self) -> Vec<MagicBytesMeta> {
+				vec![]
+			}
+		}
+	};
 }
 pub(crate) use extension_category_enum;
 
@@ -0,0 +1,287 @@
+/// MIME types
+///
+/// References:
+/// https://www.iana.org/assignments/media-types/media-types.xhtml
+/// https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types/Common_types
+///
+use std::{ffi::OsStr, path::Path};
+
+use tokio::io;
+
+use crate::{
+	extensions::{
+		ArchiveExtension, AudioExtension, CodeExtension, DatabaseExtension, DocumentExtension,
+		ExecutableExtension, Extension, FontExtension, ImageExtension, KeyExtension, MeshExtension,
+		TextExtension, VideoExtension,
+	},
+	magic::{read_header, ExtensionPossibility},
+};
+
+/// The MIME type of arbitrary binary data, for files that we can't identify
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+impl Extension {
+	/// The MIME type of files with this extension, if there's one that's commonly used for it.
+	pub fn mime(&self) -> Option<&'static str> {
+		let mime = match self {
+			Self::Video(ext) => match ext {
+				VideoExtension::Avi => "video/x-msvideo",
+				VideoExtension::Qt | VideoExtension::Mov => "video/quicktime",
+				VideoExtension::Swf => "application/x-shockwave-flash",
+				VideoExtension::Mjpeg => "video/x-motion-jpeg",
+				VideoExtension::Ts | VideoExtension::Mts | VideoExtension::M2ts => "video/mp2t",
+				VideoExtension::Mpeg
+				| VideoExtension::Mpg
+				| VideoExtension::Mpe
+				| VideoExtension::M2v
+				| VideoExtension::Vob => "video/mpeg",
+				VideoExtension::Mxf => "application/mxf",
+				VideoExtension::Flv => "video/x-flv",
+				VideoExtension::Wm | VideoExtension::Asf => "video/x-ms-asf",
+				VideoExtension::_3gp => "video/3gpp",
+				VideoExtension::M4v => "video/x-m4v",
+				VideoExtension::Wmv => "video/x-ms-wmv",
+				VideoExtension::Mp4 | VideoExtension::F4v => "video/mp4",
+				VideoExtension::Webm => "video/webm",
+				VideoExtension::Mkv => "video/x-matroska",
+				VideoExtension::Ogv => "video/ogg",
+				VideoExtension::Hevc => "video/h265",
+				VideoExtension::Wtv => return None,
+			},
+			Self::Image(ext) => match ext {
+				ImageExtension::Jpg | ImageExtension::Jpeg => "image/jpeg",
+				ImageExtension::Png => "image/png",
+				ImageExtension::Apng => "image/apng",
+				ImageExtension::Gif => "image/gif",
+				ImageExtension::Bmp => "image/bmp",
+				ImageExtension::Tiff => "image/tiff",
+				ImageExtension::Webp => "image/webp",
+				ImageExtension::Svg => "image/svg+xml",
+				ImageExtension::Ico => "image/vnd.microsoft.icon",
+				ImageExtension::Heic => "image/heic",
+				ImageExtension::Dng => "image/x-adobe-dng",
+				ImageExtension::Cr2 => "image/x-canon-cr2",
+				ImageExtension::Nef => "image/x-nikon-nef",
+				ImageExtension::Dcr => "image/x-kodak-dcr",
+				ImageExtension::Raw | ImageExtension::Akw | ImageExtension::Nwr => return None,
+			},
+			Self::Audio(ext) => match ext {
+				AudioExtension::Mp3 | AudioExtension::Mp2 => "audio/mpeg",
+				AudioExtension::M4a => "audio/mp4",
+				AudioExtension::Wav => "audio/wav",
+				AudioExtension::Aiff | AudioExtension::Aif => "audio/aiff",
+				AudioExtension::Flac => "audio/flac",
+				AudioExtension::Ogg | AudioExtension::Oga => "audio/ogg",
+				AudioExtension::Opus => "audio/opus",
+				AudioExtension::Wma => "audio/x-ms-wma",
+				AudioExtension::Amr => "audio/amr",
+				AudioExtension::Aac | AudioExtension::Adts => "audio/aac",
+				AudioExtension::Wv => "audio/wavpack",
+				AudioExtension::Voc => "audio/x-voc",
+				AudioExtension::Tta => "audio/x-tta",
+				AudioExtension::Loas => "audio/usac",
+				AudioExtension::Caf => "audio/x-caf",
+				AudioExtension::Aptx | AudioExtension::Ast => return None,
+			},
+			Self::Archive(ext) => match ext {
+				ArchiveExtension::Zip => "application/zip",
+				ArchiveExtension::Rar => "application/vnd.rar",
+				ArchiveExtension::Tar => "application/x-tar",
+				ArchiveExtension::Gz => "application/gzip",
+				ArchiveExtension::Bz2 => "application/x-bzip2",
+				ArchiveExtension::_7z => "application/x-7z-compressed",
+				ArchiveExtension::Xz => "application/x-xz",
+			},
+			Self::Executable(ext) => match ext {
+				ExecutableExtension::Exe => "application/vnd.microsoft.portable-executable",
+				ExecutableExtension::Apk => "application/vnd.android.package-archive",
+				ExecutableExtension::Deb => "application/vnd.debian.binary-package",
+				ExecutableExtension::Dmg => "application/x-apple-diskimage",
+				ExecutableExtension::Rpm => "application/x-rpm",
+				ExecutableExtension::Msi => "application/x-msi",
+				ExecutableExtension::Jar => "application/java-archive",
+				ExecutableExtension::Bat => "application/x-bat",
+				ExecutableExtension::App | ExecutableExtension::Pkg => return None,
+			},
+			Self::Document(ext) => match ext {
+				DocumentExtension::Pdf => "application/pdf",
+				DocumentExtension::Key => "application/vnd.apple.keynote",
+				DocumentExtension::Pages => "application/vnd.apple.pages",
+				DocumentExtension::Numbers => "application/vnd.apple.numbers",
+				DocumentExtension::Doc => "application/msword",
+				DocumentExtension::Docx => {
+					"application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+				}
+				DocumentExtension::Xls => "application/vnd.ms-excel",
+				DocumentExtension::Xlsx => {
+					"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+				}
+				DocumentExtension::Ppt => "application/vnd.ms-powerpoint",
+				DocumentExtension::Pptx => {
+					"application/vnd.openxmlformats-officedocument.presentationml.presentation"
+				}
+				DocumentExtension::Odt => "application/vnd.oasis.opendocument.text",
+				DocumentExtension::Ods => "application/vnd.oasis.opendocument.spreadsheet",
+				DocumentExtension::Odp => "application/vnd.oasis.opendocument.presentation",
+				DocumentExtension::Ics => "text/calendar",
+				DocumentExtension::Hwp => "application/x-hwp",
+			},
+			Self::Text(ext) => match ext {
+				TextExtension::Txt | TextExtension::Cfg => "text/plain",
+				TextExtension::Rtf => "application/rtf",
+				TextExtension::Md => "text/markdown",
+				TextExtension::Json => "application/json",
+				TextExtension::Yaml | TextExtension::Yml => "application/yaml",
+				TextExtension::Toml => "application/toml",
+				TextExtension::Xml => "application/xml",
+				TextExtension::Csv => "text/csv",
+			},
+			// Spacedrive's own formats don't have a registered type
+			Self::Encrypted(_) => return None,
+			Self::Key(ext) => match ext {
+				KeyExtension::Pgp => "application/pgp-encrypted",
+				KeyExtension::Pub => "application/x-ssh-public-key",
+				KeyExtension::Pem => "application/x-pem-file",
+				KeyExtension::P12 => "application/x-pkcs12",
+				KeyExtension::P8 => "application/pkcs8",
+				KeyExtension::Keychain => return None,
+			},
+			Self::Font(ext) => match ext {
+				FontExtension::Ttf => "font/ttf",
+				FontExtension::Otf => "font/otf",
+				FontExtension::Woff => "font/woff",
+				FontExtension::Woff2 => "font/woff2",
+			},
+			Self::Mesh(ext) => match ext {
+				MeshExtension::Fbx => "application/vnd.autodesk.fbx",
+				MeshExtension::Obj => "model/obj",
+			},
+			Self::Code(ext) => match ext {
+				CodeExtension::Ts | CodeExtension::Tsx => "text/typescript",
+				CodeExtension::Js | CodeExtension::Jsx => "text/javascript",
+				CodeExtension::Html => "text/html",
+				CodeExtension::Css => "text/css",
+				CodeExtension::Php => "application/x-httpd-php",
+				CodeExtension::Py => "text/x-python",
+				CodeExtension::Rb => "text/x-ruby",
+				CodeExtension::Sh | CodeExtension::Bash | CodeExtension::Zsh => "application/x-sh",
+				CodeExtension::C | CodeExtension::H => "text/x-c",
+				CodeExtension::Cpp | CodeExtension::Hpp => "text/x-c++",
+				CodeExtension::Java => "text/x-java",
+				CodeExtension::Go => "text/x-go",
+				CodeExtension::Rs => "text/rust",
+				CodeExtension::Mdx => "text/mdx",
+				// the rest are plain text, there's just no type for them
+				_ => "text/plain",
+			},
+			Self::Database(DatabaseExtension::Sqlite) => "application/vnd.sqlite3",
+		};
+
+		Some(mime)
+	}
+}
+
+/// The MIME type of a file with this extension whose first bytes are `header`.
+///
+/// Magic bytes are preferred over the extension, but when they're shared by more than one extension
+/// (e.g. ZIP, which documents and packages are built on) the extension is what picks between them.
+/// Files that can't be identified either way are `OCTET_STREAM`.
+pub fn identify(extension: Option<&str>, header: &[u8]) -> &'static str {
+	let by_extension = match extension.and_then(Extension::from_str) {
+		Some(ExtensionPossibility::Known(ext)) => vec![ext],
+		Some(ExtensionPossibility::Conflicts(exts)) => exts,
+		None => vec![],
+	};
+
+	let by_magic_bytes = Extension::from_magic_bytes(header);
+
+	let candidates = if by_magic_bytes.is_empty() {
+		// an extension with magic bytes would've been recognised by them, so the file isn't really one
+		by_extension
+			.into_iter()
+			.filter(|ext| ext.magic_bytes_meta().iter().all(|magic| magic.length == 0))
+			.collect()
+	} else if by_extension.iter().any(|ext| by_magic_bytes.contains(ext)) {
+		by_extension
+			.into_iter()
+			.filter(|ext| by_magic_bytes.contains(ext))
+			.collect()
+	} else {
+		by_magic_bytes
+	};
+
+	// it's only a match if the candidates agree on it
+	let mut mimes = candidates.iter().map(Extension::mime);
+	match mimes.next() {
+		Some(Some(first)) if mimes.all(|mime| mime == Some(first)) => first,
+		_ => OCTET_STREAM,
+	}
+}
+
+/// Identifies the MIME type of the file at `path`, from its extension and first bytes.
+pub async fn identify_file(path: impl AsRef<Path>) -> Result<&'static str, io::Error> {
+	let path = path.as_ref();
+	let header = read_header(path).await?;
+
+	Ok(identify(path.extension().and_then(OsStr::to_str), &header))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::magic::MAGIC_BYTES_HEADER_LEN;
+
+	use super::*;
+
+	const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0";
+
+	#[test]
+	fn mime_from_magic_bytes() {
+		assert_eq!(identify(None, PNG), "image/png");
+		// the contents are trusted over a misleading extension
+		assert_eq!(identify(Some("jpg"), PNG), "image/png");
+
+		assert_eq!(
+			identify(None, b"\xde\xad\xbe\xef\x13\x37\xc0\xff\xee\x00\x42"),
+			OCTET_STREAM
+		);
+		assert_eq!(identify(None, b""), OCTET_STREAM);
+	}
+
+	#[test]
+	fn mime_from_extension() {
+		// text has no magic bytes
+		assert_eq!(identify(Some("md"), b"# Spacedrive"), "text/markdown");
+		// but images do, so this isn't one
+		assert_eq!(identify(Some("png"), b"# Spacedrive"), OCTET_STREAM);
+
+		// ZIP's magic bytes are shared, so the extension picks between them
+		assert_eq!(identify(Some("zip"), b"PK\x03\x04"), "application/zip");
+		assert_eq!(
+			identify(Some("docx"), b"PK\x03\x04"),
+			"application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+		);
+		assert_eq!(identify(None, b"PK\x03\x04"), OCTET_STREAM);
+
+		// `ts` is either a video or TypeScript, so the contents decide
+		assert_eq!(identify(Some("ts"), b"G\x40\x11\x10"), "video/mp2t");
+		assert_eq!(identify(Some("ts"), b"export {}"), "text/typescript");
+	}
+
+	#[test]
+	fn signatures_fit_in_header() {
+		let exts = Extension::all_strs().flat_map(|ext| match Extension::from_str(ext) {
+			Some(ExtensionPossibility::Known(ext)) => vec![ext],
+			Some(ExtensionPossibility::Conflicts(exts)) => exts,
+			None => vec![],
+		});
+
+		for ext in exts {
+			for magic in ext.magic_bytes_meta() {
+				assert!(
+					magic.offset + magic.length <= MAGIC_BYTES_HEADER_LEN,
+					"{ext}"
+				);
+			}
+		}
+	}
+}
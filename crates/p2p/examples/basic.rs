@@ -32,6 +32,7 @@ pub fn main() {}
 // 			name: self.peer_name.clone(),
 // 			version: Some(env!("CARGO_PKG_VERSION").into()),
 // 			operating_system: todo!(),
+// 			records: HashMap::new(),
 // 		}
 // 	}
 
@@ -87,6 +88,7 @@ pub fn main() {}
 // 			known_peers: Default::default(),
 // 			listen_port: None,
 // 			spacetunnel_url: Some(String::new()),
+// 			..Default::default()
 // 		},
 // 	)
 // 	.await
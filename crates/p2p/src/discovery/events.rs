@@ -0,0 +1,88 @@
+use std::net::Ipv4Addr;
+
+use sd_tunnel_utils::PeerId;
+
+use crate::PeerCandidate;
+
+/// DiscoveryEvent is emitted whenever the set of discovered peers changes, regardless of which discovery mechanism noticed it.
+/// Subscribe to them with [`crate::NetworkManager::discovery_events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscoveryEvent {
+	/// A peer was found, or can now be reached at different addresses than before.
+	PeerDiscovered {
+		peer_id: PeerId,
+		addresses: Vec<Ipv4Addr>,
+		port: u16,
+	},
+	/// A peer that had been discovered is no longer available.
+	PeerExpired { peer_id: PeerId },
+}
+
+/// discovered_event returns the event for a peer that has just been discovered, given what was previously known about it.
+/// Peers are found over and over again (mDNS re-resolves them, and they may be found by more than one mechanism), so
+/// an event is only returned when the peer is new or its addresses have changed.
+pub(crate) fn discovered_event(
+	previous: Option<&PeerCandidate>,
+	peer: &PeerCandidate,
+) -> Option<DiscoveryEvent> {
+	let changed = previous.map_or(true, |previous| {
+		previous.addresses != peer.addresses || previous.port != peer.port
+	});
+
+	changed.then(|| DiscoveryEvent::PeerDiscovered {
+		peer_id: peer.id.clone(),
+		addresses: peer.addresses.clone(),
+		port: peer.port,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use crate::PeerMetadata;
+
+	use super::*;
+
+	fn candidate(addresses: &[Ipv4Addr], port: u16) -> PeerCandidate {
+		PeerCandidate {
+			id: PeerId::from_string("a".repeat(40)).unwrap(),
+			metadata: PeerMetadata {
+				name: "Spacedrive".into(),
+				operating_system: None,
+				version: None,
+				records: HashMap::new(),
+			},
+			addresses: addresses.to_vec(),
+			port,
+		}
+	}
+
+	#[test]
+	fn peers_found_again_surface_once() {
+		let lan = Ipv4Addr::new(192, 168, 1, 20);
+		let peer = candidate(&[lan], 7373);
+
+		assert_eq!(
+			discovered_event(None, &peer),
+			Some(DiscoveryEvent::PeerDiscovered {
+				peer_id: peer.id.clone(),
+				addresses: vec![lan],
+				port: 7373,
+			})
+		);
+
+		// This mirrors mDNS re-resolving the peer, or another mechanism finding it at the same address
+		assert_eq!(
+			discovered_event(Some(&peer), &candidate(&[lan], 7373)),
+			None
+		);
+
+		// Moving to another address or port is surfaced, so the application can reconnect
+		assert!(discovered_event(Some(&peer), &candidate(&[lan], 7374)).is_some());
+		assert!(
+			discovered_event(Some(&peer), &candidate(&[Ipv4Addr::new(10, 0, 0, 2)], 7373))
+				.is_some()
+		);
+	}
+}
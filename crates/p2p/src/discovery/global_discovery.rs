@@ -1,10 +1,14 @@
 /// The functions in this file are predominantly useless in the current system. This will be fixed in a future PR's.
-use std::sync::Arc;
+use std::{future::Future, sync::Arc};
 
 use sd_tunnel_utils::{Client, Message};
-use tracing::warn;
+use tokio::time::sleep;
+use tracing::{error, warn};
 
-use crate::{NetworkManager, NetworkManagerError, P2PManager};
+use crate::{
+	discovery::StatusTracker, NetworkManager, NetworkManagerError, P2PManager, RetryBackoff,
+	SubsystemState, SubsystemStatus,
+};
 
 /// GlobalDiscovery is the discovery system for discovering devices which are not on the same local network as you.
 /// This is done through the Spacetunnel server hosted by Spacedrive Inc. it could however be hosted by anyone and documentation for doing so will be released in the future once we are confident in the current design.
@@ -28,34 +32,64 @@ impl<TP2PManager: P2PManager> GlobalDiscovery<TP2PManager> {
 		}
 	}
 
-	pub async fn poll(&self) {
+	pub async fn poll(&self) -> Result<(), NetworkManagerError> {
+		if self.nm.discovery_status.global.is_paused() {
+			return Ok(());
+		}
+
+		self.query_peers().await
+	}
+
+	async fn query_peers(&self) -> Result<(), NetworkManagerError> {
 		tracing::debug!("Polling global discovery service");
 
 		// TODO: Allow the tunnel server to accept a list of PeerId's instead of doing heaps of requests
 		let peers = self.nm.known_peers.iter().map(|v| v.clone()).collect();
-		match self
+		let result = match self
 			.client
 			.send_message(Message::QueryClientAnnouncement(peers))
 			.await
 		{
 			Ok(_) => {
 				tracing::debug!("Successfully sent query announcement");
+				Ok(())
 			}
 			Err(err) => {
 				warn!(
 					"[TODO: WIP FEATURE REPORTED ERROR] Spacetunnel failed lookup peers with error: {:?}",
 					err
 				);
-				// TODO: Handle error when this is implemented.
+				Err(err.into())
 			}
-		}
+		};
 
 		// TODO: Handle error from discovery service
 		// self.nm.discovered_peers.insert(key, value); // TODO: make this work
 		// TODO: Open connection to peers if they are not already connected
+		result
+	}
+
+	/// poll_until_reachable polls the global discovery service, retrying with the `backoff` for as long as it can't be reached.
+	/// It gives up straight away if global discovery is misconfigured, as retrying wouldn't help, and once it's shut down.
+	pub async fn poll_until_reachable(&self, backoff: RetryBackoff) {
+		if let Err(err) = retry(backoff, &self.nm.discovery_status.global, || {
+			self.query_peers()
+		})
+		.await
+		{
+			error!(
+				"Global discovery is misconfigured and has been disabled: {}",
+				err
+			);
+			self.nm.discovery_status.global.failed();
+		}
 	}
 
 	pub async fn register(&self) {
+		if self.nm.discovery_status.global.is_paused() {
+			return;
+		}
+
 		// TODO: Send the metadata along with the discovery payload
 		// TODO: Only do announcement if data has changed or it's been over 10 minutes since last packet
 
@@ -68,7 +102,9 @@ impl<TP2PManager: P2PManager> GlobalDiscovery<TP2PManager> {
 			announcement
 		);
 
-		match self.client.send_message(announcement).await {
+		let result = self.client.send_message(announcement).await;
+		self.nm.discovery_status.global.registered(result.is_ok());
+		match result {
 			Ok(_) => tracing::debug!("Successfully registered with global discovery service"),
 			Err(err) => {
 				warn!("[TODO: WIP FEATURE REPORTED ERROR] Spacetunnel failed announcement with error: {:?}", err);
@@ -79,8 +115,154 @@ impl<TP2PManager: P2PManager> GlobalDiscovery<TP2PManager> {
 		// TODO: Handle error from discovery service
 	}
 
+	/// status returns the live state of global discovery. Peers aren't found through it yet, so it never has any.
+	pub fn status(&self) -> SubsystemStatus {
+		self.nm.discovery_status.global.status()
+	}
+
+	/// pause stops announcing the current peer to, and polling, the global discovery service until it's resumed.
+	pub(crate) fn pause(&self) {
+		if self.nm.discovery_status.global.paused() {
+			tracing::debug!("Pausing global discovery service");
+			// TODO: Remove the announcement from the tunnel
+		}
+	}
+
+	pub(crate) fn resume(&self) -> Result<(), NetworkManagerError> {
+		tracing::debug!("Resuming global discovery service");
+		self.nm.discovery_status.global.resumed()
+	}
+
 	pub(crate) fn shutdown(&self) {
 		tracing::debug!("Shutting down gloval discovery service");
+		self.nm.discovery_status.global.disabled();
 		// TODO: Remove the announcement from the tunnel
 	}
 }
+
+/// retry runs `attempt` until it succeeds or fails with an error that isn't transient, waiting between attempts as the `backoff` says.
+/// Nothing is attempted while the mechanism is paused, and retrying stops once it has been shut down.
+async fn retry<F, Fut>(
+	backoff: RetryBackoff,
+	status: &StatusTracker,
+	mut attempt: F,
+) -> Result<(), NetworkManagerError>
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<(), NetworkManagerError>>,
+{
+	let mut failures = 0;
+	loop {
+		match status.status().state {
+			SubsystemState::Disabled => return Ok(()),
+			// resuming doesn't wake this up, so the state is checked again after a delay
+			SubsystemState::Paused => {
+				let delay = backoff.delay(failures.max(1), &mut rand::thread_rng());
+				sleep(delay).await;
+			}
+			_ => match attempt().await {
+				Err(err) if err.is_transient() => {
+					failures += 1;
+					let delay = backoff.delay(failures, &mut rand::thread_rng());
+					warn!(
+						"Global discovery is unreachable after {} attempts, retrying in {:?}: {}",
+						failures, delay, err
+					);
+					sleep(delay).await;
+				}
+				result => return result,
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{io, time::Duration};
+
+	use sd_tunnel_utils::ClientError;
+
+	use super::*;
+
+	const BACKOFF: RetryBackoff = RetryBackoff {
+		initial: Duration::from_millis(1),
+		max: Duration::from_millis(4),
+	};
+
+	fn unreachable() -> NetworkManagerError {
+		ClientError::IoError(io::ErrorKind::TimedOut.into()).into()
+	}
+
+	#[tokio::test]
+	async fn retries_until_reachable() {
+		let mut attempts = 0;
+		let result = retry(BACKOFF, &StatusTracker::default(), || {
+			attempts += 1;
+			let result = if attempts <= 2 {
+				Err(unreachable())
+			} else {
+				Ok(())
+			};
+			async move { result }
+		})
+		.await;
+
+		assert!(result.is_ok());
+		assert_eq!(attempts, 3);
+	}
+
+	#[tokio::test]
+	async fn misconfiguration_is_not_retried() {
+		let mut attempts = 0;
+		let result = retry(BACKOFF, &StatusTracker::default(), || {
+			attempts += 1;
+			async { Err(ClientError::MissingServerAddr.into()) }
+		})
+		.await;
+
+		assert!(matches!(
+			result,
+			Err(NetworkManagerError::GlobalDiscoveryMisconfigured(_))
+		));
+		assert_eq!(attempts, 1);
+	}
+
+	#[tokio::test]
+	async fn stops_retrying_once_shut_down() {
+		let status = StatusTracker::default();
+		let mut attempts = 0;
+		let result = retry(BACKOFF, &status, || {
+			attempts += 1;
+			if attempts == 2 {
+				status.disabled();
+			}
+			async { Err(unreachable()) }
+		})
+		.await;
+
+		assert!(result.is_ok());
+		assert_eq!(attempts, 2);
+	}
+
+	#[tokio::test]
+	async fn waits_while_paused() {
+		let status = StatusTracker::default();
+		status.paused();
+
+		let mut attempts = 0;
+		let (result, _) = tokio::join!(
+			retry(BACKOFF, &status, || {
+				attempts += 1;
+				assert!(!status.is_paused(), "polled while paused");
+				async { Ok(()) }
+			}),
+			async {
+				sleep(Duration::from_millis(20)).await;
+				status.resumed().unwrap();
+			}
+		);
+
+		assert!(result.is_ok());
+		assert_eq!(attempts, 1);
+	}
+}
@@ -4,7 +4,9 @@ use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent, ServiceInfo};
 use sd_tunnel_utils::PeerId;
 use tracing::warn;
 
-use crate::{NetworkManager, NetworkManagerError, P2PManager, PeerCandidate, PeerMetadata};
+use crate::{
+	NetworkManager, NetworkManagerError, P2PManager, PeerCandidate, PeerMetadata, SubsystemStatus,
+};
 
 /// MDNS is the discovery system used for over local networks. It makes use of Multicast DNS (mDNS) to discover peers.
 /// It should also conforms to the mDNS SD specification.
@@ -31,6 +33,8 @@ impl<TP2PManager: P2PManager> Mdns<TP2PManager> {
 
 	pub async fn handle_mdns_event(&self) {
 		match self.browser.recv_async().await {
+			// The daemon keeps browsing while paused, so its events are drained and dropped to stop them piling up
+			Ok(_) if self.nm.discovery_status.mdns.is_paused() => {}
 			Ok(event) => {
 				tracing::debug!("Handling incoming mdns event: {:?}", event);
 				match event {
@@ -57,6 +61,7 @@ impl<TP2PManager: P2PManager> Mdns<TP2PManager> {
 									port: info.get_port(),
 								};
 
+								self.nm.discovery_status.mdns.peer_found(peer_id);
 								self.nm.add_discovered_peer(peer);
 							}
 							Err(_) => {
@@ -76,6 +81,7 @@ impl<TP2PManager: P2PManager> Mdns<TP2PManager> {
 									return;
 								}
 
+								self.nm.discovery_status.mdns.peer_lost(&peer_id);
 								self.nm.remove_discovered_peer(peer_id);
 							}
 							Err(_) => {
@@ -90,6 +96,7 @@ impl<TP2PManager: P2PManager> Mdns<TP2PManager> {
 				}
 			}
 			Err(err) => {
+				self.nm.discovery_status.mdns.failed();
 				tracing::warn!(
 					"Error receiving MDNS event as the ServiceDaemon has been shut down: {:?}",
 					err
@@ -100,6 +107,10 @@ impl<TP2PManager: P2PManager> Mdns<TP2PManager> {
 	}
 
 	pub async fn register(&self) {
+		if self.nm.discovery_status.mdns.is_paused() {
+			return;
+		}
+
 		let peer_id_str = &self.nm.peer_id.to_string();
 		let service_info = ServiceInfo::new(
 			&self.service_type,
@@ -116,26 +127,49 @@ impl<TP2PManager: P2PManager> Mdns<TP2PManager> {
 		);
 		tracing::debug!("Registering mdns service entry: {:?}", service_info);
 
-		match service_info {
-			Ok(service_info) => match self.mdns.register(service_info) {
-				Ok(_) => {}
-				Err(err) => {
-					warn!("failed to register mdns service: {}", err);
-				}
-			},
-			Err(err) => {
-				warn!("failed to register mdns service: {}", err);
-			}
+		let result = service_info.and_then(|service_info| self.mdns.register(service_info));
+		if let Err(err) = &result {
+			warn!("failed to register mdns service: {}", err);
+		}
+		self.nm.discovery_status.mdns.registered(result.is_ok());
+	}
+
+	/// status returns the live state of mDNS discovery.
+	pub fn status(&self) -> SubsystemStatus {
+		self.nm.discovery_status.mdns.status()
+	}
+
+	/// pause stops advertising the current peer and ignores the peers that are found, until discovery is resumed. The mDNS daemon is kept running.
+	pub(crate) fn pause(&self) {
+		if !self.nm.discovery_status.mdns.paused() {
+			return;
+		}
+
+		tracing::debug!("Pausing mdns discovery service");
+		// Unlike during shutdown, this doesn't wait for the goodbye packets to be sent
+		if let Err(err) = self.mdns.unregister(&self.fullname()) {
+			warn!("failed to unregister mdns service: {}", err);
 		}
 	}
 
+	/// resume picks mDNS discovery back up after it was paused. The current peer is advertised again on the next [Self::register].
+	pub(crate) fn resume(&self) -> Result<(), NetworkManagerError> {
+		tracing::debug!("Resuming mdns discovery service");
+		self.nm.discovery_status.mdns.resumed()
+	}
+
+	fn fullname(&self) -> String {
+		format!("{}.{}", self.nm.peer_id, self.service_type)
+	}
+
 	/// shutdown shuts down the MDNS service. This will advertise the current peer as unavailable to the rest of the network.
 	pub(crate) fn shutdown(&self) {
 		tracing::debug!("Shutting down mdns discovery service");
+		self.nm.discovery_status.mdns.disabled();
 
 		// The panics caused by `.expect` are acceptable here because they are run during shutdown where nothing can be done if they were to fail.
 		self.mdns
-			.unregister(&format!("{}.{}", self.nm.peer_id, self.service_type))
+			.unregister(&self.fullname())
 			.expect("Error unregistering the mDNS service")
 			.recv()
 			.expect("Error unregistering the mDNS service");
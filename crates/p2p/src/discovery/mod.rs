@@ -1,7 +1,15 @@
+mod events;
 mod global_discovery;
 mod mdns;
 mod stack;
+mod status;
+mod timing;
 
+pub(crate) use events::discovered_event;
+pub use events::DiscoveryEvent;
 pub(crate) use global_discovery::*;
 pub(crate) use mdns::*;
 pub(crate) use stack::*;
+pub use status::{DiscoveryStatus, SubsystemState, SubsystemStatus};
+pub(crate) use status::{DiscoveryStatusTrackers, StatusTracker};
+pub use timing::*;
@@ -1,28 +1,77 @@
 use std::sync::Arc;
 
-use crate::{GlobalDiscovery, Mdns, NetworkManager, NetworkManagerError, P2PManager};
+use tokio::time::Instant;
+
+use crate::{
+	DiscoveryStatus, DiscoveryTiming, GlobalDiscovery, Mdns, NetworkManager, NetworkManagerError,
+	P2PManager, RetryBackoff,
+};
 
 /// Represents a stack of all of the different discovery mechanisms that are used by the P2P library.
 /// Traits are not used due to Rust's current lack of proper support for async traits.
 pub(crate) struct DiscoveryStack<TP2PManager: P2PManager> {
 	pub mdns: Arc<Mdns<TP2PManager>>,
 	pub global: Arc<GlobalDiscovery<TP2PManager>>,
+	pub timing: DiscoveryTiming,
+}
+
+// This isn't derived, as that would require `TP2PManager: Clone`, which the shared members don't need.
+impl<TP2PManager: P2PManager> Clone for DiscoveryStack<TP2PManager> {
+	fn clone(&self) -> Self {
+		Self {
+			mdns: Arc::clone(&self.mdns),
+			global: Arc::clone(&self.global),
+			timing: self.timing,
+		}
+	}
 }
 
 impl<TP2PManager: P2PManager> DiscoveryStack<TP2PManager> {
 	pub async fn new(nm: &Arc<NetworkManager<TP2PManager>>) -> Result<Self, NetworkManagerError> {
 		let global = Arc::new(GlobalDiscovery::init(nm)?);
-		global.poll().await;
+		// The service may not be reachable yet (e.g. we're starting up offline), so this keeps retrying in the background.
+		tokio::spawn({
+			let global = global.clone();
+			async move { global.poll_until_reachable(RetryBackoff::default()).await }
+		});
 
 		Ok(Self {
 			mdns: Arc::new(Mdns::init(nm)?),
 			global,
+			timing: nm.discovery_timing,
 		})
 	}
 
-	pub async fn register(&self) {
+	/// register announces the current peer on all discovery mechanisms and returns when the next announcement is due.
+	pub async fn register(&self) -> Instant {
 		self.mdns.register().await;
 		self.global.register().await;
+
+		self.timing
+			.next_announcement(Instant::now(), &mut rand::thread_rng())
+	}
+
+	/// status returns the live state of each discovery mechanism.
+	pub fn status(&self) -> DiscoveryStatus {
+		DiscoveryStatus {
+			mdns: self.mdns.status(),
+			global: self.global.status(),
+		}
+	}
+
+	/// pause stops all of the discovery mechanisms from announcing the current peer and discovering others, without tearing them down.
+	/// Pausing discovery that is already paused does nothing.
+	pub fn pause(&self) {
+		self.mdns.pause();
+		self.global.pause();
+	}
+
+	/// resume restarts discovery after it was paused, announcing the current peer straight away. It returns when the next announcement is due.
+	pub async fn resume(&self) -> Result<Instant, NetworkManagerError> {
+		self.mdns.resume()?;
+		self.global.resume()?;
+
+		Ok(self.register().await)
 	}
 
 	pub fn shutdown(&self) {
@@ -30,3 +79,10 @@ impl<TP2PManager: P2PManager> DiscoveryStack<TP2PManager> {
 		self.global.shutdown();
 	}
 }
+
+// Fails to compile if cloning the stack (or announcing from the clone) starts to require `TP2PManager: Clone`.
+async fn _clone_and_register<TP2PManager: P2PManager>(
+	stack: &DiscoveryStack<TP2PManager>,
+) -> Instant {
+	stack.clone().register().await
+}
@@ -0,0 +1,241 @@
+use std::{collections::HashSet, sync::Mutex, time::SystemTime};
+
+use sd_tunnel_utils::PeerId;
+use serde::Serialize;
+
+use crate::NetworkManagerError;
+
+/// Represents what a discovery mechanism is currently doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SubsystemState {
+	/// The current peer is being announced for the first time, or again after a failure.
+	Registering,
+	/// The last announcement succeeded.
+	Active,
+	/// The last announcement failed, or the mechanism stopped working.
+	Failed,
+	/// The mechanism has been paused, and will start announcing and discovering again once it's resumed.
+	Paused,
+	/// The mechanism has been shut down.
+	Disabled,
+}
+
+/// Is a snapshot of the state of a single discovery mechanism, for showing to the user.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemStatus {
+	pub state: SubsystemState,
+	/// last_register_at is when the current peer was last announced successfully.
+	pub last_register_at: Option<SystemTime>,
+	/// peers_found is how many peers this mechanism currently knows about.
+	pub peers_found: usize,
+}
+
+/// Is a snapshot of the state of every discovery mechanism, as returned by `NetworkManager::discovery_status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveryStatus {
+	pub mdns: SubsystemStatus,
+	pub global: SubsystemStatus,
+}
+
+/// Keeps track of the state of a discovery mechanism as it runs, so it can be read at any time.
+#[derive(Debug)]
+pub(crate) struct StatusTracker(Mutex<TrackedStatus>);
+
+#[derive(Debug)]
+struct TrackedStatus {
+	state: SubsystemState,
+	last_register_at: Option<SystemTime>,
+	peers: HashSet<PeerId>,
+}
+
+impl Default for StatusTracker {
+	fn default() -> Self {
+		Self(Mutex::new(TrackedStatus {
+			state: SubsystemState::Registering,
+			last_register_at: None,
+			peers: HashSet::new(),
+		}))
+	}
+}
+
+impl StatusTracker {
+	fn with<T>(&self, func: impl FnOnce(&mut TrackedStatus) -> T) -> T {
+		func(&mut self.0.lock().unwrap_or_else(|err| err.into_inner()))
+	}
+
+	/// registered records the outcome of an announcement.
+	pub fn registered(&self, success: bool) {
+		self.with(|status| {
+			if matches!(
+				status.state,
+				SubsystemState::Paused | SubsystemState::Disabled
+			) {
+				return;
+			}
+
+			if success {
+				status.state = SubsystemState::Active;
+				status.last_register_at = Some(SystemTime::now());
+			} else {
+				status.state = SubsystemState::Failed;
+			}
+		});
+	}
+
+	/// failed marks the mechanism as no longer working, e.g. because its daemon stopped.
+	pub fn failed(&self) {
+		self.with(|status| {
+			if !matches!(
+				status.state,
+				SubsystemState::Paused | SubsystemState::Disabled
+			) {
+				status.state = SubsystemState::Failed;
+			}
+		});
+	}
+
+	/// paused marks the mechanism as paused. It returns whether it was running beforehand, so pausing twice only stops it once.
+	pub fn paused(&self) -> bool {
+		self.with(|status| match status.state {
+			SubsystemState::Paused | SubsystemState::Disabled => false,
+			_ => {
+				status.state = SubsystemState::Paused;
+				true
+			}
+		})
+	}
+
+	/// resumed marks a paused mechanism as registering again. It fails if the mechanism has been shut down, as it can't be restarted.
+	pub fn resumed(&self) -> Result<(), NetworkManagerError> {
+		self.with(|status| match status.state {
+			SubsystemState::Disabled => Err(NetworkManagerError::DiscoveryShutdown),
+			SubsystemState::Paused => {
+				status.state = SubsystemState::Registering;
+				Ok(())
+			}
+			_ => Ok(()),
+		})
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.with(|status| status.state == SubsystemState::Paused)
+	}
+
+	/// disabled marks the mechanism as shut down. The peers it found are forgotten.
+	pub fn disabled(&self) {
+		self.with(|status| {
+			status.state = SubsystemState::Disabled;
+			status.peers.clear();
+		});
+	}
+
+	pub fn peer_found(&self, peer_id: PeerId) {
+		self.with(|status| status.peers.insert(peer_id));
+	}
+
+	pub fn peer_lost(&self, peer_id: &PeerId) {
+		self.with(|status| status.peers.remove(peer_id));
+	}
+
+	pub fn status(&self) -> SubsystemStatus {
+		self.with(|status| SubsystemStatus {
+			state: status.state,
+			last_register_at: status.last_register_at,
+			peers_found: status.peers.len(),
+		})
+	}
+}
+
+/// Holds the status trackers of every discovery mechanism. It's owned by the [crate::NetworkManager] so it outlives the event loop.
+#[derive(Debug, Default)]
+pub(crate) struct DiscoveryStatusTrackers {
+	pub mdns: StatusTracker,
+	pub global: StatusTracker,
+}
+
+impl DiscoveryStatusTrackers {
+	pub fn status(&self) -> DiscoveryStatus {
+		DiscoveryStatus {
+			mdns: self.mdns.status(),
+			global: self.global.status(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn peer(c: char) -> PeerId {
+		PeerId::from_string(c.to_string().repeat(40)).unwrap()
+	}
+
+	#[test]
+	fn active_after_register() {
+		let trackers = DiscoveryStatusTrackers::default();
+		assert_eq!(trackers.status().mdns.state, SubsystemState::Registering);
+
+		// This mirrors `DiscoveryStack::register` followed by mDNS resolving two peers (one of them twice)
+		trackers.mdns.registered(true);
+		trackers.global.registered(true);
+		trackers.mdns.peer_found(peer('a'));
+		trackers.mdns.peer_found(peer('b'));
+		trackers.mdns.peer_found(peer('a'));
+
+		let status = trackers.status();
+		assert_eq!(status.mdns.state, SubsystemState::Active);
+		assert_eq!(status.global.state, SubsystemState::Active);
+		assert!(status.mdns.last_register_at.is_some());
+		assert!(status.global.last_register_at.is_some());
+		assert_eq!(status.mdns.peers_found, 2);
+		assert_eq!(status.global.peers_found, 0);
+
+		trackers.mdns.peer_lost(&peer('a'));
+		trackers.global.registered(false);
+
+		let status = trackers.status();
+		assert_eq!(status.mdns.peers_found, 1);
+		assert_eq!(status.global.state, SubsystemState::Failed);
+		// the last successful registration is kept
+		assert!(status.global.last_register_at.is_some());
+	}
+
+	#[test]
+	fn disabled_is_final() {
+		let tracker = StatusTracker::default();
+		tracker.peer_found(peer('a'));
+		tracker.disabled();
+		tracker.registered(true);
+		tracker.failed();
+
+		let status = tracker.status();
+		assert_eq!(status.state, SubsystemState::Disabled);
+		assert_eq!(status.peers_found, 0);
+		assert!(status.last_register_at.is_none());
+	}
+
+	#[test]
+	fn pause_and_resume() {
+		let tracker = StatusTracker::default();
+		tracker.registered(true);
+
+		assert!(tracker.paused());
+		// pausing again is a no-op, and announcements made while paused don't change the state
+		assert!(!tracker.paused());
+		tracker.registered(true);
+		tracker.failed();
+		assert_eq!(tracker.status().state, SubsystemState::Paused);
+
+		tracker.resumed().unwrap();
+		assert_eq!(tracker.status().state, SubsystemState::Registering);
+		tracker.resumed().unwrap();
+		assert_eq!(tracker.status().state, SubsystemState::Registering);
+
+		tracker.disabled();
+		assert!(!tracker.paused());
+		assert!(matches!(
+			tracker.resumed(),
+			Err(NetworkManagerError::DiscoveryShutdown)
+		));
+	}
+}
@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time::Instant;
+
+/// Controls how often the current peer announces itself to the discovery mechanisms.
+/// A longer `announce_interval` reduces network chatter (and battery usage) at the cost of discovery latency.
+/// The `jitter` is applied randomly in either direction so that peers on the same network don't announce in sync.
+#[derive(Debug, Clone, Copy)]
+pub struct DiscoveryTiming {
+	pub announce_interval: Duration,
+	pub jitter: Duration,
+}
+
+impl Default for DiscoveryTiming {
+	fn default() -> Self {
+		Self {
+			announce_interval: Duration::from_secs(15 * 60 /* 15 Minutes */),
+			jitter: Duration::from_secs(30),
+		}
+	}
+}
+
+impl DiscoveryTiming {
+	/// next_announcement returns when the next announcement should occur, given the time of the current one.
+	pub(crate) fn next_announcement(&self, now: Instant, rng: &mut impl Rng) -> Instant {
+		// The jitter can't be larger than the interval otherwise we would be scheduling announcements in the past.
+		let jitter = self.jitter.min(self.announce_interval);
+		let offset = rng.gen_range(Duration::ZERO..=(jitter * 2));
+
+		now + (self.announce_interval - jitter) + offset
+	}
+}
+
+/// Controls how long to wait before retrying a discovery mechanism that couldn't be reached.
+/// The delay doubles with every failed attempt, up to `max`, and a random amount of up to half of it is taken off so that
+/// peers which lost connectivity at the same time don't all retry in sync.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetryBackoff {
+	pub initial: Duration,
+	pub max: Duration,
+}
+
+impl Default for RetryBackoff {
+	fn default() -> Self {
+		Self {
+			initial: Duration::from_secs(1),
+			max: Duration::from_secs(60),
+		}
+	}
+}
+
+impl RetryBackoff {
+	/// delay returns how long to wait before the retry that follows `failures` consecutive failed attempts.
+	pub(crate) fn delay(&self, failures: u32, rng: &mut impl Rng) -> Duration {
+		let delay = self
+			.initial
+			.checked_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+			.map_or(self.max, |delay| delay.min(self.max));
+
+		delay - rng.gen_range(Duration::ZERO..=(delay / 2))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{rngs::StdRng, SeedableRng};
+
+	use super::*;
+
+	#[test]
+	fn announcements_within_jitter() {
+		let timing = DiscoveryTiming {
+			announce_interval: Duration::from_secs(60),
+			jitter: Duration::from_secs(5),
+		};
+		let mut rng = StdRng::seed_from_u64(69);
+
+		let mut now = Instant::now();
+		for _ in 0..1000 {
+			let next = timing.next_announcement(now, &mut rng);
+			let delay = next - now;
+
+			assert!(delay >= Duration::from_secs(55), "{delay:?} is too early");
+			assert!(delay <= Duration::from_secs(65), "{delay:?} is too late");
+
+			now = next;
+		}
+	}
+
+	#[test]
+	fn jitter_larger_than_interval() {
+		let timing = DiscoveryTiming {
+			announce_interval: Duration::from_secs(10),
+			jitter: Duration::from_secs(60),
+		};
+		let mut rng = StdRng::seed_from_u64(420);
+
+		let now = Instant::now();
+		for _ in 0..1000 {
+			let delay = timing.next_announcement(now, &mut rng) - now;
+			assert!(delay <= Duration::from_secs(20), "{delay:?} is too late");
+		}
+	}
+
+	#[test]
+	fn retries_back_off_exponentially() {
+		let backoff = RetryBackoff::default();
+		let mut rng = StdRng::seed_from_u64(1337);
+
+		for (failures, expected) in [(1, 1), (2, 2), (3, 4), (6, 32), (7, 60), (100, 60)] {
+			let expected = Duration::from_secs(expected);
+			for _ in 0..100 {
+				let delay = backoff.delay(failures, &mut rng);
+				assert!(delay <= expected, "{delay:?} is too late");
+				assert!(delay >= expected / 2, "{delay:?} is too early");
+			}
+		}
+	}
+}
@@ -2,13 +2,18 @@ mod discovery;
 mod network_manager;
 mod p2p_manager;
 mod peer;
+mod transfer;
 mod utils;
 
 pub(crate) use discovery::*;
+pub use discovery::{
+	DiscoveryEvent, DiscoveryStatus, DiscoveryTiming, SubsystemState, SubsystemStatus,
+};
 pub use network_manager::*;
 pub use p2p_manager::*;
 pub use peer::*;
 pub use sd_tunnel_utils::{read_value, write_value, PeerId};
+pub use transfer::*;
 pub use utils::*;
 
 /// We reexport some types from `quinn` to avoid the user needing to add `quinn` and keep its version in sync with the p2p library.
@@ -1,6 +1,7 @@
 use std::{
 	collections::HashMap,
 	net::{Ipv4Addr, SocketAddr},
+	path::Path,
 	sync::Arc,
 	time::Duration,
 };
@@ -13,13 +14,18 @@ use rustls::{Certificate, PrivateKey};
 use sd_tunnel_utils::{quic, write_value, PeerId, UtilError};
 use spake2::{Ed25519Group, Password, Spake2};
 use thiserror::Error;
-use tokio::sync::{mpsc, oneshot};
+use tokio::{
+	fs::File,
+	sync::{broadcast, mpsc, oneshot},
+};
 use tracing::{debug, error, warn};
 
 use crate::{
-	ConnectError, ConnectionEstablishmentPayload, ConnectionType, Identity, NetworkManagerConfig,
+	discovered_event, receive_file, send_file, ConnectError, ConnectionEstablishmentPayload,
+	ConnectionType, DiscoveryEvent, DiscoveryStatus, DiscoveryStatusTrackers, DiscoveryTiming,
+	FilterDecision, Identity, InboundDirs, InboundFilter, InboundFilterFn, NetworkManagerConfig,
 	NetworkManagerError, NetworkManagerInternalEvent, P2PManager, PairingParticipantType,
-	PairingPayload, Peer, PeerCandidate,
+	PairingPayload, Peer, PeerCandidate, ReceivedFile, TransferError,
 };
 
 /// Is the core of the P2P Library. It manages listening for and creating P2P network connections and also provides a nice API for the application embedding this library to interface with.
@@ -45,6 +51,14 @@ pub struct NetworkManager<TP2PManager: P2PManager> {
 	pub(crate) endpoint: Endpoint,
 	/// spacetunnel_server is the URL used to lookup information about the Spacetunnel server to establish a connection with.
 	pub(crate) spacetunnel_url: Option<String>,
+	/// discovery_timing controls the cadence at which the current peer is announced to the discovery mechanisms.
+	pub(crate) discovery_timing: DiscoveryTiming,
+	/// discovery_status tracks the state of each discovery mechanism. The mechanisms update it as they run.
+	pub(crate) discovery_status: DiscoveryStatusTrackers,
+	/// discovery_events is used to tell subscribers when a peer is discovered or expires.
+	discovery_events: broadcast::Sender<DiscoveryEvent>,
+	/// inbound_filter is consulted for every file a peer sends us, before any of its bytes are received.
+	inbound_filter: InboundFilter,
 	/// internal_channel is a channel which is used to communicate with the main internal event loop.
 	internal_channel: mpsc::UnboundedSender<NetworkManagerInternalEvent>,
 }
@@ -90,15 +104,35 @@ impl<TP2PManager: P2PManager> NetworkManager<TP2PManager> {
 			manager,
 			endpoint,
 			spacetunnel_url: config.spacetunnel_url,
+			discovery_timing: config.discovery_timing,
+			discovery_status: DiscoveryStatusTrackers::default(),
+			discovery_events: broadcast::channel(64).0,
+			inbound_filter: InboundFilter::default(),
 			internal_channel: internal_channel.0,
 		});
 		Self::event_loop(&this, incoming, internal_channel.1).await?;
 		Ok(this)
 	}
 
+	/// discovery_status returns whether each discovery mechanism is up, when it last announced the current peer and how many peers it has found.
+	pub fn discovery_status(&self) -> DiscoveryStatus {
+		self.discovery_status.status()
+	}
+
+	/// discovery_events subscribes to peers being discovered and expiring, across all of the discovery mechanisms.
+	/// A peer that is found again (e.g. by another mechanism) is only reported again if its addresses have changed.
+	/// Subscribers that fall behind miss the oldest events, and can catch up by checking the discovered peers.
+	pub fn discovery_events(&self) -> broadcast::Receiver<DiscoveryEvent> {
+		self.discovery_events.subscribe()
+	}
+
 	pub(crate) fn add_discovered_peer(&self, peer: PeerCandidate) {
 		debug!("Discovered peer: {:?}", peer);
-		self.discovered_peers.insert(peer.id.clone(), peer.clone());
+		let previous = self.discovered_peers.insert(peer.id.clone(), peer.clone());
+		if let Some(event) = discovered_event(previous.as_ref(), &peer) {
+			// This only fails if there are no subscribers, in which case there's no one to tell
+			let _ = self.discovery_events.send(event);
+		}
 		self.manager.peer_discovered(self, &peer.id);
 
 		if self.known_peers.contains(&peer.id) {
@@ -116,7 +150,11 @@ impl<TP2PManager: P2PManager> NetworkManager<TP2PManager> {
 
 	pub(crate) fn remove_discovered_peer(&self, peer_id: PeerId) {
 		debug!("Removing discovered peer: {:?}", peer_id);
-		self.discovered_peers.remove(&peer_id);
+		if self.discovered_peers.remove(&peer_id).is_some() {
+			let _ = self.discovery_events.send(DiscoveryEvent::PeerExpired {
+				peer_id: peer_id.clone(),
+			});
+		}
 		self.manager.peer_expired(self, peer_id);
 	}
 
@@ -151,6 +189,43 @@ impl<TP2PManager: P2PManager> NetworkManager<TP2PManager> {
 		self.listen_addr
 	}
 
+	/// sets the filter which decides whether files sent by peers are accepted, rejected or quarantined. This can be used to hook up a virus scanner or to refuse executables.
+	pub fn set_inbound_filter(&self, filter: InboundFilterFn) {
+		self.inbound_filter.set(filter);
+	}
+
+	/// sends a file to a connected peer on a new stream. The peer's inbound filter decides what happens to the file before any of its bytes are sent, and its decision is returned.
+	/// The peer must receive the stream it gets in [P2PManager::accept_stream] with [Self::receive_file].
+	pub async fn send_file(
+		&self,
+		peer_id: &PeerId,
+		path: &Path,
+	) -> Result<FilterDecision, NMError> {
+		debug!("Sending file '{:?}' to peer '{:?}'", path, peer_id);
+
+		let name = path
+			.file_name()
+			.and_then(|name| name.to_str())
+			.ok_or_else(|| TransferError::InvalidName(path.display().to_string()))?
+			.to_string();
+		let mut file = File::open(path).await.map_err(TransferError::Io)?;
+		let size = file.metadata().await.map_err(TransferError::Io)?.len();
+
+		let (mut tx, mut rx) = self.stream(peer_id).await?;
+		Ok(send_file((&mut tx, &mut rx), name, size, &mut file).await?)
+	}
+
+	/// receives a file a peer sent with [Self::send_file], on a stream from [P2PManager::accept_stream].
+	/// The inbound filter is consulted before any of the file's bytes are received. Rejected files are refused (and `None` is returned), while quarantined files are written to their own directory and marked as untrusted.
+	pub async fn receive_file(
+		&self,
+		peer_id: PeerId,
+		(mut tx, mut rx): (SendStream, RecvStream),
+		dirs: &InboundDirs,
+	) -> Result<Option<ReceivedFile>, TransferError> {
+		receive_file(&self.inbound_filter, peer_id, (&mut tx, &mut rx), dirs).await
+	}
+
 	/// adds a new peer to the known peers list. This will cause the NetworkManager to attempt to connect to the peer if it is discovered.
 	pub fn add_known_peer(&self, peer_id: PeerId) {
 		debug!("Adding '{:?}' as a known peer", peer_id);
@@ -167,6 +242,29 @@ impl<TP2PManager: P2PManager> NetworkManager<TP2PManager> {
 		}
 	}
 
+	/// pauses discovery, so the current peer stops being announced and other peers stop being discovered (e.g. to save battery and bandwidth on a metered connection).
+	/// Connections which have already been established are kept open. Pausing discovery that is already paused does nothing.
+	pub fn pause_discovery(&self) {
+		if let Err(err) = self
+			.internal_channel
+			.send(NetworkManagerInternalEvent::PauseDiscovery)
+		{
+			error!("Failed to send on internal_channel: {:?}", err);
+		}
+	}
+
+	/// resumes discovery after it was paused with [Self::pause_discovery]. This fails if the NetworkManager has been shut down.
+	pub async fn resume_discovery(&self) -> Result<(), NetworkManagerError> {
+		let (tx, rx) = oneshot::channel();
+		self.internal_channel
+			.send(NetworkManagerInternalEvent::ResumeDiscovery(tx))
+			.map_err(|_| NetworkManagerError::DiscoveryShutdown)?;
+
+		// The event loop drops the sender if it's shutting down
+		rx.await
+			.map_err(|_| NetworkManagerError::DiscoveryShutdown)?
+	}
+
 	/// send a single message to a peer and await a single response. This is good for quick one-off communications but any longer term communication should be done with a stream.
 	/// TODO: Error type
 	pub async fn send_to(&self, peer_id: PeerId, data: &[u8]) -> Result<Chunk, NMError> {
@@ -436,4 +534,6 @@ pub enum NMError {
 	ConnectError(#[from] ConnectError),
 	#[error("Error generating preshared key")]
 	GeneratePresharedKeyError(#[from] bip39::Error),
+	#[error("Error transferring file")]
+	TransferError(#[from] TransferError),
 }
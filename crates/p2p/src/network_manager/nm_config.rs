@@ -2,8 +2,12 @@ use std::collections::HashSet;
 
 use sd_tunnel_utils::PeerId;
 
+use crate::DiscoveryTiming;
+
 /// Stores configuration which is given to the [crate::NetworkManager] at startup so it can resume from it's previous state.
-#[derive(Clone)]
+///
+/// Every field has a sensible default, so only the ones that matter need to be set (using `..Default::default()`).
+#[derive(Clone, Default)]
 pub struct NetworkManagerConfig {
 	/// known_peers contains a list of all the peers that were connected last time the application was running.
 	/// These are used to know who to lookup when using the global discovery service.
@@ -13,4 +17,7 @@ pub struct NetworkManagerConfig {
 	pub listen_port: Option<u16>,
 	/// TODO
 	pub spacetunnel_url: Option<String>,
+	/// discovery_timing controls how often the current peer announces itself to the discovery mechanisms.
+	/// By default it announces every 15 minutes (see [DiscoveryTiming::default]).
+	pub discovery_timing: DiscoveryTiming,
 }
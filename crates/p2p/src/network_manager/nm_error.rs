@@ -1,5 +1,6 @@
 use std::io;
 
+use sd_tunnel_utils::ClientError;
 use thiserror::Error;
 
 /// Represents an error that occurs while initalising the [crate::NetworkManager].
@@ -20,4 +21,33 @@ pub enum NetworkManagerError {
 	Server(io::Error),
 	#[error("error generating P2P identity")]
 	RcGen(#[from] rcgen::RcgenError),
+	#[error("the global discovery service is temporarily unreachable")]
+	GlobalDiscoveryUnreachable(ClientError),
+	#[error("global discovery is misconfigured")]
+	GlobalDiscoveryMisconfigured(ClientError),
+	#[error("discovery has been shut down and can't be resumed")]
+	DiscoveryShutdown,
+}
+
+impl NetworkManagerError {
+	/// is_transient returns whether the operation that failed may succeed if it is retried later.
+	pub fn is_transient(&self) -> bool {
+		matches!(self, Self::GlobalDiscoveryUnreachable(_))
+	}
+}
+
+impl From<ClientError> for NetworkManagerError {
+	fn from(err: ClientError) -> Self {
+		match err {
+			// These are caused by the Spacetunnel URL or our TLS identity, so retrying won't fix them.
+			ClientError::MissingServerAddr
+			| ClientError::TlsError(_)
+			| ClientError::ConnectError(_) => Self::GlobalDiscoveryMisconfigured(err),
+			// These include DNS lookups failing and the connection dropping, which can happen while offline.
+			ClientError::IoError(_)
+			| ClientError::ConnectionError(_)
+			| ClientError::UtilError(_)
+			| ClientError::WriteError(_) => Self::GlobalDiscoveryUnreachable(err),
+		}
+	}
 }
@@ -9,7 +9,11 @@ use if_watch::{IfEvent, IfWatcher};
 use quinn::{ClientConfig, Incoming, NewConnection, VarInt};
 use sd_tunnel_utils::{quic::client_config, PeerId};
 use thiserror::Error;
-use tokio::{select, sync::mpsc, time::sleep};
+use tokio::{
+	select,
+	sync::{mpsc, oneshot},
+	time::{sleep, sleep_until},
+};
 use tracing::{debug, error, warn};
 
 use crate::{
@@ -18,10 +22,12 @@ use crate::{
 };
 
 /// Represents an event that should be handled by the [NetworkManager] event loop.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub(crate) enum NetworkManagerInternalEvent {
 	Connect(PeerCandidate),
 	NewKnownPeer(PeerId),
+	PauseDiscovery,
+	ResumeDiscovery(oneshot::Sender<Result<(), NetworkManagerError>>),
 }
 
 impl<TP2PManager: P2PManager> NetworkManager<TP2PManager> {
@@ -54,7 +60,7 @@ impl<TP2PManager: P2PManager> NetworkManager<TP2PManager> {
 			Self::handle_ifwatch_event(nm, IfEvent::Up(*iface));
 		}
 
-		discovery.register().await;
+		let mut next_announcement = discovery.register().await;
 
 		debug!(
 			"Network adapters discovered on startup: {:?}",
@@ -82,21 +88,23 @@ impl<TP2PManager: P2PManager> NetworkManager<TP2PManager> {
 							Ok(event) => {
 								debug!("Handling ifwatch event: {:?}", event);
 								if Self::handle_ifwatch_event(&nm, event) {
-									discovery.register().await;
+									next_announcement = discovery.register().await;
 								}
 							},
 							Err(_) => break,
 						}
 					}
 					_ = discovery.mdns.handle_mdns_event() => {}
-					_ = sleep(Duration::from_secs(15 * 60 /* 15 Minutes */)) => {
+					_ = sleep_until(next_announcement) => {
 						debug!("Discovery service registration timer reached");
-						discovery.register().await;
+						next_announcement = discovery.register().await;
+						debug!("Discovery status after registration: {:?}", discovery.status());
 					}
 					// TODO: Maybe use subscription system instead of polling or review this timeout!
 					_ = sleep(Duration::from_secs(60 /* 1 minute */)) => {
 						debug!("Discovery service pool timer reached");
-						discovery.global.poll().await; // TODO: this does network calls and blocks. Is this ok?
+						// Failures are logged, and it will be polled again next time
+						let _ = discovery.global.poll().await; // TODO: this does network calls and blocks. Is this ok?
 					}
 					event = internal_channel.recv() => {
 						debug!("Received internal event: {:?}", event);
@@ -117,6 +125,13 @@ impl<TP2PManager: P2PManager> NetworkManager<TP2PManager> {
 									Self::connect_to_peer(&nm, peer).await;
 								}
 							}
+							NetworkManagerInternalEvent::PauseDiscovery => discovery.pause(),
+							NetworkManagerInternalEvent::ResumeDiscovery(resp) => {
+								let result = discovery.resume().await.map(|next| {
+									next_announcement = next;
+								});
+								let _ = resp.send(result);
+							}
 						}
 					}
 					_ = shutdown_signal_rx.recv() => {
@@ -3,6 +3,13 @@ use std::{collections::HashMap, env, str::FromStr};
 use sd_tunnel_utils::PeerId;
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use tracing::warn;
+
+/// The longest a TXT record (`key=value`) can be, as DNS-SD stores its length in a single byte.
+pub const MAX_TXT_RECORD_LEN: usize = 255;
+
+/// The keys that the metadata's own fields are advertised under, which records can't use.
+const RESERVED_KEYS: [&str; 3] = ["name", "os", "version"];
 
 /// Represents the operating system which the remote peer is running.
 /// This is not used internally and predominantly is designed to be used for display purposes by the embedding application.
@@ -69,6 +76,10 @@ pub struct PeerMetadata {
 	pub name: String,
 	pub operating_system: Option<OperationSystem>,
 	pub version: Option<String>,
+	/// Application-defined records (e.g. the sync protocol version, or the ids of the libraries being shared),
+	/// which are advertised during discovery so incompatible peers can be filtered out before connecting to them.
+	#[serde(default)]
+	pub records: HashMap<String, String>,
 }
 
 impl PeerMetadata {
@@ -80,11 +91,30 @@ impl PeerMetadata {
 				.unwrap_or_else(|| peer_id.to_string()),
 			operating_system: hashmap.get("os").map(|v| v.parse().ok()).unwrap_or(None),
 			version: hashmap.get("version").map(|v| v.to_string()),
+			records: hashmap
+				.iter()
+				.filter(|(key, _)| !RESERVED_KEYS.contains(&key.as_str()))
+				.map(|(key, value)| (key.clone(), value.clone()))
+				.collect(),
 		}
 	}
 
+	/// to_hashmap converts the metadata into the TXT records that are advertised over mDNS.
+	///
+	/// Records which don't fit into a single TXT record (see [`MAX_TXT_RECORD_LEN`]) are left out rather than truncated,
+	/// as a truncated value (e.g. a list of library ids) would be misread by other peers. Records using one of the
+	/// metadata's own keys are left out too. Both are logged.
 	pub fn to_hashmap(self) -> HashMap<String, String> {
 		let mut hashmap = HashMap::new();
+		for (key, value) in self.records {
+			if RESERVED_KEYS.contains(&key.as_str()) {
+				warn!("not advertising peer metadata record '{key}' as its key is reserved");
+			} else if key.len() + 1 + value.len() > MAX_TXT_RECORD_LEN {
+				warn!("not advertising peer metadata record '{key}' as it's longer than {MAX_TXT_RECORD_LEN} bytes");
+			} else {
+				hashmap.insert(key, value);
+			}
+		}
 		hashmap.insert("name".to_string(), self.name);
 		if let Some(version) = self.version {
 			hashmap.insert("version".to_string(), version);
@@ -92,3 +122,53 @@ impl PeerMetadata {
 		hashmap
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn metadata(records: &[(&str, String)]) -> PeerMetadata {
+		PeerMetadata {
+			name: "Spacedrive".into(),
+			operating_system: None,
+			version: Some("0.1.0".into()),
+			records: records
+				.iter()
+				.map(|(key, value)| (key.to_string(), value.clone()))
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn records_round_trip() {
+		let peer_id = PeerId::from_string("a".repeat(40)).unwrap();
+		let records = [
+			("sync", "1".to_string()),
+			("libraries", "0b9d3c1e,6f2a8d40".to_string()),
+		];
+
+		let parsed = PeerMetadata::from_hashmap(&peer_id, &metadata(&records).to_hashmap());
+
+		assert_eq!(parsed.name, "Spacedrive");
+		assert_eq!(parsed.version.as_deref(), Some("0.1.0"));
+		assert_eq!(parsed.records, metadata(&records).records);
+	}
+
+	#[test]
+	fn records_that_dont_fit_are_left_out() {
+		// `key=value` has to fit into 255 bytes
+		let fits = "a".repeat(MAX_TXT_RECORD_LEN - "fits=".len());
+		let too_long = "a".repeat(MAX_TXT_RECORD_LEN - "long=".len() + 1);
+
+		let hashmap = metadata(&[
+			("fits", fits.clone()),
+			("long", too_long),
+			("name", "Impostor".to_string()),
+		])
+		.to_hashmap();
+
+		assert_eq!(hashmap.get("fits"), Some(&fits));
+		assert!(!hashmap.contains_key("long"));
+		assert_eq!(hashmap.get("name").map(String::as_str), Some("Spacedrive"));
+	}
+}
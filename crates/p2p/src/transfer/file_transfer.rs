@@ -0,0 +1,290 @@
+use std::{
+	io,
+	path::{Path, PathBuf},
+};
+
+use sd_tunnel_utils::PeerId;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+	fs::{self, File},
+	io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+};
+use tracing::{debug, warn};
+
+use crate::{FilterDecision, InboundFilter, IncomingFileInfo};
+
+/// MAX_MESSAGE_LEN is the maximum size of a message exchanged ahead of a file.
+const MAX_MESSAGE_LEN: u32 = 64 * 1024;
+
+/// Is sent by the peer sending a file, before any of its bytes, so the receiving peer can decide whether it wants it.
+#[derive(Debug, Serialize, Deserialize)]
+struct FileTransferRequest {
+	name: String,
+	size: u64,
+}
+
+/// Is the directories files received from peers are written to.
+#[derive(Debug, Clone)]
+pub struct InboundDirs {
+	/// accepted is where files the inbound filter accepted are written.
+	pub accepted: PathBuf,
+	/// quarantined is where files the inbound filter quarantined are written, so they're kept apart until the user has reviewed them.
+	pub quarantined: PathBuf,
+}
+
+/// Is a file that has been received from a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedFile {
+	/// path is where the file was written.
+	pub path: PathBuf,
+	/// untrusted is set when the file was quarantined. The application must not treat it as part of the library until the user has reviewed it.
+	pub untrusted: bool,
+}
+
+/// Represents an error that occurs while sending or receiving a file.
+#[derive(Error, Debug)]
+pub enum TransferError {
+	#[error("error reading or writing the file")]
+	Io(#[from] io::Error),
+	#[error("error encoding message")]
+	Encode(#[from] rmp_serde::encode::Error),
+	#[error("error decoding message")]
+	Decode(#[from] rmp_serde::decode::Error),
+	#[error("the peer sent a {0} byte message, which is larger than allowed")]
+	MessageTooLarge(u32),
+	#[error("the peer sent a file with an invalid name: '{0}'")]
+	InvalidName(String),
+	#[error("the peer only sent {received} of the {expected} bytes it announced")]
+	Truncated { expected: u64, received: u64 },
+}
+
+/// sends a file to a peer over the given stream. The receiving peer decides what happens to the file before any of its bytes are sent, and its decision is returned.
+pub(crate) async fn send_file(
+	(tx, rx): (
+		&mut (impl AsyncWrite + Unpin),
+		&mut (impl AsyncRead + Unpin),
+	),
+	name: String,
+	size: u64,
+	file: &mut (impl AsyncRead + Unpin),
+) -> Result<FilterDecision, TransferError> {
+	write_message(tx, &FileTransferRequest { name, size }).await?;
+
+	let decision = read_message(rx).await?;
+	if decision == FilterDecision::Reject {
+		return Ok(decision);
+	}
+
+	let sent = tokio::io::copy(&mut file.take(size), tx).await?;
+	if sent != size {
+		return Err(TransferError::Truncated {
+			expected: size,
+			received: sent,
+		});
+	}
+	tx.shutdown().await?;
+
+	Ok(decision)
+}
+
+/// receives a file sent with [send_file] from a peer. The inbound filter is consulted once the file's name and size are known, before any of its bytes are read.
+/// A rejected file is refused (and `None` is returned), while a quarantined one is written to its own directory and marked as untrusted.
+pub(crate) async fn receive_file(
+	filter: &InboundFilter,
+	peer_id: PeerId,
+	(tx, rx): (
+		&mut (impl AsyncWrite + Unpin),
+		&mut (impl AsyncRead + Unpin),
+	),
+	dirs: &InboundDirs,
+) -> Result<Option<ReceivedFile>, TransferError> {
+	let FileTransferRequest { name, size } = read_message(rx).await?;
+
+	// The name comes from the remote peer, so it must only ever be used as a file name.
+	let file_name = match Path::new(&name).file_name() {
+		Some(file_name) if file_name == name.as_str() => file_name.to_os_string(),
+		_ => return Err(TransferError::InvalidName(name)),
+	};
+
+	let decision = filter.decide(&IncomingFileInfo {
+		peer_id: peer_id.clone(),
+		name: name.clone(),
+		size,
+	});
+	write_message(tx, &decision).await?;
+
+	let dir = match decision {
+		FilterDecision::Accept => &dirs.accepted,
+		FilterDecision::Quarantine => &dirs.quarantined,
+		FilterDecision::Reject => {
+			debug!("Refused file '{}' from peer '{}'", name, peer_id);
+			return Ok(None);
+		}
+	};
+
+	fs::create_dir_all(dir).await?;
+	let path = dir.join(file_name);
+	let mut file = File::create(&path).await?;
+	let received = tokio::io::copy(&mut rx.take(size), &mut file).await?;
+	file.flush().await?;
+
+	if received != size {
+		drop(file);
+		if let Err(err) = fs::remove_file(&path).await {
+			warn!("Failed to remove incomplete file '{:?}': {:?}", path, err);
+		}
+
+		return Err(TransferError::Truncated {
+			expected: size,
+			received,
+		});
+	}
+
+	Ok(Some(ReceivedFile {
+		path,
+		untrusted: decision == FilterDecision::Quarantine,
+	}))
+}
+
+/// writes a length-prefixed message, which unlike [sd_tunnel_utils::write_value] can be followed by more data on the same stream.
+async fn write_message<T: Serialize>(
+	tx: &mut (impl AsyncWrite + Unpin),
+	value: &T,
+) -> Result<(), TransferError> {
+	let data = rmp_serde::encode::to_vec_named(value)?;
+	tx.write_u32(data.len() as u32).await?;
+	tx.write_all(&data).await?;
+	tx.flush().await?;
+	Ok(())
+}
+
+/// reads a message written by [write_message].
+async fn read_message<T: DeserializeOwned>(
+	rx: &mut (impl AsyncRead + Unpin),
+) -> Result<T, TransferError> {
+	let len = rx.read_u32().await?;
+	if len > MAX_MESSAGE_LEN {
+		return Err(TransferError::MessageTooLarge(len));
+	}
+
+	let mut data = vec![0; len as usize];
+	rx.read_exact(&mut data).await?;
+	Ok(rmp_serde::decode::from_slice(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+	use tokio::io::{duplex, split};
+
+	use super::*;
+
+	/// sends a file from one end of an in-memory stream and receives it on the other.
+	async fn transfer(
+		filter: &InboundFilter,
+		name: &str,
+		contents: &[u8],
+		dirs: &InboundDirs,
+	) -> (FilterDecision, Option<ReceivedFile>) {
+		let (sender, receiver) = duplex(1024);
+		let ((mut sender_rx, mut sender_tx), (mut receiver_rx, mut receiver_tx)) =
+			(split(sender), split(receiver));
+
+		let (sent, received) = tokio::join!(
+			send_file(
+				(&mut sender_tx, &mut sender_rx),
+				name.to_string(),
+				contents.len() as u64,
+				&mut &contents[..],
+			),
+			receive_file(
+				filter,
+				PeerId::from_string("a".repeat(40)).unwrap(),
+				(&mut receiver_tx, &mut receiver_rx),
+				dirs,
+			)
+		);
+
+		(sent.unwrap(), received.unwrap())
+	}
+
+	#[tokio::test]
+	async fn filter_is_consulted_before_receiving() {
+		let dir = tempfile::tempdir().unwrap();
+		let dirs = InboundDirs {
+			accepted: dir.path().join("accepted"),
+			quarantined: dir.path().join("quarantined"),
+		};
+
+		let filter = InboundFilter::default();
+		filter.set(Box::new(|info| match info.extension() {
+			Some(ext) if ext.eq_ignore_ascii_case("exe") => FilterDecision::Reject,
+			Some(ext) if ext.eq_ignore_ascii_case("zip") => FilterDecision::Quarantine,
+			_ => FilterDecision::Accept,
+		}));
+
+		let (decision, received) = transfer(&filter, "setup.exe", b"MZ", &dirs).await;
+		assert_eq!(decision, FilterDecision::Reject);
+		assert_eq!(received, None);
+		assert!(!dirs.accepted.join("setup.exe").exists());
+
+		let (decision, received) = transfer(&filter, "notes.txt", b"hello", &dirs).await;
+		assert_eq!(decision, FilterDecision::Accept);
+		assert_eq!(
+			received,
+			Some(ReceivedFile {
+				path: dirs.accepted.join("notes.txt"),
+				untrusted: false,
+			})
+		);
+		assert_eq!(
+			fs::read(dirs.accepted.join("notes.txt")).await.unwrap(),
+			b"hello"
+		);
+
+		let (decision, received) = transfer(&filter, "archive.zip", b"PK", &dirs).await;
+		assert_eq!(decision, FilterDecision::Quarantine);
+		assert_eq!(
+			received,
+			Some(ReceivedFile {
+				path: dirs.quarantined.join("archive.zip"),
+				untrusted: true,
+			})
+		);
+	}
+
+	#[tokio::test]
+	async fn names_must_not_be_paths() {
+		let dir = tempfile::tempdir().unwrap();
+		let dirs = InboundDirs {
+			accepted: dir.path().join("accepted"),
+			quarantined: dir.path().join("quarantined"),
+		};
+
+		let (sender, receiver) = duplex(1024);
+		let ((mut sender_rx, mut sender_tx), (mut receiver_rx, mut receiver_tx)) =
+			(split(sender), split(receiver));
+
+		let send = send_file(
+			(&mut sender_tx, &mut sender_rx),
+			"../escape.txt".to_string(),
+			2,
+			&mut &b"hi"[..],
+		);
+		let receive = receive_file(
+			&InboundFilter::default(),
+			PeerId::from_string("a".repeat(40)).unwrap(),
+			(&mut receiver_tx, &mut receiver_rx),
+			&dirs,
+		);
+
+		let received = tokio::select! {
+			received = receive => received,
+			_ = send => unreachable!("the sender waits for a decision which is never made"),
+		};
+		assert!(
+			matches!(received, Err(TransferError::InvalidName(name)) if name == "../escape.txt")
+		);
+		assert!(!dir.path().join("escape.txt").exists());
+	}
+}
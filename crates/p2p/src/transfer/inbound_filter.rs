@@ -0,0 +1,99 @@
+use std::{path::Path, sync::RwLock};
+
+use sd_tunnel_utils::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Describes a file a remote peer wants to send us.
+/// This is known once the metadata exchange is done, but before any bytes of the file have been received.
+#[derive(Debug, Clone)]
+pub struct IncomingFileInfo {
+	/// peer_id is the id of the peer sending the file.
+	pub peer_id: PeerId,
+	/// name is the file name the remote peer gave us. It must not be trusted as a path.
+	pub name: String,
+	/// size is the size of the file in bytes, as announced by the remote peer.
+	pub size: u64,
+}
+
+impl IncomingFileInfo {
+	/// returns the extension of the file name (without the dot), if it has one.
+	pub fn extension(&self) -> Option<&str> {
+		Path::new(&self.name)
+			.extension()
+			.and_then(|ext| ext.to_str())
+	}
+}
+
+/// Is returned by the inbound filter to decide what happens to an incoming file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterDecision {
+	/// The file is received and accepted into the library.
+	Accept,
+	/// The transfer is refused before any bytes are received.
+	Reject,
+	/// The file is received into a separate directory and marked as untrusted, so the user can review it first.
+	Quarantine,
+}
+
+/// The function the application provides to vet incoming files, e.g. to scan them or to refuse executables.
+pub type InboundFilterFn = Box<dyn Fn(&IncomingFileInfo) -> FilterDecision + Send + Sync>;
+
+/// Holds the application's inbound filter. Every file is accepted until a filter is set.
+#[derive(Default)]
+pub struct InboundFilter(RwLock<Option<InboundFilterFn>>);
+
+impl InboundFilter {
+	/// replaces the current filter.
+	pub fn set(&self, filter: InboundFilterFn) {
+		*self.0.write().unwrap_or_else(|err| err.into_inner()) = Some(filter);
+	}
+
+	/// decides what to do with an incoming file. This is consulted before any of its bytes are received.
+	pub fn decide(&self, info: &IncomingFileInfo) -> FilterDecision {
+		self.0
+			.read()
+			.unwrap_or_else(|err| err.into_inner())
+			.as_ref()
+			.map_or(FilterDecision::Accept, |filter| filter(info))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn incoming(name: &str) -> IncomingFileInfo {
+		IncomingFileInfo {
+			peer_id: PeerId::from_string("a".repeat(40)).unwrap(),
+			name: name.to_string(),
+			size: 1024,
+		}
+	}
+
+	#[test]
+	fn executables_are_rejected() {
+		let filter = InboundFilter::default();
+		assert_eq!(
+			filter.decide(&incoming("setup.exe")),
+			FilterDecision::Accept
+		);
+
+		filter.set(Box::new(|info| match info.extension() {
+			Some(ext) if ext.eq_ignore_ascii_case("exe") => FilterDecision::Reject,
+			_ => FilterDecision::Accept,
+		}));
+
+		assert_eq!(
+			filter.decide(&incoming("setup.exe")),
+			FilterDecision::Reject
+		);
+		assert_eq!(
+			filter.decide(&incoming("SETUP.EXE")),
+			FilterDecision::Reject
+		);
+		assert_eq!(
+			filter.decide(&incoming("notes.txt")),
+			FilterDecision::Accept
+		);
+	}
+}
@@ -0,0 +1,5 @@
+mod file_transfer;
+mod inbound_filter;
+
+pub use file_transfer::*;
+pub use inbound_filter::*;
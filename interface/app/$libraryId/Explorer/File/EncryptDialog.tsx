@@ -22,6 +22,7 @@ const schema = z.object({
 	hashingAlgo: hashingAlgoSlugSchema,
 	metadata: z.boolean(),
 	previewMedia: z.boolean(),
+	plaintextCommitment: z.boolean(),
 	outputPath: z.string()
 });
 
@@ -61,7 +62,11 @@ export default (props: Props) => {
 	});
 
 	const form = useZodForm({
-		defaultValues: { encryptionAlgo: 'XChaCha20Poly1305', outputPath: '' },
+		defaultValues: {
+			encryptionAlgo: 'XChaCha20Poly1305',
+			plaintextCommitment: false,
+			outputPath: ''
+		},
 		schema
 	});
 
@@ -73,6 +78,7 @@ export default (props: Props) => {
 			path_id: props.path_id,
 			metadata: data.metadata,
 			preview_media: data.previewMedia,
+			plaintext_commitment: data.plaintextCommitment,
 			output_path: data.outputPath || null
 		})
 	);
@@ -141,6 +147,7 @@ export default (props: Props) => {
 					>
 						<SelectOption value="XChaCha20Poly1305">XChaCha20-Poly1305</SelectOption>
 						<SelectOption value="Aes256Gcm">AES-256-GCM</SelectOption>
+						<SelectOption value="Aes128Gcm">AES-128-GCM</SelectOption>
 					</Select>
 				</div>
 				<div className="flex flex-col">
@@ -170,6 +177,10 @@ export default (props: Props) => {
 					<span className="mr-3 ml-0.5 mt-0.5 text-sm font-bold">Preview Media</span>
 					<CheckBox {...form.register('previewMedia')} />
 				</div>
+				<div className="flex">
+					<span className="mr-3 ml-0.5 mt-0.5 text-sm font-bold">Find Duplicates</span>
+					<CheckBox {...form.register('plaintextCommitment')} />
+				</div>
 			</div>
 		</Dialog>
 	);
@@ -4,7 +4,11 @@
 export type Procedures = {
     queries: 
         { key: "buildInfo", input: never, result: BuildInfo } | 
-        { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, file_paths: FilePath[], media_data: MediaData | null } | null } | 
+        { key: "files.findSimilar", input: LibraryArgs<FindSimilarArgs>, result: SimilarObject[] } | 
+        { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, kind_version: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, thumbnail_status: number, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, file_paths: FilePath[], media_data: MediaData | null } | null } | 
+        { key: "files.listByColor", input: LibraryArgs<ColorLabel>, result: Object[] } | 
+        { key: "files.listQuarantine", input: LibraryArgs<null>, result: Object[] } | 
+        { key: "files.listRecents", input: LibraryArgs<number>, result: Object[] } | 
         { key: "jobs.getHistory", input: LibraryArgs<null>, result: JobReport[] } | 
         { key: "jobs.getRunning", input: LibraryArgs<null>, result: JobReport[] } | 
         { key: "jobs.isRunning", input: LibraryArgs<null>, result: boolean } | 
@@ -17,12 +21,12 @@ export type Procedures = {
         { key: "keys.listMounted", input: LibraryArgs<null>, result: string[] } | 
         { key: "library.getStatistics", input: LibraryArgs<null>, result: Statistics } | 
         { key: "library.list", input: never, result: LibraryConfigWrapped[] } | 
-        { key: "locations.getById", input: LibraryArgs<number>, result: { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, date_created: string, indexer_rules: IndexerRulesInLocation[] } | null } | 
+        { key: "locations.getById", input: LibraryArgs<number>, result: { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, perceptual_hashing: boolean, hidden: boolean, date_created: string, indexer_rules: IndexerRulesInLocation[] } | null } | 
         { key: "locations.getExplorerData", input: LibraryArgs<LocationExplorerArgs>, result: ExplorerData } | 
         { key: "locations.indexer_rules.get", input: LibraryArgs<number>, result: IndexerRule } | 
         { key: "locations.indexer_rules.list", input: LibraryArgs<null>, result: IndexerRule[] } | 
         { key: "locations.indexer_rules.listForLocation", input: LibraryArgs<number>, result: IndexerRule[] } | 
-        { key: "locations.list", input: LibraryArgs<null>, result: { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, date_created: string, node: Node }[] } | 
+        { key: "locations.list", input: LibraryArgs<null>, result: { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, perceptual_hashing: boolean, hidden: boolean, date_created: string, node: Node }[] } | 
         { key: "nodeState", input: never, result: NodeState } | 
         { key: "tags.get", input: LibraryArgs<number>, result: Tag | null } | 
         { key: "tags.getExplorerData", input: LibraryArgs<number>, result: ExplorerData } | 
@@ -38,12 +42,22 @@ export type Procedures = {
         { key: "files.duplicateFiles", input: LibraryArgs<FileCopierJobInit>, result: null } | 
         { key: "files.encryptFiles", input: LibraryArgs<FileEncryptorJobInit>, result: null } | 
         { key: "files.eraseFiles", input: LibraryArgs<FileEraserJobInit>, result: null } | 
+        { key: "files.exportBundle", input: LibraryArgs<ExportBundleArgs>, result: BundleManifest } | 
+        { key: "files.importBundle", input: LibraryArgs<ImportBundleArgs>, result: BundleManifest } | 
+        { key: "files.moveToLocation", input: LibraryArgs<MoveToLocationArgs>, result: null } | 
+        { key: "files.recordAccess", input: LibraryArgs<number>, result: null } | 
+        { key: "files.review", input: LibraryArgs<ReviewArgs>, result: null } | 
+        { key: "files.setColorLabel", input: LibraryArgs<SetColorLabelArgs>, result: null } | 
         { key: "files.setFavorite", input: LibraryArgs<SetFavoriteArgs>, result: null } | 
         { key: "files.setNote", input: LibraryArgs<SetNoteArgs>, result: null } | 
+        { key: "jobs.cancel", input: LibraryArgs<string>, result: null } | 
         { key: "jobs.clearAll", input: LibraryArgs<null>, result: null } | 
         { key: "jobs.generateThumbsForLocation", input: LibraryArgs<GenerateThumbsForLocationArgs>, result: null } | 
         { key: "jobs.identifyUniqueFiles", input: LibraryArgs<IdentifyUniqueFilesArgs>, result: null } | 
+        { key: "jobs.importNativeTags", input: LibraryArgs<number>, result: null } | 
         { key: "jobs.objectValidator", input: LibraryArgs<ObjectValidatorArgs>, result: null } | 
+        { key: "jobs.prioritizeThumbnails", input: LibraryArgs<PrioritizeThumbnailsArgs>, result: null } | 
+        { key: "jobs.reidentifyObjects", input: LibraryArgs<number | null>, result: null } | 
         { key: "keys.add", input: LibraryArgs<KeyAddArgs>, result: null } | 
         { key: "keys.backupKeystore", input: LibraryArgs<string>, result: null } | 
         { key: "keys.changeMasterPassword", input: LibraryArgs<MasterPasswordChangeArgs>, result: null } | 
@@ -60,6 +74,8 @@ export type Procedures = {
         { key: "library.create", input: CreateLibraryArgs, result: LibraryConfigWrapped } | 
         { key: "library.delete", input: string, result: null } | 
         { key: "library.edit", input: EditLibraryArgs, result: null } | 
+        { key: "library.scheduleBackup", input: LibraryArgs<BackupSchedule>, result: null } | 
+        { key: "library.unscheduleBackup", input: LibraryArgs<null>, result: boolean } | 
         { key: "locations.addLibrary", input: LibraryArgs<LocationCreateArgs>, result: null } | 
         { key: "locations.create", input: LibraryArgs<LocationCreateArgs>, result: null } | 
         { key: "locations.delete", input: LibraryArgs<number>, result: null } | 
@@ -67,38 +83,54 @@ export type Procedures = {
         { key: "locations.indexer_rules.create", input: LibraryArgs<IndexerRuleCreateArgs>, result: IndexerRule } | 
         { key: "locations.indexer_rules.delete", input: LibraryArgs<number>, result: null } | 
         { key: "locations.quickRescan", input: LibraryArgs<null>, result: null } | 
+        { key: "locations.reconcile", input: LibraryArgs<number>, result: ReconcileReport } | 
         { key: "locations.relink", input: LibraryArgs<string>, result: null } | 
         { key: "locations.update", input: LibraryArgs<LocationUpdateArgs>, result: null } | 
         { key: "nodes.tokenizeSensitiveKey", input: TokenizeKeyArgs, result: TokenizeResponse } | 
-        { key: "tags.assign", input: LibraryArgs<TagAssignArgs>, result: null } | 
+        { key: "tags.assign", input: LibraryArgs<TagAssignArgs>, result: boolean } | 
         { key: "tags.create", input: LibraryArgs<TagCreateArgs>, result: Tag } | 
         { key: "tags.delete", input: LibraryArgs<number>, result: null } | 
         { key: "tags.update", input: LibraryArgs<TagUpdateArgs>, result: null },
     subscriptions: 
         { key: "invalidateQuery", input: never, result: InvalidateOperationEvent } | 
         { key: "jobs.newThumbnail", input: LibraryArgs<null>, result: string } | 
+        { key: "library.backupEvents", input: LibraryArgs<null>, result: BackupEvent } | 
         { key: "locations.online", input: never, result: number[][] }
 };
 
 /**
  *  These are all possible algorithms that can be used for encryption and decryption
  */
-export type Algorithm = "XChaCha20Poly1305" | "Aes256Gcm"
+export type Algorithm = "XChaCha20Poly1305" | "Aes256Gcm" | "Aes128Gcm"
 
 export type AuthOption = { type: "Password", value: string } | { type: "TokenizedPassword", value: string }
 
 export type AutomountUpdateArgs = { uuid: string, status: boolean }
 
+export type BackupEvent = { Created: string } | "Skipped" | { Failed: string }
+
+export type BackupSchedule = { location_id: number, interval_secs: number, keep: number }
+
 export type BuildInfo = { version: string, commit: string }
 
+export type BundleEntry = { path: string, size: number, tags: BundleTag[] }
+
+export type BundleManifest = { entries: BundleEntry[] }
+
+export type BundleTag = { name: string, color: string | null }
+
 /**
  *  ConfigMetadata is a part of node configuration that is loaded before the main configuration and contains information about the schema of the config.
  *  This allows us to migrate breaking changes to the config format between Spacedrive releases.
  */
+export type ColorLabel = "Red" | "Orange" | "Yellow" | "Green" | "Blue" | "Purple" | "Gray"
+
 export type ConfigMetadata = { version: string | null }
 
 export type CreateLibraryArgs = { name: string, auth: AuthOption, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm }
 
+export type Decision = { Accept: { location_id: number } } | "Reject"
+
 export type EditLibraryArgs = { id: string, name: string | null, description: string | null }
 
 /**
@@ -114,6 +146,8 @@ export type ExplorerData = { context: ExplorerContext, items: ExplorerItem[] }
 
 export type ExplorerItem = { type: "Path", has_thumbnail: boolean, item: file_path_with_object } | { type: "Object", has_thumbnail: boolean, item: object_with_file_paths }
 
+export type ExportBundleArgs = { ids: number[], path: string, password: string }
+
 export type FileCopierJobInit = { source_location_id: number, source_path_id: number, target_location_id: number, target_path: string, target_file_name_suffix: string | null }
 
 export type FileCutterJobInit = { source_location_id: number, source_path_id: number, target_location_id: number, target_path: string }
@@ -122,11 +156,13 @@ export type FileDecryptorJobInit = { location_id: number, path_id: number, mount
 
 export type FileDeleterJobInit = { location_id: number, path_id: number }
 
-export type FileEncryptorJobInit = { location_id: number, path_id: number, key_uuid: string, algorithm: Algorithm, metadata: boolean, preview_media: boolean, output_path: string | null }
+export type FileEncryptorJobInit = { location_id: number, path_id: number, key_uuid: string, algorithm: Algorithm, metadata: boolean, preview_media: boolean, plaintext_commitment: boolean, output_path: string | null }
 
 export type FileEraserJobInit = { location_id: number, path_id: number, passes: string }
 
-export type FilePath = { id: number, is_dir: boolean, cas_id: string | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string }
+export type FilePath = { id: number, is_dir: boolean, cas_id: string | null, integrity_checksum: string | null, device: number[] | null, inode: number[] | null, location_id: number, materialized_path: string, name: string, extension: string, object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string, date_deleted: string | null }
+
+export type FindSimilarArgs = { id: number, max_distance: number }
 
 export type GenerateThumbsForLocationArgs = { id: number, path: string }
 
@@ -139,6 +175,8 @@ export type HashingAlgorithm = { name: "Argon2id", params: Params } | { name: "B
 
 export type IdentifyUniqueFilesArgs = { id: number, path: string }
 
+export type ImportBundleArgs = { path: string, location_id: number, subpath: string, password: string, restore_tags: boolean }
+
 export type IndexerRule = { id: number, kind: number, name: string, parameters: number[], date_created: string, date_modified: string }
 
 /**
@@ -157,6 +195,8 @@ export type IndexerRulesInLocation = { date_created: string, location_id: number
 
 export type InvalidateOperationEvent = { key: string, arg: any }
 
+export type JobConcurrency = { hashing: number, encryption: number, thumbnails: number, transfers: number }
+
 export type JobReport = { id: string, name: string, data: number[] | null, metadata: any | null, date_created: string, date_modified: string, status: JobStatus, task_count: number, completed_task_count: number, message: string, seconds_elapsed: number }
 
 export type JobStatus = "Queued" | "Running" | "Completed" | "Canceled" | "Failed" | "Paused"
@@ -171,11 +211,11 @@ export type LibraryArgs<T> = { library_id: string, arg: T }
 /**
  *  LibraryConfig holds the configuration for a specific library. This is stored as a '{uuid}.sdlibrary' file.
  */
-export type LibraryConfig = ({ version: string | null }) & { name: string, description: string }
+export type LibraryConfig = ({ version: string | null }) & { name: string, description: string, backup_schedule: BackupSchedule | null }
 
 export type LibraryConfigWrapped = { uuid: string, config: LibraryConfig }
 
-export type Location = { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, date_created: string }
+export type Location = { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, perceptual_hashing: boolean, hidden: boolean, date_created: string }
 
 /**
  *  `LocationCreateArgs` is the argument received from the client using `rspc` to create a new location.
@@ -194,18 +234,22 @@ export type LocationExplorerArgs = { location_id: number, path: string, limit: n
  *  It is important to note that only the indexer rule ids in this vector will be used from now on.
  *  Old rules that aren't in this vector will be purged.
  */
-export type LocationUpdateArgs = { id: number, name: string | null, generate_preview_media: boolean | null, sync_preview_media: boolean | null, hidden: boolean | null, indexer_rules_ids: number[] }
+export type LocationUpdateArgs = { id: number, name: string | null, generate_preview_media: boolean | null, sync_preview_media: boolean | null, perceptual_hashing: boolean | null, hidden: boolean | null, indexer_rules_ids: number[] }
 
 export type MasterPasswordChangeArgs = { password: string, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm }
 
 export type MediaData = { id: number, pixel_width: number | null, pixel_height: number | null, longitude: number | null, latitude: number | null, fps: number | null, capture_device_make: string | null, capture_device_model: string | null, capture_device_software: string | null, duration_seconds: number | null, codecs: string | null, streams: number | null }
 
+export type MoveToLocationArgs = { id: number, location_id: number, subpath: string, on_conflict: NameConflict }
+
+export type NameConflict = "Overwrite" | "Rename"
+
 export type Node = { id: number, pub_id: number[], name: string, platform: number, version: string | null, last_seen: string, timezone: string | null, date_created: string }
 
 /**
  *  NodeConfig is the configuration for a node. This is shared between all libraries and is stored in a JSON file on disk.
  */
-export type NodeConfig = ({ version: string | null }) & { id: string, name: string, p2p_port: number | null }
+export type NodeConfig = ({ version: string | null }) & { id: string, name: string, p2p_port: number | null, job_concurrency: JobConcurrency }
 
 export type NodeState = (({ version: string | null }) & { id: string, name: string, p2p_port: number | null }) & { data_path: string }
 
@@ -216,7 +260,7 @@ export type NodeState = (({ version: string | null }) & { id: string, name: stri
  */
 export type Nonce = { XChaCha20Poly1305: number[] } | { Aes256Gcm: number[] }
 
-export type Object = { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string }
+export type Object = { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, kind_version: number, mime: string | null, perceptual_hash: number[] | null, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, color_label: number | null, pending_review: boolean, has_thumbnail: boolean, thumbnail_status: number, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, date_deleted: string | null }
 
 export type ObjectValidatorArgs = { id: number, path: string }
 
@@ -227,8 +271,19 @@ export type ObjectValidatorArgs = { id: number, path: string }
  */
 export type Params = "Standard" | "Hardened" | "Paranoid"
 
+export type PrioritizeThumbnailsArgs = { location_id: number, path: string }
+
+/**
+ *  What `reconcile` found to be out of sync between a location's index and its files, by materialized path.
+ * 
+ *  Every discrepancy in the report has been repaired by the time it's returned.
+ */
+export type ReconcileReport = { missing_on_disk: string[], missing_in_index: string[], stale: string[] }
+
 export type RestoreBackupArgs = { password: string, secret_key: string, path: string }
 
+export type ReviewArgs = { id: number, decision: Decision }
+
 export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChildrenDirectoriesArePresent" | "RejectIfChildrenDirectoriesArePresent"
 
 /**
@@ -238,10 +293,14 @@ export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChil
  */
 export type Salt = number[]
 
+export type SetColorLabelArgs = { id: number, label: ColorLabel | null }
+
 export type SetFavoriteArgs = { id: number, favorite: boolean }
 
 export type SetNoteArgs = { id: number, note: string | null }
 
+export type SimilarObject = { object: Object, distance: number }
+
 export type Statistics = { id: number, date_captured: string, total_object_count: number, library_db_size: string, total_bytes_used: string, total_bytes_capacity: string, total_unique_bytes: string, total_bytes_free: string, preview_media_bytes: string }
 
 /**
@@ -279,4 +338,4 @@ export type Volume = { name: string, mount_point: string, total_capacity: string
 
 export type file_path_with_object = { id: number, is_dir: boolean, cas_id: string | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string, object: Object | null }
 
-export type object_with_file_paths = { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, file_paths: FilePath[] }
+export type object_with_file_paths = { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, kind_version: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, thumbnail_status: number, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, file_paths: FilePath[] }